@@ -0,0 +1,66 @@
+use anyhow::Result;
+use clap::Subcommand;
+use g3_config::secrets;
+use std::io::{BufRead, IsTerminal};
+
+/// Manage provider API keys stored in the OS keyring
+#[derive(Subcommand, Clone, Debug)]
+pub enum AuthCommand {
+    /// Store an API key for a provider in the OS keyring
+    Login {
+        /// Provider name (anthropic, openai, databricks, or an openai_compatible name)
+        provider: String,
+    },
+    /// Remove a stored API key for a provider
+    Logout {
+        /// Provider name
+        provider: String,
+    },
+    /// Show whether a key is stored for a provider
+    Status {
+        /// Provider name
+        provider: String,
+    },
+}
+
+pub fn run_auth_command(command: &AuthCommand) -> Result<()> {
+    match command {
+        AuthCommand::Login { provider } => {
+            let key = read_secret(&format!("API key for {}: ", provider))?;
+            if key.is_empty() {
+                return Err(anyhow::anyhow!("No API key entered for '{}'", provider));
+            }
+            secrets::store_keyring_secret(provider, &key)?;
+            println!("✅ Stored API key for '{}' in the OS keyring", provider);
+            println!(
+                "   Leave api_key unset (or use api_key = \"keyring:\") in your config to use it."
+            );
+        }
+        AuthCommand::Logout { provider } => {
+            if secrets::delete_keyring_secret(provider)? {
+                println!("🗑️  Removed stored API key for '{}'", provider);
+            } else {
+                println!("No stored API key for '{}'", provider);
+            }
+        }
+        AuthCommand::Status { provider } => match secrets::get_keyring_secret(provider)? {
+            Some(_) => println!("✅ An API key for '{}' is stored in the OS keyring", provider),
+            None => println!("No stored API key for '{}'", provider),
+        },
+    }
+    Ok(())
+}
+
+/// Read a secret without echoing it when attached to a terminal.
+/// When stdin is piped (e.g. in CI), the first line of stdin is used instead.
+pub(crate) fn read_secret(prompt: &str) -> Result<String> {
+    let stdin = std::io::stdin();
+    let secret = if stdin.is_terminal() {
+        rpassword::prompt_password(prompt)?
+    } else {
+        let mut line = String::new();
+        stdin.lock().read_line(&mut line)?;
+        line
+    };
+    Ok(secret.trim().to_string())
+}
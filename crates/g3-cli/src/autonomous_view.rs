@@ -0,0 +1,414 @@
+//! `--tui`: a full-screen view of an autonomous run.
+//!
+//! The header shows the turn, which role is acting and what it is doing, and the coach's
+//! last verdict. Below it is a scrolling tail of the player's and the coach's output,
+//! interleaved and told apart by color. The agents write to the view through
+//! [`ViewWriter`], and the run's own messages reach it through a [`SimpleOutput`] sink.
+//! When the view closes, the end of the tail is printed so the run's last output stays on
+//! screen.
+//!
+//! [`SimpleOutput`]: crate::simple_output::SimpleOutput
+
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use g3_core::ui_writer::UiWriter;
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    DefaultTerminal, Frame,
+};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Lines of output kept for the tail
+const MAX_LINES: usize = 1000;
+
+/// Lines of the tail printed when the view closes
+const LINES_ON_CLOSE: usize = 20;
+
+/// How often the view is redrawn and the keyboard checked
+const TICK: Duration = Duration::from_millis(100);
+
+/// Who is acting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Starting,
+    Player,
+    Coach,
+    /// The player reviewing its own work, with `--self-review`
+    Review,
+}
+
+impl Role {
+    fn label(self) -> &'static str {
+        match self {
+            Role::Starting => "STARTING",
+            Role::Player => "PLAYER",
+            Role::Coach => "COACH",
+            Role::Review => "SELF-REVIEW",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Role::Player => Color::Green,
+            Role::Coach | Role::Review => Color::Magenta,
+            Role::Starting => Color::Gray,
+        }
+    }
+}
+
+struct State {
+    role: Role,
+    turn: usize,
+    max_turns: usize,
+    /// The tool running, if any
+    activity: Option<String>,
+    verdict: Option<String>,
+    lines: VecDeque<(Role, String)>,
+    /// Text of a line still being written
+    partial: String,
+    started: Instant,
+    closed: bool,
+}
+
+impl State {
+    fn push(&mut self, line: String) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back((self.role, line));
+    }
+}
+
+struct Renderer {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// The state of an autonomous run, drawn on the whole terminal while it is open
+#[derive(Clone)]
+pub struct AutonomousView {
+    state: Arc<Mutex<State>>,
+    renderer: Arc<Mutex<Option<Renderer>>>,
+}
+
+impl AutonomousView {
+    pub fn new(max_turns: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                role: Role::Starting,
+                turn: 0,
+                max_turns,
+                activity: None,
+                verdict: None,
+                lines: VecDeque::new(),
+                partial: String::new(),
+                started: Instant::now(),
+                closed: false,
+            })),
+            renderer: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Take over the terminal and draw the view until [`close`](Self::close). q or Ctrl-C
+    /// stops the run.
+    pub fn open(&self) {
+        let mut terminal = ratatui::init();
+        let stop = Arc::new(AtomicBool::new(false));
+        let state = Arc::clone(&self.state);
+        let thread = {
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || render_loop(&mut terminal, &state, &stop))
+        };
+        *self.renderer.lock().unwrap() = Some(Renderer { stop, thread });
+    }
+
+    /// Give the terminal back and print the end of the tail. Output after this is printed
+    /// as it arrives.
+    pub fn close(&self) {
+        let Some(renderer) = self.renderer.lock().unwrap().take() else {
+            return;
+        };
+        renderer.stop.store(true, Ordering::Relaxed);
+        let _ = renderer.thread.join();
+        ratatui::restore();
+
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        let partial = std::mem::take(&mut state.partial);
+        if !partial.is_empty() {
+            state.push(partial);
+        }
+        let skip = state.lines.len().saturating_sub(LINES_ON_CLOSE);
+        for (_, line) in state.lines.iter().skip(skip) {
+            println!("{}", line);
+        }
+    }
+
+    pub fn set_role(&self, role: Role, turn: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.role = role;
+        state.turn = turn;
+        state.activity = None;
+    }
+
+    pub fn set_verdict(&self, verdict: &str) {
+        self.state.lock().unwrap().verdict = Some(verdict.to_string());
+    }
+
+    fn set_activity(&self, activity: Option<String>) {
+        self.state.lock().unwrap().activity = activity;
+    }
+
+    /// Add whole lines to the tail
+    pub fn push_line(&self, text: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            println!("{}", text);
+            return;
+        }
+        let partial = std::mem::take(&mut state.partial);
+        if !partial.is_empty() {
+            state.push(partial);
+        }
+        if text.is_empty() {
+            state.push(String::new());
+        }
+        for line in text.lines() {
+            state.push(line.to_string());
+        }
+    }
+
+    /// Add text that may end in the middle of a line, such as a streamed response
+    fn push_text(&self, text: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            print!("{}", text);
+            return;
+        }
+        let mut rest = text;
+        while let Some(newline) = rest.find('\n') {
+            let line = format!("{}{}", std::mem::take(&mut state.partial), &rest[..newline]);
+            state.push(line);
+            rest = &rest[newline + 1..];
+        }
+        state.partial.push_str(rest);
+    }
+
+    /// End the line being written, if there is one
+    fn end_text(&self) {
+        let mut state = self.state.lock().unwrap();
+        let partial = std::mem::take(&mut state.partial);
+        if !partial.is_empty() {
+            state.push(partial);
+        }
+    }
+}
+
+fn render_loop(terminal: &mut DefaultTerminal, state: &Mutex<State>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        let _ = terminal.draw(|frame| draw(frame, &state.lock().unwrap()));
+        if !event::poll(TICK).unwrap_or(false) {
+            continue;
+        }
+        if let Ok(Event::Key(key)) = event::read() {
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if ctrl_c || key.code == KeyCode::Char('q') {
+                ratatui::restore();
+                println!("⚠️  Autonomous run cancelled by user");
+                std::process::exit(130);
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, state: &State) {
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let elapsed = state.started.elapsed().as_secs();
+    let dim = Style::default().fg(Color::DarkGray);
+    let status = Line::from(vec![
+        Span::raw(format!("Turn {}/{}  ", state.turn, state.max_turns)),
+        Span::styled(
+            format!("▶ {}", state.role.label()),
+            Style::default()
+                .fg(state.role.color())
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            match &state.activity {
+                Some(activity) => format!("  running {}", activity),
+                None => String::new(),
+            },
+            dim,
+        ),
+        Span::styled(format!("  {}m {:02}s", elapsed / 60, elapsed % 60), dim),
+    ]);
+    let verdict = Line::from(vec![
+        Span::styled("Last coach verdict: ", dim),
+        Span::raw(state.verdict.as_deref().unwrap_or("none yet")),
+    ]);
+    frame.render_widget(
+        Paragraph::new(vec![status, verdict]).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" g3 autonomous "),
+        ),
+        header,
+    );
+
+    let height = body.height.saturating_sub(2) as usize;
+    let partial = (!state.partial.is_empty()).then(|| (state.role, state.partial.clone()));
+    let lines: Vec<(Role, String)> = state.lines.iter().cloned().chain(partial).collect();
+    let tail: Vec<Line> = lines[lines.len().saturating_sub(height)..]
+        .iter()
+        .map(|(role, line)| {
+            Line::styled(
+                line.clone(),
+                Style::default().fg(match role {
+                    Role::Coach | Role::Review => Color::Magenta,
+                    _ => Color::Reset,
+                }),
+            )
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(tail).block(Block::default().borders(Borders::ALL).title(" Activity ")),
+        body,
+    );
+
+    frame.render_widget(
+        Paragraph::new(Line::styled("q or Ctrl-C stops the run", dim)),
+        footer,
+    );
+}
+
+/// Writes an agent's output to the view instead of the terminal
+pub struct ViewWriter {
+    view: AutonomousView,
+}
+
+impl ViewWriter {
+    pub fn new(view: AutonomousView) -> Self {
+        Self { view }
+    }
+}
+
+impl UiWriter for ViewWriter {
+    fn print(&self, message: &str) {
+        self.view.push_text(message);
+    }
+
+    fn println(&self, message: &str) {
+        self.view.push_line(message);
+    }
+
+    fn print_inline(&self, message: &str) {
+        self.view.push_text(message);
+    }
+
+    fn print_system_prompt(&self, prompt: &str) {
+        self.view.push_line(prompt);
+    }
+
+    fn print_context_status(&self, message: &str) {
+        self.view.push_line(message);
+    }
+
+    fn print_context_thinning(&self, message: &str) {
+        self.view.push_line(message);
+    }
+
+    fn print_tool_header(&self, tool_name: &str) {
+        self.view.set_activity(Some(tool_name.to_string()));
+        self.view.push_line(&format!("┌─ {}", tool_name));
+    }
+
+    fn print_tool_arg(&self, key: &str, value: &str) {
+        self.view.push_line(&format!("│ {}: {}", key, value));
+    }
+
+    fn print_tool_output_header(&self) {}
+
+    fn update_tool_output_line(&self, line: &str) {
+        self.view.push_line(&format!("│ {}", line));
+    }
+
+    fn print_tool_output_line(&self, line: &str) {
+        self.view.push_line(&format!("│ {}", line));
+    }
+
+    fn print_tool_output_summary(&self, hidden_count: usize) {
+        self.view
+            .push_line(&format!("│ ({} lines in total)", hidden_count));
+    }
+
+    fn print_tool_timing(&self, duration_str: &str) {
+        self.view.set_activity(None);
+        self.view.push_line(&format!("└─ {}", duration_str));
+    }
+
+    fn print_agent_prompt(&self) {
+        self.view.end_text();
+    }
+
+    fn print_agent_response(&self, content: &str) {
+        self.view.push_text(content);
+    }
+
+    fn notify_sse_received(&self) {}
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_autonomous_view_tail() {
+        let view = AutonomousView::new(3);
+        view.set_role(Role::Player, 1);
+        let writer = ViewWriter::new(view.clone());
+        writer.print_agent_response("Reading ");
+        writer.print_agent_response("the file\nand ");
+        writer.print_tool_header("shell");
+        assert_eq!(
+            view.state.lock().unwrap().activity.as_deref(),
+            Some("shell")
+        );
+        writer.print_tool_timing("20ms");
+        view.set_role(Role::Coach, 1);
+        view.push_line("Looks good\nbut add tests");
+
+        let state = view.state.lock().unwrap();
+        assert_eq!(state.activity, None);
+        let lines: Vec<(Role, &str)> = state
+            .lines
+            .iter()
+            .map(|(role, line)| (*role, line.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (Role::Player, "Reading the file"),
+                (Role::Player, "and "),
+                (Role::Player, "┌─ shell"),
+                (Role::Player, "└─ 20ms"),
+                (Role::Coach, "Looks good"),
+                (Role::Coach, "but add tests"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,466 @@
+//! `g3 batch tasks.jsonl`: run a queue of independent tasks.
+//!
+//! Each task runs in its own `g3` process (and so with its own Agent and working directory),
+//! optionally in a fresh git worktree. Progress is written to `.g3/batch/<name>/status.json`
+//! after every change so an interrupted batch picks up where it left off when run again.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use g3_config::env::ProjectEnv;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+use crate::Cli;
+
+/// Batch state directories, relative to the directory `g3 batch` runs in
+const BATCH_DIR: &str = ".g3/batch";
+const STATUS_FILE: &str = "status.json";
+const REPORT_FILE: &str = "report.md";
+
+/// One line of the tasks file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BatchTask {
+    /// Defaults to `task-<line number>`
+    #[serde(default)]
+    pub id: String,
+    pub task: String,
+    /// Directory to run the task in (default: the current directory)
+    #[serde(default)]
+    pub workspace: Option<PathBuf>,
+    /// Overrides `--worktree` for this task
+    #[serde(default)]
+    pub worktree: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskStatus {
+    pub state: TaskState,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Where the task actually ran (its worktree, if it had one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TaskStatus {
+    fn pending() -> Self {
+        Self {
+            state: TaskState::Pending,
+            attempts: 0,
+            started_at: None,
+            finished_at: None,
+            exit_code: None,
+            workspace: None,
+            error: None,
+        }
+    }
+
+    fn duration(&self) -> Option<chrono::Duration> {
+        Some(self.finished_at? - self.started_at?)
+    }
+}
+
+/// Progress of a batch, keyed by task id
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchStatus {
+    pub tasks: BTreeMap<String, TaskStatus>,
+}
+
+impl BatchStatus {
+    /// Tasks that still need to run. Tasks interrupted while running are run again; failed
+    /// tasks only with `retry_failed`.
+    pub fn remaining<'a>(&self, tasks: &'a [BatchTask], retry_failed: bool) -> Vec<&'a BatchTask> {
+        tasks
+            .iter()
+            .filter(|task| match self.tasks.get(&task.id).map(|s| s.state) {
+                None | Some(TaskState::Pending) | Some(TaskState::Running) => true,
+                Some(TaskState::Failed) => retry_failed,
+                Some(TaskState::Succeeded) => false,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub parallel: usize,
+    pub worktree: bool,
+    pub retry_failed: bool,
+    pub restart: bool,
+}
+
+/// Parse a tasks file: one JSON object per line, blank lines ignored. `${VAR}` in a task or
+/// its workspace is replaced from the environment or `env`.
+pub fn parse_tasks(contents: &str, env: &ProjectEnv) -> Result<Vec<BatchTask>> {
+    let mut tasks = Vec::new();
+    let mut ids = HashSet::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut task: BatchTask = serde_json::from_str(line)
+            .with_context(|| format!("Invalid task on line {}", i + 1))?;
+        task.task = env
+            .interpolate(&task.task)
+            .with_context(|| format!("Invalid task on line {}", i + 1))?;
+        if let Some(workspace) = &task.workspace {
+            let workspace = env
+                .interpolate(&workspace.to_string_lossy())
+                .with_context(|| format!("Invalid workspace on line {}", i + 1))?;
+            task.workspace = Some(PathBuf::from(workspace));
+        }
+        if task.id.is_empty() {
+            task.id = format!("task-{}", i + 1);
+        }
+        if task.id.contains(['/', '\\']) {
+            return Err(anyhow!(
+                "Task id '{}' on line {} contains a path separator",
+                task.id,
+                i + 1
+            ));
+        }
+        if !ids.insert(task.id.clone()) {
+            return Err(anyhow!("Duplicate task id '{}' on line {}", task.id, i + 1));
+        }
+        tasks.push(task);
+    }
+    Ok(tasks)
+}
+
+struct Batch {
+    dir: PathBuf,
+    status: Mutex<BatchStatus>,
+    /// `g3` arguments passed on to every task (config, provider, model, profile)
+    g3_args: Vec<String>,
+    worktree: bool,
+}
+
+impl Batch {
+    fn update(&self, id: &str, update: impl FnOnce(&mut TaskStatus)) {
+        let mut status = self.status.lock().unwrap();
+        update(
+            status
+                .tasks
+                .entry(id.to_string())
+                .or_insert_with(TaskStatus::pending),
+        );
+        if let Err(e) = save_status(&self.dir, &status) {
+            eprintln!("⚠️ Failed to save batch progress: {}", e);
+        }
+    }
+
+    async fn run_task(&self, task: &BatchTask) -> Result<i32> {
+        let workspace = match &task.workspace {
+            Some(workspace) => workspace.clone(),
+            None => std::env::current_dir()?,
+        };
+        let workspace = if task.worktree.unwrap_or(self.worktree) {
+            self.ensure_worktree(&workspace, &task.id).await?
+        } else {
+            workspace
+        };
+        self.update(&task.id, |s| s.workspace = Some(workspace.clone()));
+
+        let logs = self.dir.join("logs");
+        std::fs::create_dir_all(&logs)?;
+        let log = std::fs::File::create(logs.join(format!("{}.log", task.id)))?;
+
+        let status = tokio::process::Command::new(std::env::current_exe()?)
+            .arg("--workspace")
+            .arg(&workspace)
+            .arg("--quiet")
+            .args(&self.g3_args)
+            .arg(&task.task)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .status()
+            .await
+            .context("Failed to start g3")?;
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    /// A worktree of the repository at `workspace` on a branch of its own, reused if an
+    /// earlier run already created it
+    async fn ensure_worktree(&self, workspace: &Path, id: &str) -> Result<PathBuf> {
+        let path = self.dir.join("worktrees").join(id);
+        if path.exists() {
+            return Ok(path);
+        }
+        let batch_name = self
+            .dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let branch = format!("g3-batch/{}/{}", batch_name, id);
+        let output = tokio::process::Command::new("git")
+            .arg("-C")
+            .arg(workspace)
+            .args(["worktree", "add", "-b", &branch])
+            .arg(&path)
+            .output()
+            .await
+            .context("Failed to run git")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to create worktree for {}: {}",
+                id,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(path)
+    }
+}
+
+fn load_status(dir: &Path) -> Result<BatchStatus> {
+    match std::fs::read_to_string(dir.join(STATUS_FILE)) {
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BatchStatus::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write the status through a temporary file so a crash never leaves it half-written
+fn save_status(dir: &Path, status: &BatchStatus) -> Result<()> {
+    let tmp = dir.join(format!("{}.tmp", STATUS_FILE));
+    std::fs::write(&tmp, serde_json::to_string_pretty(status)?)?;
+    std::fs::rename(&tmp, dir.join(STATUS_FILE))?;
+    Ok(())
+}
+
+/// Markdown summary of the batch
+pub fn format_report(tasks: &[BatchTask], status: &BatchStatus) -> String {
+    let count = |state: TaskState| {
+        tasks
+            .iter()
+            .filter(|t| status.tasks.get(&t.id).map(|s| s.state) == Some(state))
+            .count()
+    };
+    let mut report = format!(
+        "# Batch report\n\n{} tasks: {} succeeded, {} failed, {} not finished\n\n\
+        | Task | Status | Attempts | Duration | Workspace |\n|---|---|---|---|---|\n",
+        tasks.len(),
+        count(TaskState::Succeeded),
+        count(TaskState::Failed),
+        tasks.len() - count(TaskState::Succeeded) - count(TaskState::Failed)
+    );
+    for task in tasks {
+        let task_status = status.tasks.get(&task.id);
+        let state = match task_status {
+            Some(s) if s.state == TaskState::Failed => match (&s.error, s.exit_code) {
+                (Some(error), _) => format!("❌ failed: {}", error),
+                (None, Some(code)) => format!("❌ failed (exit code {})", code),
+                (None, None) => "❌ failed".to_string(),
+            },
+            Some(s) if s.state == TaskState::Succeeded => "✅ succeeded".to_string(),
+            Some(s) if s.state == TaskState::Running => "⏸️ interrupted".to_string(),
+            _ => "⏳ pending".to_string(),
+        };
+        report.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            task.id,
+            state,
+            task_status.map(|s| s.attempts).unwrap_or(0),
+            task_status
+                .and_then(|s| s.duration())
+                .map(|d| format!("{}s", d.num_seconds()))
+                .unwrap_or_default(),
+            task_status
+                .and_then(|s| s.workspace.as_ref())
+                .map(|w| w.display().to_string())
+                .unwrap_or_default()
+        ));
+    }
+    report
+}
+
+pub async fn run_batch_command(cli: &Cli, tasks_file: &Path, options: BatchOptions) -> Result<()> {
+    let contents = std::fs::read_to_string(tasks_file)
+        .with_context(|| format!("Failed to read {}", tasks_file.display()))?;
+    let tasks = parse_tasks(&contents, &ProjectEnv::load(Path::new("."))?)?;
+    if tasks.is_empty() {
+        return Err(anyhow!("No tasks in {}", tasks_file.display()));
+    }
+
+    let name = tasks_file
+        .file_stem()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "batch".to_string());
+    let dir = std::env::current_dir()?.join(BATCH_DIR).join(&name);
+    std::fs::create_dir_all(&dir)?;
+
+    let status = if options.restart {
+        BatchStatus::default()
+    } else {
+        load_status(&dir)?
+    };
+    let remaining: Vec<BatchTask> = status
+        .remaining(&tasks, options.retry_failed)
+        .into_iter()
+        .cloned()
+        .collect();
+    let done = tasks.len() - remaining.len();
+    println!(
+        "📋 Batch '{}': {} tasks, {} to run{} (progress in {})",
+        name,
+        tasks.len(),
+        remaining.len(),
+        if done > 0 {
+            format!(", {} already finished", done)
+        } else {
+            String::new()
+        },
+        dir.display()
+    );
+
+    let mut g3_args = Vec::new();
+    for (flag, value) in [
+        ("--config", &cli.config),
+        ("--provider", &cli.provider),
+        ("--model", &cli.model),
+        ("--profile", &cli.profile),
+    ] {
+        if let Some(value) = value {
+            g3_args.push(flag.to_string());
+            g3_args.push(value.clone());
+        }
+    }
+    if cli.allow_dangerous_workspace {
+        g3_args.push("--allow-dangerous-workspace".to_string());
+    }
+
+    let batch = Arc::new(Batch {
+        dir: dir.clone(),
+        status: Mutex::new(status),
+        g3_args,
+        worktree: options.worktree,
+    });
+    let semaphore = Arc::new(Semaphore::new(options.parallel.max(1)));
+    let mut handles = Vec::new();
+    for task in remaining {
+        let batch = batch.clone();
+        let semaphore = semaphore.clone();
+        handles.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            println!("▶️  {}: {}", task.id, task.task);
+            batch.update(&task.id, |s| {
+                s.state = TaskState::Running;
+                s.attempts += 1;
+                s.started_at = Some(Utc::now());
+                s.finished_at = None;
+                s.exit_code = None;
+                s.error = None;
+            });
+
+            let result = batch.run_task(&task).await;
+            batch.update(&task.id, |s| {
+                s.finished_at = Some(Utc::now());
+                match &result {
+                    Ok(code) => {
+                        s.exit_code = Some(*code);
+                        s.state = if *code == 0 {
+                            TaskState::Succeeded
+                        } else {
+                            TaskState::Failed
+                        };
+                    }
+                    Err(e) => {
+                        s.state = TaskState::Failed;
+                        s.error = Some(e.to_string());
+                    }
+                }
+            });
+            match result {
+                Ok(0) => println!("✅ {} succeeded", task.id),
+                Ok(code) => println!("❌ {} failed (exit code {})", task.id, code),
+                Err(e) => println!("❌ {} failed: {}", task.id, e),
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await?;
+    }
+
+    let status = batch.status.lock().unwrap().clone();
+    let report = format_report(&tasks, &status);
+    std::fs::write(dir.join(REPORT_FILE), &report)?;
+    println!();
+    println!("{}", report);
+    println!("Logs: {}", dir.join("logs").display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tasks_and_resume() {
+        let env = ProjectEnv::default();
+        let tasks = parse_tasks(
+            "{\"id\": \"api\", \"task\": \"Rename Foo to Bar\", \"workspace\": \"${TEST_G3_API_DIR:-../api}\"}\n\
+             \n\
+             {\"task\": \"Update the README\", \"worktree\": true}\n\
+             {\"task\": \"Bump dependencies\"}\n",
+            &env,
+        )
+        .unwrap();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].workspace, Some(PathBuf::from("../api")));
+        assert_eq!(tasks[1].id, "task-3");
+        assert_eq!(tasks[1].worktree, Some(true));
+
+        assert!(parse_tasks(
+            "{\"id\": \"a\", \"task\": \"x\"}\n{\"id\": \"a\", \"task\": \"y\"}",
+            &env
+        )
+        .is_err());
+        assert!(parse_tasks("not json", &env).is_err());
+        assert!(parse_tasks("{\"task\": \"Migrate ${TEST_G3_UNSET_DB}\"}", &env).is_err());
+
+        let mut status = BatchStatus::default();
+        for (id, state) in [
+            ("api", TaskState::Succeeded),
+            ("task-3", TaskState::Failed),
+            ("task-4", TaskState::Running),
+        ] {
+            let mut task_status = TaskStatus::pending();
+            task_status.state = state;
+            status.tasks.insert(id.to_string(), task_status);
+        }
+        let ids = |retry_failed| {
+            status
+                .remaining(&tasks, retry_failed)
+                .iter()
+                .map(|t| t.id.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(false), vec!["task-4"]);
+        assert_eq!(ids(true), vec!["task-3", "task-4"]);
+
+        let report = format_report(&tasks, &status);
+        assert!(report.contains("3 tasks: 1 succeeded, 1 failed, 1 not finished"));
+        assert!(report.contains("| task-4 | ⏸️ interrupted |"));
+    }
+}
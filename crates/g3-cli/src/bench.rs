@@ -0,0 +1,266 @@
+//! `g3 bench`: time to first token, throughput and cost of a provider and model on a fixed
+//! set of prompts, for choosing between hosted models and embedded quantizations.
+//!
+//! Each prompt runs as a task of a fresh agent in a scratch directory, so every run starts
+//! from the same context. The numbers come from the agent's own per-task metrics.
+
+use anyhow::{Context, Result};
+use g3_config::Config;
+use g3_core::{AgentBuilder, TurnMetrics};
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::Cli;
+
+/// A standardized benchmark prompt
+struct Case {
+    name: &'static str,
+    prompt: &'static str,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "short",
+        prompt: "Reply with only the word: ready. Do not use any tools.",
+    },
+    Case {
+        name: "long",
+        prompt: "Without using any tools, write a complete Rust module implementing a generic \
+                 binary search tree with insert, remove, contains and an in-order iterator, \
+                 with a doc comment on every public item and unit tests. Reply with the code only.",
+    },
+    Case {
+        name: "tool",
+        prompt: "Use the shell tool to run `echo g3-bench` and reply with only its output.",
+    },
+];
+
+/// What one prompt measured over its runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub runs: usize,
+    pub failures: usize,
+    /// Median time to first token
+    pub ttft_ms: Option<u64>,
+    /// Median time of sending the request until the provider responded, the part of the time
+    /// to first token spent on the network rather than by the model
+    pub request_ms: Option<u64>,
+    /// Median wall clock time of a run
+    pub wall_ms: u64,
+    /// Output tokens per second of generation, after the first token
+    pub tokens_per_second: Option<f64>,
+    pub avg_output_tokens: f64,
+    /// Prompt and output tokens
+    pub avg_total_tokens: f64,
+    /// Estimated from `[stats] cost_per_million_tokens`
+    pub avg_cost: Option<f64>,
+}
+
+fn median(mut values: Vec<Duration>) -> Option<Duration> {
+    values.sort();
+    values.get(values.len() / 2).copied()
+}
+
+/// Summarize the metrics of a prompt's successful runs
+pub(crate) fn summarize(
+    name: &str,
+    runs: &[TurnMetrics],
+    failures: usize,
+    price_per_million: Option<f64>,
+) -> CaseResult {
+    let mut result = CaseResult {
+        name: name.to_string(),
+        runs: runs.len() + failures,
+        failures,
+        ..Default::default()
+    };
+    if runs.is_empty() {
+        return result;
+    }
+
+    let count = runs.len() as f64;
+    result.ttft_ms = median(runs.iter().filter_map(|r| r.time_to_first_token).collect())
+        .map(|d| d.as_millis() as u64);
+    result.request_ms = median(runs.iter().filter_map(|r| r.request_time).collect())
+        .map(|d| d.as_millis() as u64);
+    result.wall_ms = median(runs.iter().map(|r| r.wall_clock_time).collect())
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    let output_tokens: u32 = runs.iter().map(|r| r.output_tokens).sum();
+    let generation: Duration = runs
+        .iter()
+        .map(|r| {
+            r.wall_clock_time
+                .saturating_sub(r.time_to_first_token.unwrap_or_default())
+        })
+        .sum();
+    if output_tokens > 0 && !generation.is_zero() {
+        result.tokens_per_second = Some(output_tokens as f64 / generation.as_secs_f64());
+    }
+    result.avg_output_tokens = output_tokens as f64 / count;
+    result.avg_total_tokens = runs.iter().map(|r| r.tokens_used as f64).sum::<f64>() / count;
+    result.avg_cost = price_per_million.map(|price| result.avg_total_tokens * price / 1_000_000.0);
+    result
+}
+
+/// `g3 bench`: run each prompt `runs` times and print a table, or JSON
+pub async fn run_bench_command(
+    cli: &Cli,
+    provider: Option<String>,
+    model: Option<String>,
+    runs: usize,
+    json: bool,
+) -> Result<()> {
+    let mut config = Config::load_with_overrides(
+        cli.config.as_deref(),
+        provider.or_else(|| cli.provider.clone()),
+        model.or_else(|| cli.model.clone()),
+    )?;
+    let runs = runs.max(1);
+    let price = config.stats.cost_per_million_tokens;
+    // Benchmark tasks aren't usage, and nobody is waiting on a single run
+    config.stats.enabled = false;
+    config.notifications.bell = false;
+    config.notifications.desktop = false;
+
+    let scratch = std::env::temp_dir().join(format!("g3-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&scratch)
+        .with_context(|| format!("Failed to create {}", scratch.display()))?;
+    std::env::set_current_dir(&scratch)?;
+
+    let mut label = None;
+    let mut advertised = None;
+    let mut results = Vec::new();
+    for case in CASES {
+        let mut metrics = Vec::new();
+        let mut failures = 0;
+        for run in 1..=runs {
+            if !json {
+                eprint!("\r⏱️  {} ({}/{})   ", case.name, run, runs);
+            }
+            let mut agent = AgentBuilder::new(config.clone())
+                .quiet(true)
+                .build()
+                .await?;
+            if label.is_none() {
+                let (provider, model) = agent.get_provider_info()?;
+                label = Some(format!("{} / {}", provider, model));
+                advertised = g3_providers::hosted::service(&provider).and_then(|service| {
+                    service
+                        .model(&model)
+                        .map(|m| (service.label, m.tokens_per_second))
+                });
+            }
+            match agent
+                .execute_task_with_timing(case.prompt, None, false, false, false, false)
+                .await
+            {
+                Ok(result) => metrics.push(result.metrics),
+                Err(e) => {
+                    eprintln!("\n⚠️  {} run {} failed: {}", case.name, run, e);
+                    failures += 1;
+                }
+            }
+        }
+        results.push(summarize(case.name, &metrics, failures, price));
+    }
+    let _ = std::fs::remove_dir_all(&scratch);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    eprint!("\r{}\r", " ".repeat(40));
+    println!(
+        "⏱️  g3 bench: {} ({} run{} per prompt)",
+        label.unwrap_or_default(),
+        runs,
+        if runs == 1 { "" } else { "s" }
+    );
+    println!("{}", "=".repeat(82));
+    println!(
+        "{:<8} {:>9} {:>9} {:>10} {:>9} {:>9} {:>10} {:>10}",
+        "Prompt", "Request", "TTFT", "Tokens/s", "Output", "Total", "Time", "Cost"
+    );
+    println!("{}", "-".repeat(82));
+    for result in &results {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<8} {:>9} {:>9} {:>10} {:>9.0} {:>9.0} {:>9.2}s {:>10}{}",
+            result.name,
+            optional(
+                result
+                    .request_ms
+                    .map(|ms| format!("{:.3}s", ms as f64 / 1000.0))
+            ),
+            optional(
+                result
+                    .ttft_ms
+                    .map(|ms| format!("{:.3}s", ms as f64 / 1000.0))
+            ),
+            optional(result.tokens_per_second.map(|tps| format!("{:.1}", tps))),
+            result.avg_output_tokens,
+            result.avg_total_tokens,
+            result.wall_ms as f64 / 1000.0,
+            optional(result.avg_cost.map(|cost| format!("${:.4}", cost))),
+            if result.failures > 0 {
+                format!("  ({} failed)", result.failures)
+            } else {
+                String::new()
+            }
+        );
+    }
+    if let Some((service, tokens_per_second)) = advertised {
+        println!();
+        println!(
+            "{} advertises ~{} tokens/s for this model.",
+            service, tokens_per_second
+        );
+    }
+    if price.is_none() {
+        println!();
+        println!("Set cost_per_million_tokens under [stats] to estimate cost.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(wall_ms: u64, ttft_ms: u64, output_tokens: u32) -> TurnMetrics {
+        TurnMetrics {
+            turn_number: 1,
+            tokens_used: output_tokens + 1000,
+            wall_clock_time: Duration::from_millis(wall_ms),
+            output_tokens,
+            time_to_first_token: Some(Duration::from_millis(ttft_ms)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_summarize_runs() {
+        let runs = [
+            run(2500, 500, 100),
+            run(4000, 1000, 300),
+            run(3000, 200, 100),
+        ];
+        let result = summarize("long", &runs, 1, Some(3.0));
+        assert_eq!(result.runs, 4);
+        assert_eq!(result.ttft_ms, Some(500));
+        assert_eq!(result.wall_ms, 3000);
+        // 500 tokens over 2s + 3s + 2.8s of generation
+        let tps = result.tokens_per_second.unwrap();
+        assert!((tps - 500.0 / 7.8).abs() < 1e-9);
+        assert_eq!(result.avg_total_tokens, (1100.0 + 1300.0 + 1100.0) / 3.0);
+        assert!((result.avg_cost.unwrap() - result.avg_total_tokens * 3.0 / 1e6).abs() < 1e-12);
+
+        let failed = summarize("tool", &[], 2, None);
+        assert_eq!((failed.runs, failed.failures), (2, 2));
+        assert_eq!(failed.tokens_per_second, None);
+    }
+}
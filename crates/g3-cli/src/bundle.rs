@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use g3_config::{bundle, Config};
+use std::path::PathBuf;
+
+/// Share agent profiles, policies and tool settings as a signed bundle
+#[derive(Subcommand, Clone, Debug)]
+pub enum ProfileCommand {
+    /// Bundle the profiles, moderation hooks, scope, plugins, output token limits and Unity
+    /// Catalog settings of the config file, without secrets, signed with the team's key
+    Export {
+        /// Write the bundle here instead of to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// File holding the signing key (default: $G3_BUNDLE_KEY)
+        #[arg(long, value_name = "PATH")]
+        key_file: Option<PathBuf>,
+    },
+    /// Check a bundle's signature and use its settings beneath your own config
+    Import {
+        bundle: PathBuf,
+        /// File holding the signing key (default: $G3_BUNDLE_KEY)
+        #[arg(long, value_name = "PATH")]
+        key_file: Option<PathBuf>,
+    },
+}
+
+pub fn run_profile_command(command: &ProfileCommand, config_path: Option<&str>) -> Result<()> {
+    match command {
+        ProfileCommand::Export { output, key_file } => {
+            let key = bundle::load_key(key_file.as_deref())?;
+            let path = Config::file_path(config_path)
+                .context("No config file to export; pass --config <path>")?;
+            let config = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let export = bundle::export(&config, &key)?;
+
+            for name in &export.removed {
+                eprintln!("🔒 Left out {}, which looks like a secret", name);
+            }
+            match output {
+                Some(output) => {
+                    std::fs::write(output, &export.bundle)
+                        .with_context(|| format!("Failed to write {}", output.display()))?;
+                    eprintln!(
+                        "✅ Exported the settings of {} to {}",
+                        path.display(),
+                        output.display()
+                    );
+                }
+                None => print!("{}", export.bundle),
+            }
+        }
+        ProfileCommand::Import {
+            bundle: bundle_path,
+            key_file,
+        } => {
+            let key = bundle::load_key(key_file.as_deref())?;
+            let contents = std::fs::read_to_string(bundle_path)
+                .with_context(|| format!("Failed to read {}", bundle_path.display()))?;
+            let imported = bundle::import(&contents, &key)?;
+
+            let team = bundle::team_settings_path().context("No home directory")?;
+            if let Some(dir) = team.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let replaced = team.exists();
+            std::fs::write(&team, &imported.settings)
+                .with_context(|| format!("Failed to write {}", team.display()))?;
+
+            println!(
+                "✅ {} team settings in {} ({})",
+                if replaced { "Replaced the" } else { "Imported" },
+                team.display(),
+                imported.sections.join(", ")
+            );
+            if !imported.profiles.is_empty() {
+                println!("   Profiles: {}", imported.profiles.join(", "));
+            }
+            println!("   Settings in your own config file take precedence over them.");
+        }
+    }
+    Ok(())
+}
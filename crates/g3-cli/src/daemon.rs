@@ -0,0 +1,42 @@
+use anyhow::Result;
+use g3_config::Config;
+
+use crate::Cli;
+
+/// `g3 daemon`: keep the configured provider loaded, and load others as g3 runs ask for
+/// them, until interrupted
+#[cfg(unix)]
+pub async fn run_daemon_command(cli: &Cli) -> Result<()> {
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+    let config = Config::load_with_overrides(
+        cli.config.as_deref(),
+        cli.provider.clone(),
+        cli.model.clone(),
+    )?;
+
+    // The daemon reports loading and serving providers
+    let filter = if cli.verbose {
+        EnvFilter::new("g3_core=debug,g3_providers=debug")
+    } else {
+        EnvFilter::new("g3_core=info,llama_cpp=off,llama=off")
+    };
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(filter)
+        .init();
+
+    let socket = g3_core::daemon::socket_path();
+    println!(
+        "🔌 Starting the g3 daemon with {} (Ctrl+C to stop)",
+        config.providers.default_provider
+    );
+    g3_core::daemon::serve(&config, &socket).await?;
+    println!("👋 g3 daemon stopped");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn run_daemon_command(_cli: &Cli) -> Result<()> {
+    anyhow::bail!("g3 daemon needs unix sockets, which this platform doesn't have")
+}
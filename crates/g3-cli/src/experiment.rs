@@ -0,0 +1,406 @@
+//! `g3 experiment recipe.toml`: compare variants of the agent's setup on one task.
+//!
+//! A recipe names a task, the commands that check whether it was done, and variants that
+//! each change the system prompt, provider, model or temperature, with the fields of a
+//! `[profiles.<name>]` entry:
+//!
+//! ```toml
+//! task = "Add a --json flag to the stats command"
+//! verify = ["cargo build", "cargo test"]
+//! runs = 5
+//!
+//! [[variants]]
+//! name = "baseline"
+//!
+//! [[variants]]
+//! name = "plan-first"
+//! system_prompt = "Before editing, write a short plan with todo_write."
+//! temperature = 0.3
+//! ```
+//!
+//! Every run starts from a snapshot of the workspace taken before the first one, with a fresh
+//! agent, and the workspace is put back when the experiment ends. A run succeeds if the task
+//! finished and every verification command exited with 0. Runs alternate between variants,
+//! so a provider getting slower during the experiment doesn't favor one of them.
+
+use anyhow::{anyhow, bail, Context, Result};
+use g3_config::{Config, ProfileConfig};
+use g3_core::workspace_snapshot::WorkspaceSnapshot;
+use g3_core::{AgentBuilder, TurnMetrics};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::bench::{summarize, CaseResult};
+use crate::{check_workspace_policy, Cli};
+
+/// Experiment state directories, relative to the workspace
+const EXPERIMENTS_DIR: &str = ".g3/experiments";
+const RESULTS_FILE: &str = "results.json";
+
+/// An experiment file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Recipe {
+    pub task: String,
+    /// Shell commands run in the workspace after each run; all must exit with 0
+    #[serde(default)]
+    pub verify: Vec<String>,
+    /// Runs of each variant
+    #[serde(default = "default_runs")]
+    pub runs: usize,
+    /// Directory to run in, relative to the recipe (default: the current directory)
+    #[serde(default)]
+    pub workspace: Option<PathBuf>,
+    pub variants: Vec<Variant>,
+}
+
+fn default_runs() -> usize {
+    3
+}
+
+/// A setup to try, as a profile of the config
+#[derive(Debug, Clone, Deserialize)]
+pub struct Variant {
+    pub name: String,
+    #[serde(flatten)]
+    pub profile: ProfileConfig,
+}
+
+impl Recipe {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let recipe: Recipe = toml::from_str(contents).context("Invalid experiment recipe")?;
+        if recipe.task.trim().is_empty() {
+            bail!("The recipe has no task");
+        }
+        if recipe.variants.is_empty() {
+            bail!("The recipe has no variants");
+        }
+        let mut names = HashSet::new();
+        for variant in &recipe.variants {
+            if !names.insert(variant.name.as_str()) {
+                bail!("Duplicate variant '{}'", variant.name);
+            }
+        }
+        Ok(recipe)
+    }
+}
+
+/// How one run of a variant went
+#[derive(Debug, Clone)]
+struct Run {
+    metrics: Option<TurnMetrics>,
+    /// The task error, or the first verification command that failed
+    failure: Option<String>,
+}
+
+/// What a variant achieved over its runs
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantResult {
+    pub name: String,
+    pub successes: usize,
+    /// Runs that failed, with why
+    pub failures: Vec<String>,
+    /// Timing and tokens of the runs whose task finished, verified or not
+    pub metrics: CaseResult,
+}
+
+impl VariantResult {
+    pub fn success_rate(&self) -> f64 {
+        match self.metrics.runs {
+            0 => 0.0,
+            runs => self.successes as f64 / runs as f64,
+        }
+    }
+}
+
+fn variant_result(name: &str, runs: &[Run], price_per_million: Option<f64>) -> VariantResult {
+    let metrics: Vec<TurnMetrics> = runs.iter().filter_map(|r| r.metrics.clone()).collect();
+    let failures: Vec<String> = runs.iter().filter_map(|r| r.failure.clone()).collect();
+    VariantResult {
+        name: name.to_string(),
+        successes: runs.len() - failures.len(),
+        metrics: summarize(
+            name,
+            &metrics,
+            runs.len() - metrics.len(),
+            price_per_million,
+        ),
+        failures,
+    }
+}
+
+/// Run `commands` in `workspace` in order. Returns the first that failed, with its output.
+async fn verify(workspace: &Path, commands: &[String]) -> Result<Option<String>> {
+    for command in commands {
+        let output = tokio::process::Command::new("bash")
+            .arg("-c")
+            .arg(command)
+            .current_dir(workspace)
+            .output()
+            .await
+            .context("Failed to run bash")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let last_line = stderr.lines().rev().find(|l| !l.trim().is_empty());
+            return Ok(Some(match last_line {
+                Some(line) => format!("`{}` failed: {}", command, line.trim()),
+                None => format!("`{}` failed ({})", command, output.status),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Comparison table of the variants, best success rate first
+pub fn format_table(results: &[VariantResult]) -> String {
+    let mut sorted: Vec<&VariantResult> = results.iter().collect();
+    sorted.sort_by(|a, b| b.success_rate().total_cmp(&a.success_rate()));
+    let width = results
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    let mut table = format!(
+        "{:<width$} {:>9} {:>10} {:>9} {:>10} {:>10}\n{}\n",
+        "Variant",
+        "Success",
+        "Time",
+        "TTFT",
+        "Tokens",
+        "Cost",
+        "-".repeat(width + 53),
+    );
+    for result in sorted {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        let metrics = &result.metrics;
+        let finished = metrics.runs - metrics.failures;
+        table.push_str(&format!(
+            "{:<width$} {:>9} {:>10} {:>9} {:>10} {:>10}\n",
+            result.name,
+            format!("{}/{}", result.successes, metrics.runs),
+            optional((finished > 0).then(|| format!("{:.1}s", metrics.wall_ms as f64 / 1000.0))),
+            optional(
+                metrics
+                    .ttft_ms
+                    .map(|ms| format!("{:.2}s", ms as f64 / 1000.0))
+            ),
+            optional((finished > 0).then(|| format!("{:.0}", metrics.avg_total_tokens))),
+            optional(metrics.avg_cost.map(|cost| format!("${:.4}", cost))),
+        ));
+    }
+    table
+}
+
+/// `g3 experiment`: run every variant of the recipe and print how they compare, or JSON
+pub async fn run_experiment_command(
+    cli: &Cli,
+    recipe_file: &Path,
+    runs: Option<usize>,
+    json: bool,
+) -> Result<()> {
+    let contents = std::fs::read_to_string(recipe_file)
+        .with_context(|| format!("Failed to read {}", recipe_file.display()))?;
+    let recipe = Recipe::parse(&contents)?;
+    let runs = runs.unwrap_or(recipe.runs).max(1);
+
+    let mut base = Config::load_with_overrides(
+        cli.config.as_deref(),
+        cli.provider.clone(),
+        cli.model.clone(),
+    )?;
+    let price = base.stats.cost_per_million_tokens;
+    // Experiment runs aren't usage, must not change the repository's history, and nobody is
+    // waiting on a single run
+    base.stats.enabled = false;
+    base.notifications.bell = false;
+    base.notifications.desktop = false;
+    base.agent.commit_edits = false;
+    base.agent.commit_rounds = false;
+    let configs = recipe
+        .variants
+        .iter()
+        .map(|variant| {
+            let mut config = base.clone();
+            config
+                .profiles
+                .insert(variant.name.clone(), variant.profile.clone());
+            config
+                .with_profile(&variant.name)
+                .with_context(|| format!("Invalid variant '{}'", variant.name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let workspace = match &recipe.workspace {
+        Some(workspace) => recipe_file
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(workspace),
+        None => std::env::current_dir()?,
+    };
+    std::env::set_current_dir(&workspace)
+        .with_context(|| format!("Failed to enter {}", workspace.display()))?;
+    // Every run's shell tool works in the workspace, so it is refused where `g3` would be
+    check_workspace_policy(&workspace, &base, cli.allow_dangerous_workspace)?;
+    let name = recipe_file
+        .file_stem()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "experiment".to_string());
+    let dir = workspace.join(EXPERIMENTS_DIR).join(&name);
+    let snapshot = WorkspaceSnapshot::take(&workspace, &dir.join("snapshot"))
+        .map_err(|e| anyhow!("Failed to snapshot the workspace: {}", e))?;
+
+    let mut results: Vec<Vec<Run>> = vec![Vec::new(); recipe.variants.len()];
+    let outcome = async {
+        for run in 1..=runs {
+            for ((variant, config), variant_runs) in
+                recipe.variants.iter().zip(&configs).zip(&mut results)
+            {
+                if !json {
+                    eprint!("\r🧪 {} ({}/{})   ", variant.name, run, runs);
+                }
+                snapshot.restore()?;
+                let mut agent = AgentBuilder::new(config.clone())
+                    .quiet(true)
+                    .build()
+                    .await?;
+                let result = agent
+                    .execute_task_with_timing(&recipe.task, None, false, false, false, false)
+                    .await;
+                variant_runs.push(match result {
+                    Ok(result) => Run {
+                        metrics: Some(result.metrics),
+                        failure: verify(&workspace, &recipe.verify).await?,
+                    },
+                    Err(e) => Run {
+                        metrics: None,
+                        failure: Some(format!("task failed: {}", e)),
+                    },
+                });
+            }
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    let restored = snapshot.restore();
+    snapshot.discard();
+    outcome?;
+    restored.context("Failed to put the workspace back")?;
+
+    let results: Vec<VariantResult> = recipe
+        .variants
+        .iter()
+        .zip(&results)
+        .map(|(variant, runs)| variant_result(&variant.name, runs, price))
+        .collect();
+    std::fs::write(
+        dir.join(RESULTS_FILE),
+        serde_json::to_string_pretty(&results)?,
+    )?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+    eprint!("\r{}\r", " ".repeat(40));
+    println!(
+        "🧪 g3 experiment: {} ({} run{} per variant)",
+        name,
+        runs,
+        if runs == 1 { "" } else { "s" }
+    );
+    println!();
+    print!("{}", format_table(&results));
+    for result in &results {
+        for failure in &result.failures {
+            println!("  {}: {}", result.name, failure);
+        }
+    }
+    println!();
+    println!("Results: {}", dir.join(RESULTS_FILE).display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn finished(wall_ms: u64, failure: Option<&str>) -> Run {
+        Run {
+            metrics: Some(TurnMetrics {
+                turn_number: 1,
+                tokens_used: 2000,
+                wall_clock_time: Duration::from_millis(wall_ms),
+                ..Default::default()
+            }),
+            failure: failure.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_recipe() {
+        let recipe = Recipe::parse(
+            r#"
+            task = "Add a --json flag"
+            verify = ["cargo test"]
+
+            [[variants]]
+            name = "baseline"
+
+            [[variants]]
+            name = "terse"
+            system_prompt = "Keep replies short."
+            model = "claude-haiku-4-5"
+            temperature = 0.2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(recipe.runs, 3);
+        assert_eq!(recipe.verify, vec!["cargo test"]);
+        assert_eq!(recipe.variants[1].name, "terse");
+        assert_eq!(
+            recipe.variants[1].profile.system_prompt.as_deref(),
+            Some("Keep replies short.")
+        );
+        assert_eq!(recipe.variants[1].profile.temperature, Some(0.2));
+
+        assert!(Recipe::parse("task = \"x\"\nvariants = []").is_err());
+        assert!(Recipe::parse(
+            "task = \"x\"\n[[variants]]\nname = \"a\"\n[[variants]]\nname = \"a\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_compare_variants() {
+        let baseline = variant_result(
+            "baseline",
+            &[
+                finished(10_000, None),
+                finished(20_000, Some("`cargo test` failed: 1 failed")),
+                Run {
+                    metrics: None,
+                    failure: Some("task failed: rate limited".to_string()),
+                },
+            ],
+            None,
+        );
+        assert_eq!(baseline.successes, 1);
+        assert_eq!(baseline.metrics.runs, 3);
+        assert_eq!(baseline.metrics.failures, 1);
+        assert_eq!(baseline.failures.len(), 2);
+
+        let terse = variant_result("terse", &[finished(8_000, None)], Some(3.0));
+        assert_eq!(terse.success_rate(), 1.0);
+
+        let table = format_table(&[baseline, terse]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("Variant"));
+        assert!(lines[2].starts_with("terse"));
+        assert!(lines[2].contains("1/1"));
+        assert!(lines[2].contains("$0.0060"));
+        assert!(lines[3].starts_with("baseline"));
+        assert!(lines[3].contains("1/3"));
+    }
+}
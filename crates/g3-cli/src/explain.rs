@@ -0,0 +1,70 @@
+use anyhow::Result;
+use g3_config::Config;
+use g3_core::{
+    artifacts::ArtifactKind, explain, project::ProjectFacts, project_index::ProjectIndex, Agent,
+};
+use std::path::Path;
+
+use crate::ui_writer_impl::ConsoleUiWriter;
+use crate::Cli;
+
+/// `g3 explain [path]`: write an architecture overview of the workspace, or of `path` in it,
+/// with a read-only agent and save it as a session artifact
+pub async fn run_explain_command(cli: &Cli, path: &Path) -> Result<()> {
+    if let Some(workspace) = &cli.workspace {
+        std::env::set_current_dir(workspace)?;
+    }
+    let workspace = std::env::current_dir()?;
+    if !workspace.join(path).exists() {
+        anyhow::bail!(
+            "{} does not exist in {}",
+            path.display(),
+            workspace.display()
+        );
+    }
+    let target = path.to_string_lossy().to_string();
+
+    let mut config = Config::load_with_overrides(
+        cli.config.as_deref(),
+        cli.provider.clone(),
+        cli.model.clone(),
+    )?;
+    config.agent.read_only = true;
+    config
+        .profiles
+        .insert(explain::PROFILE.to_string(), explain::profile());
+    config.agent.profile = Some(explain::PROFILE.to_string());
+    if cli.plain {
+        config.agent.plain_output = true;
+    }
+    let ui_writer = ConsoleUiWriter::new().with_plain(config.agent.plain_output);
+    let mut agent = Agent::new_with_readme_and_quiet(config, ui_writer, None, true).await?;
+
+    // The index gives the agent the lay of the land before it reads anything
+    let index = match ProjectIndex::load(&workspace) {
+        Some(index) => index,
+        None => {
+            println!("🗺️  Indexing {}...", workspace.display());
+            agent.index_project(false).await?
+        }
+    };
+    let facts = ProjectFacts::detect(&workspace).render();
+
+    println!("🔎 Exploring {}...", target);
+    let prompt = explain::task_prompt(&target, Some(&index.render()), facts.as_deref());
+    let result = agent
+        .execute_task_with_timing(&prompt, None, false, false, false, false)
+        .await?;
+
+    let overview = explain::document(&target, &result.response);
+    let path = agent.artifacts().save(
+        ArtifactKind::Explanation,
+        "architecture.md",
+        overview.as_bytes(),
+        Some("explain"),
+    )?;
+
+    println!();
+    println!("Saved to {}", path.display());
+    Ok(())
+}
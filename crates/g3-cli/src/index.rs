@@ -0,0 +1,34 @@
+use anyhow::Result;
+use g3_config::Config;
+use g3_core::AgentBuilder;
+
+use crate::Cli;
+
+/// `g3 index`: build the project index that new sessions in the workspace start with
+pub async fn run_index_command(cli: &Cli, refresh: bool) -> Result<()> {
+    if let Some(workspace) = &cli.workspace {
+        std::env::set_current_dir(workspace)?;
+    }
+
+    let mut config = Config::load_with_overrides(
+        cli.config.as_deref(),
+        cli.provider.clone(),
+        cli.model.clone(),
+    )?;
+    if let Some(profile) = &cli.profile {
+        config.agent.profile = Some(profile.clone());
+    }
+    let agent = AgentBuilder::new(config).quiet(true).build().await?;
+
+    println!("🗺️  Indexing {}...", std::env::current_dir()?.display());
+    let index = agent.index_project(refresh).await?;
+
+    println!();
+    println!("{}", index.render());
+    println!();
+    println!(
+        "Saved to {}. New sessions in this workspace start with it.",
+        g3_core::project_index::INDEX_FILE
+    );
+    Ok(())
+}
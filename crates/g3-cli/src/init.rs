@@ -0,0 +1,564 @@
+//! `g3 init`: an interactive walk through creating a config file.
+//!
+//! Asks for a provider, its credentials and model, checks them with a test request, asks
+//! which optional subsystems to enable and writes a commented config file.
+
+use anyhow::{bail, Context, Result};
+use g3_config::{secrets, AnthropicConfig, Config, DatabricksConfig, EmbeddedConfig, OpenAIConfig};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::auth::read_secret;
+
+/// Embedded model families and their context window sizes
+const EMBEDDED_MODEL_TYPES: &[(&str, u32)] = &[
+    ("qwen", 32768),
+    ("codellama", 16384),
+    ("mistral", 8192),
+    ("llama", 4096),
+];
+
+/// A credential and how the config file refers to it
+#[derive(Debug, Clone)]
+struct Secret {
+    /// What the config file holds: the key itself or an `env:`/`keyring:` reference
+    reference: String,
+    /// The key, for the test request; `None` when it can't be read here
+    value: Option<String>,
+    /// Store `value` in the OS keyring under this account when the config is written
+    keyring_account: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Provider {
+    Anthropic {
+        api_key: Secret,
+        model: String,
+    },
+    OpenAI {
+        api_key: Secret,
+        model: String,
+        base_url: Option<String>,
+    },
+    Databricks {
+        host: String,
+        /// `None` to sign in with OAuth
+        token: Option<Secret>,
+        model: String,
+    },
+    Embedded {
+        model_path: String,
+        model_type: String,
+        context_length: u32,
+    },
+}
+
+impl Provider {
+    fn name(&self) -> &'static str {
+        match self {
+            Provider::Anthropic { .. } => "anthropic",
+            Provider::OpenAI { .. } => "openai",
+            Provider::Databricks { .. } => "databricks",
+            Provider::Embedded { .. } => "embedded",
+        }
+    }
+
+    /// A config with only this provider, using the credentials themselves
+    fn test_config(&self) -> Result<Config> {
+        fn value(secret: &Secret) -> Result<String> {
+            secret.value.clone().with_context(|| {
+                format!(
+                    "{} can't be read here, so it can't be tested",
+                    secret.reference
+                )
+            })
+        }
+
+        let mut config = Config::default();
+        let providers = &mut config.providers;
+        providers.databricks = None;
+        providers.default_provider = self.name().to_string();
+        match self {
+            Provider::Anthropic { api_key, model } => {
+                providers.anthropic = Some(AnthropicConfig {
+                    api_key: value(api_key)?,
+                    model: model.clone(),
+                    max_tokens: None,
+                    temperature: Some(0.1),
+                });
+            }
+            Provider::OpenAI {
+                api_key,
+                model,
+                base_url,
+            } => {
+                providers.openai = Some(OpenAIConfig {
+                    api_key: value(api_key)?,
+                    model: model.clone(),
+                    base_url: base_url.clone(),
+                    max_tokens: None,
+                    temperature: Some(0.1),
+                    api: None,
+                    reasoning_effort: None,
+                    context_length: None,
+                    compress_requests: false,
+                });
+            }
+            Provider::Databricks { host, token, model } => {
+                providers.databricks = Some(DatabricksConfig {
+                    host: host.clone(),
+                    token: token.as_ref().map(value).transpose()?,
+                    model: model.clone(),
+                    max_tokens: None,
+                    temperature: Some(0.1),
+                    use_oauth: Some(token.is_none()),
+                    compress_requests: false,
+                });
+            }
+            Provider::Embedded {
+                model_path,
+                model_type,
+                context_length,
+            } => {
+                providers.embedded = Some(EmbeddedConfig {
+                    model_path: shellexpand::tilde(model_path).into_owned(),
+                    model_type: model_type.clone(),
+                    context_length: Some(*context_length),
+                    max_tokens: Some(2048),
+                    temperature: Some(0.1),
+                    gpu_layers: None,
+                    threads: None,
+                    constrained_tool_calls: false,
+                });
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Answers {
+    provider: Provider,
+    webdriver: bool,
+    macax: bool,
+    computer_control: bool,
+}
+
+pub async fn run_init_command(config_path: Option<&str>) -> Result<()> {
+    let path = match config_path {
+        Some(path) => PathBuf::from(shellexpand::tilde(path).as_ref()),
+        None => dirs::home_dir()
+            .context("No home directory; pass --config <path>")?
+            .join(".config/g3/config.toml"),
+    };
+
+    println!("🛠️  Setting up g3. Press Enter to accept the [default] of a question.\n");
+    if path.exists()
+        && !confirm(
+            &format!("{} exists. Replace it? A backup is kept", path.display()),
+            false,
+        )?
+    {
+        return Ok(());
+    }
+
+    let provider = loop {
+        let provider = ask_provider()?;
+        let is_embedded = matches!(provider, Provider::Embedded { .. });
+        let prompt = if is_embedded {
+            "Load the model and send a test request?"
+        } else {
+            "Send a test request to check these settings?"
+        };
+        if !confirm(prompt, !is_embedded)? {
+            break provider;
+        }
+        println!("⏳ Sending a test request...");
+        let result = match provider.test_config() {
+            Ok(config) => g3_core::check_provider(&config).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(reply) => {
+                println!("✅ The model answered: {}\n", reply.trim());
+                break provider;
+            }
+            Err(e) => {
+                println!("❌ Test request failed: {}", e);
+                if !confirm("Change the settings and try again?", true)? {
+                    break provider;
+                }
+            }
+        }
+    };
+
+    println!("\nOptional tools (they can be turned on later in the config file):");
+    let (webdriver, macax) = if cfg!(target_os = "macos") {
+        (
+            confirm("Enable Safari automation with WebDriver?", false)?,
+            confirm(
+                "Enable the macOS Accessibility tools for native apps?",
+                false,
+            )?,
+        )
+    } else {
+        (false, false)
+    };
+    let computer_control = confirm(
+        "Enable computer control (mouse, keyboard, screenshots, OCR)?",
+        false,
+    )?;
+
+    let answers = Answers {
+        provider,
+        webdriver,
+        macax,
+        computer_control,
+    };
+    write_config(&path, &answers)?;
+
+    println!("\n✅ Wrote {}", path.display());
+    if answers.webdriver {
+        println!("   For WebDriver, run `safaridriver --enable` once and turn on Develop → Allow Remote Automation in Safari.");
+    }
+    if answers.computer_control || answers.macax {
+        println!("   Grant your terminal Accessibility permission in System Settings → Privacy & Security.");
+    }
+    println!("   Run `g3` in a project directory to start.");
+    Ok(())
+}
+
+fn ask_provider() -> Result<Provider> {
+    let choice = choose(
+        "Which provider should g3 use?",
+        &[
+            ("anthropic", "Claude models through the Anthropic API"),
+            ("openai", "OpenAI, or any OpenAI-compatible API"),
+            ("databricks", "Models served by a Databricks workspace"),
+            ("embedded", "A local GGUF model, run in-process"),
+        ],
+    )?;
+    let provider = match choice {
+        0 => Provider::Anthropic {
+            api_key: ask_secret("anthropic", "ANTHROPIC_API_KEY")?,
+            model: ask("Model", "claude-sonnet-4-5")?,
+        },
+        1 => {
+            let base_url = ask("API base URL (blank for api.openai.com)", "")?;
+            Provider::OpenAI {
+                api_key: ask_secret("openai", "OPENAI_API_KEY")?,
+                model: ask("Model", "gpt-5")?,
+                base_url: (!base_url.is_empty()).then_some(base_url),
+            }
+        }
+        2 => {
+            let host = ask(
+                "Workspace URL",
+                "https://your-workspace.cloud.databricks.com",
+            )?;
+            let token = if confirm(
+                "Sign in with OAuth in the browser instead of a token?",
+                true,
+            )? {
+                None
+            } else {
+                Some(ask_secret("databricks", "DATABRICKS_TOKEN")?)
+            };
+            Provider::Databricks {
+                host,
+                token,
+                model: ask("Model serving endpoint", "databricks-claude-sonnet-4")?,
+            }
+        }
+        _ => {
+            let model_path = ask(
+                "Model file",
+                "~/.cache/g3/models/qwen2.5-7b-instruct-q3_k_m.gguf",
+            )?;
+            let names: Vec<(&str, &str)> = EMBEDDED_MODEL_TYPES
+                .iter()
+                .map(|(name, _)| (*name, ""))
+                .collect();
+            let (model_type, context_length) =
+                EMBEDDED_MODEL_TYPES[choose("Model family", &names)?];
+            Provider::Embedded {
+                model_path,
+                model_type: model_type.to_string(),
+                context_length,
+            }
+        }
+    };
+    Ok(provider)
+}
+
+/// Ask for a provider's key and where to keep it
+fn ask_secret(provider: &str, env_var: &str) -> Result<Secret> {
+    let choice = choose(
+        "Where should the key be kept?",
+        &[
+            ("keyring", "in the OS keyring (recommended)"),
+            ("env", "in an environment variable"),
+            ("config", "in the config file, as plain text"),
+        ],
+    )?;
+    if choice == 1 {
+        let var = ask("Environment variable", env_var)?;
+        let value = std::env::var(&var).ok();
+        if value.is_none() {
+            println!("⚠️  {} is not set in this shell", var);
+        }
+        return Ok(Secret {
+            reference: format!("env:{}", var),
+            value,
+            keyring_account: None,
+        });
+    }
+
+    if choice == 0 {
+        if let Some(stored) = secrets::get_keyring_secret(provider).ok().flatten() {
+            if confirm(
+                &format!("Use the key already stored for {}?", provider),
+                true,
+            )? {
+                return Ok(Secret {
+                    reference: "keyring:".to_string(),
+                    value: Some(stored),
+                    keyring_account: None,
+                });
+            }
+        }
+    }
+    let key = read_secret(&format!("API key for {}: ", provider))?;
+    if key.is_empty() {
+        bail!("No API key entered for '{}'", provider);
+    }
+    Ok(match choice {
+        0 => Secret {
+            reference: "keyring:".to_string(),
+            value: Some(key),
+            keyring_account: Some(provider.to_string()),
+        },
+        _ => Secret {
+            reference: key.clone(),
+            value: Some(key),
+            keyring_account: None,
+        },
+    })
+}
+
+/// Store keys in the keyring, keep a backup of an existing config and write the new one
+fn write_config(path: &Path, answers: &Answers) -> Result<()> {
+    let secret = match &answers.provider {
+        Provider::Anthropic { api_key, .. } | Provider::OpenAI { api_key, .. } => Some(api_key),
+        Provider::Databricks { token, .. } => token.as_ref(),
+        Provider::Embedded { .. } => None,
+    };
+    if let Some(Secret {
+        value: Some(value),
+        keyring_account: Some(account),
+        ..
+    }) = secret
+    {
+        secrets::store_keyring_secret(account, value)?;
+        println!("🔑 Stored the key for {} in the OS keyring", account);
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if path.exists() {
+        let backup = path.with_extension("toml.bak");
+        std::fs::copy(path, &backup)?;
+        println!("📦 Saved the previous config as {}", backup.display());
+    }
+    std::fs::write(path, render(answers))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    // Loading resolves the key references, which would fail here on a typo
+    Config::load(path.to_str())
+        .with_context(|| format!("{} was written but doesn't load", path.display()))?;
+    Ok(())
+}
+
+/// The config file for `answers`, with comments explaining each setting
+fn render(answers: &Answers) -> String {
+    let secret = |name: &str, secret: &Secret, provider: &str| {
+        let comment = if secret.reference.starts_with("keyring:") {
+            format!("From the OS keyring, see `g3 auth login {}`", provider)
+        } else if secret.reference.starts_with("env:") {
+            "Read from the environment".to_string()
+        } else {
+            format!(
+                "Plain text; `g3 auth login {}` keeps it out of this file",
+                provider
+            )
+        };
+        format!("{} = {}  # {}\n", name, quote(&secret.reference), comment)
+    };
+
+    let mut out = String::from(
+        "# g3 configuration, written by `g3 init`.\n\
+         # See config.example.toml in the g3 repository for every setting.\n\n",
+    );
+    out.push_str(&format!(
+        "[providers]\ndefault_provider = {}\n\n",
+        quote(answers.provider.name())
+    ));
+    match &answers.provider {
+        Provider::Anthropic { api_key, model } => {
+            out.push_str("[providers.anthropic]\n");
+            out.push_str(&secret("api_key", api_key, "anthropic"));
+            out.push_str(&format!("model = {}\ntemperature = 0.1\n", quote(model)));
+        }
+        Provider::OpenAI {
+            api_key,
+            model,
+            base_url,
+        } => {
+            out.push_str("[providers.openai]\n");
+            out.push_str(&secret("api_key", api_key, "openai"));
+            out.push_str(&format!("model = {}\n", quote(model)));
+            match base_url {
+                Some(url) => out.push_str(&format!("base_url = {}\n", quote(url))),
+                None => out.push_str(
+                    "# base_url = \"https://openrouter.ai/api/v1\"  # For OpenAI-compatible APIs\n",
+                ),
+            }
+            out.push_str("temperature = 0.1\n");
+        }
+        Provider::Databricks { host, token, model } => {
+            out.push_str("[providers.databricks]\n");
+            out.push_str(&format!("host = {}\n", quote(host)));
+            match token {
+                Some(token) => {
+                    out.push_str(&secret("token", token, "databricks"));
+                    out.push_str("use_oauth = false\n");
+                }
+                None => out.push_str("use_oauth = true  # Sign in through the browser\n"),
+            }
+            out.push_str(&format!("model = {}\ntemperature = 0.1\n", quote(model)));
+        }
+        Provider::Embedded {
+            model_path,
+            model_type,
+            context_length,
+        } => {
+            out.push_str("[providers.embedded]\n");
+            out.push_str(&format!("model_path = {}\n", quote(model_path)));
+            out.push_str(&format!("model_type = {}\n", quote(model_type)));
+            out.push_str(&format!(
+                "context_length = {}\nmax_tokens = 2048  # Tokens generated per response\ntemperature = 0.1\n# gpu_layers = 32  # Layers to offload to the GPU\n",
+                context_length
+            ));
+        }
+    }
+
+    out.push_str(
+        "\n[agent]\n\
+         fallback_default_max_tokens = 8192\n\
+         enable_streaming = true\n\
+         timeout_seconds = 60\n\
+         auto_compact = true                # Summarize the conversation when the context window fills up\n\
+         max_retry_attempts = 3             # Retries of timeouts, rate limits and server errors\n\
+         autonomous_max_retry_attempts = 6  # Retries in autonomous mode\n",
+    );
+    out.push_str(&format!(
+        "\n[webdriver]\n\
+         enabled = {}  # Safari automation with the webdriver_* tools (macOS)\n\
+         safari_port = 4444\n\
+         \n[macax]\n\
+         enabled = {}  # Accessibility API tools for native macOS apps\n\
+         \n[computer_control]\n\
+         enabled = {}  # Mouse, keyboard, screenshot and OCR tools (needs OS permissions)\n\
+         require_confirmation = true\n\
+         max_actions_per_second = 5\n",
+        answers.webdriver, answers.macax, answers.computer_control
+    ));
+    out
+}
+
+/// A TOML string; JSON string escapes are valid TOML
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+pub(crate) fn ask(prompt: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{}: ", prompt);
+    } else {
+        print!("{} [{}]: ", prompt, default);
+    }
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    if std::io::stdin().lock().read_line(&mut line)? == 0 {
+        bail!("No more input; nothing was written");
+    }
+    let answer = line.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+fn confirm(prompt: &str, default: bool) -> Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    let answer = ask(&format!("{} ({})", prompt, hint), "")?.to_lowercase();
+    Ok(match answer.as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    })
+}
+
+/// Ask for one of `options`, by number or name. Returns its index.
+fn choose(prompt: &str, options: &[(&str, &str)]) -> Result<usize> {
+    println!("{}", prompt);
+    for (i, (name, description)) in options.iter().enumerate() {
+        if description.is_empty() {
+            println!("  {}. {}", i + 1, name);
+        } else {
+            println!("  {}. {} - {}", i + 1, name, description);
+        }
+    }
+    loop {
+        let answer = ask("Choice", "1")?;
+        let index = match answer.parse::<usize>() {
+            Ok(n) => n.checked_sub(1),
+            Err(_) => options.iter().position(|(name, _)| *name == answer),
+        };
+        match index {
+            Some(index) if index < options.len() => return Ok(index),
+            _ => println!("Enter a number from 1 to {}", options.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rendered_config_loads() {
+        let path = std::env::temp_dir().join(format!("g3-init-{}.toml", std::process::id()));
+        let answers = Answers {
+            provider: Provider::Anthropic {
+                api_key: Secret {
+                    reference: "sk-\"quoted\"".to_string(),
+                    value: None,
+                    keyring_account: None,
+                },
+                model: "claude-sonnet-4-5".to_string(),
+            },
+            webdriver: false,
+            macax: false,
+            computer_control: true,
+        };
+        std::fs::write(&path, render(&answers)).unwrap();
+        let config = Config::load(path.to_str()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(config.providers.default_provider, "anthropic");
+        let anthropic = config.providers.anthropic.unwrap();
+        assert_eq!(anthropic.api_key, "sk-\"quoted\"");
+        assert_eq!(anthropic.model, "claude-sonnet-4-5");
+        assert!(config.computer_control.enabled);
+        assert!(!config.webdriver.enabled);
+    }
+}
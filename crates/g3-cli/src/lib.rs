@@ -1,12 +1,18 @@
 use anyhow::Result;
 use crossterm::style::{Color, SetForegroundColor, ResetColor};
-use std::time::{Duration, Instant};
-
-#[derive(Debug, Clone)]
-struct TurnMetrics {
-    turn_number: usize,
-    tokens_used: u32,
-    wall_clock_time: Duration,
+use g3_core::TurnMetrics;
+use std::time::Duration;
+
+/// Combine the player and coach task metrics recorded by the agents into one autonomous turn
+fn autonomous_turn_metrics(turn: usize, player: &[TurnMetrics], coach: &[TurnMetrics]) -> TurnMetrics {
+    let mut metrics = TurnMetrics {
+        turn_number: turn,
+        ..Default::default()
+    };
+    for task in player.iter().chain(coach) {
+        metrics.add(task);
+    }
+    metrics
 }
 
 /// Generate a histogram showing tokens used and wall clock time per turn
@@ -88,89 +94,101 @@ fn generate_turn_histogram(turn_metrics: &[TurnMetrics]) -> String {
     let total_time: Duration = turn_metrics.iter().map(|t| t.wall_clock_time).sum();
     let avg_tokens = total_tokens as f64 / turn_metrics.len() as f64;
     let avg_time_ms = total_time.as_millis() as f64 / turn_metrics.len() as f64;
+    let total_tool_calls: usize = turn_metrics.iter().map(|t| t.tool_calls).sum();
+    let failed_tool_calls: usize = turn_metrics.iter().map(|t| t.failed_tool_calls).sum();
     
     histogram.push_str("\n📈 Summary Statistics:\n");
     histogram.push_str(&format!("   • Total Tokens: {} across {} turns\n", total_tokens, turn_metrics.len()));
     histogram.push_str(&format!("   • Average Tokens/Turn: {:.1}\n", avg_tokens));
     histogram.push_str(&format!("   • Total Time: {:.1}s\n", total_time.as_secs_f64()));
     histogram.push_str(&format!("   • Average Time/Turn: {:.1}s\n", avg_time_ms / 1000.0));
+    histogram.push_str(&format!("   • Tool Calls: {} ({} failed)\n", total_tool_calls, failed_tool_calls));
     
     histogram
 }
 
-/// Extract coach feedback by reading from the coach agent's specific log file
-/// Uses the coach agent's session ID to find the exact log file
-fn extract_coach_feedback_from_logs(
-    coach_result: &g3_core::TaskResult,
-    coach_agent: &g3_core::Agent<ConsoleUiWriter>,
-    output: &SimpleOutput,
-) -> Result<String> {
-    // CORRECT APPROACH: Get the session ID from the current coach agent
-    // and read its specific log file directly
-
-    // Get the coach agent's session ID
-    let session_id = coach_agent
-        .get_session_id()
-        .ok_or_else(|| anyhow::anyhow!("Coach agent has no session ID"))?;
-
-    // Construct the log file path for this specific coach session
-    let logs_dir = std::path::Path::new("logs");
-    let log_file_path = logs_dir.join(format!("g3_session_{}.json", session_id));
-
-    // Read the coach agent's specific log file
-    if log_file_path.exists() {
-        if let Ok(log_content) = std::fs::read_to_string(&log_file_path) {
-            if let Ok(log_json) = serde_json::from_str::<serde_json::Value>(&log_content) {
-                if let Some(context_window) = log_json.get("context_window") {
-                    if let Some(conversation_history) = context_window.get("conversation_history") {
-                        if let Some(messages) = conversation_history.as_array() {
-                            // Simply get the last message content - this is the coach's final feedback
-                            if let Some(last_message) = messages.last() {
-                                if let Some(content) = last_message.get("content") {
-                                    if let Some(content_str) = content.as_str() {
-                                        output.print(&format!(
-                                            "✅ Extracted coach feedback from session: {}",
-                                            session_id
-                                        ));
-                                        return Ok(content_str.to_string());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Get the coach's feedback for the player from its give_feedback call, falling back to
+/// its final_output summary if it ended the turn without giving structured feedback
+fn coach_feedback_from_result(coach_result: &g3_core::TaskResult) -> String {
+    match &coach_result.feedback {
+        Some(feedback) => feedback.to_string(),
+        None => coach_result.extract_final_output(),
     }
+}
 
-    // If we couldn't extract from logs, panic with detailed error
-    panic!(
-        "CRITICAL: Could not extract coach feedback from session: {}\n\
-         Log file path: {:?}\n\
-         Log file exists: {}\n\
-         This indicates the coach did not call any tool or the log is corrupted.\n\
-         Coach result response length: {} chars",
-        session_id,
-        log_file_path,
-        log_file_path.exists(),
-        coach_result.response.len()
-    );
+/// The critic prompt with which the player reviews its own changes, with `--self-review`
+fn self_review_prompt(requirements: &str, changes: &str) -> String {
+    let changes = if changes.is_empty() {
+        String::new()
+    } else {
+        format!("\nFILES YOU CHANGED THIS TURN (+ created, ~ modified, - deleted):\n{}\n", changes)
+    };
+    format!(
+        "Before this work is handed over, review it yourself as a strict critic would.
+
+REQUIREMENTS (the acceptance criteria):
+{}
+{}
+Call show_changes to re-read the full diff of this session and check it against every requirement:
+1. Is each requirement implemented correctly and completely?
+2. Does the project build, and do its tests pass? Run them if you haven't since your last edit.
+3. Is anything left unfinished: TODOs, stubs, placeholder values, missing error handling or tests?
+
+Do not change any files during this review. Then call give_feedback:
+- verdict \"approved\" if every requirement is met and the project builds
+- otherwise verdict \"changes_requested\", one concrete miss per item in issues, and next_steps saying what to do about them; you will carry on working from there
+
+Example:
+{{\"tool\": \"give_feedback\", \"args\": {{\"verdict\": \"changes_requested\", \"issues\": [\"The --json flag is parsed but never used\"], \"next_steps\": \"Print the results as JSON when --json is set and add a test for it.\"}}}}
+
+Don't approve out of optimism: approve only what you have checked.",
+        requirements, changes
+    )
 }
 
 use clap::Parser;
 use g3_config::Config;
-use g3_core::{project::Project, ui_writer::UiWriter, Agent};
+use g3_core::{
+    g3ignore::G3Ignore, project::{Project, ProjectFacts}, project_index::ProjectIndex, run_commits::RunRecord, ui_writer::UiWriter,
+    workspace_guard::check_workspace, Agent,
+};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
+use std::io::IsTerminal;
 use std::path::Path;
 use std::path::PathBuf;
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
-use g3_core::error_handling::{classify_error, ErrorType, RecoverableError};
+use g3_core::error_handling::{
+    classify_error, ErrorType, G3Error, RecoverableError, CONTENT_FILTER_ADVICE,
+};
+mod auth;
+use auth::AuthCommand;
+mod batch;
+mod bench;
+mod bundle;
+use bundle::ProfileCommand;
+mod daemon;
+mod experiment;
+mod explain;
+mod index;
+mod init;
+mod prompt_editor;
+mod replay_stream;
+mod review_run;
+mod sessions;
+use sessions::SessionsCommand;
+mod stats;
+mod step;
+mod summarize;
+mod tools;
 mod ui_writer_impl;
+mod autonomous_view;
+use autonomous_view::{AutonomousView, Role, ViewWriter};
 mod simple_output;
 use simple_output::SimpleOutput;
+mod stream_flush;
 mod machine_ui_writer;
 use machine_ui_writer::MachineUiWriter;
 use ui_writer_impl::ConsoleUiWriter;
@@ -246,13 +264,302 @@ pub struct Cli {
     /// Enable WebDriver browser automation tools
     #[arg(long)]
     pub webdriver: bool,
+
+    /// Explore without modifying anything: removes file-editing tools and blocks mutating shell commands
+    #[arg(long)]
+    pub read_only: bool,
+
+    /// Start with a profile from [profiles] in the config (system prompt additions, tools, model)
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Generate a commit message, PR description and CHANGELOG entry when the session ends
+    #[arg(long)]
+    pub summarize_on_exit: bool,
+
+    /// Autonomous mode: snapshot the workspace before a player round changes it and restore
+    /// the snapshot before a failed round is retried
+    #[arg(long)]
+    pub snapshot_rounds: bool,
+
+    /// Autonomous mode: commit each player round to a branch of the run's own, for review
+    /// with `g3 review-run`
+    #[arg(long)]
+    pub commit_rounds: bool,
+
+    /// Autonomous mode: the player reviews its own changes against the requirements instead
+    /// of a coach, which is cheaper for small tasks
+    #[arg(long)]
+    pub self_review: bool,
+
+    /// Commit each file edit to a session branch, with a one-line message the model writes
+    #[arg(long)]
+    pub commit_edits: bool,
+
+    /// Save the chunks of each provider response, for `g3 replay-stream`
+    #[arg(long)]
+    pub record_streams: bool,
+
+    /// Pause before each tool call, showing its arguments, to run, edit, skip or abort it.
+    /// Only in the console, not with --machine or the TUI.
+    #[arg(long)]
+    pub step: bool,
+
+    /// Run shell commands, read_file and write_file on this SSH host ([user@]host or an
+    /// ~/.ssh/config alias) instead of this machine
+    #[arg(long, value_name = "HOST")]
+    pub remote: Option<String>,
+
+    /// Run shell commands in the project's dev container (devcontainer.json), building and
+    /// starting it if needed
+    #[arg(long)]
+    pub devcontainer: bool,
+
+    /// Rebuild the dev container's image and recreate the container first (implies
+    /// --devcontainer)
+    #[arg(long)]
+    pub devcontainer_rebuild: bool,
+
+    /// Stop and remove the project's dev container, then exit
+    #[arg(long)]
+    pub devcontainer_stop: bool,
+
+    /// Run even as root, in the home directory or in a protected directory such as /etc,
+    /// where the shell tool could do lasting damage
+    #[arg(long)]
+    pub allow_dangerous_workspace: bool,
+
+    /// Load providers in this process even when a g3 daemon is running
+    #[arg(long)]
+    pub no_daemon: bool,
+
+    /// Screen-reader friendly output: ASCII tags like [OK]/[ERR] instead of emoji, and no
+    /// box drawing or redrawn lines
+    #[arg(long)]
+    pub plain: bool,
+
+    /// Show an autonomous run full screen: the turn, the acting role, the coach's last
+    /// verdict and a live tail of the player's and coach's output
+    #[arg(long, requires = "autonomous")]
+    pub tui: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum Command {
+    /// Manage provider API keys in the OS keyring
+    Auth {
+        #[command(subcommand)]
+        action: AuthCommand,
+    },
+    /// Create a config file by answering a few questions, with a test request to the
+    /// chosen provider
+    Init,
+    /// Measure time to first token, throughput and cost of a provider and model on a few
+    /// standard prompts (short answer, long generation, tool call round trip)
+    Bench {
+        /// Provider to benchmark instead of the configured default
+        #[arg(long, value_name = "PROVIDER")]
+        provider: Option<String>,
+        /// Model to benchmark
+        #[arg(long, value_name = "MODEL")]
+        model: Option<String>,
+        /// Runs of each prompt
+        #[arg(long, default_value = "3")]
+        runs: usize,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run a task several times with each variant (system prompt, provider, model,
+    /// temperature) of a TOML recipe, check each run with the recipe's verification commands
+    /// and compare the variants
+    Experiment {
+        /// Recipe file
+        recipe: PathBuf,
+        /// Runs of each variant, instead of the recipe's `runs`
+        #[arg(long)]
+        runs: Option<usize>,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Keep providers and embedded models loaded for g3 runs to share over a unix socket
+    /// ($G3_DAEMON_SOCKET or ~/.config/g3/daemon.sock), instead of each run loading its own
+    Daemon,
+    /// Write an architecture overview (modules, data flow, key types, build and test entry
+    /// points) of the project or a path in it, exploring read-only, and save it as an artifact
+    Explain {
+        /// Directory or file to explain, relative to the workspace
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
+    /// Map the project (structure, entry points, build and test commands, a one-line
+    /// description of each directory) and save it to .g3/index.json for new sessions
+    Index {
+        /// Describe every directory again instead of keeping the previous descriptions
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Feed a provider stream recorded with --record-streams through the stream parser and
+    /// show what it made of each chunk, to reproduce parsing bugs offline
+    ReplayStream {
+        /// The recording, a stream.jsonl.gz artifact in .g3/sessions/<id>/artifacts/
+        artifact: PathBuf,
+    },
+    /// Go through the rounds an autonomous run with --commit-rounds committed, accepting,
+    /// rejecting or squashing each, and build a clean branch from the choices
+    ReviewRun {
+        /// Id of the run, as printed at the end of it
+        run_id: String,
+    },
+    /// Export or import a signed bundle of agent profiles, policies and tool settings, to
+    /// share a team's setup across machines and CI
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+    /// Compress and prune old session logs
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsCommand,
+    },
+    /// Show local usage stats (last 7 days unless --all-time)
+    Stats {
+        /// Aggregate every recorded task instead of the last 7 days
+        #[arg(long)]
+        all_time: bool,
+    },
+    /// Generate a commit message, PR description and CHANGELOG entry for the changes made
+    /// since the last session started
+    SummarizeChanges {
+        /// Diff against this git revision instead of the session's starting point
+        #[arg(long, value_name = "REV")]
+        since: Option<String>,
+        /// Use the starting point of this session instead of the most recent one
+        #[arg(long, value_name = "ID", conflicts_with = "since")]
+        session: Option<String>,
+    },
+    /// List the built-in tools with their versions and capabilities
+    Tools {
+        /// Print the full specs, including input schemas, as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run the tasks in a JSONL file ({"id", "task", "workspace", "worktree"} per line), each
+    /// in its own g3 process, resuming the previous run's progress
+    Batch {
+        /// Tasks file, one JSON object per line
+        tasks: PathBuf,
+        /// Number of tasks to run at the same time
+        #[arg(long, default_value = "1")]
+        parallel: usize,
+        /// Run each task in a new git worktree of its workspace, on its own branch
+        #[arg(long)]
+        worktree: bool,
+        /// Run tasks that failed in a previous run again
+        #[arg(long)]
+        retry_failed: bool,
+        /// Ignore the previous run's progress and run every task
+        #[arg(long)]
+        restart: bool,
+    },
+}
+
+/// Fail if the workspace is dangerous to run in, unless explicitly allowed, in which case
+/// each danger is only warned about
+pub(crate) fn check_workspace_policy(workspace: &Path, config: &Config, allow: bool) -> Result<()> {
+    let dangers = check_workspace(workspace, &config.agent.protected_workspaces);
+    if dangers.is_empty() {
+        return Ok(());
+    }
+    if allow {
+        for danger in &dangers {
+            eprintln!("⚠️  Running anyway: {}", danger);
+        }
+        return Ok(());
+    }
+
+    let reasons: Vec<String> = dangers.iter().map(|d| d.to_string()).collect();
+    Err(anyhow::anyhow!(
+        "Refusing to start: {}. The shell tool runs with your full permissions, so g3 only \
+         runs in a project directory as a regular user. Pass --allow-dangerous-workspace to \
+         run anyway.",
+        reasons.join("; ")
+    ))
 }
 
 pub async fn run() -> Result<()> {
     let cli = Cli::parse();
 
+    // Subcommands run standalone, without a workspace or agent
+    match &cli.command {
+        Some(Command::Auth { action }) => return auth::run_auth_command(action),
+        Some(Command::Stats { all_time }) => {
+            return stats::run_stats_command(*all_time, cli.config.as_deref())
+        }
+        Some(Command::Profile { action }) => {
+            return bundle::run_profile_command(action, cli.config.as_deref())
+        }
+        Some(Command::Sessions { action }) => {
+            return sessions::run_sessions_command(action, cli.config.as_deref())
+        }
+        Some(Command::Init) => return init::run_init_command(cli.config.as_deref()).await,
+        Some(Command::Bench {
+            provider,
+            model,
+            runs,
+            json,
+        }) => {
+            return bench::run_bench_command(&cli, provider.clone(), model.clone(), *runs, *json)
+                .await
+        }
+        Some(Command::Experiment { recipe, runs, json }) => {
+            return experiment::run_experiment_command(&cli, recipe, *runs, *json).await
+        }
+        Some(Command::Daemon) => return daemon::run_daemon_command(&cli).await,
+        Some(Command::Explain { path }) => return explain::run_explain_command(&cli, path).await,
+        Some(Command::Index { refresh }) => return index::run_index_command(&cli, *refresh).await,
+        Some(Command::Tools { json }) => return tools::run_tools_command(*json),
+        Some(Command::ReplayStream { artifact }) => {
+            return replay_stream::run_replay_stream_command(artifact)
+        }
+        Some(Command::ReviewRun { run_id }) => {
+            return review_run::run_review_run_command(&cli, run_id)
+        }
+        Some(Command::Batch {
+            tasks,
+            parallel,
+            worktree,
+            retry_failed,
+            restart,
+        }) => {
+            let options = batch::BatchOptions {
+                parallel: *parallel,
+                worktree: *worktree,
+                retry_failed: *retry_failed,
+                restart: *restart,
+            };
+            return batch::run_batch_command(&cli, tasks, options).await;
+        }
+        Some(Command::SummarizeChanges { since, session }) => {
+            return summarize::run_summarize_changes_command(
+                &cli,
+                since.as_deref(),
+                session.as_deref(),
+            )
+            .await
+        }
+        None => {}
+    }
+
+    // The --tui view only takes over a terminal
+    let use_tui = cli.tui && std::io::stdout().is_terminal();
+
     // Only initialize logging if not in retro mode
-    if !cli.machine {
+    if !cli.machine && !use_tui {
         // Initialize logging with filtering
         use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
@@ -301,13 +608,16 @@ pub async fn run() -> Result<()> {
         std::env::current_dir()?
     };
 
-    // Check if we're in a project directory and read README and AGENTS.md if available
-    // Load AGENTS.md first (if present) to provide agent-specific instructions
+    // Check if we're in a project directory and read README and agent instructions if available
+    // Load AGENTS.md and the like first (if present) to provide agent-specific instructions
     let agents_content = read_agents_config(&workspace_dir);
     
     // Then load README for project context
     let readme_content = read_project_readme(&workspace_dir);
 
+    // And the project map built by `g3 index`, if there is one
+    let index_content = ProjectIndex::load(&workspace_dir).map(|index| index.render());
+
     // Create project model
     let project = if cli.autonomous {
         if let Some(requirements_text) = &cli.requirements {
@@ -321,6 +631,9 @@ pub async fn run() -> Result<()> {
         Project::new(workspace_dir.clone())
     };
 
+    // How to build, test and lint the project, from its manifests
+    let facts_content = project.detect_facts().render();
+
     // Ensure workspace exists and enter it
     project.ensure_workspace_exists()?;
     project.enter_workspace()?;
@@ -347,6 +660,66 @@ pub async fn run() -> Result<()> {
         config.agent.auto_compact = false;
     }
 
+    // Apply read-only flag override
+    if cli.read_only {
+        config.agent.read_only = true;
+    }
+
+    // Apply profile selection
+    if let Some(profile) = &cli.profile {
+        config.agent.profile = Some(profile.clone());
+    }
+
+    // Apply summarize-on-exit flag override
+    if cli.summarize_on_exit {
+        config.agent.summarize_changes_on_exit = true;
+    }
+
+    // Apply snapshot-rounds flag override
+    if cli.snapshot_rounds {
+        config.agent.snapshot_rounds = true;
+    }
+
+    // Apply commit-rounds flag override
+    if cli.commit_rounds {
+        config.agent.commit_rounds = true;
+    }
+
+    // Apply self-review flag override
+    if cli.self_review {
+        config.agent.self_review = true;
+    }
+
+    // Apply commit-edits flag override
+    if cli.commit_edits {
+        config.agent.commit_edits = true;
+    }
+
+    // Apply record-streams flag override
+    if cli.record_streams {
+        config.agent.record_streams = true;
+    }
+
+    // Apply remote flag override
+    if let Some(remote) = &cli.remote {
+        config.remote.host = Some(remote.clone());
+    }
+
+    // Apply devcontainer flag override
+    if cli.devcontainer || cli.devcontainer_rebuild {
+        config.agent.devcontainer = true;
+    }
+
+    // Apply no-daemon flag override
+    if cli.no_daemon {
+        config.agent.use_daemon = false;
+    }
+
+    // Apply plain flag override
+    if cli.plain {
+        config.agent.plain_output = true;
+    }
+
     // Validate provider if specified
     if let Some(ref provider) = cli.provider {
         let valid_providers = ["anthropic", "databricks", "embedded", "openai"];
@@ -359,18 +732,38 @@ pub async fn run() -> Result<()> {
         }
     }
 
+    // Refuse workspaces where the shell tool could do lasting damage
+    check_workspace_policy(&workspace_dir, &config, cli.allow_dangerous_workspace)?;
+
+    // Dev container lifecycle; the agent starts the container itself if it isn't running
+    if cli.devcontainer_stop || cli.devcontainer_rebuild {
+        let container = g3_execution::devcontainer::DevContainer::find(&workspace_dir)?
+            .ok_or_else(|| anyhow::anyhow!("The workspace has no devcontainer.json"))?;
+        if cli.devcontainer_stop {
+            if container.down().await? {
+                println!("🐳 Stopped and removed dev container {}", container.name());
+            } else {
+                println!("🐳 Dev container {} is not running", container.name());
+            }
+            return Ok(());
+        }
+        println!(
+            "🐳 Rebuilding dev container {} from {}…",
+            container.name(),
+            container.config_path().display()
+        );
+        container.up(true).await?;
+    }
+
     // Initialize agent
     // ui_writer will be created conditionally based on machine mode
     
-    // Combine AGENTS.md and README content if both exist
-    let combined_content = match (agents_content.clone(), readme_content.clone()) {
-        (Some(agents), Some(readme)) => {
-            Some(format!("{}\n\n{}", agents, readme))
-        }
-        (Some(agents), None) => Some(agents),
-        (None, Some(readme)) => Some(readme),
-        (None, None) => None,
-    };
+    // Combine AGENTS.md, README, project facts and project index content that exists
+    let sections: Vec<String> = [agents_content, readme_content, facts_content, index_content]
+        .into_iter()
+        .flatten()
+        .collect();
+    let combined_content = (!sections.is_empty()).then(|| sections.join("\n\n"));
     
     // Execute task, autonomous mode, or start interactive mode based on machine mode
     if cli.machine {
@@ -412,10 +805,35 @@ pub async fn run() -> Result<()> {
             run_accumulative_mode(workspace_dir.clone(), cli.clone(), combined_content.clone()).await?;
             return Ok(());
         }
+
+        if use_tui {
+            let view = AutonomousView::new(cli.max_turns);
+            let agent = Agent::new_autonomous_with_readme_and_quiet(
+                config.clone(),
+                ViewWriter::new(view.clone()),
+                combined_content.clone(),
+                cli.quiet,
+            )
+            .await?;
+            view.open();
+            let result = run_autonomous(
+                agent,
+                project,
+                cli.show_prompt,
+                cli.show_code,
+                cli.max_turns,
+                cli.quiet,
+                |_| ViewWriter::new(view.clone()),
+                Some(&view),
+            )
+            .await;
+            view.close();
+            return result;
+        }
         
-        let ui_writer = ConsoleUiWriter::new();
+        let ui_writer = ConsoleUiWriter::new().with_plain(config.agent.plain_output);
         
-        let agent = if cli.autonomous {
+        let mut agent = if cli.autonomous {
             Agent::new_autonomous_with_readme_and_quiet(
                 config.clone(),
                 ui_writer,
@@ -432,6 +850,9 @@ pub async fn run() -> Result<()> {
             )
             .await?
         };
+        if cli.step {
+            agent.set_stepper(std::sync::Arc::new(step::TerminalStepper));
+        }
         
         run_with_console_mode(agent, cli, project, combined_content).await?;
     }
@@ -446,7 +867,7 @@ async fn run_accumulative_mode(
     cli: Cli,
     combined_content: Option<String>,
 ) -> Result<()> {
-    let output = SimpleOutput::new();
+    let output = SimpleOutput::new().with_plain(cli.plain);
     
     output.print("");
     output.print("g3 programming agent - autonomous mode");
@@ -464,7 +885,7 @@ async fn run_accumulative_mode(
     output.print("");
     
     // Initialize rustyline editor with history
-    let mut rl = DefaultEditor::new()?;
+    let mut rl = prompt_editor::new_editor()?;
     let history_file = dirs::home_dir().map(|mut path| {
         path.push(".g3_accumulative_history");
         path
@@ -573,9 +994,39 @@ async fn run_accumulative_mode(
                             if cli.manual_compact {
                                 config.agent.auto_compact = false;
                             }
+
+                            // Apply read-only flag override
+                            if cli.read_only {
+                                config.agent.read_only = true;
+                            }
+
+                            // Apply profile selection
+                            if let Some(profile) = &cli.profile {
+                                config.agent.profile = Some(profile.clone());
+                            }
+
+                            // Apply summarize-on-exit flag override
+                            if cli.summarize_on_exit {
+                                config.agent.summarize_changes_on_exit = true;
+                            }
+
+                            // Apply remote flag override
+                            if let Some(remote) = &cli.remote {
+                                config.remote.host = Some(remote.clone());
+                            }
+
+                            // Apply devcontainer flag override
+                            if cli.devcontainer || cli.devcontainer_rebuild {
+                                config.agent.devcontainer = true;
+                            }
+
+                            // Apply plain flag override
+                            if cli.plain {
+                                config.agent.plain_output = true;
+                            }
                             
                             // Create agent for interactive mode with requirements context
-                            let ui_writer = ConsoleUiWriter::new();
+                            let ui_writer = ConsoleUiWriter::new().with_plain(config.agent.plain_output);
                             let agent = Agent::new_with_readme_and_quiet(
                                 config,
                                 ui_writer,
@@ -655,9 +1106,59 @@ async fn run_accumulative_mode(
                 if cli.manual_compact {
                     config.agent.auto_compact = false;
                 }
+
+                // Apply read-only flag override
+                if cli.read_only {
+                    config.agent.read_only = true;
+                }
+
+                // Apply profile selection
+                if let Some(profile) = &cli.profile {
+                    config.agent.profile = Some(profile.clone());
+                }
+
+                // Apply snapshot-rounds flag override
+                if cli.snapshot_rounds {
+                    config.agent.snapshot_rounds = true;
+                }
+
+                // Apply commit-rounds flag override
+                if cli.commit_rounds {
+                    config.agent.commit_rounds = true;
+                }
+
+                // Apply self-review flag override
+                if cli.self_review {
+                    config.agent.self_review = true;
+                }
+
+                // Apply commit-edits flag override
+                if cli.commit_edits {
+                    config.agent.commit_edits = true;
+                }
+
+                // Apply record-streams flag override
+                if cli.record_streams {
+                    config.agent.record_streams = true;
+                }
+
+                // Apply remote flag override
+                if let Some(remote) = &cli.remote {
+                    config.remote.host = Some(remote.clone());
+                }
+
+                // Apply devcontainer flag override
+                if cli.devcontainer || cli.devcontainer_rebuild {
+                    config.agent.devcontainer = true;
+                }
+
+                // Apply plain flag override
+                if cli.plain {
+                    config.agent.plain_output = true;
+                }
                 
                 // Create agent for this autonomous run
-                let ui_writer = ConsoleUiWriter::new();
+                let ui_writer = ConsoleUiWriter::new().with_plain(config.agent.plain_output);
                 let agent = Agent::new_autonomous_with_readme_and_quiet(
                     config.clone(),
                     ui_writer,
@@ -675,6 +1176,8 @@ async fn run_accumulative_mode(
                     cli.show_code,
                     cli.max_turns,
                     cli.quiet,
+                    |plain| ConsoleUiWriter::new().with_plain(plain),
+                    None,
                     ) => result,
                     _ = tokio::signal::ctrl_c() => {
                         output.print("\n⚠️  Autonomous run cancelled by user (Ctrl+C)");
@@ -783,15 +1286,18 @@ async fn run_with_console_mode(
             cli.show_code,
             cli.max_turns,
             cli.quiet,
+            |plain| ConsoleUiWriter::new().with_plain(plain),
+            None,
         )
         .await?;
     } else if let Some(task) = cli.task {
         // Single-shot mode
-        let output = SimpleOutput::new();
+        let output = SimpleOutput::new().with_plain(agent.get_config().agent.plain_output);
         let result = agent
             .execute_task_with_timing(&task, None, false, cli.show_prompt, cli.show_code, true)
             .await?;
         output.print_smart(&result.response);
+        summarize_on_exit(&agent).await;
     } else {
         // Interactive mode (default)
         run_interactive(agent, cli.show_prompt, cli.show_code, combined_content, project.workspace()).await?;
@@ -800,6 +1306,16 @@ async fn run_with_console_mode(
     Ok(())
 }
 
+/// Describe the session's changes if `summarize_changes_on_exit` is set
+async fn summarize_on_exit<W: UiWriter>(agent: &Agent<W>) {
+    if !agent.get_config().agent.summarize_changes_on_exit || agent.get_session_id().is_none() {
+        return;
+    }
+    if let Err(e) = summarize::summarize_changes(agent, None, None).await {
+        println!("⚠️ Could not summarize changes: {}", e);
+    }
+}
+
 async fn run_with_machine_mode(
     mut agent: Agent<MachineUiWriter>,
     cli: Cli,
@@ -832,43 +1348,10 @@ async fn run_with_machine_mode(
     Ok(())
 }
 
-/// Check if we're in a project directory and read AGENTS.md if available
+/// Read the agent instructions (AGENTS.md, CLAUDE.md, .cursor/rules) for the workspace and,
+/// in a monorepo, its repository root
 fn read_agents_config(workspace_dir: &Path) -> Option<String> {
-    // Look for AGENTS.md in the current directory
-    let agents_path = workspace_dir.join("AGENTS.md");
-    
-    if agents_path.exists() {
-        match std::fs::read_to_string(&agents_path) {
-            Ok(content) => {
-                // Return the content with a note about which file was read
-                Some(format!(
-                    "🤖 Agent Configuration (from AGENTS.md):\n\n{}",
-                    content
-                ))
-            }
-            Err(e) => {
-                // Log the error but continue without the agents config
-                error!("Failed to read AGENTS.md: {}", e);
-                None
-            }
-        }
-    } else {
-        // Check for alternative names
-        let alt_path = workspace_dir.join("agents.md");
-        if alt_path.exists() {
-            match std::fs::read_to_string(&alt_path) {
-                Ok(content) => {
-                    Some(format!("🤖 Agent Configuration (from agents.md):\n\n{}", content))
-                }
-                Err(e) => {
-                    error!("Failed to read agents.md: {}", e);
-                    None
-                }
-            }
-        } else {
-            None
-        }
-    }
+    g3_core::instructions::load(workspace_dir, &G3Ignore::load(workspace_dir))
 }
 
 /// Check if we're in a project directory and read README if available
@@ -891,9 +1374,12 @@ fn read_project_readme(workspace_dir: &Path) -> Option<String> {
         "README.rst",
     ];
 
+    // Files hidden by .g3ignore are never loaded as context
+    let ignore = G3Ignore::load(workspace_dir);
+
     for readme_name in &readme_names {
         let readme_path = workspace_dir.join(readme_name);
-        if readme_path.exists() {
+        if readme_path.exists() && !ignore.is_ignored(Path::new(readme_name)) {
             match std::fs::read_to_string(&readme_path) {
                 Ok(content) => {
                     // Return the content with a note about which file was read
@@ -956,11 +1442,7 @@ fn extract_readme_heading(readme_content: &str) -> Option<String> {
             && !trimmed.starts_with("--")
         {
             // Limit length for display
-            return Some(if trimmed.len() > 100 {
-                format!("{}...", &trimmed[..97])
-            } else {
-                trimmed.to_string()
-            });
+            return Some(g3_core::text_display::truncate(trimmed, 100));
         }
     }
     None
@@ -973,7 +1455,7 @@ async fn run_interactive<W: UiWriter>(
     combined_content: Option<String>,
     workspace_path: &Path,
 ) -> Result<()> {
-    let output = SimpleOutput::new();
+    let output = SimpleOutput::new().with_plain(agent.get_config().agent.plain_output);
 
     output.print("");
     output.print("g3 programming agent");
@@ -996,12 +1478,17 @@ async fn run_interactive<W: UiWriter>(
     // Display message if AGENTS.md or README was loaded
     if let Some(ref content) = combined_content {
         // Check what was loaded
-        let has_agents = content.contains("Agent Configuration");
+        // The instructions header names the files, e.g. "(from ../../AGENTS.md, AGENTS.md):"
+        let agents_files = content
+            .lines()
+            .find_map(|line| line.strip_prefix(g3_core::instructions::HEADER))
+            .and_then(|rest| rest.strip_prefix(" (from "))
+            .and_then(|rest| rest.strip_suffix("):"));
         let has_readme = content.contains("Project README");
         
-        if has_agents {
-            print!("{}🤖 AGENTS.md configuration loaded{}\n", 
-                SetForegroundColor(Color::DarkGrey), ResetColor);
+        if let Some(files) = agents_files {
+            print!("{}🤖 agent instructions loaded from {}{}\n", 
+                SetForegroundColor(Color::DarkGrey), files, ResetColor);
         }
         
         if has_readme {
@@ -1014,6 +1501,20 @@ async fn run_interactive<W: UiWriter>(
                 readme_snippet,
                 ResetColor);
         }
+
+        if content.contains(g3_core::project::FACTS_HEADER) {
+            println!("{}🧰 detected: {}{}",
+                SetForegroundColor(Color::DarkGrey),
+                ProjectFacts::detect(workspace_path).summary(),
+                ResetColor);
+        }
+
+        if content.contains(g3_core::project_index::HEADER) {
+            println!("{}🗺️  project index loaded from {}{}",
+                SetForegroundColor(Color::DarkGrey),
+                g3_core::project_index::INDEX_FILE,
+                ResetColor);
+        }
     }
 
     // Display workspace path
@@ -1022,18 +1523,9 @@ async fn run_interactive<W: UiWriter>(
         workspace_path.display(), ResetColor);
     output.print("");
 
-    // Initialize rustyline editor with history
-    let mut rl = DefaultEditor::new()?;
-
-    // Try to load history from a file in the user's home directory
-    let history_file = dirs::home_dir().map(|mut path| {
-        path.push(".g3_history");
-        path
-    });
-
-    if let Some(ref history_path) = history_file {
-        let _ = rl.load_history(history_path);
-    }
+    // Initialize rustyline editor with the workspace's history
+    let mut rl = prompt_editor::new_editor()?;
+    let history_file = prompt_editor::load_history(&mut rl, workspace_path);
 
     // Track multiline input
     let mut multiline_buffer = String::new();
@@ -1104,11 +1596,23 @@ async fn run_interactive<W: UiWriter>(
                                 output.print("📖 Control Commands:");
                                 output.print("  /compact   - Trigger auto-summarization (compacts conversation history)");
                                 output.print("  /thinnify  - Trigger context thinning (replaces large tool results with file references)");
-                                output.print("  /readme    - Reload README.md and AGENTS.md from disk");
+                                output.print("  /readme    - Reload README.md and agent instructions from disk");
                                 output.print("  /stats     - Show detailed context and performance statistics");
+                                output.print("  /artifacts - List files saved by this session (thinned context, screenshots)");
+                                output.print("  /profile [name|none] - Show profiles, or switch to one (none for the plain config)");
+                                output.print("  /undo [n]  - Revert the last n file modifications (default 1)");
+                                output.print("  /diff      - Show the files changed this session and how");
+                                output.print("  /rollback  - Put every file changed this session back the way it was");
+                                output.print("  /tag <name> - Save the conversation under a name");
+                                output.print("  /tags      - Show the saved conversation states as a tree");
+                                output.print("  /branch <tag|#n> - Go back to a saved conversation (files are not changed)");
+                                output.print("  /continue  - Resume a response that stopped at the output token limit");
+                                output.print("  /edit [text] - Compose a prompt in $EDITOR, starting from text");
                                 output.print("  /help      - Show this help message");
                                 output.print("  exit/quit  - Exit the interactive session");
                                 output.print("");
+                                output.print("  Ctrl-R searches history, Alt-Enter or a trailing \\ starts a new line.");
+                                output.print("");
                                 continue;
                             }
                             "/compact" => {
@@ -1127,12 +1631,12 @@ async fn run_interactive<W: UiWriter>(
                                 continue;
                             }
                             "/thinnify" => {
-                                let summary = agent.force_thin();
+                                let summary = agent.force_thin().await;
                                 println!("{}", summary);
                                 continue;
                             }
                             "/readme" => {
-                                output.print("📚 Reloading README.md and AGENTS.md...");
+                                output.print("📚 Reloading README.md and agent instructions...");
                                 match agent.reload_readme() {
                                     Ok(true) => output.print("✅ README content reloaded successfully"),
                                     Ok(false) => output.print("⚠️ No README was loaded at startup, cannot reload"),
@@ -1145,6 +1649,95 @@ async fn run_interactive<W: UiWriter>(
                                 output.print(&stats);
                                 continue;
                             }
+                            "/artifacts" => {
+                                output.print(&agent.list_artifacts());
+                                continue;
+                            }
+                            cmd if cmd == "/profile" || cmd.starts_with("/profile ") => {
+                                match cmd["/profile".len()..].trim() {
+                                    "" => output.print(&agent.list_profiles()),
+                                    name => {
+                                        let profile = (name != "none").then_some(name);
+                                        match agent.set_profile(profile).await {
+                                            Ok(()) => output.print(&format!("✅ Switched to profile: {}", name)),
+                                            Err(e) => output.print(&format!("❌ {}", e)),
+                                        }
+                                    }
+                                }
+                                continue;
+                            }
+                            cmd if cmd == "/undo" || cmd.starts_with("/undo ") => {
+                                match parse_undo_count(cmd) {
+                                    Some(count) => match agent.undo_edits(count) {
+                                        Ok(summary) => output.print(&summary),
+                                        Err(e) => output.print(&format!("❌ Failed to undo: {}", e)),
+                                    },
+                                    None => output.print("❌ Usage: /undo [number of modifications]"),
+                                }
+                                continue;
+                            }
+                            "/diff" => {
+                                match agent.diff_file_changes() {
+                                    Ok(diff) => output.print(&diff),
+                                    Err(e) => output.print(&format!("❌ Failed to diff: {}", e)),
+                                }
+                                continue;
+                            }
+                            "/rollback" => {
+                                match agent.rollback_file_changes() {
+                                    Ok(summary) => output.print(&summary),
+                                    Err(e) => output.print(&format!("❌ Failed to roll back: {}", e)),
+                                }
+                                continue;
+                            }
+                            cmd if cmd == "/tag" || cmd.starts_with("/tag ") => {
+                                match cmd["/tag".len()..].trim() {
+                                    "" => output.print("❌ Usage: /tag <name>"),
+                                    name => match agent.tag_state(name) {
+                                        Ok(message) => output.print(&message),
+                                        Err(e) => output.print(&format!("❌ Failed to tag: {}", e)),
+                                    },
+                                }
+                                continue;
+                            }
+                            "/tags" => {
+                                match agent.list_states() {
+                                    Ok(tree) => output.print(&tree),
+                                    Err(e) => output.print(&format!("❌ Failed to list tags: {}", e)),
+                                }
+                                continue;
+                            }
+                            cmd if cmd == "/branch" || cmd.starts_with("/branch ") => {
+                                match cmd["/branch".len()..].trim() {
+                                    "" => output.print("❌ Usage: /branch <tag or #number>"),
+                                    target => match agent.branch_from(target) {
+                                        Ok(message) => output.print(&message),
+                                        Err(e) => output.print(&format!("❌ Failed to branch: {}", e)),
+                                    },
+                                }
+                                continue;
+                            }
+                            cmd if cmd == "/edit" || cmd.starts_with("/edit ") => {
+                                let editor = prompt_editor::editor_command();
+                                match prompt_editor::compose(&editor, cmd["/edit".len()..].trim()) {
+                                    Ok(Some(prompt)) => {
+                                        rl.add_history_entry(&prompt)?;
+                                        output.print(&prompt);
+                                        execute_task(&mut agent, &prompt, show_prompt, show_code, &output).await;
+                                    }
+                                    Ok(None) => output.print("⚠️ The prompt was empty, nothing was sent"),
+                                    Err(e) => output.print(&format!("❌ {}", e)),
+                                }
+                                continue;
+                            }
+                            "/continue" => {
+                                if agent.response_truncated() {
+                                    execute_task(&mut agent, g3_core::CONTINUE_PROMPT, show_prompt, show_code, &output).await;
+                                } else {
+                                    output.print("⚠️ The last response was not cut off, there is nothing to continue");
+                                }
+                                continue;
+                            }
                             _ => {
                                 output.print(&format!("❌ Unknown command: {}. Type /help for available commands.", input));
                                 continue;
@@ -1180,10 +1773,9 @@ async fn run_interactive<W: UiWriter>(
     }
 
     // Save history before exiting
-    if let Some(ref history_path) = history_file {
-        let _ = rl.save_history(history_path);
-    }
+    prompt_editor::save_history(&mut rl, &history_file);
 
+    summarize_on_exit(&agent).await;
     output.print("👋 Goodbye!");
     Ok(())
 }
@@ -1209,16 +1801,18 @@ async fn execute_task<W: UiWriter>(
         attempt += 1;
 
         // Execute task with cancellation support
+        let task = agent.execute_task_with_timing_cancellable(
+            input, None, false, show_prompt, show_code, true, cancellation_token.clone()
+        );
+        tokio::pin!(task);
         let execution_result = tokio::select! {
-            result = agent.execute_task_with_timing_cancellable(
-                input, None, false, show_prompt, show_code, true, cancellation_token.clone()
-            ) => {
+            result = &mut task => {
                 result
             }
             _ = tokio::signal::ctrl_c() => {
+                // The agent stops a running shell command too, and returns once it has
                 cancel_token_clone.cancel();
-                output.print("\n⚠️  Operation cancelled by user (Ctrl+C)");
-                return;
+                task.await
             }
         };
 
@@ -1231,7 +1825,7 @@ async fn execute_task<W: UiWriter>(
                 return;
             }
             Err(e) => {
-                if e.to_string().contains("cancelled") {
+                if G3Error::is_cancelled(&e) {
                     output.print("⚠️  Operation cancelled by user");
                     return;
                 }
@@ -1328,7 +1922,7 @@ async fn run_interactive_machine(
                         }
                         "/thinnify" => {
                             println!("COMMAND: thinnify");
-                            let summary = agent.force_thin();
+                            let summary = agent.force_thin().await;
                             println!("{}", summary);
                             continue;
                         }
@@ -1348,9 +1942,96 @@ async fn run_interactive_machine(
                             println!("{}", stats);
                             continue;
                         }
+                        "/artifacts" => {
+                            println!("COMMAND: artifacts");
+                            println!("{}", agent.list_artifacts());
+                            continue;
+                        }
+                        cmd if cmd == "/profile" || cmd.starts_with("/profile ") => {
+                            println!("COMMAND: profile");
+                            match cmd["/profile".len()..].trim() {
+                                "" => println!("{}", agent.list_profiles()),
+                                name => {
+                                    let profile = (name != "none").then_some(name);
+                                    match agent.set_profile(profile).await {
+                                        Ok(()) => println!("RESULT: Switched to profile {}", name),
+                                        Err(e) => println!("ERROR: {}", e),
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+                        cmd if cmd == "/undo" || cmd.starts_with("/undo ") => {
+                            println!("COMMAND: undo");
+                            match parse_undo_count(cmd) {
+                                Some(count) => match agent.undo_edits(count) {
+                                    Ok(summary) => println!("RESULT: {}", summary),
+                                    Err(e) => println!("ERROR: {}", e),
+                                },
+                                None => println!("ERROR: Usage: /undo [number of modifications]"),
+                            }
+                            continue;
+                        }
+                        "/diff" => {
+                            println!("COMMAND: diff");
+                            match agent.diff_file_changes() {
+                                Ok(diff) => println!("RESULT:\n{}", diff),
+                                Err(e) => println!("ERROR: {}", e),
+                            }
+                            continue;
+                        }
+                        "/rollback" => {
+                            println!("COMMAND: rollback");
+                            match agent.rollback_file_changes() {
+                                Ok(summary) => println!("RESULT: {}", summary),
+                                Err(e) => println!("ERROR: {}", e),
+                            }
+                            continue;
+                        }
+                        cmd if cmd == "/tag" || cmd.starts_with("/tag ") => {
+                            println!("COMMAND: tag");
+                            match cmd["/tag".len()..].trim() {
+                                "" => println!("ERROR: Usage: /tag <name>"),
+                                name => match agent.tag_state(name) {
+                                    Ok(message) => println!("RESULT: {}", message),
+                                    Err(e) => println!("ERROR: {}", e),
+                                },
+                            }
+                            continue;
+                        }
+                        "/tags" => {
+                            println!("COMMAND: tags");
+                            match agent.list_states() {
+                                Ok(tree) => println!("RESULT:\n{}", tree),
+                                Err(e) => println!("ERROR: {}", e),
+                            }
+                            continue;
+                        }
+                        cmd if cmd == "/branch" || cmd.starts_with("/branch ") => {
+                            println!("COMMAND: branch");
+                            match cmd["/branch".len()..].trim() {
+                                "" => println!("ERROR: Usage: /branch <tag or #number>"),
+                                target => match agent.branch_from(target) {
+                                    Ok(message) => println!("RESULT: {}", message),
+                                    Err(e) => println!("ERROR: {}", e),
+                                },
+                            }
+                            continue;
+                        }
+                        "/continue" => {
+                            println!("COMMAND: continue");
+                            if agent.response_truncated() {
+                                println!("TASK_START");
+                                execute_task_machine(&mut agent, g3_core::CONTINUE_PROMPT, show_prompt, show_code).await;
+                                println!("TASK_END");
+                            } else {
+                                println!("ERROR: The last response was not cut off");
+                            }
+                            continue;
+                        }
                         "/help" => {
                             println!("COMMAND: help");
-                            println!("AVAILABLE_COMMANDS: /compact /thinnify /readme /stats /help");
+                            println!("AVAILABLE_COMMANDS: /compact /thinnify /readme /stats /artifacts /profile /undo /diff /rollback /tag /tags /branch /continue /help");
                             continue;
                         }
                         _ => {
@@ -1400,16 +2081,18 @@ async fn execute_task_machine(
         attempt += 1;
 
         // Execute task with cancellation support
+        let task = agent.execute_task_with_timing_cancellable(
+            input, None, false, show_prompt, show_code, true, cancellation_token.clone()
+        );
+        tokio::pin!(task);
         let execution_result = tokio::select! {
-            result = agent.execute_task_with_timing_cancellable(
-                input, None, false, show_prompt, show_code, true, cancellation_token.clone()
-            ) => {
+            result = &mut task => {
                 result
             }
             _ = tokio::signal::ctrl_c() => {
+                // The agent stops a running shell command too, and returns once it has
                 cancel_token_clone.cancel();
-                println!("CANCELLED");
-                return;
+                task.await
             }
         };
 
@@ -1424,10 +2107,14 @@ async fn execute_task_machine(
                 return;
             }
             Err(e) => {
-                if e.to_string().contains("cancelled") {
+                if G3Error::is_cancelled(&e) {
                     println!("CANCELLED");
                     return;
                 }
+                if let Some(G3Error::ContentFiltered(message)) = G3Error::from_error(&e) {
+                    println!("CONTENT_FILTERED: {}", message);
+                    return;
+                }
 
                 // Check if this is a timeout error that we should retry
                 let error_type = classify_error(&e);
@@ -1459,6 +2146,14 @@ async fn execute_task_machine(
     }
 }
 
+/// The number of modifications `/undo [n]` asks to revert
+fn parse_undo_count(cmd: &str) -> Option<usize> {
+    match cmd["/undo".len()..].trim() {
+        "" => Some(1),
+        n => n.parse().ok().filter(|&n| n > 0),
+    }
+}
+
 fn handle_execution_error(e: &anyhow::Error, input: &str, output: &SimpleOutput, attempt: u32) {
     // Enhanced error logging with detailed information
     error!("=== TASK EXECUTION ERROR ===");
@@ -1480,11 +2175,18 @@ fn handle_execution_error(e: &anyhow::Error, input: &str, output: &SimpleOutput,
     error!("Task input: {}", input);
     error!("Error type: {}", std::any::type_name_of_val(&e));
 
+    // A content filter or refusal isn't a failure, so it gets its own explanation
+    if let Some(G3Error::ContentFiltered(message)) = G3Error::from_error(e) {
+        output.print(&format!("🚫 {}", message));
+        output.print(&format!("💡 {}", CONTENT_FILTER_ADVICE));
+        return;
+    }
+
     // Display user-friendly error message
     output.print(&format!("❌ Error: {}", e));
 
     // If it's a stream error, provide helpful guidance
-    if e.to_string().contains("No response received") || e.to_string().contains("timed out") {
+    if matches!(classify_error(e), ErrorType::Recoverable(_)) {
         output.print("💡 This may be a temporary issue. Please try again or check the logs for more details.");
         output.print("   Log files are saved in the 'logs/' directory.");
     }
@@ -1544,16 +2246,40 @@ fn setup_workspace_directory(machine_mode: bool) -> Result<PathBuf> {
 }
 
 // Simplified autonomous mode implementation
-async fn run_autonomous(
-    mut agent: Agent<ConsoleUiWriter>,
+/// Undo a failed player round's changes to the workspace, if `snapshot_rounds` is enabled
+fn restore_round<W: UiWriter>(agent: &Agent<W>, output: &SimpleOutput) {
+    match agent.restore_round() {
+        Ok(true) => output.print("⏪ Restored the workspace to its state before the round"),
+        Ok(false) => {}
+        Err(e) => output.print(&format!("⚠️ Failed to restore the workspace: {}", e)),
+    }
+}
+
+/// The coach-player loop. The coach writes to a writer from `new_ui_writer`, given the
+/// `plain_output` setting, and `view` is kept up to date when the run is shown with `--tui`.
+#[allow(clippy::too_many_arguments)]
+async fn run_autonomous<W: UiWriter>(
+    mut agent: Agent<W>,
     project: Project,
     show_prompt: bool,
     show_code: bool,
     max_turns: usize,
     quiet: bool,
+    new_ui_writer: impl Fn(bool) -> W,
+    view: Option<&AutonomousView>,
 ) -> Result<()> {
     let start_time = std::time::Instant::now();
-    let output = SimpleOutput::new();
+    let mut output = SimpleOutput::new().with_plain(agent.get_config().agent.plain_output);
+    if let Some(view) = view {
+        let view = view.clone();
+        output = output.with_sink(std::sync::Arc::new(move |message| view.push_line(message)));
+    }
+    // The report at the end of a run is printed after the view
+    let close_view = || {
+        if let Some(view) = view {
+            view.close();
+        }
+    };
     let mut turn_metrics: Vec<TurnMetrics> = Vec::new();
 
     output.print("g3 programming agent - autonomous mode");
@@ -1579,6 +2305,7 @@ async fn run_autonomous(
         let elapsed = start_time.elapsed();
         let context_window = agent.get_context_window();
 
+        close_view();
         output.print(&format!("\n{}", "=".repeat(60)));
         output.print("📊 AUTONOMOUS MODE SESSION REPORT");
         output.print(&"=".repeat(60));
@@ -1621,6 +2348,7 @@ async fn run_autonomous(
             let elapsed = start_time.elapsed();
             let context_window = agent.get_context_window();
 
+            close_view();
             output.print(&format!("\n{}", "=".repeat(60)));
             output.print("📊 AUTONOMOUS MODE SESSION REPORT");
             output.print(&"=".repeat(60));
@@ -1660,7 +2388,11 @@ async fn run_autonomous(
     } else {
         output.print("📋 Requirements loaded from requirements.md");
     }
-    output.print("🔄 Starting coach-player feedback loop...");
+    if agent.get_config().agent.self_review {
+        output.print("🔄 Starting implement and self-review loop...");
+    } else {
+        output.print("🔄 Starting coach-player feedback loop...");
+    }
 
     // Check if implementation files already exist
     let skip_first_player = project.has_implementation_files();
@@ -1672,15 +2404,38 @@ async fn run_autonomous(
         output.print("🎯 Starting with player implementation");
     }
 
+    // Commit each player round to a branch of the run's own, for `g3 review-run`
+    let mut run_record = None;
+    if agent.get_config().agent.commit_rounds {
+        match RunRecord::start(project.workspace()) {
+            Ok(Some(record)) => {
+                output.print(&format!("🌿 Committing each round to {}", record.branch));
+                run_record = Some(record);
+            }
+            Ok(None) => output.print("⚠️ Not committing rounds: the workspace has no git history"),
+            Err(e) => output.print(&format!("⚠️ Not committing rounds: {}", e)),
+        }
+    }
+
     let mut turn = 1;
     let mut coach_feedback = String::new();
     let mut implementation_approved = false;
+    // Responses stopped by a content filter, for the report
+    let mut content_filtered: Vec<String> = Vec::new();
 
     loop {
-        let turn_start_time = Instant::now();
-        let turn_start_tokens = agent.get_context_window().used_tokens;
+        let player_tasks_before = agent.get_turn_metrics().len();
+        // Files the player changed, for the coach to review
+        let mut player_changes = String::new();
+        // The player's report of the round, for the round's commit message
+        let mut player_summary = String::new();
+        // Why a content filter stopped the player this round, for the coach to work around
+        let mut player_filtered = None;
         // Skip player turn if it's the first turn and implementation files exist
         if !(turn == 1 && skip_first_player) {
+            if let Some(view) = view {
+                view.set_role(Role::Player, turn);
+            }
             output.print(&format!(
                 "\n=== TURN {}/{} - PLAYER MODE ===",
                 turn, max_turns
@@ -1694,8 +2449,14 @@ async fn run_autonomous(
                 )
             } else {
                 format!(
-                    "You are G3 in implementation mode. Address the following specific feedback from the coach:\n\n{}\n\nContext: You are improving an implementation based on these requirements:\n{}\n\nFocus on fixing the issues mentioned in the coach feedback above.",
-                    coach_feedback, requirements
+                    "You are G3 in implementation mode. Address the following specific feedback from {}:\n\n{}\n\nContext: You are improving an implementation based on these requirements:\n{}\n\nFocus on fixing the issues mentioned in the feedback above.",
+                    if agent.get_config().agent.self_review {
+                        "your self-review"
+                    } else {
+                        "the coach"
+                    },
+                    coach_feedback,
+                    requirements
                 )
             };
 
@@ -1720,6 +2481,9 @@ async fn run_autonomous(
             }
             output.print(""); // Empty line for readability
 
+            // A new snapshot is taken before the round first changes the workspace
+            agent.begin_round();
+
             // Execute player task with retry on error
             let mut _player_retry_count = 0;
             const MAX_PLAYER_RETRIES: u32 = 3;
@@ -1741,9 +2505,25 @@ async fn run_autonomous(
                         // Display player's implementation result
                         output.print("📝 Player implementation completed:");
                         output.print_smart(&result.response);
+                        player_changes = result.file_changes.render(project.workspace());
+                        player_summary = result.response;
                         break;
                     }
                     Err(e) => {
+                        // Retrying would be stopped the same way; the coach decides how to go on
+                        if let Some(G3Error::ContentFiltered(message)) = G3Error::from_error(&e) {
+                            output.print(&format!("🚫 Player stopped by the content filter: {}", message));
+                            agent.log_error_to_session(
+                                &e,
+                                "assistant",
+                                Some(format!("Turn: {}\nRole: Player", turn)),
+                            );
+                            content_filtered.push(format!("Turn {} (player): {}", turn, message));
+                            player_summary = format!("Stopped by the content filter: {}", message);
+                            player_filtered = Some(message);
+                            break;
+                        }
+
                         // Check if this is a context length exceeded error
                         use g3_core::error_handling::{classify_error, ErrorType, RecoverableError};
                         let error_type = classify_error(&e);
@@ -1782,6 +2562,7 @@ async fn run_autonomous(
                             let elapsed = start_time.elapsed();
                             let context_window = agent.get_context_window();
 
+                            close_view();
                             output.print(&format!("\n{}", "=".repeat(60)));
                             output.print("📊 AUTONOMOUS MODE SESSION REPORT");
                             output.print(&"=".repeat(60));
@@ -1830,6 +2611,7 @@ async fn run_autonomous(
                             player_failed = true;
                             break; // Exit retry loop
                         }
+                        restore_round(&agent, &output);
                         output.print("🔄 Retrying player implementation...");
                     }
                 }
@@ -1837,18 +2619,17 @@ async fn run_autonomous(
 
             // If player failed after max retries, increment turn and continue
             if player_failed {
+                restore_round(&agent, &output);
                 output.print(&format!(
                     "⚠️ Player turn {} failed after max retries. Moving to next turn.",
                     turn
                 ));
                 // Record turn metrics before incrementing
-                let turn_duration = turn_start_time.elapsed();
-                let turn_tokens = agent.get_context_window().used_tokens.saturating_sub(turn_start_tokens);
-                turn_metrics.push(TurnMetrics {
-                    turn_number: turn,
-                    tokens_used: turn_tokens,
-                    wall_clock_time: turn_duration,
-                });
+                turn_metrics.push(autonomous_turn_metrics(
+                    turn,
+                    &agent.get_turn_metrics()[player_tasks_before..],
+                    &[],
+                ));
                 turn += 1;
 
                 // Check if we've reached max turns
@@ -1865,6 +2646,88 @@ async fn run_autonomous(
 
             // Give some time for file operations to complete
             tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+            if let Some(record) = run_record.as_mut() {
+                match record.commit_round(project.workspace(), turn, &player_summary, &coach_feedback) {
+                    Ok(Some(round)) => output.print(&format!(
+                        "📌 Committed round {} as {}",
+                        turn,
+                        &round.commit[..round.commit.len().min(12)]
+                    )),
+                    Ok(None) => output.print(&format!("📌 Round {} changed nothing to commit", turn)),
+                    Err(e) => output.print(&format!("⚠️ Failed to commit round {}: {}", turn, e)),
+                }
+            }
+        }
+
+        // With self_review, the player reviews its own changes instead of a fresh coach
+        if agent.get_config().agent.self_review {
+            if let Some(view) = view {
+                view.set_role(Role::Review, turn);
+            }
+            output.print(&format!(
+                "\n=== TURN {}/{} - SELF-REVIEW ===",
+                turn, max_turns
+            ));
+            output.print("🔍 Reviewing the changes against the requirements...");
+
+            let review_tasks_before = agent.get_turn_metrics().len();
+            agent.set_reviewing(true);
+            let review = agent
+                .execute_task_with_timing(
+                    &self_review_prompt(&requirements, &player_changes),
+                    None,
+                    false,
+                    show_prompt,
+                    show_code,
+                    true,
+                )
+                .await;
+            agent.set_reviewing(false);
+            let metrics = agent.get_turn_metrics();
+            turn_metrics.push(autonomous_turn_metrics(
+                turn,
+                &metrics[player_tasks_before..review_tasks_before],
+                &metrics[review_tasks_before..],
+            ));
+
+            let (approved, feedback) = match review {
+                Ok(result) => (result.is_approved(), coach_feedback_from_result(&result)),
+                Err(e) => {
+                    output.print(&format!("⚠️ Self-review failed: {}", e));
+                    (false, String::new())
+                }
+            };
+            if approved {
+                if let Some(view) = view {
+                    view.set_verdict("✅ approved");
+                }
+                output.print("\n=== SESSION COMPLETED - IMPLEMENTATION APPROVED ===");
+                output.print("✅ Self-review confirmed the implementation");
+                implementation_approved = true;
+                break;
+            }
+            if feedback.is_empty() {
+                if let Some(view) = view {
+                    view.set_verdict("none, no feedback was given");
+                }
+                output.print("⚠️ The self-review gave no feedback. This may be a model issue.");
+                coach_feedback = "The implementation needs review. Please ensure all requirements are met and the code compiles without errors.".to_string();
+            } else {
+                if let Some(view) = view {
+                    view.set_verdict("🔄 changes requested");
+                }
+                output.print_smart(&format!("Self-review feedback:\n{}", feedback));
+                coach_feedback = feedback;
+            }
+
+            if turn >= max_turns {
+                output.print("\n=== SESSION COMPLETED - MAX TURNS REACHED ===");
+                output.print(&format!("⏰ Maximum turns ({}) reached", max_turns));
+                break;
+            }
+            turn += 1;
+            continue;
         }
 
         // Create a new agent instance for coach mode to ensure fresh context
@@ -1875,13 +2738,16 @@ async fn run_autonomous(
         // Reset filter suppression state before creating coach agent
         g3_core::fixed_filter_json::reset_fixed_json_tool_state();
 
-        let ui_writer = ConsoleUiWriter::new();
+        let ui_writer = new_ui_writer(coach_config.agent.plain_output);
         let mut coach_agent =
-            Agent::new_autonomous_with_readme_and_quiet(coach_config, ui_writer, None, quiet).await?;
+            Agent::new_coach_with_quiet(coach_config, ui_writer, quiet).await?;
 
         // Ensure coach agent is also in the workspace directory
         project.enter_workspace()?;
 
+        if let Some(view) = view {
+            view.set_role(Role::Coach, turn);
+        }
         output.print(&format!(
             "\n=== TURN {}/{} - COACH MODE ===",
             turn, max_turns
@@ -1893,7 +2759,7 @@ async fn run_autonomous(
 
 REQUIREMENTS:
 {}
-
+{}
 IMPLEMENTATION REVIEW:
 Review the current state of the project and provide a concise critique focusing on:
 1. Whether the requirements are correctly implemented
@@ -1903,20 +2769,45 @@ Review the current state of the project and provide a concise critique focusing
 5. Use UI tools such as webdriver or macax to test functionality thoroughly
 
 CRITICAL INSTRUCTIONS:
-1. You MUST use the final_output tool to provide your feedback
-2. The summary in final_output should be CONCISE and ACTIONABLE
+1. You MUST use the give_feedback tool to deliver your review
+2. The issues and next_steps in give_feedback should be CONCISE and ACTIONABLE
 3. Focus ONLY on what needs to be fixed or improved
-4. Do NOT include your analysis process, file contents, or compilation output in the summary
+4. Do NOT include your analysis process, file contents, or compilation output in the feedback
 
 If the implementation thoroughly meets all requirements, compiles and is fully tested (especially UI flows) *WITHOUT* minor gaps or errors:
-- Call final_output with summary: 'IMPLEMENTATION_APPROVED'
+- Call give_feedback with verdict \"approved\"
 
 If improvements are needed:
-- Call final_output with a brief summary listing ONLY the specific issues to fix
+- Call give_feedback with verdict \"changes_requested\", one issue per item in issues, and next_steps telling the implementer what to do next
+
+Example:
+{{\"tool\": \"give_feedback\", \"args\": {{\"verdict\": \"changes_requested\", \"issues\": [\"`cargo build` fails: missing import in src/main.rs\"], \"next_steps\": \"Fix the build, then add tests for the parser.\"}}}}
 
 Remember: Be clear in your review and concise in your feedback. APPROVE iff the implementation works and thoroughly fits the requirements (implementation > 95% complete). Be rigorous, especially by testing that all UI features work.",
-            requirements
+            requirements,
+            if player_changes.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "\nFILES CHANGED BY THE IMPLEMENTER THIS TURN (+ created, ~ modified, - deleted), review these first:\n{}\n",
+                    player_changes
+                )
+            }
         );
+        // The coach decides whether rephrasing the instructions gets past the filter
+        let coach_prompt = match &player_filtered {
+            Some(message) => format!(
+                "{}\n\nTHE IMPLEMENTER WAS STOPPED BY THE PROVIDER'S CONTENT FILTER THIS TURN:\n{}\nIt was working from {}. If the wording of the requirements or of your feedback is what tripped the filter, rephrase next_steps so the implementer can carry on without it; if the requirements themselves can't be implemented, say so in issues.",
+                coach_prompt,
+                message,
+                if coach_feedback.is_empty() {
+                    "the requirements alone".to_string()
+                } else {
+                    format!("your previous feedback:\n{}\n", coach_feedback)
+                }
+            ),
+            None => coach_prompt,
+        };
 
         output.print("🎓 Starting coach review...");
 
@@ -1936,6 +2827,19 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
                     break;
                 }
                 Err(e) => {
+                    if let Some(G3Error::ContentFiltered(message)) = G3Error::from_error(&e) {
+                        output.print(&format!("🚫 Coach stopped by the content filter: {}", message));
+                        coach_agent.log_error_to_session(
+                            &e,
+                            "assistant",
+                            Some(format!("Turn: {}\nRole: Coach", turn)),
+                        );
+                        content_filtered.push(format!("Turn {} (coach): {}", turn, message));
+                        coach_result_opt = None;
+                        coach_failed = true;
+                        break;
+                    }
+
                     // Check if this is a context length exceeded error
                     use g3_core::error_handling::{classify_error, ErrorType, RecoverableError};
                     let error_type = classify_error(&e);
@@ -1975,6 +2879,7 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
                         let elapsed = start_time.elapsed();
                         let context_window = agent.get_context_window();
 
+                        close_view();
                         output.print(&format!("\n{}", "=".repeat(60)));
                         output.print("📊 AUTONOMOUS MODE SESSION REPORT");
                         output.print(&"=".repeat(60));
@@ -2029,19 +2934,20 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
 
         // If coach failed after max retries, increment turn and continue with default feedback
         if coach_failed {
+            if let Some(view) = view {
+                view.set_verdict("none, the review failed");
+            }
             output.print(&format!(
                 "⚠️ Coach turn {} failed after max retries. Using default feedback.",
                 turn
             ));
             coach_feedback = "The implementation needs review. Please ensure all requirements are met and the code compiles without errors.".to_string();
             // Record turn metrics before incrementing
-            let turn_duration = turn_start_time.elapsed();
-            let turn_tokens = agent.get_context_window().used_tokens.saturating_sub(turn_start_tokens);
-            turn_metrics.push(TurnMetrics {
-                turn_number: turn,
-                tokens_used: turn_tokens,
-                wall_clock_time: turn_duration,
-            });
+            turn_metrics.push(autonomous_turn_metrics(
+                turn,
+                &agent.get_turn_metrics()[player_tasks_before..],
+                coach_agent.get_turn_metrics(),
+            ));
             turn += 1;
 
             if turn > max_turns {
@@ -2055,9 +2961,8 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
         // We have a valid coach result, process it
         let coach_result = coach_result_opt.unwrap();
 
-        // Extract the complete coach feedback from final_output
-        let coach_feedback_text =
-            extract_coach_feedback_from_logs(&coach_result, &coach_agent, &output)?;
+        // Take the coach's structured feedback straight from the task result
+        let coach_feedback_text = coach_feedback_from_result(&coach_result);
 
         // Log the size of the feedback for debugging
         info!(
@@ -2066,26 +2971,36 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
             coach_result.response.len()
         );
 
-        // Check if we got empty feedback (this can happen if the coach doesn't call final_output)
+        // Check if we got empty feedback (this can happen if the coach doesn't call give_feedback)
         if coach_feedback_text.is_empty() {
+            if let Some(view) = view {
+                view.set_verdict("none, no feedback was given");
+            }
             output.print("⚠️ Coach did not provide feedback. This may be a model issue.");
             coach_feedback = "The implementation needs review. Please ensure all requirements are met and the code compiles without errors.".to_string();
             // Record turn metrics before incrementing
-            let turn_duration = turn_start_time.elapsed();
-            let turn_tokens = agent.get_context_window().used_tokens.saturating_sub(turn_start_tokens);
-            turn_metrics.push(TurnMetrics {
-                turn_number: turn,
-                tokens_used: turn_tokens,
-                wall_clock_time: turn_duration,
-            });
+            turn_metrics.push(autonomous_turn_metrics(
+                turn,
+                &agent.get_turn_metrics()[player_tasks_before..],
+                coach_agent.get_turn_metrics(),
+            ));
             turn += 1;
             continue;
         }
 
         output.print_smart(&format!("Coach feedback:\n{}", coach_feedback_text));
 
-        // Check if coach approved the implementation
-        if coach_result.is_approved() || coach_feedback_text.contains("IMPLEMENTATION_APPROVED") {
+        // The coach's verdict decides; IMPLEMENTATION_APPROVED only counts when the coach
+        // gave no structured feedback, so a rejection that quotes it doesn't approve
+        let approved = coach_result.is_approved();
+        if let Some(view) = view {
+            view.set_verdict(if approved {
+                "✅ approved"
+            } else {
+                "🔄 changes requested"
+            });
+        }
+        if approved {
             output.print("\n=== SESSION COMPLETED - IMPLEMENTATION APPROVED ===");
             output.print("✅ Coach approved the implementation!");
             implementation_approved = true;
@@ -2102,13 +3017,11 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
         // Store coach feedback for next iteration
         coach_feedback = coach_feedback_text;
         // Record turn metrics before incrementing
-        let turn_duration = turn_start_time.elapsed();
-        let turn_tokens = agent.get_context_window().used_tokens.saturating_sub(turn_start_tokens);
-        turn_metrics.push(TurnMetrics {
-            turn_number: turn,
-            tokens_used: turn_tokens,
-            wall_clock_time: turn_duration,
-        });
+        turn_metrics.push(autonomous_turn_metrics(
+            turn,
+            &agent.get_turn_metrics()[player_tasks_before..],
+            coach_agent.get_turn_metrics(),
+        ));
         turn += 1;
 
         output.print("🔄 Coach provided feedback for next iteration");
@@ -2118,6 +3031,7 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
     let elapsed = start_time.elapsed();
     let context_window = agent.get_context_window();
 
+    close_view();
     output.print(&format!("\n{}", "=".repeat(60)));
     output.print("📊 AUTONOMOUS MODE SESSION REPORT");
     output.print(&"=".repeat(60));
@@ -2138,6 +3052,13 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
         }
     ));
 
+    if !content_filtered.is_empty() {
+        output.print("\n🚫 Stopped by Content Filters:");
+        for event in &content_filtered {
+            output.print(&format!("   • {}", event));
+        }
+    }
+
     output.print("\n📈 Token Usage Statistics:");
     output.print(&format!("   • Used Tokens: {}", context_window.used_tokens));
     output.print(&format!(
@@ -2162,6 +3083,46 @@ Remember: Be clear in your review and concise in your feedback. APPROVE iff the
     } else {
         output.print("\n🔄 Autonomous mode terminated (max iterations)");
     }
+    let notifications = &agent.get_config().notifications;
+    if elapsed >= std::time::Duration::from_secs(notifications.min_turn_seconds) {
+        g3_core::notify::send(
+            notifications,
+            &g3_core::notify::Notification {
+                title: format!(
+                    "g3 autonomous run {} after {} turns",
+                    if implementation_approved { "approved" } else { "stopped" },
+                    turn
+                ),
+                body: format!("Took {:.0}s", elapsed.as_secs_f64()),
+            },
+        );
+    }
+    if let Some(record) = run_record.filter(|record| !record.rounds.is_empty()) {
+        output.print(&format!(
+            "🌿 {} rounds committed to {}; review them with `g3 review-run {}`",
+            record.rounds.len(),
+            record.branch,
+            record.run_id
+        ));
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::self_review_prompt;
+
+    #[test]
+    fn test_self_review_prompt_lists_the_requirements_and_changes() {
+        let prompt = self_review_prompt("- Add --json", "~ src/main.rs");
+        assert!(prompt.contains("REQUIREMENTS (the acceptance criteria):\n- Add --json"));
+        assert!(prompt.contains("FILES YOU CHANGED THIS TURN"));
+        assert!(prompt.contains("~ src/main.rs"));
+        assert!(prompt.contains("show_changes"));
+        assert!(prompt.contains("give_feedback"));
+
+        // Nothing changed, so no empty file list
+        assert!(!self_review_prompt("- Add --json", "").contains("FILES YOU CHANGED"));
+    }
+}
@@ -1,13 +1,19 @@
 use g3_core::ui_writer::UiWriter;
 use std::io::{self, Write};
 
+use crate::stream_flush::StreamFlush;
+
 /// Machine-mode implementation of UiWriter that prints plain, unformatted output
 /// This is designed for programmatic consumption and outputs everything verbatim
-pub struct MachineUiWriter;
+pub struct MachineUiWriter {
+    stream: StreamFlush,
+}
 
 impl MachineUiWriter {
     pub fn new() -> Self {
-        Self
+        Self {
+            stream: StreamFlush::new(),
+        }
     }
 }
 
@@ -77,15 +83,16 @@ impl UiWriter for MachineUiWriter {
 
     fn print_agent_response(&self, content: &str) {
         print!("{}", content);
-        let _ = io::stdout().flush();
+        self.stream.wrote(content);
     }
 
     fn notify_sse_received(&self) {
-        // No-op for machine mode
+        self.stream.tick();
     }
 
     fn flush(&self) {
-        let _ = io::stdout().flush();
+        // Called after every streamed chunk, so flushes are batched
+        self.stream.tick();
     }
     
     fn wants_full_output(&self) -> bool {
@@ -0,0 +1,110 @@
+//! The interactive prompt: a line editor with per-project history, and composing long
+//! prompts in `$EDITOR`.
+//!
+//! History is kept per workspace in `.g3/history`, so the prompts of one project don't mix
+//! with another's; a workspace without one starts from the global `~/.g3_history`. Ctrl-R
+//! searches it. Pasted text keeps its newlines (bracketed paste), and Alt-Enter starts a
+//! new line without sending the prompt.
+
+use anyhow::{Context, Result};
+use rustyline::{Cmd, DefaultEditor, KeyCode, KeyEvent, Modifiers};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// History of a workspace's prompts, relative to the workspace
+pub const HISTORY_FILE: &str = ".g3/history";
+
+/// History used before it was kept per project, in the home directory
+const GLOBAL_HISTORY_FILE: &str = ".g3_history";
+
+const MAX_HISTORY_ENTRIES: usize = 10_000;
+
+/// A line editor for prompts
+pub fn new_editor() -> Result<DefaultEditor> {
+    let config = rustyline::Config::builder()
+        .max_history_size(MAX_HISTORY_ENTRIES)?
+        .history_ignore_dups(true)?
+        .history_ignore_space(true)
+        .bracketed_paste(true)
+        .build();
+    let mut editor = DefaultEditor::with_config(config)?;
+    editor.bind_sequence(KeyEvent(KeyCode::Enter, Modifiers::ALT), Cmd::Newline);
+    Ok(editor)
+}
+
+/// Load the workspace's history into `editor`, and return where to save it
+pub fn load_history(editor: &mut DefaultEditor, workspace: &Path) -> PathBuf {
+    let path = workspace.join(HISTORY_FILE);
+    if editor.load_history(&path).is_err() {
+        if let Some(global) = dirs::home_dir().map(|home| home.join(GLOBAL_HISTORY_FILE)) {
+            let _ = editor.load_history(&global);
+        }
+    }
+    path
+}
+
+pub fn save_history(editor: &mut DefaultEditor, path: &Path) {
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let _ = editor.save_history(path);
+}
+
+/// The editor to compose prompts in: `$VISUAL`, `$EDITOR` or `vi`
+pub fn editor_command() -> String {
+    ["VISUAL", "EDITOR"]
+        .into_iter()
+        .find_map(|var| {
+            std::env::var(var)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+        })
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Open `editor` (a command, possibly with arguments such as `code --wait`) on a file
+/// holding `initial`, and return what was saved. `None` if it was left empty.
+pub fn compose(editor: &str, initial: &str) -> Result<Option<String>> {
+    let path = std::env::temp_dir().join(format!("g3-prompt-{}.md", std::process::id()));
+    std::fs::write(&path, initial)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("No editor configured")?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to start {}", program));
+    let prompt = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let status = status?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}; nothing was sent", program, status);
+    }
+    let prompt = prompt.with_context(|| format!("Failed to read {}", path.display()))?;
+    let prompt = prompt.trim();
+    Ok((!prompt.is_empty()).then(|| prompt.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_in_editor() {
+        // `true` leaves the file as it was written
+        assert_eq!(
+            compose(
+                "true",
+                "Refactor the parser\n\n```rust\nfn parse() {}\n```\n"
+            )
+            .unwrap(),
+            Some("Refactor the parser\n\n```rust\nfn parse() {}\n```".to_string())
+        );
+        assert_eq!(compose("true", "  \n").unwrap(), None);
+        assert!(compose("false", "draft").is_err());
+        assert!(compose("g3-no-such-editor", "draft").is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use g3_core::stream_recording::{replay, StreamRecording};
+use std::path::Path;
+
+/// `g3 replay-stream <artifact>`: feed a recorded provider stream through the stream parser
+/// and show what it made of each chunk
+pub fn run_replay_stream_command(artifact: &Path) -> Result<()> {
+    let recording = StreamRecording::load(artifact)?;
+    println!(
+        "🎞️  {} chunks from {} ({}), recorded {}",
+        recording.chunks.len(),
+        recording.provider,
+        recording.model,
+        recording.started_at.format("%Y-%m-%d %H:%M:%S UTC")
+    );
+    println!();
+
+    let replay = replay(&recording);
+    for step in &replay.steps {
+        println!(
+            "#{:<4} +{:>7.3}s  {}",
+            step.index,
+            step.at_ms as f64 / 1000.0,
+            step.summary
+        );
+        if let Some(error) = &step.error {
+            println!("       ❌ {}", error);
+        }
+        for call in &step.tool_calls {
+            println!("       🔧 {} {}", call.tool, call.args);
+        }
+    }
+
+    println!();
+    println!("Text: {} chars", replay.text.chars().count());
+    println!(
+        "Message stopped: {}",
+        if replay.message_stopped { "yes" } else { "no" }
+    );
+    if !replay.partial_tool_calls.is_empty() {
+        println!("Incomplete tool calls:");
+        for call in &replay.partial_tool_calls {
+            println!("  {} {}", call.tool, call.arguments);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use g3_core::run_commits::{round_diff, RoundChoice, RunRecord};
+
+use crate::init::ask;
+use crate::Cli;
+
+/// `g3 review-run <run id>`: go through the rounds an autonomous run committed, choosing to
+/// accept, reject or squash each, and build a branch from the choices
+pub fn run_review_run_command(cli: &Cli, run_id: &str) -> Result<()> {
+    if let Some(workspace) = &cli.workspace {
+        std::env::set_current_dir(workspace)?;
+    }
+    let workspace = std::env::current_dir()?;
+    let record = RunRecord::load(&workspace, run_id)?;
+    if record.rounds.is_empty() {
+        println!("Run {} committed no rounds.", run_id);
+        return Ok(());
+    }
+
+    println!(
+        "🌿 Run {} committed {} rounds to {}",
+        run_id,
+        record.rounds.len(),
+        record.branch
+    );
+    let mut choices = Vec::with_capacity(record.rounds.len());
+    for (i, round) in record.rounds.iter().enumerate() {
+        println!();
+        println!("{}", "=".repeat(60));
+        println!("Round {} ({}/{})", round.round, i + 1, record.rounds.len());
+        println!("{}", "=".repeat(60));
+        println!("{}", round_diff(&workspace, round)?.trim_end());
+        println!();
+        let choice = loop {
+            match ask("[a]ccept, [r]eject, [s]quash into previous, [q]uit", "a")?
+                .to_lowercase()
+                .as_str()
+            {
+                "a" | "accept" => break Some(RoundChoice::Accept),
+                "r" | "reject" => break Some(RoundChoice::Reject),
+                "s" | "squash" => break Some(RoundChoice::Squash),
+                "q" | "quit" => break None,
+                _ => println!("Enter a, r, s or q"),
+            }
+        };
+        match choice {
+            Some(choice) => choices.push(choice),
+            None => {
+                println!("Review stopped; no branch was written.");
+                return Ok(());
+            }
+        }
+    }
+
+    let outcome = record.build_reviewed_branch(&workspace, &choices)?;
+    println!();
+    for round in &outcome.conflicts {
+        println!(
+            "⚠️  Round {} doesn't apply without the rounds rejected before it and was left out",
+            round
+        );
+    }
+    println!(
+        "✅ {} with {} commits on top of {}",
+        outcome.branch,
+        outcome.commits,
+        &record.base[..record.base.len().min(12)]
+    );
+    Ok(())
+}
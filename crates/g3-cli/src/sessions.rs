@@ -0,0 +1,33 @@
+use anyhow::Result;
+use clap::Subcommand;
+use g3_config::Config;
+use g3_core::session_logs::{self, LOGS_DIR};
+use std::path::Path;
+
+/// Manage the session logs in logs/
+#[derive(Subcommand, Clone, Debug)]
+pub enum SessionsCommand {
+    /// Compress old session logs and delete the oldest, as `[logs]` says
+    Prune,
+}
+
+pub fn run_sessions_command(command: &SessionsCommand, config_path: Option<&str>) -> Result<()> {
+    match command {
+        SessionsCommand::Prune => {
+            let config = Config::load(config_path)?;
+            let report = session_logs::prune(Path::new(LOGS_DIR), &config.logs, None)?;
+            if report.compressed == 0 && report.removed == 0 {
+                println!("Nothing to prune in {}/", LOGS_DIR);
+            } else {
+                println!(
+                    "✅ Compressed {} and removed {} session logs in {}/, freeing {:.1} MB",
+                    report.compressed,
+                    report.removed,
+                    LOGS_DIR,
+                    report.freed_bytes as f64 / (1024.0 * 1024.0)
+                );
+            }
+            Ok(())
+        }
+    }
+}
@@ -1,26 +1,70 @@
+use g3_core::text_display;
+use std::sync::Arc;
+
+/// Receives messages in place of stdout
+pub type Sink = Arc<dyn Fn(&str) + Send + Sync>;
+
 /// Simple output helper for printing messages
 pub struct SimpleOutput {
     machine_mode: bool,
+    plain: bool,
+    /// Where messages go instead of stdout, such as the `--tui` view
+    sink: Option<Sink>,
 }
 
 impl SimpleOutput {
     pub fn new() -> Self {
-        SimpleOutput { machine_mode: false }
+        SimpleOutput {
+            machine_mode: false,
+            plain: false,
+            sink: None,
+        }
     }
 
     pub fn new_with_mode(machine_mode: bool) -> Self {
-        SimpleOutput { machine_mode }
+        SimpleOutput {
+            machine_mode,
+            plain: false,
+            sink: None,
+        }
+    }
+
+    /// Print ASCII tags in place of emoji and box drawing (`--plain`)
+    pub fn with_plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    /// Send messages to `sink` instead of printing them
+    pub fn with_sink(mut self, sink: Sink) -> Self {
+        self.sink = Some(sink);
+        self
     }
 
     pub fn print(&self, message: &str) {
         if !self.machine_mode {
-            println!("{}", message);
+            self.emit(&self.render(message));
         }
     }
 
     pub fn print_smart(&self, message: &str) {
         if !self.machine_mode {
-            println!("{}", message);
+            self.emit(&self.render(message));
+        }
+    }
+
+    fn emit(&self, message: &str) {
+        match &self.sink {
+            Some(sink) => sink(message),
+            None => println!("{}", message),
+        }
+    }
+
+    fn render(&self, message: &str) -> String {
+        if self.plain {
+            text_display::plain(message)
+        } else {
+            message.to_string()
         }
     }
 }
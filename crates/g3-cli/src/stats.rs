@@ -0,0 +1,118 @@
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use g3_config::Config;
+use g3_core::stats::{StatsStore, StatsSummary};
+
+/// Window shown by `g3 stats` without `--all-time`
+const RECENT_DAYS: i64 = 7;
+
+pub fn run_stats_command(all_time: bool, config_path: Option<&str>) -> Result<()> {
+    let config = Config::load(config_path)?;
+    let store = StatsStore::from_config(&config.stats);
+    let records = store.load()?;
+
+    if !config.stats.enabled && records.is_empty() {
+        println!("Usage stats are disabled. Enable them in your config with:");
+        println!();
+        println!("  [stats]");
+        println!("  enabled = true");
+        println!();
+        println!(
+            "Stats are only written to {} and never sent anywhere.",
+            store.path().display()
+        );
+        return Ok(());
+    }
+
+    let since = (!all_time).then(|| Utc::now() - Duration::days(RECENT_DAYS));
+    let summary = StatsSummary::from_records(&records, since);
+    let period = if all_time {
+        "all time".to_string()
+    } else {
+        format!("last {} days", RECENT_DAYS)
+    };
+
+    println!("📊 g3 usage stats ({})", period);
+    println!("{}", "=".repeat(60));
+    if summary.tasks == 0 {
+        println!("No tasks recorded in {}", store.path().display());
+        return Ok(());
+    }
+
+    if let (Some(first), Some(last)) = (summary.first, summary.last) {
+        println!(
+            "Period:          {} to {}",
+            first.format("%Y-%m-%d"),
+            last.format("%Y-%m-%d")
+        );
+    }
+    println!("Tasks run:       {}", summary.tasks);
+    println!(
+        "Success rate:    {:.1}% ({} of {})",
+        summary.success_rate() * 100.0,
+        summary.successful_tasks,
+        summary.tasks
+    );
+    println!(
+        "Avg per task:    {:.0} tokens, {:.1}s",
+        summary.average_tokens_per_task(),
+        summary.total_duration.as_secs_f64() / summary.tasks as f64
+    );
+    if let Some(average_cost) = summary.cost.average_cost() {
+        let unpriced = summary.tasks - summary.cost.costed_tasks;
+        println!(
+            "Est. cost:       ${:.4} per task, ${:.2} total{}",
+            average_cost,
+            summary.cost.cost,
+            if unpriced > 0 {
+                format!(" ({} tasks recorded without a price)", unpriced)
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    println!();
+    println!("Models:");
+    for (model, stats) in &summary.models {
+        match stats.average_cost() {
+            Some(cost) => println!(
+                "  {:<40} {:>6} tasks  ${:.4} per task",
+                model, stats.tasks, cost
+            ),
+            None => println!("  {:<40} {:>6} tasks", model, stats.tasks),
+        }
+    }
+
+    if !summary.tools.is_empty() {
+        let mut tools: Vec<_> = summary.tools.iter().collect();
+        tools.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.calls));
+
+        println!();
+        println!(
+            "{:<24} {:>8} {:>10} {:>9}",
+            "Tool", "Calls", "Success", "Retries"
+        );
+        println!("{}", "-".repeat(54));
+        for (name, stats) in tools {
+            println!(
+                "{:<24} {:>8} {:>9.1}% {:>9}",
+                name,
+                stats.calls,
+                stats.success_rate() * 100.0,
+                stats.retries
+            );
+        }
+    }
+
+    let retried = summary.most_retried_tools();
+    if !retried.is_empty() {
+        println!();
+        println!("Most retried:");
+        for (name, stats) in retried.iter().take(5) {
+            println!("  {} ({} retries)", name, stats.retries);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! `--step`: pause before each tool call to run, edit, skip or abort it.
+
+use crossterm::style::{Color, ResetColor, SetForegroundColor};
+use g3_core::step::{StepDecision, ToolStepper};
+use g3_core::ToolCall;
+use serde_json::Value;
+
+use crate::init::ask;
+use crate::prompt_editor;
+
+/// Asks on the terminal about each tool call
+pub struct TerminalStepper;
+
+impl ToolStepper for TerminalStepper {
+    fn before_tool_call(&self, tool_call: &ToolCall) -> StepDecision {
+        let args = serde_json::to_string_pretty(&tool_call.args)
+            .unwrap_or_else(|_| tool_call.args.to_string());
+        println!(
+            "\n{}⏸  {}{}\n{}",
+            SetForegroundColor(Color::Yellow),
+            tool_call.tool,
+            ResetColor,
+            args
+        );
+        loop {
+            // Without a terminal to ask on, the call is aborted rather than run unseen
+            let Ok(answer) = ask("[r]un, [e]dit args, [s]kip, [a]bort", "r") else {
+                return StepDecision::Abort;
+            };
+            match parse_choice(&answer) {
+                Some('r') => return StepDecision::Run,
+                Some('s') => return StepDecision::Skip,
+                Some('a') => return StepDecision::Abort,
+                Some('e') => match edit_args(&args) {
+                    Ok(Some(args)) => return StepDecision::Edit(args),
+                    Ok(None) => println!("Arguments unchanged"),
+                    Err(e) => println!("❌ {}", e),
+                },
+                _ => println!("Answer r, e, s or a"),
+            }
+        }
+    }
+}
+
+/// `r`, `e`, `s` or `a`, from the letter or the whole word
+fn parse_choice(answer: &str) -> Option<char> {
+    let answer = answer.trim().to_lowercase();
+    ["run", "edit", "skip", "abort"]
+        .into_iter()
+        .find(|word| !answer.is_empty() && word.starts_with(&answer))
+        .and_then(|word| word.chars().next())
+}
+
+/// Open the arguments in the editor. `None` if they were left as they were.
+fn edit_args(args: &str) -> anyhow::Result<Option<Value>> {
+    let edited = prompt_editor::compose(&prompt_editor::editor_command(), args)?
+        .ok_or_else(|| anyhow::anyhow!("The arguments were left empty"))?;
+    if edited == args.trim() {
+        return Ok(None);
+    }
+    let edited: Value = serde_json::from_str(&edited)
+        .map_err(|e| anyhow::anyhow!("The arguments are not valid JSON: {}", e))?;
+    if !edited.is_object() {
+        anyhow::bail!("The arguments must be a JSON object");
+    }
+    Ok(Some(edited))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_choice() {
+        assert_eq!(parse_choice("r"), Some('r'));
+        assert_eq!(parse_choice(" Edit "), Some('e'));
+        assert_eq!(parse_choice("sk"), Some('s'));
+        assert_eq!(parse_choice("abort"), Some('a'));
+        assert_eq!(parse_choice("runs"), None);
+        assert_eq!(parse_choice(""), None);
+    }
+}
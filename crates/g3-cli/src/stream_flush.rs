@@ -0,0 +1,75 @@
+//! Batching of streamed text written to stdout.
+//!
+//! Providers can stream a response a few characters at a time, and flushing stdout after
+//! each chunk makes the terminal flicker and slows the stream down. Streamed text is left
+//! in stdout's buffer until it ends a line or [`FLUSH_INTERVAL`] has passed since the last
+//! flush.
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest streamed text is held back
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct StreamFlush {
+    last_flush: Mutex<Instant>,
+}
+
+impl StreamFlush {
+    pub fn new() -> Self {
+        Self {
+            last_flush: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Flush stdout if `text`, just written, ends a line or the interval has passed
+    pub fn wrote(&self, text: &str) {
+        if text.contains('\n') {
+            self.flush();
+        } else {
+            self.tick();
+        }
+    }
+
+    /// Flush stdout if the interval has passed, for when the stream shows signs of life
+    /// without text
+    pub fn tick(&self) {
+        if self.due(Instant::now()) {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&self) {
+        let _ = io::stdout().flush();
+        *self.last_flush.lock().unwrap() = Instant::now();
+    }
+
+    fn due(&self, now: Instant) -> bool {
+        now.duration_since(*self.last_flush.lock().unwrap()) >= FLUSH_INTERVAL
+    }
+}
+
+impl Default for StreamFlush {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_flush_interval() {
+        let stream = StreamFlush::new();
+        let start = *stream.last_flush.lock().unwrap();
+        assert!(!stream.due(start + FLUSH_INTERVAL / 2));
+        assert!(stream.due(start + FLUSH_INTERVAL));
+
+        stream.wrote("a line\n");
+        let flushed = *stream.last_flush.lock().unwrap();
+        assert!(flushed >= start);
+        assert!(!stream.due(flushed));
+    }
+}
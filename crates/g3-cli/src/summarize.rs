@@ -0,0 +1,53 @@
+use anyhow::Result;
+use g3_config::Config;
+use g3_core::{ui_writer::UiWriter, Agent};
+
+use crate::ui_writer_impl::ConsoleUiWriter;
+use crate::Cli;
+
+/// `g3 summarize-changes`: describe the changes made since a session started
+pub async fn run_summarize_changes_command(
+    cli: &Cli,
+    since: Option<&str>,
+    session: Option<&str>,
+) -> Result<()> {
+    if let Some(workspace) = &cli.workspace {
+        std::env::set_current_dir(workspace)?;
+    }
+
+    let mut config = Config::load_with_overrides(
+        cli.config.as_deref(),
+        cli.provider.clone(),
+        cli.model.clone(),
+    )?;
+    if let Some(profile) = &cli.profile {
+        config.agent.profile = Some(profile.clone());
+    }
+    if cli.plain {
+        config.agent.plain_output = true;
+    }
+    let ui_writer = ConsoleUiWriter::new().with_plain(config.agent.plain_output);
+    let agent = Agent::new_with_readme_and_quiet(config, ui_writer, None, true).await?;
+
+    summarize_changes(&agent, since, session).await
+}
+
+/// Generate and print the commit message, PR description and CHANGELOG entry for `agent`'s
+/// changes, pointing at the artifacts they were saved to
+pub async fn summarize_changes<W: UiWriter>(
+    agent: &Agent<W>,
+    since: Option<&str>,
+    session: Option<&str>,
+) -> Result<()> {
+    println!("📝 Summarizing changes...");
+    let (summary, paths) = agent.summarize_changes(since, session).await?;
+
+    println!();
+    println!("{}", summary.commit_message);
+    println!();
+    println!("Saved for review:");
+    for path in paths {
+        println!("  {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use g3_core::tool_registry::ToolRegistry;
+
+/// `g3 tools`: list the built-in tools with their versions and capabilities, so integrations
+/// can check what a g3 binary provides before relying on it
+pub fn run_tools_command(json: bool) -> Result<()> {
+    let registry = ToolRegistry::builtin();
+    if json {
+        println!("{}", serde_json::to_string_pretty(registry.specs())?);
+        return Ok(());
+    }
+
+    println!("{:<28} {:>7}  {:<17} CAPABILITIES", "TOOL", "VERSION", "GROUP");
+    for spec in registry.specs() {
+        println!(
+            "{:<28} {:>7}  {:<17} {}",
+            spec.name,
+            spec.version,
+            format!("{:?}", spec.group),
+            spec.capabilities.names().join(", ")
+        );
+    }
+    Ok(())
+}
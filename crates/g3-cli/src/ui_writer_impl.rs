@@ -1,6 +1,38 @@
+use g3_core::stream_rate::StreamProgress;
+use g3_core::text_display;
 use g3_core::ui_writer::UiWriter;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::stream_flush::StreamFlush;
+
+/// A failed tool call whose block is the last thing printed. An identical failure right
+/// after it is folded into its block as a count instead of printed again.
+struct Failure {
+    tool: String,
+    args: Vec<(String, String)>,
+    line: String,
+    count: usize,
+}
+
+/// The stream indicator is only shown once no text has been printed for this long, so
+/// it doesn't flicker between the lines of a response
+const INDICATOR_QUIET: Duration = Duration::from_secs(2);
+
+/// The line of a streaming response's elapsed time and speed, redrawn in place
+#[derive(Default)]
+struct ProgressLine {
+    /// It is on screen, on a line of its own
+    shown: bool,
+    /// The last text printed didn't end its line
+    mid_line: bool,
+    /// When streamed text was last printed
+    last_text: Option<Instant>,
+    /// A line of text was ended early to show the indicator, so the blank line that would
+    /// have ended it is left out
+    line_broken: bool,
+}
 
 /// Console implementation of UiWriter that prints to stdout
 pub struct ConsoleUiWriter {
@@ -9,6 +41,17 @@ pub struct ConsoleUiWriter {
     current_output_line: Mutex<Option<String>>,
     output_line_printed: Mutex<bool>,
     in_todo_tool: Mutex<bool>,
+    /// Rows printed by the current tool block, from its header on
+    block_rows: Mutex<usize>,
+    last_failure: Mutex<Option<Failure>>,
+    /// Blank rows printed since the last failure's block
+    rows_after_failure: Mutex<usize>,
+    /// A reasoning summary is being printed, and the response will need a new line
+    in_reasoning: Mutex<bool>,
+    stream: StreamFlush,
+    progress: Mutex<ProgressLine>,
+    /// ASCII instead of emoji and box drawing, and no redrawn lines, for screen readers
+    plain: bool,
 }
 
 impl ConsoleUiWriter {
@@ -19,6 +62,94 @@ impl ConsoleUiWriter {
             current_output_line: Mutex::new(None),
             output_line_printed: Mutex::new(false),
             in_todo_tool: Mutex::new(false),
+            block_rows: Mutex::new(0),
+            last_failure: Mutex::new(None),
+            rows_after_failure: Mutex::new(0),
+            in_reasoning: Mutex::new(false),
+            stream: StreamFlush::new(),
+            progress: Mutex::new(ProgressLine::default()),
+            plain: false,
+        }
+    }
+
+    /// Print screen-reader friendly output (`--plain`)
+    pub fn with_plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    /// Remove the stream indicator before printing `text`, and note where the line ends.
+    /// Returns whether a line was ended early for the indicator.
+    fn end_progress(&self, text: &str) -> bool {
+        let mut progress = self.progress.lock().unwrap();
+        if std::mem::take(&mut progress.shown) {
+            print!("\r\x1b[2K");
+        }
+        if !text.is_empty() {
+            progress.mid_line = !text.ends_with('\n');
+        }
+        std::mem::take(&mut progress.line_broken)
+    }
+
+    /// Something other than a tool block was printed, so the next failure can't be folded
+    /// into the last one
+    fn end_failures(&self) {
+        *self.last_failure.lock().unwrap() = None;
+    }
+
+    /// Fold the tool call finishing now into the block of the failure before it, if it
+    /// failed the same way with the same arguments. Only single-line failures are folded,
+    /// so the block is three rows: a blank, the header and the failure.
+    fn fold_repeated_failure(&self, timing: &str) -> bool {
+        let tool_name = self.current_tool_name.lock().unwrap().clone().unwrap_or_default();
+        let line = self.current_output_line.lock().unwrap().clone();
+        let Some(line) = line.filter(|line| line.trim_start().starts_with('❌')) else {
+            self.end_failures();
+            return false;
+        };
+        let args = self.current_tool_args.lock().unwrap().clone();
+        let mut last_failure = self.last_failure.lock().unwrap();
+        if *self.block_rows.lock().unwrap() != 3 {
+            *last_failure = None;
+            return false;
+        }
+
+        match last_failure.as_mut() {
+            Some(failure)
+                if failure.tool == tool_name
+                    && failure.args == args && failure.line == line =>
+            {
+                failure.count += 1;
+                // Up over this block, the blanks between the blocks, and the last block's
+                // closing blank, timing and failure rows
+                let rows = 3 + *self.rows_after_failure.lock().unwrap() + 3;
+                print!("\x1b[{}A\r\x1b[J", rows);
+                println!(
+                    "│ \x1b[2m{}\x1b[0m \x1b[33m(×{})\x1b[0m",
+                    line, failure.count
+                );
+                println!("{}", timing);
+                *self.rows_after_failure.lock().unwrap() = 0;
+                true
+            }
+            _ => {
+                *last_failure = Some(Failure {
+                    tool: tool_name,
+                    args,
+                    line,
+                    count: 1,
+                });
+                *self.rows_after_failure.lock().unwrap() = 0;
+                false
+            }
+        }
+    }
+
+    fn render(&self, message: &str) -> String {
+        if self.plain {
+            text_display::plain(message)
+        } else {
+            message.to_string()
         }
     }
 
@@ -45,18 +176,18 @@ impl ConsoleUiWriter {
         if trimmed.starts_with("- [ ]") {
             // Incomplete task
             let task = trimmed.strip_prefix("- [ ]").unwrap_or(trimmed).trim();
-            println!("{}☐ {}", indent, task);
+            print_wrapped(&self.render(&format!("{}☐ ", indent)), task, "");
         } else if trimmed.starts_with("- [x]") || trimmed.starts_with("- [X]") {
             // Completed task
             let task = trimmed.strip_prefix("- [x]")
                 .or_else(|| trimmed.strip_prefix("- [X]"))
                 .unwrap_or(trimmed)
                 .trim();
-            println!("{}\x1b[2m☑ {}\x1b[0m", indent, task);
+            print_wrapped(&self.render(&format!("{}☑ ", indent)), task, "\x1b[2m");
         } else if trimmed.starts_with("- ") {
             // Regular bullet point
             let item = trimmed.strip_prefix("- ").unwrap_or(trimmed).trim();
-            println!("{}• {}", indent, item);
+            print_wrapped(&self.render(&format!("{}• ", indent)), item, "");
         } else if trimmed.starts_with("# ") {
             // Heading
             let heading = trimmed.strip_prefix("# ").unwrap_or(trimmed).trim();
@@ -71,27 +202,62 @@ impl ConsoleUiWriter {
             println!("{}\x1b[1m{}\x1b[0m", indent, text);
         } else {
             // Regular text or note
-            println!("{}{}", indent, trimmed);
+            print_wrapped(&indent, trimmed, "");
+        }
+    }
+}
+
+/// Print `text` after `prefix`, wrapped to the terminal width with continuation lines
+/// aligned under the text, in the ANSI `style` given
+fn print_wrapped(prefix: &str, text: &str, style: &str) {
+    let columns = crossterm::terminal::size()
+        .map(|(columns, _)| columns as usize)
+        .unwrap_or(80);
+    let prefix_width = text_display::display_width(prefix);
+    let continuation = " ".repeat(prefix_width);
+    let lines = text_display::wrap(text, columns.saturating_sub(prefix_width).max(20));
+    for (i, line) in lines.iter().enumerate() {
+        let lead = if i == 0 { prefix } else { &continuation };
+        if style.is_empty() {
+            println!("{}{}", lead, line);
+        } else {
+            println!("{}{}{}\x1b[0m", style, lead, line);
         }
     }
 }
 
 impl UiWriter for ConsoleUiWriter {
     fn print(&self, message: &str) {
-        print!("{}", message);
+        self.end_failures();
+        self.end_progress(message);
+        print!("{}", self.render(message));
     }
 
     fn println(&self, message: &str) {
-        println!("{}", message);
+        *self.in_reasoning.lock().unwrap() = false;
+        let line_broken = self.end_progress("\n");
+        if message.is_empty() && line_broken {
+            return;
+        }
+        if message.is_empty() && self.last_failure.lock().unwrap().is_some() {
+            *self.rows_after_failure.lock().unwrap() += 1;
+        } else {
+            self.end_failures();
+        }
+        println!("{}", self.render(message));
     }
 
     fn print_inline(&self, message: &str) {
-        print!("{}", message);
+        self.end_failures();
+        self.end_progress(message);
+        print!("{}", self.render(message));
         let _ = io::stdout().flush();
     }
 
     fn print_system_prompt(&self, prompt: &str) {
-        println!("🔍 System Prompt:");
+        self.end_failures();
+        self.end_progress("\n");
+        println!("{}", self.render("🔍 System Prompt:"));
         println!("================");
         println!("{}", prompt);
         println!("================");
@@ -99,10 +265,21 @@ impl UiWriter for ConsoleUiWriter {
     }
 
     fn print_context_status(&self, message: &str) {
-        println!("{}", message);
+        self.end_failures();
+        self.end_progress("\n");
+        println!("{}", self.render(message));
     }
 
     fn print_context_thinning(&self, message: &str) {
+        self.end_failures();
+        self.end_progress("\n");
+        if self.plain {
+            println!();
+            println!("{}", text_display::plain(message));
+            println!();
+            return;
+        }
+
         // Animated highlight for context thinning
         // Use bright cyan/green with a quick flash animation
         
@@ -170,10 +347,12 @@ impl UiWriter for ConsoleUiWriter {
         // Skip normal header for todo tools
         if *self.in_todo_tool.lock().unwrap() {
             println!(); // Just add a newline
+            *self.block_rows.lock().unwrap() = 1;
             return;
         }
         
         println!();
+        *self.block_rows.lock().unwrap() = 2;
         // Now print the tool header with the most important arg in bold green
         if let Some(tool_name) = self.current_tool_name.lock().unwrap().as_ref() {
             let args = self.current_tool_args.lock().unwrap();
@@ -186,20 +365,8 @@ impl UiWriter for ConsoleUiWriter {
                 .or_else(|| args.first());
 
             if let Some((_, value)) = important_arg {
-                // For multi-line values, only show the first line
-                let first_line = value.lines().next().unwrap_or("");
-
-                // Truncate long values for display
-                let display_value = if first_line.len() > 80 {
-                    // Use char_indices to safely truncate at character boundary
-                    let truncate_at = first_line.char_indices()
-                        .nth(77)
-                        .map(|(i, _)| i)
-                        .unwrap_or(first_line.len());
-                    format!("{}...", &first_line[..truncate_at])
-                } else {
-                    first_line.to_string()
-                };
+                // For multi-line values, only show the first line, cut to 80 columns
+                let display_value = text_display::truncate(value.lines().next().unwrap_or(""), 80);
 
                 // Add range information for read_file tool calls
                 let header_suffix = if tool_name == "read_file" {
@@ -218,8 +385,15 @@ impl UiWriter for ConsoleUiWriter {
                     String::new()
                 };
 
+                if self.plain {
+                    println!("Tool {}: {}{}", tool_name, text_display::plain(&display_value), header_suffix);
+                    return;
+                }
+
                 // Print with bold green tool name, purple (non-bold) for pipe and args
                 println!("┌─\x1b[1;32m {}\x1b[0m\x1b[35m | {}{}\x1b[0m", tool_name, display_value, header_suffix);
+            } else if self.plain {
+                println!("Tool {}", tool_name);
             } else {
                 // Print with bold green formatting using ANSI escape codes
                 println!("┌─\x1b[1;32m {}\x1b[0m", tool_name);
@@ -228,6 +402,12 @@ impl UiWriter for ConsoleUiWriter {
     }
 
     fn update_tool_output_line(&self, line: &str) {
+        // Lines that are redrawn in place are hard to follow with a screen reader
+        if self.plain {
+            println!("  {}", text_display::plain(line));
+            return;
+        }
+
         let mut current_line = self.current_output_line.lock().unwrap();
         let mut line_printed = self.output_line_printed.lock().unwrap();
 
@@ -240,6 +420,9 @@ impl UiWriter for ConsoleUiWriter {
         // Print the new line
         println!("│ \x1b[2m{}\x1b[0m", line);
         let _ = io::stdout().flush();
+        if !*line_printed {
+            *self.block_rows.lock().unwrap() += 1;
+        }
 
         // Update state
         *current_line = Some(line.to_string());
@@ -252,8 +435,14 @@ impl UiWriter for ConsoleUiWriter {
             self.print_todo_line(line);
             return;
         }
+
+        if self.plain {
+            println!("  {}", text_display::plain(line));
+            return;
+        }
         
         println!("│ \x1b[2m{}\x1b[0m", line);
+        *self.block_rows.lock().unwrap() += 1;
     }
 
     fn print_tool_output_summary(&self, count: usize) {
@@ -263,10 +452,13 @@ impl UiWriter for ConsoleUiWriter {
         }
         
         println!(
-            "│ \x1b[2m({} line{})\x1b[0m",
+            "{}({} line{}){}",
+            if self.plain { "  " } else { "│ \x1b[2m" },
             count,
-            if count == 1 { "" } else { "s" }
+            if count == 1 { "" } else { "s" },
+            if self.plain { "" } else { "\x1b[0m" }
         );
+        *self.block_rows.lock().unwrap() += 1;
     }
 
     fn print_tool_timing(&self, duration_str: &str) {
@@ -318,7 +510,14 @@ impl UiWriter for ConsoleUiWriter {
             ""
         };
 
-        println!("└─ ⚡️ {}{}\x1b[0m", color_code, duration_str);
+        if self.plain {
+            println!("Done in {}", duration_str);
+        } else {
+            let timing = format!("└─ ⚡️ {}{}\x1b[0m", color_code, duration_str);
+            if !self.fold_repeated_failure(&timing) {
+                println!("{}", timing);
+            }
+        }
         println!();
         // Clear the stored tool info
         *self.current_tool_name.lock().unwrap() = None;
@@ -328,20 +527,73 @@ impl UiWriter for ConsoleUiWriter {
     }
 
     fn print_agent_prompt(&self) {
-        let _ = io::stdout().flush();
+        self.stream.flush();
     }
 
     fn print_agent_response(&self, content: &str) {
+        if !content.is_empty() {
+            self.end_failures();
+            self.end_progress(content);
+            self.progress.lock().unwrap().last_text = Some(Instant::now());
+            if std::mem::take(&mut *self.in_reasoning.lock().unwrap()) {
+                println!("\n");
+            }
+        }
         print!("{}", content);
-        let _ = io::stdout().flush();
+        self.stream.wrote(content);
+    }
+
+    fn print_agent_reasoning(&self, text: &str) {
+        self.end_failures();
+        self.end_progress(text);
+        self.progress.lock().unwrap().last_text = Some(Instant::now());
+        let mut in_reasoning = self.in_reasoning.lock().unwrap();
+        if !*in_reasoning {
+            print!("{}", self.render("💭 "));
+            *in_reasoning = true;
+        }
+        if self.plain {
+            print!("{}", text);
+        } else {
+            // Dim italics, to tell it apart from the response
+            print!("\x1b[2;3m{}\x1b[0m", text);
+        }
+        self.stream.wrote(text);
     }
 
     fn notify_sse_received(&self) {
-        // No-op for console - we don't track SSEs in console mode
+        // Text held back from the last chunks is shown while the stream is quiet
+        self.stream.tick();
+    }
+
+    fn print_stream_progress(&self, progress: &StreamProgress) {
+        // Redrawn lines don't work for screen readers or in logs
+        if self.plain || !io::stdout().is_terminal() {
+            return;
+        }
+        let mut line = self.progress.lock().unwrap();
+        // Text still arriving shows well enough that the response is alive
+        if line.last_text.is_some_and(|at| at.elapsed() < INDICATOR_QUIET) {
+            return;
+        }
+        if line.mid_line {
+            println!();
+            line.mid_line = false;
+            line.line_broken = true;
+        }
+        print!("\r\x1b[2K\x1b[2m{}\x1b[0m", progress);
+        line.shown = true;
+        self.stream.flush();
+    }
+
+    fn clear_stream_progress(&self) {
+        self.end_progress("");
+        self.stream.flush();
     }
 
     fn flush(&self) {
-        let _ = io::stdout().flush();
+        // Called after every streamed chunk, so flushes are batched
+        self.stream.tick();
     }
 }
 
@@ -0,0 +1,211 @@
+//! Display topology: where each screen is in the global coordinate space and how many
+//! pixels it has per point.
+//!
+//! Global coordinates are points with the origin at the top-left of the main display and y
+//! growing downward, the space of CGEvent, `kCGWindowBounds` and accessibility frames.
+//! Screens to the left of or above the main display have negative coordinates. Screenshots
+//! are in pixels of the display they were taken on, 2 per point on a Retina display, so
+//! positions found in them by OCR go through [`DisplayTopology::image_to_screen`] before
+//! anything is clicked.
+
+use crate::types::{Rect, TextLocation};
+use serde::{Deserialize, Serialize};
+
+/// One screen
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Display {
+    pub id: u32,
+    /// Where the screen is, in global points
+    pub bounds: Rect,
+    /// Pixels per point (2.0 on Retina displays)
+    pub scale: f64,
+    pub main: bool,
+}
+
+/// The screens attached, as the window server arranges them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DisplayTopology {
+    displays: Vec<Display>,
+}
+
+impl DisplayTopology {
+    pub fn new(displays: Vec<Display>) -> Self {
+        Self { displays }
+    }
+
+    pub fn displays(&self) -> &[Display] {
+        &self.displays
+    }
+
+    /// The main display, or the first one if none is marked as main
+    pub fn main(&self) -> Option<&Display> {
+        self.displays
+            .iter()
+            .find(|display| display.main)
+            .or(self.displays.first())
+    }
+
+    /// The display containing the global point (x, y)
+    pub fn display_at(&self, x: i32, y: i32) -> Option<&Display> {
+        self.displays.iter().find(|display| {
+            let b = display.bounds;
+            x >= b.x && x < b.x + b.width && y >= b.y && y < b.y + b.height
+        })
+    }
+
+    /// The display showing most of `rect`, which is where a window spanning screens is drawn
+    /// (and screenshotted) at that screen's scale
+    pub fn display_for(&self, rect: Rect) -> Option<&Display> {
+        self.displays
+            .iter()
+            .map(|display| (display, overlap(display.bounds, rect)))
+            .filter(|(_, area)| *area > 0)
+            .max_by_key(|(_, area)| *area)
+            .map(|(display, _)| display)
+            .or_else(|| self.main())
+    }
+
+    /// Map `location`, in pixels of a `image_size` screenshot of a window at `window` (global
+    /// points), to global points.
+    ///
+    /// The scale comes from the display showing the window. When the screenshot isn't the
+    /// window's size at that scale (a window partly off screen, or one moved between screens
+    /// since) the scale measured from the screenshot is used instead.
+    pub fn image_to_screen(
+        &self,
+        location: TextLocation,
+        window: Rect,
+        image_size: (i32, i32),
+    ) -> TextLocation {
+        let (image_width, image_height) = image_size;
+        let measured = (
+            window.width as f64 / image_width.max(1) as f64,
+            window.height as f64 / image_height.max(1) as f64,
+        );
+        let (scale_x, scale_y) = match self.display_for(window) {
+            Some(display) if fits(window, image_size, display.scale) => {
+                (1.0 / display.scale, 1.0 / display.scale)
+            }
+            _ => measured,
+        };
+        let to_points = |value: i32, scale: f64| (value as f64 * scale).round() as i32;
+        TextLocation {
+            x: window.x + to_points(location.x, scale_x),
+            y: window.y + to_points(location.y, scale_y),
+            width: to_points(location.width, scale_x),
+            height: to_points(location.height, scale_y),
+            ..location
+        }
+    }
+}
+
+/// Area of the intersection of two rectangles
+fn overlap(a: Rect, b: Rect) -> i64 {
+    let width = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+    let height = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+    if width <= 0 || height <= 0 {
+        return 0;
+    }
+    width as i64 * height as i64
+}
+
+/// Whether an `image_size` screenshot is the whole of `window` at `scale`, give or take
+/// the rounding of fractional points
+fn fits(window: Rect, image_size: (i32, i32), scale: f64) -> bool {
+    let expected_width = window.width as f64 * scale;
+    let expected_height = window.height as f64 * scale;
+    (image_size.0 as f64 - expected_width).abs() <= scale
+        && (image_size.1 as f64 - expected_height).abs() <= scale
+}
+
+/// The point to click for `location`, in global points: its centre
+pub fn center(location: &TextLocation) -> (i32, i32) {
+    (
+        location.x + location.width / 2,
+        location.y + location.height / 2,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn location(x: i32, y: i32, width: i32, height: i32) -> TextLocation {
+        TextLocation {
+            text: "OK".to_string(),
+            x,
+            y,
+            width,
+            height,
+            confidence: 1.0,
+        }
+    }
+
+    /// A Retina laptop with a standard monitor above it and to the left
+    fn topology() -> DisplayTopology {
+        DisplayTopology::new(vec![
+            Display {
+                id: 1,
+                bounds: rect(0, 0, 1512, 982),
+                scale: 2.0,
+                main: true,
+            },
+            Display {
+                id: 2,
+                bounds: rect(-1920, -1080, 1920, 1080),
+                scale: 1.0,
+                main: false,
+            },
+        ])
+    }
+
+    #[test]
+    fn test_displays_are_found_by_position() {
+        let topology = topology();
+        assert_eq!(topology.main().unwrap().id, 1);
+        assert_eq!(topology.display_at(100, 100).unwrap().id, 1);
+        assert_eq!(topology.display_at(-10, -10).unwrap().id, 2);
+        assert!(topology.display_at(-10, 100).is_none());
+        // Mostly on the external monitor
+        assert_eq!(
+            topology.display_for(rect(-800, -600, 900, 500)).unwrap().id,
+            2
+        );
+    }
+
+    #[test]
+    fn test_image_to_screen_uses_the_scale_of_the_windows_display() {
+        let topology = topology();
+
+        // A Retina screenshot has two pixels per point
+        let window = rect(200, 100, 800, 600);
+        let screen = topology.image_to_screen(location(100, 50, 40, 20), window, (1600, 1200));
+        assert_eq!(
+            (screen.x, screen.y, screen.width, screen.height),
+            (250, 125, 20, 10)
+        );
+        assert_eq!(center(&screen), (260, 130));
+
+        // On the external monitor, above and left of the main display, pixels are points
+        let window = rect(-1500, -900, 800, 600);
+        let screen = topology.image_to_screen(location(100, 50, 40, 20), window, (800, 600));
+        assert_eq!(
+            (screen.x, screen.y, screen.width, screen.height),
+            (-1400, -850, 40, 20)
+        );
+
+        // A screenshot that doesn't match the display's scale is measured instead
+        let window = rect(200, 100, 800, 600);
+        let screen = topology.image_to_screen(location(100, 50, 40, 20), window, (1200, 900));
+        assert_eq!((screen.x, screen.y), (267, 133));
+    }
+}
@@ -2,19 +2,25 @@
 #![allow(unexpected_cfgs)]
 
 pub mod types;
+pub mod display;
 pub mod platform;
 pub mod ocr;
 pub mod webdriver;
 pub mod macax;
+pub mod recording;
 
 // Re-export webdriver types for convenience
 pub use webdriver::{WebDriverController, WebElement, safari::SafariDriver};
 
 // Re-export macax types for convenience
-pub use macax::{MacAxController, AXElement, AXApplication};
+pub use macax::{MacAxController, AXElement, AXApplication, AXNode};
 
 use anyhow::Result;
 use async_trait::async_trait;
+use display::DisplayTopology;
+use recording::Recording;
+use std::path::Path;
+use std::time::Duration;
 use types::*;
 
 #[async_trait]
@@ -22,15 +28,66 @@ pub trait ComputerController: Send + Sync {
     // Screen capture
     async fn take_screenshot(&self, path: &str, region: Option<Rect>, window_id: Option<&str>) -> Result<()>;
     
+    /// Capture `region` of the screen, in global screen points, to a PNG at `path`
+    async fn capture_screen_region(&self, _path: &str, _region: Rect) -> Result<()> {
+        anyhow::bail!("Capturing the screen is not supported on this platform")
+    }
+    
+    /// The screens attached, with their positions and scale factors
+    fn displays(&self) -> Result<DisplayTopology> {
+        anyhow::bail!("Listing displays is not supported on this platform")
+    }
+    
     // OCR operations
     async fn extract_text_from_screen(&self, region: Rect, window_id: &str) -> Result<String>;
     async fn extract_text_from_image(&self, path: &str) -> Result<String>;
     async fn extract_text_with_locations(&self, path: &str) -> Result<Vec<TextLocation>>;
     async fn find_text_in_app(&self, app_name: &str, search_text: &str) -> Result<Option<TextLocation>>;
     
+    /// Map a region of a screenshot of `app_name` (taken with take_screenshot using the app
+    /// as window_id) to global screen points, through the display topology
+    fn screenshot_region_to_screen(&self, _app_name: &str, _screenshot_path: &str, region: TextLocation) -> Result<TextLocation> {
+        Ok(region)
+    }
+    
     // Mouse operations
+    //
+    // Coordinates are global screen points with the origin at the top-left of the main
+    // display and y growing downward, the space of CGEvent and of accessibility element
+    // frames. Text locations from find_text_in_app and screenshot_region_to_screen are in the
+    // same space. See the display module.
     fn move_mouse(&self, x: i32, y: i32) -> Result<()>;
     fn click_at(&self, x: i32, y: i32, app_name: Option<&str>) -> Result<()>;
+    
+    /// Click `button` `clicks` times (2 for a double click) at (x, y)
+    fn mouse_click(&self, _x: i32, _y: i32, _button: MouseButton, _clicks: u32) -> Result<()> {
+        anyhow::bail!("Mouse clicks are not supported on this platform")
+    }
+    
+    /// Scroll by `delta_x`/`delta_y` lines with the pointer at (x, y); positive values
+    /// scroll right/down
+    fn scroll(&self, _x: i32, _y: i32, _delta_x: i32, _delta_y: i32) -> Result<()> {
+        anyhow::bail!("Scrolling is not supported on this platform")
+    }
+    
+    /// Press `button` at `from`, move to `to` while holding it, and release
+    fn drag(&self, _from: (i32, i32), _to: (i32, i32), _button: MouseButton) -> Result<()> {
+        anyhow::bail!("Dragging is not supported on this platform")
+    }
+    
+    /// Bounds of the main window of `app_name`, in top-left origin screen points
+    fn window_bounds(&self, _app_name: &str) -> Result<Rect> {
+        anyhow::bail!("Finding windows is not supported on this platform")
+    }
+    
+    // Screen recording
+    
+    /// Start recording the screen, or only the window of `app_name`, to a video at `path`.
+    /// The recording stops by itself after `max_duration`.
+    fn start_recording(&self, path: &Path, app_name: Option<&str>, max_duration: Duration) -> Result<Recording> {
+        let region = app_name.map(|app| self.window_bounds(app)).transpose()?;
+        Recording::start(path, region, max_duration)
+    }
 }
 
 // Platform-specific constructor
@@ -1,4 +1,4 @@
-use super::{AXApplication, AXElement};
+use super::{AXApplication, AXElement, AXNode};
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 
@@ -11,6 +11,31 @@ use core_foundation::base::TCFType;
 #[cfg(target_os = "macos")]
 use core_foundation::string::CFString;
 
+/// Most elements read from an application for `dump_elements`
+#[cfg(target_os = "macos")]
+const MAX_DUMP_ELEMENTS: usize = 5000;
+
+#[cfg(target_os = "macos")]
+const AX_VALUE_TYPE_CG_POINT: u32 = 1;
+
+#[cfg(target_os = "macos")]
+const AX_VALUE_TYPE_CG_SIZE: u32 = 2;
+
+#[cfg(target_os = "macos")]
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXUIElementCopyAttributeValue(
+        element: core_foundation::base::CFTypeRef,
+        attribute: core_foundation::string::CFStringRef,
+        value: *mut core_foundation::base::CFTypeRef,
+    ) -> i32;
+    fn AXValueGetValue(
+        value: core_foundation::base::CFTypeRef,
+        value_type: u32,
+        value_ptr: *mut std::ffi::c_void,
+    ) -> u8;
+}
+
 /// macOS Accessibility API controller using native APIs
 pub struct MacAxController {
     // Cache for application elements
@@ -249,6 +274,94 @@ impl MacAxController {
         anyhow::bail!("Not supported on this platform")
     }
     
+    /// Get the accessibility tree of an application with each element's role, title, value,
+    /// identifier and frame, pruned of hidden elements and unlabelled containers
+    #[cfg(target_os = "macos")]
+    pub fn dump_elements(&self, app_name: &str, max_depth: usize) -> Result<Vec<AXNode>> {
+        let app_element = self.get_app_element(app_name)?;
+        let mut budget = MAX_DUMP_ELEMENTS;
+        let tree = Self::build_node(&app_element, 0, max_depth, &mut budget);
+        Ok(tree.prune())
+    }
+    
+    #[cfg(not(target_os = "macos"))]
+    pub fn dump_elements(&self, _app_name: &str, _max_depth: usize) -> Result<Vec<AXNode>> {
+        anyhow::bail!("Not supported on this platform")
+    }
+    
+    /// Read `element` and its descendants, visiting at most `budget` elements
+    #[cfg(target_os = "macos")]
+    fn build_node(element: &AXUIElement, depth: usize, max_depth: usize, budget: &mut usize) -> AXNode {
+        use core_foundation::number::CFNumber;
+        
+        *budget = budget.saturating_sub(1);
+        let value = element.value().ok().and_then(|v| {
+            v.downcast::<CFString>()
+                .map(|s| s.to_string())
+                .or_else(|| v.downcast::<CFNumber>().and_then(|n| n.to_f64()).map(|n| n.to_string()))
+        });
+        
+        let mut children = Vec::new();
+        if depth + 1 < max_depth {
+            if let Ok(elements) = element.children() {
+                for i in 0..elements.len() {
+                    if *budget == 0 {
+                        break;
+                    }
+                    if let Some(child) = elements.get(i) {
+                        children.push(Self::build_node(&child, depth + 1, max_depth, budget));
+                    }
+                }
+            }
+        }
+        
+        AXNode {
+            role: element.role().ok().map(|s| s.to_string())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            title: element.title().ok().map(|s| s.to_string()),
+            value,
+            identifier: element.identifier().ok().map(|s| s.to_string()),
+            frame: Self::element_frame(element),
+            children,
+        }
+    }
+    
+    /// Screen position and size of an element, from its AXPosition and AXSize values
+    #[cfg(target_os = "macos")]
+    fn element_frame(element: &AXUIElement) -> Option<(f64, f64, f64, f64)> {
+        use core_graphics::geometry::{CGPoint, CGSize};
+        
+        let position: CGPoint = Self::ax_value(element, "AXPosition", AX_VALUE_TYPE_CG_POINT)?;
+        let size: CGSize = Self::ax_value(element, "AXSize", AX_VALUE_TYPE_CG_SIZE)?;
+        Some((position.x, position.y, size.width, size.height))
+    }
+    
+    /// Read an attribute holding an AXValue of `value_type` into a `T` of the matching layout
+    #[cfg(target_os = "macos")]
+    fn ax_value<T: Copy>(element: &AXUIElement, attribute: &str, value_type: u32) -> Option<T> {
+        use core_foundation::base::{CFType, CFTypeRef};
+        
+        let attribute = CFString::new(attribute);
+        let mut value: CFTypeRef = std::ptr::null();
+        let status = unsafe {
+            AXUIElementCopyAttributeValue(
+                element.as_concrete_TypeRef() as CFTypeRef,
+                attribute.as_concrete_TypeRef(),
+                &mut value,
+            )
+        };
+        if status != 0 || value.is_null() {
+            return None;
+        }
+        // Released when dropped
+        let value = unsafe { CFType::wrap_under_create_rule(value) };
+        let mut out = std::mem::MaybeUninit::<T>::uninit();
+        let ok = unsafe {
+            AXValueGetValue(value.as_CFTypeRef(), value_type, out.as_mut_ptr() as *mut std::ffi::c_void)
+        };
+        (ok != 0).then(|| unsafe { out.assume_init() })
+    }
+    
     #[cfg(target_os = "macos")]
     fn build_ui_tree(
         element: &AXUIElement,
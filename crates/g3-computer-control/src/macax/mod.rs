@@ -63,3 +63,100 @@ impl AXElement {
         parts.join(", ")
     }
 }
+
+/// Roles that only group other elements. Unlabelled ones are pruned from element dumps.
+const CONTAINER_ROLES: &[&str] = &[
+    "AXGroup",
+    "AXScrollArea",
+    "AXSplitGroup",
+    "AXLayoutArea",
+    "AXLayoutItem",
+    "AXUnknown",
+];
+
+/// Longest value shown for an element in a dump; text areas can hold whole documents
+const MAX_DUMP_VALUE_CHARS: usize = 80;
+
+/// An element of an application's accessibility tree, with its children
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AXNode {
+    pub role: String,
+    pub title: Option<String>,
+    pub value: Option<String>,
+    pub identifier: Option<String>,
+    /// x, y, width, height in screen points
+    pub frame: Option<(f64, f64, f64, f64)>,
+    pub children: Vec<AXNode>,
+}
+
+impl AXNode {
+    fn is_labelled(&self) -> bool {
+        [&self.title, &self.value, &self.identifier]
+            .iter()
+            .any(|s| s.as_deref().is_some_and(|s| !s.is_empty()))
+    }
+
+    /// Drop what the agent can't act on: elements with an empty frame (hidden or offscreen),
+    /// and unlabelled containers, whose children take their place
+    pub fn prune(mut self) -> Vec<AXNode> {
+        let hidden = matches!(self.frame, Some((_, _, w, h)) if w <= 0.0 || h <= 0.0);
+        let container = CONTAINER_ROLES.contains(&self.role.as_str()) && !self.is_labelled();
+
+        let children: Vec<AXNode> = std::mem::take(&mut self.children)
+            .into_iter()
+            .flat_map(AXNode::prune)
+            .collect();
+        if container || (hidden && children.is_empty()) {
+            return children;
+        }
+        self.children = children;
+        vec![self]
+    }
+
+    /// Number of elements in the tree
+    pub fn count(&self) -> usize {
+        1 + self.children.iter().map(AXNode::count).sum::<usize>()
+    }
+
+    /// One line per element, indented by depth, stopping after `max_elements` elements
+    pub fn format(&self, max_elements: usize) -> String {
+        let mut lines = Vec::new();
+        self.format_into(0, max_elements, &mut lines);
+        let total = self.count();
+        let mut output = lines.join("\n");
+        if total > lines.len() {
+            output.push_str(&format!(
+                "\n... {} more elements not shown",
+                total - lines.len()
+            ));
+        }
+        output
+    }
+
+    fn format_into(&self, depth: usize, max_elements: usize, lines: &mut Vec<String>) {
+        if lines.len() >= max_elements {
+            return;
+        }
+        let mut line = format!("{}{}", "  ".repeat(depth), self.role);
+        if let Some(title) = self.title.as_deref().filter(|s| !s.is_empty()) {
+            line.push_str(&format!(" \"{}\"", title));
+        }
+        if let Some(id) = self.identifier.as_deref().filter(|s| !s.is_empty()) {
+            line.push_str(&format!(" id={}", id));
+        }
+        if let Some(value) = self.value.as_deref().filter(|s| !s.is_empty()) {
+            let mut shown: String = value.chars().take(MAX_DUMP_VALUE_CHARS).collect();
+            if shown.len() < value.len() {
+                shown.push('…');
+            }
+            line.push_str(&format!(" value={:?}", shown));
+        }
+        if let Some((x, y, w, h)) = self.frame {
+            line.push_str(&format!(" @({:.0},{:.0} {:.0}x{:.0})", x, y, w, h));
+        }
+        lines.push(line);
+        for child in &self.children {
+            child.format_into(depth + 1, max_elements, lines);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use crate::{AXElement, MacAxController};
+    use crate::{AXElement, AXNode, MacAxController};
 
     #[test]
     fn test_ax_element_to_string() {
@@ -34,4 +34,44 @@ mod tests {
         let result = MacAxController::new();
         assert!(result.is_ok());
     }
+
+    fn node(
+        role: &str,
+        title: Option<&str>,
+        frame: (f64, f64, f64, f64),
+        children: Vec<AXNode>,
+    ) -> AXNode {
+        AXNode {
+            role: role.to_string(),
+            title: title.map(|s| s.to_string()),
+            value: None,
+            identifier: None,
+            frame: Some(frame),
+            children,
+        }
+    }
+
+    #[test]
+    fn test_ax_node_prune_and_format() {
+        let save = node("AXButton", Some("Save"), (10.0, 20.0, 80.0, 30.0), vec![]);
+        let hidden = node("AXButton", Some("Hidden"), (0.0, 0.0, 0.0, 0.0), vec![]);
+        let mut field = node("AXTextField", None, (10.0, 60.0, 200.0, 24.0), vec![]);
+        field.identifier = Some("name-field".to_string());
+        field.value = Some("x".repeat(100));
+        let group = node("AXGroup", None, (0.0, 0.0, 300.0, 100.0), vec![save, hidden, field]);
+        let window = node("AXWindow", Some("Untitled"), (0.0, 0.0, 300.0, 100.0), vec![group]);
+
+        let pruned = window.prune();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].count(), 3);
+
+        let dump = pruned[0].format(10);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "AXWindow \"Untitled\" @(0,0 300x100)");
+        assert_eq!(lines[1], "  AXButton \"Save\" @(10,20 80x30)");
+        assert!(lines[2].starts_with("  AXTextField id=name-field value=\"xxx"));
+        assert!(lines[2].contains("…\" @(10,60 200x24)"));
+
+        assert!(pruned[0].format(2).ends_with("... 1 more elements not shown"));
+    }
 }
@@ -16,6 +16,7 @@ pub trait OCREngine: Send + Sync {
 #[cfg(target_os = "macos")]
 pub mod vision;
 
+pub mod prepare;
 pub mod tesseract;
 
 // Re-export the default OCR engine for the platform
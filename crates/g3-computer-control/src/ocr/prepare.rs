@@ -0,0 +1,187 @@
+//! Cropping and downscaling images before OCR.
+//!
+//! OCR time grows with the number of pixels, and a full retina screenshot has several times
+//! more than its text needs. An image is cropped to the region asked for and scaled down to
+//! a maximum size into a temporary PNG, and the positions of text found in it are mapped
+//! back to the original image.
+
+use crate::types::{Rect, TextLocation};
+use anyhow::{bail, Context, Result};
+use image::imageops::FilterType;
+use std::path::{Path, PathBuf};
+
+/// An image ready for OCR: the original, or a cropped and scaled copy of it
+#[derive(Debug)]
+pub struct PreparedImage {
+    path: PathBuf,
+    temporary: bool,
+    /// Pixels of the prepared image per pixel of the original
+    scale: f64,
+    /// Top left corner of the prepared image in the original
+    offset: (i32, i32),
+}
+
+impl PreparedImage {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the image was cropped or scaled
+    pub fn is_changed(&self) -> bool {
+        self.temporary
+    }
+
+    /// `location` in the prepared image as a location in the original
+    pub fn to_original(&self, location: TextLocation) -> TextLocation {
+        let unscale = |value: i32| (value as f64 / self.scale).round() as i32;
+        TextLocation {
+            x: unscale(location.x) + self.offset.0,
+            y: unscale(location.y) + self.offset.1,
+            width: unscale(location.width),
+            height: unscale(location.height),
+            ..location
+        }
+    }
+}
+
+impl Drop for PreparedImage {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Crop the image at `path` to `region` and scale it down so its longer side is at most
+/// `max_dimension` pixels. An image that needs neither is used as it is, without decoding.
+pub fn prepare(
+    path: &Path,
+    region: Option<Rect>,
+    max_dimension: Option<u32>,
+) -> Result<PreparedImage> {
+    let (width, height) = image::image_dimensions(path)
+        .with_context(|| format!("Failed to read image {}", path.display()))?;
+    let region = match region {
+        Some(region) => Some(clamp(region, width, height)?),
+        None => None,
+    };
+    let (cropped_width, cropped_height) = region
+        .map(|r| (r.width as u32, r.height as u32))
+        .unwrap_or((width, height));
+    let scale = match max_dimension {
+        Some(max) if cropped_width.max(cropped_height) > max => {
+            max as f64 / cropped_width.max(cropped_height) as f64
+        }
+        _ => 1.0,
+    };
+    if region.is_none() && scale == 1.0 {
+        return Ok(PreparedImage {
+            path: path.to_path_buf(),
+            temporary: false,
+            scale,
+            offset: (0, 0),
+        });
+    }
+
+    let mut image =
+        image::open(path).with_context(|| format!("Failed to read image {}", path.display()))?;
+    if let Some(r) = region {
+        image = image.crop_imm(r.x as u32, r.y as u32, r.width as u32, r.height as u32);
+    }
+    if scale < 1.0 {
+        let target_width = ((cropped_width as f64 * scale).round() as u32).max(1);
+        let target_height = ((cropped_height as f64 * scale).round() as u32).max(1);
+        image = image.resize_exact(target_width, target_height, FilterType::Triangle);
+    }
+    let prepared = std::env::temp_dir().join(format!("g3_ocr_{}.png", uuid::Uuid::new_v4()));
+    image
+        .save(&prepared)
+        .with_context(|| format!("Failed to write {}", prepared.display()))?;
+    Ok(PreparedImage {
+        path: prepared,
+        temporary: true,
+        scale,
+        offset: region.map(|r| (r.x, r.y)).unwrap_or((0, 0)),
+    })
+}
+
+/// `region` cut to the image, or an error when nothing of it is inside
+fn clamp(region: Rect, width: u32, height: u32) -> Result<Rect> {
+    let x = region.x.max(0);
+    let y = region.y.max(0);
+    let right = (region.x.saturating_add(region.width)).min(width as i32);
+    let bottom = (region.y.saturating_add(region.height)).min(height as i32);
+    if right <= x || bottom <= y {
+        bail!(
+            "The region {}x{} at ({}, {}) is outside the {}x{} image",
+            region.width,
+            region.height,
+            region.x,
+            region.y,
+            width,
+            height
+        );
+    }
+    Ok(Rect {
+        x,
+        y,
+        width: right - x,
+        height: bottom - y,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_image(width: u32, height: u32) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("g3_ocr_test_{}.png", uuid::Uuid::new_v4()));
+        image::RgbImage::new(width, height).save(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_prepare_crops_and_scales() {
+        let path = write_image(400, 200);
+
+        let unchanged = prepare(&path, None, Some(1000)).unwrap();
+        assert!(!unchanged.is_changed());
+        assert_eq!(unchanged.path(), path);
+
+        let region = Rect {
+            x: 100,
+            y: 50,
+            width: 300,
+            height: 500,
+        };
+        let prepared = prepare(&path, Some(region), Some(75)).unwrap();
+        assert!(prepared.is_changed());
+        // Cropped to the 300x150 inside the image, then scaled to a quarter
+        assert_eq!(image::image_dimensions(prepared.path()).unwrap(), (75, 38));
+        let location = prepared.to_original(TextLocation {
+            text: "OK".to_string(),
+            x: 10,
+            y: 5,
+            width: 20,
+            height: 4,
+            confidence: 0.9,
+        });
+        assert_eq!(
+            (location.x, location.y, location.width, location.height),
+            (140, 70, 80, 16)
+        );
+
+        let temporary = prepared.path().to_path_buf();
+        drop(prepared);
+        assert!(!temporary.exists());
+
+        let outside = Rect {
+            x: 500,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        assert!(prepare(&path, Some(outside), None).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
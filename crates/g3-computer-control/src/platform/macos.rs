@@ -1,4 +1,5 @@
-use crate::{ComputerController, types::{Rect, TextLocation}};
+use crate::{ComputerController, types::{MouseButton, Rect, TextLocation}};
+use crate::display::{Display, DisplayTopology};
 use crate::ocr::{OCREngine, DefaultOCR};
 use anyhow::{Result, Context};
 use async_trait::async_trait;
@@ -156,6 +157,7 @@ impl ComputerController for MacOSController {
         // TODO: Implement direct CGWindowListCreateImage approach with proper image saving
         let mut cmd = std::process::Command::new("screencapture");
         cmd.arg("-x"); // No sound
+        cmd.arg("-o"); // No window shadow, so the image is exactly the window's bounds
         cmd.arg("-l");
         cmd.arg(cg_window_id.to_string());
         
@@ -176,6 +178,50 @@ impl ComputerController for MacOSController {
         Ok(())
     }
     
+    async fn capture_screen_region(&self, path: &str, region: Rect) -> Result<()> {
+        let output = std::process::Command::new("screencapture")
+            .arg("-x")
+            .arg(format!("-R{},{},{},{}", region.x, region.y, region.width, region.height))
+            .arg(path)
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!("screencapture failed: {}", stderr));
+        }
+        Ok(())
+    }
+    
+    fn displays(&self) -> Result<DisplayTopology> {
+        use core_graphics::display::CGDisplay;
+        
+        let ids = CGDisplay::active_displays()
+            .map_err(|e| anyhow::anyhow!("Failed to list displays (CGError {})", e))?;
+        let displays = ids
+            .into_iter()
+            .map(|id| {
+                let display = CGDisplay::new(id);
+                let bounds = display.bounds();
+                // pixels_wide() reports points on Retina displays; the mode has the real pixels
+                let scale = display
+                    .display_mode()
+                    .map(|mode| mode.pixel_width() as f64 / mode.width().max(1) as f64)
+                    .unwrap_or(1.0);
+                Display {
+                    id,
+                    bounds: Rect {
+                        x: bounds.origin.x as i32,
+                        y: bounds.origin.y as i32,
+                        width: bounds.size.width as i32,
+                        height: bounds.size.height as i32,
+                    },
+                    scale,
+                    main: display.is_main(),
+                }
+            })
+            .collect();
+        Ok(DisplayTopology::new(displays))
+    }
+    
     async fn extract_text_from_screen(&self, region: Rect, window_id: &str) -> Result<String> {
         // Take screenshot of region first
         let temp_path = format!("/tmp/g3_ocr_{}.png", uuid::Uuid::new_v4());
@@ -213,8 +259,9 @@ impl ComputerController for MacOSController {
         // Extract all text with locations
         let locations = self.extract_text_with_locations(&temp_path).await?;
         
-        // Get window bounds to calculate coordinate transformation
-        let window_bounds = self.get_window_bounds(app_name)?;
+        // Get window bounds and the screens to calculate coordinate transformation
+        let window_bounds = self.window_bounds(app_name)?;
+        let topology = self.displays()?;
         
         // Clean up temp file
         let _ = std::fs::remove_file(&temp_path);
@@ -223,19 +270,20 @@ impl ComputerController for MacOSController {
         let search_lower = search_text.to_lowercase();
         for location in locations {
             if location.text.to_lowercase().contains(&search_lower) {
-                // Transform coordinates from screenshot space to screen space
-                let transformed = transform_screenshot_to_screen_coords(
-                    location,
-                    window_bounds,
-                    screenshot_dims,
-                );
-                return Ok(Some(transformed));
+                // Transform coordinates from screenshot pixels to screen points
+                return Ok(Some(topology.image_to_screen(location, window_bounds, screenshot_dims)));
             }
         }
         
         Ok(None)
     }
     
+    fn screenshot_region_to_screen(&self, app_name: &str, screenshot_path: &str, region: TextLocation) -> Result<TextLocation> {
+        let screenshot_dims = get_image_dimensions(screenshot_path)?;
+        let window_bounds = self.window_bounds(app_name)?;
+        Ok(self.displays()?.image_to_screen(region, window_bounds, screenshot_dims))
+    }
+    
     fn move_mouse(&self, x: i32, y: i32) -> Result<()> {
         use core_graphics::event::{
             CGEvent, CGEventTapLocation, CGEventType, CGMouseButton,
@@ -268,23 +316,10 @@ impl ComputerController for MacOSController {
             CGEventSource, CGEventSourceStateID,
         };
         use core_graphics::geometry::CGPoint;
-        use core_graphics::display::CGDisplay;
-        
-        // IMPORTANT: Coordinates passed here are in NSScreen/CGWindowListCopyWindowInfo space
-        // (Y=0 at BOTTOM, increases UPWARD)
-        // But CGEvent uses a different coordinate system (Y=0 at TOP, increases DOWNWARD)
-        // We need to convert: CGEvent.y = screenHeight - NSScreen.y
-        
-        let screen_height = CGDisplay::main().pixels_high() as i32;
-        let cgevent_x = x;
-        let cgevent_y = screen_height - y;
         
-        tracing::debug!("click_at: NSScreen coords ({}, {}) -> CGEvent coords ({}, {}) [screen_height={}]",
-            x, y, cgevent_x, cgevent_y, screen_height);
-        
-        let (global_x, global_y) = (cgevent_x, cgevent_y);
-        
-        let point = CGPoint::new(global_x as f64, global_y as f64);
+        // Global points with a top-left origin, which is what CGEvent takes on every display
+        tracing::debug!("click_at: ({}, {})", x, y);
+        let point = CGPoint::new(x as f64, y as f64);
         
         let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
             .ok().context("Failed to create event source")?;
@@ -322,9 +357,123 @@ impl ComputerController for MacOSController {
         
         Ok(())
     }
+    
+    fn window_bounds(&self, app_name: &str) -> Result<Rect> {
+        let (x, y, width, height) = self.get_window_bounds(app_name)?;
+        Ok(Rect { x, y, width, height })
+    }
+    
+    fn mouse_click(&self, x: i32, y: i32, button: MouseButton, clicks: u32) -> Result<()> {
+        use core_graphics::event::CGEventType;
+        use core_graphics::geometry::CGPoint;
+        
+        let point = CGPoint::new(x as f64, y as f64);
+        let (down, up, _) = Self::button_event_types(button);
+        Self::post_mouse_event(CGEventType::MouseMoved, point, button, 0)?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        
+        // Each click of a double click carries its position in the sequence
+        for click in 1..=clicks.max(1) {
+            Self::post_mouse_event(down, point, button, click)?;
+            std::thread::sleep(std::time::Duration::from_millis(30));
+            Self::post_mouse_event(up, point, button, click)?;
+            std::thread::sleep(std::time::Duration::from_millis(30));
+        }
+        Ok(())
+    }
+    
+    fn scroll(&self, x: i32, y: i32, delta_x: i32, delta_y: i32) -> Result<()> {
+        use core_graphics::event::{CGEvent, CGEventTapLocation, CGEventType, ScrollEventUnit};
+        use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+        use core_graphics::geometry::CGPoint;
+        
+        // Scroll events go to whatever is under the pointer
+        Self::post_mouse_event(CGEventType::MouseMoved, CGPoint::new(x as f64, y as f64), MouseButton::Left, 0)?;
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        
+        let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+            .ok().context("Failed to create event source")?;
+        // Wheel deltas are positive for up/left
+        let event = CGEvent::new_scroll_event(source, ScrollEventUnit::LINE, 2, -delta_y, -delta_x, 0)
+            .ok().context("Failed to create scroll event")?;
+        event.post(CGEventTapLocation::HID);
+        Ok(())
+    }
+    
+    fn drag(&self, from: (i32, i32), to: (i32, i32), button: MouseButton) -> Result<()> {
+        use core_graphics::event::CGEventType;
+        use core_graphics::geometry::CGPoint;
+        
+        const STEPS: i32 = 20;
+        let (down, up, dragged) = Self::button_event_types(button);
+        let start = CGPoint::new(from.0 as f64, from.1 as f64);
+        let end = CGPoint::new(to.0 as f64, to.1 as f64);
+        
+        Self::post_mouse_event(CGEventType::MouseMoved, start, button, 0)?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        Self::post_mouse_event(down, start, button, 1)?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        
+        // Apps only see a drag if the pointer moves through intermediate points
+        for step in 1..=STEPS {
+            let t = step as f64 / STEPS as f64;
+            let point = CGPoint::new(
+                start.x + (end.x - start.x) * t,
+                start.y + (end.y - start.y) * t,
+            );
+            Self::post_mouse_event(dragged, point, button, 1)?;
+            std::thread::sleep(std::time::Duration::from_millis(15));
+        }
+        
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        Self::post_mouse_event(up, end, button, 1)?;
+        Ok(())
+    }
 }
 
 impl MacOSController {
+    /// The (down, up, dragged) event types for `button`
+    fn button_event_types(button: MouseButton) -> (
+        core_graphics::event::CGEventType,
+        core_graphics::event::CGEventType,
+        core_graphics::event::CGEventType,
+    ) {
+        use core_graphics::event::CGEventType;
+        
+        match button {
+            MouseButton::Left => (CGEventType::LeftMouseDown, CGEventType::LeftMouseUp, CGEventType::LeftMouseDragged),
+            MouseButton::Right => (CGEventType::RightMouseDown, CGEventType::RightMouseUp, CGEventType::RightMouseDragged),
+            MouseButton::Middle => (CGEventType::OtherMouseDown, CGEventType::OtherMouseUp, CGEventType::OtherMouseDragged),
+        }
+    }
+    
+    /// Post a mouse event at `point` (top-left origin screen points). `click_state` is the
+    /// click count for button events, 0 for moves.
+    fn post_mouse_event(
+        event_type: core_graphics::event::CGEventType,
+        point: core_graphics::geometry::CGPoint,
+        button: MouseButton,
+        click_state: u32,
+    ) -> Result<()> {
+        use core_graphics::event::{CGEvent, CGEventTapLocation, CGMouseButton, EventField};
+        use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+        
+        let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+            .ok().context("Failed to create event source")?;
+        let cg_button = match button {
+            MouseButton::Left => CGMouseButton::Left,
+            MouseButton::Right => CGMouseButton::Right,
+            MouseButton::Middle => CGMouseButton::Center,
+        };
+        let event = CGEvent::new_mouse_event(source, event_type, point, cg_button)
+            .ok().context("Failed to create mouse event")?;
+        if click_state > 0 {
+            event.set_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE, click_state as i64);
+        }
+        event.post(CGEventTapLocation::HID);
+        Ok(())
+    }
+    
     /// Get window bounds for an application (helper method)
     fn get_window_bounds(&self, app_name: &str) -> Result<(i32, i32, i32, i32)> {
         unsafe {
@@ -443,65 +592,6 @@ fn get_image_dimensions(path: &str) -> Result<(i32, i32)> {
     Ok((width, height))
 }
 
-/// Transform coordinates from screenshot space to screen space
-/// 
-/// The screenshot is taken of a window, and Vision OCR returns coordinates
-/// relative to the screenshot image. We need to transform these to actual
-/// screen coordinates for clicking.
-/// 
-/// On Retina displays, screenshots are taken at 2x resolution, so we need
-/// to account for this scaling factor.
-fn transform_screenshot_to_screen_coords(
-    location: TextLocation,
-    window_bounds: (i32, i32, i32, i32), // (x, y, width, height) in screen space
-    screenshot_dims: (i32, i32), // (width, height) in pixels
-) -> TextLocation {
-    let (win_x, win_y, win_width, win_height) = window_bounds;
-    let (screenshot_width, screenshot_height) = screenshot_dims;
-    
-    // Calculate scale factors
-    // On Retina displays, screenshot is typically 2x the window size
-    let scale_x = win_width as f64 / screenshot_width as f64;
-    let scale_y = win_height as f64 / screenshot_height as f64;
-    
-    tracing::debug!("Transform: screenshot={}x{}, window={}x{} at ({},{}), scale=({:.2},{:.2})",
-        screenshot_width, screenshot_height, win_width, win_height, win_x, win_y, scale_x, scale_y);
-    
-    // Transform coordinates from image space to screen space
-    // IMPORTANT: macOS screen coordinates have origin at BOTTOM-LEFT (Y increases upward)
-    // Image coordinates have origin at TOP-LEFT (Y increases downward)
-    // win_y is the BOTTOM of the window in screen coordinates
-    // So we need to: (win_y + win_height) to get window TOP, then subtract screenshot_y
-    let window_top_y = win_y + win_height;
-    
-    tracing::debug!("[transform] Input location in image space: x={}, y={}, width={}, height={}",
-        location.x, location.y, location.width, location.height);
-    tracing::debug!("[transform] Scale factors: scale_x={:.4}, scale_y={:.4}", scale_x, scale_y);
-    
-    let transformed_x = win_x + (location.x as f64 * scale_x) as i32;
-    let transformed_y = window_top_y - (location.y as f64 * scale_y) as i32;
-    let transformed_width = (location.width as f64 * scale_x) as i32;
-    let transformed_height = (location.height as f64 * scale_y) as i32;
-    
-    tracing::debug!("[transform] Calculation details:");
-    tracing::debug!("  - transformed_x = {} + ({} * {:.4}) = {} + {:.2} = {}", win_x, location.x, scale_x, win_x, location.x as f64 * scale_x, transformed_x);
-    tracing::debug!("  - transformed_width = ({} * {:.4}) = {:.2} -> {}", location.width, scale_x, location.width as f64 * scale_x, transformed_width);
-    tracing::debug!("  - transformed_height = ({} * {:.4}) = {:.2} -> {}", location.height, scale_y, location.height as f64 * scale_y, transformed_height);
-    
-    tracing::debug!("Transformed location: screenshot=({},{}) {}x{} -> screen=({},{}) {}x{}",
-        location.x, location.y, location.width, location.height,
-        transformed_x, transformed_y, transformed_width, transformed_height);
-    
-    TextLocation {
-        text: location.text,
-        x: transformed_x,
-        y: transformed_y,
-        width: transformed_width,
-        height: transformed_height,
-        confidence: location.confidence,
-    }
-}
-
 #[path = "macos_window_matching_test.rs"]
 #[cfg(test)]
 mod tests;
\ No newline at end of file
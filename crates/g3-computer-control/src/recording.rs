@@ -0,0 +1,177 @@
+//! Screen recordings of UI automation, so failed runs can be reviewed afterwards.
+//!
+//! A recording is an external capture process (`screencapture -v` on macOS, `ffmpeg` with
+//! x11grab on Linux) writing a video file. It is stopped with SIGINT, which both tools treat
+//! as "finish the file", and is killed if the handle is dropped without being stopped.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+
+use crate::types::Rect;
+
+/// How long a capture process gets to finish writing the video after being stopped
+const STOP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A screen recording in progress
+#[derive(Debug)]
+pub struct Recording {
+    child: Child,
+    path: PathBuf,
+    started: Instant,
+}
+
+/// A finished recording
+#[derive(Debug, Clone)]
+pub struct RecordingInfo {
+    pub path: PathBuf,
+    pub duration: Duration,
+    pub size: u64,
+}
+
+impl Recording {
+    /// Start recording the screen, or `region` of it, to `path` for at most `max_duration`
+    pub fn start(path: &Path, region: Option<Rect>, max_duration: Duration) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut command = capture_command(path, region, max_duration)?;
+        let child = command
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start screen recording")?;
+        Ok(Self {
+            child,
+            path: path.to_path_buf(),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Stop recording and wait for the video to be written
+    pub async fn stop(mut self) -> Result<RecordingInfo> {
+        let duration = self.started.elapsed();
+        if self.child.try_wait()?.is_none() {
+            if let Some(pid) = self.child.id() {
+                Command::new("kill")
+                    .args(["-INT", &pid.to_string()])
+                    .status()
+                    .await
+                    .context("Failed to signal the recording process")?;
+            }
+            if tokio::time::timeout(STOP_TIMEOUT, self.child.wait())
+                .await
+                .is_err()
+            {
+                self.child.kill().await?;
+                anyhow::bail!(
+                    "Recording did not finish within {}s and was killed; {} may be incomplete",
+                    STOP_TIMEOUT.as_secs(),
+                    self.path.display()
+                );
+            }
+        }
+
+        let size = std::fs::metadata(&self.path)
+            .with_context(|| format!("Recording was not written to {}", self.path.display()))?
+            .len();
+        Ok(RecordingInfo {
+            path: self.path,
+            duration,
+            size,
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn capture_command(path: &Path, region: Option<Rect>, max_duration: Duration) -> Result<Command> {
+    let mut command = Command::new("screencapture");
+    // Video, no sound, stop after max_duration at the latest
+    command
+        .arg("-x")
+        .arg("-V")
+        .arg(max_duration.as_secs().max(1).to_string());
+    if let Some(region) = region {
+        command.arg(format!(
+            "-R{},{},{},{}",
+            region.x, region.y, region.width, region.height
+        ));
+    }
+    command.arg(path);
+    Ok(command)
+}
+
+#[cfg(target_os = "linux")]
+fn capture_command(path: &Path, region: Option<Rect>, max_duration: Duration) -> Result<Command> {
+    let display =
+        std::env::var("DISPLAY").context("DISPLAY is not set; screen recording needs X11")?;
+    let mut command = Command::new("ffmpeg");
+    command.args([
+        "-loglevel",
+        "error",
+        "-y",
+        "-f",
+        "x11grab",
+        "-framerate",
+        "15",
+    ]);
+    let input = match region {
+        Some(region) => {
+            command.args([
+                "-video_size",
+                &format!("{}x{}", region.width, region.height),
+            ]);
+            format!("{}+{},{}", display, region.x, region.y)
+        }
+        None => display,
+    };
+    command
+        .args([
+            "-i",
+            &input,
+            "-t",
+            &max_duration.as_secs().max(1).to_string(),
+        ])
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(path);
+    Ok(command)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn capture_command(
+    _path: &Path,
+    _region: Option<Rect>,
+    _max_duration: Duration,
+) -> Result<Command> {
+    anyhow::bail!("Screen recording is not supported on this platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stop_without_output_fails() {
+        // A process that exits without writing the video
+        let path = std::env::temp_dir().join(format!("g3-recording-{}.mov", uuid::Uuid::new_v4()));
+        let recording = Recording {
+            child: Command::new("true").spawn().unwrap(),
+            path: path.clone(),
+            started: Instant::now(),
+        };
+        assert_eq!(recording.path(), path);
+        let error = recording.stop().await.unwrap_err().to_string();
+        assert!(error.contains("Recording was not written"));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod safari;
 
+pub use safari::{Tab, WaitOutcome};
+
 use anyhow::Result;
 use async_trait::async_trait;
 use serde_json::Value;
@@ -5,6 +5,42 @@ use fantoccini::{Client, ClientBuilder};
 use serde_json::Value;
 use std::time::Duration;
 
+/// A tab or window of a WebDriver session
+#[derive(Debug, Clone)]
+pub struct Tab {
+    pub handle: String,
+    pub title: String,
+    pub url: String,
+    /// Whether commands currently go to this tab
+    pub current: bool,
+}
+
+/// How often a wait checks the page
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Chars of element text kept in a [`WaitOutcome`]
+const WAIT_STATE_CHARS: usize = 300;
+
+/// How a wait for a condition on the page ended
+#[derive(Debug, Clone)]
+pub struct WaitOutcome {
+    /// Whether the condition was met before the timeout
+    pub met: bool,
+    pub elapsed: Duration,
+    /// What the page showed when the wait ended: the matching element's text, or why the
+    /// condition wasn't met
+    pub state: String,
+}
+
+fn excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > WAIT_STATE_CHARS {
+        format!("{}...", text.chars().take(WAIT_STATE_CHARS).collect::<String>())
+    } else {
+        text
+    }
+}
+
 /// SafariDriver WebDriver controller
 pub struct SafariDriver {
     client: Client,
@@ -91,6 +127,27 @@ impl SafariDriver {
         Ok(response.handle.into())
     }
     
+    /// List the session's tabs and windows in the order the browser reports them. Each is
+    /// switched to in turn to read its title and URL, then the current one is switched
+    /// back to.
+    pub async fn tabs(&mut self) -> Result<Vec<Tab>> {
+        let current = self.current_window_handle().await.ok();
+        let mut tabs = Vec::new();
+        for handle in self.window_handles().await? {
+            self.switch_to_window(&handle).await?;
+            tabs.push(Tab {
+                title: self.title().await.unwrap_or_default(),
+                url: self.current_url().await.unwrap_or_default(),
+                current: current.as_deref() == Some(handle.as_str()),
+                handle,
+            });
+        }
+        if let Some(current) = current {
+            self.switch_to_window(&current).await?;
+        }
+        Ok(tabs)
+    }
+    
     /// Get cookies
     pub async fn get_cookies(&mut self) -> Result<Vec<fantoccini::cookies::Cookie<'static>>> {
         Ok(self.client.get_all_cookies().await?)
@@ -108,6 +165,72 @@ impl SafariDriver {
         Ok(())
     }
     
+    /// Wait until an element matches `selector`, and if `visible` is set, until one of the
+    /// matches is displayed
+    pub async fn wait_for_selector(&mut self, selector: &str, visible: bool, timeout: Duration) -> WaitOutcome {
+        let start = std::time::Instant::now();
+        loop {
+            let elements = self.find_elements(selector).await.unwrap_or_default();
+            let mut shown = None;
+            for element in &elements {
+                if !visible || element.is_displayed().await.unwrap_or(false) {
+                    shown = Some(element);
+                    break;
+                }
+            }
+            if let Some(element) = shown {
+                return WaitOutcome {
+                    met: true,
+                    elapsed: start.elapsed(),
+                    state: excerpt(&element.text().await.unwrap_or_default()),
+                };
+            }
+            if start.elapsed() >= timeout {
+                return WaitOutcome {
+                    met: false,
+                    elapsed: start.elapsed(),
+                    state: if elements.is_empty() {
+                        format!("no element matches '{}'", selector)
+                    } else {
+                        format!("{} element(s) match '{}' but none is visible", elements.len(), selector)
+                    },
+                };
+            }
+            tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        }
+    }
+    
+    /// Wait until the text of the first element matching `selector` contains `text`
+    pub async fn wait_for_text(&mut self, text: &str, selector: &str, timeout: Duration) -> WaitOutcome {
+        let start = std::time::Instant::now();
+        loop {
+            let current = match self.find_element(selector).await {
+                Ok(element) => element.text().await.ok(),
+                Err(_) => None,
+            };
+            if let Some(current) = &current {
+                if current.contains(text) {
+                    return WaitOutcome {
+                        met: true,
+                        elapsed: start.elapsed(),
+                        state: excerpt(current),
+                    };
+                }
+            }
+            if start.elapsed() >= timeout {
+                return WaitOutcome {
+                    met: false,
+                    elapsed: start.elapsed(),
+                    state: match current {
+                        Some(current) => format!("text of '{}' was: {}", selector, excerpt(&current)),
+                        None => format!("no element matches '{}'", selector),
+                    },
+                };
+            }
+            tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+        }
+    }
+    
     /// Wait for an element to appear (with timeout)
     pub async fn wait_for_element(&mut self, selector: &str, timeout: Duration) -> Result<WebElement> {
         let start = std::time::Instant::now();
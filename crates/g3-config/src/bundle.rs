@@ -0,0 +1,297 @@
+//! Settings bundles, for standardizing a team's g3 setup across machines and CI.
+//!
+//! `g3 profile export` takes the shareable sections of a config file (agent profiles,
+//! moderation hooks, `[scope]`, command plugins, `[output_tokens]` and Unity Catalog tools)
+//! as they are written, so `${VAR}`, `env:` and `keyring:` references stay references, and
+//! leaves out literal values of secret-looking keys. The bundle is signed with HMAC-SHA256
+//! using a key the team shares. `g3 profile import` checks the signature and writes the
+//! settings to [`team_settings_path`], which is loaded beneath the user's own config file, so
+//! personal settings still take precedence.
+
+use anyhow::{anyhow, bail, Context, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+
+use crate::env::is_secret;
+
+/// Config sections a bundle carries. Provider settings stay on each machine.
+pub const SHARED_SECTIONS: &[&str] = &[
+    "profiles",
+    "moderation",
+    "scope",
+    "plugins",
+    "output_tokens",
+    "unity_catalog",
+];
+
+/// Environment variable holding the signing key, when no key file is given
+pub const KEY_VAR: &str = "G3_BUNDLE_KEY";
+
+const FORMAT: u32 = 1;
+const SIGNATURE_PREFIX: &str = "hmac-sha256:";
+const MIN_KEY_LEN: usize = 16;
+
+/// A signed bundle, stored as TOML
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    /// Format version
+    pub g3_bundle: u32,
+    /// The shared settings, as TOML
+    pub settings: String,
+    /// `hmac-sha256:<hex>` of the format version and the settings
+    pub signature: String,
+}
+
+/// An exported bundle, with the settings that were left out of it
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub bundle: String,
+    /// Dotted names of the secret values that were removed
+    pub removed: Vec<String>,
+}
+
+/// The verified settings of an imported bundle
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// The settings, as TOML
+    pub settings: String,
+    pub sections: Vec<String>,
+    /// Names of the agent profiles it sets
+    pub profiles: Vec<String>,
+}
+
+/// Where imported settings are kept: `~/.config/g3/team.toml`
+pub fn team_settings_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config/g3/team.toml"))
+}
+
+/// The signing key from `key_file`, or else from `$G3_BUNDLE_KEY`
+pub fn load_key(key_file: Option<&Path>) -> Result<Vec<u8>> {
+    let key = match key_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?,
+        None => std::env::var(KEY_VAR).map_err(|_| {
+            anyhow!(
+                "No signing key: pass --key-file <path> or set {} to the key your team shares",
+                KEY_VAR
+            )
+        })?,
+    };
+    let key = key.trim();
+    if key.len() < MIN_KEY_LEN {
+        bail!(
+            "The signing key is too short; use at least {} characters",
+            MIN_KEY_LEN
+        );
+    }
+    Ok(key.as_bytes().to_vec())
+}
+
+/// Bundle the shareable sections of `config`, the text of a config file, signed with `key`
+pub fn export(config: &str, key: &[u8]) -> Result<Export> {
+    let table: toml::Table = toml::from_str(config).context("Failed to parse the config file")?;
+    let mut settings: toml::Table = table
+        .into_iter()
+        .filter(|(section, _)| SHARED_SECTIONS.contains(&section.as_str()))
+        .collect();
+    if settings.is_empty() {
+        bail!(
+            "The config file has no settings to share (sections: {})",
+            SHARED_SECTIONS.join(", ")
+        );
+    }
+    let mut removed = Vec::new();
+    strip_secrets(&mut settings, "", &mut removed);
+
+    let settings = toml::to_string(&settings)?;
+    let bundle = Bundle {
+        g3_bundle: FORMAT,
+        signature: sign(key, &settings),
+        settings,
+    };
+    Ok(Export {
+        bundle: toml::to_string(&bundle)?,
+        removed,
+    })
+}
+
+/// Check the signature of `bundle` against `key` and return its settings
+pub fn import(bundle: &str, key: &[u8]) -> Result<Import> {
+    let bundle: Bundle = toml::from_str(bundle).context("Not a g3 settings bundle")?;
+    if bundle.g3_bundle != FORMAT {
+        bail!(
+            "Bundle format {} isn't supported by this version of g3 (expected {})",
+            bundle.g3_bundle,
+            FORMAT
+        );
+    }
+    let signature = bundle
+        .signature
+        .strip_prefix(SIGNATURE_PREFIX)
+        .and_then(decode_hex);
+    let verified = signature.is_some_and(|signature| {
+        hmac_sha256(key, &signed_message(&bundle.settings))
+            .verify_slice(&signature)
+            .is_ok()
+    });
+    if !verified {
+        bail!("The bundle's signature doesn't match; it was changed or signed with another key");
+    }
+
+    let settings: toml::Table = toml::from_str(&bundle.settings)?;
+    if let Some(section) = settings
+        .keys()
+        .find(|section| !SHARED_SECTIONS.contains(&section.as_str()))
+    {
+        bail!("The bundle sets [{}], which bundles can't carry", section);
+    }
+    let profiles = settings
+        .get("profiles")
+        .and_then(|profiles| profiles.as_table())
+        .map(|profiles| profiles.keys().cloned().collect())
+        .unwrap_or_default();
+    Ok(Import {
+        sections: settings.keys().cloned().collect(),
+        profiles,
+        settings: bundle.settings,
+    })
+}
+
+/// Remove string values of secret-looking keys, unless they only refer to a secret
+fn strip_secrets(table: &mut toml::Table, prefix: &str, removed: &mut Vec<String>) {
+    table.retain(|key, value| {
+        let name = format!("{}{}", prefix, key);
+        match value {
+            toml::Value::Table(inner) => {
+                strip_secrets(inner, &format!("{}.", name), removed);
+                true
+            }
+            toml::Value::String(text) if is_secret(key, text) && !is_reference(text) => {
+                removed.push(name);
+                false
+            }
+            _ => true,
+        }
+    });
+}
+
+/// `${VAR}`, `env:VAR`, `file:/path` and `keyring:` values name a secret without holding it
+fn is_reference(value: &str) -> bool {
+    (value.starts_with("${") && value.ends_with('}') && !value[2..].contains("${"))
+        || ["env:", "file:", "keyring:"]
+            .iter()
+            .any(|prefix| value.starts_with(prefix))
+}
+
+fn sign(key: &[u8], settings: &str) -> String {
+    let mac: String = hmac_sha256(key, &signed_message(settings))
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}{}", SIGNATURE_PREFIX, mac)
+}
+
+/// The format version and the settings, which the signature covers
+fn signed_message(settings: &str) -> Vec<u8> {
+    format!("g3-bundle-{}\n{}", FORMAT, settings).into_bytes()
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"team-key-0123456789";
+
+    const CONFIG: &str = r#"
+[providers]
+default_provider = "anthropic"
+
+[providers.anthropic]
+api_key = "sk-ant-secret"
+model = "claude-sonnet-4-5"
+
+[profiles.reviewer]
+system_prompt = "Point out bugs; do not change files."
+tools = ["read_file", "code_search"]
+
+[moderation]
+pre_send = "./hooks/redact.sh"
+
+[plugins.jira]
+command = "jira-mcp"
+env = { JIRA_URL = "https://jira.example.com", JIRA_TOKEN = "abc123secret", JIRA_AUTH = "${JIRA_AUTH}" }
+"#;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?").finalize();
+        let hex: String = mac
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(
+            hex,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let exported = export(CONFIG, KEY).unwrap();
+        assert_eq!(exported.removed, vec!["plugins.jira.env.JIRA_TOKEN"]);
+        assert!(!exported.bundle.contains("sk-ant-secret"));
+        assert!(!exported.bundle.contains("abc123secret"));
+
+        let imported = import(&exported.bundle, KEY).unwrap();
+        let mut sections = imported.sections.clone();
+        sections.sort();
+        assert_eq!(sections, ["moderation", "plugins", "profiles"]);
+        assert_eq!(imported.profiles, ["reviewer"]);
+        let settings: toml::Table = toml::from_str(&imported.settings).unwrap();
+        assert_eq!(
+            settings["plugins"]["jira"]["env"]["JIRA_AUTH"].as_str(),
+            Some("${JIRA_AUTH}")
+        );
+
+        let err = import(&exported.bundle, b"another-key-0123456789").unwrap_err();
+        assert!(err.to_string().contains("signature"));
+        let tampered = exported
+            .bundle
+            .replace("do not change", "go ahead and change");
+        assert!(import(&tampered, KEY).is_err());
+        let mut unsigned: Bundle = toml::from_str(&exported.bundle).unwrap();
+        unsigned.signature = "hmac-sha256:not-hex".to_string();
+        assert!(import(&toml::to_string(&unsigned).unwrap(), KEY).is_err());
+
+        // A signed bundle still can't carry provider settings
+        let mut bundle: Bundle = toml::from_str(&exported.bundle).unwrap();
+        bundle.settings = "[providers]\ndefault_provider = \"openai\"\n".to_string();
+        bundle.signature = sign(KEY, &bundle.settings);
+        let err = import(&toml::to_string(&bundle).unwrap(), KEY).unwrap_err();
+        assert!(err.to_string().contains("[providers]"));
+
+        assert!(export("[providers]\n", KEY).is_err());
+    }
+}
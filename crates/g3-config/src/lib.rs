@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::path::Path;
 
+pub mod bundle;
+pub mod env;
+pub mod secrets;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub providers: ProvidersConfig,
@@ -9,8 +13,58 @@ pub struct Config {
     pub computer_control: ComputerControlConfig,
     pub webdriver: WebDriverConfig,
     pub macax: MacAxConfig,
+    #[serde(default)]
+    pub context: ContextConfig,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    #[serde(default)]
+    pub artifacts: ArtifactsConfig,
+    #[serde(default)]
+    pub logs: LogsConfig,
+    /// Named agent profiles, selected with `--profile <name>` or `/profile <name>`
+    #[serde(default)]
+    pub profiles: std::collections::HashMap<String, ProfileConfig>,
+    #[serde(default)]
+    pub unity_catalog: UnityCatalogConfig,
+    #[serde(default)]
+    pub output_tokens: OutputTokensConfig,
+    #[serde(default)]
+    pub moderation: ModerationConfig,
+    #[serde(default)]
+    pub embeddings: EmbeddingsConfig,
+    #[serde(default)]
+    pub quotas: QuotasConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    /// Directories tools may write, read and run commands in
+    #[serde(default)]
+    pub scope: ScopeConfig,
+    /// SSH host that shell commands, read_file and write_file work on
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    /// Syntax checks of source files after file tools change them
+    #[serde(default)]
+    pub syntax_check: SyntaxCheckConfig,
+    /// Limits on how often tools run, by tool name or group: `all`, `file_writes` (write_file,
+    /// str_replace and edit_symbol) or `webdriver` (every webdriver_ tool)
+    #[serde(default)]
+    pub budgets: std::collections::HashMap<String, ToolBudget>,
+    /// Command plugins offering tools over JSON-RPC, by name
+    #[serde(default)]
+    pub plugins: std::collections::HashMap<String, PluginConfig>,
+    /// Tools and subsystems with a model of their own, by name: `summarizer`,
+    /// `vision_locate` and `index`
+    #[serde(default)]
+    pub models: std::collections::HashMap<String, ModelConfig>,
 }
 
+/// `[models]` entry of the model that summarizes the conversation
+pub const SUMMARIZER_MODEL: &str = "summarizer";
+/// `[models]` entry of the vision model that locates UI elements in screenshots
+pub const VISION_LOCATE_MODEL: &str = "vision_locate";
+/// `[models]` entry of the model that describes directories for `g3 index`
+pub const INDEX_MODEL: &str = "index";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProvidersConfig {
     pub openai: Option<OpenAIConfig>,
@@ -27,21 +81,96 @@ pub struct ProvidersConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIConfig {
+    /// API key, or an `env:`/`file:`/`keyring:` reference (see [`secrets`])
+    #[serde(default)]
     pub api_key: String,
     pub model: String,
     pub base_url: Option<String>,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
+    /// The API to call. Default: the Responses API for reasoning models (o-series and
+    /// gpt-5) on api.openai.com, Chat Completions otherwise.
+    #[serde(default)]
+    pub api: Option<OpenAIApi>,
+    /// How much reasoning models think before answering: `minimal`, `low`, `medium` or
+    /// `high`. Default: the model's own default.
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    /// Size of the model's context window. Default: the known size for the model.
+    #[serde(default)]
+    pub context_length: Option<u32>,
+    /// Gzip large request bodies. Only for servers that accept `Content-Encoding: gzip`
+    /// requests.
+    #[serde(default)]
+    pub compress_requests: bool,
+}
+
+/// The OpenAI API a provider calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OpenAIApi {
+    /// `/chat/completions`, which every OpenAI-compatible server has
+    ChatCompletions,
+    /// `/responses`, which streams summaries of a reasoning model's thinking
+    Responses,
+}
+
+impl ProvidersConfig {
+    /// Replace the secret references of provider `name` with their resolved values, leaving
+    /// those of other providers as written. Done when the provider is built, so a reference
+    /// of an unused provider can't fail the load and the keyring isn't asked for it.
+    ///
+    /// Keys that are omitted from the config file fall back to the OS keyring.
+    pub fn resolve_secrets_of(&mut self, name: &str) -> Result<()> {
+        if name == "openai" {
+            if let Some(openai) = &mut self.openai {
+                openai.api_key = openai.resolved_api_key(name)?;
+            }
+        }
+        if let Some(openai) = self.openai_compatible.get_mut(name) {
+            openai.api_key = openai.resolved_api_key(name)?;
+        }
+        if name == "anthropic" {
+            if let Some(anthropic) = &mut self.anthropic {
+                anthropic.api_key = anthropic.resolved_api_key()?;
+            }
+        }
+        if name == "databricks" {
+            if let Some(databricks) = &mut self.databricks {
+                databricks.token = databricks.resolved_token()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OpenAIConfig {
+    /// The API key of provider `name`, with a secret reference resolved
+    pub fn resolved_api_key(&self, name: &str) -> Result<String> {
+        Ok(secrets::resolve_optional_secret(Some(self.api_key.as_str()), name)?.unwrap_or_default())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicConfig {
+    /// API key, or an `env:`/`file:`/`keyring:` reference (see [`secrets`])
+    #[serde(default)]
     pub api_key: String,
     pub model: String,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
 }
 
+impl AnthropicConfig {
+    /// The API key, with a secret reference resolved
+    pub fn resolved_api_key(&self) -> Result<String> {
+        Ok(
+            secrets::resolve_optional_secret(Some(self.api_key.as_str()), "anthropic")?
+                .unwrap_or_default(),
+        )
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabricksConfig {
     pub host: String,
@@ -50,6 +179,17 @@ pub struct DatabricksConfig {
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
     pub use_oauth: Option<bool>, // Default to true if token not provided
+    /// Gzip large request bodies. Only for endpoints that accept `Content-Encoding: gzip`
+    /// requests.
+    #[serde(default)]
+    pub compress_requests: bool,
+}
+
+impl DatabricksConfig {
+    /// The token, with a secret reference resolved. `None` means OAuth.
+    pub fn resolved_token(&self) -> Result<Option<String>> {
+        secrets::resolve_optional_secret(self.token.as_deref(), "databricks")
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,6 +201,10 @@ pub struct EmbeddedConfig {
     pub temperature: Option<f32>,
     pub gpu_layers: Option<u32>, // Number of layers to offload to GPU
     pub threads: Option<u32>,    // Number of CPU threads to use
+    /// Decode with a grammar that only allows tool calls that are valid JSON matching a
+    /// known tool's input schema, instead of relying on pattern matching to find them
+    #[serde(default)]
+    pub constrained_tool_calls: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,6 +216,569 @@ pub struct AgentConfig {
     pub auto_compact: bool,
     pub max_retry_attempts: u32,
     pub autonomous_max_retry_attempts: u32,
+    /// Abort and retry a streaming response when no data (not even a keep-alive ping)
+    /// arrives for this many seconds
+    #[serde(default = "default_stream_stall_timeout_seconds")]
+    pub stream_stall_timeout_seconds: u64,
+    /// Show the elapsed time and live tokens/sec of a response while it streams
+    #[serde(default = "default_stream_indicator")]
+    pub stream_indicator: bool,
+    /// Remove file-editing tools and refuse shell commands that could modify anything
+    #[serde(default)]
+    pub read_only: bool,
+    /// The active profile from `[profiles]`, if any
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Generate a commit message, PR description and CHANGELOG entry when a session ends
+    #[serde(default)]
+    pub summarize_changes_on_exit: bool,
+    /// In autonomous mode, snapshot the workspace before the first mutating command of a
+    /// player round and restore it before the round is retried
+    #[serde(default)]
+    pub snapshot_rounds: bool,
+    /// In autonomous mode in a git repository, commit each player round to a branch of the
+    /// run's own, for review with `g3 review-run`
+    #[serde(default)]
+    pub commit_rounds: bool,
+    /// In autonomous mode, have the player review its own changes against the requirements
+    /// instead of handing them to a coach, which is cheaper for small tasks
+    #[serde(default)]
+    pub self_review: bool,
+    /// In a git repository, commit each successful file edit to a session branch with a
+    /// one-line message the model writes
+    #[serde(default)]
+    pub commit_edits: bool,
+    /// Write `write_file` content to `<file_path>.g3-partial` while the tool call's
+    /// arguments are still streaming
+    #[serde(default)]
+    pub progressive_writes: bool,
+    /// Save the chunks of each provider response as a gzipped artifact, for replaying them
+    /// through the stream parser with `g3 replay-stream`
+    #[serde(default)]
+    pub record_streams: bool,
+    /// Run shell commands in the workspace's dev container (`devcontainer.json`), starting
+    /// it if needed
+    #[serde(default)]
+    pub devcontainer: bool,
+    /// In autonomous mode, how many times in a row a response that stops at the output
+    /// token limit is continued automatically (0 to leave it truncated)
+    #[serde(default = "default_max_continuations")]
+    pub max_continuations: u32,
+    /// Wall-clock limit of a turn in seconds (0 for none). Past it the model is told to
+    /// wrap up and call `final_output` with its progress so far.
+    #[serde(default)]
+    pub turn_timeout_seconds: u64,
+    /// Workspaces g3 refuses without `--allow-dangerous-workspace`, in addition to the
+    /// built-in system and credentials directories. `dir/**` covers subdirectories too.
+    #[serde(default)]
+    pub protected_workspaces: Vec<String>,
+    /// Send requests to the providers of a running `g3 daemon` instead of loading them
+    #[serde(default = "default_use_daemon")]
+    pub use_daemon: bool,
+    /// Replace emoji and box drawing in output and tool results with ASCII tags such as
+    /// `[OK]` and `[ERR]`, for screen readers (`--plain`)
+    #[serde(default)]
+    pub plain_output: bool,
+    /// Pass the variables of the workspace's `.env` and `.env.g3` to shell commands,
+    /// redacting secret values from their output (see [`env`])
+    #[serde(default = "default_load_env_files")]
+    pub load_env_files: bool,
+}
+
+/// A persona preset: extra instructions, a narrower tool set and a model of its own
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    /// Appended to the system prompt
+    pub system_prompt: Option<String>,
+    /// Tools offered to the model (all tools when unset)
+    pub tools: Option<Vec<String>>,
+    /// Provider to use instead of `providers.default_provider`
+    pub provider: Option<String>,
+    /// Model for the profile's provider
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    /// Output token limits that take precedence over `[output_tokens]`
+    pub output_tokens: OutputTokensConfig,
+}
+
+/// Overrides for the `max_tokens` of each request. Unset limits are worked out from the
+/// model's output limit, the room left in the context window and the kind of request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputTokensConfig {
+    /// Upper bound for every request
+    pub max: Option<u32>,
+    /// Requests that start work on a task
+    pub task: Option<u32>,
+    /// Requests that continue after tool results
+    pub tool_followup: Option<u32>,
+    /// Summaries made when compacting the context
+    pub summary: Option<u32>,
+}
+
+impl OutputTokensConfig {
+    /// These limits, with any unset ones taken from `base`
+    pub fn or(&self, base: &OutputTokensConfig) -> OutputTokensConfig {
+        OutputTokensConfig {
+            max: self.max.or(base.max),
+            task: self.task.or(base.task),
+            tool_followup: self.tool_followup.or(base.tool_followup),
+            summary: self.summary.or(base.summary),
+        }
+    }
+}
+
+fn default_stream_stall_timeout_seconds() -> u64 {
+    120
+}
+
+fn default_max_continuations() -> u32 {
+    3
+}
+
+fn default_use_daemon() -> bool {
+    true
+}
+
+fn default_stream_indicator() -> bool {
+    true
+}
+
+fn default_load_env_files() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextConfig {
+    #[serde(default)]
+    pub thinning: ThinningConfig,
+    /// Model that summarizes the conversation when the context is compacted (default: the
+    /// active provider)
+    #[serde(default)]
+    pub summarizer: Option<SummarizerConfig>,
+    /// Size limit on tool results as they are added to the context
+    #[serde(default)]
+    pub tool_results: ToolResultLimits,
+}
+
+/// How a tool result over its size limit is cut down before it enters the context
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToolResultStrategy {
+    /// Keep the start and the end of the result
+    #[default]
+    HeadTail,
+    /// Replace the result with a summary from the summarizer model, falling back to
+    /// head-tail if it fails
+    Summarize,
+}
+
+/// Limits that replace a tool's own `[context.tool_results]` settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolResultLimit {
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub strategy: Option<ToolResultStrategy>,
+}
+
+/// Tool results larger than a token limit are saved as an artifact and cut down (or
+/// summarized) as they are added to the context, instead of waiting for thinning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolResultLimits {
+    /// Results over this many tokens are cut down (0 = no limit)
+    pub max_tokens: u32,
+    pub strategy: ToolResultStrategy,
+    /// Settings for individual tools, by tool name
+    pub tools: std::collections::HashMap<String, ToolResultLimit>,
+}
+
+impl Default for ToolResultLimits {
+    fn default() -> Self {
+        Self {
+            max_tokens: 10_000,
+            strategy: ToolResultStrategy::default(),
+            tools: std::collections::HashMap::new(),
+        }
+    }
+}
+
+impl ToolResultLimits {
+    /// The token limit and strategy for results of `tool`
+    pub fn for_tool(&self, tool: &str) -> (u32, ToolResultStrategy) {
+        let limit = self.tools.get(tool);
+        (
+            limit.and_then(|l| l.max_tokens).unwrap_or(self.max_tokens),
+            limit.and_then(|l| l.strategy).unwrap_or(self.strategy),
+        )
+    }
+}
+
+/// The model of a tool or subsystem: a configured provider, optionally with another of
+/// its models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelConfig {
+    /// A configured provider: anthropic, databricks, embedded, openai or an
+    /// openai_compatible name
+    pub provider: String,
+    /// Model to use instead of the provider's configured one
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Output limit of its responses, e.g. of a summary instead of `[output_tokens] summary`
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+/// A (cheap, fast) model for context summarization
+pub type SummarizerConfig = ModelConfig;
+
+/// How context thinning chooses what to shrink
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThinningStrategy {
+    /// Thin large tool results and tool call payloads in message order
+    #[default]
+    OldestFirst,
+    /// Thin the largest tool results and tool call payloads first
+    LargestFirst,
+    /// Only thin tool results, leaving the model's own tool calls intact
+    ToolResultsOnly,
+    /// Replace large tool results with a summary from a (cheap) model
+    SummarizeInPlace,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThinningConfig {
+    pub strategy: ThinningStrategy,
+    /// Context usage (percent) at which automatic thinning first triggers
+    pub start_percentage: u32,
+    /// Thinning triggers again each time usage crosses another step
+    pub step_percentage: u32,
+    /// No automatic thinning above this usage; summarization takes over
+    pub max_percentage: u32,
+    /// Number of oldest messages scanned per pass (default: the first third)
+    pub chunk_size: Option<usize>,
+    /// Tool results and payloads smaller than this (in chars) are left alone
+    pub min_chars: usize,
+    /// Stop once this percentage of the conversation has been removed (0 = no limit)
+    pub target_reduction: u32,
+    /// Directory that thinned content is saved to (default: the session's artifact directory)
+    pub output_dir: Option<String>,
+    /// Provider used by summarize-in-place (defaults to `[context.summarizer]`, then the
+    /// active provider)
+    pub summary_provider: Option<String>,
+}
+
+impl Default for ThinningConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ThinningStrategy::default(),
+            start_percentage: 50,
+            step_percentage: 10,
+            max_percentage: 80,
+            chunk_size: None,
+            min_chars: 500,
+            target_reduction: 0,
+            output_dir: None,
+            summary_provider: None,
+        }
+    }
+}
+
+/// Cleanup policy for per-session artifact directories (`.g3/sessions/<id>/artifacts/`).
+/// Old sessions are pruned when an agent starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtifactsConfig {
+    /// Delete artifacts of sessions inactive for longer than this (unset = never)
+    pub max_age_days: Option<u32>,
+    /// Keep artifacts for at most this many sessions, deleting the oldest (unset = no limit)
+    pub max_sessions: Option<usize>,
+}
+
+impl Default for ArtifactsConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: Some(7),
+            max_sessions: Some(20),
+        }
+    }
+}
+
+/// Retention of session logs in `logs/`. Old logs are compressed and pruned when a session
+/// starts, and with `g3 sessions prune`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogsConfig {
+    /// Compress and prune automatically when a session starts
+    pub auto_prune: bool,
+    /// Most recent sessions kept as plain JSON; older ones are compressed to `.json.zst`
+    pub keep_uncompressed: usize,
+    /// Keep at most this many sessions, deleting the oldest (unset = no limit)
+    pub max_sessions: Option<usize>,
+    /// Delete logs of sessions inactive for longer than this (unset = never)
+    pub max_age_days: Option<u32>,
+    /// Delete the oldest logs while all of them together take more than this (unset = no
+    /// limit)
+    pub max_total_mb: Option<u64>,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            auto_prune: true,
+            keep_uncompressed: 20,
+            max_sessions: Some(1000),
+            max_age_days: None,
+            max_total_mb: Some(1024),
+        }
+    }
+}
+
+/// Getting the user's attention when a long turn finishes or the agent asks a question, so
+/// they can work on something else in the meantime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Ring the terminal bell
+    pub bell: bool,
+    /// Show a desktop notification (`osascript` on macOS, `notify-send` elsewhere)
+    pub desktop: bool,
+    /// Turns that finish sooner than this don't notify, unless they end with a question
+    pub min_turn_seconds: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            bell: false,
+            desktop: false,
+            min_turn_seconds: 30,
+        }
+    }
+}
+
+/// Limits on where tools may act, for working on one part of a monorepo. Directories are
+/// relative to the workspace; an empty list doesn't restrict.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScopeConfig {
+    /// Directories files may be written, edited or created in
+    pub write: Vec<String>,
+    /// Directories files may be read from
+    pub read: Vec<String>,
+    /// Directory shell commands run in; changing out of it is refused
+    pub shell: Option<String>,
+}
+
+/// A machine to work on over SSH, such as a build server or a device. Shell commands,
+/// read_file and write_file run there; str_replace and edit_symbol are refused, and the
+/// other tools work on local files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// `host` or `user@host`, or a `Host` alias of `~/.ssh/config`; unset works locally
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    /// Private key to log in with, instead of ssh's defaults
+    pub identity_file: Option<String>,
+    /// Remote directory commands run in and relative paths start from (default: the login
+    /// directory)
+    pub dir: Option<String>,
+    /// Also give remote commands the variables of the workspace's `.env` files, which sends
+    /// their values to the host (default: off)
+    pub forward_env: bool,
+}
+
+/// Checks of source files right after write_file, str_replace or edit_symbol change them,
+/// whose errors are added to the tool result so a broken edit is fixed in the next step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyntaxCheckConfig {
+    /// Parse the file with tree-sitter and report syntax errors the edit introduced
+    pub enabled: bool,
+    /// Also run the compiler's check: `cargo check` for Rust and a compile of the file for
+    /// Python. Slower, and reports errors anywhere in the crate.
+    pub compiler: bool,
+    /// Compiler checks that take longer are stopped and skipped
+    pub timeout_seconds: u64,
+}
+
+impl Default for SyntaxCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            compiler: false,
+            timeout_seconds: 60,
+        }
+    }
+}
+
+/// Limits on calls of one tool or group of tools, so a model stuck in a loop gets a clear
+/// error instead of running commands or writing files without end. Unset limits don't apply.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolBudget {
+    pub per_turn: Option<u32>,
+    pub per_session: Option<u32>,
+    /// Calls in any 60 seconds
+    pub per_minute: Option<u32>,
+}
+
+/// Policy hooks: external commands that inspect, rewrite or block what is sent to the model
+/// and what comes back from it. Each gets a JSON document on stdin and replies on stdout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModerationConfig {
+    /// Run on the messages of every request before it is sent
+    pub pre_send: Option<String>,
+    /// Run on every tool call before it runs, and on the model's final response to a task
+    pub post_receive: Option<String>,
+    /// Block when a hook fails, times out or gives an unreadable reply, instead of allowing
+    pub fail_closed: bool,
+    pub timeout_seconds: u64,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            pre_send: None,
+            post_receive: None,
+            fail_closed: false,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+/// How code_search_semantic turns code into vectors
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingsConfig {
+    /// `hashed` (lexical: hashed words and trigrams, no model or network needed), `openai`
+    /// or an openai_compatible name, whose API key and base URL are used
+    pub provider: String,
+    /// Default: text-embedding-3-small. Not used by `hashed`.
+    pub model: Option<String>,
+}
+
+impl EmbeddingsConfig {
+    /// Whether vectors come from hashing words rather than from an embeddings model, so
+    /// matches share vocabulary with the query rather than meaning
+    pub fn is_hashed(&self) -> bool {
+        self.provider == "hashed"
+    }
+}
+
+impl Default for EmbeddingsConfig {
+    fn default() -> Self {
+        Self {
+            provider: "hashed".to_string(),
+            model: None,
+        }
+    }
+}
+
+/// An executable that offers tools over JSON-RPC on its stdin and stdout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extra environment variables for the plugin process
+    #[serde(default)]
+    pub env: std::collections::HashMap<String, String>,
+    /// How long a tool call may take
+    #[serde(default = "default_plugin_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+fn default_plugin_timeout_seconds() -> u64 {
+    60
+}
+
+/// Opt-in usage statistics, aggregated locally across sessions and never sent anywhere
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Record a line per task in the stats file
+    pub enabled: bool,
+    /// Stats file location (default: ~/.g3/stats.jsonl)
+    pub path: Option<String>,
+    /// Blended price of a task's tokens, used for its cost when it is recorded unless its
+    /// model is in `model_prices` (tokens are always shown)
+    pub cost_per_million_tokens: Option<f64>,
+    /// Prices per million tokens of models priced differently, by `model` or
+    /// `provider/model`
+    pub model_prices: std::collections::HashMap<String, f64>,
+}
+
+impl StatsConfig {
+    /// Price per million tokens of `model` of `provider`: its entry in `model_prices`, or
+    /// else `cost_per_million_tokens`
+    pub fn price_for(&self, provider: &str, model: &str) -> Option<f64> {
+        self.model_prices
+            .get(&format!("{}/{}", provider, model))
+            .or_else(|| self.model_prices.get(model))
+            .copied()
+            .or(self.cost_per_million_tokens)
+    }
+}
+
+/// Daily and weekly usage limits per provider, counted across sessions and processes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotasConfig {
+    /// Usage file location (default: ~/.g3/usage.json)
+    pub path: Option<String>,
+    /// What happens when the active provider has used up a quota
+    pub action: QuotaAction,
+    /// Provider switched to with `action = "fallback"`
+    pub fallback_provider: Option<String>,
+    /// Limits by provider name; usage is only counted when at least one is set
+    pub providers: std::collections::HashMap<String, ProviderQuota>,
+}
+
+/// What happens when a provider's quota is used up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuotaAction {
+    /// Show a warning before each turn and carry on
+    #[default]
+    Warn,
+    /// Switch to `fallback_provider` for the rest of the session
+    Fallback,
+    /// Refuse to start new turns until the quota resets
+    Block,
+}
+
+/// Limits of one provider. Days and weeks (from Monday) are in local time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProviderQuota {
+    pub daily_tokens: Option<u64>,
+    pub weekly_tokens: Option<u64>,
+    /// In the currency of `cost_per_million_tokens`
+    pub daily_cost: Option<f64>,
+    pub weekly_cost: Option<f64>,
+    /// Price used to count cost (default: `stats.cost_per_million_tokens`)
+    pub cost_per_million_tokens: Option<f64>,
+}
+
+/// Databricks Unity Catalog functions exposed to the agent as tools. Uses the host and
+/// credentials of `providers.databricks`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnityCatalogConfig {
+    pub enabled: bool,
+    /// Schemas whose functions become tools, as `catalog.schema`
+    pub schemas: Vec<String>,
+    /// SQL warehouse that runs the functions
+    pub warehouse_id: Option<String>,
+    /// Rows of a function's result returned to the agent (default: 100)
+    pub max_rows: Option<usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -79,12 +786,48 @@ pub struct ComputerControlConfig {
     pub enabled: bool,
     pub require_confirmation: bool,
     pub max_actions_per_second: u32,
+    /// Provider used by vision_locate to find UI elements in screenshots. When unset,
+    /// vision_locate only uses OCR and no screenshots are sent to a model.
+    #[serde(default)]
+    pub vision_provider: Option<String>,
+    #[serde(default)]
+    pub ocr: OcrConfig,
+}
+
+/// Limits on OCR of images and screenshots, which can be slow for large retina screenshots
+/// and produce a lot of text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OcrConfig {
+    /// Images with a longer side than this many pixels are scaled down to it before OCR
+    /// (0 = never)
+    pub max_dimension: u32,
+    /// OCR that takes longer than this is given up on
+    pub timeout_seconds: u64,
+    /// Extracted text is cut to this many characters
+    pub max_chars: usize,
+    /// Reuse the text of an image that was already read, by the hash of its content
+    pub cache: bool,
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            max_dimension: 2560,
+            timeout_seconds: 30,
+            max_chars: 20_000,
+            cache: true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebDriverConfig {
     pub enabled: bool,
     pub safari_port: u16,
+    /// Where webdriver_wait_for_download looks for downloads; `~/Downloads` if unset
+    #[serde(default)]
+    pub download_dir: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -105,6 +848,7 @@ impl Default for WebDriverConfig {
         Self {
             enabled: false,
             safari_port: 4444,
+            download_dir: None,
         }
     }
 }
@@ -115,6 +859,8 @@ impl Default for ComputerControlConfig {
             enabled: false, // Disabled by default for safety
             require_confirmation: true,
             max_actions_per_second: 5,
+            vision_provider: None,
+            ocr: OcrConfig::default(),
         }
     }
 }
@@ -133,6 +879,7 @@ impl Default for Config {
                     max_tokens: Some(4096),
                     temperature: Some(0.1),
                     use_oauth: Some(true),
+                    compress_requests: false,
                 }),
                 embedded: None,
                 default_provider: "databricks".to_string(),
@@ -147,14 +894,76 @@ impl Default for Config {
                 auto_compact: true,
                 max_retry_attempts: 3,
                 autonomous_max_retry_attempts: 6,
+                stream_stall_timeout_seconds: default_stream_stall_timeout_seconds(),
+                stream_indicator: default_stream_indicator(),
+                read_only: false,
+                profile: None,
+                summarize_changes_on_exit: false,
+                snapshot_rounds: false,
+                commit_rounds: false,
+                self_review: false,
+                commit_edits: false,
+                progressive_writes: false,
+                record_streams: false,
+                devcontainer: false,
+                max_continuations: default_max_continuations(),
+                protected_workspaces: Vec::new(),
+                use_daemon: default_use_daemon(),
+                turn_timeout_seconds: 0,
+                plain_output: false,
+                load_env_files: default_load_env_files(),
             },
             computer_control: ComputerControlConfig::default(),
             webdriver: WebDriverConfig::default(),
             macax: MacAxConfig::default(),
+            context: ContextConfig::default(),
+            stats: StatsConfig::default(),
+            artifacts: ArtifactsConfig::default(),
+            logs: LogsConfig::default(),
+            profiles: std::collections::HashMap::new(),
+            unity_catalog: UnityCatalogConfig::default(),
+            output_tokens: OutputTokensConfig::default(),
+            moderation: ModerationConfig::default(),
+            embeddings: EmbeddingsConfig::default(),
+            quotas: QuotasConfig::default(),
+            notifications: NotificationsConfig::default(),
+            scope: ScopeConfig::default(),
+            remote: RemoteConfig::default(),
+            syntax_check: SyntaxCheckConfig::default(),
+            budgets: std::collections::HashMap::new(),
+            plugins: std::collections::HashMap::new(),
+            models: std::collections::HashMap::new(),
         }
     }
 }
 
+/// A TOML config file with its `${VAR}` references replaced (see [`env`]). Comment lines are
+/// left alone, so they can mention variables that aren't set.
+fn config_file_source(
+    path: &str,
+) -> Result<config::File<config::FileSourceString, config::FileFormat>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path))?;
+    let contents = if contents.contains("${") {
+        let project_env = env::ProjectEnv::load(Path::new("."))?;
+        contents
+            .lines()
+            .map(|line| {
+                if line.trim_start().starts_with('#') {
+                    Ok(line.to_string())
+                } else {
+                    project_env.interpolate(line)
+                }
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Failed to load config file {}", path))?
+            .join("\n")
+    } else {
+        contents
+    };
+    Ok(config::File::from_str(&contents, config::FileFormat::Toml))
+}
+
 impl Config {
     pub fn load(config_path: Option<&str>) -> Result<Self> {
         // Check if any config file exists
@@ -205,11 +1014,16 @@ impl Config {
         
         // Load default configuration
         settings = settings.add_source(config::Config::try_from(&Config::default())?);
+
+        // Settings imported from a team's bundle, beneath the user's own
+        if let Some(team) = bundle::team_settings_path().filter(|path| path.exists()) {
+            settings = settings.add_source(config_file_source(&team.to_string_lossy())?);
+        }
         
         // Load from config file if provided
         if let Some(path) = config_path {
             if Path::new(path).exists() {
-                settings = settings.add_source(config::File::with_name(path));
+                settings = settings.add_source(config_file_source(path)?);
             }
         } else {
             // Try to load from default locations
@@ -222,7 +1036,7 @@ impl Config {
             for path in &default_paths {
                 let expanded_path = shellexpand::tilde(path);
                 if Path::new(expanded_path.as_ref()).exists() {
-                    settings = settings.add_source(config::File::with_name(expanded_path.as_ref()));
+                    settings = settings.add_source(config_file_source(expanded_path.as_ref())?);
                     break;
                 }
             }
@@ -234,10 +1048,24 @@ impl Config {
                 .separator("_")
         );
         
-        let config = settings.build()?.try_deserialize()?;
+        // Secret references are resolved when a provider is built (see
+        // `ProvidersConfig::resolve_secrets_of`), so unused providers' are never read
+        let config: Self = settings.build()?.try_deserialize()?;
         Ok(config)
     }
 
+    /// The config file [`Config::load`] reads: `config_path`, or else the first of the
+    /// default locations that exists
+    pub fn file_path(config_path: Option<&str>) -> Option<std::path::PathBuf> {
+        match config_path {
+            Some(path) => Some(std::path::PathBuf::from(shellexpand::tilde(path).as_ref())),
+            None => ["./g3.toml", "~/.config/g3/config.toml", "~/.g3.toml"]
+                .iter()
+                .map(|path| std::path::PathBuf::from(shellexpand::tilde(path).as_ref()))
+                .find(|path| path.exists()),
+        }
+    }
+
     #[allow(dead_code)]
     fn default_qwen_config() -> Self {
         Self {
@@ -254,6 +1082,7 @@ impl Config {
                     temperature: Some(0.1),
                     gpu_layers: Some(32),
                     threads: Some(8),
+                    constrained_tool_calls: false,
                 }),
                 default_provider: "embedded".to_string(),
                 coach: None,  // Will use default_provider if not specified
@@ -267,10 +1096,45 @@ impl Config {
                 auto_compact: true,
                 max_retry_attempts: 3,
                 autonomous_max_retry_attempts: 6,
+                stream_stall_timeout_seconds: default_stream_stall_timeout_seconds(),
+                stream_indicator: default_stream_indicator(),
+                read_only: false,
+                profile: None,
+                summarize_changes_on_exit: false,
+                snapshot_rounds: false,
+                commit_rounds: false,
+                self_review: false,
+                commit_edits: false,
+                progressive_writes: false,
+                record_streams: false,
+                devcontainer: false,
+                max_continuations: default_max_continuations(),
+                protected_workspaces: Vec::new(),
+                use_daemon: default_use_daemon(),
+                turn_timeout_seconds: 0,
+                plain_output: false,
+                load_env_files: default_load_env_files(),
             },
             computer_control: ComputerControlConfig::default(),
             webdriver: WebDriverConfig::default(),
             macax: MacAxConfig::default(),
+            context: ContextConfig::default(),
+            stats: StatsConfig::default(),
+            artifacts: ArtifactsConfig::default(),
+            logs: LogsConfig::default(),
+            profiles: std::collections::HashMap::new(),
+            unity_catalog: UnityCatalogConfig::default(),
+            output_tokens: OutputTokensConfig::default(),
+            moderation: ModerationConfig::default(),
+            embeddings: EmbeddingsConfig::default(),
+            quotas: QuotasConfig::default(),
+            notifications: NotificationsConfig::default(),
+            scope: ScopeConfig::default(),
+            remote: RemoteConfig::default(),
+            syntax_check: SyntaxCheckConfig::default(),
+            budgets: std::collections::HashMap::new(),
+            plugins: std::collections::HashMap::new(),
+            models: std::collections::HashMap::new(),
         }
     }
     
@@ -390,6 +1254,118 @@ impl Config {
         Ok(config)
     }
     
+    /// Create a copy of the config with the named profile's provider, model and
+    /// temperature applied and `agent.profile` set
+    pub fn with_profile(&self, name: &str) -> Result<Self> {
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            names.sort();
+            anyhow::anyhow!(
+                "Profile '{}' is not defined. Available profiles: {}",
+                name,
+                if names.is_empty() { "none".to_string() } else { names.join(", ") }
+            )
+        })?;
+
+        let mut config = match &profile.provider {
+            Some(provider) => self.with_provider_override(provider)?,
+            None => self.clone(),
+        };
+        config.agent.profile = Some(name.to_string());
+
+        if profile.model.is_none() && profile.temperature.is_none() {
+            return Ok(config);
+        }
+        let provider = config.providers.default_provider.clone();
+        let (model, temperature) = match provider.as_str() {
+            "anthropic" => config.providers.anthropic.as_mut().map(|c| (&mut c.model, &mut c.temperature)),
+            "databricks" => config.providers.databricks.as_mut().map(|c| (&mut c.model, &mut c.temperature)),
+            "embedded" => config.providers.embedded.as_mut().map(|c| (&mut c.model_path, &mut c.temperature)),
+            "openai" => config.providers.openai.as_mut().map(|c| (&mut c.model, &mut c.temperature)),
+            name => config.providers.openai_compatible.get_mut(name).map(|c| (&mut c.model, &mut c.temperature)),
+        }
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Profile '{}' uses provider '{}', which is not configured.",
+                name, provider
+            )
+        })?;
+        if let Some(profile_model) = &profile.model {
+            *model = profile_model.clone();
+        }
+        if profile.temperature.is_some() {
+            *temperature = profile.temperature;
+        }
+        Ok(config)
+    }
+    
+    /// The model of a tool or subsystem: its `[models]` entry, or for the summarizer and
+    /// vision_locate their own settings, `[context.summarizer]` and
+    /// `computer_control.vision_provider`
+    pub fn model_for(&self, name: &str) -> Option<ModelConfig> {
+        if let Some(model) = self.models.get(name) {
+            return Some(model.clone());
+        }
+        match name {
+            SUMMARIZER_MODEL => self.context.summarizer.clone(),
+            VISION_LOCATE_MODEL => self.computer_control.vision_provider.clone().map(|provider| ModelConfig {
+                provider,
+                model: None,
+                max_tokens: None,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The tools and subsystems that have a model of their own
+    pub fn model_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.models.keys().cloned().collect();
+        for name in [SUMMARIZER_MODEL, VISION_LOCATE_MODEL] {
+            if !self.models.contains_key(name) && self.model_for(name).is_some() {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        names
+    }
+
+    /// The config for the model of a tool or subsystem (see [`Config::model_for`]): its
+    /// provider as the default, with its model and output limit. `None` when it uses the
+    /// active provider.
+    pub fn for_model(&self, name: &str) -> Result<Option<Self>> {
+        let Some(model_config) = self.model_for(name) else {
+            return Ok(None);
+        };
+        let mut config = self.with_provider_override(&model_config.provider)?;
+        let provider = model_config.provider.as_str();
+        let (model, max_tokens) = match provider {
+            "anthropic" => config.providers.anthropic.as_mut().map(|c| (&mut c.model, &mut c.max_tokens)),
+            "databricks" => config.providers.databricks.as_mut().map(|c| (&mut c.model, &mut c.max_tokens)),
+            "embedded" => config.providers.embedded.as_mut().map(|c| (&mut c.model_path, &mut c.max_tokens)),
+            "openai" => config.providers.openai.as_mut().map(|c| (&mut c.model, &mut c.max_tokens)),
+            other => config.providers.openai_compatible.get_mut(other).map(|c| (&mut c.model, &mut c.max_tokens)),
+        }
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "The {} model uses provider '{}', which is not configured.",
+                name, provider
+            )
+        })?;
+        if let Some(configured_model) = &model_config.model {
+            *model = configured_model.clone();
+        }
+        if model_config.max_tokens.is_some() {
+            *max_tokens = model_config.max_tokens;
+        }
+        Ok(Some(config))
+    }
+
+    /// The config for `[context.summarizer]` (or `[models.summarizer]`): its provider as
+    /// the default, with its model and output limit. `None` when no summarizer is configured.
+    pub fn for_summarizer(&self) -> Result<Option<Self>> {
+        self.for_model(SUMMARIZER_MODEL)
+    }
+
     /// Create a copy of the config for coach mode in autonomous execution
     pub fn for_coach(&self) -> Result<Self> {
         self.with_provider_override(self.get_coach_provider())
@@ -0,0 +1,99 @@
+//! Resolution of provider secrets (API keys and tokens).
+//!
+//! A secret value in the config file can be written in one of several forms:
+//!
+//! - `env:VAR_NAME`   - read from the environment variable `VAR_NAME`
+//! - `file:/path`     - read from a file (trailing whitespace is trimmed, `~` is expanded)
+//! - `keyring:`       - read from the OS keyring entry for the provider
+//! - `keyring:NAME`   - read from the OS keyring entry `NAME`
+//! - anything else    - used verbatim as the secret
+//!
+//! Keys stored with `g3 auth login <provider>` live in the OS keyring under the
+//! service name [`KEYRING_SERVICE`] with the provider name as the account.
+
+use anyhow::{Context, Result};
+
+/// Service name used for all g3 entries in the OS keyring
+pub const KEYRING_SERVICE: &str = "g3";
+
+const ENV_PREFIX: &str = "env:";
+const FILE_PREFIX: &str = "file:";
+const KEYRING_PREFIX: &str = "keyring:";
+
+/// Resolve a secret reference for the given provider.
+///
+/// Plain values are returned unchanged. Explicit references (`env:`, `file:`, `keyring:`)
+/// that cannot be resolved are reported as errors.
+pub fn resolve_secret(value: &str, provider: &str) -> Result<String> {
+    if let Some(var) = value.strip_prefix(ENV_PREFIX) {
+        return std::env::var(var).with_context(|| {
+            format!(
+                "Environment variable '{}' referenced by provider '{}' is not set",
+                var, provider
+            )
+        });
+    }
+
+    if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+        let expanded = shellexpand::tilde(path);
+        let contents = std::fs::read_to_string(expanded.as_ref()).with_context(|| {
+            format!(
+                "Failed to read secret file '{}' referenced by provider '{}'",
+                path, provider
+            )
+        })?;
+        return Ok(contents.trim_end().to_string());
+    }
+
+    if let Some(account) = value.strip_prefix(KEYRING_PREFIX) {
+        let account = if account.is_empty() { provider } else { account };
+        return get_keyring_secret(account)?.ok_or_else(|| {
+            anyhow::anyhow!(
+                "No keyring entry found for '{}'. Run `g3 auth login {}` to store one.",
+                account,
+                account
+            )
+        });
+    }
+
+    Ok(value.to_string())
+}
+
+/// Resolve a secret that may have been omitted from the config file.
+///
+/// Missing or empty values fall back to the keyring entry for the provider, if any.
+pub fn resolve_optional_secret(value: Option<&str>, provider: &str) -> Result<Option<String>> {
+    match value {
+        Some(value) if !value.is_empty() => resolve_secret(value, provider).map(Some),
+        // Keyring access is best-effort here; the provider reports a missing key itself
+        _ => Ok(get_keyring_secret(provider).ok().flatten()),
+    }
+}
+
+/// Look up a secret in the OS keyring. Returns `Ok(None)` if there is no entry.
+pub fn get_keyring_secret(account: &str) -> Result<Option<String>> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, account)?;
+    match entry.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Store a secret in the OS keyring, replacing any existing entry.
+pub fn store_keyring_secret(account: &str, secret: &str) -> Result<()> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, account)?;
+    entry
+        .set_password(secret)
+        .with_context(|| format!("Failed to store keyring entry for '{}'", account))
+}
+
+/// Remove a secret from the OS keyring. Returns `false` if there was no entry.
+pub fn delete_keyring_secret(account: &str) -> Result<bool> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, account)?;
+    match entry.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,290 @@
+//! Per-session artifact storage.
+//!
+//! Intermediate data that tools persist (thinned context, screenshots, scratch files) lives
+//! in `.g3/sessions/<session id>/artifacts/` under the workspace instead of being scattered
+//! across a global temp directory. Each store keeps an `index.jsonl` of typed metadata so
+//! artifacts can be listed, and old sessions are pruned according to `[artifacts]` config.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use g3_config::ArtifactsConfig;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+/// Session directories, relative to the workspace
+pub const SESSIONS_DIR: &str = ".g3/sessions";
+
+const ARTIFACTS_DIR: &str = "artifacts";
+const INDEX_FILE: &str = "index.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    /// A tool result removed from the context by thinning
+    ThinnedToolResult,
+    /// A large tool call argument removed from the context by thinning
+    ThinnedToolCall,
+    Screenshot,
+    /// Screen recording made with `start_recording`
+    Recording,
+    Scratch,
+    /// Commit message, PR description or changelog entry written by `summarize-changes`
+    ChangeSummary,
+    /// Full output of a shell command too long for its tool result
+    ShellOutput,
+    /// A tool result over `[context.tool_results]` limits, cut down before it entered the
+    /// context
+    LargeToolResult,
+    /// Architecture overview written by `g3 explain`
+    Explanation,
+    /// Provider chunks of one response, recorded with `record_streams`
+    StreamRecording,
+    /// Summary of a turn, from its `final_output` call
+    Summary,
+}
+
+/// Metadata recorded for each artifact
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// File name within the artifact directory
+    pub name: String,
+    pub kind: ArtifactKind,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+    /// What produced the artifact, e.g. a tool name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+    dir: PathBuf,
+}
+
+impl ArtifactStore {
+    /// A store writing directly into `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The artifact store of a session in `workspace`
+    pub fn for_session(workspace: &Path, session_id: &str) -> Self {
+        Self::new(
+            workspace
+                .join(SESSIONS_DIR)
+                .join(session_id)
+                .join(ARTIFACTS_DIR),
+        )
+    }
+
+    /// A store in the system temp directory, for use before a session exists
+    pub fn temporary() -> Self {
+        Self::new(std::env::temp_dir().join("g3-artifacts"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// A fresh path in the store for an artifact that will be written by someone else
+    /// (e.g. a screenshot); record it with [`ArtifactStore::register`] once written
+    pub fn path_for(&self, name: &str) -> Result<PathBuf> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        Ok(self.dir.join(unique_name(name)))
+    }
+
+    /// Write an artifact and record its metadata. Returns the path it was written to.
+    pub fn save(
+        &self,
+        kind: ArtifactKind,
+        name: &str,
+        contents: &[u8],
+        source: Option<&str>,
+    ) -> Result<PathBuf> {
+        let path = self.path_for(name)?;
+        std::fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        self.register(kind, &path, source)?;
+        Ok(path)
+    }
+
+    /// Record metadata for a file already written into the store
+    pub fn register(
+        &self,
+        kind: ArtifactKind,
+        path: &Path,
+        source: Option<&str>,
+    ) -> Result<Artifact> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow::anyhow!("Artifact path has no file name: {}", path.display()))?;
+        let artifact = Artifact {
+            name,
+            kind,
+            created_at: Utc::now(),
+            size: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+            source: source.map(str::to_string),
+        };
+
+        let mut index = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INDEX_FILE))?;
+        writeln!(index, "{}", serde_json::to_string(&artifact)?)?;
+        Ok(artifact)
+    }
+
+    /// Recorded artifacts that still exist, oldest first
+    pub fn list(&self) -> Result<Vec<Artifact>> {
+        let contents = match std::fs::read_to_string(self.dir.join(INDEX_FILE)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Artifact>(line).ok())
+            .filter(|artifact| self.dir.join(&artifact.name).exists())
+            .collect())
+    }
+
+    pub fn path_of(&self, artifact: &Artifact) -> PathBuf {
+        self.dir.join(&artifact.name)
+    }
+}
+
+/// Prefix a name with the current time so repeated saves don't collide
+fn unique_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+    format!("{}_{}", Utc::now().format("%Y%m%d-%H%M%S%.3f"), name)
+}
+
+/// Delete session directories in `workspace` according to the cleanup policy, skipping
+/// `current_session`. Returns the number of sessions removed.
+pub fn prune_sessions(
+    workspace: &Path,
+    config: &ArtifactsConfig,
+    current_session: Option<&str>,
+) -> Result<usize> {
+    let sessions_dir = workspace.join(SESSIONS_DIR);
+    let entries = match std::fs::read_dir(&sessions_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    // Newest first
+    let mut sessions: Vec<(PathBuf, SystemTime)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter(|entry| current_session.is_none_or(|id| entry.file_name() != id))
+        .map(|entry| {
+            // The index is appended to whenever an artifact is saved, so it tracks activity
+            let index = entry.path().join(ARTIFACTS_DIR).join(INDEX_FILE);
+            let modified = std::fs::metadata(&index)
+                .or_else(|_| entry.metadata())
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (entry.path(), modified)
+        })
+        .collect();
+    sessions.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+
+    let max_age = config
+        .max_age_days
+        .map(|days| Duration::from_secs(days as u64 * 24 * 60 * 60));
+    // The current session counts towards the limit
+    let keep = config
+        .max_sessions
+        .map(|max| max.saturating_sub(current_session.is_some() as usize));
+
+    let mut removed = 0;
+    for (i, (path, modified)) in sessions.iter().enumerate() {
+        let too_old =
+            max_age.is_some_and(|max_age| modified.elapsed().is_ok_and(|age| age > max_age));
+        let over_limit = keep.is_some_and(|keep| i >= keep);
+        if !(too_old || over_limit) {
+            continue;
+        }
+        match std::fs::remove_dir_all(path) {
+            Ok(()) => {
+                debug!("Removed session artifacts {}", path.display());
+                removed += 1;
+            }
+            Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_list_artifacts() {
+        let workspace = TempDir::new().unwrap();
+        let store = ArtifactStore::for_session(workspace.path(), "session_1");
+        assert!(store.list().unwrap().is_empty());
+
+        let path = store
+            .save(
+                ArtifactKind::ThinnedToolResult,
+                "tool_result_3.txt",
+                b"large output",
+                Some("shell"),
+            )
+            .unwrap();
+        assert!(path.starts_with(workspace.path().join(".g3/sessions/session_1/artifacts")));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "large output");
+
+        let screenshot = store.path_for("safari.png").unwrap();
+        std::fs::write(&screenshot, b"png").unwrap();
+        store
+            .register(ArtifactKind::Screenshot, &screenshot, None)
+            .unwrap();
+
+        let artifacts = store.list().unwrap();
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[0].kind, ArtifactKind::ThinnedToolResult);
+        assert_eq!(artifacts[0].size, 12);
+        assert_eq!(artifacts[0].source.as_deref(), Some("shell"));
+        assert_eq!(store.path_of(&artifacts[1]), screenshot);
+
+        // Deleted files drop out of the listing
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(store.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_prune_sessions_keeps_newest_and_current() {
+        let workspace = TempDir::new().unwrap();
+        for id in ["a", "b", "c", "current"] {
+            ArtifactStore::for_session(workspace.path(), id)
+                .save(ArtifactKind::Scratch, "notes.txt", b"x", None)
+                .unwrap();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let config = ArtifactsConfig {
+            max_age_days: None,
+            max_sessions: Some(2),
+        };
+        let removed = prune_sessions(workspace.path(), &config, Some("current")).unwrap();
+        assert_eq!(removed, 2);
+
+        let sessions = workspace.path().join(SESSIONS_DIR);
+        assert!(sessions.join("current").exists());
+        assert!(sessions.join("c").exists());
+        assert!(!sessions.join("a").exists());
+    }
+}
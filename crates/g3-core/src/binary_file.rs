@@ -0,0 +1,265 @@
+//! Handling of binary and non-UTF-8 files in the file tools.
+//!
+//! Instead of failing with "stream did not contain valid UTF-8", `read_file` describes binary
+//! files (size, type from magic bytes, a hexdump of the start) and can return hex dumps of
+//! byte ranges with `read_binary`.
+
+use std::io::{Read, Seek, SeekFrom};
+
+/// Bytes shown in the hexdump when a binary file is read as text
+pub const PREVIEW_BYTES: usize = 256;
+
+/// Most bytes a single `read_binary` call returns
+pub const MAX_HEX_READ_BYTES: u64 = 4096;
+
+/// Like git, a NUL in the first this-many bytes marks a file as binary
+const SNIFF_BYTES: usize = 8000;
+
+/// Long enough to cover every signature in [`MAGIC`]
+const HEADER_BYTES: u64 = 512;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// (offset, signature, description)
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (0, b"\xff\xd8\xff", "JPEG image"),
+    (0, b"GIF87a", "GIF image"),
+    (0, b"GIF89a", "GIF image"),
+    (0, b"BM", "BMP image"),
+    (0, b"II*\x00", "TIFF image"),
+    (0, b"MM\x00*", "TIFF image"),
+    (0, b"\x00\x00\x01\x00", "ICO icon"),
+    (0, b"%PDF-", "PDF document"),
+    (0, b"PK\x03\x04", "ZIP archive (or jar/docx/xlsx)"),
+    (0, b"\x1f\x8b", "gzip compressed data"),
+    (0, b"BZh", "bzip2 compressed data"),
+    (0, b"\xfd7zXZ\x00", "xz compressed data"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd compressed data"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7-zip archive"),
+    (257, b"ustar", "tar archive"),
+    (0, b"\x7fELF", "ELF executable"),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O executable (64-bit)"),
+    (0, b"\xce\xfa\xed\xfe", "Mach-O executable (32-bit)"),
+    (
+        0,
+        b"\xca\xfe\xba\xbe",
+        "Mach-O universal binary or Java class file",
+    ),
+    (0, b"MZ", "Windows executable (PE)"),
+    (0, b"\x00asm", "WebAssembly module"),
+    (0, b"SQLite format 3\x00", "SQLite database"),
+    (0, b"ID3", "MP3 audio"),
+    (0, b"OggS", "Ogg media"),
+    (0, b"fLaC", "FLAC audio"),
+    (0, b"\x1aE\xdf\xa3", "Matroska/WebM video"),
+    (4, b"ftyp", "MP4/QuickTime media"),
+    (0, b"wOFF", "WOFF font"),
+    (0, b"wOF2", "WOFF2 font"),
+    (0, b"\x00\x01\x00\x00\x00", "TrueType font"),
+    (0, b"OTTO", "OpenType font"),
+];
+
+/// The file type identified by `bytes`' magic number, if known
+pub fn detect_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" {
+        return Some(match &bytes[8..12] {
+            b"WEBP" => "WebP image",
+            b"WAVE" => "WAV audio",
+            b"AVI " => "AVI video",
+            _ => "RIFF container",
+        });
+    }
+    MAGIC
+        .iter()
+        .find(|(offset, signature, _)| {
+            bytes.get(*offset..offset + signature.len()) == Some(signature)
+        })
+        .map(|(_, _, description)| *description)
+}
+
+/// Whether `bytes` should be treated as binary rather than (possibly mis-encoded) text.
+/// Short signatures like "BM" or "MZ" also start ordinary text, so a known magic number
+/// only counts when the content is not valid UTF-8.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_BYTES)].contains(&0)
+        || (std::str::from_utf8(bytes).is_err() && detect_type(bytes).is_some())
+}
+
+/// A `hexdump -C` style dump of `bytes`, numbering from `offset`
+pub fn hexdump(bytes: &[u8], offset: u64) -> String {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut hex = String::new();
+            for (j, byte) in chunk.iter().enumerate() {
+                if j == BYTES_PER_LINE / 2 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{:02x} ", byte));
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}  {:<49} |{}|",
+                offset + (i * BYTES_PER_LINE) as u64,
+                hex,
+                ascii
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What `read_file` returns for a binary file
+pub fn describe(path: &str, bytes: &[u8]) -> String {
+    let preview = &bytes[..bytes.len().min(PREVIEW_BYTES)];
+    format!(
+        "📦 Binary file '{}' ({}, {} bytes). First {} bytes:\n{}\n\nUse read_file with read_binary=true and start/end byte offsets to read other ranges as hex.",
+        path,
+        detect_type(bytes).unwrap_or("unknown type"),
+        bytes.len(),
+        preview.len(),
+        hexdump(preview, 0)
+    )
+}
+
+/// Hex dump of bytes `start..end` of the file at `path`, at most [`MAX_HEX_READ_BYTES`] long
+pub fn read_range(path: &str, start: Option<u64>, end: Option<u64>) -> Result<String, String> {
+    let mut file =
+        std::fs::File::open(path).map_err(|e| format!("Failed to read file '{}': {}", path, e))?;
+    let size = file
+        .metadata()
+        .map_err(|e| format!("Failed to read file '{}': {}", path, e))?
+        .len();
+
+    let start = start.unwrap_or(0);
+    let end = end
+        .unwrap_or(start.saturating_add(PREVIEW_BYTES as u64))
+        .min(size);
+    if start > size {
+        return Err(format!("Start offset {} exceeds file size {}", start, size));
+    }
+    if start > end {
+        return Err(format!(
+            "Start offset {} is greater than end offset {}",
+            start, end
+        ));
+    }
+    if end - start > MAX_HEX_READ_BYTES {
+        return Err(format!(
+            "Requested {} bytes; read at most {} bytes at a time",
+            end - start,
+            MAX_HEX_READ_BYTES
+        ));
+    }
+
+    // Enough of the start of the file to identify its type
+    let mut header = Vec::new();
+    let mut bytes = vec![0; (end - start) as usize];
+    file.by_ref()
+        .take(HEADER_BYTES)
+        .read_to_end(&mut header)
+        .and_then(|_| file.seek(SeekFrom::Start(start)))
+        .and_then(|_| file.read_exact(&mut bytes))
+        .map_err(|e| format!("Failed to read file '{}': {}", path, e))?;
+
+    Ok(format!(
+        "📦 Bytes {}-{} of '{}' ({}, {} bytes):\n{}",
+        start,
+        end,
+        path,
+        detect_type(&header).unwrap_or("unknown type"),
+        size,
+        hexdump(&bytes, start)
+    ))
+}
+
+/// Read a file to edit as text, explaining instead of failing opaquely when it is binary or
+/// not UTF-8
+pub fn read_text(path: &str) -> Result<String, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Failed to read file '{}': {}", path, e))?;
+    match String::from_utf8(bytes) {
+        Ok(content) => Ok(content),
+        Err(e) => {
+            let bytes = e.as_bytes();
+            let kind = if is_binary(bytes) {
+                detect_type(bytes).unwrap_or("binary file")
+            } else {
+                "text that is not valid UTF-8"
+            };
+            Err(format!(
+                "'{}' is {} ({} bytes) and cannot be edited as text",
+                path,
+                kind,
+                bytes.len()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_type() {
+        assert_eq!(
+            detect_type(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"),
+            Some("PNG image")
+        );
+        assert_eq!(
+            detect_type(b"RIFF\x00\x00\x00\x00WEBPVP8 "),
+            Some("WebP image")
+        );
+        assert_eq!(detect_type(b"\x7fELF\x02\x01\x01"), Some("ELF executable"));
+        assert_eq!(detect_type(b"fn main() {}"), None);
+        assert!(!is_binary(
+            b"MZ is a text file that happens to start like an executable"
+        ));
+
+        assert!(is_binary(b"\x00\x01\x02\x03"));
+        assert!(!is_binary("caf\u{e9}".as_bytes()));
+        // Latin-1 text is not UTF-8 but not binary either
+        assert!(!is_binary(b"caf\xe9"));
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dump = hexdump(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR\x00\x01", 0x20);
+        assert_eq!(
+            dump,
+            "00000020  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|\n\
+             00000030  00 01                                             |..|"
+        );
+    }
+
+    #[test]
+    fn test_read_range() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("image.png");
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(0..=255u8);
+        std::fs::write(&path, &png).unwrap();
+        let path = path.to_str().unwrap();
+
+        let dump = read_range(path, Some(16), Some(20)).unwrap();
+        assert!(dump.starts_with("📦 Bytes 16-20 of"));
+        assert!(dump.contains("PNG image, 264 bytes"));
+        assert!(dump.contains("00000010  08 09 0a 0b"));
+
+        assert!(read_range(path, Some(0), Some(10_000)).is_ok());
+        assert!(read_range(path, Some(300), None).is_err());
+        assert!(read_text(path).unwrap_err().contains("PNG image"));
+    }
+}
@@ -0,0 +1,280 @@
+//! Tool call budgets (`[budgets]`).
+//!
+//! Every tool call is counted against the limits of its tool, of its group (`file_writes` or
+//! `webdriver`) and of `all`, before it runs. A call that would go over a limit isn't run
+//! and isn't counted; the model gets an error naming the budget and when it frees up, so a
+//! model stuck in a loop is stopped without hammering the machine. Turn counts start again
+//! with each turn and session counts last as long as the agent.
+
+use crate::undo::FILE_TOOLS;
+use g3_config::ToolBudget;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Budget every tool call counts against
+pub const ALL: &str = "all";
+/// Budget of the tools that write files
+pub const FILE_WRITES: &str = "file_writes";
+/// Budget of the browser automation tools
+pub const WEBDRIVER: &str = "webdriver";
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// The budgets `tool` counts against
+fn budgets_of(tool: &str) -> Vec<&str> {
+    let mut budgets = vec![tool, ALL];
+    if FILE_TOOLS.contains(&tool) {
+        budgets.push(FILE_WRITES);
+    }
+    if tool.starts_with("webdriver_") {
+        budgets.push(WEBDRIVER);
+    }
+    budgets
+}
+
+/// Calls counted against one budget
+#[derive(Debug, Clone, Default)]
+struct Count {
+    turn: u32,
+    session: u32,
+    /// When the calls of the last minute were made, oldest first
+    recent: VecDeque<Instant>,
+}
+
+/// The period a budget is counted over
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Turn,
+    Session,
+    /// With the time until the oldest call of the minute drops out
+    Minute(Duration),
+}
+
+/// A budget that a tool call would go over
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    pub budget: String,
+    pub limit: u32,
+    pub period: Period,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let calls = if self.budget == ALL {
+            "Tool calls".to_string()
+        } else {
+            format!("Calls of {}", self.budget)
+        };
+        match self.period {
+            Period::Turn => write!(
+                f,
+                "Budget exceeded: {} are limited to {} per turn. Don't retry; finish the turn \
+                 with what you have and tell the user what is left.",
+                calls, self.limit
+            ),
+            Period::Session => write!(
+                f,
+                "Budget exceeded: {} are limited to {} per session. Don't retry; tell the user \
+                 the budget is used up.",
+                calls, self.limit
+            ),
+            Period::Minute(wait) => write!(
+                f,
+                "Budget exceeded: {} are limited to {} per minute. The next one is allowed in \
+                 {}s.",
+                calls,
+                self.limit,
+                wait.as_secs().max(1)
+            ),
+        }
+    }
+}
+
+/// The limits of `[budgets]` and the calls counted against them
+#[derive(Debug, Clone, Default)]
+pub struct ToolBudgets {
+    limits: HashMap<String, ToolBudget>,
+    counts: HashMap<String, Count>,
+}
+
+impl ToolBudgets {
+    pub fn new(limits: &HashMap<String, ToolBudget>) -> Self {
+        Self {
+            limits: limits.clone(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Start counting a new turn
+    pub fn start_turn(&mut self) {
+        for count in self.counts.values_mut() {
+            count.turn = 0;
+        }
+    }
+
+    /// Say which budget a call of `tool` made at `now` would go over, without counting it
+    pub fn check(&self, tool: &str, now: Instant) -> Result<(), BudgetExceeded> {
+        if self.limits.is_empty() {
+            return Ok(());
+        }
+        self.clone().record(tool, now)
+    }
+
+    /// Count a call of `tool` made at `now`, or say which budget it would go over
+    pub fn record(&mut self, tool: &str, now: Instant) -> Result<(), BudgetExceeded> {
+        if self.limits.is_empty() {
+            return Ok(());
+        }
+        let budgets: Vec<&str> = budgets_of(tool)
+            .into_iter()
+            .filter(|budget| self.limits.contains_key(*budget))
+            .collect();
+        for budget in &budgets {
+            let limit = &self.limits[*budget];
+            let count = self.counts.entry(budget.to_string()).or_default();
+            while count
+                .recent
+                .front()
+                .is_some_and(|made| now.duration_since(*made) >= MINUTE)
+            {
+                count.recent.pop_front();
+            }
+            let exceeded = |limit: u32, period: Period| BudgetExceeded {
+                budget: budget.to_string(),
+                limit,
+                period,
+            };
+            if let Some(limit) = limit.per_turn.filter(|limit| count.turn >= *limit) {
+                return Err(exceeded(limit, Period::Turn));
+            }
+            if let Some(limit) = limit.per_session.filter(|limit| count.session >= *limit) {
+                return Err(exceeded(limit, Period::Session));
+            }
+            if let Some(limit) = limit.per_minute {
+                if count.recent.len() >= limit as usize {
+                    let wait = match count.recent.front() {
+                        Some(oldest) => MINUTE.saturating_sub(now.duration_since(*oldest)),
+                        None => MINUTE,
+                    };
+                    return Err(exceeded(limit, Period::Minute(wait)));
+                }
+            }
+        }
+        for budget in budgets {
+            let count = self.counts.entry(budget.to_string()).or_default();
+            count.turn += 1;
+            count.session += 1;
+            count.recent.push_back(now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budgets(entries: &[(&str, ToolBudget)]) -> ToolBudgets {
+        let limits = entries
+            .iter()
+            .map(|(name, budget)| (name.to_string(), budget.clone()))
+            .collect();
+        ToolBudgets::new(&limits)
+    }
+
+    #[test]
+    fn test_turn_and_session_budgets() {
+        let mut budgets = budgets(&[
+            (
+                "shell",
+                ToolBudget {
+                    per_turn: Some(2),
+                    ..Default::default()
+                },
+            ),
+            (
+                FILE_WRITES,
+                ToolBudget {
+                    per_session: Some(1),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let now = Instant::now();
+        assert!(budgets.record("shell", now).is_ok());
+        assert!(budgets.record("shell", now).is_ok());
+        // Checking doesn't count the call
+        assert!(budgets.check("shell", now).is_err());
+        assert!(budgets.check("write_file", now).is_ok());
+        assert!(budgets.check("write_file", now).is_ok());
+        let exceeded = budgets.record("shell", now).unwrap_err();
+        assert_eq!(exceeded.period, Period::Turn);
+        assert!(exceeded
+            .to_string()
+            .contains("Calls of shell are limited to 2 per turn"));
+        budgets.start_turn();
+        assert!(budgets.record("shell", now).is_ok());
+
+        assert!(budgets.record("str_replace", now).is_ok());
+        budgets.start_turn();
+        let exceeded = budgets.record("write_file", now).unwrap_err();
+        assert_eq!(exceeded.budget, FILE_WRITES);
+        assert_eq!(exceeded.period, Period::Session);
+        assert!(budgets.record("read_file", now).is_ok());
+    }
+
+    #[test]
+    fn test_minute_budget() {
+        let mut budgets = budgets(&[(
+            WEBDRIVER,
+            ToolBudget {
+                per_minute: Some(2),
+                ..Default::default()
+            },
+        )]);
+        let start = Instant::now();
+        assert!(budgets.record("webdriver_click", start).is_ok());
+        assert!(budgets
+            .record("webdriver_navigate", start + Duration::from_secs(20))
+            .is_ok());
+        let exceeded = budgets
+            .record("webdriver_click", start + Duration::from_secs(30))
+            .unwrap_err();
+        assert_eq!(exceeded.period, Period::Minute(Duration::from_secs(30)));
+        assert!(exceeded.to_string().contains("allowed in 30s"));
+        assert!(budgets
+            .record("webdriver_click", start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_refused_calls_are_not_counted() {
+        let mut budgets = budgets(&[
+            (
+                ALL,
+                ToolBudget {
+                    per_turn: Some(3),
+                    ..Default::default()
+                },
+            ),
+            (
+                "shell",
+                ToolBudget {
+                    per_turn: Some(1),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let now = Instant::now();
+        assert!(budgets.record("shell", now).is_ok());
+        assert!(budgets.record("shell", now).is_err());
+        assert!(budgets.record("read_file", now).is_ok());
+        assert!(budgets.record("read_file", now).is_ok());
+        let exceeded = budgets.record("read_file", now).unwrap_err();
+        assert_eq!(exceeded.budget, ALL);
+        assert!(exceeded
+            .to_string()
+            .starts_with("Budget exceeded: Tool calls are limited"));
+    }
+}
@@ -0,0 +1,351 @@
+//! Commit message, PR description and changelog generation for a session's changes.
+//!
+//! When a session starts in a git repository its starting point is recorded in
+//! `.g3/sessions/<session id>/baseline.json`: a commit capturing the working tree (including
+//! uncommitted changes) and the untracked files present at the time. `g3 summarize-changes`
+//! diffs the working tree against that baseline and asks the model to describe the changes.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+use tracing::debug;
+
+use crate::artifacts::SESSIONS_DIR;
+
+const BASELINE_FILE: &str = "baseline.json";
+
+/// Diff text sent to the model is cut off after this many characters
+const MAX_DIFF_CHARS: usize = 60_000;
+
+/// New untracked files are included in full up to this size
+const MAX_NEW_FILE_CHARS: usize = 4_000;
+
+const COMMIT_MESSAGE_HEADING: &str = "## Commit Message";
+const PR_DESCRIPTION_HEADING: &str = "## PR Description";
+const CHANGELOG_HEADING: &str = "## Changelog Entry";
+
+/// The state of the workspace when a session started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Commit capturing the working tree; HEAD if the tree was clean
+    pub rev: String,
+    /// Untracked files present at the start, which are not part of `rev`
+    #[serde(default)]
+    pub untracked: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// Session the baseline belongs to, filled in when it is loaded
+    #[serde(skip)]
+    pub session_id: Option<String>,
+}
+
+/// Changes in the workspace since a baseline
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    /// `git diff --stat` output
+    pub stat: String,
+    pub diff: String,
+    /// Files created since the baseline that git does not track yet
+    pub new_files: Vec<String>,
+    /// The files the agent changed itself, one per line, when known
+    pub agent_files: Option<String>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.diff.trim().is_empty() && self.new_files.is_empty()
+    }
+}
+
+/// Generated descriptions of a set of changes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeSummary {
+    pub commit_message: String,
+    pub pr_description: String,
+    pub changelog_entry: String,
+}
+
+fn git(workspace: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(workspace)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn untracked_files(workspace: &Path) -> Result<Vec<String>> {
+    Ok(
+        git(workspace, &["ls-files", "--others", "--exclude-standard"])?
+            .lines()
+            .filter(|line| !line.starts_with(".g3/"))
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Record the current state of `workspace` as the baseline of `session_id`. Returns `None`
+/// if the workspace is not a git repository with at least one commit.
+pub fn record_baseline(workspace: &Path, session_id: &str) -> Result<Option<Baseline>> {
+    if git(workspace, &["rev-parse", "--verify", "HEAD"]).is_err() {
+        debug!("Not recording a change baseline: no git history in workspace");
+        return Ok(None);
+    }
+
+    // `stash create` commits the working tree without touching it; it prints nothing when
+    // there are no local changes
+    let stash = git(workspace, &["stash", "create"])?;
+    let rev = match stash.trim() {
+        "" => git(workspace, &["rev-parse", "HEAD"])?.trim().to_string(),
+        rev => rev.to_string(),
+    };
+    let baseline = Baseline {
+        rev,
+        untracked: untracked_files(workspace)?,
+        created_at: Utc::now(),
+        session_id: Some(session_id.to_string()),
+    };
+
+    let dir = workspace.join(SESSIONS_DIR).join(session_id);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    std::fs::write(
+        dir.join(BASELINE_FILE),
+        serde_json::to_string_pretty(&baseline)?,
+    )?;
+    Ok(Some(baseline))
+}
+
+/// The baseline recorded for `session_id`, if any
+pub fn load_baseline(workspace: &Path, session_id: &str) -> Result<Option<Baseline>> {
+    let path = workspace
+        .join(SESSIONS_DIR)
+        .join(session_id)
+        .join(BASELINE_FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut baseline: Baseline = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid baseline in {}", path.display()))?;
+    baseline.session_id = Some(session_id.to_string());
+    Ok(Some(baseline))
+}
+
+/// The most recently recorded baseline in `workspace`
+pub fn latest_baseline(workspace: &Path) -> Result<Option<Baseline>> {
+    let entries = match std::fs::read_dir(workspace.join(SESSIONS_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut latest: Option<Baseline> = None;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let session_id = entry.file_name().to_string_lossy().into_owned();
+        if let Some(baseline) = load_baseline(workspace, &session_id)? {
+            if latest
+                .as_ref()
+                .is_none_or(|latest| baseline.created_at > latest.created_at)
+            {
+                latest = Some(baseline);
+            }
+        }
+    }
+    Ok(latest)
+}
+
+/// A baseline for an arbitrary revision, e.g. from `--since`
+pub fn baseline_for_rev(workspace: &Path, rev: &str) -> Result<Baseline> {
+    let resolved = git(workspace, &["rev-parse", "--verify", rev])
+        .with_context(|| format!("Unknown revision '{}'", rev))?;
+    Ok(Baseline {
+        rev: resolved.trim().to_string(),
+        untracked: Vec::new(),
+        created_at: Utc::now(),
+        session_id: None,
+    })
+}
+
+/// Changes in the working tree of `workspace` since `baseline`
+pub fn collect_changes(workspace: &Path, baseline: &Baseline) -> Result<Changes> {
+    let rev = baseline.rev.as_str();
+    let stat = git(workspace, &["diff", "--stat", rev, "--"])
+        .with_context(|| format!("Baseline {} is no longer available", rev))?;
+    let diff = git(workspace, &["diff", rev, "--"])?;
+
+    let existing: HashSet<&String> = baseline.untracked.iter().collect();
+    let new_files = untracked_files(workspace)?
+        .into_iter()
+        .filter(|file| !existing.contains(file))
+        .collect();
+
+    Ok(Changes {
+        stat,
+        diff,
+        new_files,
+        agent_files: None,
+    })
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!(
+            "{}\n[... truncated, {} more characters]",
+            &text[..end],
+            text[end..].chars().count()
+        ),
+        None => text.to_string(),
+    }
+}
+
+/// The request for the model, describing `changes` in `workspace`
+pub fn build_prompt(workspace: &Path, changes: &Changes) -> String {
+    let mut prompt = format!(
+        "Below are the changes made to a code base. Write:\n\
+        1. A git commit message: a summary line of at most 72 characters, a blank line, then a short body explaining what changed and why.\n\
+        2. A pull request description in Markdown: what the change does and why, the notable changes, and how to test it.\n\
+        3. A CHANGELOG entry in Keep a Changelog style, grouped under Added/Changed/Fixed/Removed headings as appropriate.\n\n\
+        Describe only what is in the diff. Reply with exactly these three sections and nothing else:\n\n\
+        {}\n<commit message>\n\n{}\n<pr description>\n\n{}\n<changelog entry>\n\n\
+        # Diff summary\n\n{}\n# Diff\n\n```diff\n{}\n```\n",
+        COMMIT_MESSAGE_HEADING,
+        PR_DESCRIPTION_HEADING,
+        CHANGELOG_HEADING,
+        changes.stat.trim_end(),
+        truncate(changes.diff.trim_end(), MAX_DIFF_CHARS)
+    );
+
+    if !changes.new_files.is_empty() {
+        prompt.push_str("\n# New files\n");
+        for file in &changes.new_files {
+            let contents = std::fs::read_to_string(workspace.join(file))
+                .map(|contents| truncate(&contents, MAX_NEW_FILE_CHARS))
+                .unwrap_or_else(|_| "[binary or unreadable]".to_string());
+            prompt.push_str(&format!("\n## {}\n\n```\n{}\n```\n", file, contents));
+        }
+    }
+
+    if let Some(files) = &changes.agent_files {
+        prompt.push_str(&format!(
+            "\n# Files changed by the agent\n\n(+ created, ~ modified, - deleted; any other changes in the diff were made outside the agent's session)\n\n{}\n",
+            files
+        ));
+    }
+    prompt
+}
+
+/// Split the model's reply into its three sections
+pub fn parse_summary(response: &str) -> Result<ChangeSummary> {
+    let headings = [
+        COMMIT_MESSAGE_HEADING,
+        PR_DESCRIPTION_HEADING,
+        CHANGELOG_HEADING,
+    ];
+    let mut starts = Vec::new();
+    for heading in headings {
+        let start = response
+            .find(heading)
+            .ok_or_else(|| anyhow!("Response is missing the '{}' section", heading))?;
+        starts.push(start);
+    }
+
+    let section = |heading: &str| {
+        let start = response.find(heading).unwrap() + heading.len();
+        let end = starts
+            .iter()
+            .copied()
+            .filter(|next| *next > start)
+            .min()
+            .unwrap_or(response.len());
+        strip_fence(response[start..end].trim()).to_string()
+    };
+
+    Ok(ChangeSummary {
+        commit_message: section(COMMIT_MESSAGE_HEADING),
+        pr_description: section(PR_DESCRIPTION_HEADING),
+        changelog_entry: section(CHANGELOG_HEADING),
+    })
+}
+
+/// Models sometimes wrap a section in a code fence
+fn strip_fence(text: &str) -> &str {
+    let Some(inner) = text.strip_prefix("```") else {
+        return text;
+    };
+    let Some(inner) = inner.strip_suffix("```") else {
+        return text;
+    };
+    // Drop the language tag on the opening line
+    match inner.split_once('\n') {
+        Some((_, body)) => body.trim(),
+        None => inner.trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=g3", "-c", "user.email=g3@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_changes_since_baseline() {
+        let workspace = TempDir::new().unwrap();
+        let dir = workspace.path();
+        run_git(dir, &["init", "-q"]);
+        std::fs::write(dir.join("lib.rs"), "fn a() {}\n").unwrap();
+        run_git(dir, &["add", "."]);
+        run_git(dir, &["commit", "-q", "-m", "initial"]);
+
+        // Uncommitted and untracked work from before the session is part of the baseline
+        std::fs::write(dir.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "todo").unwrap();
+        record_baseline(dir, "session_1").unwrap().unwrap();
+
+        std::fs::write(dir.join("lib.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+        std::fs::write(dir.join("new.rs"), "fn d() {}\n").unwrap();
+
+        let baseline = latest_baseline(dir).unwrap().unwrap();
+        assert_eq!(baseline.session_id.as_deref(), Some("session_1"));
+        let changes = collect_changes(dir, &baseline).unwrap();
+        assert!(changes.diff.contains("+fn c() {}"));
+        assert!(!changes.diff.contains("+fn b() {}"));
+        assert_eq!(changes.new_files, vec!["new.rs".to_string()]);
+
+        let prompt = build_prompt(dir, &changes);
+        assert!(prompt.contains("## new.rs"));
+        assert!(prompt.contains("fn d() {}"));
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let response = "## Commit Message\n\nAdd c()\n\nNeeded by d().\n\n\
+            ## PR Description\n```markdown\nAdds `c()`.\n```\n\n\
+            ## Changelog Entry\n### Added\n- `c()`\n";
+        let summary = parse_summary(response).unwrap();
+        assert_eq!(summary.commit_message, "Add c()\n\nNeeded by d().");
+        assert_eq!(summary.pr_description, "Adds `c()`.");
+        assert_eq!(summary.changelog_entry, "### Added\n- `c()`");
+
+        assert!(parse_summary("## Commit Message\nFix").is_err());
+    }
+}
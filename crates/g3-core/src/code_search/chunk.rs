@@ -0,0 +1,184 @@
+//! Syntax-aware ranges for partial reads of source files.
+//!
+//! A line range that starts or ends in the middle of a function or type is moved to the
+//! nearest symbol boundary, so read_file returns whole definitions and can tell the model
+//! which symbols it got and where to continue.
+
+use super::outline::Symbol;
+
+/// How far a range boundary may move to avoid cutting a symbol in two
+pub const MAX_SNAP_LINES: usize = 150;
+
+/// A line range adjusted to symbol boundaries, with the symbols it overlaps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk<'a> {
+    /// 1-indexed, inclusive
+    pub start_line: usize,
+    /// 1-indexed, inclusive
+    pub end_line: usize,
+    /// Symbols overlapping the range, and whether each is entirely inside it
+    pub symbols: Vec<(&'a Symbol, bool)>,
+}
+
+/// Adjust `start_line..=end_line` of a file with `lines` so it doesn't cut through symbols.
+/// The start moves back to the beginning of the innermost symbol it falls inside, including
+/// the doc comments and attributes above it. The end moves forward to the end of the
+/// innermost symbol it falls inside, or back to before that symbol when it is too long to
+/// include, so the next read can start there. Boundaries that would move more than
+/// [`MAX_SNAP_LINES`] are left alone.
+pub fn snap_range<'a>(
+    symbols: &'a [Symbol],
+    lines: &[&str],
+    start_line: usize,
+    end_line: usize,
+) -> Chunk<'a> {
+    let mut start = start_line;
+    if let Some(symbol) = innermost(symbols, |s| s.start_line < start && start <= s.end_line) {
+        if start - symbol.start_line <= MAX_SNAP_LINES {
+            start = symbol.start_line;
+        }
+    }
+    if symbols.iter().any(|s| s.start_line == start) {
+        start = with_leading_comments(lines, start);
+    }
+
+    let mut end = end_line.max(start);
+    if let Some(symbol) = innermost(symbols, |s| s.start_line <= end && end < s.end_line) {
+        let before = with_leading_comments(lines, symbol.start_line).saturating_sub(1);
+        if symbol.end_line - end <= MAX_SNAP_LINES {
+            end = symbol.end_line;
+        } else if before >= start {
+            end = before;
+        }
+    }
+
+    let symbols = symbols
+        .iter()
+        .filter(|s| s.start_line <= end && s.end_line >= start)
+        .map(|s| (s, start <= s.start_line && s.end_line <= end))
+        .collect();
+    Chunk {
+        start_line: start,
+        end_line: end,
+        symbols,
+    }
+}
+
+/// The deepest symbol matching `pred`, unless it contains other symbols: a line inside
+/// an impl or class but outside its methods is already a good place to cut
+fn innermost(symbols: &[Symbol], pred: impl Fn(&Symbol) -> bool) -> Option<&Symbol> {
+    let symbol = symbols.iter().filter(|s| pred(s)).max_by_key(|s| s.depth)?;
+    let has_children = symbols.iter().any(|s| {
+        s.depth > symbol.depth
+            && s.start_line >= symbol.start_line
+            && s.end_line <= symbol.end_line
+    });
+    (!has_children).then_some(symbol)
+}
+
+/// The first line of the doc comments, comments, attributes and decorators directly
+/// above `line`
+fn with_leading_comments(lines: &[&str], line: usize) -> usize {
+    let mut first = line;
+    while first > 1 {
+        let above = lines.get(first - 2).map(|l| l.trim_start()).unwrap_or("");
+        let is_preamble = ["//", "/*", "*", "#[", "#!", "@"]
+            .iter()
+            .any(|prefix| above.starts_with(prefix))
+            || (above.starts_with('#') && !above.starts_with("#include"));
+        if !is_preamble {
+            break;
+        }
+        first -= 1;
+    }
+    first
+}
+
+/// Describe the symbols in `chunk` and where to continue reading
+pub fn format_chunk(chunk: &Chunk, total_lines: usize) -> String {
+    let mut out = String::from("🧩 Symbols in range:");
+    if chunk.symbols.is_empty() {
+        out.push_str(" none");
+    }
+    for (symbol, complete) in &chunk.symbols {
+        out.push_str(&format!(
+            "\n{}{} {} (lines {}-{}){}",
+            "  ".repeat(symbol.depth),
+            symbol.kind,
+            symbol.name,
+            symbol.start_line,
+            symbol.end_line,
+            if *complete { "" } else { " [partial]" }
+        ));
+    }
+    if chunk.end_line < total_lines {
+        out.push_str(&format!(
+            "\nContinue with start_line={}",
+            chunk.end_line + 1
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_search::outline::outline_source;
+    use std::path::Path;
+
+    const SOURCE: &str = "use std::fmt;
+
+/// A point
+#[derive(Debug)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+impl Point {
+    fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// Sum of the coordinates
+    fn sum(&self) -> i32 {
+        self.x + self.y
+    }
+}
+";
+
+    #[test]
+    fn test_snap_range() {
+        let symbols = outline_source(Path::new("point.rs"), SOURCE)
+            .unwrap()
+            .unwrap();
+        let lines: Vec<&str> = SOURCE.lines().collect();
+
+        // Starting inside `struct Point` and ending inside `fn new` takes both whole
+        let chunk = snap_range(&symbols, &lines, 6, 12);
+        assert_eq!((chunk.start_line, chunk.end_line), (3, 13));
+        let names: Vec<(&str, bool)> = chunk
+            .symbols
+            .iter()
+            .map(|(s, complete)| (s.name.as_str(), *complete))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("Point", true), ("Point", false), ("new", true)]
+        );
+
+        let formatted = format_chunk(&chunk, lines.len());
+        assert!(formatted.contains("impl Point (lines 10-19) [partial]"));
+        assert!(formatted.contains("  fn new (lines 11-13)\n"));
+        assert!(formatted.ends_with("Continue with start_line=14"));
+
+        // Cutting between the methods of an impl is fine
+        let chunk = snap_range(&symbols, &lines, 14, 17);
+        assert_eq!((chunk.start_line, chunk.end_line), (14, 18));
+
+        // Ranges between symbols are left alone
+        let chunk = snap_range(&symbols, &lines, 1, 2);
+        assert_eq!((chunk.start_line, chunk.end_line), (1, 2));
+        assert!(chunk.symbols.is_empty());
+    }
+}
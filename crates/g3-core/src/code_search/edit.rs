@@ -0,0 +1,184 @@
+//! Structural edits located via tree-sitter, used by the edit_symbol tool.
+
+use super::outline::{language_for_path, symbol_for_node, Symbol};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use tree_sitter::{Node, Parser, Tree};
+
+/// Result of a successful symbol replacement
+#[derive(Debug, Clone)]
+pub struct SymbolEdit {
+    /// The new file contents
+    pub content: String,
+    /// The symbol that was replaced, with its original line range
+    pub replaced: Symbol,
+    /// Number of lines the replacement occupies in the new file
+    pub new_line_count: usize,
+}
+
+/// Replace a named definition in `source`.
+///
+/// `symbol` is a name such as `new`, or a path through enclosing definitions such as
+/// `Point::new` or `Point.new`. `kind` optionally restricts matches to an outline kind
+/// ("fn", "class", "impl", ...). When `body_only` is set only the definition's body is
+/// replaced (including braces for brace-delimited languages) and the signature is kept.
+pub fn replace_symbol(
+    path: &Path,
+    source: &str,
+    symbol: &str,
+    kind: Option<&str>,
+    new_code: &str,
+    body_only: bool,
+) -> Result<SymbolEdit> {
+    let (language_name, language) = language_for_path(path).ok_or_else(|| {
+        anyhow!("edit_symbol does not support this file type: {}", path.display())
+    })?;
+
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language)
+        .map_err(|e| anyhow!("Failed to set {} language: {}", language_name, e))?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| anyhow!("Failed to parse {}", path.display()))?;
+
+    let wanted: Vec<&str> = symbol
+        .split("::")
+        .flat_map(|part| part.split('.'))
+        .filter(|part| !part.is_empty())
+        .collect();
+    if wanted.is_empty() {
+        return Err(anyhow!("Symbol name is empty"));
+    }
+
+    let mut candidates = Vec::new();
+    find_symbols(
+        tree.root_node(),
+        source,
+        language_name,
+        &mut Vec::new(),
+        &wanted,
+        kind,
+        &mut candidates,
+    );
+
+    let (node, found) = match candidates.len() {
+        0 => return Err(anyhow!("Symbol '{}' not found in {}", symbol, path.display())),
+        1 => candidates.remove(0),
+        _ => {
+            let locations = candidates
+                .iter()
+                .map(|(_, s)| {
+                    format!("{} {} (lines {}-{})", s.kind, s.name, s.start_line, s.end_line)
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(anyhow!(
+                "Symbol '{}' is ambiguous ({} matches: {}). Qualify it with its parent (e.g. Parent::name) or pass a kind.",
+                symbol,
+                candidates.len(),
+                locations
+            ));
+        }
+    };
+
+    let target = if body_only {
+        node.child_by_field_name("body")
+            .ok_or_else(|| anyhow!("{} '{}' has no body to replace", found.kind, found.name))?
+    } else {
+        node
+    };
+
+    // The target starts mid-line, after its indentation, so drop any leading
+    // indentation the model repeated on the first line of the replacement
+    let replacement = new_code.trim_start_matches([' ', '\t']).trim_end_matches('\n');
+    let range = target.byte_range();
+    let mut content = String::with_capacity(source.len() + replacement.len());
+    content.push_str(&source[..range.start]);
+    content.push_str(replacement);
+    content.push_str(&source[range.end..]);
+
+    // Refuse edits that break a file that parsed cleanly before
+    if !tree.root_node().has_error() {
+        let new_tree = parser
+            .parse(&content, None)
+            .ok_or_else(|| anyhow!("Failed to re-parse {} after edit", path.display()))?;
+        if let Some(line) = first_error_line(&new_tree) {
+            return Err(anyhow!(
+                "Replacement for '{}' introduces a syntax error near line {}; file left unchanged",
+                symbol,
+                line
+            ));
+        }
+    }
+
+    let new_end = node.end_byte() - range.len() + replacement.len();
+    let new_line_count = content[node.start_byte()..new_end].lines().count().max(1);
+
+    Ok(SymbolEdit {
+        content,
+        replaced: found,
+        new_line_count,
+    })
+}
+
+fn find_symbols<'a>(
+    node: Node<'a>,
+    source: &str,
+    language: &str,
+    path: &mut Vec<String>,
+    wanted: &[&str],
+    kind: Option<&str>,
+    out: &mut Vec<(Node<'a>, Symbol)>,
+) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match symbol_for_node(child, source, language) {
+            Some((child_kind, name)) => {
+                path.push(name.clone());
+                if matches_path(path, wanted) && kind.is_none_or(|k| k == child_kind) {
+                    out.push((
+                        child,
+                        Symbol {
+                            kind: child_kind.to_string(),
+                            name,
+                            start_line: child.start_position().row + 1,
+                            end_line: child.end_position().row + 1,
+                            depth: path.len() - 1,
+                        },
+                    ));
+                }
+                find_symbols(child, source, language, path, wanted, kind, out);
+                path.pop();
+            }
+            None => find_symbols(child, source, language, path, wanted, kind, out),
+        }
+    }
+}
+
+/// A symbol matches when its name equals the last component and the preceding components
+/// match its enclosing definitions (impl blocks match on their type name)
+fn matches_path(path: &[String], wanted: &[&str]) -> bool {
+    if wanted.len() > path.len() {
+        return false;
+    }
+    let tail = &path[path.len() - wanted.len()..];
+    tail.iter()
+        .zip(wanted)
+        .all(|(have, want)| have == want || have.rsplit(" for ").next() == Some(*want))
+}
+
+fn first_error_line(tree: &Tree) -> Option<usize> {
+    fn walk(node: Node) -> Option<usize> {
+        if node.is_error() || node.is_missing() {
+            return Some(node.start_position().row + 1);
+        }
+        if !node.has_error() {
+            return None;
+        }
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        children.into_iter().find_map(walk)
+    }
+    walk(tree.root_node())
+}
@@ -1,5 +1,6 @@
 //! Code search functionality using tree-sitter for syntax-aware searches
 
+use crate::g3ignore::G3Ignore;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -7,6 +8,11 @@ use std::collections::HashMap;
 mod searcher;
 pub use searcher::TreeSitterSearcher;
 
+pub mod chunk;
+pub mod edit;
+pub mod outline;
+pub mod semantic;
+
 /// Request for batch code searches
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodeSearchRequest {
@@ -76,6 +82,14 @@ pub struct Match {
 
 /// Main entry point for code search
 pub async fn execute_code_search(request: CodeSearchRequest) -> Result<CodeSearchResponse> {
-    let mut searcher = TreeSitterSearcher::new()?;
+    execute_code_search_with_ignore(request, G3Ignore::default()).await
+}
+
+/// Like [`execute_code_search`], skipping files excluded by `.g3ignore`
+pub async fn execute_code_search_with_ignore(
+    request: CodeSearchRequest,
+    ignore: G3Ignore,
+) -> Result<CodeSearchResponse> {
+    let mut searcher = TreeSitterSearcher::new()?.with_ignore(ignore);
     searcher.execute_search(request).await
 }
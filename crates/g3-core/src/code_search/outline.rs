@@ -0,0 +1,173 @@
+//! Symbol outlines for source files, used by read_file to give the model a map of
+//! a file (functions, types, impl blocks, ...) with line ranges.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser};
+
+/// A named definition in a source file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Symbol {
+    /// Short kind label, e.g. "fn", "struct", "class"
+    pub kind: String,
+    pub name: String,
+    /// 1-indexed, inclusive
+    pub start_line: usize,
+    /// 1-indexed, inclusive
+    pub end_line: usize,
+    /// Nesting depth (0 for top-level definitions)
+    pub depth: usize,
+}
+
+/// Map a file extension to the language name used by code_search and its grammar
+pub fn language_for_path(path: &Path) -> Option<(&'static str, Language)> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    let result: (&'static str, Language) = match ext.as_str() {
+        "rs" => ("rust", tree_sitter_rust::LANGUAGE.into()),
+        "py" => ("python", tree_sitter_python::LANGUAGE.into()),
+        "js" | "jsx" | "mjs" => ("javascript", tree_sitter_javascript::LANGUAGE.into()),
+        "ts" => ("typescript", tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into()),
+        "tsx" => ("typescript", tree_sitter_typescript::LANGUAGE_TSX.into()),
+        "go" => ("go", tree_sitter_go::LANGUAGE.into()),
+        "java" => ("java", tree_sitter_java::LANGUAGE.into()),
+        "c" | "h" => ("c", tree_sitter_c::LANGUAGE.into()),
+        "cpp" | "cc" | "cxx" | "hpp" | "hxx" => ("cpp", tree_sitter_cpp::LANGUAGE.into()),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Parse `source` with the grammar for `path` and return its symbol outline.
+/// Returns `Ok(None)` for files in languages without outline support.
+pub fn outline_source(path: &Path, source: &str) -> Result<Option<Vec<Symbol>>> {
+    let Some((language_name, language)) = language_for_path(path) else {
+        return Ok(None);
+    };
+
+    let mut parser = Parser::new();
+    parser
+        .set_language(&language)
+        .map_err(|e| anyhow!("Failed to set {} language: {}", language_name, e))?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| anyhow!("Failed to parse {}", path.display()))?;
+
+    let mut symbols = Vec::new();
+    collect_symbols(tree.root_node(), source, language_name, 0, &mut symbols);
+    Ok(Some(symbols))
+}
+
+/// Render an outline as an indented list, one symbol per line
+pub fn format_outline(symbols: &[Symbol]) -> String {
+    symbols
+        .iter()
+        .map(|s| {
+            format!(
+                "{}{} {} (lines {}-{})",
+                "  ".repeat(s.depth),
+                s.kind,
+                s.name,
+                s.start_line,
+                s.end_line
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn collect_symbols(
+    node: Node,
+    source: &str,
+    language: &str,
+    depth: usize,
+    symbols: &mut Vec<Symbol>,
+) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match symbol_for_node(child, source, language) {
+            Some((kind, name)) => {
+                symbols.push(Symbol {
+                    kind: kind.to_string(),
+                    name,
+                    start_line: child.start_position().row + 1,
+                    end_line: child.end_position().row + 1,
+                    depth,
+                });
+                collect_symbols(child, source, language, depth + 1, symbols);
+            }
+            None => collect_symbols(child, source, language, depth, symbols),
+        }
+    }
+}
+
+/// Returns the outline kind label and name for nodes that define a symbol
+pub(crate) fn symbol_for_node(node: Node, source: &str, language: &str) -> Option<(&'static str, String)> {
+    let kind = match (language, node.kind()) {
+        ("rust", "function_item") => "fn",
+        ("rust", "struct_item") => "struct",
+        ("rust", "enum_item") => "enum",
+        ("rust", "union_item") => "union",
+        ("rust", "trait_item") => "trait",
+        ("rust", "impl_item") => "impl",
+        ("rust", "mod_item") => "mod",
+        ("rust", "macro_definition") => "macro",
+        ("python", "function_definition") => "def",
+        ("python", "class_definition") => "class",
+        ("javascript" | "typescript", "function_declaration" | "generator_function_declaration") => {
+            "function"
+        }
+        ("javascript" | "typescript", "class_declaration") => "class",
+        ("javascript" | "typescript", "method_definition") => "method",
+        ("typescript", "interface_declaration") => "interface",
+        ("typescript", "enum_declaration") => "enum",
+        ("go", "function_declaration") => "func",
+        ("go", "method_declaration") => "method",
+        ("go", "type_spec") => "type",
+        ("java", "class_declaration") => "class",
+        ("java", "interface_declaration") => "interface",
+        ("java", "enum_declaration") => "enum",
+        ("java", "record_declaration") => "record",
+        ("java", "method_declaration") => "method",
+        ("java", "constructor_declaration") => "constructor",
+        ("c" | "cpp", "function_definition") => "fn",
+        ("c" | "cpp", "struct_specifier") if node.child_by_field_name("body").is_some() => "struct",
+        ("c" | "cpp", "enum_specifier") if node.child_by_field_name("body").is_some() => "enum",
+        ("cpp", "class_specifier") if node.child_by_field_name("body").is_some() => "class",
+        ("cpp", "namespace_definition") => "namespace",
+        _ => return None,
+    };
+
+    let name = match node.kind() {
+        "impl_item" => {
+            let ty = node_text(node.child_by_field_name("type")?, source);
+            match node.child_by_field_name("trait") {
+                Some(trait_node) => format!("{} for {}", node_text(trait_node, source), ty),
+                None => ty,
+            }
+        }
+        "function_definition" if language == "c" || language == "cpp" => {
+            declarator_name(node.child_by_field_name("declarator")?, source)?
+        }
+        "namespace_definition" => node
+            .child_by_field_name("name")
+            .map(|n| node_text(n, source))
+            .unwrap_or_else(|| "(anonymous)".to_string()),
+        _ => node_text(node.child_by_field_name("name")?, source),
+    };
+
+    Some((kind, name))
+}
+
+/// Walk nested C/C++ declarators (pointer, function, reference) down to the identifier
+fn declarator_name(node: Node, source: &str) -> Option<String> {
+    match node.kind() {
+        "identifier" | "field_identifier" | "qualified_identifier" | "destructor_name"
+        | "operator_name" => Some(node_text(node, source)),
+        _ => declarator_name(node.child_by_field_name("declarator")?, source),
+    }
+}
+
+fn node_text(node: Node, source: &str) -> String {
+    source[node.byte_range()].to_string()
+}
@@ -1,4 +1,5 @@
 use super::{CodeSearchRequest, CodeSearchResponse, Match, SearchResult, SearchSpec};
+use crate::g3ignore::G3Ignore;
 use anyhow::{anyhow, Result};
 use std::collections::HashMap;
 use std::fs;
@@ -10,6 +11,7 @@ use walkdir::WalkDir;
 pub struct TreeSitterSearcher {
     parsers: HashMap<String, Parser>,
     languages: HashMap<String, Language>,
+    ignore: G3Ignore,
 }
 
 impl TreeSitterSearcher {
@@ -158,7 +160,17 @@ impl TreeSitterSearcher {
             ));
         }
 
-        Ok(Self { parsers, languages })
+        Ok(Self {
+            parsers,
+            languages,
+            ignore: G3Ignore::default(),
+        })
+    }
+
+    /// Skip files and directories excluded by `.g3ignore`
+    pub fn with_ignore(mut self, ignore: G3Ignore) -> Self {
+        self.ignore = ignore;
+        self
     }
 
     pub async fn execute_search(
@@ -233,6 +245,7 @@ impl TreeSitterSearcher {
             for entry in WalkDir::new(&search_path)
                 .follow_links(true)
                 .into_iter()
+                .filter_entry(|e| !self.ignore.is_ignored(e.path()))
                 .filter_map(|e| e.ok())
             {
                 if matches.len() >= max_matches {
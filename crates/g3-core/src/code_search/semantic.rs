@@ -0,0 +1,462 @@
+//! Code search by description, for code_search_semantic: finding code from a query in plain
+//! words rather than its exact text.
+//!
+//! Source files are cut into chunks along symbol boundaries (whole functions, types and
+//! so on, from the outline read_file uses) and other text files into fixed windows of
+//! lines. Each chunk is embedded, and a query is answered with the chunks whose vectors
+//! are closest to the query's. Only a model's embeddings make that search semantic; with
+//! the default hashed embeddings it matches shared vocabulary. The vectors are saved to `.g3/semantic_index.json`, and
+//! each search first re-embeds only the files that changed since the last one.
+
+use anyhow::{Context, Result};
+use g3_providers::embeddings::cosine_similarity;
+use g3_providers::EmbeddingProvider;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use super::outline::{language_for_path, outline_source};
+use crate::file_changes;
+use crate::g3ignore::G3Ignore;
+use crate::project_index::{relative, SKIPPED_DIRS};
+
+pub const INDEX_FILE: &str = ".g3/semantic_index.json";
+
+/// Symbols longer than this are split into their members, or into windows
+const MAX_CHUNK_LINES: usize = 80;
+
+/// Lines per chunk of text outside any symbol, and of files without an outline
+const WINDOW_LINES: usize = 40;
+
+/// Characters of a chunk that are embedded; the rest of a long chunk is cut off
+const MAX_EMBED_CHARS: usize = 4000;
+
+/// Lines of each match shown in search results
+const SNIPPET_LINES: usize = 8;
+
+/// Files larger than this are left out of the index
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+
+/// Files besides source files with an outline that are indexed
+const TEXT_EXTENSIONS: &[&str] = &[
+    "md", "txt", "toml", "yaml", "yml", "json", "sh", "kt", "rb", "swift", "scala", "sql",
+];
+
+/// A piece of a file that is embedded as a whole
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    /// 1-indexed, inclusive
+    pub start_line: usize,
+    /// 1-indexed, inclusive
+    pub end_line: usize,
+    /// The symbol the chunk is, e.g. "fn retry_with_backoff"
+    pub symbol: Option<String>,
+    pub text: String,
+}
+
+/// Cut `source` into chunks: symbols that fit in [`MAX_CHUNK_LINES`], and windows of
+/// [`WINDOW_LINES`] for the lines between them. Chunks of only whitespace are left out.
+pub fn chunk_source(path: &Path, source: &str) -> Vec<TextChunk> {
+    let lines: Vec<&str> = source.lines().collect();
+    let symbols = match outline_source(path, source) {
+        Ok(Some(symbols)) => symbols,
+        _ => Vec::new(),
+    };
+
+    // The outline lists parents before their members, so the first symbol that fits
+    // covers its members
+    let mut taken: Vec<(usize, usize, String)> = Vec::new();
+    for symbol in &symbols {
+        let lines = symbol.end_line + 1 - symbol.start_line.min(symbol.end_line);
+        let covered = taken
+            .iter()
+            .any(|(start, end, _)| *start <= symbol.start_line && symbol.end_line <= *end);
+        if lines <= MAX_CHUNK_LINES && !covered {
+            taken.push((
+                symbol.start_line,
+                symbol.end_line,
+                format!("{} {}", symbol.kind, symbol.name),
+            ));
+        }
+    }
+    taken.sort_by_key(|(start, _, _)| *start);
+
+    let text = |start: usize, end: usize| lines[start - 1..end.min(lines.len())].join("\n");
+    let mut chunks = Vec::new();
+    let windows = |from: usize, to: usize, chunks: &mut Vec<TextChunk>| {
+        let mut start = from;
+        while start <= to {
+            let end = (start + WINDOW_LINES - 1).min(to);
+            let window = text(start, end);
+            if !window.trim().is_empty() {
+                chunks.push(TextChunk {
+                    start_line: start,
+                    end_line: end,
+                    symbol: None,
+                    text: window,
+                });
+            }
+            start = end + 1;
+        }
+    };
+    let mut next = 1;
+    for (start, end, symbol) in taken {
+        if start < next || start > lines.len() {
+            continue;
+        }
+        windows(next, start - 1, &mut chunks);
+        chunks.push(TextChunk {
+            start_line: start,
+            end_line: end.min(lines.len()),
+            symbol: Some(symbol),
+            text: text(start, end),
+        });
+        next = end + 1;
+    }
+    windows(next, lines.len(), &mut chunks);
+    chunks
+}
+
+/// A chunk of an indexed file and its vector
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    pub start_line: usize,
+    pub end_line: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    pub vector: Vec<f32>,
+}
+
+/// An indexed file, and the version of it that was embedded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedFile {
+    pub hash: String,
+    pub len: u64,
+    #[serde(default)]
+    pub modified: Option<SystemTime>,
+    pub chunks: Vec<IndexedChunk>,
+}
+
+/// What [`SemanticIndex::update`] changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexUpdate {
+    /// Files embedded because they are new or changed
+    pub embedded_files: usize,
+    pub embedded_chunks: usize,
+    pub removed_files: usize,
+}
+
+/// A chunk that matches a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticMatch {
+    /// Relative to the workspace
+    pub file: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub symbol: Option<String>,
+    /// Cosine similarity to the query
+    pub score: f32,
+}
+
+/// The embedded chunks of a workspace's files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SemanticIndex {
+    /// Provider and model the vectors came from; vectors of another aren't comparable
+    pub provider: String,
+    pub model: String,
+    /// By path relative to the workspace, with `/` separators
+    pub files: BTreeMap<String, IndexedFile>,
+}
+
+impl SemanticIndex {
+    pub fn path(workspace: &Path) -> PathBuf {
+        workspace.join(INDEX_FILE)
+    }
+
+    /// The saved index of `workspace`, if there is one
+    pub fn load(workspace: &Path) -> Option<Self> {
+        let path = Self::path(workspace);
+        let contents = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&contents) {
+            Ok(index) => Some(index),
+            Err(e) => {
+                warn!("Failed to parse {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, workspace: &Path) -> Result<PathBuf> {
+        let path = Self::path(workspace);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        debug!("Saved semantic index to {}", path.display());
+        Ok(path)
+    }
+
+    /// Bring the index up to date with the files in `workspace`, embedding the new and
+    /// changed ones with `embedder`
+    pub async fn update(
+        &mut self,
+        workspace: &Path,
+        embedder: &dyn EmbeddingProvider,
+    ) -> Result<IndexUpdate> {
+        if self.provider != embedder.name() || self.model != embedder.model() {
+            self.files.clear();
+            self.provider = embedder.name().to_string();
+            self.model = embedder.model().to_string();
+        }
+
+        let mut update = IndexUpdate::default();
+        let mut present = HashSet::new();
+        let mut changed: Vec<(String, IndexedFile, Vec<TextChunk>)> = Vec::new();
+        for path in indexed_files(workspace) {
+            let relative = relative(&path, workspace);
+            present.insert(relative.clone());
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            let modified = metadata.modified().ok();
+            if let Some(file) = self.files.get(&relative) {
+                if file.len == metadata.len() && file.modified == modified {
+                    continue;
+                }
+            }
+            let Ok(content) = std::fs::read(&path) else {
+                continue;
+            };
+            let hash = file_changes::hash(&content);
+            if let Some(file) = self.files.get_mut(&relative) {
+                if file.hash == hash {
+                    file.modified = modified;
+                    continue;
+                }
+            }
+            let Ok(source) = String::from_utf8(content) else {
+                continue;
+            };
+            let chunks = chunk_source(&path, &source);
+            let file = IndexedFile {
+                hash,
+                len: metadata.len(),
+                modified,
+                chunks: Vec::new(),
+            };
+            changed.push((relative, file, chunks));
+        }
+
+        let before = self.files.len();
+        self.files.retain(|path, _| present.contains(path));
+        update.removed_files = before - self.files.len();
+
+        let texts: Vec<String> = changed
+            .iter()
+            .flat_map(|(path, _, chunks)| chunks.iter().map(move |chunk| embed_text(path, chunk)))
+            .collect();
+        let mut vectors = if texts.is_empty() {
+            Vec::new()
+        } else {
+            embedder.embed(&texts).await?
+        }
+        .into_iter();
+        for (path, mut file, chunks) in changed {
+            update.embedded_files += 1;
+            update.embedded_chunks += chunks.len();
+            for chunk in chunks {
+                let Some(vector) = vectors.next() else {
+                    break;
+                };
+                file.chunks.push(IndexedChunk {
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    symbol: chunk.symbol,
+                    vector,
+                });
+            }
+            self.files.insert(path, file);
+        }
+        Ok(update)
+    }
+
+    /// The `limit` chunks closest to `query`, best first, from files under one of
+    /// `paths` (relative to the workspace) or from all files if `paths` is empty
+    pub fn search(&self, query: &[f32], limit: usize, paths: &[String]) -> Vec<SemanticMatch> {
+        let prefixes: Vec<&str> = paths
+            .iter()
+            .map(|path| path.trim_start_matches("./").trim_end_matches('/'))
+            .filter(|path| !path.is_empty() && *path != ".")
+            .collect();
+        let mut matches: Vec<SemanticMatch> = self
+            .files
+            .iter()
+            .filter(|(file, _)| {
+                prefixes.is_empty()
+                    || prefixes.iter().any(|prefix| {
+                        file.as_str() == *prefix || file.starts_with(&format!("{}/", prefix))
+                    })
+            })
+            .flat_map(|(file, indexed)| {
+                indexed.chunks.iter().map(move |chunk| SemanticMatch {
+                    file: file.clone(),
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    symbol: chunk.symbol.clone(),
+                    score: cosine_similarity(query, &chunk.vector),
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(limit);
+        matches
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.files.values().map(|file| file.chunks.len()).sum()
+    }
+}
+
+/// The matches with the first lines of each, numbered as in the file
+pub fn render_matches(workspace: &Path, matches: &[SemanticMatch]) -> String {
+    let mut out = String::new();
+    for (i, m) in matches.iter().enumerate() {
+        out.push_str(&format!(
+            "{}. {}:{}-{}{} (score {:.2})\n",
+            i + 1,
+            m.file,
+            m.start_line,
+            m.end_line,
+            m.symbol
+                .as_ref()
+                .map(|symbol| format!(" {}", symbol))
+                .unwrap_or_default(),
+            m.score
+        ));
+        let Ok(source) = std::fs::read_to_string(workspace.join(&m.file)) else {
+            continue;
+        };
+        let end = m.end_line.min(m.start_line + SNIPPET_LINES - 1);
+        for (number, line) in source.lines().enumerate().take(end).skip(m.start_line - 1) {
+            out.push_str(&format!("   {:>5} | {}\n", number + 1, line));
+        }
+        if end < m.end_line {
+            out.push_str("         | ...\n");
+        }
+    }
+    out
+}
+
+/// What is embedded for a chunk: where it is, what it is and its text
+fn embed_text(path: &str, chunk: &TextChunk) -> String {
+    let mut text = match &chunk.symbol {
+        Some(symbol) => format!("{} {}\n{}", path, symbol, chunk.text),
+        None => format!("{}\n{}", path, chunk.text),
+    };
+    if text.len() > MAX_EMBED_CHARS {
+        let mut end = MAX_EMBED_CHARS;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+/// Files of `workspace` that are indexed: source and text files outside hidden, skipped
+/// and ignored directories, up to [`MAX_FILE_BYTES`]
+fn indexed_files(workspace: &Path) -> Vec<PathBuf> {
+    let ignore = G3Ignore::load(workspace);
+    WalkDir::new(workspace)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            let skipped = entry.file_type().is_dir() && SKIPPED_DIRS.contains(&name.as_ref());
+            !name.starts_with('.') && !skipped && !ignore.is_ignored(entry.path())
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry
+                .metadata()
+                .is_ok_and(|metadata| metadata.len() <= MAX_FILE_BYTES)
+        })
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            language_for_path(path).is_some()
+                || path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_providers::HashedEmbeddings;
+
+    #[test]
+    fn test_chunk_source_by_symbol() {
+        let source = "use std::time::Duration;\n\nfn retry_with_backoff() {\n    sleep();\n}\n\nstruct Config {\n    retries: u32,\n}\n";
+        let chunks = chunk_source(Path::new("lib.rs"), source);
+        let summary: Vec<(usize, usize, Option<&str>)> = chunks
+            .iter()
+            .map(|chunk| (chunk.start_line, chunk.end_line, chunk.symbol.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 2, None),
+                (3, 5, Some("fn retry_with_backoff")),
+                (7, 9, Some("struct Config")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_semantic_index_update_and_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        std::fs::create_dir(&src).unwrap();
+        std::fs::write(
+            src.join("net.rs"),
+            "async fn send_with_retry(attempts: u32) {\n    for attempt in 0..attempts {\n        retry_after_backoff(attempt);\n    }\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            src.join("table.rs"),
+            "fn parse_markdown_table(input: &str) -> Table {\n    rows(input)\n}\n",
+        )
+        .unwrap();
+        let embedder = HashedEmbeddings::new();
+
+        let mut index = SemanticIndex::default();
+        let update = index.update(dir.path(), &embedder).await.unwrap();
+        assert_eq!(update.embedded_files, 2);
+        let query = embedder.embed_text("where is retry logic implemented?");
+        let matches = index.search(&query, 1, &[]);
+        assert_eq!(matches[0].file, "src/net.rs");
+        assert_eq!(matches[0].symbol.as_deref(), Some("fn send_with_retry"));
+
+        // Only changed and removed files are touched on the next update
+        std::fs::remove_file(src.join("table.rs")).unwrap();
+        let update = index.update(dir.path(), &embedder).await.unwrap();
+        assert_eq!(
+            update,
+            IndexUpdate {
+                embedded_files: 0,
+                embedded_chunks: 0,
+                removed_files: 1
+            }
+        );
+        assert!(index.search(&query, 10, &["lib".to_string()]).is_empty());
+    }
+}
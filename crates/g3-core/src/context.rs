@@ -0,0 +1,1060 @@
+//! The conversation sent to the model and what it costs in tokens.
+//!
+//! A [`ContextWindow`] holds the conversation, estimates its size, and keeps it within the
+//! model's window: thinning moves large tool results and payloads to artifacts as usage
+//! crosses the `[context.thinning]` thresholds, [`ContextWindow::shrink_to_fit`] makes room
+//! for a request that wouldn't fit, and a [`CompactionStrategy`] decides when the whole
+//! conversation is replaced by a summary of it and how it is rebuilt. The window is `Send`
+//! and `Sync`, so it can be handed to other tasks and threads (its strategy is shared
+//! behind an `Arc`).
+
+use g3_config::{ThinningConfig, ThinningStrategy};
+use g3_providers::{Message, MessageRole};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use crate::artifacts::{ArtifactKind, ArtifactStore};
+use crate::{tokenizer, ToolCall, CONTINUE_PROMPT, RESUME_PROMPT};
+
+/// A tool result, or a large tool call payload, that context thinning may replace
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThinningCandidate {
+    /// Index into the conversation history
+    pub index: usize,
+    /// Argument name for tool call payloads, `None` for tool results
+    pub payload_arg: Option<&'static str>,
+    /// Size in chars of the content that would be replaced
+    pub size: usize,
+}
+
+/// What [`ContextWindow::shrink_to_fit`] removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShrinkOutcome {
+    /// Tool results replaced by a file reference
+    pub tool_results: usize,
+    pub messages_dropped: usize,
+    /// Tokens in the conversation afterwards
+    pub tokens: u32,
+}
+
+/// Messages listed in [`ContextBreakdown::largest`]
+const LARGEST_MESSAGES: usize = 10;
+
+/// What a part of the conversation is, for [`ContextBreakdown`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ContextSource {
+    /// System messages: the system prompt, project context and summaries
+    SystemPrompt,
+    UserMessages,
+    ToolResults,
+    /// The tool calls in assistant messages
+    ToolCalls,
+    AssistantText,
+}
+
+impl ContextSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            ContextSource::SystemPrompt => "System prompt",
+            ContextSource::UserMessages => "User messages",
+            ContextSource::ToolResults => "Tool results",
+            ContextSource::ToolCalls => "Tool calls",
+            ContextSource::AssistantText => "Assistant text",
+        }
+    }
+}
+
+/// One message of the conversation and its size
+#[derive(Debug, Clone)]
+pub struct MessageSize {
+    /// Index into the conversation history
+    pub index: usize,
+    pub role: MessageRole,
+    pub tokens: u32,
+    /// The tool called, or whose result this is
+    pub tool: Option<String>,
+    /// The start of the message, on one line
+    pub preview: String,
+}
+
+/// What the conversation's tokens are spent on, as counted for one model
+#[derive(Debug, Clone, Default)]
+pub struct ContextBreakdown {
+    pub total: u32,
+    pub system: u32,
+    pub user: u32,
+    pub assistant: u32,
+    /// Tokens by what they are, in [`ContextSource`] order
+    pub by_source: std::collections::BTreeMap<ContextSource, u32>,
+    /// The largest messages, largest first
+    pub largest: Vec<MessageSize>,
+}
+
+impl ContextBreakdown {
+    /// Share of the total, in percent
+    pub fn percentage(&self, tokens: u32) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            tokens as f32 / self.total as f32 * 100.0
+        }
+    }
+}
+
+/// What one tool's results have added to the context over a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolContribution {
+    pub results: usize,
+    pub tokens: u64,
+}
+
+/// Context tokens added by each tool's results over a session, counted when a result is
+/// added. Results thinned or summarized away later still count, so this shows what fills the
+/// context rather than what is in it now.
+#[derive(Debug, Clone, Default)]
+pub struct ToolContextLedger {
+    tools: HashMap<String, ToolContribution>,
+}
+
+impl ToolContextLedger {
+    pub fn record(&mut self, tool: &str, tokens: u32) {
+        let contribution = self.tools.entry(tool.to_string()).or_default();
+        contribution.results += 1;
+        contribution.tokens += tokens as u64;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Tokens added by all tools
+    pub fn total(&self) -> u64 {
+        self.tools.values().map(|c| c.tokens).sum()
+    }
+
+    /// Share of all tools' tokens, in percent
+    pub fn percentage(&self, tokens: u64) -> f32 {
+        match self.total() {
+            0 => 0.0,
+            total => tokens as f32 / total as f32 * 100.0,
+        }
+    }
+
+    /// Tools by tokens added, most first
+    pub fn leaderboard(&self) -> Vec<(&str, ToolContribution)> {
+        let mut tools: Vec<_> = self
+            .tools
+            .iter()
+            .map(|(tool, contribution)| (tool.as_str(), *contribution))
+            .collect();
+        tools.sort_by(|a, b| b.1.tokens.cmp(&a.1.tokens).then(a.0.cmp(b.0)));
+        tools
+    }
+}
+
+/// Decides when the conversation is compacted into a summary and how it is rebuilt around
+/// it. The agent asks the model for the summary with [`summary_prompt`] when
+/// [`should_compact`] says so, then hands it to [`compact`].
+///
+/// [`summary_prompt`]: CompactionStrategy::summary_prompt
+/// [`should_compact`]: CompactionStrategy::should_compact
+/// [`compact`]: CompactionStrategy::compact
+pub trait CompactionStrategy: std::fmt::Debug + Send + Sync {
+    /// Whether the conversation should be compacted before the next request
+    fn should_compact(&self, context: &ContextWindow) -> bool;
+
+    /// The request for a summary of the conversation
+    fn summary_prompt(&self, context: &ContextWindow) -> String;
+
+    /// Replace the conversation with one built around `summary`, keeping
+    /// `latest_user_message` if there is one. Returns the number of chars removed.
+    fn compact(
+        &self,
+        context: &mut ContextWindow,
+        summary: String,
+        latest_user_message: Option<String>,
+    ) -> usize;
+}
+
+/// The default strategy: at 80% of the window (or 150k tokens), the conversation becomes a
+/// system message with the summary followed by the latest user message
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SummaryCompaction;
+
+impl CompactionStrategy for SummaryCompaction {
+    fn should_compact(&self, context: &ContextWindow) -> bool {
+        // Trigger at 80% OR if we're getting close to absolute limits
+        // This prevents issues with models that have large contexts but still hit limits
+        let percentage_trigger = context.percentage_used() >= 80.0;
+
+        // Also trigger if we're approaching common token limits
+        // Most models start having issues around 150k tokens
+        let absolute_trigger = context.used_tokens > 150_000;
+
+        percentage_trigger || absolute_trigger
+    }
+
+    fn summary_prompt(&self, _context: &ContextWindow) -> String {
+        "Please provide a comprehensive summary of our conversation so far. Include:
+
+1. **Main Topic/Goal**: What is the primary task or objective being worked on?
+2. **Key Decisions**: What important decisions have been made?
+3. **Actions Taken**: What specific actions, commands, or code changes have been completed?
+4. **Current State**: What is the current status of the work?
+5. **Important Context**: Any critical information, file paths, configurations, or constraints that should be remembered?
+6. **Pending Items**: What remains to be done or what was the user's last request?
+
+Format this as a detailed but concise summary that can be used to resume the conversation from scratch while maintaining full context.".to_string()
+    }
+
+    fn compact(
+        &self,
+        context: &mut ContextWindow,
+        summary: String,
+        latest_user_message: Option<String>,
+    ) -> usize {
+        // Calculate chars saved (old history minus new summary)
+        let old_chars = context.history_chars();
+
+        // Clear the conversation history
+        context.conversation_history.clear();
+        context.used_tokens = 0;
+
+        // Add the summary as a system message
+        let summary_message = Message {
+            role: MessageRole::System,
+            content: format!("Previous conversation summary:\n\n{}", summary),
+        };
+        context.add_message(summary_message);
+
+        // Add the latest user message if provided
+        if let Some(user_msg) = latest_user_message {
+            context.add_message(Message {
+                role: MessageRole::User,
+                content: user_msg,
+            });
+        }
+
+        old_chars.saturating_sub(context.history_chars())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContextWindow {
+    pub used_tokens: u32,
+    pub total_tokens: u32,
+    pub cumulative_tokens: u32, // Track cumulative tokens across all interactions
+    pub conversation_history: Vec<Message>,
+    pub last_thinning_percentage: u32, // Track the last percentage at which we thinned
+    pub thinning: ThinningConfig,
+    /// Where thinned content is saved, unless `thinning.output_dir` overrides it
+    pub artifacts: ArtifactStore,
+    /// When and how the conversation is compacted into a summary
+    pub compaction: Arc<dyn CompactionStrategy>,
+}
+
+impl ContextWindow {
+    pub fn new(total_tokens: u32) -> Self {
+        Self {
+            used_tokens: 0,
+            total_tokens,
+            cumulative_tokens: 0,
+            conversation_history: Vec::new(),
+            last_thinning_percentage: 0,
+            thinning: ThinningConfig::default(),
+            artifacts: ArtifactStore::temporary(),
+            compaction: Arc::new(SummaryCompaction),
+        }
+    }
+
+    /// The window with another compaction strategy
+    pub fn with_compaction(mut self, compaction: Arc<dyn CompactionStrategy>) -> Self {
+        self.compaction = compaction;
+        self
+    }
+
+    pub fn add_message(&mut self, message: Message) {
+        self.add_message_with_tokens(message, None);
+    }
+
+    /// Add a message with optional token count from the provider
+    pub fn add_message_with_tokens(&mut self, message: Message, tokens: Option<u32>) {
+        // Skip messages with empty content to avoid API errors
+        if message.content.trim().is_empty() {
+            warn!("Skipping empty message to avoid API error");
+            return;
+        }
+
+        // Use provided token count if available, otherwise estimate
+        let token_count = tokens.unwrap_or_else(|| Self::estimate_tokens(&message.content));
+        self.used_tokens += token_count;
+        self.cumulative_tokens += token_count;
+        self.conversation_history.push(message);
+
+        debug!(
+            "Added message with {} tokens (used: {}/{}, cumulative: {})",
+            token_count, self.used_tokens, self.total_tokens, self.cumulative_tokens
+        );
+    }
+
+    /// Add the text of an assistant response. The continuation of a response that stopped
+    /// at the output token limit, or was interrupted, is joined onto it in place of
+    /// [`CONTINUE_PROMPT`] or [`RESUME_PROMPT`].
+    pub fn add_response_part(&mut self, content: String) {
+        let history = &self.conversation_history;
+        let continues = self.awaiting_continuation()
+            && history.len() >= 2
+            && matches!(history[history.len() - 2].role, MessageRole::Assistant);
+        if !continues || content.trim().is_empty() {
+            self.add_message(Message {
+                role: MessageRole::Assistant,
+                content,
+            });
+            return;
+        }
+
+        let prompt = self.conversation_history.pop().expect("checked above");
+        self.used_tokens = self
+            .used_tokens
+            .saturating_sub(Self::estimate_tokens(&prompt.content));
+        let token_count = Self::estimate_tokens(&content);
+        self.used_tokens += token_count;
+        self.cumulative_tokens += token_count;
+        if let Some(previous) = self.conversation_history.last_mut() {
+            previous.content.push_str(&content);
+        }
+    }
+
+    /// Whether the last message asks for the rest of a response that was cut off
+    pub fn awaiting_continuation(&self) -> bool {
+        self.conversation_history.last().is_some_and(|m| {
+            matches!(m.role, MessageRole::User)
+                && (m.content == CONTINUE_PROMPT || m.content == RESUME_PROMPT)
+        })
+    }
+
+    /// Update token usage from provider response
+    pub fn update_usage_from_response(&mut self, usage: &g3_providers::Usage) {
+        // Add the tokens from this response to our running total
+        // The usage.total_tokens represents tokens used in this single API call. Reasoning
+        // tokens are paid for but dropped from the conversation, so they don't fill the window.
+        self.used_tokens += usage.total_tokens.saturating_sub(usage.reasoning_tokens);
+        self.cumulative_tokens += usage.total_tokens;
+
+        debug!(
+            "Added {} tokens from provider response (used: {}/{}, cumulative: {})",
+            usage.total_tokens, self.used_tokens, self.total_tokens, self.cumulative_tokens
+        );
+    }
+
+    /// More accurate token estimation
+    pub(crate) fn estimate_tokens(text: &str) -> u32 {
+        // Better heuristic:
+        // - Average English text: ~4 characters per token
+        // - Code/JSON: ~3 characters per token (more symbols)
+        // - Add 10% buffer for safety
+        let base_estimate = if text.contains("{") || text.contains("```") || text.contains("fn ") {
+            (text.len() as f32 / 3.0).ceil() as u32 // Code/JSON
+        } else {
+            (text.len() as f32 / 4.0).ceil() as u32 // Regular text
+        };
+        (base_estimate as f32 * 1.1).ceil() as u32 // Add 10% buffer
+    }
+
+    pub fn update_usage(&mut self, usage: &g3_providers::Usage) {
+        // Deprecated: Use update_usage_from_response instead
+        self.update_usage_from_response(usage);
+    }
+
+    /// Update cumulative token usage (for streaming)
+    pub fn add_streaming_tokens(&mut self, new_tokens: u32) {
+        self.used_tokens += new_tokens;
+        self.cumulative_tokens += new_tokens;
+        debug!(
+            "Added {} streaming tokens (used: {}/{}, cumulative: {})",
+            new_tokens, self.used_tokens, self.total_tokens, self.cumulative_tokens
+        );
+    }
+
+    pub fn percentage_used(&self) -> f32 {
+        if self.total_tokens == 0 {
+            0.0
+        } else {
+            (self.used_tokens as f32 / self.total_tokens as f32) * 100.0
+        }
+    }
+
+    pub fn remaining_tokens(&self) -> u32 {
+        self.total_tokens.saturating_sub(self.used_tokens)
+    }
+
+    /// Whether the conversation should be compacted, according to its strategy
+    pub fn should_summarize(&self) -> bool {
+        self.compaction.should_compact(self)
+    }
+
+    /// Create a summary request prompt for the current conversation
+    pub fn create_summary_prompt(&self) -> String {
+        self.compaction.summary_prompt(self)
+    }
+
+    /// Reset the context window with a summary, as its strategy rebuilds it
+    pub fn reset_with_summary(
+        &mut self,
+        summary: String,
+        latest_user_message: Option<String>,
+    ) -> usize {
+        let compaction = Arc::clone(&self.compaction);
+        compaction.compact(self, summary, latest_user_message)
+    }
+
+    /// Chars in the conversation
+    pub fn history_chars(&self) -> usize {
+        self.conversation_history
+            .iter()
+            .map(|m| m.content.len())
+            .sum()
+    }
+
+    /// Break the conversation's tokens down by role and by what they are, and find the
+    /// largest messages, counting tokens for `model`
+    pub fn breakdown(&self, model: &str) -> ContextBreakdown {
+        let mut breakdown = ContextBreakdown::default();
+        let mut sizes = Vec::new();
+        let mut last_tool: Option<String> = None;
+        for (index, message) in self.conversation_history.iter().enumerate() {
+            let tokens = tokenizer::count_message_tokens(model, message);
+            let mut tool = None;
+            let mut sources = vec![];
+            match message.role {
+                MessageRole::System => {
+                    breakdown.system += tokens;
+                    sources.push((ContextSource::SystemPrompt, tokens));
+                }
+                MessageRole::User => {
+                    breakdown.user += tokens;
+                    if message.content.starts_with("Tool result:") {
+                        sources.push((ContextSource::ToolResults, tokens));
+                        tool = last_tool.take();
+                    } else {
+                        sources.push((ContextSource::UserMessages, tokens));
+                    }
+                }
+                MessageRole::Assistant => {
+                    breakdown.assistant += tokens;
+                    match Self::find_tool_call(&message.content) {
+                        Some((start, len, call)) => {
+                            let call_tokens = tokenizer::count_tokens(
+                                model,
+                                &message.content[start..start + len],
+                            )
+                            .min(tokens);
+                            sources.push((ContextSource::ToolCalls, call_tokens));
+                            sources.push((ContextSource::AssistantText, tokens - call_tokens));
+                            tool = Some(call.tool);
+                        }
+                        None => sources.push((ContextSource::AssistantText, tokens)),
+                    }
+                    last_tool = tool.clone();
+                }
+            }
+            for (source, tokens) in sources {
+                *breakdown.by_source.entry(source).or_default() += tokens;
+            }
+            breakdown.total += tokens;
+            sizes.push(MessageSize {
+                index,
+                role: message.role.clone(),
+                tokens,
+                tool,
+                preview: message
+                    .content
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .chars()
+                    .take(60)
+                    .collect(),
+            });
+        }
+        sizes.sort_by_key(|size| std::cmp::Reverse(size.tokens));
+        sizes.truncate(LARGEST_MESSAGES);
+        breakdown.largest = sizes;
+        breakdown
+    }
+
+    /// Check if we should trigger context thinning
+    /// Triggers each time usage crosses another configured step between the start and max
+    /// thresholds (50%, 60%, 70% and 80% by default)
+    pub fn should_thin(&self) -> bool {
+        let current_percentage = self.percentage_used() as u32;
+        if current_percentage < self.thinning.start_percentage {
+            return false;
+        }
+
+        let current_threshold = self.thinning_threshold();
+        current_threshold > self.last_thinning_percentage
+            && current_threshold <= self.thinning.max_percentage
+    }
+
+    /// The most recent threshold crossed, rounded down to the configured step
+    fn thinning_threshold(&self) -> u32 {
+        let current_percentage = self.percentage_used() as u32;
+        let start = self.thinning.start_percentage;
+        let step = self.thinning.step_percentage.max(1);
+        if current_percentage >= start {
+            start + ((current_percentage - start) / step) * step
+        } else {
+            (current_percentage / step) * step
+        }
+    }
+
+    /// Find the tool results and tool call payloads that thinning may replace, in the order
+    /// the configured strategy would thin them. Only the oldest `chunk_size` messages
+    /// (the first third of the conversation by default) are considered.
+    pub fn thinning_candidates(&self) -> Vec<ThinningCandidate> {
+        let total_messages = self.conversation_history.len();
+        let scan_end = self
+            .thinning
+            .chunk_size
+            .unwrap_or(total_messages / 3)
+            .max(1)
+            .min(total_messages);
+        let min_chars = self.thinning.min_chars;
+
+        let mut candidates = Vec::new();
+        for (i, message) in self.conversation_history[..scan_end].iter().enumerate() {
+            match message.role {
+                // TODO tool results are kept so the plan stays visible to the model
+                MessageRole::User
+                    if message.content.starts_with("Tool result:")
+                        && message.content.len() > min_chars
+                        && !self.follows_todo_call(i) =>
+                {
+                    candidates.push(ThinningCandidate {
+                        index: i,
+                        payload_arg: None,
+                        size: message.content.len(),
+                    });
+                }
+                MessageRole::Assistant
+                    if self.thinning.strategy != ThinningStrategy::ToolResultsOnly =>
+                {
+                    let payload =
+                        Self::find_tool_call(&message.content).and_then(|(_, _, call)| {
+                            let arg_name = Self::thinnable_payload_arg(&call.tool)?;
+                            let size = call.args.get(arg_name)?.as_str()?.len();
+                            Some((arg_name, size))
+                        });
+                    if let Some((arg_name, size)) = payload {
+                        if size > min_chars {
+                            candidates.push(ThinningCandidate {
+                                index: i,
+                                payload_arg: Some(arg_name),
+                                size,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if self.thinning.strategy == ThinningStrategy::LargestFirst {
+            candidates.sort_by_key(|c| std::cmp::Reverse(c.size));
+        }
+        candidates
+    }
+
+    /// Perform context thinning with the configured strategy: large tool results and tool call
+    /// payloads are saved to files and replaced with a reference to the file.
+    /// Returns a summary message about what was thinned
+    pub fn thin_context(&mut self) -> (String, usize) {
+        self.thin_context_with_summaries(&std::collections::HashMap::new())
+    }
+
+    /// Like [`ContextWindow::thin_context`], but tool results with an entry in `summaries`
+    /// (keyed by message index) keep that summary alongside the file reference
+    pub fn thin_context_with_summaries(
+        &mut self,
+        summaries: &std::collections::HashMap<usize, String>,
+    ) -> (String, usize) {
+        let current_threshold = self.thinning_threshold();
+
+        // Update the last thinning percentage
+        self.last_thinning_percentage = current_threshold;
+
+        let mut leaned_count = 0;
+        let mut tool_call_leaned_count = 0;
+        let mut chars_saved = 0;
+
+        let store = match &self.thinning.output_dir {
+            Some(dir) => ArtifactStore::new(shellexpand::tilde(dir).as_ref()),
+            None => self.artifacts.clone(),
+        };
+
+        let total_chars: usize = self
+            .conversation_history
+            .iter()
+            .map(|m| m.content.len())
+            .sum();
+        let target_chars = total_chars * self.thinning.target_reduction as usize / 100;
+
+        for candidate in self.thinning_candidates() {
+            if target_chars > 0 && chars_saved >= target_chars {
+                break;
+            }
+
+            let i = candidate.index;
+            let message = &mut self.conversation_history[i];
+
+            match candidate.payload_arg {
+                None => {
+                    let file_path = match store.save(
+                        ArtifactKind::ThinnedToolResult,
+                        &format!("tool_result_{}.txt", i),
+                        message.content.as_bytes(),
+                        None,
+                    ) {
+                        Ok(path) => path.display().to_string(),
+                        Err(e) => {
+                            warn!("Failed to save thinned content: {}", e);
+                            continue;
+                        }
+                    };
+
+                    // Replace the message content with a note (and summary, if we have one)
+                    let original_len = message.content.len();
+                    message.content = match summaries.get(&i) {
+                        Some(summary) => format!(
+                            "Tool result summarized (full output saved to {}):\n{}",
+                            file_path, summary
+                        ),
+                        None => format!("Tool result saved to {}", file_path),
+                    };
+
+                    leaned_count += 1;
+                    chars_saved += original_len.saturating_sub(message.content.len());
+
+                    debug!(
+                        "Thinned tool result {} ({} chars) to {}",
+                        i, original_len, file_path
+                    );
+                }
+                Some(arg_name) => {
+                    if let Some(saved) = Self::thin_tool_call_payload(message, arg_name, &store, i)
+                    {
+                        chars_saved += saved;
+                        tool_call_leaned_count += 1;
+                    }
+                }
+            }
+        }
+
+        // Recalculate token usage after thinning
+        self.recalculate_tokens();
+
+        if leaned_count > 0 {
+            if tool_call_leaned_count > 0 {
+                (format!("🥒 Context thinned at {}%: {} tool results + {} tool calls, ~{} chars saved",
+                        current_threshold, leaned_count, tool_call_leaned_count, chars_saved), chars_saved)
+            } else {
+                (
+                    format!(
+                        "🥒 Context thinned at {}%: {} tool results, ~{} chars saved",
+                        current_threshold, leaned_count, chars_saved
+                    ),
+                    chars_saved,
+                )
+            }
+        } else if tool_call_leaned_count > 0 {
+            (
+                format!(
+                    "🥒 Context thinned at {}%: {} tool calls, ~{} chars saved",
+                    current_threshold, tool_call_leaned_count, chars_saved
+                ),
+                chars_saved,
+            )
+        } else {
+            (format!("ℹ Context thinning triggered at {}% but no large tool results or tool calls found to thin",
+                    current_threshold), 0)
+        }
+    }
+
+    /// Emergency shrinking for a conversation that no longer fits in `budget` tokens (as
+    /// counted for `model`). Tool results are moved to files oldest first, and if that is not
+    /// enough the oldest messages after the system prompt are dropped. The latest message is
+    /// always kept, so the result may still be over budget.
+    pub fn shrink_to_fit(&mut self, model: &str, budget: u32) -> ShrinkOutcome {
+        let mut counts: Vec<u32> = self
+            .conversation_history
+            .iter()
+            .map(|m| tokenizer::count_message_tokens(model, m))
+            .collect();
+        let mut tokens: u32 = counts.iter().sum();
+        let mut outcome = ShrinkOutcome::default();
+
+        let store = match &self.thinning.output_dir {
+            Some(dir) => ArtifactStore::new(shellexpand::tilde(dir).as_ref()),
+            None => self.artifacts.clone(),
+        };
+        for (i, (message, count)) in self
+            .conversation_history
+            .iter_mut()
+            .zip(counts.iter_mut())
+            .enumerate()
+        {
+            if tokens <= budget {
+                break;
+            }
+            if !matches!(message.role, MessageRole::User)
+                || !message.content.starts_with("Tool result:")
+            {
+                continue;
+            }
+            let reference = match store.save(
+                ArtifactKind::ThinnedToolResult,
+                &format!("tool_result_{}.txt", i),
+                message.content.as_bytes(),
+                None,
+            ) {
+                Ok(path) => format!("Tool result saved to {}", path.display()),
+                Err(e) => {
+                    warn!("Failed to save tool result {}: {}", i, e);
+                    "Tool result removed to fit the context window".to_string()
+                }
+            };
+            message.content = reference;
+            let new_count = tokenizer::count_message_tokens(model, message);
+            tokens = tokens - *count + new_count;
+            *count = new_count;
+            outcome.tool_results += 1;
+        }
+
+        // The conversation has to keep starting with a user message after the system prompt
+        let first = self
+            .conversation_history
+            .iter()
+            .position(|m| !matches!(m.role, MessageRole::System))
+            .unwrap_or(self.conversation_history.len());
+        while first + 1 < self.conversation_history.len()
+            && (tokens > budget
+                || (outcome.messages_dropped > 0
+                    && !matches!(self.conversation_history[first].role, MessageRole::User)))
+        {
+            self.conversation_history.remove(first);
+            tokens -= counts.remove(first);
+            outcome.messages_dropped += 1;
+        }
+        if outcome.messages_dropped > 0 {
+            let message = &mut self.conversation_history[first];
+            message.content = format!(
+                "[{} earlier messages were removed to fit the context window]\n\n{}",
+                outcome.messages_dropped, message.content
+            );
+            let count = tokenizer::count_message_tokens(model, message);
+            tokens = tokens - counts[first] + count;
+        }
+
+        self.recalculate_tokens();
+        outcome.tokens = tokens;
+        outcome
+    }
+
+    /// Save a tool call's large payload argument (write_file content, str_replace diff,
+    /// edit_symbol new_code) to a file and replace it with a reference.
+    /// Returns the number of chars removed from the message
+    fn thin_tool_call_payload(
+        message: &mut Message,
+        arg_name: &str,
+        store: &ArtifactStore,
+        index: usize,
+    ) -> Option<usize> {
+        let (start, len, mut tool_call) = Self::find_tool_call(&message.content)?;
+        let args_obj = tool_call.args.as_object_mut()?;
+        let payload = args_obj.get(arg_name)?.as_str()?.to_string();
+        let original_len = message.content.len();
+
+        let file_path = match store.save(
+            ArtifactKind::ThinnedToolCall,
+            &format!("{}_{}_{}.txt", tool_call.tool, arg_name, index),
+            payload.as_bytes(),
+            Some(&tool_call.tool),
+        ) {
+            Ok(path) => path.display().to_string(),
+            Err(e) => {
+                warn!(
+                    "Failed to save thinned {} {}: {}",
+                    tool_call.tool, arg_name, e
+                );
+                return None;
+            }
+        };
+        args_obj.insert(
+            arg_name.to_string(),
+            serde_json::Value::String(format!("<{} saved to {}>", arg_name, file_path)),
+        );
+
+        // Reconstruct the message around the modified tool call
+        let new_json = serde_json::to_string(&tool_call).ok()?;
+        message.content = format!(
+            "{}{}{}",
+            &message.content[..start],
+            new_json,
+            &message.content[start + len..]
+        );
+
+        debug!(
+            "Thinned {} {} {} ({} chars) to {}",
+            tool_call.tool,
+            arg_name,
+            index,
+            payload.len(),
+            file_path
+        );
+        // The reference takes up room too, so only the difference is saved
+        Some(original_len.saturating_sub(message.content.len()))
+    }
+
+    /// The argument holding a tool's large payload, for tools whose calls can be thinned
+    fn thinnable_payload_arg(tool: &str) -> Option<&'static str> {
+        match tool {
+            "write_file" => Some("content"),
+            "str_replace" => Some("diff"),
+            "edit_symbol" => Some("new_code"),
+            _ => None,
+        }
+    }
+
+    /// Locate and parse the first JSON tool call in an assistant message.
+    /// Returns its byte offset, length and the parsed call
+    fn find_tool_call(content: &str) -> Option<(usize, usize, ToolCall)> {
+        let tool_call_start = content
+            .find(r#"{"tool":"#)
+            .or_else(|| content.find(r#"{ "tool":"#))
+            .or_else(|| content.find(r#"{"tool" :"#))
+            .or_else(|| content.find(r#"{ "tool" :"#))?;
+        let json_portion = &content[tool_call_start..];
+        let json_end = Self::find_json_end(json_portion)?;
+        let json_str = &json_portion[..=json_end];
+        let tool_call = serde_json::from_str::<ToolCall>(json_str).ok()?;
+        Some((tool_call_start, json_str.len(), tool_call))
+    }
+
+    /// Whether the message at `index` is the result of a todo_read or todo_write call
+    fn follows_todo_call(&self, index: usize) -> bool {
+        index
+            .checked_sub(1)
+            .and_then(|prev| self.conversation_history.get(prev))
+            .is_some_and(|prev_message| {
+                matches!(prev_message.role, MessageRole::Assistant)
+                    && (prev_message.content.contains(r#""tool":"todo_read""#)
+                        || prev_message.content.contains(r#""tool":"todo_write""#)
+                        || prev_message.content.contains(r#""tool": "todo_read""#)
+                        || prev_message.content.contains(r#""tool": "todo_write""#))
+            })
+    }
+
+    /// Recalculate token usage based on current conversation history
+    pub(crate) fn recalculate_tokens(&mut self) {
+        let mut total = 0;
+        for message in &self.conversation_history {
+            total += Self::estimate_tokens(&message.content);
+        }
+        self.used_tokens = total;
+
+        debug!("Recalculated tokens after thinning: {} tokens", total);
+    }
+
+    /// Helper function to find the end of a JSON object
+    fn find_json_end(json_str: &str) -> Option<usize> {
+        let mut brace_count = 0;
+        let mut in_string = false;
+        let mut escape_next = false;
+
+        for (i, ch) in json_str.char_indices() {
+            if escape_next {
+                escape_next = false;
+                continue;
+            }
+
+            match ch {
+                '\\' => escape_next = true,
+                '"' if !escape_next => in_string = !in_string,
+                '{' if !in_string => brace_count += 1,
+                '}' if !in_string => {
+                    brace_count -= 1;
+                    if brace_count == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compacts once the conversation has more than a few messages, keeping the last one
+    #[derive(Debug)]
+    struct KeepLast(usize);
+
+    impl CompactionStrategy for KeepLast {
+        fn should_compact(&self, context: &ContextWindow) -> bool {
+            context.conversation_history.len() > self.0
+        }
+
+        fn summary_prompt(&self, _context: &ContextWindow) -> String {
+            "Summarize".to_string()
+        }
+
+        fn compact(
+            &self,
+            context: &mut ContextWindow,
+            summary: String,
+            _latest_user_message: Option<String>,
+        ) -> usize {
+            let old_chars = context.history_chars();
+            let last = context.conversation_history.pop();
+            context.conversation_history.clear();
+            context.add_message(Message {
+                role: MessageRole::System,
+                content: summary,
+            });
+            context.conversation_history.extend(last);
+            context.recalculate_tokens();
+            old_chars.saturating_sub(context.history_chars())
+        }
+    }
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: MessageRole::User,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_context_breakdown() {
+        let mut context = ContextWindow::new(10000);
+        context.add_message(Message {
+            role: MessageRole::System,
+            content: "You are a coding agent.".to_string(),
+        });
+        context.add_message(user("Fix the build"));
+        context.add_message(Message {
+            role: MessageRole::Assistant,
+            content: r#"Let me look. {"tool":"read_file","args":{"file_path":"src/main.rs"}}"#
+                .to_string(),
+        });
+        context.add_message(user(&format!("Tool result: {}", "fn main() {}\n".repeat(50))));
+
+        let breakdown = context.breakdown("gpt-4o");
+        assert_eq!(
+            breakdown.total,
+            breakdown.system + breakdown.user + breakdown.assistant
+        );
+        assert_eq!(breakdown.by_source.values().sum::<u32>(), breakdown.total);
+        assert!(breakdown.by_source[&ContextSource::ToolCalls] > 0);
+        assert!(breakdown.by_source[&ContextSource::AssistantText] > 0);
+
+        let largest = &breakdown.largest[0];
+        assert_eq!(largest.index, 3);
+        assert_eq!(largest.tool.as_deref(), Some("read_file"));
+        assert!(largest.preview.starts_with("Tool result: fn main() {}"));
+        assert_eq!(breakdown.largest.len(), 4);
+        assert!(breakdown.percentage(breakdown.by_source[&ContextSource::ToolResults]) > 50.0);
+    }
+
+    #[test]
+    fn test_tool_context_ledger() {
+        let mut ledger = ToolContextLedger::default();
+        assert!(ledger.is_empty());
+        assert_eq!(ledger.percentage(10), 0.0);
+
+        ledger.record("read_file", 300);
+        ledger.record("webdriver_get_page_source", 1200);
+        ledger.record("read_file", 300);
+        ledger.record("todo_read", 0);
+
+        assert_eq!(ledger.total(), 1800);
+        let leaderboard = ledger.leaderboard();
+        assert_eq!(
+            leaderboard
+                .iter()
+                .map(|(tool, c)| (*tool, c.results, c.tokens))
+                .collect::<Vec<_>>(),
+            vec![
+                ("webdriver_get_page_source", 1, 1200),
+                ("read_file", 2, 600),
+                ("todo_read", 1, 0),
+            ]
+        );
+        assert!((ledger.percentage(leaderboard[0].1.tokens) - 66.7).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_compaction_strategy() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ContextWindow>();
+
+        let mut context = ContextWindow::new(1_000_000);
+        for i in 0..3 {
+            context.add_message(user(&format!("message {}", i)));
+        }
+        // The default strategy waits for 80% of the window
+        assert!(!context.should_summarize());
+        assert!(context
+            .create_summary_prompt()
+            .contains("comprehensive summary"));
+
+        let mut context = context.with_compaction(Arc::new(KeepLast(2)));
+        assert!(context.should_summarize());
+        assert_eq!(context.create_summary_prompt(), "Summarize");
+        let saved = context.reset_with_summary("s".to_string(), None);
+        assert_eq!(saved, "message 0message 1".len() - 1);
+        assert_eq!(context.conversation_history.len(), 2);
+        assert_eq!(context.conversation_history[1].content, "message 2");
+        assert!(!context.should_summarize());
+
+        // The default keeps the summary and the latest user message
+        let mut context = context.with_compaction(Arc::new(SummaryCompaction));
+        context.reset_with_summary("done".to_string(), Some("next".to_string()));
+        let contents: Vec<&str> = context
+            .conversation_history
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            vec!["Previous conversation summary:\n\ndone", "next"]
+        );
+    }
+
+    #[test]
+    fn test_thinned_payload_counts_the_reference_it_leaves() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = ArtifactStore::new(dir.path());
+        let call = |content: &str| Message {
+            role: MessageRole::Assistant,
+            content: format!(
+                r#"{{"tool": "write_file", "args": {{"file_path": "a.txt", "content": "{}"}}}}"#,
+                content
+            ),
+        };
+
+        for content in ["x".repeat(2000), "short".to_string()] {
+            let mut message = call(&content);
+            let before = message.content.len();
+            let saved = ContextWindow::thin_tool_call_payload(&mut message, "content", &store, 0);
+            assert!(message.content.contains("<content saved to"));
+            assert_eq!(saved, Some(before.saturating_sub(message.content.len())));
+        }
+    }
+}
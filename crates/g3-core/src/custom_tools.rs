@@ -0,0 +1,202 @@
+//! Tools added at runtime by a program embedding g3-core.
+//!
+//! [`Agent::register_tool`](crate::Agent::register_tool) offers a [`Tool`] definition to the
+//! model and answers its calls with a [`CustomToolHandler`], usually an async closure:
+//!
+//! ```rust,no_run
+//! # use g3_core::{ui_writer::NullUiWriter, Agent};
+//! # async fn example(agent: &mut Agent<NullUiWriter>) -> anyhow::Result<()> {
+//! use g3_providers::Tool;
+//! use serde_json::{json, Value};
+//!
+//! let definition = Tool {
+//!     name: "ticket_lookup".to_string(),
+//!     description: "Look up a ticket in the issue tracker by its key".to_string(),
+//!     input_schema: json!({
+//!         "type": "object",
+//!         "properties": {"key": {"type": "string"}},
+//!         "required": ["key"],
+//!     }),
+//! };
+//! agent.register_read_only_tool(definition, |args: Value| async move {
+//!     let key = args["key"].as_str().unwrap_or_default().to_string();
+//!     Ok(format!("{}: Login fails on Safari (open)", key))
+//! })?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Custom tools are named like built-in ones but can't take their names, or the `__` of
+//! plugin and Unity Catalog tools. Like plugin tools, only those registered as read-only are
+//! offered in read-only mode, and a profile's `tools` allowlist applies to them.
+
+use crate::plugins::SEPARATOR;
+use crate::tool_registry::{external_tools_reference, ToolRegistry};
+use anyhow::{bail, Result};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use g3_providers::Tool;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Longest tool name the providers accept
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Answers calls of a custom tool. The result, or the error, is what the model sees.
+pub trait CustomToolHandler: Send + Sync {
+    fn call(&self, args: Value) -> BoxFuture<'static, Result<String>>;
+}
+
+impl<F, Fut> CustomToolHandler for F
+where
+    F: Fn(Value) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String>> + Send + 'static,
+{
+    fn call(&self, args: Value) -> BoxFuture<'static, Result<String>> {
+        self(args).boxed()
+    }
+}
+
+/// A registered tool
+#[derive(Clone)]
+pub struct CustomTool {
+    pub definition: Tool,
+    /// The tool doesn't modify anything, so it may be used in read-only mode
+    pub read_only: bool,
+    handler: Arc<dyn CustomToolHandler>,
+}
+
+impl CustomTool {
+    /// Run the tool. The call doesn't borrow the tool, so the agent isn't held while it runs.
+    pub fn call(&self, args: Value) -> BoxFuture<'static, Result<String>> {
+        self.handler.call(args)
+    }
+}
+
+impl std::fmt::Debug for CustomTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomTool")
+            .field("name", &self.definition.name)
+            .field("read_only", &self.read_only)
+            .finish()
+    }
+}
+
+/// The tools registered with an agent, in the order they were registered
+#[derive(Debug, Default)]
+pub struct CustomTools {
+    tools: Vec<CustomTool>,
+}
+
+impl CustomTools {
+    /// Add a tool, or fail if its name is invalid or taken
+    pub fn register(
+        &mut self,
+        definition: Tool,
+        read_only: bool,
+        handler: Arc<dyn CustomToolHandler>,
+    ) -> Result<()> {
+        let name = definition.name.as_str();
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if name.is_empty() || name.len() > MAX_NAME_LENGTH || !valid {
+            bail!(
+                "Invalid tool name '{}': use up to {} letters, digits, '_' and '-'",
+                name,
+                MAX_NAME_LENGTH
+            );
+        }
+        if name.contains(SEPARATOR) {
+            bail!(
+                "Invalid tool name '{}': '{}' is reserved for plugin tools",
+                name,
+                SEPARATOR
+            );
+        }
+        if ToolRegistry::builtin().get(name).is_some() {
+            bail!("'{}' is the name of a built-in tool", name);
+        }
+        if self.find(name).is_some() {
+            bail!("A tool named '{}' is already registered", name);
+        }
+        if !definition.input_schema.is_object() {
+            bail!("The input schema of '{}' isn't a JSON object", name);
+        }
+        self.tools.push(CustomTool {
+            definition,
+            read_only,
+            handler,
+        });
+        Ok(())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&CustomTool> {
+        self.tools.iter().find(|tool| tool.definition.name == name)
+    }
+
+    /// Tool definitions for all custom tools, or only the read-only ones
+    pub fn tools(&self, read_only: bool) -> Vec<Tool> {
+        self.tools
+            .iter()
+            .filter(|tool| tool.read_only || !read_only)
+            .map(|tool| tool.definition.clone())
+            .collect()
+    }
+
+    /// The custom tools described for providers without native tool calling
+    pub fn prompt_reference(&self, read_only: bool) -> String {
+        external_tools_reference("Application Tools", &self.tools(read_only))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn definition(name: &str) -> Tool {
+        Tool {
+            name: name.to_string(),
+            description: "Look up a ticket".to_string(),
+            input_schema: json!({"type": "object", "properties": {"key": {"type": "string"}}}),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_and_call() {
+        let mut tools = CustomTools::default();
+        let handler = Arc::new(|args: Value| async move {
+            match args["key"].as_str() {
+                Some(key) => Ok(format!("{} is open", key)),
+                None => bail!("no key"),
+            }
+        });
+        tools
+            .register(definition("ticket_lookup"), true, handler.clone())
+            .unwrap();
+        tools
+            .register(definition("ticket_close"), false, handler.clone())
+            .unwrap();
+
+        let tool = tools.find("ticket_lookup").unwrap();
+        let result = tool.call(json!({"key": "CORE-1"})).await.unwrap();
+        assert_eq!(result, "CORE-1 is open");
+        assert!(tool.call(json!({})).await.is_err());
+
+        assert_eq!(tools.tools(false).len(), 2);
+        let read_only = tools.tools(true);
+        assert_eq!(read_only.len(), 1);
+        assert_eq!(read_only[0].name, "ticket_lookup");
+        assert!(tools
+            .prompt_reference(false)
+            .contains("**ticket_close** (key): Look up a ticket"));
+
+        for name in ["ticket_lookup", "read_file", "jira__search", "bad name", ""] {
+            assert!(tools
+                .register(definition(name), false, handler.clone())
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! `g3 daemon`: keeps providers, and the models of embedded providers, loaded for every g3
+//! invocation of the user to share over a unix socket.
+//!
+//! Agents still run in their own process, in their own workspace; only completion requests
+//! go to the daemon. While a daemon is listening, [`Agent`](crate::Agent)s register a
+//! [`DaemonProvider`] in place of each provider. The daemon builds a provider the first time
+//! a client asks for it and keeps it for every client with the same settings, so an
+//! embedded model is loaded once rather than on every start.
+//!
+//! The protocol is one JSON request line per connection, answered with JSON reply lines.
+
+use anyhow::{Context, Result};
+use g3_config::{Config, ProvidersConfig};
+use g3_providers::{
+    Capabilities, CompletionChunk, CompletionRequest, CompletionResponse, CompletionStream,
+    ImageContent, LLMProvider,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::OnceCell;
+use tokio_stream::StreamExt;
+use tracing::{debug, info, warn};
+
+use crate::{ui_writer::NullUiWriter, Agent};
+
+/// Environment variable overriding [`socket_path`]
+pub const SOCKET_ENV: &str = "G3_DAEMON_SOCKET";
+
+/// Where the daemon listens: `$G3_DAEMON_SOCKET`, or `~/.config/g3/daemon.sock`
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os(SOCKET_ENV) {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(shellexpand::tilde("~/.config/g3/daemon.sock").as_ref()),
+    }
+}
+
+/// The socket of the running daemon, if one is listening
+pub async fn running() -> Option<PathBuf> {
+    let socket = socket_path();
+    if !socket.exists() {
+        return None;
+    }
+    match UnixStream::connect(&socket).await {
+        Ok(_) => Some(socket),
+        Err(e) => {
+            debug!("No daemon listening on {}: {}", socket.display(), e);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    /// Name of the provider to use
+    provider: String,
+    /// The client's provider settings, so the daemon uses the same model and credentials
+    providers: ProvidersConfig,
+    #[serde(flatten)]
+    op: Op,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Op {
+    Info,
+    Complete {
+        request: CompletionRequest,
+    },
+    Stream {
+        request: CompletionRequest,
+    },
+    Image {
+        prompt: String,
+        media_type: String,
+        data: Vec<u8>,
+        max_tokens: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProviderInfo {
+    name: String,
+    model: String,
+    native_tool_calling: bool,
+    capabilities: Capabilities,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+    Info(ProviderInfo),
+    Response(CompletionResponse),
+    Chunk(CompletionChunk),
+    Error { message: String },
+}
+
+/// A provider of the daemon, used like a local one
+pub struct DaemonProvider {
+    socket: PathBuf,
+    providers: ProvidersConfig,
+    info: ProviderInfo,
+}
+
+impl DaemonProvider {
+    /// Connect to provider `name` of the daemon at `socket`, which loads it if it hasn't yet
+    pub async fn connect(socket: &Path, name: &str, providers: &ProvidersConfig) -> Result<Self> {
+        let mut provider = Self {
+            socket: socket.to_path_buf(),
+            providers: providers.clone(),
+            info: ProviderInfo {
+                name: name.to_string(),
+                model: String::new(),
+                native_tool_calling: false,
+                capabilities: Capabilities::default(),
+            },
+        };
+        match provider.call(Op::Info).await? {
+            Reply::Info(info) => provider.info = info,
+            other => anyhow::bail!("Unexpected reply from the g3 daemon: {:?}", other),
+        }
+        Ok(provider)
+    }
+
+    /// Send a request and return the reply lines
+    async fn send(&self, op: Op) -> Result<Lines<BufReader<OwnedReadHalf>>> {
+        let stream = UnixStream::connect(&self.socket).await.with_context(|| {
+            format!(
+                "Failed to connect to the g3 daemon at {}",
+                self.socket.display()
+            )
+        })?;
+        let (read, mut write) = stream.into_split();
+        let request = Request {
+            provider: self.info.name.clone(),
+            providers: self.providers.clone(),
+            op,
+        };
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        write.write_all(line.as_bytes()).await?;
+        Ok(BufReader::new(read).lines())
+    }
+
+    /// Send a request that has a single reply
+    async fn call(&self, op: Op) -> Result<Reply> {
+        let mut lines = self.send(op).await?;
+        let line = lines
+            .next_line()
+            .await?
+            .context("The g3 daemon closed the connection without replying")?;
+        parse_reply(&line)
+    }
+}
+
+fn parse_reply(line: &str) -> Result<Reply> {
+    match serde_json::from_str(line)? {
+        Reply::Error { message } => Err(anyhow::anyhow!(message)),
+        reply => Ok(reply),
+    }
+}
+
+#[async_trait::async_trait]
+impl LLMProvider for DaemonProvider {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        match self.call(Op::Complete { request }).await? {
+            Reply::Response(response) => Ok(response),
+            other => anyhow::bail!("Unexpected reply from the g3 daemon: {:?}", other),
+        }
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let mut lines = self.send(Op::Stream { request }).await?;
+        let (tx, rx) = tokio::sync::mpsc::channel(100);
+        tokio::spawn(async move {
+            loop {
+                let chunk = match lines.next_line().await {
+                    Ok(Some(line)) => match parse_reply(&line) {
+                        Ok(Reply::Chunk(chunk)) => Ok(chunk),
+                        Ok(other) => Err(anyhow::anyhow!(
+                            "Unexpected reply from the g3 daemon: {:?}",
+                            other
+                        )),
+                        Err(e) => Err(e),
+                    },
+                    Ok(None) => break,
+                    Err(e) => Err(e.into()),
+                };
+                let failed = chunk.is_err();
+                if tx.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(CompletionStream::new(rx))
+    }
+
+    fn name(&self) -> &str {
+        &self.info.name
+    }
+
+    fn model(&self) -> &str {
+        &self.info.model
+    }
+
+    fn has_native_tool_calling(&self) -> bool {
+        self.info.native_tool_calling
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.info.capabilities
+    }
+
+    async fn complete_with_image(
+        &self,
+        prompt: &str,
+        image: &ImageContent,
+        max_tokens: Option<u32>,
+    ) -> Result<CompletionResponse> {
+        let op = Op::Image {
+            prompt: prompt.to_string(),
+            media_type: image.media_type.clone(),
+            data: image.data.clone(),
+            max_tokens,
+        };
+        match self.call(op).await? {
+            Reply::Response(response) => Ok(response),
+            other => anyhow::bail!("Unexpected reply from the g3 daemon: {:?}", other),
+        }
+    }
+}
+
+/// A provider of the pool, built by the first client asking for it
+type Slot = Arc<OnceCell<Arc<dyn LLMProvider>>>;
+
+/// Providers the daemon has built, by name and settings. None is ever dropped: each one asked
+/// for, such as an embedded provider with another `model_path`, stays loaded until the
+/// daemon stops.
+#[derive(Default)]
+struct Pool {
+    providers: std::sync::Mutex<HashMap<String, Slot>>,
+}
+
+impl Pool {
+    async fn get(&self, name: &str, providers: &ProvidersConfig) -> Result<Arc<dyn LLMProvider>> {
+        let key = provider_key(name, providers)?;
+        // Clients asking for the same model wait for it to load once, while other models
+        // are served or loaded meanwhile. A provider that fails to build is tried again by
+        // the next client.
+        let cell = self
+            .providers
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .clone();
+        cell.get_or_try_init(|| Self::build(name, providers))
+            .await
+            .cloned()
+    }
+
+    async fn build(name: &str, providers: &ProvidersConfig) -> Result<Arc<dyn LLMProvider>> {
+        info!("Loading provider {}", name);
+        let mut config = Config {
+            providers: providers.clone(),
+            ..Default::default()
+        };
+        config.providers.default_provider = name.to_string();
+        config.agent.use_daemon = false;
+        let provider: Arc<dyn LLMProvider> = Agent::<NullUiWriter>::build_providers(&config, false)
+            .await?
+            .into_default()
+            .with_context(|| format!("Provider '{}' is not configured", name))?
+            .into();
+        info!("Loaded {} / {}", provider.name(), provider.model());
+        Ok(provider)
+    }
+}
+
+/// Identifies a provider by its name and its own settings only, so that clients with other
+/// differences in their provider config still share it
+fn provider_key(name: &str, providers: &ProvidersConfig) -> Result<String> {
+    let all = serde_json::to_value(providers)?;
+    let settings = all
+        .get(name)
+        .or_else(|| {
+            all.get("openai_compatible")
+                .and_then(|named| named.get(name))
+        })
+        .cloned()
+        .unwrap_or_default();
+    Ok(format!("{}:{}", name, settings))
+}
+
+/// Serve the providers of clients on `socket` until interrupted, starting with the default
+/// provider of `config` loaded
+pub async fn serve(config: &Config, socket: &Path) -> Result<()> {
+    if UnixStream::connect(socket).await.is_ok() {
+        anyhow::bail!("A g3 daemon is already listening on {}", socket.display());
+    }
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Left behind by a daemon that didn't shut down cleanly
+    let _ = std::fs::remove_file(socket);
+
+    // Clients send their provider's secrets resolved, so it is loaded with them resolved too,
+    // or its settings wouldn't match theirs and it would be loaded a second time
+    let name = &config.providers.default_provider;
+    let mut providers = config.providers.clone();
+    providers.resolve_secrets_of(name)?;
+    let pool = Arc::new(Pool::default());
+    pool.get(name, &providers).await?;
+
+    let listener = listen(socket)?;
+    info!("Listening on {}", socket.display());
+
+    let result = loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    let pool = pool.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle(stream, &pool).await {
+                            debug!("Daemon client failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => break Err(e.into()),
+            },
+        }
+    };
+    let _ = std::fs::remove_file(socket);
+    result
+}
+
+/// Listen on `socket`, which only its owner can connect to. The socket is bound and made
+/// private in a directory only the owner can enter, and then moved into place, so no other
+/// user can connect in between.
+fn listen(socket: &Path) -> Result<UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let parent = socket.parent().unwrap_or(Path::new("."));
+    let private = parent.join(format!(".g3-daemon-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&private)
+        .with_context(|| format!("Failed to create {}", private.display()))?;
+    let bound = private.join("daemon.sock");
+    let listener = UnixListener::bind(&bound)
+        .and_then(|listener| {
+            std::fs::set_permissions(&bound, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&bound, socket)?;
+            Ok(listener)
+        })
+        .with_context(|| format!("Failed to listen on {}", socket.display()));
+    let _ = std::fs::remove_dir_all(&private);
+    listener
+}
+
+async fn handle(stream: UnixStream, pool: &Pool) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let Some(line) = BufReader::new(read).lines().next_line().await? else {
+        // Connections that check whether the daemon is running send nothing
+        return Ok(());
+    };
+    let request: Request = serde_json::from_str(&line)?;
+    debug!("Daemon request for {}", request.provider);
+
+    if let Err(e) = respond(request, pool, &mut write).await {
+        warn!("Daemon request failed: {}", e);
+        let reply = Reply::Error {
+            message: e.to_string(),
+        };
+        write_reply(&mut write, &reply).await?;
+    }
+    Ok(())
+}
+
+async fn respond(
+    request: Request,
+    pool: &Pool,
+    write: &mut (impl AsyncWriteExt + Unpin),
+) -> Result<()> {
+    let provider = pool.get(&request.provider, &request.providers).await?;
+    let reply = match request.op {
+        Op::Info => Reply::Info(ProviderInfo {
+            name: provider.name().to_string(),
+            model: provider.model().to_string(),
+            native_tool_calling: provider.has_native_tool_calling(),
+            capabilities: provider.capabilities(),
+        }),
+        Op::Complete { request } => Reply::Response(provider.complete(request).await?),
+        Op::Image {
+            prompt,
+            media_type,
+            data,
+            max_tokens,
+        } => {
+            let image = ImageContent { media_type, data };
+            Reply::Response(
+                provider
+                    .complete_with_image(&prompt, &image, max_tokens)
+                    .await?,
+            )
+        }
+        Op::Stream { request } => {
+            let mut stream = provider.stream(request).await?;
+            while let Some(chunk) = stream.next().await {
+                // Fails once the client has gone, which drops the provider's stream
+                write_reply(write, &Reply::Chunk(chunk?)).await?;
+            }
+            return Ok(());
+        }
+    };
+    write_reply(write, &reply).await
+}
+
+async fn write_reply(write: &mut (impl AsyncWriteExt + Unpin), reply: &Reply) -> Result<()> {
+    let mut line = serde_json::to_string(reply)?;
+    line.push('\n');
+    write.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[tokio::test]
+    async fn test_socket_is_private_from_the_start() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("daemon.sock");
+
+        let listener = listen(&socket).unwrap();
+
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Only the socket is left behind
+        let entries: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        let (_client, accepted) = tokio::join!(UnixStream::connect(&socket), listener.accept());
+        assert!(accepted.is_ok());
+    }
+}
@@ -0,0 +1,156 @@
+//! Waiting for files a browser downloads.
+//!
+//! Browsers write a download under a temporary name (`.download` bundles in Safari,
+//! `.crdownload` in Chrome, `.part` in Firefox) and rename it once it is complete, so a
+//! download counts as finished when a file matching the pattern exists under its final
+//! name and its size holds still between two checks. Only files modified since the wait's
+//! starting point count, so an older download with the same name isn't mistaken for it.
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::g3ignore::glob_to_regex;
+
+/// Where downloads are looked for unless `webdriver.download_dir` or
+/// `webdriver_set_download_dir` says otherwise
+pub const DEFAULT_DOWNLOAD_DIR: &str = "~/Downloads";
+
+/// Endings of files browsers are still downloading
+const IN_PROGRESS_SUFFIXES: &[&str] = &[".download", ".crdownload", ".part"];
+
+/// Leeway for file systems that store modification times coarsely
+const MTIME_SLACK: Duration = Duration::from_secs(2);
+
+/// What a check of the download directory found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadState {
+    Finished {
+        path: PathBuf,
+        size: u64,
+    },
+    /// Names of matching downloads that are still being written
+    InProgress(Vec<String>),
+    Missing,
+}
+
+/// Watches a directory for a download whose name matches a glob pattern
+#[derive(Debug)]
+pub struct DownloadWatch {
+    dir: PathBuf,
+    pattern: Regex,
+    since: SystemTime,
+    /// Sizes seen at the last check, to tell when a file has stopped growing
+    sizes: HashMap<PathBuf, u64>,
+}
+
+impl DownloadWatch {
+    /// Watch `dir` for files matching `pattern` (`*`, `?` and `[...]` wildcards) modified
+    /// at or after `since`
+    pub fn new(dir: &Path, pattern: &str, since: SystemTime) -> Result<Self, String> {
+        let pattern = Regex::new(&format!("^{}$", glob_to_regex(pattern)))
+            .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            pattern,
+            since: since.checked_sub(MTIME_SLACK).unwrap_or(since),
+            sizes: HashMap::new(),
+        })
+    }
+
+    /// Look at the directory once
+    pub fn poll(&mut self) -> DownloadState {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return DownloadState::Missing;
+        };
+        let mut in_progress = Vec::new();
+        let mut sizes = HashMap::new();
+        let mut finished = None;
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata
+                .modified()
+                .is_ok_and(|modified| modified < self.since)
+            {
+                continue;
+            }
+            let partial = IN_PROGRESS_SUFFIXES
+                .iter()
+                .find_map(|suffix| name.strip_suffix(suffix));
+            match partial {
+                Some(final_name) if self.pattern.is_match(final_name) => in_progress.push(name),
+                Some(_) => {}
+                None if metadata.is_file() && self.pattern.is_match(&name) => {
+                    let path = entry.path();
+                    let size = metadata.len();
+                    if self.sizes.get(&path) == Some(&size) && finished.is_none() {
+                        finished = Some((path.clone(), size));
+                    }
+                    sizes.insert(path, size);
+                }
+                None => {}
+            }
+        }
+        self.sizes = sizes;
+
+        match finished {
+            Some((path, size)) if in_progress.is_empty() => DownloadState::Finished { path, size },
+            _ if !in_progress.is_empty() => DownloadState::InProgress(in_progress),
+            // Files that just appeared are checked again before they count as finished
+            _ if !self.sizes.is_empty() => DownloadState::InProgress(
+                self.sizes
+                    .keys()
+                    .filter_map(|path| path.file_name())
+                    .map(|name| name.to_string_lossy().to_string())
+                    .collect(),
+            ),
+            _ => DownloadState::Missing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_watch() {
+        let dir = tempfile::tempdir().unwrap();
+        let since = SystemTime::now();
+        let mut watch = DownloadWatch::new(dir.path(), "report*.csv", since).unwrap();
+        assert_eq!(watch.poll(), DownloadState::Missing);
+
+        std::fs::write(dir.path().join("notes.txt"), "unrelated").unwrap();
+        std::fs::create_dir(dir.path().join("report-2024.csv.download")).unwrap();
+        assert_eq!(
+            watch.poll(),
+            DownloadState::InProgress(vec!["report-2024.csv.download".to_string()])
+        );
+
+        std::fs::remove_dir(dir.path().join("report-2024.csv.download")).unwrap();
+        std::fs::write(dir.path().join("report-2024.csv"), "a,b\n1,2\n").unwrap();
+        // A file is finished once its size is the same at two checks
+        assert!(matches!(watch.poll(), DownloadState::InProgress(_)));
+        assert_eq!(
+            watch.poll(),
+            DownloadState::Finished {
+                path: dir.path().join("report-2024.csv"),
+                size: 8
+            }
+        );
+
+        // Files from before the wait started don't count
+        let mut later = DownloadWatch::new(
+            dir.path(),
+            "*.csv",
+            SystemTime::now() + Duration::from_secs(60),
+        )
+        .unwrap();
+        later.poll();
+        assert_eq!(later.poll(), DownloadState::Missing);
+    }
+}
@@ -0,0 +1,205 @@
+//! Micro-commits of the agent's file edits (`commit_edits`).
+//!
+//! With `commit_edits` enabled, the first file edit of a session in a git repository starts
+//! a branch `g3/session-<id>` from the current commit, and every successful write_file,
+//! str_replace and edit_symbol after that is committed on it with a one-line message the
+//! model writes from the diff. Only the edited file is committed, so the history is one
+//! commit per edit and can be bisected, reverted or squashed with the usual git tools.
+//! Uncommitted changes from before the first edit are committed first, as with
+//! `commit_rounds`, so they aren't mistaken for the agent's.
+
+use anyhow::Result;
+use chrono::Local;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::run_commits::git;
+
+const BRANCH_PREFIX: &str = "g3/session-";
+
+/// Commit subjects are cut to this many characters
+const MAX_SUBJECT_CHARS: usize = 72;
+
+/// Diffs longer than this are cut before the model sees them
+const MAX_DIFF_CHARS: usize = 6000;
+
+/// Trailer that marks the agent's edit commits
+const TRAILER: &str = "G3-Edit";
+
+/// The session branch edits are committed to
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditCommits {
+    workspace: PathBuf,
+    pub branch: String,
+    /// Edits committed so far
+    pub commits: usize,
+}
+
+impl EditCommits {
+    /// Start the session branch in `workspace` and check it out. Returns `None` if the
+    /// workspace is not a git repository with at least one commit.
+    pub fn start(workspace: &Path) -> Result<Option<Self>> {
+        if git(workspace, &["rev-parse", "--verify", "HEAD"]).is_err() {
+            debug!("Not committing edits: no git history in workspace");
+            return Ok(None);
+        }
+        let branch = format!("{}{}", BRANCH_PREFIX, Local::now().format("%Y%m%d-%H%M%S"));
+        git(workspace, &["checkout", "-q", "-b", &branch])?;
+        git(workspace, &["add", "-A", "--", ".", ":(exclude).g3"])?;
+        if !git(workspace, &["diff", "--cached", "--name-only"])?
+            .trim()
+            .is_empty()
+        {
+            git(
+                workspace,
+                &[
+                    "commit",
+                    "-q",
+                    "--no-verify",
+                    "-m",
+                    "g3: uncommitted changes from before the first edit",
+                ],
+            )?;
+        }
+        Ok(Some(Self {
+            workspace: workspace.to_path_buf(),
+            branch,
+            commits: 0,
+        }))
+    }
+
+    /// Stage `path` and return its diff, or `None` if the edit left it as committed
+    pub fn stage(&self, path: &Path) -> Result<Option<String>> {
+        let path = path.to_string_lossy();
+        git(&self.workspace, &["add", "--", &path])?;
+        let diff = git(&self.workspace, &["diff", "--cached", "--", &path])?;
+        Ok((!diff.trim().is_empty()).then_some(diff))
+    }
+
+    /// Commit what is staged with `subject`, for an edit by `tool`. Returns the short hash.
+    pub fn commit(&mut self, subject: &str, tool: &str) -> Result<String> {
+        let message = format!("{}\n\n{}: {}\n", subject, TRAILER, tool);
+        git(
+            &self.workspace,
+            &["commit", "-q", "--no-verify", "-m", &message],
+        )?;
+        self.commits += 1;
+        Ok(git(&self.workspace, &["rev-parse", "--short", "HEAD"])?
+            .trim()
+            .to_string())
+    }
+}
+
+/// The request for a commit subject describing `diff`
+pub fn message_prompt(diff: &str) -> String {
+    let diff = match diff.char_indices().nth(MAX_DIFF_CHARS) {
+        Some((end, _)) => format!("{}\n[diff cut]", &diff[..end]),
+        None => diff.to_string(),
+    };
+    format!(
+        "Write a git commit subject line for this change: imperative mood, at most {} \
+         characters, no trailing period. Reply with the subject line only.\n\n```diff\n{}\n```",
+        MAX_SUBJECT_CHARS, diff
+    )
+}
+
+/// The subject in the model's reply, or `None` if it gave nothing usable
+pub fn parse_subject(reply: &str) -> Option<String> {
+    let line = reply
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix("Subject:")
+                .unwrap_or(line)
+                .trim()
+                .trim_matches(|c| matches!(c, '`' | '"' | '\'' | '*'))
+                .trim()
+                .trim_end_matches('.')
+        })
+        .find(|line| !line.is_empty())?;
+    Some(match line.char_indices().nth(MAX_SUBJECT_CHARS) {
+        Some((end, _)) => format!("{}…", line[..end].trim_end()),
+        None => line.to_string(),
+    })
+}
+
+/// Subject for when the model couldn't write one
+pub fn fallback_subject(tool: &str, path: &Path, workspace: &Path) -> String {
+    let path = path.strip_prefix(workspace).unwrap_or(path);
+    let verb = if tool == "write_file" {
+        "Write"
+    } else {
+        "Edit"
+    };
+    format!("{} {}", verb, path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_edits_are_committed_one_by_one() {
+        let workspace = TempDir::new().unwrap();
+        let dir = workspace.path();
+        git(dir, &["init", "-q"]).unwrap();
+        git(dir, &["config", "user.name", "g3"]).unwrap();
+        git(dir, &["config", "user.email", "g3@example.com"]).unwrap();
+        std::fs::write(dir.join("lib.rs"), "fn a() {}\n").unwrap();
+        git(dir, &["add", "."]).unwrap();
+        git(dir, &["commit", "-q", "-m", "initial"]).unwrap();
+        std::fs::write(dir.join("notes.txt"), "before the session").unwrap();
+
+        let mut commits = EditCommits::start(dir).unwrap().unwrap();
+        assert!(commits.branch.starts_with("g3/session-"));
+        assert_eq!(
+            git(dir, &["log", "-1", "--format=%s"]).unwrap().trim(),
+            "g3: uncommitted changes from before the first edit"
+        );
+
+        std::fs::write(dir.join("lib.rs"), "fn a() { b() }\n").unwrap();
+        std::fs::write(dir.join("other.rs"), "// not edited by the agent\n").unwrap();
+        let diff = commits.stage(&dir.join("lib.rs")).unwrap().unwrap();
+        assert!(diff.contains("+fn a() { b() }"));
+        commits.commit("Call b from a", "str_replace").unwrap();
+        assert_eq!(commits.commits, 1);
+        assert_eq!(
+            git(dir, &["log", "-1", "--format=%s%n%b"]).unwrap().trim(),
+            "Call b from a\nG3-Edit: str_replace"
+        );
+        // Only the edited file is committed
+        assert_eq!(
+            git(dir, &["status", "--porcelain"]).unwrap().trim(),
+            "?? other.rs"
+        );
+        assert_eq!(commits.stage(&dir.join("lib.rs")).unwrap(), None);
+
+        let empty = TempDir::new().unwrap();
+        git(empty.path(), &["init", "-q"]).unwrap();
+        assert_eq!(EditCommits::start(empty.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_commit_subjects() {
+        assert_eq!(
+            parse_subject("```\nAdd retry to the HTTP client.\n```"),
+            Some("Add retry to the HTTP client".to_string())
+        );
+        assert_eq!(
+            parse_subject("Subject: `Fix off-by-one in pager`"),
+            Some("Fix off-by-one in pager".to_string())
+        );
+        assert_eq!(parse_subject("  \n"), None);
+        assert_eq!(parse_subject(&"x".repeat(100)).unwrap().chars().count(), 73);
+        assert_eq!(
+            fallback_subject(
+                "write_file",
+                Path::new("/repo/src/lib.rs"),
+                Path::new("/repo")
+            ),
+            "Write src/lib.rs"
+        );
+        assert!(message_prompt(&"+line\n".repeat(2000)).contains("[diff cut]"));
+    }
+}
@@ -0,0 +1,287 @@
+//! Embedding g3 in other Rust programs.
+//!
+//! [`AgentBuilder`] makes an agent from a config and the options a front end would set,
+//! without choosing between the `Agent::new_*` constructors or writing a [`UiWriter`].
+//! [`Agent::run_task`] runs a task as a stream of the agent's events that ends with the
+//! task's result:
+//!
+//! ```rust,no_run
+//! use g3_core::events::AgentEvent;
+//! use g3_core::{AgentBuilder, TaskEvent};
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let config = g3_config::Config::load(None)?;
+//! let mut agent = AgentBuilder::new(config)
+//!     .quiet(true)
+//!     .tools(["read_file", "code_search", "final_output"])
+//!     .build()
+//!     .await?;
+//!
+//! let mut task = agent.run_task("Where are requests retried?");
+//! while let Some(event) = task.next().await {
+//!     match event {
+//!         TaskEvent::Event(AgentEvent::ToolStarted { tool, .. }) => eprintln!("[{}]", tool),
+//!         TaskEvent::Event(_) => {}
+//!         TaskEvent::Finished(result) => println!("{}", result?.response),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Agent::register_tool`] adds the program's own tools; see [`crate::custom_tools`].
+
+use crate::custom_tools::CustomToolHandler;
+use crate::events::{AgentEvent, ChannelSubscriber};
+use crate::ui_writer::{NullUiWriter, UiWriter};
+use crate::{Agent, TaskResult};
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use g3_config::Config;
+use g3_providers::Tool;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_stream::{Stream, StreamExt};
+
+/// Builds an [`Agent`]. Without [`AgentBuilder::ui_writer`] nothing is printed.
+pub struct AgentBuilder<W: UiWriter = NullUiWriter> {
+    config: Config,
+    ui_writer: W,
+    autonomous: bool,
+    quiet: bool,
+    readme: Option<String>,
+    tools: Option<Vec<String>>,
+}
+
+impl AgentBuilder<NullUiWriter> {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            ui_writer: NullUiWriter,
+            autonomous: false,
+            quiet: false,
+            readme: None,
+            tools: None,
+        }
+    }
+}
+
+impl<W: UiWriter> AgentBuilder<W> {
+    /// Render the agent's output with `ui_writer`
+    pub fn ui_writer<U: UiWriter>(self, ui_writer: U) -> AgentBuilder<U> {
+        AgentBuilder {
+            config: self.config,
+            ui_writer,
+            autonomous: self.autonomous,
+            quiet: self.quiet,
+            readme: self.readme,
+            tools: self.tools,
+        }
+    }
+
+    /// Run as the player of autonomous mode, with its retry limits
+    pub fn autonomous(mut self, autonomous: bool) -> Self {
+        self.autonomous = autonomous;
+        self
+    }
+
+    /// Don't write session logs or show startup status
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Project instructions added to the start of the conversation, as g3 does with
+    /// README.md and AGENTS.md
+    pub fn readme(mut self, readme: impl Into<String>) -> Self {
+        self.readme = Some(readme.into());
+        self
+    }
+
+    /// Offer only these built-in tools, by name. Plugin, Unity Catalog and registered tools
+    /// aren't affected.
+    pub fn tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tools = Some(tools.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Use this provider of the config instead of `providers.default_provider`
+    pub fn provider(mut self, provider: impl Into<String>) -> Self {
+        self.config.providers.default_provider = provider.into();
+        self
+    }
+
+    pub async fn build(self) -> Result<Agent<W>> {
+        let mut agent = Agent::new_with_mode_and_readme(
+            self.config,
+            self.ui_writer,
+            self.autonomous,
+            self.readme,
+            self.quiet,
+        )
+        .await?;
+        agent.allowed_tools = self.tools;
+        Ok(agent)
+    }
+}
+
+/// What a [`TaskStream`] yields
+// A stream has one result, so it isn't worth boxing to make the events smaller
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum TaskEvent {
+    /// Something the agent did, in the order it happened
+    Event(AgentEvent),
+    /// The task's result, always the last item
+    Finished(Result<TaskResult>),
+}
+
+/// A running task, from [`Agent::run_task`]. Dropping it cancels the task.
+pub struct TaskStream<'a> {
+    task: Option<BoxFuture<'a, Result<TaskResult>>>,
+    result: Option<Result<TaskResult>>,
+    events: UnboundedReceiver<AgentEvent>,
+    channel: Arc<ChannelSubscriber>,
+}
+
+impl<'a> TaskStream<'a> {
+    pub(crate) fn new(
+        task: BoxFuture<'a, Result<TaskResult>>,
+        channel: Arc<ChannelSubscriber>,
+    ) -> Self {
+        Self {
+            task: Some(task),
+            result: None,
+            events: channel.connect(),
+            channel,
+        }
+    }
+
+    /// Wait for the task to finish, skipping its events
+    pub async fn result(mut self) -> Result<TaskResult> {
+        while let Some(event) = self.next().await {
+            if let TaskEvent::Finished(result) = event {
+                return result;
+            }
+        }
+        unreachable!("a task stream ends with its result")
+    }
+}
+
+impl Stream for TaskStream<'_> {
+    type Item = TaskEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<TaskEvent>> {
+        // Events published before the task returned come out before its result
+        if let Ok(event) = self.events.try_recv() {
+            return Poll::Ready(Some(TaskEvent::Event(event)));
+        }
+        if let Some(task) = self.task.as_mut() {
+            match task.poll_unpin(cx) {
+                Poll::Ready(result) => {
+                    self.task = None;
+                    self.result = Some(result);
+                    self.channel.disconnect();
+                }
+                Poll::Pending => {
+                    return match self.events.poll_recv(cx) {
+                        Poll::Ready(Some(event)) => Poll::Ready(Some(TaskEvent::Event(event))),
+                        _ => Poll::Pending,
+                    };
+                }
+            }
+        }
+        if let Ok(event) = self.events.try_recv() {
+            return Poll::Ready(Some(TaskEvent::Event(event)));
+        }
+        Poll::Ready(self.result.take().map(TaskEvent::Finished))
+    }
+}
+
+impl Drop for TaskStream<'_> {
+    fn drop(&mut self) {
+        if self.task.is_some() {
+            self.channel.disconnect();
+        }
+    }
+}
+
+impl<W: UiWriter> Agent<W> {
+    /// Run `task` and stream what the agent does, ending with the result. Events still
+    /// reach the UI writer and other subscribers as usual.
+    pub fn run_task<'a>(&'a mut self, task: &'a str) -> TaskStream<'a> {
+        let channel = self
+            .task_events
+            .get_or_insert_with(|| {
+                let channel = Arc::new(ChannelSubscriber::default());
+                self.events.subscribe(channel.clone());
+                channel
+            })
+            .clone();
+        TaskStream::new(self.execute_task(task, None, false).boxed(), channel)
+    }
+
+    /// Offer the tool `definition` to the model, with calls answered by `handler`. The tool
+    /// may change things, so it isn't offered in read-only mode. Fails when the name is
+    /// invalid or already taken.
+    pub fn register_tool(
+        &mut self,
+        definition: Tool,
+        handler: impl CustomToolHandler + 'static,
+    ) -> Result<()> {
+        self.custom_tools
+            .register(definition, false, Arc::new(handler))
+    }
+
+    /// Like [`Agent::register_tool`], for a tool that only reads, which is also offered in
+    /// read-only mode
+    pub fn register_read_only_tool(
+        &mut self,
+        definition: Tool,
+        handler: impl CustomToolHandler + 'static,
+    ) -> Result<()> {
+        self.custom_tools
+            .register(definition, true, Arc::new(handler))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventSubscriber;
+
+    #[tokio::test]
+    async fn test_task_stream_yields_events_then_result() {
+        let channel = Arc::new(ChannelSubscriber::default());
+        let publisher = channel.clone();
+        let task = async move {
+            publisher.on_event(&AgentEvent::ResponseStarted);
+            tokio::task::yield_now().await;
+            publisher.on_event(&AgentEvent::ResponseFinished);
+            Err(anyhow::anyhow!("no provider"))
+        };
+        let events: Vec<TaskEvent> = TaskStream::new(task.boxed(), channel.clone())
+            .collect()
+            .await;
+        assert!(matches!(
+            events[..],
+            [
+                TaskEvent::Event(AgentEvent::ResponseStarted),
+                TaskEvent::Event(AgentEvent::ResponseFinished),
+                TaskEvent::Finished(Err(_)),
+            ]
+        ));
+
+        let task = async { Err(anyhow::anyhow!("no provider")) };
+        let result = TaskStream::new(task.boxed(), channel).result().await;
+        assert_eq!(result.unwrap_err().to_string(), "no provider");
+    }
+}
@@ -7,6 +7,8 @@
 //! - Request/response capture for debugging
 
 use anyhow::Result;
+use g3_execution::ExecutionError;
+use g3_providers::{ProviderError, ProviderErrorKind};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use tracing::{error, info, warn};
@@ -184,10 +186,138 @@ pub enum RecoverableError {
     TokenLimit,
     /// Context length exceeded (prompt too long) - should end current turn in autonomous mode
     ContextLengthExceeded,
+    /// The provider stopped sending data mid-stream
+    Stalled,
 }
 
+/// A streaming response that produced no chunks (including keep-alive pings) for longer than
+/// the configured stall timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("provider stream stalled: no data received for {}s", .0.as_secs())]
+pub struct StallError(pub Duration);
+
+/// The failures the agent distinguishes between, built from the typed errors raised by
+/// providers ([`ProviderError`]) and command execution ([`ExecutionError`])
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum G3Error {
+    #[error("{0}")]
+    ProviderAuth(String),
+    #[error("{0}")]
+    RateLimit(String),
+    #[error("{0}")]
+    ContextOverflow(String),
+    #[error("{0}")]
+    ModelBusy(String),
+    #[error("{0}")]
+    ServerError(String),
+    #[error("{0}")]
+    Network(String),
+    #[error("{0}")]
+    Timeout(String),
+    #[error(transparent)]
+    Stalled(#[from] StallError),
+    /// The provider rejected the request for a reason retrying won't fix
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// The provider's content filter stopped the request or response, or the model refused.
+    /// The same request would be stopped again, so it is never retried.
+    #[error("{0}")]
+    ContentFiltered(String),
+    #[error("{0}")]
+    ToolFailure(String),
+    #[error("Operation cancelled by user")]
+    Cancelled,
+}
+
+impl G3Error {
+    /// The typed error behind `error`, searching its whole chain of causes. Returns `None`
+    /// for errors that no part of g3 has classified.
+    pub fn from_error(error: &anyhow::Error) -> Option<G3Error> {
+        for cause in error.chain() {
+            if let Some(e) = cause.downcast_ref::<G3Error>() {
+                return Some(e.clone());
+            }
+            if let Some(e) = cause.downcast_ref::<ProviderError>() {
+                return Some(G3Error::from(e));
+            }
+            if let Some(e) = cause.downcast_ref::<ExecutionError>() {
+                return Some(G3Error::ToolFailure(e.to_string()));
+            }
+            if let Some(e) = cause.downcast_ref::<StallError>() {
+                return Some(G3Error::Stalled(*e));
+            }
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                return Some(if e.is_timeout() {
+                    G3Error::Timeout(e.to_string())
+                } else {
+                    G3Error::Network(e.to_string())
+                });
+            }
+        }
+        None
+    }
+
+    pub fn error_type(&self) -> ErrorType {
+        match self {
+            G3Error::RateLimit(_) => ErrorType::Recoverable(RecoverableError::RateLimit),
+            G3Error::Network(_) => ErrorType::Recoverable(RecoverableError::NetworkError),
+            G3Error::ServerError(_) => ErrorType::Recoverable(RecoverableError::ServerError),
+            G3Error::ModelBusy(_) => ErrorType::Recoverable(RecoverableError::ModelBusy),
+            G3Error::Timeout(_) => ErrorType::Recoverable(RecoverableError::Timeout),
+            G3Error::ContextOverflow(_) => {
+                ErrorType::Recoverable(RecoverableError::ContextLengthExceeded)
+            }
+            G3Error::Stalled(_) => ErrorType::Recoverable(RecoverableError::Stalled),
+            G3Error::ProviderAuth(_)
+            | G3Error::InvalidRequest(_)
+            | G3Error::ContentFiltered(_)
+            | G3Error::ToolFailure(_)
+            | G3Error::Cancelled => ErrorType::NonRecoverable,
+        }
+    }
+
+    /// Whether `error` is (or was caused by) the user cancelling the operation
+    pub fn is_cancelled(error: &anyhow::Error) -> bool {
+        matches!(Self::from_error(error), Some(G3Error::Cancelled))
+    }
+
+    /// Whether `error` is (or was caused by) the provider's content filter or a refusal
+    pub fn is_content_filtered(error: &anyhow::Error) -> bool {
+        matches!(Self::from_error(error), Some(G3Error::ContentFiltered(_)))
+    }
+}
+
+impl From<&ProviderError> for G3Error {
+    fn from(error: &ProviderError) -> Self {
+        let message = error.message.clone();
+        match error.kind {
+            ProviderErrorKind::Auth => G3Error::ProviderAuth(message),
+            ProviderErrorKind::RateLimit => G3Error::RateLimit(message),
+            ProviderErrorKind::ContextOverflow => G3Error::ContextOverflow(message),
+            ProviderErrorKind::ModelBusy => G3Error::ModelBusy(message),
+            ProviderErrorKind::Server => G3Error::ServerError(message),
+            ProviderErrorKind::Network => G3Error::Network(message),
+            ProviderErrorKind::Timeout => G3Error::Timeout(message),
+            ProviderErrorKind::ContentFilter => G3Error::ContentFiltered(message),
+            ProviderErrorKind::Other => G3Error::InvalidRequest(message),
+        }
+    }
+}
+
+/// What to tell the user after a [`G3Error::ContentFiltered`] error
+pub const CONTENT_FILTER_ADVICE: &str = "The provider's content filter stopped this response, or the model refused it. It wasn't retried, since the same request would be stopped again; rephrasing the request or leaving out what triggered the filter usually gets past it.";
+
 /// Classify an error as recoverable or non-recoverable
 pub fn classify_error(error: &anyhow::Error) -> ErrorType {
+    match G3Error::from_error(error) {
+        Some(e) => e.error_type(),
+        None => classify_untyped_error(error),
+    }
+}
+
+/// Fallback for errors that don't carry a [`G3Error`] (e.g. from third-party crates),
+/// based on common phrases in their messages
+fn classify_untyped_error(error: &anyhow::Error) -> ErrorType {
     let error_str = error.to_string().to_lowercase();
 
     // Check for recoverable error patterns
@@ -281,6 +411,16 @@ pub fn calculate_retry_delay(attempt: u32, is_autonomous: bool) -> Duration {
     Duration::from_millis(final_delay)
 }
 
+/// Delay before retrying after `error`: as long as the provider asked (e.g. until its rate
+/// limit resets, up to the autonomous maximum), otherwise the usual backoff
+pub fn retry_delay(error: &anyhow::Error, attempt: u32, is_autonomous: bool) -> Duration {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ProviderError>()?.retry_after)
+        .map(|delay| delay.min(Duration::from_millis(AUTONOMOUS_MAX_RETRY_DELAY_MS)))
+        .unwrap_or_else(|| calculate_retry_delay(attempt, is_autonomous))
+}
+
 /// Retry logic for async operations
 pub async fn retry_with_backoff<F, Fut, T>(
     operation_name: &str,
@@ -322,7 +462,7 @@ where
                             return Err(error);
                         }
                         
-                        let delay = calculate_retry_delay(attempt, is_autonomous);
+                        let delay = retry_delay(&error, attempt, is_autonomous);
                         warn!(
                             "Recoverable error ({:?}) in '{}' (attempt {}/{}). Retrying in {:?}...",
                             recoverable_type, operation_name, attempt, max_attempts, delay
@@ -430,6 +570,16 @@ mod tests {
         let error = anyhow!("Error 400: prompt is too long");
         assert_eq!(classify_error(&error), ErrorType::Recoverable(RecoverableError::ContextLengthExceeded));
         
+        // Stalled stream
+        let error = anyhow::Error::new(StallError(Duration::from_secs(90)));
+        assert_eq!(classify_error(&error), ErrorType::Recoverable(RecoverableError::Stalled));
+        assert_eq!(error.to_string(), "provider stream stalled: no data received for 90s");
+        
+        // Cancellation
+        let error = anyhow::Error::new(G3Error::Cancelled);
+        assert_eq!(classify_error(&error), ErrorType::NonRecoverable);
+        assert!(G3Error::is_cancelled(&error));
+        
         // Non-recoverable
         let error = anyhow!("Invalid API key");
         assert_eq!(classify_error(&error), ErrorType::NonRecoverable);
@@ -438,6 +588,42 @@ mod tests {
         assert_eq!(classify_error(&error), ErrorType::NonRecoverable);
     }
 
+    #[test]
+    fn test_typed_errors_are_classified_by_kind() {
+        // A typed error is classified by its kind, whatever its message says
+        let error = anyhow::Error::new(ProviderError::new(
+            ProviderErrorKind::Auth,
+            "Anthropic API error 401: token limit exceeded",
+        ));
+        assert_eq!(
+            G3Error::from_error(&error),
+            Some(G3Error::ProviderAuth("Anthropic API error 401: token limit exceeded".to_string()))
+        );
+        assert_eq!(classify_error(&error), ErrorType::NonRecoverable);
+
+        let error = anyhow::Error::new(ProviderError::new(ProviderErrorKind::ModelBusy, "please wait"))
+            .context("Failed to start stream");
+        assert_eq!(classify_error(&error), ErrorType::Recoverable(RecoverableError::ModelBusy));
+
+        let error = anyhow::Error::new(ExecutionError::Spawn {
+            program: "bash".to_string(),
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "connection refused"),
+        });
+        assert!(matches!(G3Error::from_error(&error), Some(G3Error::ToolFailure(_))));
+        assert_eq!(classify_error(&error), ErrorType::NonRecoverable);
+
+        assert_eq!(G3Error::from_error(&anyhow!("Rate limit exceeded")), None);
+
+        // A refusal is never retried, even though the message mentions a server
+        let error = anyhow::Error::new(ProviderError::content_filtered(
+            "OpenAI",
+            "I can't help with hacking the server",
+        ));
+        assert_eq!(classify_error(&error), ErrorType::NonRecoverable);
+        assert!(G3Error::is_content_filtered(&error));
+        assert!(!G3Error::is_content_filtered(&anyhow!("Content filter")));
+    }
+
     #[test]
     fn test_retry_delay_calculation() {
         // Test that delays increase exponentially
@@ -507,4 +693,22 @@ mod tests {
         assert!(truncated.contains("Some text"));
         assert!(truncated.contains("truncated"));
     }
+
+    #[test]
+    fn test_retry_delay_follows_provider() {
+        let limited = ProviderError::new(ProviderErrorKind::RateLimit, "Groq API error 429")
+            .with_retry_after(Some(Duration::from_secs(7)));
+        assert_eq!(
+            retry_delay(&anyhow::Error::new(limited.clone()), 1, false),
+            Duration::from_secs(7)
+        );
+        // A reset hours away is capped
+        let daily = limited.with_retry_after(Some(Duration::from_secs(36_000)));
+        assert_eq!(
+            retry_delay(&anyhow::Error::new(daily), 1, false),
+            Duration::from_millis(AUTONOMOUS_MAX_RETRY_DELAY_MS)
+        );
+        let unhinted = anyhow::Error::new(ProviderError::new(ProviderErrorKind::Server, "502"));
+        assert!(retry_delay(&unhinted, 1, false) <= Duration::from_millis(1300));
+    }
 }
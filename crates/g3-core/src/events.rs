@@ -0,0 +1,450 @@
+//! What happens during an agent's turns, as typed events on an append-only bus.
+//!
+//! The agent publishes events instead of calling its [`UiWriter`] directly. The writer is
+//! the bus's first subscriber and renders the events as it always has; other front ends
+//! and sinks (a JSON output mode, a WebSocket stream, an audit log, a metrics exporter)
+//! subscribe through [`Agent::subscribe`](crate::Agent::subscribe) without changes to the
+//! agent. Subscribers can only be added, and each sees every event in the order it was
+//! published.
+
+use serde_json::Value;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::stream_rate::StreamProgress;
+use crate::task_result::TurnMetrics;
+use crate::text_display;
+use crate::ui_writer::UiWriter;
+
+/// Tool output shown to people is cut to this many lines...
+const MAX_LINES: usize = 5;
+/// ...of at most this many columns
+const MAX_LINE_WIDTH: usize = 80;
+/// Tool arguments shown in the tool header are cut to this many columns
+const MAX_ARG_WIDTH: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    /// The system prompt, at the start of a session when prompts are shown
+    SystemPrompt { prompt: String },
+    /// A plain line of output
+    Line { text: String },
+    /// Progress of context management, retries and continuations
+    Status { message: String },
+    /// Large tool results were moved out of the context window
+    ContextThinned { summary: String },
+    /// The conversation was replaced by a summary of it
+    ContextCompacted { message: String },
+    /// Data arrived from the provider, including keep-alive pings
+    StreamActivity,
+    /// Elapsed time and speed of the streaming response, also published while no data
+    /// arrives
+    StreamProgress(StreamProgress),
+    /// The model started writing a response
+    ResponseStarted,
+    /// More text of the response
+    TextDelta { text: String },
+    /// More of a summary of the model's reasoning, which isn't part of the response
+    ReasoningDelta { text: String },
+    /// The model finished writing a response without calling a tool
+    ResponseFinished,
+    /// The response shown since `ResponseStarted` was interrupted with nothing to resume
+    /// from, so it is left out of the conversation and written again from the start
+    ResponseDiscarded,
+    /// More of the summary of a `final_output` call, shown while the call streams
+    SummaryDelta { text: String },
+    /// A tool call is about to run
+    ToolStarted { tool: String, args: Value },
+    /// A line of output of a running tool
+    ToolOutput { line: String },
+    /// A tool call finished. `ends_turn` is set for tools that end the turn, such as
+    /// `final_output`, and `streamed` when its result was already shown in `SummaryDelta`s.
+    ToolFinished {
+        tool: String,
+        result: String,
+        success: bool,
+        duration: Duration,
+        ends_turn: bool,
+        streamed: bool,
+    },
+    /// A task finished, successfully or not
+    TurnFinished { metrics: TurnMetrics, success: bool },
+}
+
+impl AgentEvent {
+    /// The event with the emoji and box drawing in g3's own text replaced by ASCII. The
+    /// model's text is left as it wrote it.
+    fn into_plain(self) -> Self {
+        let plain = |text: String| text_display::plain(&text);
+        match self {
+            AgentEvent::Line { text } => AgentEvent::Line { text: plain(text) },
+            AgentEvent::Status { message } => AgentEvent::Status {
+                message: plain(message),
+            },
+            AgentEvent::ContextThinned { summary } => AgentEvent::ContextThinned {
+                summary: plain(summary),
+            },
+            AgentEvent::ContextCompacted { message } => AgentEvent::ContextCompacted {
+                message: plain(message),
+            },
+            AgentEvent::ToolOutput { line } => AgentEvent::ToolOutput { line: plain(line) },
+            AgentEvent::ToolFinished {
+                tool,
+                result,
+                success,
+                duration,
+                ends_turn,
+                streamed,
+            } => AgentEvent::ToolFinished {
+                tool,
+                result: plain(result),
+                success,
+                duration,
+                ends_turn,
+                streamed,
+            },
+            event => event,
+        }
+    }
+}
+
+/// Receives every event published on a bus it subscribed to
+pub trait EventSubscriber: Send + Sync {
+    fn on_event(&self, event: &AgentEvent);
+}
+
+/// Forwards events to a channel, for front ends that consume them as a stream. Events are
+/// dropped while it isn't connected.
+#[derive(Default)]
+pub struct ChannelSubscriber {
+    sender: Mutex<Option<UnboundedSender<AgentEvent>>>,
+}
+
+impl ChannelSubscriber {
+    /// Forward events to a new channel from now on, in place of the previous one
+    pub fn connect(&self) -> UnboundedReceiver<AgentEvent> {
+        let (sender, receiver) = unbounded_channel();
+        *self.sender.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    pub fn disconnect(&self) {
+        *self.sender.lock().unwrap() = None;
+    }
+}
+
+impl EventSubscriber for ChannelSubscriber {
+    fn on_event(&self, event: &AgentEvent) {
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            // A receiver that was dropped just misses the rest
+            let _ = sender.send(event.clone());
+        }
+    }
+}
+
+/// The agent's events, delivered to its UI writer and then to every other subscriber
+pub struct EventBus<W: UiWriter> {
+    ui_writer: W,
+    subscribers: RwLock<Vec<Arc<dyn EventSubscriber>>>,
+    plain: bool,
+}
+
+impl<W: UiWriter> EventBus<W> {
+    pub fn new(ui_writer: W) -> Self {
+        Self {
+            ui_writer,
+            subscribers: RwLock::new(Vec::new()),
+            plain: false,
+        }
+    }
+
+    /// Deliver events with ASCII in place of emoji and box drawing (`plain_output`)
+    pub fn with_plain(mut self, plain: bool) -> Self {
+        self.plain = plain;
+        self
+    }
+
+    pub fn subscribe(&self, subscriber: Arc<dyn EventSubscriber>) {
+        self.subscribers.write().unwrap().push(subscriber);
+    }
+
+    pub fn publish(&self, event: AgentEvent) {
+        let event = if self.plain {
+            event.into_plain()
+        } else {
+            event
+        };
+        self.ui_writer.on_event(&event);
+        for subscriber in self.subscribers.read().unwrap().iter() {
+            subscriber.on_event(&event);
+        }
+    }
+
+    pub fn ui_writer(&self) -> &W {
+        &self.ui_writer
+    }
+}
+
+/// A UI writer renders the events meant for people
+impl<W: UiWriter> EventSubscriber for W {
+    fn on_event(&self, event: &AgentEvent) {
+        match event {
+            AgentEvent::SystemPrompt { prompt } => self.print_system_prompt(prompt),
+            AgentEvent::Line { text } => self.println(text),
+            AgentEvent::Status { message } | AgentEvent::ContextCompacted { message } => {
+                self.print_context_status(message)
+            }
+            AgentEvent::ContextThinned { summary } => self.print_context_thinning(summary),
+            AgentEvent::StreamActivity => self.notify_sse_received(),
+            AgentEvent::StreamProgress(progress) => self.print_stream_progress(progress),
+            AgentEvent::ResponseStarted => self.print_agent_prompt(),
+            AgentEvent::TextDelta { text } => {
+                self.print_agent_response(text);
+                self.flush();
+            }
+            AgentEvent::ReasoningDelta { text } => {
+                self.print_agent_reasoning(text);
+                self.flush();
+            }
+            AgentEvent::SummaryDelta { text } => {
+                self.print_agent_response(text);
+                self.flush();
+            }
+            AgentEvent::ResponseFinished => self.println(""),
+            AgentEvent::ResponseDiscarded => self.println(""),
+            AgentEvent::ToolStarted { tool, args } => {
+                self.println(""); // New line before tool execution
+
+                // Skip printing tool call details for final_output
+                if tool != "final_output" {
+                    self.print_tool_header(tool);
+                    if let Some(args_obj) = args.as_object() {
+                        for (key, value) in args_obj {
+                            self.print_tool_arg(key, &display_arg(tool, key, value));
+                        }
+                    }
+                    self.print_tool_output_header();
+                }
+            }
+            AgentEvent::ToolOutput { line } => self.update_tool_output_line(line),
+            AgentEvent::ToolFinished {
+                tool,
+                result,
+                duration,
+                ends_turn,
+                streamed,
+                ..
+            } => {
+                if *streamed {
+                    self.println("");
+                } else if *ends_turn {
+                    // For final_output, display the summary without truncation
+                    for line in result.lines() {
+                        self.update_tool_output_line(line);
+                    }
+                    self.println("");
+                    self.println("");
+                } else {
+                    print_tool_result(self, tool, result);
+                    // Closure marker with timing
+                    self.print_tool_timing(&crate::format_duration(*duration));
+                    self.print_agent_prompt();
+                }
+            }
+            AgentEvent::TurnFinished { .. } => self.clear_stream_progress(),
+        }
+    }
+}
+
+/// A tool argument as shown in the tool header: the first line of a shell command, and
+/// at most 100 columns of anything else
+fn display_arg(tool: &str, key: &str, value: &Value) -> String {
+    match value {
+        Value::String(s) if tool == "shell" && key == "command" => {
+            text_display::first_line(s, usize::MAX)
+        }
+        Value::String(s) => text_display::truncate(s, MAX_ARG_WIDTH),
+        _ => value.to_string(),
+    }
+}
+
+/// Tool output, truncated for people unless the writer wants all of it. Todo lists are
+/// always shown in full.
+fn print_tool_result<W: UiWriter + ?Sized>(ui_writer: &W, tool: &str, result: &str) {
+    let output_lines: Vec<&str> = result.lines().collect();
+    let wants_full = ui_writer.wants_full_output();
+    let is_todo_tool = tool == "todo_read" || tool == "todo_write";
+    let truncate = !wants_full && !is_todo_tool;
+
+    for (idx, line) in output_lines.iter().enumerate() {
+        if truncate && idx >= MAX_LINES {
+            break;
+        }
+        let clipped_line = if truncate {
+            text_display::truncate(line, MAX_LINE_WIDTH)
+        } else {
+            line.to_string()
+        };
+
+        // Use print_tool_output_line for todo tools to get special formatting
+        if is_todo_tool {
+            ui_writer.print_tool_output_line(&clipped_line);
+        } else {
+            ui_writer.update_tool_output_line(&clipped_line);
+        }
+    }
+
+    if truncate && output_lines.len() > MAX_LINES {
+        ui_writer.print_tool_output_summary(output_lines.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Records what it was asked to print
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Recorder {
+        fn push(&self, s: String) {
+            self.0.lock().unwrap().push(s);
+        }
+    }
+
+    impl UiWriter for Recorder {
+        fn print(&self, message: &str) {
+            self.push(message.to_string());
+        }
+        fn println(&self, message: &str) {
+            self.push(format!("{}\n", message));
+        }
+        fn print_inline(&self, message: &str) {
+            self.push(message.to_string());
+        }
+        fn print_system_prompt(&self, prompt: &str) {
+            self.push(format!("system: {}", prompt));
+        }
+        fn print_context_status(&self, message: &str) {
+            self.push(format!("status: {}", message));
+        }
+        fn print_context_thinning(&self, message: &str) {
+            self.push(format!("thinning: {}", message));
+        }
+        fn print_tool_header(&self, tool_name: &str) {
+            self.push(format!("tool: {}", tool_name));
+        }
+        fn print_tool_arg(&self, key: &str, value: &str) {
+            self.push(format!("arg: {}={}", key, value));
+        }
+        fn print_tool_output_header(&self) {}
+        fn update_tool_output_line(&self, line: &str) {
+            self.push(format!("out: {}", line));
+        }
+        fn print_tool_output_line(&self, line: &str) {
+            self.push(format!("out: {}", line));
+        }
+        fn print_tool_output_summary(&self, hidden_count: usize) {
+            self.push(format!("summary: {}", hidden_count));
+        }
+        fn print_tool_timing(&self, duration_str: &str) {
+            self.push(format!("timing: {}", duration_str));
+        }
+        fn print_agent_prompt(&self) {
+            self.push("prompt".to_string());
+        }
+        fn print_agent_response(&self, content: &str) {
+            self.push(content.to_string());
+        }
+        fn notify_sse_received(&self) {}
+        fn flush(&self) {}
+    }
+
+    #[derive(Default)]
+    struct Collector(Mutex<Vec<AgentEvent>>);
+
+    impl EventSubscriber for Collector {
+        fn on_event(&self, event: &AgentEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_event_bus() {
+        let bus = EventBus::new(Recorder::default());
+        let collector = Arc::new(Collector::default());
+        bus.subscribe(collector.clone());
+
+        let events = vec![
+            AgentEvent::ToolStarted {
+                tool: "shell".to_string(),
+                args: json!({ "command": "make\nmake test" }),
+            },
+            AgentEvent::ToolOutput {
+                line: "building".to_string(),
+            },
+            AgentEvent::ToolFinished {
+                tool: "shell".to_string(),
+                result: (1..=7)
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                success: true,
+                duration: Duration::from_millis(20),
+                ends_turn: false,
+                streamed: false,
+            },
+            AgentEvent::TextDelta {
+                text: "Done".to_string(),
+            },
+        ];
+        for event in events.clone() {
+            bus.publish(event);
+        }
+
+        assert_eq!(*collector.0.lock().unwrap(), events);
+        assert_eq!(
+            *bus.ui_writer().0.lock().unwrap(),
+            vec![
+                "\n",
+                "tool: shell",
+                "arg: command=make...",
+                "out: building",
+                "out: 1",
+                "out: 2",
+                "out: 3",
+                "out: 4",
+                "out: 5",
+                "summary: 7",
+                "timing: 20ms",
+                "prompt",
+                "Done",
+            ]
+        );
+
+        // Plain output reaches every subscriber
+        let bus = EventBus::new(Recorder::default()).with_plain(true);
+        let collector = Arc::new(Collector::default());
+        bus.subscribe(collector.clone());
+        bus.publish(AgentEvent::Status {
+            message: "✅ Saved".to_string(),
+        });
+        bus.publish(AgentEvent::TextDelta {
+            text: "Done 🎉".to_string(),
+        });
+        assert_eq!(
+            *bus.ui_writer().0.lock().unwrap(),
+            vec!["status: [OK] Saved", "Done 🎉"]
+        );
+        assert_eq!(
+            collector.0.lock().unwrap()[0],
+            AgentEvent::Status {
+                message: "[OK] Saved".to_string()
+            }
+        );
+    }
+}
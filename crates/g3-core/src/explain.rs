@@ -0,0 +1,114 @@
+//! `g3 explain`: an architecture overview of a repository for people new to it.
+//!
+//! The overview is written by a regular agent turn, constrained to the `explain` profile:
+//! read-only mode, a handful of reading and searching tools, and a prompt that starts from
+//! the project index and detected build facts and asks for a fixed set of sections.
+
+use g3_config::ProfileConfig;
+
+/// Name of the profile the explain agent runs with
+pub const PROFILE: &str = "explain";
+
+/// Tools the explain agent may use; shell commands are limited by read-only mode
+const TOOLS: &[&str] = &["read_file", "code_search", "code_search_semantic", "shell"];
+
+/// Sections of the overview, in order, with what each should cover
+const SECTIONS: &[(&str, &str)] = &[
+    (
+        "Purpose",
+        "what the project does and who uses it, in a short paragraph",
+    ),
+    (
+        "Modules",
+        "the main crates, packages or directories, one bullet each with its responsibility",
+    ),
+    (
+        "Data Flow",
+        "how a typical request, command or input moves through the modules, step by step",
+    ),
+    (
+        "Key Types",
+        "the types, traits or interfaces a newcomer meets first, with their file paths",
+    ),
+    (
+        "Build & Test",
+        "how to build, run and test the project, and where the entry points are",
+    ),
+    (
+        "Where to Start",
+        "the few files to read first, and the conventions to follow when changing code",
+    ),
+];
+
+const SYSTEM_PROMPT: &str = "You are explaining this repository to an engineer who has just joined the team. Read the code to check what you say: name real files, modules and types, and never guess at behaviour you haven't seen. Do not change anything.";
+
+/// The profile the explain agent runs with
+pub fn profile() -> ProfileConfig {
+    ProfileConfig {
+        system_prompt: Some(SYSTEM_PROMPT.to_string()),
+        tools: Some(TOOLS.iter().map(|tool| tool.to_string()).collect()),
+        ..Default::default()
+    }
+}
+
+/// The task for explaining `target` (a path in the workspace, "." for all of it), given the
+/// rendered project index and project facts when there are any
+pub fn task_prompt(target: &str, index: Option<&str>, facts: Option<&str>) -> String {
+    let scope = if target == "." {
+        "this repository".to_string()
+    } else {
+        format!("`{}` in this repository", target)
+    };
+    let mut prompt = format!(
+        "Write an architecture overview of {} for a new team member.\n\n",
+        scope
+    );
+    for context in [index, facts].into_iter().flatten() {
+        prompt.push_str(context.trim());
+        prompt.push_str("\n\n");
+    }
+    prompt.push_str(
+        "Start from the above, then read the entry points and the most central files to \
+         confirm and fill in the details. Keep exploring proportionate: a dozen or two file \
+         reads is usually enough.\n\nWhen done, call final_output with the overview as a \
+         Markdown document with exactly these sections, as `##` headings:\n",
+    );
+    for (heading, contents) in SECTIONS {
+        prompt.push_str(&format!("- {}: {}\n", heading, contents));
+    }
+    prompt
+}
+
+/// The overview saved to the artifact: `overview` under a title naming `target`
+pub fn document(target: &str, overview: &str) -> String {
+    let overview = overview.trim();
+    if overview.starts_with("# ") {
+        return format!("{}\n", overview);
+    }
+    let title = if target == "." {
+        "Architecture Overview".to_string()
+    } else {
+        format!("Architecture Overview: {}", target)
+    };
+    format!("# {}\n\n{}\n", title, overview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_prompt_lists_sections_and_context() {
+        let prompt = task_prompt("crates/g3-core", Some("# Project Index\n..."), None);
+        assert!(prompt.contains("`crates/g3-core` in this repository"));
+        assert!(prompt.contains("# Project Index"));
+        for (heading, _) in SECTIONS {
+            assert!(prompt.contains(&format!("- {}: ", heading)));
+        }
+
+        assert_eq!(
+            document(".", "## Purpose\nA coding agent.\n"),
+            "# Architecture Overview\n\n## Purpose\nA coding agent.\n"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Structured coach feedback for autonomous mode, produced by the give_feedback tool.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackVerdict {
+    Approved,
+    ChangesRequested,
+}
+
+/// The coach's review of a player turn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoachFeedback {
+    pub verdict: FeedbackVerdict,
+    /// Specific problems found in the implementation
+    #[serde(default)]
+    pub issues: Vec<String>,
+    /// Instructions for the player's next turn
+    #[serde(default)]
+    pub next_steps: String,
+}
+
+impl CoachFeedback {
+    /// Parse the arguments of a give_feedback tool call
+    pub fn from_args(args: &serde_json::Value) -> Result<Self, String> {
+        let feedback: CoachFeedback = serde_json::from_value(args.clone()).map_err(|e| {
+            format!(
+                "Invalid give_feedback arguments: {}. Expected verdict (\"approved\" or \"changes_requested\"), issues and next_steps.",
+                e
+            )
+        })?;
+
+        if feedback.verdict == FeedbackVerdict::ChangesRequested
+            && feedback.issues.is_empty()
+            && feedback.next_steps.trim().is_empty()
+        {
+            return Err(
+                "give_feedback with verdict \"changes_requested\" needs at least one issue or next_steps"
+                    .to_string(),
+            );
+        }
+        Ok(feedback)
+    }
+
+    pub fn is_approved(&self) -> bool {
+        self.verdict == FeedbackVerdict::Approved
+    }
+}
+
+/// Renders the feedback as markdown, suitable for display and as the player's next prompt
+impl fmt::Display for CoachFeedback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.verdict {
+            FeedbackVerdict::Approved => write!(f, "**Verdict:** approved")?,
+            FeedbackVerdict::ChangesRequested => write!(f, "**Verdict:** changes requested")?,
+        }
+        if !self.issues.is_empty() {
+            write!(f, "\n\n**Issues:**")?;
+            for (i, issue) in self.issues.iter().enumerate() {
+                write!(f, "\n{}. {}", i + 1, issue)?;
+            }
+        }
+        if !self.next_steps.trim().is_empty() {
+            write!(f, "\n\n**Next steps:**\n{}", self.next_steps.trim())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_changes_requested() {
+        let feedback = CoachFeedback::from_args(&json!({
+            "verdict": "changes_requested",
+            "issues": ["Missing error handling in parse()", "No tests"],
+            "next_steps": "Handle the empty input case, then add unit tests."
+        }))
+        .unwrap();
+
+        assert!(!feedback.is_approved());
+        assert_eq!(feedback.issues.len(), 2);
+        let rendered = feedback.to_string();
+        assert!(rendered.contains("1. Missing error handling in parse()"));
+        assert!(rendered.contains("2. No tests"));
+        assert!(rendered.contains("**Next steps:**\nHandle the empty input case"));
+    }
+
+    #[test]
+    fn test_parse_approved_without_details() {
+        let feedback = CoachFeedback::from_args(&json!({"verdict": "approved"})).unwrap();
+        assert!(feedback.is_approved());
+        assert_eq!(feedback.to_string(), "**Verdict:** approved");
+    }
+
+    #[test]
+    fn test_rejects_invalid_feedback() {
+        assert!(CoachFeedback::from_args(&json!({"verdict": "maybe"})).is_err());
+        assert!(CoachFeedback::from_args(&json!({"issues": ["x"]})).is_err());
+        // Requesting changes without saying what to change is not actionable
+        assert!(CoachFeedback::from_args(&json!({"verdict": "changes_requested"})).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Files the agent created, modified or deleted, with hashes of their content before and
+//! after.
+//!
+//! File tools are watched through the file they name. For shell commands that aren't
+//! read-only, the files named on the command line are watched: that catches `rm`, `mv`,
+//! `sed -i`, redirections and the like, but not files changed by a build or a script.
+//! The changes feed `/diff`, `/rollback`, the summaries of `summarize-changes` and the
+//! coach's review, and are returned in [`TaskResult`](crate::TaskResult).
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{read_only, undo, ToolCall};
+
+/// Files larger than this aren't watched
+const MAX_WATCHED_BYTES: u64 = 10 * 1024 * 1024;
+
+/// At most this many files named by a shell command are watched
+const MAX_SHELL_FILES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    fn symbol(self) -> char {
+        match self {
+            ChangeKind::Created => '+',
+            ChangeKind::Modified => '~',
+            ChangeKind::Deleted => '-',
+        }
+    }
+}
+
+/// The net change to one file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// SHA-256 of the content before the first change; `None` if the file didn't exist
+    pub before: Option<String>,
+    /// SHA-256 of the content now; `None` if the file was deleted
+    pub after: Option<String>,
+    /// Tools that changed the file, in order
+    pub tools: Vec<String>,
+}
+
+impl FileChange {
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.before, &self.after) {
+            (None, _) => ChangeKind::Created,
+            (Some(_), None) => ChangeKind::Deleted,
+            (Some(_), Some(_)) => ChangeKind::Modified,
+        }
+    }
+}
+
+/// Changed files by path
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileChangeSet {
+    changes: BTreeMap<PathBuf, FileChange>,
+}
+
+impl FileChangeSet {
+    /// Record that `tool` changed `path` from `before` to `after`. A file changed back to
+    /// the content it started with is no longer a change.
+    pub fn record(
+        &mut self,
+        path: &Path,
+        before: Option<String>,
+        after: Option<String>,
+        tool: &str,
+    ) {
+        let change = self
+            .changes
+            .entry(path.to_path_buf())
+            .or_insert_with(|| FileChange {
+                path: path.to_path_buf(),
+                before,
+                after: None,
+                tools: Vec::new(),
+            });
+        change.after = after;
+        if change.tools.last().map(String::as_str) != Some(tool) {
+            change.tools.push(tool.to_string());
+        }
+        if change.before == change.after {
+            self.changes.remove(path);
+        }
+    }
+
+    /// Hash every changed file again, after something other than a tool changed them
+    pub fn refresh(&mut self) {
+        for change in self.changes.values_mut() {
+            change.after = read_hash(&change.path);
+        }
+        self.changes
+            .retain(|_, change| change.before != change.after);
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    /// Keep only the changes `keep` returns true for
+    pub fn retain(&mut self, mut keep: impl FnMut(&FileChange) -> bool) {
+        self.changes.retain(|_, change| keep(change));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Changes in path order
+    pub fn iter(&self) -> impl Iterator<Item = &FileChange> {
+        self.changes.values()
+    }
+
+    /// One line per file, `+` created, `~` modified and `-` deleted, with paths relative
+    /// to `root` where they are inside it
+    pub fn render(&self, root: &Path) -> String {
+        self.iter()
+            .map(|change| {
+                format!(
+                    "{} {}",
+                    change.kind().symbol(),
+                    display_path(&change.path, root)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// A unified diff from `before` to `after` (a missing side is an empty file), labelled
+/// with `label`
+pub fn unified_diff(before: Option<&Path>, after: Option<&Path>, label: &str) -> Result<String> {
+    let null = Path::new("/dev/null");
+    let labels = (
+        if before.is_some() {
+            format!("a/{}", label)
+        } else {
+            "/dev/null".to_string()
+        },
+        if after.is_some() {
+            format!("b/{}", label)
+        } else {
+            "/dev/null".to_string()
+        },
+    );
+    run_diff(before.unwrap_or(null), after.unwrap_or(null), labels, label)
+}
+
+/// A unified diff between two files, labelled with the paths as given
+pub fn compare_files(a: &Path, b: &Path) -> Result<String> {
+    let what = format!("{} and {}", a.display(), b.display());
+    run_diff(
+        a,
+        b,
+        (a.display().to_string(), b.display().to_string()),
+        &what,
+    )
+}
+
+fn run_diff(before: &Path, after: &Path, labels: (String, String), what: &str) -> Result<String> {
+    let output = std::process::Command::new("diff")
+        .arg("-u")
+        .arg("--label")
+        .arg(labels.0)
+        .arg("--label")
+        .arg(labels.1)
+        .arg(before)
+        .arg(after)
+        .output()
+        .context("Failed to run diff")?;
+    // diff exits with 1 when the files differ and 2 on errors
+    if output.status.code() == Some(2) {
+        anyhow::bail!(
+            "diff of {} failed: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end()
+        .to_string())
+}
+
+/// `path` relative to `root` if it is inside it
+pub fn display_path(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+pub fn hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+fn read_hash(path: &Path) -> Option<String> {
+    std::fs::read(path).ok().map(|content| hash(&content))
+}
+
+/// Content of a file, if it is a regular file small enough to watch
+fn read_watched(path: &Path) -> Option<Vec<u8>> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_WATCHED_BYTES {
+        return None;
+    }
+    std::fs::read(path).ok()
+}
+
+/// The files a tool call may change, with their content just before it ran
+#[derive(Debug)]
+pub struct FileWatch {
+    tool: String,
+    files: Vec<(PathBuf, Option<Vec<u8>>)>,
+}
+
+impl FileWatch {
+    /// Capture the files `tool_call` may change. `None` for tools that don't change files.
+    pub fn take(tool_call: &ToolCall) -> Option<Self> {
+        let paths = if undo::FILE_TOOLS.contains(&tool_call.tool.as_str()) {
+            let path = tool_call
+                .args
+                .get("file_path")
+                .or_else(|| tool_call.args.get("path"))?
+                .as_str()?;
+            vec![path.to_string()]
+        } else if tool_call.tool == "shell" {
+            let command = tool_call.args.get("command")?.as_str()?;
+            if read_only::check_shell_command(command).is_ok() {
+                return None;
+            }
+            shell_file_args(command)
+        } else {
+            return None;
+        };
+
+        let files = paths
+            .iter()
+            .map(|path| {
+                let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+                std::path::absolute(&path).unwrap_or(path)
+            })
+            .map(|path| {
+                let content = read_watched(&path);
+                (path, content)
+            })
+            // Directories and files too large to watch
+            .filter(|(path, content)| content.is_some() || !path.exists())
+            .collect();
+        Some(Self {
+            tool: tool_call.tool.clone(),
+            files,
+        })
+    }
+
+    /// Record the files that changed since the watch was taken. The earlier content of
+    /// each is passed to `store` so it can be restored later.
+    pub fn finish(self, changes: &mut [&mut FileChangeSet], mut store: impl FnMut(&[u8])) {
+        for (path, before) in self.files {
+            let after = read_watched(&path);
+            if after == before || (after.is_none() && path.exists()) {
+                continue;
+            }
+            if let Some(content) = &before {
+                store(content);
+            }
+            let (before, after) = (before.as_deref().map(hash), after.as_deref().map(hash));
+            for set in changes.iter_mut() {
+                set.record(&path, before.clone(), after.clone(), &self.tool);
+            }
+        }
+    }
+}
+
+/// Words of a shell command that could name files: not options, variables, globs or
+/// assignments
+fn shell_file_args(command: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for word in command.split(|c: char| c.is_whitespace() || "|;&<>()".contains(c)) {
+        let word = word.trim_matches(|c| c == '"' || c == '\'');
+        if word.is_empty()
+            || word.starts_with('-')
+            || word.contains(['$', '*', '?', '=', '`'])
+            || paths.iter().any(|p| p == word)
+        {
+            continue;
+        }
+        paths.push(word.to_string());
+        if paths.len() == MAX_SHELL_FILES {
+            break;
+        }
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn shell(command: &str) -> ToolCall {
+        ToolCall {
+            tool: "shell".to_string(),
+            args: json!({ "command": command }),
+        }
+    }
+
+    #[test]
+    fn test_file_change_set() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("old.txt"), "old").unwrap();
+        std::fs::write(root.join("keep.txt"), "keep").unwrap();
+
+        let mut session = FileChangeSet::default();
+        let mut stored = Vec::new();
+        let command = format!(
+            "mv {0}/old.txt {0}/new.txt; echo hi > {0}/keep.txt",
+            root.display()
+        );
+        let watch = FileWatch::take(&shell(&command)).unwrap();
+        std::fs::rename(root.join("old.txt"), root.join("new.txt")).unwrap();
+        std::fs::write(root.join("keep.txt"), "hi\n").unwrap();
+        watch.finish(&mut [&mut session], |content| stored.push(content.to_vec()));
+
+        assert_eq!(session.render(root), "~ keep.txt\n+ new.txt\n- old.txt");
+        assert_eq!(stored, vec![b"old".to_vec(), b"keep".to_vec()]);
+        let keep = session
+            .iter()
+            .find(|c| c.path.ends_with("keep.txt"))
+            .unwrap();
+        assert_eq!(keep.before, Some(hash(b"keep")));
+        assert_eq!(keep.after, Some(hash(b"hi\n")));
+        assert_eq!(keep.tools, vec!["shell"]);
+
+        let before = root.join("before");
+        std::fs::write(&before, &stored[1]).unwrap();
+        let diff = unified_diff(Some(&before), Some(&root.join("keep.txt")), "keep.txt").unwrap();
+        assert!(
+            diff.starts_with("--- a/keep.txt\n+++ b/keep.txt"),
+            "{}",
+            diff
+        );
+        assert!(
+            diff.ends_with("-keep\n\\ No newline at end of file\n+hi"),
+            "{}",
+            diff
+        );
+        let diff = unified_diff(None, Some(&root.join("new.txt")), "new.txt").unwrap();
+        assert!(diff.starts_with("--- /dev/null\n+++ b/new.txt"), "{}", diff);
+        let diff = compare_files(&before, &root.join("keep.txt")).unwrap();
+        assert!(
+            diff.starts_with(&format!("--- {}\n", before.display())),
+            "{}",
+            diff
+        );
+        assert_eq!(compare_files(&before, &before).unwrap(), "");
+
+        let mut kept = session.clone();
+        kept.retain(|change| change.path.ends_with("new.txt"));
+        assert_eq!(kept.render(root), "+ new.txt");
+
+        // Changing a file back to its first content is no change
+        std::fs::write(root.join("keep.txt"), "keep").unwrap();
+        session.refresh();
+        assert_eq!(session.len(), 2);
+
+        // Read-only commands and tools that don't write aren't watched
+        assert!(FileWatch::take(&shell("cat keep.txt | grep k")).is_none());
+        let read = ToolCall {
+            tool: "read_file".to_string(),
+            args: json!({ "file_path": "keep.txt" }),
+        };
+        assert!(FileWatch::take(&read).is_none());
+    }
+}
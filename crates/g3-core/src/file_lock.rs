@@ -0,0 +1,292 @@
+//! Advisory file locking and conflict detection between agents working on the same files.
+//!
+//! Several g3 instances, or an agent and its sub-agents, can edit the same repository at
+//! once. Two things keep one from silently overwriting another's edits:
+//!
+//! - While `write_file`, `str_replace` or `edit_symbol` writes a file, it holds an
+//!   exclusive lock on a lock file for that path under the system temp directory, so a
+//!   second writer waits briefly and then gets a conflict instead of interleaving with it.
+//! - Each agent remembers the version (modification time, size and SHA-256) of every file
+//!   it has read or written. Before a file tool writes a file the agent has seen, the file
+//!   must still be at that version; if someone else changed or deleted it in between, the
+//!   write is refused so the agent can read the file again and redo its edit.
+//!
+//! The agent's own shell commands may change files too, so after a command that isn't
+//! read-only, or an undo, the remembered versions are brought up to date.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use tracing::warn;
+
+use crate::{file_changes, undo, ToolCall};
+
+/// Directory of the lock files, under the system temp directory
+pub const LOCK_DIR: &str = "g3-locks";
+
+/// How long a writer waits for another to release a file before reporting a conflict
+pub const LOCK_WAIT: Duration = Duration::from_secs(2);
+
+/// The file a file tool reads or writes, as an absolute path. `None` for other tools.
+pub fn target_path(tool_call: &ToolCall) -> Option<PathBuf> {
+    if tool_call.tool != "read_file" && !undo::FILE_TOOLS.contains(&tool_call.tool.as_str()) {
+        return None;
+    }
+    let path = tool_call
+        .args
+        .get("file_path")
+        .or_else(|| tool_call.args.get("path"))?
+        .as_str()?;
+    let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+    Some(std::path::absolute(&path).unwrap_or(path))
+}
+
+/// Why a file tool didn't write a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileConflict {
+    /// Another agent is writing the file right now
+    Locked { path: PathBuf, holder: String },
+    /// The file changed since the agent last read or wrote it
+    Changed { path: PathBuf },
+    /// The file was deleted since the agent last read or wrote it
+    Deleted { path: PathBuf },
+}
+
+impl fmt::Display for FileConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileConflict::Locked { path, holder } => write!(
+                f,
+                "File conflict (locked): {} is being written by another agent ({}). Nothing was written. Try again, or work on something else first.",
+                path.display(),
+                holder
+            ),
+            FileConflict::Changed { path } => write!(
+                f,
+                "File conflict (changed): {} was modified by someone else since you last read or wrote it. Nothing was written. Read the file again and redo your edit against its current content.",
+                path.display()
+            ),
+            FileConflict::Deleted { path } => write!(
+                f,
+                "File conflict (deleted): {} was deleted by someone else since you last read or wrote it. Nothing was written. Check whether it should still exist before recreating it.",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FileConflict {}
+
+/// A held lock on one file, released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    /// `None` if the lock file couldn't be used, and the write goes ahead unguarded
+    _file: Option<File>,
+}
+
+/// Takes locks on files, in a directory every agent on the machine shares
+#[derive(Debug, Clone)]
+pub struct FileLocks {
+    dir: PathBuf,
+    /// Who takes the locks, shown to agents that find a file locked
+    holder: String,
+}
+
+impl FileLocks {
+    pub fn new(dir: impl Into<PathBuf>, holder: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            holder: holder.into(),
+        }
+    }
+
+    /// Locks in the shared directory, held by this process
+    pub fn shared() -> Self {
+        Self::new(
+            std::env::temp_dir().join(LOCK_DIR),
+            format!("g3 process {}", std::process::id()),
+        )
+    }
+
+    /// Lock `path` for writing if nobody else holds it. Lock files are left in place once
+    /// released: removing one could let two writers lock different files for the same path.
+    pub fn try_lock(&self, path: &Path) -> Result<FileLock, FileConflict> {
+        let lock_path = self.dir.join(format!(
+            "{}.lock",
+            file_changes::hash(path.as_os_str().as_encoded_bytes())
+        ));
+        let open = || -> std::io::Result<File> {
+            std::fs::create_dir_all(&self.dir)?;
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&lock_path)
+        };
+        let mut file = match open() {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Failed to open lock file {}: {}", lock_path.display(), e);
+                return Ok(FileLock { _file: None });
+            }
+        };
+        match file.try_lock() {
+            Ok(()) => {
+                let _ = file.set_len(0);
+                let _ = file.write_all(self.holder.as_bytes());
+                Ok(FileLock { _file: Some(file) })
+            }
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                let _ = file.rewind().and_then(|_| file.read_to_string(&mut holder));
+                Err(FileConflict::Locked {
+                    path: path.to_path_buf(),
+                    holder: if holder.is_empty() {
+                        "unknown".to_string()
+                    } else {
+                        holder
+                    },
+                })
+            }
+            Err(TryLockError::Error(e)) => {
+                warn!("Failed to lock {}: {}", lock_path.display(), e);
+                Ok(FileLock { _file: None })
+            }
+        }
+    }
+}
+
+/// A file's content as an agent last saw it
+#[derive(Debug, Clone, PartialEq)]
+struct Version {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: String,
+}
+
+impl Version {
+    /// `None` if the file doesn't exist or can't be read
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: file_changes::hash(&std::fs::read(path).ok()?),
+        })
+    }
+}
+
+/// The versions of the files one agent has read or written
+#[derive(Debug, Clone, Default)]
+pub struct FileVersions {
+    seen: HashMap<PathBuf, Version>,
+}
+
+impl FileVersions {
+    /// Remember the current version of `path`
+    pub fn record(&mut self, path: &Path) {
+        match Version::read(path) {
+            Some(version) => {
+                self.seen.insert(path.to_path_buf(), version);
+            }
+            None => {
+                self.seen.remove(path);
+            }
+        }
+    }
+
+    /// Check that `path` is still at the version last recorded. Files never recorded pass.
+    pub fn check(&self, path: &Path) -> Result<(), FileConflict> {
+        let Some(seen) = self.seen.get(path) else {
+            return Ok(());
+        };
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return Err(FileConflict::Deleted {
+                path: path.to_path_buf(),
+            });
+        };
+        // Unchanged metadata is taken to mean unchanged content; otherwise compare hashes,
+        // so a file that was only touched isn't a conflict
+        if metadata.len() == seen.len && metadata.modified().ok() == seen.modified {
+            return Ok(());
+        }
+        match Version::read(path) {
+            Some(current) if current.hash == seen.hash => Ok(()),
+            _ => Err(FileConflict::Changed {
+                path: path.to_path_buf(),
+            }),
+        }
+    }
+
+    /// Remember the current version of every file seen, after the agent itself may have
+    /// changed them
+    pub fn refresh(&mut self) {
+        let paths: Vec<PathBuf> = self.seen.keys().cloned().collect();
+        for path in paths {
+            self.record(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_lock_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        let first = FileLocks::new(dir.path().join("locks"), "agent one");
+        let second = FileLocks::new(dir.path().join("locks"), "agent two");
+
+        let lock = first.try_lock(&path).unwrap();
+        assert_eq!(
+            second.try_lock(&path).unwrap_err(),
+            FileConflict::Locked {
+                path: path.clone(),
+                holder: "agent one".to_string()
+            }
+        );
+        assert!(second.try_lock(&dir.path().join("lib.rs")).is_ok());
+
+        drop(lock);
+        assert!(second.try_lock(&path).is_ok());
+    }
+
+    #[test]
+    fn test_file_versions_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}").unwrap();
+        let mut versions = FileVersions::default();
+
+        // Files the agent hasn't seen aren't checked
+        assert!(versions.check(&dir.path().join("new.rs")).is_ok());
+
+        versions.record(&path);
+        assert!(versions.check(&path).is_ok());
+
+        std::fs::write(&path, "fn main() { other() }").unwrap();
+        assert_eq!(
+            versions.check(&path),
+            Err(FileConflict::Changed { path: path.clone() })
+        );
+
+        versions.refresh();
+        assert!(versions.check(&path).is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            versions.check(&path),
+            Err(FileConflict::Deleted { path: path.clone() })
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! Support for `.g3ignore`, a gitignore-style list of files the agent must not see.
+//!
+//! Ignored files are refused by read_file, skipped by code_search, refused when named in
+//! shell commands and never loaded as project README/AGENTS context. Shell commands are
+//! handled on a best-effort basis: `rg` and `grep` are told to skip ignored files and lines
+//! of output that name one are removed, but output that shows an ignored file's contents
+//! without naming it (`cat *`, a script reading it) still gets through.
+
+use crate::ToolCall;
+use g3_execution::remote::quote;
+use regex::{Captures, Regex};
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{debug, warn};
+
+pub const G3IGNORE_FILE: &str = ".g3ignore";
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    /// The pattern as written, without `!`
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+/// Compiled `.g3ignore` rules, matched against paths relative to the directory the file
+/// was loaded from
+#[derive(Debug, Clone, Default)]
+pub struct G3Ignore {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl G3Ignore {
+    /// Load `.g3ignore` from `root`. A missing file ignores nothing.
+    pub fn load(root: &Path) -> Self {
+        let path = root.join(G3IGNORE_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(root, &contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                warn!("Failed to read {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Parse gitignore syntax: `#` comments, `!` negation, trailing `/` for directories,
+    /// a leading or inner `/` to anchor to the root, and `*`, `?`, `**` and `[...]` globs
+    pub fn parse(root: &Path, contents: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let written = pattern.to_string();
+            let (dir_only, pattern) = match pattern.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, pattern),
+            };
+            // Patterns containing a slash are relative to the root, others match at any depth
+            let anchored = pattern.contains('/');
+            let pattern = pattern.trim_start_matches('/');
+            if pattern.is_empty() {
+                continue;
+            }
+
+            let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+            match Regex::new(&format!("{}{}$", prefix, glob_to_regex(pattern))) {
+                Ok(regex) => rules.push(Rule {
+                    regex,
+                    pattern: written,
+                    negated,
+                    dir_only,
+                    anchored,
+                }),
+                Err(e) => warn!(
+                    "Ignoring invalid {} pattern '{}': {}",
+                    G3IGNORE_FILE, line, e
+                ),
+            }
+        }
+        debug!("Loaded {} {} rules", rules.len(), G3IGNORE_FILE);
+
+        Self {
+            root: root.to_path_buf(),
+            rules,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether `path` (absolute, or relative to the root) is hidden from the agent.
+    /// Paths outside the root are never ignored.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let Some(relative) = self.relative_path(path) else {
+            return false;
+        };
+
+        // As in git, nothing inside an ignored directory can be re-included
+        let mut prefix = String::new();
+        let components: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        for (i, component) in components.iter().enumerate() {
+            if !prefix.is_empty() {
+                prefix.push('/');
+            }
+            prefix.push_str(component);
+            let is_last = i + 1 == components.len();
+            let is_dir = !is_last || self.root.join(&relative).is_dir();
+            if self.matches(&prefix, is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns a reason if the tool call names a file hidden by `.g3ignore`. Shell commands
+    /// are checked on a best-effort basis by looking at each argument that could be a path.
+    pub fn check_tool_call(&self, tool_call: &ToolCall) -> Option<String> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        if let Some(file_path) = tool_call.args.get("file_path").and_then(|v| v.as_str()) {
+            let expanded = shellexpand::tilde(file_path);
+            if self.is_ignored(Path::new(expanded.as_ref())) {
+                return Some(format!("{} is excluded by {}", file_path, G3IGNORE_FILE));
+            }
+        }
+        if tool_call.tool == "shell" {
+            let command = tool_call.args.get("command").and_then(|v| v.as_str())?;
+            let hidden = command
+                .split(|c: char| c.is_whitespace() || matches!(c, '|' | ';' | '&' | '<' | '>'))
+                .map(|word| word.trim_matches(|c| c == '"' || c == '\''))
+                .filter(|word| !word.is_empty() && !word.starts_with('-'))
+                .find(|word| self.is_ignored(Path::new(shellexpand::tilde(word).as_ref())))?;
+            return Some(format!(
+                "Command refers to {}, which is excluded by {}",
+                hidden, G3IGNORE_FILE
+            ));
+        }
+        None
+    }
+
+    /// `command` with the ignore rules passed to each `rg` and `grep` in it, so their searches
+    /// skip ignored files. Negated rules are left out, and `grep` only gets the rules without
+    /// a slash, which its `--exclude` globs can express; [`filter_output`] covers the rest.
+    ///
+    /// [`filter_output`]: Self::filter_output
+    pub fn exclude_from_searches(&self, command: &str) -> String {
+        static SEARCH: OnceLock<Regex> = OnceLock::new();
+        let search = SEARCH.get_or_init(|| {
+            Regex::new(r"(^\s*|[|;&(\n]\s*)(rg|grep|egrep|fgrep)(\s|$)").expect("valid regex")
+        });
+
+        let excluded: Vec<&Rule> = self.rules.iter().filter(|rule| !rule.negated).collect();
+        let rg_args: Vec<String> = excluded
+            .iter()
+            .map(|rule| format!("-g {}", quote(&format!("!{}", rule.pattern))))
+            .collect();
+        let grep_args: Vec<String> = excluded
+            .iter()
+            .filter(|rule| !rule.anchored)
+            .flat_map(|rule| {
+                let pattern = rule.pattern.trim_end_matches('/');
+                let files = (!rule.dir_only).then(|| format!("--exclude={}", quote(pattern)));
+                files
+                    .into_iter()
+                    .chain(Some(format!("--exclude-dir={}", quote(pattern))))
+            })
+            .collect();
+
+        search
+            .replace_all(command, |caps: &Captures| {
+                let args = if &caps[2] == "rg" {
+                    &rg_args
+                } else {
+                    &grep_args
+                };
+                if args.is_empty() {
+                    return caps[0].to_string();
+                }
+                format!("{}{} {}{}", &caps[1], &caps[2], args.join(" "), &caps[3])
+            })
+            .into_owned()
+    }
+
+    /// `output` of a shell command run in `cwd` without the lines that name an ignored
+    /// path, as `ls`, `find` and `grep -r` print them, and a note of how many were removed
+    pub fn filter_output(&self, output: &str, cwd: &Path) -> String {
+        if self.rules.is_empty() {
+            return output.to_string();
+        }
+        let mut removed = 0;
+        let kept: Vec<&str> = output
+            .lines()
+            .filter(|line| {
+                let hidden = self.names_ignored_path(line, cwd);
+                removed += usize::from(hidden);
+                !hidden
+            })
+            .collect();
+        if removed == 0 {
+            return output.to_string();
+        }
+        let mut filtered = kept.join("\n");
+        if !filtered.is_empty() {
+            filtered.push('\n');
+        }
+        filtered.push_str(&format!(
+            "({} line{} naming files excluded by {} removed)",
+            removed,
+            if removed == 1 { "" } else { "s" },
+            G3IGNORE_FILE
+        ));
+        filtered
+    }
+
+    /// Whether any word of `line`, split at whitespace and at the `:` after a path in
+    /// `grep` output, is an ignored path relative to `cwd`
+    pub fn names_ignored_path(&self, line: &str, cwd: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        line.split(|c: char| c.is_whitespace() || c == ':')
+            .map(|word| word.trim_matches(|c| matches!(c, '"' | '\'' | ',' | '(' | ')')))
+            .filter(|word| !word.is_empty() && !word.starts_with('-'))
+            .any(|word| self.is_ignored(&cwd.join(shellexpand::tilde(word).as_ref())))
+    }
+
+    /// Last matching rule wins
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            if rule.regex.is_match(relative) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Normalize `path` to a root-relative path without `.` or `..` components
+    fn relative_path(&self, path: &Path) -> Option<PathBuf> {
+        let joined = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.root.join(path)
+        };
+
+        let mut normalized = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                other => normalized.push(other.as_os_str()),
+            }
+        }
+
+        let relative = normalized.strip_prefix(&self.root).ok()?;
+        if relative.as_os_str().is_empty() {
+            return None;
+        }
+        Some(relative.to_path_buf())
+    }
+}
+
+pub(crate) fn glob_to_regex(pattern: &str) -> String {
+    let mut regex = String::new();
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    // `**/` matches zero or more directories
+                    regex.push_str("(?:.*/)?");
+                    i += 3;
+                } else {
+                    regex.push_str(".*");
+                    i += 2;
+                }
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match chars[i..].iter().position(|&c| c == ']') {
+                Some(len) if len > 1 => {
+                    let class: String = chars[i + 1..i + len].iter().collect();
+                    let class = class
+                        .strip_prefix('!')
+                        .map_or(class.clone(), |c| format!("^{}", c));
+                    regex.push('[');
+                    regex.push_str(&class.replace('\\', "\\\\"));
+                    regex.push(']');
+                    i += len + 1;
+                    continue;
+                }
+                _ => regex.push_str("\\["),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(contents: &str) -> G3Ignore {
+        G3Ignore::parse(Path::new("/project"), contents)
+    }
+
+    #[test]
+    fn test_unanchored_patterns_match_at_any_depth() {
+        let ignore = rules("*.pem\nnode_modules\n# comment\n");
+        assert!(ignore.is_ignored(Path::new("key.pem")));
+        assert!(ignore.is_ignored(Path::new("certs/prod/key.pem")));
+        assert!(ignore.is_ignored(Path::new("web/node_modules/react/index.js")));
+        assert!(!ignore.is_ignored(Path::new("src/pem.rs")));
+    }
+
+    #[test]
+    fn test_anchored_and_directory_patterns() {
+        let ignore = rules("/target/\nfixtures/**/*.json\n");
+        assert!(ignore.is_ignored(Path::new("target/debug/g3")));
+        assert!(!ignore.is_ignored(Path::new("crates/target")));
+        assert!(ignore.is_ignored(Path::new("fixtures/a/b/data.json")));
+        assert!(ignore.is_ignored(Path::new("fixtures/data.json")));
+        assert!(!ignore.is_ignored(Path::new("src/fixtures/data.json")));
+    }
+
+    #[test]
+    fn test_negation_and_absolute_paths() {
+        let ignore = rules(".env*\n!.env.example\n");
+        assert!(ignore.is_ignored(Path::new("/project/.env")));
+        assert!(ignore.is_ignored(Path::new("./config/../.env.local")));
+        assert!(!ignore.is_ignored(Path::new(".env.example")));
+        // Outside the root
+        assert!(!ignore.is_ignored(Path::new("/elsewhere/.env")));
+    }
+
+    #[test]
+    fn test_check_tool_call() {
+        let ignore = rules("*.pem\n");
+        let call = |tool: &str, args: serde_json::Value| ToolCall {
+            tool: tool.to_string(),
+            args,
+        };
+
+        assert!(ignore
+            .check_tool_call(&call("read_file", json!({"file_path": "certs/key.pem"})))
+            .is_some());
+        assert!(ignore
+            .check_tool_call(&call("read_file", json!({"file_path": "src/main.rs"})))
+            .is_none());
+        assert!(ignore
+            .check_tool_call(&call(
+                "shell",
+                json!({"command": "cat 'certs/key.pem' | head"})
+            ))
+            .is_some());
+        assert!(ignore
+            .check_tool_call(&call("shell", json!({"command": "ls -la src"})))
+            .is_none());
+    }
+
+    #[test]
+    fn test_searches_exclude_ignored_files() {
+        let ignore = rules("*.pem\n/target/\n.env*\n!.env.example\n");
+        assert_eq!(
+            ignore.exclude_from_searches("rg -n TODO src | head"),
+            "rg -g '!*.pem' -g '!/target/' -g '!.env*' -n TODO src | head"
+        );
+        assert_eq!(
+            ignore.exclude_from_searches("cd src && grep -rn key ."),
+            "cd src && grep --exclude='*.pem' --exclude-dir='*.pem' --exclude='.env*' \
+             --exclude-dir='.env*' -rn key ."
+        );
+        // Only commands, not arguments that happen to be called grep
+        assert_eq!(
+            ignore.exclude_from_searches("git log --grep fix"),
+            "git log --grep fix"
+        );
+        assert_eq!(rules("").exclude_from_searches("rg x"), "rg x");
+    }
+
+    #[test]
+    fn test_filter_output_removes_lines_naming_ignored_files() {
+        let ignore = rules("*.pem\n.env\n");
+        let root = Path::new("/project");
+        let output = "src/main.rs:3:fn main() {}\n./.env:1:API_KEY=abc\ncerts/key.pem\nREADME.md";
+        assert_eq!(
+            ignore.filter_output(output, root),
+            "src/main.rs:3:fn main() {}\nREADME.md\n(2 lines naming files excluded by \
+             .g3ignore removed)"
+        );
+        // Relative to the directory the command ran in
+        assert!(ignore.names_ignored_path("key.pem", &root.join("certs")));
+        assert_eq!(
+            ignore.filter_output("src\nREADME.md", root),
+            "src\nREADME.md"
+        );
+    }
+
+    #[test]
+    fn test_character_classes() {
+        let ignore = rules("secret[0-9].txt\nlog[!s].txt\n");
+        assert!(ignore.is_ignored(Path::new("secret1.txt")));
+        assert!(!ignore.is_ignored(Path::new("secretx.txt")));
+        assert!(ignore.is_ignored(Path::new("log1.txt")));
+        assert!(!ignore.is_ignored(Path::new("logs.txt")));
+    }
+}
@@ -0,0 +1,189 @@
+//! Agent instructions from the project: `AGENTS.md`, `CLAUDE.md` and Cursor rules.
+//!
+//! In a monorepo g3 is often started in a package directory. The instructions of the nearest
+//! directory with any, from the working directory up to the repository root, are loaded
+//! together with the root's. Root instructions come first and the nearer ones after them,
+//! so the model is told that later instructions take precedence where they disagree.
+
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+use crate::g3ignore::G3Ignore;
+
+/// Instruction files looked for in each directory, in the order they are loaded. Only the
+/// first of the `AGENTS.md` spellings present is used.
+const AGENTS_FILES: &[&str] = &["AGENTS.md", "agents.md"];
+const CLAUDE_FILE: &str = "CLAUDE.md";
+/// A directory of `.md`/`.mdc` rule files
+const CURSOR_RULES_DIR: &str = ".cursor/rules";
+
+/// Start of the instructions section, also used to recognise it in the conversation
+pub const HEADER: &str = "🤖 Agent Configuration";
+
+/// An instruction file that was found
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionFile {
+    pub path: PathBuf,
+    /// The path relative to the working directory, for display
+    pub display: String,
+}
+
+/// The repository root above `dir`: the nearest directory with `.git`, or `dir` itself
+pub fn repository_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Find the instruction files for `dir`, root's first. Files hidden by `ignore` (the
+/// working directory's `.g3ignore`) or the root's `.g3ignore` are skipped.
+pub fn discover(dir: &Path, ignore: &G3Ignore) -> Vec<InstructionFile> {
+    let root = repository_root(dir);
+    let root_ignore = G3Ignore::load(&root);
+    let visible = |path: &Path| !ignore.is_ignored(path) && !root_ignore.is_ignored(path);
+
+    let nearest = dir
+        .ancestors()
+        .take_while(|d| d.starts_with(&root))
+        .map(|d| (d, files_in(d, &visible)))
+        .find(|(_, files)| !files.is_empty());
+    let mut files = match nearest {
+        Some((nearest_dir, _)) if nearest_dir == root => Vec::new(),
+        _ => files_in(&root, &visible),
+    };
+    files.extend(nearest.map(|(_, files)| files).unwrap_or_default());
+
+    files
+        .into_iter()
+        .map(|path| InstructionFile {
+            display: display_path(&path, dir),
+            path,
+        })
+        .collect()
+}
+
+fn files_in(dir: &Path, visible: &impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Some(agents) = AGENTS_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file() && visible(path))
+    {
+        files.push(agents);
+    }
+    let claude = dir.join(CLAUDE_FILE);
+    if claude.is_file() && visible(&claude) {
+        files.push(claude);
+    }
+    if let Ok(entries) = std::fs::read_dir(dir.join(CURSOR_RULES_DIR)) {
+        let mut rules: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.is_file()
+                    && matches!(
+                        path.extension().and_then(|e| e.to_str()),
+                        Some("md" | "mdc")
+                    )
+                    && visible(path)
+            })
+            .collect();
+        rules.sort();
+        files.extend(rules);
+    }
+    files
+}
+
+/// `path` relative to `dir`, with `..` for the directories above it
+fn display_path(path: &Path, dir: &Path) -> String {
+    let mut up = String::new();
+    for ancestor in dir.ancestors() {
+        if let Ok(relative) = path.strip_prefix(ancestor) {
+            return format!("{}{}", up, relative.display());
+        }
+        up.push_str("../");
+    }
+    path.display().to_string()
+}
+
+/// Load the instructions for `dir` as a context section, or `None` if there are none
+pub fn load(dir: &Path, ignore: &G3Ignore) -> Option<String> {
+    let mut names = Vec::new();
+    let mut sections = Vec::new();
+    for file in discover(dir, ignore) {
+        match std::fs::read_to_string(&file.path) {
+            Ok(content) => {
+                sections.push(format!("### {}\n\n{}", file.display, content.trim_end()));
+                names.push(file.display);
+            }
+            Err(e) => error!("Failed to read {}: {}", file.path.display(), e),
+        }
+    }
+
+    match sections.len() {
+        0 => None,
+        1 => Some(format!(
+            "{} (from {}):\n\n{}",
+            HEADER,
+            names[0],
+            sections[0].split_once("\n\n").map_or("", |(_, c)| c)
+        )),
+        _ => Some(format!(
+            "{} (from {}):\n\nThese files are listed from the repository root down to the \
+             working directory. Where they disagree, later files take precedence.\n\n{}",
+            HEADER,
+            names.join(", "),
+            sections.join("\n\n")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discover_in_monorepo() {
+        let repo = tempfile::TempDir::new().unwrap();
+        let root = repo.path();
+        let package = root.join("packages/web");
+        let src = package.join("src");
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::create_dir_all(package.join(".cursor/rules")).unwrap();
+        std::fs::write(root.join("AGENTS.md"), "Use tabs").unwrap();
+        std::fs::write(root.join("CLAUDE.md"), "Be brief").unwrap();
+        std::fs::write(root.join("packages/AGENTS.md"), "Not nearest").unwrap();
+        std::fs::write(package.join("AGENTS.md"), "Use spaces").unwrap();
+        std::fs::write(package.join(".cursor/rules/style.mdc"), "No emoji").unwrap();
+        std::fs::write(package.join(".cursor/rules/notes.txt"), "Skipped").unwrap();
+        std::fs::write(root.join(".g3ignore"), "CLAUDE.md\n").unwrap();
+
+        // The root's and the nearest directory's files, root first; the root's CLAUDE.md is
+        // hidden by its .g3ignore
+        let files: Vec<String> = discover(&src, &G3Ignore::default())
+            .into_iter()
+            .map(|f| f.display)
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "../../../AGENTS.md",
+                "../AGENTS.md",
+                "../.cursor/rules/style.mdc"
+            ]
+        );
+        let content = load(&src, &G3Ignore::default()).unwrap();
+        assert!(content.starts_with(HEADER));
+        assert!(content.find("Use tabs").unwrap() < content.find("Use spaces").unwrap());
+        assert!(content.contains("later files take precedence"));
+
+        // At the root only its own files are loaded, with the single-file format
+        std::fs::remove_file(root.join(".g3ignore")).unwrap();
+        std::fs::remove_file(root.join("CLAUDE.md")).unwrap();
+        assert_eq!(
+            load(root, &G3Ignore::default()).unwrap(),
+            format!("{} (from AGENTS.md):\n\nUse tabs", HEADER)
+        );
+    }
+}
@@ -1,12 +1,70 @@
+pub mod artifacts;
+pub mod binary_file;
+pub mod budget;
+pub mod change_summary;
 pub mod code_search;
+pub mod context;
+pub mod custom_tools;
+#[cfg(unix)]
+pub mod daemon;
+pub mod downloads;
+pub mod edit_commits;
+pub mod embed;
 pub mod error_handling;
+pub mod events;
+pub mod explain;
+pub mod feedback;
+pub mod file_changes;
+pub mod file_lock;
+pub mod g3ignore;
+pub mod instructions;
+pub mod moderation;
+pub mod notify;
+pub mod ocr;
+pub mod output_tokens;
+pub mod partial_tool_call;
+pub mod plugins;
+pub mod profile;
 pub mod project;
+pub mod project_index;
+pub mod quota;
+pub mod read_history;
+pub mod read_only;
+pub mod remote;
+pub mod run_commits;
+pub mod scope;
+pub mod session_graph;
+pub mod session_logs;
+pub mod shell_output;
+pub mod shell_redirect;
+pub mod stats;
+pub mod step;
+pub mod stream_rate;
+pub mod stream_recording;
+pub mod syntax_check;
+pub mod system_prompt;
 pub mod task_result;
+pub mod text_display;
+pub mod tokenizer;
+pub mod tool_registry;
+pub mod tool_result_limit;
+pub mod tools;
+pub mod undo;
+pub mod unity_catalog;
+pub mod vision;
+pub mod workspace_guard;
+pub mod workspace_snapshot;
 pub mod ui_writer;
-pub use task_result::TaskResult;
+pub use context::{CompactionStrategy, ContextWindow, ShrinkOutcome, SummaryCompaction, ThinningCandidate};
+pub use embed::{AgentBuilder, TaskEvent, TaskStream};
+pub use feedback::{CoachFeedback, FeedbackVerdict};
+pub use task_result::{TaskResult, TurnMetrics};
 
 #[cfg(test)]
 mod task_result_comprehensive_tests;
+use crate::artifacts::{ArtifactKind, ArtifactStore};
+use crate::change_summary::ChangeSummary;
+use crate::tool_registry::{AuditEntry, ToolGroup, ToolRegistry, ToolSpec};
 use crate::ui_writer::UiWriter;
 
 // Make fixed_filter_json public so it can be accessed from g3-cli
@@ -19,19 +77,52 @@ mod tilde_expansion_tests;
 
 #[cfg(test)]
 mod error_handling_test;
+#[cfg(test)]
+mod test_provider;
+#[cfg(test)]
+mod turn_tests;
+#[cfg(test)]
+mod webdriver_tests;
 use anyhow::Result;
+use error_handling::G3Error;
 use g3_computer_control::WebDriverController;
-use g3_config::Config;
-use g3_execution::CodeExecutor;
-use g3_providers::{CompletionRequest, Message, MessageRole, ProviderRegistry, Tool};
+use g3_config::{Config, QuotaAction, ThinningStrategy, ToolResultStrategy};
+use g3_providers::{
+    Capabilities, CompletionRequest, Message, MessageRole, ProviderRegistry, Tool, ToolChoice,
+};
 #[allow(unused_imports)]
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
+/// Room always left for the model's response when checking a request against the context window
+const MIN_RESPONSE_TOKENS: u32 = 4096;
+
+/// How long a WebDriver session has to answer the check made before each WebDriver tool
+const WEBDRIVER_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the WebDriver wait tools wait by default, and at most
+const WEBDRIVER_WAIT_DEFAULT: Duration = Duration::from_secs(10);
+const WEBDRIVER_WAIT_MAX: Duration = Duration::from_secs(120);
+
+/// Sent to resume a response that stopped at the output token limit, with `/continue` or
+/// automatically in autonomous mode. The response and its continuation are joined into one
+/// message in the context window.
+pub const CONTINUE_PROMPT: &str = "Your previous response was cut off because it reached the output token limit. Continue exactly where it stopped, without repeating anything or adding a preamble. If it stopped in the middle of a tool call, make the complete call again.";
+
+/// Sent to resume a response whose stream was interrupted by a dropped connection, a
+/// stall or a server error. Joined with the response like [`CONTINUE_PROMPT`].
+pub const RESUME_PROMPT: &str = "Your previous response was interrupted because the connection to you dropped. Continue exactly where it stopped, without repeating anything or adding a preamble. If it stopped in the middle of a tool call, make the complete call again.";
+
+/// System message added once a turn has run past `turn_timeout_seconds`, before the
+/// model's next response
+pub const WRAP_UP_PROMPT: &str = "This turn has run past its time limit. Stop starting new work: finish or undo any edit in progress so the code is left in a consistent state, then call final_output with a summary of what you completed, what is left to do, and anything the user should check.";
+
+/// Responses allowed after [`WRAP_UP_PROMPT`] before the turn is stopped
+const WRAP_UP_ITERATIONS: usize = 5;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
     pub tool: String,
@@ -53,6 +144,8 @@ pub struct StreamingToolParser {
     text_buffer: String,
     /// Buffer for accumulating native tool calls
     native_tool_calls: Vec<g3_providers::ToolCall>,
+    /// Native tool calls whose arguments are still streaming, by position in the response
+    partial_tool_calls: Vec<partial_tool_call::PartialToolCall>,
     /// Whether we've received a message_stop event
     message_stopped: bool,
     /// Whether we're currently in a JSON tool call (for fallback parsing)
@@ -72,6 +165,7 @@ impl StreamingToolParser {
         Self {
             text_buffer: String::new(),
             native_tool_calls: Vec::new(),
+            partial_tool_calls: Vec::new(),
             message_stopped: false,
             in_json_tool_call: false,
             json_tool_start: None,
@@ -87,6 +181,24 @@ impl StreamingToolParser {
             self.text_buffer.push_str(&chunk.content);
         }
 
+        // Assemble tool calls whose arguments are streaming
+        if let Some(ref deltas) = chunk.tool_call_deltas {
+            for delta in deltas {
+                if self.partial_tool_calls.len() <= delta.index {
+                    self.partial_tool_calls
+                        .resize_with(delta.index + 1, Default::default);
+                }
+                let partial = &mut self.partial_tool_calls[delta.index];
+                if let Some(id) = &delta.id {
+                    partial.id = Some(id.clone());
+                }
+                if let Some(tool) = &delta.tool {
+                    partial.tool = tool.clone();
+                }
+                partial.arguments.push_str(&delta.arguments);
+            }
+        }
+
         // Handle native tool calls
         if let Some(ref tool_calls) = chunk.tool_calls {
             debug!("Received native tool calls: {:?}", tool_calls);
@@ -120,6 +232,7 @@ impl StreamingToolParser {
 
             // Clear native tool calls after processing
             self.native_tool_calls.clear();
+            self.partial_tool_calls.clear();
         }
 
         // Fallback: Try to parse JSON tool calls from text if no native tool calls
@@ -260,6 +373,11 @@ impl StreamingToolParser {
         }
     }
 
+    /// Native tool calls whose arguments are still arriving
+    pub fn partial_tool_calls(&self) -> &[partial_tool_call::PartialToolCall] {
+        &self.partial_tool_calls
+    }
+
     /// Check if the message has been stopped/finished
     pub fn is_message_stopped(&self) -> bool {
         self.message_stopped
@@ -269,6 +387,7 @@ impl StreamingToolParser {
     pub fn reset(&mut self) {
         self.text_buffer.clear();
         self.native_tool_calls.clear();
+        self.partial_tool_calls.clear();
         self.message_stopped = false;
         self.in_json_tool_call = false;
         self.json_tool_start = None;
@@ -280,482 +399,90 @@ impl StreamingToolParser {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ContextWindow {
-    pub used_tokens: u32,
-    pub total_tokens: u32,
-    pub cumulative_tokens: u32, // Track cumulative tokens across all interactions
-    pub conversation_history: Vec<Message>,
-    pub last_thinning_percentage: u32, // Track the last percentage at which we thinned
-}
-
-impl ContextWindow {
-    pub fn new(total_tokens: u32) -> Self {
-        Self {
-            used_tokens: 0,
-            total_tokens,
-            cumulative_tokens: 0,
-            conversation_history: Vec::new(),
-            last_thinning_percentage: 0,
-        }
-    }
-
-    pub fn add_message(&mut self, message: Message) {
-        self.add_message_with_tokens(message, None);
-    }
-
-    /// Add a message with optional token count from the provider
-    pub fn add_message_with_tokens(&mut self, message: Message, tokens: Option<u32>) {
-        // Skip messages with empty content to avoid API errors
-        if message.content.trim().is_empty() {
-            warn!("Skipping empty message to avoid API error");
-            return;
-        }
-
-        // Use provided token count if available, otherwise estimate
-        let token_count = tokens.unwrap_or_else(|| Self::estimate_tokens(&message.content));
-        self.used_tokens += token_count;
-        self.cumulative_tokens += token_count;
-        self.conversation_history.push(message);
-
-        debug!(
-            "Added message with {} tokens (used: {}/{}, cumulative: {})",
-            token_count, self.used_tokens, self.total_tokens, self.cumulative_tokens
-        );
-    }
-
-    /// Update token usage from provider response
-    pub fn update_usage_from_response(&mut self, usage: &g3_providers::Usage) {
-        // Add the tokens from this response to our running total
-        // The usage.total_tokens represents tokens used in this single API call
-        self.used_tokens += usage.total_tokens;
-        self.cumulative_tokens += usage.total_tokens;
-
-        debug!(
-            "Added {} tokens from provider response (used: {}/{}, cumulative: {})",
-            usage.total_tokens, self.used_tokens, self.total_tokens, self.cumulative_tokens
-        );
-    }
-
-    /// More accurate token estimation
-    fn estimate_tokens(text: &str) -> u32 {
-        // Better heuristic:
-        // - Average English text: ~4 characters per token
-        // - Code/JSON: ~3 characters per token (more symbols)
-        // - Add 10% buffer for safety
-        let base_estimate = if text.contains("{") || text.contains("```") || text.contains("fn ") {
-            (text.len() as f32 / 3.0).ceil() as u32 // Code/JSON
-        } else {
-            (text.len() as f32 / 4.0).ceil() as u32 // Regular text
-        };
-        (base_estimate as f32 * 1.1).ceil() as u32 // Add 10% buffer
-    }
-
-    pub fn update_usage(&mut self, usage: &g3_providers::Usage) {
-        // Deprecated: Use update_usage_from_response instead
-        self.update_usage_from_response(usage);
-    }
-
-    /// Update cumulative token usage (for streaming)
-    pub fn add_streaming_tokens(&mut self, new_tokens: u32) {
-        self.used_tokens += new_tokens;
-        self.cumulative_tokens += new_tokens;
-        debug!(
-            "Added {} streaming tokens (used: {}/{}, cumulative: {})",
-            new_tokens, self.used_tokens, self.total_tokens, self.cumulative_tokens
-        );
-    }
-
-    pub fn percentage_used(&self) -> f32 {
-        if self.total_tokens == 0 {
-            0.0
-        } else {
-            (self.used_tokens as f32 / self.total_tokens as f32) * 100.0
-        }
-    }
-
-    pub fn remaining_tokens(&self) -> u32 {
-        self.total_tokens.saturating_sub(self.used_tokens)
-    }
-
-    /// Check if we should trigger summarization (at 80% capacity)
-    pub fn should_summarize(&self) -> bool {
-        // Trigger at 80% OR if we're getting close to absolute limits
-        // This prevents issues with models that have large contexts but still hit limits
-        let percentage_trigger = self.percentage_used() >= 80.0;
-
-        // Also trigger if we're approaching common token limits
-        // Most models start having issues around 150k tokens
-        let absolute_trigger = self.used_tokens > 150_000;
-
-        percentage_trigger || absolute_trigger
-    }
-
-    /// Create a summary request prompt for the current conversation
-    pub fn create_summary_prompt(&self) -> String {
-        "Please provide a comprehensive summary of our conversation so far. Include:
-
-1. **Main Topic/Goal**: What is the primary task or objective being worked on?
-2. **Key Decisions**: What important decisions have been made?
-3. **Actions Taken**: What specific actions, commands, or code changes have been completed?
-4. **Current State**: What is the current status of the work?
-5. **Important Context**: Any critical information, file paths, configurations, or constraints that should be remembered?
-6. **Pending Items**: What remains to be done or what was the user's last request?
-
-Format this as a detailed but concise summary that can be used to resume the conversation from scratch while maintaining full context.".to_string()
-    }
-
-    /// Reset the context window with a summary
-    pub fn reset_with_summary(
-        &mut self,
-        summary: String,
-        latest_user_message: Option<String>,
-    ) -> usize {
-        // Calculate chars saved (old history minus new summary)
-        let old_chars: usize = self
-            .conversation_history
-            .iter()
-            .map(|m| m.content.len())
-            .sum();
-
-        // Clear the conversation history
-        self.conversation_history.clear();
-        self.used_tokens = 0;
-
-        // Add the summary as a system message
-        let summary_message = Message {
-            role: MessageRole::System,
-            content: format!("Previous conversation summary:\n\n{}", summary),
-        };
-        self.add_message(summary_message);
-
-        // Add the latest user message if provided
-        if let Some(user_msg) = latest_user_message {
-            self.add_message(Message {
-                role: MessageRole::User,
-                content: user_msg,
-            });
-        }
-
-        let new_chars: usize = self
-            .conversation_history
-            .iter()
-            .map(|m| m.content.len())
-            .sum();
-        old_chars.saturating_sub(new_chars)
-    }
-
-    /// Check if we should trigger context thinning
-    /// Triggers at 50%, 60%, 70%, and 80% thresholds
-    pub fn should_thin(&self) -> bool {
-        let current_percentage = self.percentage_used() as u32;
-
-        // Check if we've crossed a new 10% threshold starting at 50%
-        if current_percentage >= 50 {
-            let current_threshold = (current_percentage / 10) * 10; // Round down to nearest 10%
-            if current_threshold > self.last_thinning_percentage && current_threshold <= 80 {
-                return true;
-            }
-        }
-
-        false
-    }
-
-    /// Perform context thinning: scan first third of conversation and replace large tool results
-    /// Returns a summary message about what was thinned
-    pub fn thin_context(&mut self) -> (String, usize) {
-        let current_percentage = self.percentage_used() as u32;
-        let current_threshold = (current_percentage / 10) * 10;
-
-        // Update the last thinning percentage
-        self.last_thinning_percentage = current_threshold;
-
-        // Calculate the first third of the conversation
-        let total_messages = self.conversation_history.len();
-        let first_third_end = (total_messages / 3).max(1);
-
-        let mut leaned_count = 0;
-        let mut tool_call_leaned_count = 0;
-        let mut chars_saved = 0;
-
-        // Create ~/tmp directory if it doesn't exist
-        let tmp_dir = shellexpand::tilde("~/tmp").to_string();
-        if let Err(e) = std::fs::create_dir_all(&tmp_dir) {
-            warn!("Failed to create ~/tmp directory: {}", e);
-            return (
-                "⚠️  Context thinning failed: could not create ~/tmp directory".to_string(),
-                0,
-            );
-        }
-
-        // Scan the first third of messages
-        for i in 0..first_third_end {
-            // Check if the previous message was a TODO tool call (before getting mutable reference)
-            let is_todo_result = if i > 0 {
-                if let Some(prev_message) = self.conversation_history.get(i - 1) {
-                    if matches!(prev_message.role, MessageRole::Assistant) {
-                        prev_message.content.contains(r#""tool":"todo_read""#) ||
-                        prev_message.content.contains(r#""tool":"todo_write""#) ||
-                        prev_message.content.contains(r#""tool": "todo_read""#) ||
-                        prev_message.content.contains(r#""tool": "todo_write""#)
-                    } else { false }
-                } else { false }
-            } else { false };
-            
-            if let Some(message) = self.conversation_history.get_mut(i) {
-                // Process User messages that look like tool results
-                if matches!(message.role, MessageRole::User)
-                    && message.content.starts_with("Tool result:")
-                {
-                    let content_len = message.content.len();
-                    
-                    // Only thin if the content is greater than 500 chars and not a TODO tool result
-                    if !is_todo_result && content_len > 500 {
-                        // Generate a unique filename based on timestamp and index
-                        let timestamp = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .as_secs();
-                        let filename = format!("leaned_tool_result_{}_{}.txt", timestamp, i);
-                        let file_path = format!("{}/{}", tmp_dir, filename);
-
-                        // Write the content to file
-                        if let Err(e) = std::fs::write(&file_path, &message.content) {
-                            warn!("Failed to write thinned content to {}: {}", file_path, e);
-                            continue;
-                        }
-
-                        // Replace the message content with a note
-                        let original_len = message.content.len();
-                        message.content = format!("Tool result saved to {}", file_path);
-
-                        leaned_count += 1;
-                        chars_saved += original_len - message.content.len();
-
-                        debug!(
-                            "Thinned tool result {} ({} chars) to {}",
-                            i, original_len, file_path
-                        );
-                    }
-                }
-
-                // Process Assistant messages that contain tool calls with large arguments
-                if matches!(message.role, MessageRole::Assistant) {
-                    // Try to parse the message content as JSON to find tool calls
-                    let content = &message.content;
-
-                    // Look for JSON tool call patterns
-                    if let Some(tool_call_start) = content
-                        .find(r#"{"tool":"#)
-                        .or_else(|| content.find(r#"{ "tool":"#))
-                        .or_else(|| content.find(r#"{"tool" :"#))
-                        .or_else(|| content.find(r#"{ "tool" :"#))
-                    {
-                        // Try to extract and parse the JSON tool call
-                        let json_portion = &content[tool_call_start..];
-
-                        // Find the end of the JSON object
-                        if let Some(json_end) = Self::find_json_end(json_portion) {
-                            let json_str = &json_portion[..=json_end];
-
-                            // Try to parse as ToolCall
-                            if let Ok(mut tool_call) = serde_json::from_str::<ToolCall>(json_str) {
-                                let mut modified = false;
-
-                                // Handle write_file tool calls
-                                if tool_call.tool == "write_file" {
-                                    if let Some(args_obj) = tool_call.args.as_object_mut() {
-                                        // Extract content to avoid borrow issues
-                                        let content_info = args_obj
-                                            .get("content")
-                                            .and_then(|v| v.as_str())
-                                            .map(|s| (s.to_string(), s.len()));
-
-                                        if let Some((content_str, content_len)) = content_info {
-                                            // Only thin if content is greater than 500 chars
-                                            if content_len > 500 {
-                                                let timestamp = std::time::SystemTime::now()
-                                                    .duration_since(std::time::UNIX_EPOCH)
-                                                    .unwrap_or_default()
-                                                    .as_secs();
-                                                let filename = format!(
-                                                    "leaned_write_file_content_{}_{}.txt",
-                                                    timestamp, i
-                                                );
-                                                let file_path = format!("{}/{}", tmp_dir, filename);
-
-                                                if std::fs::write(&file_path, &content_str).is_ok()
-                                                {
-                                                    args_obj.insert(
-                                                        "content".to_string(),
-                                                        serde_json::Value::String(format!(
-                                                            "<content saved to {}>",
-                                                            file_path
-                                                        )),
-                                                    );
-                                                    modified = true;
-                                                    chars_saved += content_len;
-                                                    tool_call_leaned_count += 1;
-                                                    debug!("Thinned write_file content {} ({} chars) to {}", i, content_len, file_path);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                // Handle str_replace tool calls
-                                if tool_call.tool == "str_replace" {
-                                    if let Some(args_obj) = tool_call.args.as_object_mut() {
-                                        // Extract diff to avoid borrow issues
-                                        let diff_info = args_obj
-                                            .get("diff")
-                                            .and_then(|v| v.as_str())
-                                            .map(|s| (s.to_string(), s.len()));
-
-                                        if let Some((diff_str, diff_len)) = diff_info {
-                                            // Only thin if diff is greater than 500 chars
-                                            if diff_len > 500 {
-                                                let timestamp = std::time::SystemTime::now()
-                                                    .duration_since(std::time::UNIX_EPOCH)
-                                                    .unwrap_or_default()
-                                                    .as_secs();
-                                                let filename = format!(
-                                                    "leaned_str_replace_diff_{}_{}.txt",
-                                                    timestamp, i
-                                                );
-                                                let file_path = format!("{}/{}", tmp_dir, filename);
-
-                                                if std::fs::write(&file_path, &diff_str).is_ok() {
-                                                    args_obj.insert(
-                                                        "diff".to_string(),
-                                                        serde_json::Value::String(format!(
-                                                            "<diff saved to {}>",
-                                                            file_path
-                                                        )),
-                                                    );
-                                                    modified = true;
-                                                    chars_saved += diff_len;
-                                                    tool_call_leaned_count += 1;
-                                                    debug!("Thinned str_replace diff {} ({} chars) to {}", i, diff_len, file_path);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-
-                                // If we modified the tool call, reconstruct the message
-                                if modified {
-                                    let prefix = &content[..tool_call_start];
-                                    let suffix = &content[tool_call_start + json_str.len()..];
-
-                                    // Serialize the modified tool call
-                                    if let Ok(new_json) = serde_json::to_string(&tool_call) {
-                                        message.content =
-                                            format!("{}{}{}", prefix, new_json, suffix);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // Recalculate token usage after thinning
-        self.recalculate_tokens();
-
-        if leaned_count > 0 {
-            if tool_call_leaned_count > 0 {
-                (format!("🥒 Context thinned at {}%: {} tool results + {} tool calls, ~{} chars saved",
-                        current_threshold, leaned_count, tool_call_leaned_count, chars_saved), chars_saved)
-            } else {
-                (
-                    format!(
-                        "🥒 Context thinned at {}%: {} tool results, ~{} chars saved",
-                        current_threshold, leaned_count, chars_saved
-                    ),
-                    chars_saved,
-                )
-            }
-        } else if tool_call_leaned_count > 0 {
-            (
-                format!(
-                    "🥒 Context thinned at {}%: {} tool calls, ~{} chars saved",
-                    current_threshold, tool_call_leaned_count, chars_saved
-                ),
-                chars_saved,
-            )
-        } else {
-            (format!("ℹ Context thinning triggered at {}% but no large tool results or tool calls found in first third",
-                    current_threshold), 0)
-        }
-    }
-
-    /// Recalculate token usage based on current conversation history
-    fn recalculate_tokens(&mut self) {
-        let mut total = 0;
-        for message in &self.conversation_history {
-            total += Self::estimate_tokens(&message.content);
-        }
-        self.used_tokens = total;
-
-        debug!("Recalculated tokens after thinning: {} tokens", total);
-    }
-
-    /// Helper function to find the end of a JSON object
-    fn find_json_end(json_str: &str) -> Option<usize> {
-        let mut brace_count = 0;
-        let mut in_string = false;
-        let mut escape_next = false;
-
-        for (i, ch) in json_str.char_indices() {
-            if escape_next {
-                escape_next = false;
-                continue;
-            }
-
-            match ch {
-                '\\' => escape_next = true,
-                '"' if !escape_next => in_string = !in_string,
-                '{' if !in_string => brace_count += 1,
-                '}' if !in_string => {
-                    brace_count -= 1;
-                    if brace_count == 0 {
-                        return Some(i);
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        None
-    }
+/// Send a one-line request to the default provider of `config`, e.g. to check that its
+/// credentials and model work. Returns the model's reply.
+pub async fn check_provider(config: &Config) -> Result<String> {
+    let providers = Agent::<ui_writer::NullUiWriter>::build_providers(config, false).await?;
+    let provider = providers.get(None)?;
+    let request = CompletionRequest {
+        messages: vec![Message {
+            role: MessageRole::User,
+            content: "Reply with the single word: ready".to_string(),
+        }],
+        max_tokens: Some(16),
+        temperature: Some(0.0),
+        stream: false,
+        tools: None,
+        tool_choice: ToolChoice::Auto,
+        stop_sequences: Vec::new(),
+    };
+    Ok(provider.complete(request).await?.content)
 }
 
 pub struct Agent<W: UiWriter> {
     providers: ProviderRegistry,
+    base_providers: g3_config::ProvidersConfig, // provider config before any profile was applied
     context_window: ContextWindow,
     thinning_events: Vec<usize>, // chars saved per thinning event
     pending_90_summarization: bool, // flag to trigger summarization at 90%
     auto_compact: bool, // whether to auto-compact at 90% before tool calls
     summarization_events: Vec<usize>, // chars saved per summarization event
     first_token_times: Vec<Duration>, // time to first token for each completion
+    request_times: Vec<Duration>, // of each first token time, sending the request until the provider responded
+    output_tokens: u32, // tokens generated by the model over the session
+    reasoning_tokens: u32, // of those, tokens a reasoning model spent thinking
     config: Config,
     session_id: Option<String>,
     tool_call_metrics: Vec<(String, Duration, bool)>, // (tool_name, duration, success)
-    ui_writer: W,
+    tool_context: context::ToolContextLedger, // context tokens added by each tool's results
+    turn_metrics: Vec<TurnMetrics>, // per-task token/time/tool accounting
+    events: events::EventBus<W>, // what happens during turns, rendered by the UI writer
     is_autonomous: bool,
+    is_coach: bool,
+    coach_feedback: tokio::sync::RwLock<Option<CoachFeedback>>, // set by give_feedback during the current turn
     quiet: bool,
-    computer_controller: Option<Box<dyn g3_computer_control::ComputerController>>,
+    computer_controller: Option<std::sync::Arc<dyn g3_computer_control::ComputerController>>,
+    ocr: ocr::Ocr, // limits and cache of OCR, from [computer_control.ocr]
+    recording: tokio::sync::Mutex<Option<g3_computer_control::recording::Recording>>, // screen recording in progress
     todo_content: std::sync::Arc<tokio::sync::RwLock<String>>,
+    g3ignore: g3ignore::G3Ignore, // files hidden from the agent
+    scope: scope::PathScope, // directories tools may write, read and run commands in
+    remote: Option<g3_execution::SshTarget>, // host shell, read_file and write_file work on
+    container: Option<g3_execution::ContainerTarget>, // dev container shell commands run in
+    project_env: g3_config::env::ProjectEnv, // variables of the workspace's .env files, for shell commands
+    semantic_index: tokio::sync::Mutex<Option<(std::path::PathBuf, code_search::semantic::SemanticIndex)>>, // loaded on first semantic search, by workspace
     webdriver_session: std::sync::Arc<
         tokio::sync::RwLock<
             Option<std::sync::Arc<tokio::sync::Mutex<g3_computer_control::SafariDriver>>>,
         >,
     >,
     safaridriver_process: std::sync::Arc<tokio::sync::RwLock<Option<tokio::process::Child>>>,
+    webdriver_last_url: std::sync::Mutex<Option<String>>, // page reopened if the session is restarted
+    webdriver_download_dir: std::sync::Mutex<std::path::PathBuf>, // watched for downloads
+    webdriver_last_call: std::sync::Mutex<Option<std::time::SystemTime>>, // when the last WebDriver action started
     macax_controller:
         std::sync::Arc<tokio::sync::RwLock<Option<g3_computer_control::MacAxController>>>,
+    unity_catalog: Option<unity_catalog::UnityCatalogTools>, // functions exposed as tools
+    moderator: moderation::Moderator,
+    stepper: Option<std::sync::Arc<dyn step::ToolStepper>>, // asked before each tool call runs (--step)
+    plugins: plugins::Plugins, // tools of command plugins
+    custom_tools: custom_tools::CustomTools, // tools registered by an embedding program
+    tool_handlers: tools::ToolHandlers<W>, // what calls of the built-in tools do
+    round_snapshot: std::sync::Mutex<Option<workspace_snapshot::WorkspaceSnapshot>>, // workspace before the current autonomous round
+    edit_commits: tokio::sync::Mutex<Option<edit_commits::EditCommits>>, // session branch file edits are committed to
+    edit_commits_started: std::sync::atomic::AtomicBool, // whether starting the session branch was tried
+    response_truncated: bool, // the last response stopped at the output token limit
+    file_changes: std::sync::Mutex<file_changes::FileChangeSet>, // files changed this session
+    task_file_changes: std::sync::Mutex<file_changes::FileChangeSet>, // files changed by the current task
+    file_locks: file_lock::FileLocks, // held while file tools write
+    file_versions: std::sync::Mutex<file_lock::FileVersions>, // files as the agent last saw them
+    read_history: read_history::ReadHistory, // read_file results, to spot repeated reads
+    tool_budgets: std::sync::Mutex<budget::ToolBudgets>, // calls counted against [budgets]
+    cancellation: CancellationToken, // cancels the current task, including a running shell command
+    running_command: std::sync::Arc<std::sync::atomic::AtomicBool>, // a shell command is running
+    next_tool_choice: ToolChoice, // tool choice of the first request of the next task
+    allowed_tools: Option<Vec<String>>, // built-in tools offered, set by AgentBuilder::tools
+    task_events: Option<std::sync::Arc<events::ChannelSubscriber>>, // events of run_task streams
 }
 
 impl<W: UiWriter> Agent<W> {
@@ -805,6 +532,14 @@ impl<W: UiWriter> Agent<W> {
         Self::new_with_mode_and_readme(config, ui_writer, true, readme_content, quiet).await
     }
 
+    /// Create the coach agent for autonomous mode. Coach agents get the give_feedback tool,
+    /// whose payload is returned in [`TaskResult::feedback`]
+    pub async fn new_coach_with_quiet(config: Config, ui_writer: W, quiet: bool) -> Result<Self> {
+        let mut agent = Self::new_with_mode(config, ui_writer, true, quiet).await?;
+        agent.is_coach = true;
+        Ok(agent)
+    }
+
     async fn new_with_mode(
         config: Config,
         ui_writer: W,
@@ -821,72 +556,344 @@ impl<W: UiWriter> Agent<W> {
         readme_content: Option<String>,
         quiet: bool,
     ) -> Result<Self> {
-        let mut providers = ProviderRegistry::new();
+        // A profile's provider settings are applied on top of the base provider config,
+        // which is kept so that switching profiles later starts from it again
+        let base_providers = config.providers.clone();
+        let config = match config.agent.profile.clone() {
+            Some(name) => config.with_profile(&name)?,
+            None => config,
+        };
+        let providers = Self::build_providers(&config, is_autonomous).await?;
+        let moderator = moderation::Moderator::new(config.moderation.clone());
 
-        // In autonomous mode, we need to register both coach and player providers
-        // Otherwise, only register the default provider
-        let providers_to_register: Vec<String> = if is_autonomous {
-            let mut providers = vec![config.providers.default_provider.clone()];
-            if let Some(coach) = &config.providers.coach {
-                if !providers.contains(coach) {
-                    providers.push(coach.clone());
-                }
-            }
-            if let Some(player) = &config.providers.player {
-                if !providers.contains(player) {
-                    providers.push(player.clone());
+        // Determine context window size based on active provider
+        let context_length = Self::get_configured_context_length(&config, &providers)?;
+        let mut context_window = ContextWindow::new(context_length);
+        context_window.thinning = config.context.thinning.clone();
+
+        // If README content is provided, add it as the first system message
+        if let Some(readme) = readme_content {
+            let readme_message = Message {
+                role: MessageRole::System,
+                content: readme,
+            };
+            context_window.add_message(readme_message);
+        }
+
+        // Initialize computer controller if enabled
+        let computer_controller = if config.computer_control.enabled {
+            match g3_computer_control::create_controller() {
+                Ok(controller) => Some(controller.into()),
+                Err(e) => {
+                    warn!("Failed to initialize computer control: {}", e);
+                    None
                 }
             }
-            providers
         } else {
-            vec![config.providers.default_provider.clone()]
+            None
         };
 
-        // Only register providers that are configured AND selected as the default provider
-        // This prevents unnecessary initialization of heavy providers like embedded models
-
-        // Register embedded provider if configured AND it's the default provider
-        if let Some(embedded_config) = &config.providers.embedded {
-            if providers_to_register.contains(&"embedded".to_string()) {
-                let embedded_provider = g3_providers::EmbeddedProvider::new(
-                    embedded_config.model_path.clone(),
-                    embedded_config.model_type.clone(),
-                    embedded_config.context_length,
-                    embedded_config.max_tokens,
-                    embedded_config.temperature,
-                    embedded_config.gpu_layers,
-                    embedded_config.threads,
-                )?;
-                providers.register(embedded_provider);
+        let unity_catalog = match unity_catalog::UnityCatalogTools::discover(&config).await {
+            Ok(tools) => tools,
+            Err(e) => {
+                warn!("Failed to set up Unity Catalog tools: {}", e);
+                None
             }
-        }
+        };
 
-        // Register OpenAI provider if configured AND it's the default provider
-        if let Some(openai_config) = &config.providers.openai {
-            if providers_to_register.contains(&"openai".to_string()) {
-                let openai_provider = g3_providers::OpenAIProvider::new(
-                    openai_config.api_key.clone(),
-                    Some(openai_config.model.clone()),
-                    openai_config.base_url.clone(),
-                    openai_config.max_tokens,
-                    openai_config.temperature,
-                )?;
-                providers.register(openai_provider);
-            }
-        }
+        let plugins = plugins::Plugins::start(&config.plugins).await;
+        let tool_budgets = budget::ToolBudgets::new(&config.budgets);
+        let ocr = ocr::Ocr::new(&config.computer_control.ocr);
 
-        // Register OpenAI-compatible providers (e.g., OpenRouter, Groq, etc.)
-        for (name, openai_config) in &config.providers.openai_compatible {
-            if providers_to_register.contains(name) {
-                let openai_provider = g3_providers::OpenAIProvider::new_with_name(
-                    name.clone(),
-                    openai_config.api_key.clone(),
-                    Some(openai_config.model.clone()),
-                    openai_config.base_url.clone(),
-                    openai_config.max_tokens,
-                    openai_config.temperature,
-                )?;
-                providers.register(openai_provider);
+        // Capture macax_enabled before moving config
+        let macax_enabled = config.macax.enabled;
+        let download_dir = std::path::PathBuf::from(
+            shellexpand::tilde(
+                config
+                    .webdriver
+                    .download_dir
+                    .as_deref()
+                    .unwrap_or(downloads::DEFAULT_DOWNLOAD_DIR),
+            )
+            .as_ref(),
+        );
+        let events = events::EventBus::new(ui_writer).with_plain(config.agent.plain_output);
+        let project_env = if config.agent.load_env_files {
+            match std::env::current_dir().map(|dir| g3_config::env::ProjectEnv::load(&dir)) {
+                Ok(Ok(env)) => env,
+                Ok(Err(e)) => {
+                    warn!("Ignoring the workspace's env files: {}", e);
+                    Default::default()
+                }
+                Err(_) => Default::default(),
+            }
+        } else {
+            Default::default()
+        };
+        let scope = std::env::current_dir()
+            .map(|dir| scope::PathScope::new(&dir, &config.scope))
+            .unwrap_or_default();
+        let remote_host = remote::target(&config.remote);
+        if !project_env.is_empty() && !quiet {
+            let names: Vec<_> = project_env.files.iter().filter_map(|f| f.file_name()).collect();
+            events.publish(events::AgentEvent::Status {
+                message: format!(
+                    "🌱 Loaded {} variables for shell commands from {}",
+                    project_env.names().count(),
+                    names
+                        .iter()
+                        .map(|name| name.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(" and ")
+                ),
+            });
+        }
+        let dev_container = std::env::current_dir()
+            .map(|dir| g3_execution::devcontainer::DevContainer::find(&dir));
+        let container = match dev_container {
+            Ok(Ok(Some(dev_container))) if config.agent.devcontainer && remote_host.is_none() => {
+                let container = dev_container
+                    .up(false)
+                    .await
+                    .map_err(|e| e.context("Failed to start the dev container"))?;
+                if !quiet {
+                    events.publish(events::AgentEvent::Status {
+                        message: format!(
+                            "🐳 Running shell commands in dev container {}",
+                            container.name
+                        ),
+                    });
+                }
+                Some(container)
+            }
+            Ok(Ok(Some(_))) if !config.agent.devcontainer && !quiet => {
+                events.publish(events::AgentEvent::Status {
+                    message: "🐳 This project has a dev container; run with --devcontainer to run \
+                              shell commands in it"
+                        .to_string(),
+                });
+                None
+            }
+            Ok(Ok(None)) if config.agent.devcontainer => {
+                warn!("No devcontainer.json in the workspace; running shell commands locally");
+                None
+            }
+            Ok(Err(e)) => {
+                warn!("Ignoring the workspace's dev container: {}", e);
+                None
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            providers,
+            base_providers,
+            context_window,
+            auto_compact: config.agent.auto_compact,
+            pending_90_summarization: false,
+            thinning_events: Vec::new(),
+            summarization_events: Vec::new(),
+            first_token_times: Vec::new(),
+            request_times: Vec::new(),
+            output_tokens: 0,
+            reasoning_tokens: 0,
+            config,
+            session_id: None,
+            tool_call_metrics: Vec::new(),
+            tool_context: context::ToolContextLedger::default(),
+            turn_metrics: Vec::new(),
+            events,
+            todo_content: std::sync::Arc::new(tokio::sync::RwLock::new({
+                // Initialize from TODO.md file if it exists
+                let todo_path = std::env::current_dir()
+                    .ok()
+                    .map(|p| p.join("todo.g3.md"));
+                
+                if let Some(path) = todo_path {
+                    std::fs::read_to_string(&path).unwrap_or_default()
+                } else {
+                    String::new()
+                }
+            })),
+            g3ignore: std::env::current_dir()
+                .map(|dir| g3ignore::G3Ignore::load(&dir))
+                .unwrap_or_default(),
+            scope,
+            remote: remote_host,
+            container,
+            project_env,
+            is_autonomous,
+            is_coach: false,
+            coach_feedback: tokio::sync::RwLock::new(None),
+            quiet,
+            computer_controller,
+            ocr,
+            recording: tokio::sync::Mutex::new(None),
+            semantic_index: tokio::sync::Mutex::new(None),
+            webdriver_session: std::sync::Arc::new(tokio::sync::RwLock::new(None)),
+            safaridriver_process: std::sync::Arc::new(tokio::sync::RwLock::new(None)),
+            webdriver_last_url: std::sync::Mutex::new(None),
+            webdriver_download_dir: std::sync::Mutex::new(download_dir),
+            webdriver_last_call: std::sync::Mutex::new(None),
+            macax_controller: {
+                std::sync::Arc::new(tokio::sync::RwLock::new(if macax_enabled {
+                    Some(g3_computer_control::MacAxController::new()?)
+                } else {
+                    None
+                }))
+            },
+            unity_catalog,
+            moderator,
+            stepper: None,
+            plugins,
+            custom_tools: custom_tools::CustomTools::default(),
+            tool_handlers: tools::ToolHandlers::builtin(),
+            round_snapshot: std::sync::Mutex::new(None),
+            edit_commits: tokio::sync::Mutex::new(None),
+            edit_commits_started: std::sync::atomic::AtomicBool::new(false),
+            response_truncated: false,
+            file_changes: std::sync::Mutex::new(file_changes::FileChangeSet::default()),
+            task_file_changes: std::sync::Mutex::new(file_changes::FileChangeSet::default()),
+            file_locks: file_lock::FileLocks::shared(),
+            file_versions: std::sync::Mutex::new(file_lock::FileVersions::default()),
+            read_history: read_history::ReadHistory::default(),
+            tool_budgets: std::sync::Mutex::new(tool_budgets),
+            cancellation: CancellationToken::new(),
+            running_command: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            next_tool_choice: ToolChoice::Auto,
+            allowed_tools: None,
+            task_events: None,
+        })
+    }
+
+    /// Register the providers `config` selects: the default provider, plus the coach and
+    /// player providers in autonomous mode. The models of tools and subsystems (`[models]`)
+    /// are registered under their names too, and loaded the first time they are used.
+    async fn build_providers(config: &Config, is_autonomous: bool) -> Result<ProviderRegistry> {
+        let mut providers = Self::build_selected_providers(config, is_autonomous).await?;
+        for name in config.model_names() {
+            let (config, model) = (config.clone(), name.clone());
+            providers.register_lazy(
+                &name,
+                Box::new(move || {
+                    let (config, model) = (config.clone(), model.clone());
+                    Box::pin(async move {
+                        let config = config
+                            .for_model(&model)?
+                            .ok_or_else(|| anyhow::anyhow!("no model configured for {}", model))?;
+                        let provider =
+                            Agent::<ui_writer::NullUiWriter>::build_selected_providers(&config, false)
+                                .await?
+                                .into_default()
+                                .ok_or_else(|| anyhow::anyhow!("provider was not registered"))?;
+                        info!(
+                            "Using {} ({}) for {}",
+                            provider.name(),
+                            provider.model(),
+                            model
+                        );
+                        Ok(provider)
+                    })
+                }),
+            );
+        }
+        Ok(providers)
+    }
+
+    /// Register the default provider, plus the coach and player providers in autonomous mode
+    async fn build_selected_providers(
+        config: &Config,
+        is_autonomous: bool,
+    ) -> Result<ProviderRegistry> {
+        let mut providers = ProviderRegistry::new();
+
+        // In autonomous mode, we need to register both coach and player providers
+        // Otherwise, only register the default provider
+        let providers_to_register: Vec<String> = if is_autonomous {
+            let mut providers = vec![config.providers.default_provider.clone()];
+            if let Some(coach) = &config.providers.coach {
+                if !providers.contains(coach) {
+                    providers.push(coach.clone());
+                }
+            }
+            if let Some(player) = &config.providers.player {
+                if !providers.contains(player) {
+                    providers.push(player.clone());
+                }
+            }
+            providers
+        } else {
+            vec![config.providers.default_provider.clone()]
+        };
+
+        // Only the secrets of the providers built here are resolved
+        let mut config = config.clone();
+        for name in &providers_to_register {
+            config.providers.resolve_secrets_of(name)?;
+        }
+        let config = &config;
+
+        // Use the providers of a running `g3 daemon`, which keeps them loaded between runs
+        #[cfg(unix)]
+        if config.agent.use_daemon {
+            if let Some(socket) = daemon::running().await {
+                for name in &providers_to_register {
+                    let provider =
+                        daemon::DaemonProvider::connect(&socket, name, &config.providers).await?;
+                    info!("Using {} of the g3 daemon at {}", name, socket.display());
+                    providers.register(provider);
+                }
+                providers.set_default(&config.providers.default_provider)?;
+                return Ok(providers);
+            }
+        }
+
+        // Only register providers that are configured AND selected as the default provider
+        // This prevents unnecessary initialization of heavy providers like embedded models
+
+        // Register embedded provider if configured AND it's the default provider
+        if let Some(embedded_config) = &config.providers.embedded {
+            if providers_to_register.contains(&"embedded".to_string()) {
+                let embedded_provider = g3_providers::EmbeddedProvider::new(
+                    embedded_config.model_path.clone(),
+                    embedded_config.model_type.clone(),
+                    embedded_config.context_length,
+                    embedded_config.max_tokens,
+                    embedded_config.temperature,
+                    embedded_config.gpu_layers,
+                    embedded_config.threads,
+                )?
+                .with_constrained_tool_calls(embedded_config.constrained_tool_calls);
+                providers.register(embedded_provider);
+            }
+        }
+
+        // Register OpenAI provider if configured AND it's the default provider
+        if let Some(openai_config) = &config.providers.openai {
+            if providers_to_register.contains(&"openai".to_string()) {
+                let openai_provider = g3_providers::OpenAIProvider::new(
+                    openai_config.api_key.clone(),
+                    Some(openai_config.model.clone()),
+                    openai_config.base_url.clone(),
+                    openai_config.max_tokens,
+                    openai_config.temperature,
+                )?;
+                providers.register(configure_openai(openai_provider, openai_config));
+            }
+        }
+
+        // Register OpenAI-compatible providers (e.g., OpenRouter, Groq, etc.)
+        for (name, openai_config) in &config.providers.openai_compatible {
+            if providers_to_register.contains(name) {
+                let openai_provider = g3_providers::OpenAIProvider::new_with_name(
+                    name.clone(),
+                    openai_config.api_key.clone(),
+                    Some(openai_config.model.clone()),
+                    openai_config.base_url.clone(),
+                    openai_config.max_tokens,
+                    openai_config.temperature,
+                )?;
+                providers.register(configure_openai(openai_provider, openai_config));
             }
         }
 
@@ -926,7 +933,9 @@ impl<W: UiWriter> Agent<W> {
                     .await?
                 };
 
-                providers.register(databricks_provider);
+                providers.register(
+                    databricks_provider.with_compression(databricks_config.compress_requests),
+                );
             }
         }
 
@@ -938,72 +947,85 @@ impl<W: UiWriter> Agent<W> {
         providers.set_default(&config.providers.default_provider)?;
         debug!("Default provider set successfully");
 
-        // Determine context window size based on active provider
-        let context_length = Self::get_configured_context_length(&config, &providers)?;
-        let mut context_window = ContextWindow::new(context_length);
+        Ok(providers)
+    }
 
-        // If README content is provided, add it as the first system message
-        if let Some(readme) = readme_content {
-            let readme_message = Message {
-                role: MessageRole::System,
-                content: readme,
-            };
-            context_window.add_message(readme_message);
+    /// The active profile and its settings
+    pub fn profile(&self) -> Option<(&str, &g3_config::ProfileConfig)> {
+        let name = self.config.agent.profile.as_deref()?;
+        Some((name, self.config.profiles.get(name)?))
+    }
+
+    /// The configured profiles, marking the active one
+    pub fn list_profiles(&self) -> String {
+        if self.config.profiles.is_empty() {
+            return "No profiles defined. Add them to your config as [profiles.<name>]".to_string();
         }
+        let active = self.config.agent.profile.as_deref();
+        let mut names: Vec<&String> = self.config.profiles.keys().collect();
+        names.sort();
 
-        // Initialize computer controller if enabled
-        let computer_controller = if config.computer_control.enabled {
-            match g3_computer_control::create_controller() {
-                Ok(controller) => Some(controller),
-                Err(e) => {
-                    warn!("Failed to initialize computer control: {}", e);
-                    None
-                }
+        let mut listing = format!(
+            "🎭 Active profile: {}\nProfiles:",
+            active.unwrap_or("none")
+        );
+        for name in names {
+            let profile = &self.config.profiles[name];
+            let marker = if active == Some(name.as_str()) { "*" } else { " " };
+            let mut details = Vec::new();
+            if let Some(model) = &profile.model {
+                details.push(format!("model {}", model));
+            } else if let Some(provider) = &profile.provider {
+                details.push(format!("provider {}", provider));
             }
-        } else {
-            None
-        };
+            if let Some(tools) = &profile.tools {
+                details.push(format!("{} tools", tools.len()));
+            }
+            listing.push_str(&format!("\n {} {}", marker, name));
+            if !details.is_empty() {
+                listing.push_str(&format!(" ({})", details.join(", ")));
+            }
+        }
+        listing
+    }
 
-        // Capture macax_enabled before moving config
-        let macax_enabled = config.macax.enabled;
+    /// Switch to a profile from `[profiles]`, or back to the plain config with `None`.
+    /// The conversation is kept; the new prompt, tools and model apply from the next request.
+    pub async fn set_profile(&mut self, name: Option<&str>) -> Result<()> {
+        let mut config = self.config.clone();
+        config.providers = self.base_providers.clone();
+        config.agent.profile = None;
+        if let Some(name) = name {
+            config = config.with_profile(name)?;
+        }
 
-        Ok(Self {
-            providers,
-            context_window,
-            auto_compact: config.agent.auto_compact,
-            pending_90_summarization: false,
-            thinning_events: Vec::new(),
-            summarization_events: Vec::new(),
-            first_token_times: Vec::new(),
-            config,
-            session_id: None,
-            tool_call_metrics: Vec::new(),
-            ui_writer,
-            todo_content: std::sync::Arc::new(tokio::sync::RwLock::new({
-                // Initialize from TODO.md file if it exists
-                let todo_path = std::env::current_dir()
-                    .ok()
-                    .map(|p| p.join("todo.g3.md"));
-                
-                if let Some(path) = todo_path {
-                    std::fs::read_to_string(&path).unwrap_or_default()
-                } else {
-                    String::new()
-                }
-            })),
-            is_autonomous,
-            quiet,
-            computer_controller,
-            webdriver_session: std::sync::Arc::new(tokio::sync::RwLock::new(None)),
-            safaridriver_process: std::sync::Arc::new(tokio::sync::RwLock::new(None)),
-            macax_controller: {
-                std::sync::Arc::new(tokio::sync::RwLock::new(if macax_enabled {
-                    Some(g3_computer_control::MacAxController::new()?)
-                } else {
-                    None
-                }))
-            },
-        })
+        let providers = Self::build_providers(&config, self.is_autonomous).await?;
+        self.context_window.total_tokens = Self::get_configured_context_length(&config, &providers)?;
+        self.providers = providers;
+        self.config = config;
+        self.apply_profile_prompt();
+        Ok(())
+    }
+
+    /// Bring the profile section of the system prompt in line with the active profile.
+    /// The last system message is used, as that is the one every provider honours.
+    fn apply_profile_prompt(&mut self) {
+        // Borrow the fields directly so the history can be borrowed mutably alongside
+        let profile = self
+            .config
+            .agent
+            .profile
+            .as_deref()
+            .and_then(|name| Some((name, self.config.profiles.get(name)?)));
+        if let Some(message) = self
+            .context_window
+            .conversation_history
+            .iter_mut()
+            .rev()
+            .find(|m| matches!(m.role, MessageRole::System))
+        {
+            message.content = profile::with_profile_prompt(&message.content, profile);
+        }
     }
 
     fn get_configured_context_length(config: &Config, providers: &ProviderRegistry) -> Result<u32> {
@@ -1013,65 +1035,14 @@ impl<W: UiWriter> Agent<W> {
             return Ok(max_context_length);
         }
 
-        // Get the configured max_tokens for the current provider
-        fn get_provider_max_tokens(config: &Config, provider_name: &str) -> Option<u32> {
-            match provider_name {
-                "anthropic" => config.providers.anthropic.as_ref()?.max_tokens,
-                "openai" => config.providers.openai.as_ref()?.max_tokens,
-                "databricks" => config.providers.databricks.as_ref()?.max_tokens,
-                "embedded" => config.providers.embedded.as_ref()?.max_tokens,
-                _ => None,
-            }
-        }
-
-        // Get the active provider to determine context length
+        // The window of the active provider's model, if it knows it
         let provider = providers.get(None)?;
         let provider_name = provider.name();
         let model_name = provider.model();
-
-        // Use provider-specific context length if available, otherwise fall back to agent config
-        let context_length = match provider_name {
-            "embedded" => {
-                // For embedded models, use the configured context_length or model-specific defaults
-                if let Some(embedded_config) = &config.providers.embedded {
-                    embedded_config.context_length.unwrap_or_else(|| {
-                        // Model-specific defaults for embedded models
-                        match embedded_config.model_type.to_lowercase().as_str() {
-                            "codellama" => 16384, // CodeLlama supports 16k context
-                            "llama" => 4096,      // Base Llama models
-                            "mistral" => 8192,    // Mistral models
-                            "qwen" => 32768,      // Qwen2.5 supports 32k context
-                            _ => 4096,            // Conservative default
-                        }
-                    })
-                } else {
-                    config.agent.fallback_default_max_tokens as u32
-                }
-            }
-            "openai" => {
-                // gpt-5 has 400k window
-                get_provider_max_tokens(config, "openai").unwrap_or(400000)
-            }
-            "anthropic" => {
-                // Claude models have large context windows
-                // Use configured max_tokens or fall back to default
-                get_provider_max_tokens(config, "anthropic").unwrap_or(200000)
-            }
-            "databricks" => {
-                // Databricks models have varying context windows depending on the model
-                // Use configured max_tokens or fall back to model-specific defaults
-                get_provider_max_tokens(config, "databricks").unwrap_or_else(|| {
-                    if model_name.contains("claude") {
-                        200000 // Claude models on Databricks have large context windows
-                    } else if model_name.contains("llama") || model_name.contains("dbrx") {
-                        32768 // DBRX supports 32k context
-                    } else {
-                        16384 // Conservative default for other Databricks models
-                    }
-                })
-            }
-            _ => config.agent.fallback_default_max_tokens as u32,
-        };
+        let context_length = provider
+            .capabilities()
+            .context_window
+            .unwrap_or(config.agent.fallback_default_max_tokens as u32);
 
         debug!(
             "Using context length: {} tokens for provider: {} (model: {})",
@@ -1091,405 +1062,1168 @@ impl<W: UiWriter> Agent<W> {
         self.session_id.as_deref()
     }
 
-    pub async fn execute_task(
-        &mut self,
-        description: &str,
-        language: Option<&str>,
-        _auto_execute: bool,
-    ) -> Result<TaskResult> {
-        self.execute_task_with_options(description, language, false, false, false)
-            .await
+    /// Artifact store for the current session (a temp directory until the first task starts)
+    pub fn artifacts(&self) -> &ArtifactStore {
+        &self.context_window.artifacts
     }
 
-    pub async fn execute_task_with_options(
-        &mut self,
-        description: &str,
-        language: Option<&str>,
-        _auto_execute: bool,
-        show_prompt: bool,
-        show_code: bool,
-    ) -> Result<TaskResult> {
-        self.execute_task_with_timing(
-            description,
-            language,
-            _auto_execute,
-            show_prompt,
-            show_code,
-            false,
-        )
-        .await
+    /// Human-readable listing of the current session's artifacts
+    pub fn list_artifacts(&self) -> String {
+        let store = self.artifacts();
+        let artifacts = match store.list() {
+            Ok(artifacts) => artifacts,
+            Err(e) => return format!("❌ Failed to list artifacts: {}", e),
+        };
+        if artifacts.is_empty() {
+            return format!("No artifacts saved in {}", store.dir().display());
+        }
+
+        let mut listing = format!(
+            "📦 {} artifacts in {}:",
+            artifacts.len(),
+            store.dir().display()
+        );
+        for artifact in &artifacts {
+            listing.push_str(&format!(
+                "\n  {} {:?} {} bytes{}",
+                artifact.name,
+                artifact.kind,
+                artifact.size,
+                artifact
+                    .source
+                    .as_ref()
+                    .map(|s| format!(" (from {})", s))
+                    .unwrap_or_default()
+            ));
+        }
+        listing
     }
 
-    pub async fn execute_task_with_timing(
-        &mut self,
-        description: &str,
-        language: Option<&str>,
-        _auto_execute: bool,
-        show_prompt: bool,
-        show_code: bool,
-        show_timing: bool,
-    ) -> Result<TaskResult> {
-        // Create a cancellation token that never cancels for backward compatibility
-        let cancellation_token = CancellationToken::new();
-        self.execute_task_with_timing_cancellable(
-            description,
-            language,
-            _auto_execute,
-            show_prompt,
-            show_code,
-            show_timing,
-            cancellation_token,
-        )
-        .await
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    pub async fn execute_task_with_timing_cancellable(
-        &mut self,
-        description: &str,
-        _language: Option<&str>,
-        _auto_execute: bool,
-        show_prompt: bool,
-        show_code: bool,
-        show_timing: bool,
-        cancellation_token: CancellationToken,
-    ) -> Result<TaskResult> {
-        // Execute the task directly without splitting
-        self.execute_single_task(
-            description,
-            show_prompt,
-            show_code,
-            show_timing,
-            cancellation_token,
-        )
-        .await
-    }
-
-    async fn execute_single_task(
-        &mut self,
-        description: &str,
-        show_prompt: bool,
-        _show_code: bool,
-        show_timing: bool,
-        cancellation_token: CancellationToken,
-    ) -> Result<TaskResult> {
-        // Reset the JSON tool call filter state at the start of each new task
-        // This prevents the filter from staying in suppression mode between user interactions
-        fixed_filter_json::reset_fixed_json_tool_state();
+    /// Generate a commit message, PR description and CHANGELOG entry for the changes in the
+    /// workspace and save them as artifacts of the session they belong to.
+    ///
+    /// Changes are diffed against `since` if given, otherwise against the starting point of
+    /// `session` (default: this agent's session, or the most recent one in the workspace).
+    pub async fn summarize_changes(
+        &self,
+        since: Option<&str>,
+        session: Option<&str>,
+    ) -> Result<(ChangeSummary, Vec<std::path::PathBuf>)> {
+        let workspace = std::env::current_dir()?;
+        let baseline = match (since, session.or(self.session_id.as_deref())) {
+            (Some(rev), _) => change_summary::baseline_for_rev(&workspace, rev)?,
+            (None, Some(session_id)) => change_summary::load_baseline(&workspace, session_id)?
+                .ok_or_else(|| {
+                    anyhow::anyhow!("No starting point recorded for session {}", session_id)
+                })?,
+            (None, None) => change_summary::latest_baseline(&workspace)?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No session starting point found in {}; use --since <rev> to pick one",
+                    workspace.display()
+                )
+            })?,
+        };
 
-        // Generate session ID based on the initial prompt if this is a new session
-        if self.session_id.is_none() {
-            self.session_id = Some(self.generate_session_id(description));
+        let mut changes = change_summary::collect_changes(&workspace, &baseline)?;
+        // What the agent changed itself is known when summarizing its own session
+        let own_session =
+            since.is_none() && session.is_none_or(|s| Some(s) == self.session_id.as_deref());
+        let file_changes = self.file_changes();
+        if own_session && !file_changes.is_empty() {
+            changes.agent_files = Some(file_changes.render(&workspace));
+        }
+        if changes.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No changes since {}",
+                &baseline.rev[..baseline.rev.len().min(12)]
+            ));
         }
 
-        // Only add system message if this is the first interaction (empty conversation history)
-        if self.context_window.conversation_history.is_empty() {
-            let provider = self.providers.get(None)?;
-            let system_prompt = if provider.has_native_tool_calling() {
-                // For native tool calling providers, use a more explicit system prompt
-                "You are G3, an AI programming agent of the same skill level as a seasoned engineer at a major technology company. You analyze given tasks and write code to achieve goals.
-
-You have access to tools. When you need to accomplish a task, you MUST use the appropriate tool. Do not just describe what you would do - actually use the tools.
+        let provider = self.providers.get(None)?;
+        let mut request = CompletionRequest {
+            messages: vec![
+                Message {
+                    role: MessageRole::System,
+                    content: "You are an experienced engineer who writes clear, accurate commit messages, pull request descriptions and changelogs.".to_string(),
+                },
+                Message {
+                    role: MessageRole::User,
+                    content: change_summary::build_prompt(&workspace, &changes),
+                },
+            ],
+            max_tokens: Some(MIN_RESPONSE_TOKENS),
+            temperature: Some(0.3),
+            stream: false,
+            tools: None,
+            tool_choice: ToolChoice::Auto,
+            stop_sequences: Vec::new(),
+        };
+        self.moderate_request(&mut request).await?;
+        let response = provider.complete(request).await?;
+        let summary = change_summary::parse_summary(&response.content)?;
 
-IMPORTANT: You must call tools to achieve goals. When you receive a request:
-1. Analyze and identify what needs to be done
-2. Call the appropriate tool with the required parameters
-3. Continue or complete the task based on the result
-4. If you repeatedly try something and it fails, try a different approach
-5. Call the final_output tool with a detailed summary when done.
+        let store = match &baseline.session_id {
+            Some(session_id) => ArtifactStore::for_session(&workspace, session_id),
+            None => self.artifacts().clone(),
+        };
+        let paths = [
+            ("commit_message.txt", &summary.commit_message),
+            ("pr_description.md", &summary.pr_description),
+            ("changelog_entry.md", &summary.changelog_entry),
+        ]
+        .into_iter()
+        .map(|(name, contents)| {
+            store.save(
+                ArtifactKind::ChangeSummary,
+                name,
+                format!("{}\n", contents).as_bytes(),
+                Some("summarize_changes"),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
 
-For shell commands: Use the shell tool with the exact command needed. Avoid commands that produce a large amount of output, and consider piping those outputs to files. Example: If asked to list files, immediately call the shell tool with command parameter \"ls\".
-If you create temporary files for verification, place these in a subdir named 'tmp'. Do NOT pollute the current dir.
+        Ok((summary, paths))
+    }
 
-# Task Management with TODO Tools
+    /// Build the project index of the working directory and save it to `.g3/index.json`.
+    /// Directories described by the previous index keep their descriptions unless
+    /// `refresh` is set; the model describes the rest.
+    pub async fn index_project(&self, refresh: bool) -> Result<project_index::ProjectIndex> {
+        let workspace = std::env::current_dir()?;
+        let mut index = project_index::scan(&workspace);
+        if !refresh {
+            if let Some(previous) = project_index::ProjectIndex::load(&workspace) {
+                index.keep_descriptions(&previous);
+            }
+        }
 
-**REQUIRED for multi-step tasks.** Use TODO tools when your task involves ANY of:
-- Multiple files to create/modify (2+)
-- Multiple distinct steps (3+)
-- Dependencies between steps
-- Testing or verification needed
-- Uncertainty about approach
+        if !index.undescribed().is_empty() {
+            let model = self
+                .load_model(g3_config::INDEX_MODEL)
+                .await
+                .then_some(g3_config::INDEX_MODEL);
+            let provider = self.providers.get(model)?;
+            let mut request = CompletionRequest {
+                messages: vec![
+                    Message {
+                        role: MessageRole::System,
+                        content: "You are an experienced engineer who explains the layout of codebases briefly and accurately.".to_string(),
+                    },
+                    Message {
+                        role: MessageRole::User,
+                        content: index.description_prompt(&workspace),
+                    },
+                ],
+                max_tokens: Some(
+                    model
+                        .and_then(|name| self.config.model_for(name))
+                        .and_then(|index_model| index_model.max_tokens)
+                        .unwrap_or(MIN_RESPONSE_TOKENS),
+                ),
+                temperature: Some(0.2),
+                stream: false,
+                tools: None,
+                tool_choice: ToolChoice::Auto,
+                stop_sequences: Vec::new(),
+            };
+            self.moderate_request(&mut request).await?;
+            let response = provider.complete(request).await?;
+            index.apply_descriptions(&project_index::parse_descriptions(&response.content));
+        }
 
-## Workflow
+        index.save(&workspace)?;
+        Ok(index)
+    }
 
-Every multi-step task follows this pattern:
-1. **Start**: Call todo_read, then todo_write to create your plan
-2. **During**: Execute steps, then todo_read and todo_write to mark progress
-3. **End**: Call todo_read to verify all items complete
+    /// The embeddings `[embeddings]` selects, for code_search_semantic
+    fn embedding_provider(&self) -> Result<Box<dyn g3_providers::EmbeddingProvider>> {
+        let embeddings = &self.config.embeddings;
+        let openai = match embeddings.provider.as_str() {
+            "hashed" => return Ok(Box::new(g3_providers::HashedEmbeddings::new())),
+            "openai" => self.config.providers.openai.as_ref(),
+            name => self.config.providers.openai_compatible.get(name),
+        };
+        let Some(openai) = openai else {
+            anyhow::bail!(
+                "Embeddings provider '{}' is not configured under [providers]",
+                embeddings.provider
+            );
+        };
+        Ok(Box::new(g3_providers::OpenAIEmbeddings::new(
+            embeddings.provider.clone(),
+            openai.resolved_api_key(&embeddings.provider)?,
+            embeddings.model.clone(),
+            openai.base_url.clone(),
+        )))
+    }
 
-Note: todo_write replaces the entire todo.g3.md file, so always read first to preserve content. TODO lists persist across g3 sessions in the workspace directory.
+    /// Record a tool call in the session's `tool_audit.jsonl`, next to its artifacts
+    fn audit_tool_call(&self, tool: &str, success: bool, duration: Duration) {
+        let Some(session_dir) = self.artifacts().dir().parent() else {
+            return;
+        };
+        let entry = AuditEntry::new(tool, success, duration);
+        if let Err(e) = entry.append(&session_dir.join(tool_registry::AUDIT_LOG_FILE)) {
+            warn!("Failed to write tool audit log: {}", e);
+        }
+    }
 
-## Examples
+    /// The session's `moderation_audit.jsonl`, next to its tool audit log
+    fn moderation_log(&self) -> std::path::PathBuf {
+        let session_dir = self.artifacts().dir().parent().unwrap_or(std::path::Path::new("."));
+        session_dir.join(moderation::AUDIT_LOG_FILE)
+    }
 
-**Example 1: Feature Implementation**
-User asks: \"Add user authentication with tests\"
+    /// Run the pre_send moderation hook on `request`, which may rewrite its messages
+    async fn moderate_request(&self, request: &mut CompletionRequest) -> Result<()> {
+        match self
+            .moderator
+            .pre_send(&request.messages, &self.moderation_log())
+            .await
+        {
+            moderation::Decision::Allow => Ok(()),
+            moderation::Decision::Modify(messages) => {
+                request.messages = messages;
+                Ok(())
+            }
+            moderation::Decision::Block(reason) => Err(anyhow::anyhow!(
+                "Request blocked by moderation hook: {}",
+                reason
+            )),
+        }
+    }
 
-First action:
-{\"tool\": \"todo_read\", \"args\": {}}
+    /// The undo stack of the session's file modifications
+    fn undo_stack(&self) -> undo::UndoStack {
+        let session_dir = self.artifacts().dir().parent().unwrap_or(std::path::Path::new("."));
+        undo::UndoStack::new(session_dir.join(undo::UNDO_DIR))
+    }
 
-Then create plan:
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Add user authentication\\n  - [ ] Create User struct\\n  - [ ] Add login endpoint\\n  - [ ] Add password hashing\\n  - [ ] Write unit tests\\n  - [ ] Write integration tests\"}}
+    /// The saved conversation states of the session, for `/tag` and `/branch`
+    fn session_graph(&self) -> Result<session_graph::SessionGraph> {
+        if self.session_id.is_none() {
+            anyhow::bail!("Nothing to save yet: the session starts with its first task");
+        }
+        let session_dir = self.artifacts().dir().parent().unwrap_or(std::path::Path::new("."));
+        session_graph::SessionGraph::open(session_dir.join(session_graph::GRAPH_DIR))
+    }
 
-After completing User struct:
-{\"tool\": \"todo_read\", \"args\": {}}
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Add user authentication\\n  - [x] Create User struct\\n  - [ ] Add login endpoint\\n  - [ ] Add password hashing\\n  - [ ] Write unit tests\\n  - [ ] Write integration tests\"}}
+    /// Save the conversation as a state named `name`, for `/tag`
+    pub fn tag_state(&mut self, name: &str) -> Result<String> {
+        let id = self
+            .session_graph()?
+            .tag(name, &self.context_window.conversation_history)?;
+        Ok(format!("🏷️ Tagged the conversation as '{}' (#{})", name, id))
+    }
 
-**Example 2: Bug Fix**
-User asks: \"Fix the memory leak in cache module\"
+    /// The saved states of the session as a tree, for `/tags`
+    pub fn list_states(&self) -> Result<String> {
+        let graph = self.session_graph()?;
+        if graph.states().is_empty() {
+            return Ok("No saved states yet, tag one with /tag <name>".to_string());
+        }
+        Ok(graph.render())
+    }
 
-{\"tool\": \"todo_read\", \"args\": {}}
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Fix memory leak\\n  - [ ] Review cache.rs\\n  - [ ] Check for unclosed resources\\n  - [ ] Add drop implementation\\n  - [ ] Write test to verify fix\"}}
+    /// Replace the conversation with the state a tag or number names, for `/branch`. The
+    /// current conversation is saved first so it can be branched back to.
+    pub fn branch_from(&mut self, target: &str) -> Result<String> {
+        let mut graph = self.session_graph()?;
+        if graph.resolve(target).is_none() {
+            anyhow::bail!("No tag or state named '{}', see /tags", target);
+        }
+        let saved = graph.save(&self.context_window.conversation_history)?;
+        let (id, messages) = graph.checkout(target)?;
+        self.context_window.conversation_history = messages;
+        self.context_window.recalculate_tokens();
+        self.response_truncated = false;
+        Ok(format!(
+            "🔀 Branched from '{}' (#{}, {} messages). The conversation before the branch is saved as #{}. Files were not changed.",
+            target,
+            id,
+            self.context_window.conversation_history.len(),
+            saved
+        ))
+    }
 
-**Example 3: Refactoring**
-User asks: \"Refactor database layer to use async/await\"
+    fn snapshot_dir(&self) -> std::path::PathBuf {
+        let session_dir = self.artifacts().dir().parent().unwrap_or(std::path::Path::new("."));
+        session_dir.join(workspace_snapshot::SNAPSHOTS_DIR)
+    }
 
-{\"tool\": \"todo_read\", \"args\": {}}
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Refactor to async\\n  - [ ] Update function signatures\\n  - [ ] Replace blocking calls\\n  - [ ] Update all callers\\n  - [ ] Update tests\"}}
+    /// Start a new autonomous round: the snapshot of the previous round is dropped and the
+    /// next mutating tool call takes a new one (with `snapshot_rounds` enabled)
+    pub fn begin_round(&self) {
+        if let Some(snapshot) = self.round_snapshot.lock().unwrap().take() {
+            snapshot.discard();
+        }
+    }
 
-## Format
+    /// Let the agent deliver a review of its own work with give_feedback, as the coach does,
+    /// for autonomous runs with `self_review`. The verdict is in [`TaskResult::feedback`].
+    pub fn set_reviewing(&mut self, reviewing: bool) {
+        self.is_coach = reviewing;
+    }
 
-Use markdown checkboxes:
-- \"- [ ]\" for incomplete tasks
-- \"- [x]\" for completed tasks
-- Indent with 2 spaces for subtasks
+    /// Put the workspace back the way it was before the current round changed anything.
+    /// Returns false if the round has no snapshot, e.g. because it didn't modify anything.
+    pub fn restore_round(&self) -> Result<bool> {
+        match self.round_snapshot.lock().unwrap().as_ref() {
+            Some(snapshot) => {
+                snapshot.restore()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
 
-Keep items short, specific, and action-oriented.
+    /// Whether the last response stopped at the output token limit; send [`CONTINUE_PROMPT`]
+    /// as the next task to resume it
+    pub fn response_truncated(&self) -> bool {
+        self.response_truncated
+    }
 
-## Benefits
+    /// Why `tool_call` would be refused by read-only mode, `.g3ignore`, `[scope]` or the
+    /// profile
+    fn policy_refusal(&self, tool_call: &ToolCall) -> Option<String> {
+        if self.config.agent.read_only {
+            if let Some(reason) = read_only::check_tool_call(tool_call) {
+                return Some(reason);
+            }
+        }
+        if let Some(reason) = self.g3ignore.check_tool_call(tool_call) {
+            return Some(reason);
+        }
+        if let Some(reason) = self.scope.check_tool_call(tool_call) {
+            return Some(reason);
+        }
+        self.profile()
+            .and_then(|(name, profile)| profile::check_tool_call(name, profile, tool_call))
+    }
 
-✓ Prevents missed steps
-✓ Makes progress visible
-✓ Helps recover from interruptions
-✓ Creates better summaries
+    /// Whether `write_file` content is written to `<file_path>.g3-partial` as it streams. Not
+    /// while a stepper or a tool call moderation hook may still skip or block the call, or on
+    /// a remote session, where the call writes the host's file rather than the local one.
+    fn progressive_writes(&self) -> bool {
+        self.config.agent.progressive_writes
+            && self.remote.is_none()
+            && self.stepper.is_none()
+            && !self.moderator.checks_tool_calls()
+    }
 
-## When NOT to Use
+    /// Why a streaming `write_file` call must not be written as it arrives: the refusals of
+    /// [`policy_refusal`](Self::policy_refusal), or a budget the call would go over
+    fn partial_write_refusal(&self, tool_call: &ToolCall) -> Option<String> {
+        self.policy_refusal(tool_call).or_else(|| {
+            let budgets = self.tool_budgets.lock().unwrap();
+            budgets
+                .check(&tool_call.tool, Instant::now())
+                .err()
+                .map(|exceeded| exceeded.to_string())
+        })
+    }
 
-Skip TODO tools for simple single-step tasks:
-- \"List files\" → just use shell
-- \"Read config.json\" → just use read_file
-- \"Search for functions\" → just use code_search
+    /// Update the indicator of the streaming response, if one is due
+    fn publish_stream_progress(&self, rate: &mut stream_rate::StreamRate) {
+        if !self.config.agent.stream_indicator {
+            return;
+        }
+        if let Some(progress) = rate.report(Instant::now()) {
+            self.events.publish(events::AgentEvent::StreamProgress(progress));
+        }
+    }
 
-If you can complete it with 1-2 tool calls, skip TODO.
+    /// Start safaridriver on the configured port and open a Safari session through it
+    async fn start_webdriver(&self) -> std::result::Result<(), String> {
+        // Note: Safari Remote Automation must be enabled before using WebDriver.
+        // Run this once: safaridriver --enable
+        // Or enable manually: Safari → Develop → Allow Remote Automation
+        let port = self.config.webdriver.safari_port;
+
+        let mut safaridriver_process = tokio::process::Command::new("safaridriver")
+            .arg("--port")
+            .arg(port.to_string())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                format!(
+                    "Failed to start safaridriver: {}\n\nMake sure safaridriver is installed.",
+                    e
+                )
+            })?;
 
-# Code Search Guidelines
+        // Wait for safaridriver to start up
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
 
-IMPORTANT: When searching for code constructs (functions, classes, methods, structs, etc.), ALWAYS use `code_search` instead of shell grep/rg.
-If you create temporary files for verification, place these in a subdir named 'tmp'. Do NOT pollute the current dir.
+        match g3_computer_control::SafariDriver::with_port(port).await {
+            Ok(driver) => {
+                let session = std::sync::Arc::new(tokio::sync::Mutex::new(driver));
+                *self.webdriver_session.write().await = Some(session);
+                *self.safaridriver_process.write().await = Some(safaridriver_process);
+                Ok(())
+            }
+            Err(e) => {
+                // Kill the safaridriver process if connection failed
+                let _ = safaridriver_process.kill().await;
 
-# Code Search Guidelines
+                Err(format!("Failed to connect to SafariDriver: {}\n\nThis might be because:\n  - Safari Remote Automation is not enabled (run: safaridriver --enable)\n  - Port {} is already in use\n  - Safari failed to start\n  - Network connectivity issue\n\nTo enable Remote Automation:\n  1. Run: safaridriver --enable (requires password, one-time setup)\n  2. Or manually: Safari → Develop → Allow Remote Automation", e, port))
+            }
+        }
+    }
 
-IMPORTANT: When searching for code constructs (functions, classes, methods, structs, etc.), ALWAYS use `code_search` instead of shell grep/rg. 
-It's syntax-aware and finds actual code, not comments or strings. Only use shell grep for:
-  - Searching non-code files (logs, markdown, text)
-  - Simple string searches across all file types
-  - When you need regex for text content (not code structure)
+    /// Before a WebDriver tool uses the session, check that safaridriver is still running
+    /// and answering on its port, and that its Safari session still responds. If either
+    /// died, both are restarted and the last page is opened again. Returns a note about the
+    /// restart for the tool result, or what is wrong if the restart failed.
+    async fn check_webdriver(
+        &self,
+        tool_call: &ToolCall,
+    ) -> std::result::Result<Option<String>, String> {
+        if !tool_call.tool.starts_with("webdriver_")
+            || matches!(tool_call.tool.as_str(), "webdriver_start" | "webdriver_quit")
+            || !self.config.webdriver.enabled
+        {
+            return Ok(None);
+        }
+        // Without a session the tool itself says to start one
+        let Some(session) = self.webdriver_session.read().await.clone() else {
+            return Ok(None);
+        };
 
-Common code_search query patterns:
+        let port = self.config.webdriver.safari_port;
+        let exited = match self.safaridriver_process.write().await.as_mut() {
+            Some(process) => process.try_wait().ok().flatten(),
+            None => None,
+        };
+        let problem = if let Some(status) = exited {
+            Some(format!("safaridriver exited ({})", status))
+        } else if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_err()
+        {
+            Some(format!("safaridriver stopped answering on port {}", port))
+        } else {
+            let driver = session.lock().await;
+            match tokio::time::timeout(WEBDRIVER_CHECK_TIMEOUT, driver.current_url()).await {
+                Ok(Ok(url)) => {
+                    *self.webdriver_last_url.lock().unwrap() = Some(url);
+                    None
+                }
+                Ok(Err(e)) => Some(format!(
+                    "the Safari session ended while safaridriver kept running ({})",
+                    e
+                )),
+                Err(_) => Some(format!(
+                    "the Safari session stopped responding (no answer within {}s)",
+                    WEBDRIVER_CHECK_TIMEOUT.as_secs()
+                )),
+            }
+        };
+        let Some(problem) = problem else {
+            return Ok(None);
+        };
 
-**Rust:**
-  - All functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"functions\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\"}]}}
-  - Async functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"async_fns\", \"query\": \"(function_item (function_modifiers) name: (identifier) @name)\", \"language\": \"rust\"}]}}
-  - Structs: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"structs\", \"query\": \"(struct_item name: (type_identifier) @name)\", \"language\": \"rust\"}]}}
-  - Enums: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"enums\", \"query\": \"(enum_item name: (type_identifier) @name)\", \"language\": \"rust\"}]}}
-  - Impl blocks: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"impls\", \"query\": \"(impl_item type: (type_identifier) @name)\", \"language\": \"rust\"}]}}
+        warn!("WebDriver is unhealthy, restarting it: {}", problem);
+        drop(session);
+        // The dead session can't be quit cleanly, so it is dropped with its driver
+        self.webdriver_session.write().await.take();
+        if let Some(mut process) = self.safaridriver_process.write().await.take() {
+            let _ = process.kill().await;
+        }
+        if let Err(e) = self.start_webdriver().await {
+            return Err(format!(
+                "WebDriver stopped working because {}, and restarting it failed: {}",
+                problem, e
+            ));
+        }
 
-**Python:**
-  - Functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"functions\", \"query\": \"(function_definition name: (identifier) @name)\", \"language\": \"python\"}]}}
-  - Classes: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"classes\", \"query\": \"(class_definition name: (identifier) @name)\", \"language\": \"python\"}]}}
+        let mut note = format!("⚠️ WebDriver was restarted because {}", problem);
+        let last_url = self.webdriver_last_url.lock().unwrap().clone();
+        if let Some(url) = last_url {
+            let session = self.webdriver_session.read().await.clone();
+            if let Some(session) = session {
+                match session.lock().await.navigate(&url).await {
+                    Ok(()) => note.push_str(&format!("; reopened {}", url)),
+                    Err(e) => note.push_str(&format!("; reopening {} failed: {}", url, e)),
+                }
+            }
+        }
+        note.push_str(". Page state such as form input or logins may have been lost.");
+        Ok(Some(note))
+    }
 
-**JavaScript/TypeScript:**
-  - Functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"functions\", \"query\": \"(function_declaration name: (identifier) @name)\", \"language\": \"javascript\"}]}}
-  - Classes: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"classes\", \"query\": \"(class_declaration name: (identifier) @name)\", \"language\": \"javascript\"}]}}
-  - Arrow functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"arrow_fns\", \"query\": \"(arrow_function) @fn\", \"language\": \"javascript\"}]}}
+    /// Snapshot the workspace before the first mutating tool call of an autonomous round
+    fn snapshot_round_before(&self, tool_call: &ToolCall) {
+        if !self.is_autonomous
+            || self.is_coach
+            || !self.config.agent.snapshot_rounds
+            || read_only::check_tool_call(tool_call).is_none()
+        {
+            return;
+        }
+        let mut round_snapshot = self.round_snapshot.lock().unwrap();
+        if round_snapshot.is_some() {
+            return;
+        }
+        let workspace = std::env::current_dir().unwrap_or_else(|_| ".".into());
+        let dir = self.snapshot_dir().join(uuid::Uuid::new_v4().to_string());
+        match workspace_snapshot::WorkspaceSnapshot::take(&workspace, &dir) {
+            Ok(snapshot) => *round_snapshot = Some(snapshot),
+            Err(e) => warn!("Failed to snapshot the workspace before {}: {}", tool_call.tool, e),
+        }
+    }
 
-**Go:**
-  - Functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"functions\", \"query\": \"(function_declaration name: (identifier) @name)\", \"language\": \"go\"}]}}
-  - Methods: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"methods\", \"query\": \"(method_declaration name: (field_identifier) @name)\", \"language\": \"go\"}]}}
+    /// Revert the last `count` file modifications of the session, for `/undo`. The agent is
+    /// told which files were reverted so it doesn't rely on its edits still being there.
+    pub fn undo_edits(&mut self, count: usize) -> Result<String> {
+        let undone = self.undo_stack().undo(count)?;
+        if undone.is_empty() {
+            return Ok("Nothing to undo".to_string());
+        }
+        let summary = format!("- {}", undone.join("\n- "));
+        self.file_changes.get_mut().unwrap().refresh();
+        self.task_file_changes.get_mut().unwrap().refresh();
+        self.file_versions.get_mut().unwrap().refresh();
+        self.context_window.add_message(Message {
+            role: MessageRole::User,
+            content: format!(
+                "Note: the user undid your last {} file modification(s):\n{}",
+                undone.len(),
+                summary
+            ),
+        });
+        Ok(format!("↩️ Undid {} file modification(s):\n{}", undone.len(), summary))
+    }
 
-**Java/C++:**
-  - Classes: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"classes\", \"query\": \"(class_declaration name: (identifier) @name)\", \"language\": \"java\"}]}}
-  - Methods: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"methods\", \"query\": \"(method_declaration name: (identifier) @name)\", \"language\": \"java\"}]}}
+    /// Record what a file-changing tool call did, keeping the earlier content of each file
+    /// with the undo data so it can be shown by `/diff` and put back by `/rollback`
+    fn record_file_changes(&self, watch: file_changes::FileWatch) {
+        let undo_stack = self.undo_stack();
+        let mut session = self.file_changes.lock().unwrap();
+        let mut task = self.task_file_changes.lock().unwrap();
+        watch.finish(&mut [&mut session, &mut task], |content| {
+            if let Err(e) = undo_stack.store(content) {
+                warn!("Failed to keep the previous content of a changed file: {}", e);
+            }
+        });
+    }
 
-**Advanced features:**
-  - Multiple searches: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"funcs\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\"}, {\"name\": \"structs\", \"query\": \"(struct_item name: (type_identifier) @name)\", \"language\": \"rust\"}]}}
-  - With context: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"funcs\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\", \"context_lines\": 3}]}}
-  - Specific paths: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"funcs\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\", \"paths\": [\"src/core\"]}]}}
+    /// Make the first response of the next task call a tool (`Required`), a particular tool
+    /// (`Tool`), or answer without tools (`None`). Later responses of the task are left to
+    /// the model. Only providers with native tool calling honor it.
+    pub fn set_tool_choice(&mut self, choice: ToolChoice) {
+        self.next_tool_choice = choice;
+    }
 
+    /// Decide when and how the conversation is compacted with another strategy than the
+    /// default [`SummaryCompaction`]
+    pub fn set_compaction_strategy(&mut self, compaction: std::sync::Arc<dyn CompactionStrategy>) {
+        self.context_window.compaction = compaction;
+    }
 
-IMPORTANT: If the user asks you to just respond with text (like \"just say hello\" or \"tell me about X\"), do NOT use tools. Simply respond with the requested text directly. Only use tools when you need to execute commands or complete tasks that require action.
+    /// Ask `stepper` before each tool call runs, to approve, edit, skip or abort it
+    pub fn set_stepper(&mut self, stepper: std::sync::Arc<dyn step::ToolStepper>) {
+        self.stepper = Some(stepper);
+    }
 
-When taking screenshots of specific windows (like \"my Safari window\" or \"my terminal\"), ALWAYS use list_windows first to identify the correct window ID, then use take_screenshot with the window_id parameter.
+    /// Receive every event of the agent's turns after its UI writer does
+    pub fn subscribe(&self, subscriber: std::sync::Arc<dyn events::EventSubscriber>) {
+        self.events.subscribe(subscriber);
+    }
 
-Do not explain what you're going to do - just do it by calling the tools.
+    /// The files the agent has created, modified or deleted this session
+    pub fn file_changes(&self) -> file_changes::FileChangeSet {
+        self.file_changes.lock().unwrap().clone()
+    }
 
+    /// A unified diff of each file changed this session against its content before the
+    /// session first changed it, for `/diff`
+    pub fn diff_file_changes(&self) -> Result<String> {
+        Ok(self
+            .session_diff(&[])?
+            .unwrap_or_else(|| "No files changed this session".to_string()))
+    }
 
-# Response Guidelines
+    /// The diff of [`Agent::diff_file_changes`], limited to the files under `paths` if any
+    /// are given. `None` if no such file changed.
+    fn session_diff(&self, paths: &[std::path::PathBuf]) -> Result<Option<String>> {
+        let mut changes = self.file_changes();
+        if !paths.is_empty() {
+            changes.retain(|change| paths.iter().any(|path| change.path.starts_with(path)));
+        }
+        if changes.is_empty() {
+            return Ok(None);
+        }
+        let workspace = std::env::current_dir()?;
+        let undo_stack = self.undo_stack();
+        let mut diffs = vec![format!(
+            "📂 {} file(s) changed:\n{}",
+            changes.len(),
+            changes.render(&workspace)
+        )];
+        for change in changes.iter() {
+            let before = change.before.as_ref().map(|hash| undo_stack.object_path(hash));
+            let after = change.after.as_ref().map(|_| change.path.clone());
+            let label = file_changes::display_path(&change.path, &workspace);
+            diffs.push(file_changes::unified_diff(
+                before.as_deref(),
+                after.as_deref(),
+                &label,
+            )?);
+        }
+        Ok(Some(diffs.join("\n\n")))
+    }
 
-- Use Markdown formatting for all responses except tool calls.
-- Whenever taking actions, use the pronoun 'I'
-".to_string()
-            } else {
-                // For non-native providers (embedded models), use JSON format instructions
-                "You are G3, a general-purpose AI agent. Your goal is to analyze and solve problems by writing code.
+    /// Run `show_changes`: the session's changes, or the difference between two files
+    fn show_changes(&self, tool_call: &ToolCall) -> Result<String> {
+        let absolute = |path: &str| {
+            let path = std::path::PathBuf::from(shellexpand::tilde(path).as_ref());
+            std::path::absolute(&path).unwrap_or(path)
+        };
+        let file = |name: &str| tool_call.args.get(name).and_then(|v| v.as_str());
+        match (file("file_a"), file("file_b")) {
+            (Some(a), Some(b)) => {
+                // Both files are read, so both must be readable under the session's policies
+                for path in [a, b] {
+                    let read = ToolCall {
+                        tool: "read_file".to_string(),
+                        args: serde_json::json!({ "file_path": path }),
+                    };
+                    if let Some(reason) = self.policy_refusal(&read) {
+                        return Ok(format!("❌ {}", reason));
+                    }
+                    if !absolute(path).is_file() {
+                        return Ok(format!("❌ {} is not a file", path));
+                    }
+                }
+                let diff = file_changes::compare_files(&absolute(a), &absolute(b))?;
+                if diff.is_empty() {
+                    Ok(format!("✅ {} and {} are identical", a, b))
+                } else {
+                    Ok(diff)
+                }
+            }
+            (None, None) => {
+                let paths: Vec<std::path::PathBuf> = tool_call
+                    .args
+                    .get("paths")
+                    .and_then(|v| v.as_array())
+                    .map(|paths| {
+                        paths
+                            .iter()
+                            .filter_map(|p| p.as_str())
+                            .map(absolute)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                match self.session_diff(&paths)? {
+                    Some(diff) => Ok(diff),
+                    None if paths.is_empty() => {
+                        Ok("✅ You haven't changed any files this session".to_string())
+                    }
+                    None => Ok(
+                        "✅ You haven't changed any of those files this session".to_string(),
+                    ),
+                }
+            }
+            _ => Ok("❌ Pass both file_a and file_b to compare two files".to_string()),
+        }
+    }
 
-You have access to tools. When you need to accomplish a task, you MUST use the appropriate tool. Do not just describe what you would do - actually use the tools.
+    /// Put every file changed this session back the way it was before, deleting the files
+    /// the session created, for `/rollback`. The agent is told what was rolled back.
+    pub fn rollback_file_changes(&mut self) -> Result<String> {
+        let workspace = std::env::current_dir()?;
+        let undo_stack = self.undo_stack();
+        let changes = self.file_changes.get_mut().unwrap();
+        if changes.is_empty() {
+            return Ok("Nothing to roll back".to_string());
+        }
+        let mut rolled_back = Vec::new();
+        for change in changes.iter() {
+            let path = file_changes::display_path(&change.path, &workspace);
+            match &change.before {
+                Some(hash) => {
+                    let content = std::fs::read(undo_stack.object_path(hash)).map_err(|e| {
+                        anyhow::anyhow!("Missing earlier content of {}: {}", path, e)
+                    })?;
+                    if let Some(parent) = change.path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&change.path, content)?;
+                    rolled_back.push(format!("Restored {}", path));
+                }
+                None => {
+                    if let Err(e) = std::fs::remove_file(&change.path) {
+                        if e.kind() != std::io::ErrorKind::NotFound {
+                            return Err(e.into());
+                        }
+                    }
+                    rolled_back.push(format!("Deleted {}, which this session created", path));
+                }
+            }
+        }
+        changes.clear();
+        self.task_file_changes.get_mut().unwrap().clear();
+        self.file_versions.get_mut().unwrap().refresh();
+        // The individual modifications are all undone now
+        undo_stack.clear()?;
+
+        let summary = format!("- {}", rolled_back.join("\n- "));
+        self.context_window.add_message(Message {
+            role: MessageRole::User,
+            content: format!(
+                "Note: the user rolled back every file you changed this session:\n{}",
+                summary
+            ),
+        });
+        Ok(format!(
+            "⏪ Rolled back {} file(s):\n{}",
+            rolled_back.len(),
+            summary
+        ))
+    }
 
-# Tool Call Format
+    /// Point the artifact store at `.g3/sessions/<id>/artifacts/` in the workspace, record the
+    /// session's starting point for `summarize-changes` and prune old sessions according to
+    /// `[artifacts]` and `[logs]`
+    fn start_session_artifacts(&mut self, session_id: &str) {
+        let Ok(workspace) = std::env::current_dir() else {
+            return;
+        };
+        self.context_window.artifacts = ArtifactStore::for_session(&workspace, session_id);
+        if let Err(e) = change_summary::record_baseline(&workspace, session_id) {
+            warn!("Failed to record the session's starting point: {}", e);
+        }
+        match artifacts::prune_sessions(&workspace, &self.config.artifacts, Some(session_id)) {
+            Ok(0) => {}
+            Ok(removed) => debug!("Pruned artifacts of {} old sessions", removed),
+            Err(e) => warn!("Failed to prune old session artifacts: {}", e),
+        }
+        if !self.quiet {
+            session_logs::prune_in_background(&self.config.logs, session_id);
+        }
+    }
 
-When you need to execute a tool, write ONLY the JSON tool call on a new line:
+    pub async fn execute_task(
+        &mut self,
+        description: &str,
+        language: Option<&str>,
+        _auto_execute: bool,
+    ) -> Result<TaskResult> {
+        self.execute_task_with_options(description, language, false, false, false)
+            .await
+    }
 
-{\"tool\": \"tool_name\", \"args\": {\"param\": \"value\"}
+    pub async fn execute_task_with_options(
+        &mut self,
+        description: &str,
+        language: Option<&str>,
+        _auto_execute: bool,
+        show_prompt: bool,
+        show_code: bool,
+    ) -> Result<TaskResult> {
+        self.execute_task_with_timing(
+            description,
+            language,
+            _auto_execute,
+            show_prompt,
+            show_code,
+            false,
+        )
+        .await
+    }
 
-The tool will execute immediately and you'll receive the result (success or error) to continue with.
+    pub async fn execute_task_with_timing(
+        &mut self,
+        description: &str,
+        language: Option<&str>,
+        _auto_execute: bool,
+        show_prompt: bool,
+        show_code: bool,
+        show_timing: bool,
+    ) -> Result<TaskResult> {
+        // Create a cancellation token that never cancels for backward compatibility
+        let cancellation_token = CancellationToken::new();
+        self.execute_task_with_timing_cancellable(
+            description,
+            language,
+            _auto_execute,
+            show_prompt,
+            show_code,
+            show_timing,
+            cancellation_token,
+        )
+        .await
+    }
 
-# Available Tools
-
-Short description for providers without native calling specs:
-
-- **shell**: Execute shell commands
-  - Format: {\"tool\": \"shell\", \"args\": {\"command\": \"your_command_here\"}
-  - Example: {\"tool\": \"shell\", \"args\": {\"command\": \"ls ~/Downloads\"}
-
-- **read_file**: Read the contents of a file (supports partial reads via start/end)
-  - Format: {\"tool\": \"read_file\", \"args\": {\"file_path\": \"path/to/file\", \"start\": 0, \"end\": 100}
-  - Example: {\"tool\": \"read_file\", \"args\": {\"file_path\": \"src/main.rs\"}
-  - Example (partial): {\"tool\": \"read_file\", \"args\": {\"file_path\": \"large.log\", \"start\": 0, \"end\": 1000}
-
-- **write_file**: Write content to a file (creates or overwrites)
-  - Format: {\"tool\": \"write_file\", \"args\": {\"file_path\": \"path/to/file\", \"content\": \"file content\"}
-  - Example: {\"tool\": \"write_file\", \"args\": {\"file_path\": \"src/lib.rs\", \"content\": \"pub fn hello() {}\"}
-
-- **str_replace**: Replace text in a file using a diff
-  - Format: {\"tool\": \"str_replace\", \"args\": {\"file_path\": \"path/to/file\", \"diff\": \"--- old\\n-old text\\n+++ new\\n+new text\"}
-  - Example: {\"tool\": \"str_replace\", \"args\": {\"file_path\": \"src/main.rs\", \"diff\": \"--- old\\n-old_code();\\n+++ new\\n+new_code();\"}
-
-- **final_output**: Signal task completion with a detailed summary of work done in markdown format
-  - Format: {\"tool\": \"final_output\", \"args\": {\"summary\": \"what_was_accomplished\"}
-
-- **todo_read**: Read the entire TODO list from todo.g3.md file in workspace directory
-  - Format: {\"tool\": \"todo_read\", \"args\": {}}
-  - Example: {\"tool\": \"todo_read\", \"args\": {}}
-
-- **todo_write**: Write or overwrite the entire todo.g3.md file (WARNING: overwrites completely, always read first)
-  - Format: {\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Task 1\\n- [ ] Task 2\"}}
-  - Example: {\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Implement feature\\n  - [ ] Write tests\\n  - [ ] Run tests\"}}
-
-- **code_search**: Syntax-aware code search using tree-sitter. Supports Rust, Python, JavaScript, TypeScript.
-  - Format: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"label\", \"query\": \"tree-sitter query\", \"language\": \"rust|python|javascript|typescript\", \"paths\": [\"src/\"], \"context_lines\": 0}]}}
-  - Find functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"find_functions\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\", \"paths\": [\"src/\"]}]}}
-  - Find async functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"find_async\", \"query\": \"(function_item (function_modifiers) name: (identifier) @name)\", \"language\": \"rust\"}]}}
-  - Find structs: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"structs\", \"query\": \"(struct_item name: (type_identifier) @name)\", \"language\": \"rust\"}]}}
-  - Multiple searches: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"funcs\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\"}, {\"name\": \"structs\", \"query\": \"(struct_item name: (type_identifier) @name)\", \"language\": \"rust\"}]}}
-  - With context lines: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"funcs\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\", \"context_lines\": 3}]}}
-       - \"context\": 3 (show surrounding lines),
-       - \"json_style\": \"stream\" (for large results)
-
-# Instructions
-
-1. Analyze the request and break down into smaller tasks if appropriate
-2. Execute ONE tool at a time. An exception exists for when you're writing files. See below.
-3. STOP when the original request was satisfied
-4. Call the final_output tool when done
-
-For reading files, prioritize use of code_search tool use with multiple search requests per call instead of read_file, if it makes sense.
-
-Exception to using ONE tool at a time:
-If all you’re doing is WRITING files, and you don’t need to do anything else between each step.
-You can issue MULTIPLE write_file tool calls in a request, however you may ONLY make a SINGLE write_file call for any file in that request.
-For example you may call:
-[START OF REQUEST]
-write_file(\"helper.rs\", \"...\")
-write_file(\"file2.txt\", \"...\")
-[DONE]
-
-But NOT:
-[START OF REQUEST]
-write_file(\"helper.rs\", \"...\")
-write_file(\"file2.txt\", \"...\")
-write_file(\"helper.rs\", \"...\")
-[DONE]
-
-# Task Management with TODO Tools
-
-**REQUIRED for multi-step tasks.** Use TODO tools when your task involves ANY of:
-- Multiple files to create/modify (2+)
-- Multiple distinct steps (3+)
-- Dependencies between steps
-- Testing or verification needed
-- Uncertainty about approach
-
-## Workflow
-
-Every multi-step task follows this pattern:
-1. **Start**: Call todo_read, then todo_write to create your plan
-2. **During**: Execute steps, then todo_read and todo_write to mark progress
-3. **End**: Call todo_read to verify all items complete
-
-Note: todo_write replaces the entire list, so always read first to preserve content.
-
-## Examples
+    #[allow(clippy::too_many_arguments)]
+    pub async fn execute_task_with_timing_cancellable(
+        &mut self,
+        description: &str,
+        _language: Option<&str>,
+        _auto_execute: bool,
+        show_prompt: bool,
+        show_code: bool,
+        show_timing: bool,
+        cancellation_token: CancellationToken,
+    ) -> Result<TaskResult> {
+        let turn_start = Instant::now();
+        let start_tokens = self.context_window.cumulative_tokens;
+        let start_tool_calls = self.tool_call_metrics.len();
+        let start_output_tokens = self.output_tokens;
+        let start_reasoning_tokens = self.reasoning_tokens;
+        let start_first_tokens = self.first_token_times.len();
+        *self.coach_feedback.get_mut() = None;
+        self.task_file_changes.get_mut().unwrap().clear();
+        self.tool_budgets.get_mut().unwrap().start_turn();
+        self.enforce_quota().await?;
 
-**Example 1: Feature Implementation**
-User asks: \"Add user authentication with tests\"
+        // Execute the task directly without splitting
+        let result = self
+            .execute_single_task(
+                description,
+                show_prompt,
+                show_code,
+                show_timing,
+                cancellation_token,
+            )
+            .await;
 
-First action:
-{\"tool\": \"todo_read\", \"args\": {}}
+        // Record the turn whether or not it succeeded so callers see failed turns too
+        let tool_calls = &self.tool_call_metrics[start_tool_calls..];
+        let metrics = TurnMetrics {
+            turn_number: self.turn_metrics.len() + 1,
+            tokens_used: self
+                .context_window
+                .cumulative_tokens
+                .saturating_sub(start_tokens),
+            wall_clock_time: turn_start.elapsed(),
+            tool_calls: tool_calls.len(),
+            failed_tool_calls: tool_calls.iter().filter(|(_, _, ok)| !ok).count(),
+            output_tokens: self.output_tokens.saturating_sub(start_output_tokens),
+            reasoning_tokens: self.reasoning_tokens.saturating_sub(start_reasoning_tokens),
+            time_to_first_token: self.first_token_times.get(start_first_tokens).copied(),
+            request_time: self.request_times.get(start_first_tokens).copied(),
+        };
+        self.turn_metrics.push(metrics.clone());
+        self.record_stats(&metrics, result.is_ok(), start_tool_calls);
+        self.record_usage(&metrics);
+        self.events.publish(events::AgentEvent::TurnFinished {
+            metrics: metrics.clone(),
+            success: result.is_ok(),
+        });
+        // An autonomous run notifies once, when it ends
+        if !self.is_autonomous {
+            let response = match &result {
+                Ok(r) => r.response.clone(),
+                Err(e) => e.to_string(),
+            };
+            if let Some(notification) = notify::for_turn(
+                &self.config.notifications,
+                metrics.wall_clock_time,
+                result.is_ok(),
+                &response,
+            ) {
+                notify::send(&self.config.notifications, &notification);
+            }
+        }
 
-Then create plan:
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Add user authentication\\n  - [ ] Create User struct\\n  - [ ] Add login endpoint\\n  - [ ] Add password hashing\\n  - [ ] Write unit tests\\n  - [ ] Write integration tests\"}}
+        let feedback = self.coach_feedback.get_mut().take();
+        let file_changes = std::mem::take(self.task_file_changes.get_mut().unwrap());
+        result.map(|r| {
+            r.with_metrics(metrics)
+                .with_feedback(feedback)
+                .with_file_changes(file_changes)
+        })
+    }
 
-After completing User struct:
-{\"tool\": \"todo_read\", \"args\": {}}
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Add user authentication\\n  - [x] Create User struct\\n  - [ ] Add login endpoint\\n  - [ ] Add password hashing\\n  - [ ] Write unit tests\\n  - [ ] Write integration tests\"}}
+    /// Ask a vision-capable provider for the bounding box of a described UI element in a
+    /// screenshot of `app_name`, in screen coordinates
+    async fn vision_locate(
+        &self,
+        controller: &dyn g3_computer_control::ComputerController,
+        app_name: &str,
+        description: &str,
+    ) -> Result<Option<g3_computer_control::types::TextLocation>> {
+        let provider = self
+            .providers
+            .resolve(g3_config::VISION_LOCATE_MODEL)
+            .await?;
+        if !provider.capabilities().vision {
+            anyhow::bail!("provider '{}' does not support images", provider.name());
+        }
 
-**Example 2: Bug Fix**
-User asks: \"Fix the memory leak in cache module\"
+        let screenshot_path = self
+            .artifacts()
+            .path_for("vision_locate.png")?
+            .display()
+            .to_string();
+        controller
+            .take_screenshot(&screenshot_path, None, Some(app_name))
+            .await?;
+
+        let result = async {
+            let data = std::fs::read(&screenshot_path)?;
+            let image_size = vision::png_dimensions(&data)
+                .ok_or_else(|| anyhow::anyhow!("screenshot is not a PNG image"))?;
+            let response = provider
+                .complete_with_image(
+                    &vision::locate_prompt(description),
+                    &g3_providers::ImageContent::png(data),
+                    Some(200),
+                )
+                .await?;
+            debug!("vision_locate response: {}", response.content);
 
-{\"tool\": \"todo_read\", \"args\": {}}
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Fix memory leak\\n  - [ ] Review cache.rs\\n  - [ ] Check for unclosed resources\\n  - [ ] Add drop implementation\\n  - [ ] Write test to verify fix\"}}
+            match vision::parse_bounding_box(&response.content, description, image_size)
+                .map_err(|e| anyhow::anyhow!(e))?
+            {
+                Some(region) => Ok(Some(controller.screenshot_region_to_screen(
+                    app_name,
+                    &screenshot_path,
+                    region,
+                )?)),
+                None => Ok(None),
+            }
+        }
+        .await;
 
-**Example 3: Refactoring**
-User asks: \"Refactor database layer to use async/await\"
+        let _ = std::fs::remove_file(&screenshot_path);
+        result
+    }
 
-{\"tool\": \"todo_read\", \"args\": {}}
-{\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Refactor to async\\n  - [ ] Update function signatures\\n  - [ ] Replace blocking calls\\n  - [ ] Update all callers\\n  - [ ] Update tests\"}}
+    /// Describe the displays and, given an app and text visible in it, check that the screen
+    /// point a click on the text would go to shows that text
+    async fn vision_calibrate(
+        &self,
+        controller: &std::sync::Arc<dyn g3_computer_control::ComputerController>,
+        target: Option<(&str, &str)>,
+        move_pointer: bool,
+    ) -> Result<String> {
+        let topology = controller.displays()?;
+        let mut report =
+            vec!["Displays, in screen points from the top-left of the main display:".to_string()];
+        for display in topology.displays() {
+            let b = display.bounds;
+            report.push(format!(
+                "  {} {}: {}x{} at ({}, {}), scale {}x",
+                if display.main { "Main display" } else { "Display" },
+                display.id,
+                b.width,
+                b.height,
+                b.x,
+                b.y,
+                display.scale
+            ));
+        }
+        let Some((app_name, text)) = target else {
+            return Ok(format!("✅ {}", report.join("\n")));
+        };
 
-## Format
+        let Some(location) = controller.find_text_in_app(app_name, text).await? else {
+            anyhow::bail!("could not find '{}' in {}", text, app_name);
+        };
+        let (click_x, click_y) = g3_computer_control::display::center(&location);
+        let display = topology
+            .display_at(click_x, click_y)
+            .map(|display| display.id.to_string())
+            .unwrap_or_else(|| "none (off screen)".to_string());
+        report.push(format!(
+            "'{}' in {} is at ({}, {}) with size {}x{}; a click goes to ({}, {}) on display {}",
+            location.text,
+            app_name,
+            location.x,
+            location.y,
+            location.width,
+            location.height,
+            click_x,
+            click_y,
+            display
+        ));
 
-Use markdown checkboxes:
-- \"- [ ]\" for incomplete tasks
-- \"- [x]\" for completed tasks
-- Indent with 2 spaces for subtasks
+        // Read that spot of the screen back, with a margin for the OCR box being tight
+        let margin = 4;
+        let region = g3_computer_control::types::Rect {
+            x: location.x - margin,
+            y: location.y - margin,
+            width: location.width + 2 * margin,
+            height: location.height + 2 * margin,
+        };
+        let path = self.artifacts().path_for("vision_calibrate.png")?;
+        let path = path.display().to_string();
+        controller.capture_screen_region(&path, region).await?;
+        let read = self.ocr.extract_text(controller, &path, None).await;
+        let _ = std::fs::remove_file(&path);
+        let read = read?.trim().to_string();
+        if move_pointer {
+            controller.move_mouse(click_x, click_y)?;
+            report.push("The pointer was moved there.".to_string());
+        }
+        if read.to_lowercase().contains(&text.to_lowercase()) {
+            report.push(format!("The screen there reads '{}', so clicks on it land.", read));
+            Ok(format!("✅ {}", report.join("\n")))
+        } else {
+            report.push(format!(
+                "The screen there reads '{}' instead, so clicks are misplaced. Compare the \
+                 window's display and scale above with the system's display settings.",
+                read
+            ));
+            Ok(format!("❌ {}", report.join("\n")))
+        }
+    }
 
-Keep items short, specific, and action-oriented.
+    /// Append the task to the local stats file when `[stats]` is enabled
+    fn record_stats(&self, metrics: &TurnMetrics, success: bool, start_tool_calls: usize) {
+        if !self.config.stats.enabled {
+            return;
+        }
+        let (provider, model) = match self.providers.get(None) {
+            Ok(provider) => (provider.name().to_string(), provider.model().to_string()),
+            Err(_) => (String::new(), String::new()),
+        };
+        let record = stats::TaskRecord::new(
+            &provider,
+            &model,
+            success,
+            metrics.tokens_used,
+            metrics.wall_clock_time,
+            &self.tool_call_metrics[start_tool_calls..],
+        )
+        .with_price(self.config.stats.price_for(&provider, &model));
+        if let Err(e) = stats::StatsStore::from_config(&self.config.stats).record(&record) {
+            warn!("Failed to record usage stats: {}", e);
+        }
+    }
 
-## Benefits
+    /// Add the turn's tokens to the usage of the active provider when `[quotas]` sets limits
+    fn record_usage(&self, metrics: &TurnMetrics) {
+        let quotas = &self.config.quotas;
+        if quotas.providers.is_empty() || metrics.tokens_used == 0 {
+            return;
+        }
+        let provider = &self.config.providers.default_provider;
+        let price = quotas
+            .providers
+            .get(provider)
+            .and_then(|quota| quota.cost_per_million_tokens)
+            .or(self.config.stats.cost_per_million_tokens);
+        let usage = quota::Usage {
+            tokens: metrics.tokens_used as u64,
+            cost: quota::cost(metrics.tokens_used as u64, price),
+        };
+        let today = chrono::Local::now().date_naive();
+        if let Err(e) = quota::UsageStore::from_config(quotas).record(provider, today, &usage) {
+            warn!("Failed to record quota usage: {}", e);
+        }
+    }
 
-✓ Prevents missed steps
-✓ Makes progress visible
-✓ Helps recover from interruptions
-✓ Creates better summaries
+    /// Check the active provider's quota before a turn, and warn, switch to the fallback
+    /// provider or refuse the turn as `quotas.action` says
+    async fn enforce_quota(&mut self) -> Result<()> {
+        let quotas = &self.config.quotas;
+        let provider = &self.config.providers.default_provider;
+        let Some(quota) = quotas.providers.get(provider) else {
+            return Ok(());
+        };
+        let ledger = quota::UsageStore::from_config(quotas).load();
+        let today = chrono::Local::now().date_naive();
+        let Some(exceeded) = quota::check(quota, provider, &ledger, today) else {
+            return Ok(());
+        };
 
-## When NOT to Use
+        let fallback = quotas
+            .fallback_provider
+            .clone()
+            .filter(|fallback| fallback != provider);
+        match (quotas.action, fallback) {
+            (QuotaAction::Block, _) => anyhow::bail!(
+                "Quota exceeded: {}. Raise the limit under [quotas] or wait for it to reset.",
+                exceeded
+            ),
+            (QuotaAction::Fallback, Some(fallback)) => {
+                self.switch_provider(&fallback).await?;
+                self.events.publish(events::AgentEvent::Status {
+                    message: format!("⚠️ Quota exceeded: {}. Switched to {}.", exceeded, fallback),
+                });
+            }
+            _ => {
+                self.events.publish(events::AgentEvent::Status {
+                    message: format!("⚠️ Quota exceeded: {}", exceeded),
+                });
+            }
+        }
+        Ok(())
+    }
 
-Skip TODO tools for simple single-step tasks:
-- \"List files\" → just use shell
-- \"Read config.json\" → just use read_file
-- \"Search for functions\" → just use code_search
+    /// Make `name` the default provider for the rest of the session
+    pub async fn switch_provider(&mut self, name: &str) -> Result<()> {
+        let mut config = self.config.clone();
+        config.providers.default_provider = name.to_string();
+        let providers = Self::build_providers(&config, self.is_autonomous).await?;
+        self.context_window.total_tokens = Self::get_configured_context_length(&config, &providers)?;
+        self.providers = providers;
+        self.config = config;
+        self.base_providers.default_provider = name.to_string();
+        Ok(())
+    }
 
-If you can complete it with 1-2 tool calls, skip TODO.
+    async fn execute_single_task(
+        &mut self,
+        description: &str,
+        show_prompt: bool,
+        _show_code: bool,
+        show_timing: bool,
+        cancellation_token: CancellationToken,
+    ) -> Result<TaskResult> {
+        // Reset the JSON tool call filter state at the start of each new task
+        // This prevents the filter from staying in suppression mode between user interactions
+        fixed_filter_json::reset_fixed_json_tool_state();
+        self.cancellation = cancellation_token.clone();
 
+        // Generate session ID based on the initial prompt if this is a new session
+        if self.session_id.is_none() {
+            let session_id = self.generate_session_id(description);
+            self.start_session_artifacts(&session_id);
+            self.session_id = Some(session_id);
+        }
 
-# Response Guidelines
+        // Only add system message if this is the first interaction (empty conversation history)
+        if self.context_window.conversation_history.is_empty() {
+            let provider = self.providers.get(None)?;
+            let system_prompt =
+                system_prompt::build(provider.has_native_tool_calling(), &self.tool_specs());
 
-- Use Markdown formatting for all responses except tool calls.
-- Whenever taking actions, use the pronoun 'I'
+            let system_prompt = if self.config.agent.read_only {
+                system_prompt + read_only::READ_ONLY_PROMPT
+            } else {
+                system_prompt
+            };
+            let system_prompt = system_prompt + &self.scope.prompt();
+            let system_prompt = match &self.remote {
+                Some(host) => system_prompt + &remote::prompt(host),
+                None => system_prompt,
+            };
 
-".to_string()
+            // Providers without native tool calling only learn about tools from the prompt
+            let system_prompt = if provider.has_native_tool_calling() {
+                system_prompt
+            } else {
+                let mut system_prompt = system_prompt;
+                if let Some(uc) = &self.unity_catalog {
+                    system_prompt.push_str(&uc.prompt_reference());
+                }
+                system_prompt.push_str(&self.plugins.prompt_reference(self.config.agent.read_only));
+                let read_only = self.config.agent.read_only;
+                system_prompt.push_str(&self.custom_tools.prompt_reference(read_only));
+                system_prompt
             };
 
             if show_prompt {
-                self.ui_writer.print_system_prompt(&system_prompt);
+                self.events.publish(events::AgentEvent::SystemPrompt {
+                    prompt: system_prompt.clone(),
+                });
             }
 
             // Add system message to context window
@@ -1499,11 +2233,18 @@ If you can complete it with 1-2 tool calls, skip TODO.
             };
             self.context_window.add_message(system_message);
         }
+        self.apply_profile_prompt();
 
-        // Add user message to context window
+        // Add user message to context window. A continuation is sent as it is, so the
+        // history reads as one response once it is joined with what it continues.
+        self.response_truncated = false;
         let user_message = Message {
             role: MessageRole::User,
-            content: format!("Task: {}", description),
+            content: if description == CONTINUE_PROMPT {
+                description.to_string()
+            } else {
+                format!("Task: {}", description)
+            },
         };
         self.context_window.add_message(user_message);
 
@@ -1512,27 +2253,13 @@ If you can complete it with 1-2 tool calls, skip TODO.
 
         // Check if provider supports native tool calling and add tools if so
         let provider = self.providers.get(None)?;
-        let tools = if provider.has_native_tool_calling() {
-            Some(Self::create_tool_definitions(
-                self.config.webdriver.enabled,
-                self.config.macax.enabled,
-                self.config.computer_control.enabled,
-            ))
-        } else {
-            None
-        };
+        let tools =
+            self.request_tools(provider.has_native_tool_calling(), &provider.capabilities());
 
-        // Get max_tokens from provider configuration
-        let max_tokens = match provider.name() {
-            "databricks" => {
-                // Use the model's maximum limit for Databricks to allow large file generation
-                Some(32000)
-            }
-            _ => {
-                // Default for other providers
-                Some(16000)
-            }
-        };
+        let max_tokens = Some(self.output_tokens(
+            &provider.capabilities(),
+            output_tokens::Phase::Task,
+        ));
 
         let request = CompletionRequest {
             messages,
@@ -1540,20 +2267,35 @@ If you can complete it with 1-2 tool calls, skip TODO.
             temperature: Some(0.1),
             stream: true, // Enable streaming
             tools,
+            tool_choice: ToolChoice::Auto,
+            stop_sequences: Vec::new(),
         };
 
         // Time the LLM call with cancellation support and streaming
         let llm_start = Instant::now();
-        let result = tokio::select! {
-            result = self.stream_completion(request, show_timing) => result,
-            _ = cancellation_token.cancelled() => {
-                // Save context window on cancellation
-                self.save_context_window("cancelled");
-                Err(anyhow::anyhow!("Operation cancelled by user"))
+        let running_command = self.running_command.clone();
+        let result = {
+            let stream = self.stream_completion(request, show_timing);
+            tokio::pin!(stream);
+            tokio::select! {
+                result = &mut stream => result,
+                _ = cancellation_token.cancelled() => {
+                    // A running shell command is stopped by the same token: let it wind
+                    // down so its result is recorded before the turn ends
+                    if running_command.load(std::sync::atomic::Ordering::SeqCst) {
+                        stream.await
+                    } else {
+                        Err(G3Error::Cancelled.into())
+                    }
+                }
             }
         };
+        if result.is_err() && cancellation_token.is_cancelled() {
+            // Save context window on cancellation
+            self.save_context_window("cancelled");
+        }
 
-        let task_result = match result {
+        let mut task_result = match result {
             Ok(result) => result,
             Err(e) => {
                 // Save context window on error
@@ -1562,6 +2304,18 @@ If you can complete it with 1-2 tool calls, skip TODO.
             }
         };
 
+        match self
+            .moderator
+            .response(&task_result.response, &self.moderation_log())
+            .await
+        {
+            moderation::Decision::Allow => {}
+            moderation::Decision::Modify(content) => task_result.response = content,
+            moderation::Decision::Block(reason) => {
+                task_result.response = format!("⛔ Response withheld by moderation hook: {}", reason)
+            }
+        }
+
         let response_content = task_result.response.clone();
         let _llm_duration = llm_start.elapsed();
 
@@ -1570,6 +2324,7 @@ If you can complete it with 1-2 tool calls, skip TODO.
             prompt_tokens: 100,                                   // Estimate
             completion_tokens: response_content.len() as u32 / 4, // Rough estimate
             total_tokens: 100 + (response_content.len() as u32 / 4),
+            reasoning_tokens: 0,
         };
 
         // Update context window with estimated token usage
@@ -1592,13 +2347,15 @@ If you can complete it with 1-2 tool calls, skip TODO.
 
         // Check if we need to do 90% auto-compaction
         if self.pending_90_summarization {
-            self.ui_writer.print_context_status(
-                "\n⚡ Context window reached 90% - auto-compacting...\n"
-            );
+            self.events.publish(events::AgentEvent::Status {
+                message: "\n⚡ Context window reached 90% - auto-compacting...\n".to_string(),
+            });
             if let Err(e) = self.force_summarize().await {
                 warn!("Failed to auto-compact at 90%: {}", e);
             } else {
-                self.ui_writer.println("");
+                self.events.publish(events::AgentEvent::Line {
+                    text: String::new(),
+                });
             }
             self.pending_90_summarization = false;
         }
@@ -1664,6 +2421,7 @@ If you can complete it with 1-2 tool calls, skip TODO.
             "session_id": self.session_id,
             "timestamp": timestamp,
             "status": status,
+            "tool_versions": ToolRegistry::builtin().versions(),
             "context_window": {
                 "used_tokens": self.context_window.used_tokens,
                 "total_tokens": self.context_window.total_tokens,
@@ -1765,81 +2523,22 @@ If you can complete it with 1-2 tool calls, skip TODO.
     pub async fn force_summarize(&mut self) -> Result<bool> {
         info!("Manual summarization triggered");
 
-        self.ui_writer.print_context_status(&format!(
-            "\n🗜️ Manual summarization requested (current usage: {}%)...",
-            self.context_window.percentage_used() as u32
-        ));
-
-        // Create summary request with FULL history
-        let summary_prompt = self.context_window.create_summary_prompt();
-
-        // Get the full conversation history
-        let conversation_text = self
-            .context_window
-            .conversation_history
-            .iter()
-            .map(|m| format!("{:?}: {}", m.role, m.content))
-            .collect::<Vec<_>>()
-            .join("\n\n");
-
-        let summary_messages = vec![
-            Message {
-                role: MessageRole::System,
-                content: "You are a helpful assistant that creates concise summaries.".to_string(),
-            },
-            Message {
-                role: MessageRole::User,
-                content: format!(
-                    "Based on this conversation history, {}\n\nConversation:\n{}",
-                    summary_prompt, conversation_text
-                ),
-            },
-        ];
-
-        let provider = self.providers.get(None)?;
-
-        // Dynamically calculate max_tokens for summary based on what's left
-        let summary_max_tokens = match provider.name() {
-            "databricks" | "anthropic" => {
-                let model_limit = self.context_window.total_tokens;
-                let current_usage = self.context_window.used_tokens;
-                let available = model_limit
-                    .saturating_sub(current_usage)
-                    .saturating_sub(5000);
-                Some(available.min(10_000))
-            }
-            "embedded" => {
-                let model_limit = self.context_window.total_tokens;
-                let current_usage = self.context_window.used_tokens;
-                let available = model_limit
-                    .saturating_sub(current_usage)
-                    .saturating_sub(1000);
-                Some(available.min(3000))
-            }
-            _ => {
-                let available = self.context_window.remaining_tokens().saturating_sub(2000);
-                Some(available.min(5000))
-            }
-        };
-
-        debug!(
-            "Requesting summary with max_tokens: {:?} (current usage: {} tokens)",
-            summary_max_tokens, self.context_window.used_tokens
-        );
+        self.events.publish(events::AgentEvent::Status {
+            message: format!(
+                "\n🗜️ Manual summarization requested (current usage: {}%)...",
+                self.context_window.percentage_used() as u32
+            ),
+        });
 
-        let summary_request = CompletionRequest {
-            messages: summary_messages,
-            max_tokens: summary_max_tokens,
-            temperature: Some(0.3),
-            stream: false,
-            tools: None,
-        };
+        let (provider, mut summary_request) = self.summary_request().await?;
+        self.moderate_request(&mut summary_request).await?;
 
         // Get the summary
         match provider.complete(summary_request).await {
             Ok(summary_response) => {
-                self.ui_writer
-                    .print_context_status("✅ Context compacted successfully.\n");
+                self.events.publish(events::AgentEvent::ContextCompacted {
+                    message: "✅ Context compacted successfully.\n".to_string(),
+                });
 
                 // Get the latest user message to preserve it
                 let latest_user_msg = self
@@ -1860,77 +2559,419 @@ If you can complete it with 1-2 tool calls, skip TODO.
             }
             Err(e) => {
                 error!("Failed to create summary: {}", e);
-                self.ui_writer.print_context_status(
-                    "⚠️ Unable to create summary. Please try again or start a new session.\n",
-                );
+                self.events.publish(events::AgentEvent::Status {
+                    message: "⚠️ Unable to create summary. Please try again or start a new session.\n"
+                        .to_string(),
+                });
                 Ok(false)
             }
         }
     }
 
-    /// Manually trigger context thinning regardless of thresholds
-    pub fn force_thin(&mut self) -> String {
-        info!("Manual context thinning triggered");
-        let (message, chars_saved) = self.context_window.thin_context();
-        self.thinning_events.push(chars_saved);
-        message
+    /// The registry name of the `[context.summarizer]` provider, which is loaded the first
+    /// time it is needed. `None` (the active provider) when there is no summarizer or it
+    /// can't be set up.
+    async fn summarizer_provider(&self) -> Option<&'static str> {
+        self.load_model(g3_config::SUMMARIZER_MODEL)
+            .await
+            .then_some(g3_config::SUMMARIZER_MODEL)
     }
 
-    /// Reload README.md and AGENTS.md and replace the first system message
-    /// Returns Ok(true) if README was found and reloaded, Ok(false) if no README was present initially
-    pub fn reload_readme(&mut self) -> Result<bool> {
-        info!("Manual README reload triggered");
+    /// Load the model of a tool or subsystem from `[models]`. `false` when it has none or
+    /// it can't be loaded, in which case the active provider stands in for it.
+    async fn load_model(&self, name: &str) -> bool {
+        if !self.providers.contains(name) {
+            return false;
+        }
+        match self.providers.resolve(name).await {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("The {} model is unavailable, using the active provider: {}", name, e);
+                false
+            }
+        }
+    }
 
-        // Check if the first message in conversation history is a system message with README content
-        let has_readme = self
+    /// The provider that writes context summaries and the output limit of a summary
+    fn summary_provider(
+        &self,
+        summarizer: Option<&str>,
+    ) -> Result<(&dyn g3_providers::LLMProvider, u32)> {
+        let provider = self.providers.get(summarizer)?;
+        let summarizer_max_tokens = summarizer
+            .and_then(|name| self.config.model_for(name))
+            .and_then(|summarizer| summarizer.max_tokens);
+        let cap = summarizer_max_tokens.unwrap_or_else(|| {
+            self.output_tokens(&provider.capabilities(), output_tokens::Phase::Summary)
+        });
+        Ok((provider, cap))
+    }
+
+    /// Count the tokens of a response: the usage the provider sent, or else an estimate from
+    /// the text that streamed
+    fn record_response_usage(&mut self, usage: Option<g3_providers::Usage>, streamed: &str) {
+        if let Some(usage) = usage {
+            debug!("Updating context window with actual usage from stream");
+            self.context_window.update_usage_from_response(&usage);
+            self.output_tokens = self.output_tokens.saturating_add(usage.completion_tokens);
+            self.reasoning_tokens = self.reasoning_tokens.saturating_add(usage.reasoning_tokens);
+        } else {
+            // Fall back to estimation if no usage data was provided
+            debug!("No usage data from stream, using estimation");
+            let estimated_tokens = ContextWindow::estimate_tokens(streamed);
+            self.context_window.add_streaming_tokens(estimated_tokens);
+            self.output_tokens = self.output_tokens.saturating_add(estimated_tokens);
+        }
+    }
+
+    /// Tell the model that the turn has run past its time limit of `limit`
+    fn request_wrap_up(&mut self, limit: Duration) {
+        self.events.publish(events::AgentEvent::Status {
+            message: format!(
+                "\n⏰ Turn time limit of {} reached, asking the model to wrap up...\n",
+                format_duration(limit)
+            ),
+        });
+        self.context_window.add_message(Message {
+            role: MessageRole::System,
+            content: WRAP_UP_PROMPT.to_string(),
+        });
+    }
+
+    /// The provider that writes a context summary and the request for it. The conversation is
+    /// cut to fit the summarizer's own context window with room left for the summary: its
+    /// oldest messages are left out first, and a newest message too large by itself keeps
+    /// only its end.
+    async fn summary_request(
+        &self,
+    ) -> Result<(&dyn g3_providers::LLMProvider, CompletionRequest)> {
+        let summarizer = self.summarizer_provider().await;
+        let (provider, summary_cap) = self.summary_provider(summarizer)?;
+        let model = provider.model();
+        let capabilities = provider.capabilities();
+        let window = capabilities
             .context_window
-            .conversation_history
-            .first()
-            .map(|m| {
-                matches!(m.role, MessageRole::System)
-                    && (m.content.contains("Project README")
-                        || m.content.contains("Agent Configuration"))
-            })
-            .unwrap_or(false);
+            .unwrap_or(self.context_window.total_tokens);
 
-        if !has_readme {
-            return Ok(false);
+        let system = Message {
+            role: MessageRole::System,
+            content: "You are a helpful assistant that creates concise summaries.".to_string(),
+        };
+        let instructions = format!(
+            "Based on this conversation history, {}\n\nConversation:\n",
+            self.context_window.create_summary_prompt()
+        );
+        // At least a quarter of the window is kept for the summary
+        let reserved = summary_cap
+            .min(capabilities.max_output_tokens.unwrap_or(summary_cap))
+            .min(window / 4);
+        let fixed = tokenizer::count_message_tokens(model, &system)
+            + tokenizer::count_tokens(model, &instructions);
+        let mut budget = window
+            .saturating_sub((window / 40).clamp(1000, 10000))
+            .saturating_sub(reserved)
+            .saturating_sub(fixed);
+
+        let mut kept = Vec::new();
+        for message in self.context_window.conversation_history.iter().rev() {
+            let text = format!("{:?}: {}", message.role, message.content);
+            let tokens = tokenizer::count_tokens(model, &text) + 1;
+            if tokens <= budget {
+                budget -= tokens;
+                kept.push(text);
+                continue;
+            }
+            if kept.is_empty() && budget > 0 {
+                let mut start = text.len() - text.len() * budget as usize / tokens as usize;
+                while !text.is_char_boundary(start) {
+                    start += 1;
+                }
+                kept.push(format!("[... start left out]\n{}", &text[start..]));
+            }
+            break;
+        }
+        let left_out = self.context_window.conversation_history.len() - kept.len();
+        if left_out > 0 {
+            debug!(
+                "Leaving out {} messages to fit the summarizer's window of {} tokens",
+                left_out, window
+            );
+            kept.push(format!(
+                "[{} earlier messages left out to fit the summarizer's context window]",
+                left_out
+            ));
         }
+        kept.reverse();
 
-        // Try to load README.md and AGENTS.md
-        let mut combined_content = String::new();
-        let mut found_any = false;
+        let user = Message {
+            role: MessageRole::User,
+            content: format!("{}{}", instructions, kept.join("\n\n")),
+        };
+        let prompt_tokens = tokenizer::count_message_tokens(model, &system)
+            + tokenizer::count_message_tokens(model, &user);
+        let max_tokens = self.summary_max_tokens(provider, prompt_tokens, summary_cap)?;
+        debug!(
+            "Requesting summary with max_tokens: {} (prompt: {} tokens, window: {})",
+            max_tokens, prompt_tokens, window
+        );
 
-        if let Ok(agents_content) = std::fs::read_to_string("AGENTS.md") {
-            combined_content.push_str("# Agent Configuration\n\n");
-            combined_content.push_str(&agents_content);
-            combined_content.push_str("\n\n");
-            found_any = true;
+        let request = CompletionRequest {
+            messages: vec![system, user],
+            max_tokens: Some(max_tokens),
+            temperature: Some(0.3), // Lower temperature for factual summary
+            stream: false,
+            tools: None,
+            tool_choice: ToolChoice::Auto,
+            stop_sequences: Vec::new(),
+        };
+        Ok((provider, request))
+    }
+
+    /// `max_tokens` for a summary by `provider` of `prompt_tokens` of conversation: what is
+    /// left of its context window less a buffer of 2.5% of the window (1k to 10k tokens),
+    /// and no more than `summary_cap` or the model's output limit
+    fn summary_max_tokens(
+        &self,
+        provider: &dyn g3_providers::LLMProvider,
+        prompt_tokens: u32,
+        summary_cap: u32,
+    ) -> Result<u32> {
+        let capabilities = provider.capabilities();
+        let window = capabilities
+            .context_window
+            .unwrap_or(self.context_window.total_tokens);
+        let buffer = (window / 40).clamp(1000, 10000);
+        let available = window.saturating_sub(prompt_tokens).saturating_sub(buffer);
+        if available == 0 {
+            error!(
+                "Context window at capacity ({}%), cannot summarize. Current: {}, Limit: {}",
+                self.context_window.percentage_used(),
+                prompt_tokens,
+                window
+            );
+            anyhow::bail!(
+                "Context window at capacity. Try using /thinnify or /compact commands to reduce \
+                 context size, or start a new session."
+            );
         }
+        let cap = capabilities
+            .max_output_tokens
+            .map_or(summary_cap, |limit| limit.min(summary_cap));
+        Ok(available.min(cap))
+    }
 
-        if let Ok(readme_content) = std::fs::read_to_string("README.md") {
-            combined_content.push_str("# Project README\n\n");
-            combined_content.push_str(&readme_content);
-            found_any = true;
+    /// `result` cut down to its tool's `[context.tool_results]` limit before it enters the
+    /// context. The full result is saved as an artifact that the cut-down one points to.
+    async fn limit_tool_result(&self, tool: &str, result: String, model: &str) -> String {
+        let (max_tokens, strategy) = self.config.context.tool_results.for_tool(tool);
+        if !tool_result_limit::may_exceed(&result, max_tokens) {
+            return result;
+        }
+        let tokens = tokenizer::count_tokens(model, &result);
+        if tokens <= max_tokens {
+            return result;
         }
+        let path = match self.artifacts().save(
+            ArtifactKind::LargeToolResult,
+            &format!("{}_result.txt", tool),
+            result.as_bytes(),
+            Some(tool),
+        ) {
+            Ok(path) => path,
+            Err(e) => {
+                // Without the artifact nothing would point to the rest; thinning handles it
+                warn!("Failed to save large {} result: {}", tool, e);
+                return result;
+            }
+        };
+        info!(
+            "{} result of {} tokens is over its limit of {}, saved to {}",
+            tool,
+            tokens,
+            max_tokens,
+            path.display()
+        );
 
-        if found_any {
-            // Replace the first message with the new content
-            if let Some(first_msg) = self.context_window.conversation_history.first_mut() {
-                first_msg.content = combined_content;
-                info!("README content reloaded successfully");
-                Ok(true)
-            } else {
-                Ok(false)
+        if strategy == ToolResultStrategy::Summarize {
+            let summarizer = self.summarizer_provider().await.map(str::to_string);
+            match self.providers.get(summarizer.as_deref()) {
+                Ok(provider) => {
+                    let mut request = tool_output_summary_request(&result, max_tokens.min(2000));
+                    match self.moderate_request(&mut request).await {
+                        Ok(()) => match provider.complete(request).await {
+                            Ok(response) if !response.content.trim().is_empty() => {
+                                return tool_result_limit::summarized(&response.content, tokens, &path);
+                            }
+                            Ok(_) => warn!("Empty summary of large {} result", tool),
+                            Err(e) => warn!("Failed to summarize large {} result: {}", tool, e),
+                        },
+                        Err(e) => warn!("Not summarizing large {} result: {}", tool, e),
+                    }
+                }
+                Err(e) => warn!("No summarizer for large {} result: {}", tool, e),
             }
-        } else {
-            Ok(false)
         }
+        tool_result_limit::head_tail(&result, tokens, max_tokens, &path)
     }
 
-    /// Get detailed context statistics
-    pub fn get_stats(&self) -> String {
-        let mut stats = String::new();
+    /// Manually trigger context thinning regardless of thresholds
+    pub async fn force_thin(&mut self) -> String {
+        info!("Manual context thinning triggered");
+        self.thin_context().await
+    }
+
+    /// Thin the context with the configured strategy and record the event.
+    /// For summarize-in-place, tool results are condensed by the summary provider first
+    async fn thin_context(&mut self) -> String {
+        let summaries = if self.context_window.thinning.strategy == ThinningStrategy::SummarizeInPlace {
+            let provider = match self.context_window.thinning.summary_provider.clone() {
+                Some(provider) => Some(provider),
+                None => self.summarizer_provider().await.map(str::to_string),
+            };
+            self.summarize_thinning_candidates(provider.as_deref()).await
+        } else {
+            std::collections::HashMap::new()
+        };
+        let (message, chars_saved) = self.context_window.thin_context_with_summaries(&summaries);
+        self.thinning_events.push(chars_saved);
+        message
+    }
+
+    /// Ask the summary provider to condense each tool result that thinning would replace,
+    /// stopping once the configured target reduction is covered
+    async fn summarize_thinning_candidates(
+        &self,
+        provider: Option<&str>,
+    ) -> std::collections::HashMap<usize, String> {
+        let mut summaries = std::collections::HashMap::new();
+        let thinning = &self.context_window.thinning;
+        let provider = match self.providers.get(provider) {
+            Ok(provider) => provider,
+            Err(e) => {
+                warn!("Summarize-in-place thinning unavailable, saving to files only: {}", e);
+                return summaries;
+            }
+        };
+
+        let total_chars: usize = self
+            .context_window
+            .conversation_history
+            .iter()
+            .map(|m| m.content.len())
+            .sum();
+        let target_chars = total_chars * thinning.target_reduction as usize / 100;
+        let mut covered_chars = 0;
+
+        for candidate in self.context_window.thinning_candidates() {
+            if target_chars > 0 && covered_chars >= target_chars {
+                break;
+            }
+            if candidate.payload_arg.is_some() {
+                continue;
+            }
+
+            let content = &self.context_window.conversation_history[candidate.index].content;
+            let mut request = tool_output_summary_request(content, 500);
+            if let Err(e) = self.moderate_request(&mut request).await {
+                warn!("Not summarizing tool result: {}", e);
+                continue;
+            }
+
+            match provider.complete(request).await {
+                Ok(response) if !response.content.trim().is_empty() => {
+                    summaries.insert(candidate.index, response.content.trim().to_string());
+                    covered_chars += candidate.size;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Failed to summarize tool result {}: {}", candidate.index, e);
+                    break;
+                }
+            }
+        }
+        summaries
+    }
+
+    /// Reload README.md, the agent instructions and the project index and replace the first system message
+    /// Returns Ok(true) if README was found and reloaded, Ok(false) if no README was present initially
+    pub fn reload_readme(&mut self) -> Result<bool> {
+        info!("Manual README reload triggered");
+
+        // Check if the first message in conversation history is a system message with README content
+        let has_readme = self
+            .context_window
+            .conversation_history
+            .first()
+            .map(|m| {
+                matches!(m.role, MessageRole::System)
+                    && (m.content.contains("Project README")
+                        || m.content.contains("Agent Configuration")
+                        || m.content.contains(project::FACTS_HEADER)
+                        || m.content.contains(project_index::HEADER))
+            })
+            .unwrap_or(false);
+
+        if !has_readme {
+            return Ok(false);
+        }
+
+        // Try to load README.md and AGENTS.md
+        let mut combined_content = String::new();
+        let mut found_any = false;
+
+        let read_visible = |name: &str| {
+            if self.g3ignore.is_ignored(std::path::Path::new(name)) {
+                return None;
+            }
+            std::fs::read_to_string(name).ok()
+        };
+
+        let cwd = std::env::current_dir()?;
+        if let Some(agents_content) = instructions::load(&cwd, &self.g3ignore) {
+            combined_content.push_str(&agents_content);
+            combined_content.push_str("\n\n");
+            found_any = true;
+        }
+
+        if let Some(readme_content) = read_visible("README.md") {
+            combined_content.push_str("# Project README\n\n");
+            combined_content.push_str(&readme_content);
+            found_any = true;
+        }
+
+        if let Some(facts) = project::ProjectFacts::detect(&cwd).render() {
+            if found_any {
+                combined_content.push_str("\n\n");
+            }
+            combined_content.push_str(&facts);
+            found_any = true;
+        }
+
+        if let Some(index) = project_index::ProjectIndex::load(&cwd) {
+            if found_any {
+                combined_content.push_str("\n\n");
+            }
+            combined_content.push_str(&index.render());
+            found_any = true;
+        }
+
+        if found_any {
+            // Replace the first message with the new content
+            if let Some(first_msg) = self.context_window.conversation_history.first_mut() {
+                first_msg.content = combined_content;
+                info!("README content reloaded successfully");
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Get detailed context statistics
+    pub fn get_stats(&self) -> String {
+        let mut stats = String::new();
         use std::time::Duration;
 
         stats.push_str("\n📊 Context Window Statistics\n");
@@ -1955,6 +2996,12 @@ If you can complete it with 1-2 tool calls, skip TODO.
             "   • Cumulative Tokens: {:>10}\n",
             self.context_window.cumulative_tokens
         ));
+        if self.reasoning_tokens > 0 {
+            stats.push_str(&format!(
+                "   • Reasoning Tokens:  {:>10}\n",
+                self.reasoning_tokens
+            ));
+        }
         stats.push_str(&format!(
             "   • Last Thinning:     {:>10}%\n",
             self.context_window.last_thinning_percentage
@@ -2005,6 +3052,18 @@ If you can complete it with 1-2 tool calls, skip TODO.
                 "   • Median Time to First Token: {:>6.3}s\n",
                 median_ttft.as_secs_f64()
             ));
+            // Time to first token is the request's trip to the provider plus the model's
+            // latency
+            let avg_request = self.request_times.iter().sum::<Duration>()
+                / self.request_times.len().max(1) as u32;
+            stats.push_str(&format!(
+                "   • Avg Request Time:           {:>6.3}s  (sending and network)\n",
+                avg_request.as_secs_f64()
+            ));
+            stats.push_str(&format!(
+                "   • Avg Model Latency:          {:>6.3}s\n",
+                avg_ttft.saturating_sub(avg_request).as_secs_f64()
+            ));
         }
         stats.push('\n');
 
@@ -2036,6 +3095,62 @@ If you can complete it with 1-2 tool calls, skip TODO.
         ));
         stats.push('\n');
 
+        // What the context is spent on, counted locally so it adds up message by message
+        let model = self
+            .get_provider_info()
+            .map(|(_, model)| model)
+            .unwrap_or_default();
+        let breakdown = self.context_window.breakdown(&model);
+        stats.push_str("🧮 Context Breakdown:\n");
+        for (label, tokens) in [
+            ("System", breakdown.system),
+            ("User", breakdown.user),
+            ("Assistant", breakdown.assistant),
+        ] {
+            stats.push_str(&format!(
+                "   • {:<19}{:>10} tokens\n",
+                format!("{}:", label),
+                tokens
+            ));
+        }
+        for (source, tokens) in &breakdown.by_source {
+            stats.push_str(&format!(
+                "   • {:<19}{:>9.1}%\n",
+                format!("{}:", source.label()),
+                breakdown.percentage(*tokens)
+            ));
+        }
+        if !breakdown.largest.is_empty() {
+            stats.push_str("   • Largest Messages:\n");
+            for size in &breakdown.largest {
+                stats.push_str(&format!(
+                    "     #{:<4} {:<9} {:>7} {:<14} {}\n",
+                    size.index,
+                    format!("{:?}", size.role).to_lowercase(),
+                    size.tokens,
+                    size.tool.as_deref().unwrap_or("-"),
+                    size.preview
+                ));
+            }
+        }
+        stats.push('\n');
+
+        // Which tools fill the context, over the whole session
+        if !self.tool_context.is_empty() {
+            stats.push_str("📥 Context by Tool:\n");
+            for (tool, contribution) in self.tool_context.leaderboard() {
+                stats.push_str(&format!(
+                    "   • {:<28}{:>9} tokens {:>5.1}%  ({} result{})\n",
+                    tool,
+                    contribution.tokens,
+                    self.tool_context.percentage(contribution.tokens),
+                    contribution.results,
+                    if contribution.results == 1 { "" } else { "s" }
+                ));
+            }
+            stats.push('\n');
+        }
+
         // Tool call metrics
         stats.push_str("🔧 Tool Call Metrics:\n");
         stats.push_str(&format!(
@@ -2075,6 +3190,34 @@ If you can complete it with 1-2 tool calls, skip TODO.
         }
         stats.push('\n');
 
+        // Usage against the active provider's quotas, counted across sessions
+        let provider = &self.config.providers.default_provider;
+        if let Some(quota) = self.config.quotas.providers.get(provider) {
+            let ledger = quota::UsageStore::from_config(&self.config.quotas).load();
+            let today = chrono::Local::now().date_naive();
+            let limit = |limit: Option<u64>| limit.map_or("-".to_string(), |l| l.to_string());
+            stats.push_str("💳 Usage Quotas:\n");
+            for (period, usage, tokens) in [
+                ("Today", ledger.day(provider, today), quota.daily_tokens),
+                ("This Week", ledger.week(provider, today), quota.weekly_tokens),
+            ] {
+                stats.push_str(&format!(
+                    "   • {:<18} {:>10} / {} tokens",
+                    format!("{}:", period),
+                    usage.tokens,
+                    limit(tokens)
+                ));
+                if usage.cost > 0.0 {
+                    stats.push_str(&format!(" ({:.2})", usage.cost));
+                }
+                stats.push('\n');
+            }
+            if let Some(exceeded) = quota::check(quota, provider, &ledger, today) {
+                stats.push_str(&format!("   ⚠️ {}\n", exceeded));
+            }
+            stats.push('\n');
+        }
+
         // Provider info
         stats.push_str("🔌 Provider:\n");
         if let Ok((provider, model)) = self.get_provider_info() {
@@ -2092,6 +3235,11 @@ If you can complete it with 1-2 tool calls, skip TODO.
         &self.tool_call_metrics
     }
 
+    /// Per-turn usage for every task executed by this agent, including failed ones
+    pub fn get_turn_metrics(&self) -> &[TurnMetrics] {
+        &self.turn_metrics
+    }
+
     pub fn get_config(&self) -> &Config {
         &self.config
     }
@@ -2105,570 +3253,204 @@ If you can complete it with 1-2 tool calls, skip TODO.
             .await
     }
 
-    /// Create tool definitions for native tool calling providers
-    fn create_tool_definitions(
-        enable_webdriver: bool,
-        enable_macax: bool,
-        enable_computer_control: bool,
-    ) -> Vec<Tool> {
-        let mut tools = vec![
-            Tool {
-                name: "shell".to_string(),
-                description: "Execute shell commands".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "command": {
-                            "type": "string",
-                            "description": "The shell command to execute"
-                        }
-                    },
-                    "required": ["command"]
-                }),
-            },
-            Tool {
-                name: "read_file".to_string(),
-                description: "Read the contents of a file. For image files (png, jpg, jpeg, gif, bmp, tiff, webp), automatically extracts text using OCR. For text files, optionally read a specific character range.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "file_path": {
-                            "type": "string",
-                            "description": "The path to the file to read"
-                        },
-                        "start": {
-                            "type": "integer",
-                            "description": "Starting character position (0-indexed, inclusive). If omitted, reads from beginning."
-                        },
-                        "end": {
-                            "type": "integer",
-                            "description": "Ending character position (0-indexed, EXCLUSIVE). If omitted, reads to end of file."
-                        }
-                    },
-                    "required": ["file_path"]
-                }),
-            },
-            Tool {
-                name: "write_file".to_string(),
-                description: "Write content to a file (creates or overwrites). You MUST provide all arguments".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "file_path": {
-                            "type": "string",
-                            "description": "The path to the file to write"
-                        },
-                        "content": {
-                            "type": "string",
-                            "description": "The content to write to the file"
-                        }
-                    },
-                    "required": ["file_path", "content"]
-                }),
-            },
-            Tool {
-                name: "str_replace".to_string(),
-                description: "Apply a unified diff to a file. Supports multiple hunks and context lines. Optionally constrain the search to a [start, end) character range (0-indexed; end is EXCLUSIVE). Useful to disambiguate matches or limit scope in large files.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "file_path": {
-                            "type": "string",
-                            "description": "The path to the file to edit"
-                        },
-                        "diff": {
-                            "type": "string",
-                            "description": "A unified diff showing what to replace. Supports @@ hunk headers, context lines, and multiple hunks (---/+++ headers optional for minimal diffs)."
-                        },
-                        "start": {
-                            "type": "integer",
-                            "description": "Starting character position in the file (0-indexed, inclusive). If omitted, searches from beginning."
-                        },
-                        "end": {
-                            "type": "integer",
-                            "description": "Ending character position in the file (0-indexed, EXCLUSIVE - character at this position is NOT included). If omitted, searches to end of file."
-                        }
-                    },
-                    "required": ["file_path", "diff"]
-                }),
-            },
-            Tool {
-                name: "final_output".to_string(),
-                description: "Signal task completion with a detailed summary".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "summary": {
-                            "type": "string",
-                            "description": "A detailed summary in markdown of what was accomplished"
-                        }
-                    },
-                    "required": ["summary"]
-                }),
-            },
-            Tool {
-                name: "take_screenshot".to_string(),
-                description: "Capture a screenshot of a specific application window. You MUST specify the window_id parameter with the application name (e.g., 'Safari', 'Terminal', 'Google Chrome'). The tool will automatically use the native screencapture command with the application's window ID for a clean capture. Use list_windows first to identify available windows.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Filename for the screenshot (e.g., 'safari.png'). If a relative path is provided, the screenshot will be saved to ~/tmp or $TMPDIR. Use an absolute path to save elsewhere."
-                        },
-                        "window_id": {
-                            "type": "string",
-                            "description": "REQUIRED: Application name to capture (e.g., 'Safari', 'Terminal', 'Google Chrome'). The tool will capture the frontmost window of that application using its native window ID."
-                        },
-                        "region": {
-                            "type": "object",
-                            "properties": {
-                                "x": {"type": "integer"},
-                                "y": {"type": "integer"},
-                                "width": {"type": "integer"},
-                                "height": {"type": "integer"}
-                            }
-                        }
-                    },
-                    "required": ["path", "window_id"]
-                }),
-            },
-            Tool {
-                name: "extract_text".to_string(),
-                description: "Extract text from an image file using OCR. For extracting text from a specific window, use vision_find_text instead which automatically handles window capture.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Path to image file (optional if region is provided)"
-                        },
-                    }
-                }),
-            },
-            Tool {
-                name: "todo_read".to_string(),
-                description: "Read your current TODO list from todo.g3.md file in the workspace directory. Shows what tasks are planned and their status. Call this at the start of multi-step tasks to check for existing plans, and during execution to review progress before updating. TODO lists persist across g3 sessions.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }),
-            },
-            Tool {
-                name: "todo_write".to_string(),
-                description: "Create or update your TODO list in todo.g3.md file with a complete task plan. Use markdown checkboxes: - [ ] for pending, - [x] for complete. This tool replaces the entire file content, so always call todo_read first to preserve existing content. Essential for multi-step tasks. Changes persist across g3 sessions.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "content": {
-                            "type": "string",
-                            "description": "The TODO list content to save. Use markdown checkbox format: - [ ] for incomplete tasks, - [x] for completed tasks. Support nested tasks with indentation."
-                        }
-                    },
-                    "required": ["content"]
-                }),
-            },
-        ];
-
-        // Add code_search tool
-        tools.push(Tool {
-            name: "code_search".to_string(),
-            description: "Syntax-aware code search that understands code structure, not just text. Finds actual functions, classes, methods, and other code constructs - ignores matches in comments and strings. Much more accurate than grep for code searches. Supports batch searches (up to 20 parallel) with structured results and context lines. Languages: Rust, Python, JavaScript, TypeScript, Go, Java, C, C++, Kotlin. Uses tree-sitter query syntax.".to_string(),
-            input_schema: json!({
-                "type": "object",
-                "properties": {
-                    "searches": {
-                        "type": "array",
-                        "maxItems": 20,
-                        "items": {
-                            "type": "object",
-                            "properties": {
-                                "name": { "type": "string", "description": "Label for this search." },
-                                "query": { "type": "string", "description": "tree-sitter query in S-expression format (e.g., \"(function_item name: (identifier) @name)\")"},
-                                "language": { "type": "string", "enum": ["rust", "python", "javascript", "typescript", "go", "java", "c", "cpp", "kotlin"], "description": "Programming language to search." },
-                                "paths": { "type": "array", "items": { "type": "string" }, "description": "Paths/dirs to search. Defaults to current dir if empty." },
-                                "context_lines": { "type": "integer", "minimum": 0, "maximum": 20, "default": 0, "description": "Lines of context to include around each match." }
-                            },
-                            "required": ["name", "query", "language"]
-                        }
-                    },
-                    "max_concurrency": { "type": "integer", "minimum": 1, "default": 4 },
-                    "max_matches_per_search": { "type": "integer", "minimum": 1, "default": 500 }
-                },
-                "required": ["searches"]
-            }),
-        });
-
-        // Add WebDriver tools if enabled
-        if enable_webdriver {
-            tools.extend(vec![
-                Tool {
-                    name: "webdriver_start".to_string(),
-                    description: "Start a Safari WebDriver session for browser automation. Must be called before any other webdriver tools. Requires Safari's 'Allow Remote Automation' to be enabled in Develop menu.".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "webdriver_navigate".to_string(),
-                    description: "Navigate to a URL in the browser".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "url": {
-                                "type": "string",
-                                "description": "The URL to navigate to (must include protocol, e.g., https://)"
-                            }
-                        },
-                        "required": ["url"]
-                    }),
-                },
-                Tool {
-                    name: "webdriver_get_url".to_string(),
-                    description: "Get the current URL of the browser".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "webdriver_get_title".to_string(),
-                    description: "Get the title of the current page".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "webdriver_find_element".to_string(),
-                    description: "Find an element on the page by CSS selector and return its text content".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "selector": {
-                                "type": "string",
-                                "description": "CSS selector to find the element (e.g., 'h1', '.class-name', '#id')"
-                            }
-                        },
-                        "required": ["selector"]
-                    }),
-                },
-                Tool {
-                    name: "webdriver_find_elements".to_string(),
-                    description: "Find all elements matching a CSS selector and return their text content".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "selector": {
-                                "type": "string",
-                                "description": "CSS selector to find elements"
-                            }
-                        },
-                        "required": ["selector"]
-                    }),
-                },
-                Tool {
-                    name: "webdriver_click".to_string(),
-                    description: "Click an element on the page".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "selector": {
-                                "type": "string",
-                                "description": "CSS selector for the element to click"
-                            }
-                        },
-                        "required": ["selector"]
-                    }),
-                },
-                Tool {
-                    name: "webdriver_send_keys".to_string(),
-                    description: "Type text into an input element".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "selector": {
-                                "type": "string",
-                                "description": "CSS selector for the input element"
-                            },
-                            "text": {
-                                "type": "string",
-                                "description": "Text to type into the element"
-                            },
-                            "clear_first": {
-                                "type": "boolean",
-                                "description": "Whether to clear the element before typing (default: true)"
-                            }
-                        },
-                        "required": ["selector", "text"]
-                    }),
-                },
-                Tool {
-                    name: "webdriver_execute_script".to_string(),
-                    description: "Execute JavaScript code in the browser and return the result".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "script": {
-                                "type": "string",
-                                "description": "JavaScript code to execute (use 'return' to return a value)"
-                            }
-                        },
-                        "required": ["script"]
-                    }),
-                },
-                Tool {
-                    name: "webdriver_get_page_source".to_string(),
-                    description: "Get the HTML source of the current page".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "webdriver_screenshot".to_string(),
-                    description: "Take a screenshot of the browser window".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "path": {
-                                "type": "string",
-                                "description": "Path where to save the screenshot (e.g., '/tmp/screenshot.png')"
-                            }
-                        },
-                        "required": ["path"]
-                    }),
-                },
-                Tool {
-                    name: "webdriver_back".to_string(),
-                    description: "Navigate back in browser history".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "webdriver_forward".to_string(),
-                    description: "Navigate forward in browser history".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "webdriver_refresh".to_string(),
-                    description: "Refresh the current page".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "webdriver_quit".to_string(),
-                    description: "Close the browser and end the WebDriver session".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-            ]);
-        }
-
-        // Add macOS Accessibility tools if enabled
-        if enable_macax {
-            tools.extend(vec![
-                Tool {
-                    name: "macax_list_apps".to_string(),
-                    description: "List all running applications that can be controlled via macOS Accessibility API".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "macax_get_frontmost_app".to_string(),
-                    description: "Get the name of the currently active (frontmost) application".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    }),
-                },
-                Tool {
-                    name: "macax_activate_app".to_string(),
-                    description: "Bring an application to the front (activate it)".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "app_name": {
-                                "type": "string",
-                                "description": "Name of the application to activate (e.g., 'Safari', 'TextEdit')"
-                            }
-                        },
-                        "required": ["app_name"]
-                    }),
-                },
-                Tool {
-                    name: "macax_press_key".to_string(),
-                    description: "Press a keyboard key or shortcut in an application (e.g., Cmd+S to save)".to_string(),
-                    input_schema: json!({
-                        "type": "object",
-                        "properties": {
-                            "app_name": {
-                                "type": "string",
-                                "description": "Name of the application"
-                            },
-                            "key": {
-                                "type": "string",
-                                "description": "Key to press (e.g., 's', 'return', 'tab')"
-                            },
-                            "modifiers": {
-                                "type": "array",
-                                "items": {
-                                    "type": "string"
-                                },
-                                "description": "Modifier keys (e.g., ['command', 'shift'])"
-                            }
-                        },
-                        "required": ["app_name", "key"]
-                    }),
-                },
-            ]);
-
-            // Add type_text tool for typing arbitrary text
-            tools.push(Tool {
-                name: "macax_type_text".to_string(),
-                description: "Type arbitrary text into the currently focused element in an application (supports unicode, emojis, etc.)".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "app_name": {
-                            "type": "string",
-                            "description": "Name of the application"
-                        },
-                        "text": {
-                            "type": "string",
-                            "description": "Text to type (can include unicode, emojis, special characters)"
-                        }
-                    },
-                    "required": ["app_name", "text"]
-                }),
-            });
+    /// Specs of the tools this agent offers: the groups enabled in the config, minus
+    /// mutating tools in read-only mode and tools outside the active profile's allowlist
+    fn tool_specs(&self) -> Vec<&'static ToolSpec> {
+        let mut groups = vec![ToolGroup::Core];
+        if self.config.webdriver.enabled {
+            groups.push(ToolGroup::WebDriver);
         }
-
-        // Add extract_text_with_boxes tool (requires macax flag)
-        if enable_macax {
-            tools.push(Tool {
-                name: "extract_text_with_boxes".to_string(),
-                description: "Extract all text from an image file with bounding box coordinates for each text element. Returns JSON array with text, position (x, y), size (width, height), and confidence for each detected text. Uses Apple Vision Framework for precise sub-pixel accuracy.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Path to image file to extract text from"
-                        },
-                        "app_name": {
-                            "type": "string",
-                            "description": "Optional: Name of application to screenshot first (e.g., 'Safari', 'Things3'). If provided, takes screenshot of app before extracting text."
-                        }
-                    },
-                    "required": ["path"]
-                }),
-            });
+        if self.config.macax.enabled {
+            groups.push(ToolGroup::MacAx);
+        }
+        if self.config.computer_control.enabled {
+            groups.push(ToolGroup::ComputerControl);
+        }
+        if self.is_coach {
+            groups.push(ToolGroup::Coach);
         }
 
-        // Add vision-guided tools (requires computer control)
-        if enable_computer_control {
-            // Add vision-guided tools
-            tools.push(Tool {
-                name: "vision_find_text".to_string(),
-                description: "Find text in a specific application window and return its location with bounding box coordinates (x, y, width, height) and confidence score. Useful for locating UI elements. Uses Apple Vision Framework for precise sub-pixel accuracy.".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "app_name": {
-                            "type": "string",
-                            "description": "Name of the application to search in (e.g., 'Things3', 'Safari', 'TextEdit')"
-                        },
-                        "text": {
-                            "type": "string",
-                            "description": "The text to search for on screen"
-                        }
-                    },
-                    "required": ["app_name", "text"]
-                }),
-            });
-
-            tools.push(Tool {
-                name: "vision_click_text".to_string(),
-                description: "Find text in a specific application window and click on it (useful for clicking buttons, links, menu items)".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "app_name": {
-                            "type": "string",
-                            "description": "Name of the application (e.g., 'Things3', 'Safari', 'TextEdit')"
-                        },
-                        "text": {
-                            "type": "string",
-                            "description": "The text to click on (e.g., 'Submit', 'OK', 'Cancel', '+')"
-                        }
-                    },
-                    "required": ["app_name", "text"]
-                }),
-            });
+        let profile = self.profile();
+        ToolRegistry::builtin()
+            .enabled(&groups)
+            .into_iter()
+            .filter(|spec| !(self.config.agent.read_only && spec.capabilities.mutating))
+            .filter(|spec| {
+                profile.is_none_or(|(_, profile)| profile::is_tool_allowed(profile, spec.name))
+            })
+            .filter(|spec| {
+                let allowed = self.allowed_tools.as_ref();
+                allowed.is_none_or(|tools| tools.iter().any(|tool| tool == spec.name))
+            })
+            .collect()
+    }
 
-            tools.push(Tool {
-                name: "vision_click_near_text".to_string(),
-                description: "Find text in a specific application window and click near it (useful for clicking text fields next to labels)".to_string(),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "app_name": {
-                            "type": "string",
-                            "description": "Name of the application (e.g., 'Things3', 'Safari', 'TextEdit')"
-                        },
-                        "text": {
-                            "type": "string",
-                            "description": "The label text to find (e.g., 'Name:', 'Email:', 'Task:')"
-                        },
-                        "direction": {
-                            "type": "string",
-                            "enum": ["right", "below", "left", "above"],
-                            "description": "Direction to click relative to the text (default: right)"
-                        },
-                        "distance": {
-                            "type": "integer",
-                            "description": "Distance in pixels from the text (default: 50)"
-                        }
-                    },
-                    "required": ["app_name", "text"]
-                }),
-            });
+    /// Tool definitions for native tool calling providers
+    fn tool_definitions(&self) -> Vec<Tool> {
+        let mut tools: Vec<Tool> = self.tool_specs().into_iter().map(ToolSpec::tool).collect();
+        if self.config.embeddings.is_hashed() {
+            let semantic = tools.iter_mut().find(|tool| tool.name == "code_search_semantic");
+            if let Some(tool) = semantic {
+                tool.description = tool_registry::HASHED_SEMANTIC_SEARCH_DESCRIPTION.to_string();
+            }
         }
-
+        tools.extend(self.unity_catalog_tools());
+        tools.extend(self.plugin_tools());
+        tools.extend(self.custom_tool_definitions());
         tools
     }
 
-    /// Helper method to stream with retry logic
-    async fn stream_with_retry(
-        &self,
-        request: &CompletionRequest,
+    /// Tools to send with a request: their definitions for native tool calling, or only their
+    /// names and input schemas for a provider that constrains its JSON tool calls to them
+    fn request_tools(&self, native: bool, capabilities: &Capabilities) -> Option<Vec<Tool>> {
+        if native {
+            Some(self.tool_definitions())
+        } else if capabilities.json_mode {
+            let tools = self.tool_definitions().into_iter();
+            Some(tools.map(g3_providers::tool_grammar::grammar_tool).collect())
+        } else {
+            None
+        }
+    }
+
+    /// Tools of command plugins, within read-only mode and the active profile's allowlist
+    fn plugin_tools(&self) -> Vec<Tool> {
+        let profile = self.profile();
+        self.plugins
+            .tools(self.config.agent.read_only)
+            .into_iter()
+            .filter(|tool| {
+                profile.is_none_or(|(_, profile)| profile::is_tool_allowed(profile, &tool.name))
+            })
+            .collect()
+    }
+
+    /// Tools registered by an embedding program, within read-only mode and the active
+    /// profile's allowlist
+    fn custom_tool_definitions(&self) -> Vec<Tool> {
+        let profile = self.profile();
+        self.custom_tools
+            .tools(self.config.agent.read_only)
+            .into_iter()
+            .filter(|tool| {
+                profile.is_none_or(|(_, profile)| profile::is_tool_allowed(profile, &tool.name))
+            })
+            .collect()
+    }
+
+    /// Unity Catalog functions offered as tools, within the active profile's allowlist
+    fn unity_catalog_tools(&self) -> Vec<Tool> {
+        let Some(uc) = &self.unity_catalog else {
+            return Vec::new();
+        };
+        let profile = self.profile();
+        uc.tools()
+            .into_iter()
+            .filter(|tool| {
+                profile.is_none_or(|(_, profile)| profile::is_tool_allowed(profile, &tool.name))
+            })
+            .collect()
+    }
+
+    /// `max_tokens` for a request of `phase` to `model` of `provider`, from the active
+    /// profile's and `[output_tokens]` limits or else [`output_tokens::max_tokens`]
+    fn output_tokens(&self, capabilities: &Capabilities, phase: output_tokens::Phase) -> u32 {
+        let config = match self.profile() {
+            Some((_, profile)) => profile.output_tokens.or(&self.config.output_tokens),
+            None => self.config.output_tokens.clone(),
+        };
+        let model_limit = capabilities
+            .max_output_tokens
+            .unwrap_or(output_tokens::DEFAULT_OUTPUT_LIMIT);
+        let task = self
+            .context_window
+            .conversation_history
+            .iter()
+            .rev()
+            .find_map(|m| match m.role {
+                MessageRole::User => m.content.strip_prefix("Task: "),
+                _ => None,
+            })
+            .unwrap_or("");
+
+        let max_tokens = output_tokens::max_tokens(
+            &config,
+            phase,
+            task,
+            model_limit,
+            self.context_window.remaining_tokens(),
+        );
+        debug!("Using max_tokens={} for {:?} request", max_tokens, phase);
+        max_tokens
+    }
+
+    /// Check `request` against the model's context window with a real tokenizer before it
+    /// is sent. An oversized request has the context shrunk first; if it still doesn't fit it
+    /// fails with [`G3Error::ContextOverflow`] instead of being sent. The response's
+    /// `max_tokens` is capped to whatever room is left.
+    fn ensure_request_fits(&mut self, request: &mut CompletionRequest, model: &str) -> Result<()> {
+        let limit = self.context_window.total_tokens;
+        let tool_tokens = tokenizer::count_tool_tokens(model, request.tools.as_deref());
+        let reserve = request.max_tokens.unwrap_or(0).min(MIN_RESPONSE_TOKENS);
+        let mut message_tokens: u32 = request
+            .messages
+            .iter()
+            .map(|m| tokenizer::count_message_tokens(model, m))
+            .sum();
+
+        if tool_tokens + message_tokens + reserve > limit {
+            let budget = limit.saturating_sub(tool_tokens + reserve);
+            warn!(
+                "Request is {} tokens ({} messages + {} tools), over the {} token context window",
+                tool_tokens + message_tokens,
+                message_tokens,
+                tool_tokens,
+                limit
+            );
+            self.events.publish(events::AgentEvent::Status {
+                message: format!(
+                    "\n⚠️ Request is ~{} tokens, over the {} token context window. Shrinking context before sending...",
+                    tool_tokens + message_tokens,
+                    limit
+                ),
+            });
+
+            let outcome = self.context_window.shrink_to_fit(model, budget);
+            request.messages = self.context_window.conversation_history.clone();
+            if outcome.tokens > budget {
+                return Err(G3Error::ContextOverflow(format!(
+                    "Request is {} tokens even after shrinking the context, over the {} token context window. Start a new session or use a model with a larger context.",
+                    tool_tokens + outcome.tokens,
+                    limit
+                ))
+                .into());
+            }
+            self.events.publish(events::AgentEvent::Status {
+                message: format!(
+                    "✅ Moved {} tool results to files and dropped {} old messages. Continuing...\n",
+                    outcome.tool_results, outcome.messages_dropped
+                ),
+            });
+            message_tokens = outcome.tokens;
+        }
+
+        let available = limit.saturating_sub(tool_tokens + message_tokens);
+        if request.max_tokens.is_some_and(|max| max > available) {
+            debug!("Capping max_tokens to the {} tokens left in the context window", available);
+            request.max_tokens = Some(available);
+        }
+        Ok(())
+    }
+
+    /// Helper method to stream with retry logic. Also returns how long the successful
+    /// attempt took to send the request and get the provider's response, which is network
+    /// time rather than the model's.
+    async fn stream_with_retry(
+        &self,
+        request: &CompletionRequest,
         error_context: &error_handling::ErrorContext,
-    ) -> Result<g3_providers::CompletionStream> {
-        use crate::error_handling::{calculate_retry_delay, classify_error, ErrorType};
+    ) -> Result<(g3_providers::CompletionStream, Duration)> {
+        use crate::error_handling::{classify_error, retry_delay, ErrorType};
 
         let mut attempt = 0;
         let max_attempts = if self.is_autonomous {
@@ -2681,6 +3463,7 @@ If you can complete it with 1-2 tool calls, skip TODO.
             attempt += 1;
             let provider = self.providers.get(None)?;
 
+            let sent = Instant::now();
             match provider.stream(request.clone()).await {
                 Ok(stream) => {
                     if attempt > 1 {
@@ -2693,11 +3476,11 @@ If you can complete it with 1-2 tool calls, skip TODO.
                         request.tools.is_some(),
                         request.max_tokens
                     );
-                    return Ok(stream);
+                    return Ok((stream, sent.elapsed()));
                 }
                 Err(e) if attempt < max_attempts => {
                     if matches!(classify_error(&e), ErrorType::Recoverable(_)) {
-                        let delay = calculate_retry_delay(attempt, self.is_autonomous);
+                        let delay = retry_delay(&e, attempt, self.is_autonomous);
                         warn!(
                             "Recoverable error on attempt {}/{}: {}. Retrying in {:?}...",
                             attempt, max_attempts, e, delay
@@ -2732,146 +3515,71 @@ If you can complete it with 1-2 tool calls, skip TODO.
         let mut iteration_count = 0;
         const MAX_ITERATIONS: usize = 400; // Prevent infinite loops
         let mut response_started = false;
+        let stall_timeout = Duration::from_secs(self.config.agent.stream_stall_timeout_seconds);
+        let max_stall_retries = if self.is_autonomous {
+            self.config.agent.autonomous_max_retry_attempts
+        } else {
+            self.config.agent.max_retry_attempts
+        };
+        let mut interruptions = 0;
+        let mut continuations = 0;
+        let turn_timeout = (self.config.agent.turn_timeout_seconds > 0)
+            .then(|| Duration::from_secs(self.config.agent.turn_timeout_seconds));
+        // First iteration after the model was told to wrap up
+        let mut wrap_up_iteration: Option<usize> = None;
+        let past_deadline = |told: bool| {
+            !told && turn_timeout.is_some_and(|limit| stream_start.elapsed() >= limit)
+        };
+        request.tool_choice = std::mem::take(&mut self.next_tool_choice);
 
         // Check if we need to summarize before starting
         if self.context_window.should_summarize() {
             // First try thinning if we are at capacity, don't call the LLM for a summary (might fail)
             if self.context_window.percentage_used() > 90.0 && self.context_window.should_thin() {
-                self.ui_writer.print_context_status(&format!(
-                    "\n🥒 Context window at {}%. Trying thinning first...",
-                    self.context_window.percentage_used() as u32
-                ));
+                self.events.publish(events::AgentEvent::Status {
+                    message: format!(
+                        "\n🥒 Context window at {}%. Trying thinning first...",
+                        self.context_window.percentage_used() as u32
+                    ),
+                });
                 
-                let (thin_summary, chars_saved) = self.context_window.thin_context();
-                self.thinning_events.push(chars_saved);
-                self.ui_writer.print_context_thinning(&thin_summary);
+                let thin_summary = self.thin_context().await;
+                self.events.publish(events::AgentEvent::ContextThinned {
+                    summary: thin_summary,
+                });
                 
                 // Check if thinning was sufficient
                 if !self.context_window.should_summarize() {
-                    self.ui_writer.print_context_status("✅ Thinning resolved capacity issue. Continuing...\n");
+                    self.events.publish(events::AgentEvent::Status {
+                        message: "✅ Thinning resolved capacity issue. Continuing...\n".to_string(),
+                    });
                     // Continue with the original request without summarization
                 } else {
-                    self.ui_writer.print_context_status("⚠️ Thinning insufficient. Proceeding with summarization...\n");
+                    self.events.publish(events::AgentEvent::Status {
+                        message: "⚠️ Thinning insufficient. Proceeding with summarization...\n".to_string(),
+                    });
                 }
             }
             
             // Only proceed with summarization if still needed after thinning
             if self.context_window.should_summarize() {
             // Notify user about summarization
-            self.ui_writer.print_context_status(&format!(
-                "\n🗜️ Context window reaching capacity ({}%). Creating summary...",
-                self.context_window.percentage_used() as u32
-            ));
-
-            // Create summary request with FULL history
-            let summary_prompt = self.context_window.create_summary_prompt();
-
-            // Get the full conversation history
-            let conversation_text = self
-                .context_window
-                .conversation_history
-                .iter()
-                .map(|m| format!("{:?}: {}", m.role, m.content))
-                .collect::<Vec<_>>()
-                .join("\n\n");
-
-            let summary_messages = vec![
-                Message {
-                    role: MessageRole::System,
-                    content: "You are a helpful assistant that creates concise summaries."
-                        .to_string(),
-                },
-                Message {
-                    role: MessageRole::User,
-                    content: format!(
-                        "Based on this conversation history, {}\n\nConversation:\n{}",
-                        summary_prompt, conversation_text
-                    ),
-                },
-            ];
-
-            let provider = self.providers.get(None)?;
-
-            // Dynamically calculate max_tokens for summary based on what's left
-            // We need to ensure: used_tokens + max_tokens <= total_context_limit
-            let summary_max_tokens = match provider.name() {
-                "databricks" | "anthropic" => {
-                    // Use the actual configured context window size
-                    let model_limit = self.context_window.total_tokens;
-                    let current_usage = self.context_window.used_tokens;
-                    
-                    // Check if we have enough capacity for summarization
-                    if current_usage >= model_limit.saturating_sub(1000) {
-                        error!("Context window at capacity ({}%), cannot summarize. Current: {}, Limit: {}", 
-                               self.context_window.percentage_used(), current_usage, model_limit);
-                        return Err(anyhow::anyhow!("Context window at capacity. Try using /thinnify or /compact commands to reduce context size, or start a new session."));
-                    }
-                    
-                    // Leave buffer proportional to model size (min 1k, max 10k)
-                    let buffer = (model_limit / 40).clamp(1000, 10000); // 2.5% buffer
-                    let available = model_limit
-                        .saturating_sub(current_usage)
-                        .saturating_sub(buffer);
-                    // Cap at a reasonable summary size (10k tokens max)
-                    Some(available.min(10_000))
-                }
-                "embedded" => {
-                    // For smaller context models, be more conservative
-                    let model_limit = self.context_window.total_tokens;
-                    let current_usage = self.context_window.used_tokens;
-                    
-                    // Check capacity for embedded models too
-                    if current_usage >= model_limit.saturating_sub(500) {
-                        error!("Embedded model context window at capacity ({}%)", self.context_window.percentage_used());
-                        return Err(anyhow::anyhow!("Context window at capacity. Try using /thinnify command to reduce context size, or start a new session."));
-                    }
-                    
-                    // Leave 1k buffer
-                    let available = model_limit
-                        .saturating_sub(current_usage)
-                        .saturating_sub(1000);
-                    // Cap at 3k for embedded models
-                    Some(available.min(3000))
-                }
-                _ => {
-                    // Default: conservative approach
-                    let model_limit = self.context_window.total_tokens;
-                    let current_usage = self.context_window.used_tokens;
-                    
-                    if current_usage >= model_limit.saturating_sub(1000) {
-                        error!("Context window at capacity ({}%)", self.context_window.percentage_used());
-                        return Err(anyhow::anyhow!("Context window at capacity. Try using /thinnify or /compact commands, or start a new session."));
-                    }
-                    
-                    let available = self.context_window.remaining_tokens().saturating_sub(2000);
-                    Some(available.min(5000))
-                }
-            };
-
-            debug!(
-                "Requesting summary with max_tokens: {:?} (current usage: {} tokens)",
-                summary_max_tokens, self.context_window.used_tokens
-            );
-            
-            // Final safety check
-            if summary_max_tokens.unwrap_or(0) == 0 {
-                error!("No tokens available for summarization");
-                return Err(anyhow::anyhow!("No context window capacity left for summarization. Use /thinnify to reduce context size or start a new session."));
-            }
+            self.events.publish(events::AgentEvent::Status {
+                message: format!(
+                    "\n🗜️ Context window reaching capacity ({}%). Creating summary...",
+                    self.context_window.percentage_used() as u32
+                ),
+            });
 
-            let summary_request = CompletionRequest {
-                messages: summary_messages,
-                max_tokens: summary_max_tokens,
-                temperature: Some(0.3), // Lower temperature for factual summary
-                stream: false,
-                tools: None,
-            };
+            let (provider, mut summary_request) = self.summary_request().await?;
+            self.moderate_request(&mut summary_request).await?;
 
             // Get the summary
             match provider.complete(summary_request).await {
                 Ok(summary_response) => {
-                    self.ui_writer
-                        .print_context_status("✅ Context compacted successfully. Continuing...\n");
+                    self.events.publish(events::AgentEvent::ContextCompacted {
+                        message: "✅ Context compacted successfully. Continuing...\n".to_string(),
+                    });
 
                     // Extract the latest user message from the request
                     let latest_user_msg = request
@@ -2892,7 +3600,9 @@ If you can complete it with 1-2 tool calls, skip TODO.
                 }
                 Err(e) => {
                     error!("Failed to create summary: {}", e);
-                    self.ui_writer.print_context_status("⚠️ Unable to create summary. Consider starting a new session if you continue to see errors.\n");
+                    self.events.publish(events::AgentEvent::Status {
+                        message: "⚠️ Unable to create summary. Consider starting a new session if you continue to see errors.\n".to_string(),
+                    });
                     // Don't continue with the original request if summarization failed
                     // as we're likely at token limit
                     return Err(anyhow::anyhow!("Context window at capacity and summarization failed. Please start a new session."));
@@ -2908,6 +3618,23 @@ If you can complete it with 1-2 tool calls, skip TODO.
                 warn!("Maximum iterations reached, stopping stream");
                 break;
             }
+            let wrap_up_over = wrap_up_iteration
+                .is_some_and(|start| iteration_count >= start + WRAP_UP_ITERATIONS);
+            if wrap_up_over {
+                warn!("Turn kept going after being told to wrap up, stopping stream");
+                self.events.publish(events::AgentEvent::Status {
+                    message: "\n⏰ The turn did not wrap up after its time limit and was stopped.\n"
+                        .to_string(),
+                });
+                break;
+            }
+            // Past the turn's time limit, the model is told to wrap up before it goes on
+            let timed_out = turn_timeout.filter(|_| past_deadline(wrap_up_iteration.is_some()));
+            if let Some(limit) = timed_out {
+                wrap_up_iteration = Some(iteration_count);
+                self.request_wrap_up(limit);
+                request.messages = self.context_window.conversation_history.clone();
+            }
 
             // Add a small delay between iterations to prevent "model busy" errors
             if iteration_count > 1 {
@@ -2916,6 +3643,11 @@ If you can complete it with 1-2 tool calls, skip TODO.
 
             let provider = self.providers.get(None)?;
             debug!("Got provider: {}", provider.name());
+            // Copy what we need so self can be borrowed mutably while streaming (e.g. for thinning)
+            let provider_name = provider.name().to_string();
+            let provider_model = provider.model().to_string();
+            let has_native_tool_calling = provider.has_native_tool_calling();
+            let capabilities = provider.capabilities();
 
             // Create error context for detailed logging
             let last_prompt = request
@@ -2928,8 +3660,8 @@ If you can complete it with 1-2 tool calls, skip TODO.
 
             let error_context = ErrorContext::new(
                 "stream_completion".to_string(),
-                provider.name().to_string(),
-                provider.model().to_string(),
+                provider_name.clone(),
+                provider_model.clone(),
                 last_prompt,
                 self.session_id.clone(),
                 self.context_window.used_tokens,
@@ -2942,20 +3674,29 @@ If you can complete it with 1-2 tool calls, skip TODO.
 
             // Log initial request details
             debug!("Starting stream with provider={}, model={}, messages={}, tools={}, max_tokens={:?}",
-                provider.name(),
-                provider.model(),
+                provider_name,
+                provider_model,
                 request.messages.len(),
                 request.tools.is_some(),
                 request.max_tokens
             );
 
+            self.ensure_request_fits(&mut request, &provider_model)?;
+            self.moderate_request(&mut request).await?;
+
             // Try to get stream with retry logic
-            let mut stream = match self.stream_with_retry(&request, &error_context).await {
+            let mut rate = stream_rate::StreamRate::new(Instant::now());
+            let (mut stream, request_time) = match self
+                .stream_with_retry(&request, &error_context)
+                .await
+            {
                 Ok(s) => s,
                 Err(e) => {
                     error!("Failed to start stream: {}", e);
                     // Additional retry for "busy" errors on subsequent iterations
-                    if iteration_count > 1 && e.to_string().contains("busy") {
+                    if iteration_count > 1
+                        && matches!(G3Error::from_error(&e), Some(G3Error::ModelBusy(_)))
+                    {
                         warn!(
                             "Model busy on iteration {}, attempting one more retry in 500ms",
                             iteration_count
@@ -2977,18 +3718,75 @@ If you can complete it with 1-2 tool calls, skip TODO.
             };
 
             let mut parser = StreamingToolParser::new();
+            // Partial files of streaming write_file calls, removed when the stream ends
+            let mut partial_writes = partial_tool_call::PartialWrites::default();
+            // The summary of a streaming final_output call, shown as it arrives
+            let mut partial_summary = partial_tool_call::PartialSummary::default();
+            // Chunks of this response, saved as an artifact when the stream ends
+            let mut recorder = self.config.agent.record_streams.then(|| {
+                stream_recording::StreamRecorder::new(
+                    &provider_name,
+                    &provider_model,
+                    self.artifacts().clone(),
+                )
+            });
             let mut current_response = String::new();
             let mut tool_executed = false;
+            let mut continuing = false;
             let mut chunks_received = 0;
             let mut raw_chunks: Vec<String> = Vec::new(); // Store raw chunks for debugging
             let mut _last_error: Option<String> = None;
             let mut accumulated_usage: Option<g3_providers::Usage> = None;
-
-            while let Some(chunk_result) = stream.next().await {
+            let mut stalled = false;
+            // The turn ran past its time limit while this response was streaming
+            let mut out_of_time = false;
+            // A connection or server error that ended the stream before the response did
+            let mut interrupted: Option<anyhow::Error> = None;
+
+            // The stall watchdog counts from when the stream opened
+            let opened = Instant::now();
+            loop {
+                if past_deadline(wrap_up_iteration.is_some()) {
+                    out_of_time = true;
+                    break;
+                }
+                // Watchdog: a hung connection would otherwise block this turn forever.
+                // While waiting, the speed indicator keeps counting.
+                let next = loop {
+                    match tokio::time::timeout(stream_rate::PROGRESS_INTERVAL, stream.next()).await
+                    {
+                        Ok(next) => break Ok(next),
+                        Err(_) if past_deadline(wrap_up_iteration.is_some()) => {
+                            out_of_time = true;
+                            break Ok(None);
+                        }
+                        Err(_)
+                            if !stall_timeout.is_zero()
+                                && rate.idle(Instant::now()).min(opened.elapsed())
+                                    >= stall_timeout =>
+                        {
+                            break Err(())
+                        }
+                        Err(_) => self.publish_stream_progress(&mut rate),
+                    }
+                };
+                let chunk_result = match next {
+                    Ok(Some(chunk_result)) => chunk_result,
+                    Ok(None) => break,
+                    Err(_) => {
+                        stalled = true;
+                        break;
+                    }
+                };
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(&chunk_result);
+                }
                 match chunk_result {
                     Ok(chunk) => {
                         // Notify UI about SSE received (including pings)
-                        self.ui_writer.notify_sse_received();
+                        self.events.publish(events::AgentEvent::StreamActivity);
+                        rate.record(Instant::now(), &chunk);
+                        self.publish_stream_progress(&mut rate);
 
                         // Capture usage data if available
                         if let Some(ref usage) = chunk.usage {
@@ -2999,6 +3797,11 @@ If you can complete it with 1-2 tool calls, skip TODO.
                             );
                         }
 
+                        if let Some(text) = chunk.reasoning.clone() {
+                            self.events
+                                .publish(events::AgentEvent::ReasoningDelta { text });
+                        }
+
                         // Store raw chunk for debugging (limit to first 20 and last 5)
                         if chunks_received < 20 || chunk.finished {
                             raw_chunks.push(format!(
@@ -3018,6 +3821,7 @@ If you can complete it with 1-2 tool calls, skip TODO.
                             // Record in agent metrics
                             if let Some(ttft) = first_token_time {
                                 self.first_token_times.push(ttft);
+                                self.request_times.push(request_time);
                             }
                         }
 
@@ -3033,6 +3837,18 @@ If you can complete it with 1-2 tool calls, skip TODO.
                         // Process chunk with the new parser
                         let completed_tools = parser.process_chunk(&chunk);
 
+                        if chunk.tool_call_deltas.is_some() && self.progressive_writes() {
+                            partial_writes.update(parser.partial_tool_calls(), |call| {
+                                self.partial_write_refusal(call)
+                            });
+                        }
+                        if chunk.tool_call_deltas.is_some() {
+                            if let Some(text) = partial_summary.update(parser.partial_tool_calls())
+                            {
+                                self.events.publish(events::AgentEvent::SummaryDelta { text });
+                            }
+                        }
+
                         // Handle completed tool calls
                         if let Some(tool_call) = completed_tools.into_iter().next() {
                             debug!("Processing completed tool call: {:?}", tool_call);
@@ -3047,11 +3863,11 @@ If you can complete it with 1-2 tool calls, skip TODO.
                             
                             // Check if we should thin the context BEFORE executing the tool
                             if self.context_window.should_thin() {
-                                let (thin_summary, chars_saved) =
-                                    self.context_window.thin_context();
-                                self.thinning_events.push(chars_saved);
+                                let thin_summary = self.thin_context().await;
                                 // Print the thinning summary to the user
-                                self.ui_writer.print_context_thinning(&thin_summary);
+                                self.events.publish(events::AgentEvent::ContextThinned {
+                                    summary: thin_summary,
+                                });
                             }
 
 
@@ -3062,19 +3878,12 @@ If you can complete it with 1-2 tool calls, skip TODO.
                             // Get the text content accumulated so far
                             let text_content = parser.get_text_content();
 
-                            // Clean the content
-                            let clean_content = text_content
-                                .replace("<|im_end|>", "")
-                                .replace("</s>", "")
-                                .replace("[/INST]", "")
-                                .replace("<</SYS>>", "");
-
                             // Store the raw content BEFORE filtering for the context window log
-                            let raw_content_for_log = clean_content.clone();
+                            let raw_content_for_log = text_content.to_string();
 
                             // Filter out JSON tool calls from the display
                             let filtered_content =
-                                fixed_filter_json::fixed_filter_json_tool_calls(&clean_content);
+                                fixed_filter_json::fixed_filter_json_tool_calls(text_content);
                             let final_display_content = filtered_content.trim();
 
                             // Display any new content before tool execution
@@ -3097,56 +3906,32 @@ If you can complete it with 1-2 tool calls, skip TODO.
                             if !new_content.trim().is_empty() && tool_call.tool != "final_output" {
                                 #[allow(unused_assignments)]
                                 if !response_started {
-                                    self.ui_writer.print_agent_prompt();
+                                    self.events.publish(events::AgentEvent::ResponseStarted);
                                     response_started = true;
                                 }
-                                self.ui_writer.print_agent_response(&new_content);
-                                self.ui_writer.flush();
+                                self.events.publish(events::AgentEvent::TextDelta {
+                                    text: new_content.clone(),
+                                });
                                 // Update current_response to track what we've displayed
                                 current_response.push_str(&new_content);
                             }
 
-                            // Execute the tool with formatted output
-                            self.ui_writer.println(""); // New line before tool execution
-
-                            // Skip printing tool call details for final_output
-                            if tool_call.tool != "final_output" {
-                                // Tool call header
-                                self.ui_writer.print_tool_header(&tool_call.tool);
-                                if let Some(args_obj) = tool_call.args.as_object() {
-                                    for (key, value) in args_obj {
-                                        let value_str = match value {
-                                            serde_json::Value::String(s) => {
-                                                if tool_call.tool == "shell" && key == "command" {
-                                                    if let Some(first_line) = s.lines().next() {
-                                                        if s.lines().count() > 1 {
-                                                            format!("{}...", first_line)
-                                                        } else {
-                                                            first_line.to_string()
-                                                        }
-                                                    } else {
-                                                        s.clone()
-                                                    }
-                                                } else if s.len() > 100 {
-                                                    // Use char_indices to respect UTF-8 boundaries
-                                                    let truncated = s
-                                                        .char_indices()
-                                                        .take(100)
-                                                        .map(|(_, c)| c)
-                                                        .collect::<String>();
-                                                    format!("{}...", truncated)
-                                                } else {
-                                                    s.clone()
-                                                }
-                                            }
-                                            _ => value.to_string(),
-                                        };
-                                        self.ui_writer.print_tool_arg(key, &value_str);
-                                    }
-                                }
-                                self.ui_writer.print_tool_output_header();
+                            // The rest of a summary shown while it streamed
+                            let summary_rest = match tool_call.tool.as_str() {
+                                "final_output" => partial_summary.finish(&tool_call),
+                                _ => None,
+                            };
+                            let streamed = summary_rest.is_some();
+                            if let Some(text) = summary_rest.filter(|text| !text.is_empty()) {
+                                self.events.publish(events::AgentEvent::SummaryDelta { text });
                             }
 
+                            // Execute the tool with formatted output
+                            self.events.publish(events::AgentEvent::ToolStarted {
+                                tool: tool_call.tool.clone(),
+                                args: tool_call.args.clone(),
+                            });
+
                             let exec_start = Instant::now();
                             // Add 8-minute timeout for tool execution
                             let tool_result = match tokio::time::timeout(
@@ -3170,78 +3955,27 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                 exec_duration,
                                 tool_success,
                             ));
-
-                            // Display tool execution result with proper indentation
-                            if tool_call.tool == "final_output" {
-                                // For final_output, display the summary without truncation
-                                for line in tool_result.lines() {
-                                    self.ui_writer.update_tool_output_line(line);
-                                }
-                                self.ui_writer.println("");
-                            } else {
-                                let output_lines: Vec<&str> = tool_result.lines().collect();
-
-                                // Check if UI wants full output (machine mode) or truncated (human mode)
-                                let wants_full = self.ui_writer.wants_full_output();
-
-                                // Helper function to safely truncate strings at character boundaries
-                                let truncate_line =
-                                    |line: &str, max_width: usize, truncate: bool| -> String {
-                                        if !truncate {
-                                            // Machine mode - return full line
-                                            line.to_string()
-                                        } else if line.chars().count() <= max_width {
-                                            // Human mode - line fits within limit
-                                            line.to_string()
-                                        } else {
-                                            // Human mode - truncate long line
-                                            let truncated: String = line
-                                                .chars()
-                                                .take(max_width.saturating_sub(3))
-                                                .collect();
-                                            format!("{}...", truncated)
-                                        }
-                                    };
-
-                                const MAX_LINES: usize = 5;
-                                const MAX_LINE_WIDTH: usize = 80;
-                                let output_len = output_lines.len();
-
-                                // For todo tools, show all lines without truncation
-                                let is_todo_tool =
-                                    tool_call.tool == "todo_read" || tool_call.tool == "todo_write";
-                                let max_lines_to_show = if is_todo_tool || wants_full {
-                                    output_len
-                                } else {
-                                    MAX_LINES
-                                };
-
-                                for (idx, line) in output_lines.iter().enumerate() {
-                                    if !is_todo_tool && !wants_full && idx >= max_lines_to_show {
-                                        break;
-                                    }
-                                    // Clip line to max width (but not for todo tools)
-                                    let clipped_line = truncate_line(line, MAX_LINE_WIDTH, !wants_full && !is_todo_tool);
-                                    
-                                    // Use print_tool_output_line for todo tools to get special formatting
-                                    if is_todo_tool {
-                                        self.ui_writer.print_tool_output_line(&clipped_line);
-                                    } else {
-                                        self.ui_writer.update_tool_output_line(&clipped_line);
-                                    }
-                                }
-
-                                if !is_todo_tool && !wants_full && output_len > MAX_LINES {
-                                    self.ui_writer.print_tool_output_summary(output_len);
-                                }
-                            }
-
-                            // Check if this was a final_output tool call
-                            if tool_call.tool == "final_output" {
+                            self.audit_tool_call(&tool_call.tool, tool_success, exec_duration);
+
+                            // final_output, and accepted coach feedback, end the turn
+                            let ends_turn = tool_call.tool == "final_output"
+                                || (tool_call.tool == "give_feedback"
+                                    && self.coach_feedback.read().await.is_some());
+
+                            self.events.publish(events::AgentEvent::ToolFinished {
+                                tool: tool_call.tool.clone(),
+                                result: tool_result.clone(),
+                                success: tool_success,
+                                duration: exec_duration,
+                                ends_turn,
+                                streamed,
+                            });
+
+                            // Check if this was a final_output (or give_feedback) tool call
+                            if ends_turn {
                                 // The summary was displayed above when we printed the tool result
                                 // Add it to full_response so it's included in the TaskResult
                                 full_response.push_str(&tool_result);
-                                self.ui_writer.println("");
                                 let _ttft =
                                     first_token_time.unwrap_or_else(|| stream_start.elapsed());
 
@@ -3250,8 +3984,8 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                     format!(
                                         "{}\n\n🕝 {} | 💭 {}",
                                         full_response,
-                                        Self::format_duration(stream_start.elapsed()),
-                                        Self::format_duration(_ttft)
+                                        format_duration(stream_start.elapsed()),
+                                        format_duration(_ttft)
                                     )
                                 } else {
                                     full_response
@@ -3263,13 +3997,6 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                 ));
                             }
 
-                            // Closure marker with timing
-                            if tool_call.tool != "final_output" {
-                                self.ui_writer
-                                    .print_tool_timing(&Self::format_duration(exec_duration));
-                                self.ui_writer.print_agent_prompt();
-                            }
-
                             // Add the tool call and result to the context window using RAW unfiltered content
                             // This ensures the log file contains the true raw content including JSON tool calls
                             let tool_message = if !raw_content_for_log.trim().is_empty() {
@@ -3292,26 +4019,69 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                     ),
                                 }
                             };
+                            let tool_result = self
+                                .limit_tool_result(&tool_call.tool, tool_result, &provider_model)
+                                .await;
+                            // A read returning what an earlier one still in the context did
+                            // is replaced by a note pointing back to it
+                            let tool_result = match read_history::ReadHistory::key(&tool_call)
+                                .filter(|_| tool_success)
+                                .and_then(|key| {
+                                    self.read_history.repeat_of(
+                                        key,
+                                        &tool_result,
+                                        self.turn_metrics.len() + 1,
+                                        &self.context_window.conversation_history,
+                                    )
+                                }) {
+                                Some(turn) => read_history::unchanged_note(&tool_call, turn),
+                                None => tool_result,
+                            };
                             let result_message = Message {
                                 role: MessageRole::User,
                                 content: format!("Tool result: {}", tool_result),
                             };
 
-                            self.context_window.add_message(tool_message);
+                            self.tool_context.record(
+                                &tool_call.tool,
+                                tokenizer::count_message_tokens(&provider_model, &result_message),
+                            );
+                            self.context_window.add_response_part(tool_message.content);
                             self.context_window.add_message(result_message);
 
+                            // A command cancelled by the user ends the turn with its result
+                            if self.cancellation.is_cancelled() {
+                                return Err(G3Error::Cancelled.into());
+                            }
+
                             // Update the request with the new context for next iteration
                             request.messages = self.context_window.conversation_history.clone();
+                            request.max_tokens = Some(self.output_tokens(
+                                &capabilities,
+                                output_tokens::Phase::ToolFollowUp,
+                            ));
 
-                            // Ensure tools are included for native providers in subsequent iterations
-                            if provider.has_native_tool_calling() {
-                                request.tools = Some(Self::create_tool_definitions(
-                                    self.config.webdriver.enabled,
-                                    self.config.macax.enabled,
-                                    self.config.computer_control.enabled,
-                                ));
+                            // Ensure tools are included in subsequent iterations
+                            if let Some(tools) =
+                                self.request_tools(has_native_tool_calling, &capabilities)
+                            {
+                                request.tools = Some(tools);
                             }
 
+                            // The last response allowed after a wrap-up must be final_output
+                            let last_after_wrap_up = wrap_up_iteration.is_some_and(|start| {
+                                iteration_count + 2 == start + WRAP_UP_ITERATIONS
+                            });
+                            let has_final_output = request
+                                .tools
+                                .as_ref()
+                                .is_some_and(|tools| tools.iter().any(|t| t.name == "final_output"));
+                            request.tool_choice = if last_after_wrap_up && has_final_output {
+                                ToolChoice::Tool("final_output".to_string())
+                            } else {
+                                ToolChoice::Auto
+                            };
+
                             // DO NOT add final_display_content to full_response here!
                             // The content was already displayed during streaming and added to current_response.
                             // Adding it again would cause duplication when the agent message is printed.
@@ -3337,28 +4107,20 @@ If you can complete it with 1-2 tool calls, skip TODO.
                         }
 
                         // If no tool calls were completed, continue streaming normally
-                        if !tool_executed {
-                            let clean_content = chunk
-                                .content
-                                .replace("<|im_end|>", "")
-                                .replace("</s>", "")
-                                .replace("[/INST]", "")
-                                .replace("<</SYS>>", "");
-
-                            if !clean_content.is_empty() {
-                                let filtered_content =
-                                    fixed_filter_json::fixed_filter_json_tool_calls(&clean_content);
-
-                                if !filtered_content.is_empty() {
-                                    if !response_started {
-                                        self.ui_writer.print_agent_prompt();
-                                        response_started = true;
-                                    }
+                        if !tool_executed && !chunk.content.is_empty() {
+                            let filtered_content =
+                                fixed_filter_json::fixed_filter_json_tool_calls(&chunk.content);
 
-                                    self.ui_writer.print_agent_response(&filtered_content);
-                                    self.ui_writer.flush();
-                                    current_response.push_str(&filtered_content);
+                            if !filtered_content.is_empty() {
+                                if !response_started {
+                                    self.events.publish(events::AgentEvent::ResponseStarted);
+                                    response_started = true;
                                 }
+
+                                self.events.publish(events::AgentEvent::TextDelta {
+                                    text: filtered_content.clone(),
+                                });
+                                current_response.push_str(&filtered_content);
                             }
                         }
 
@@ -3366,6 +4128,47 @@ If you can complete it with 1-2 tool calls, skip TODO.
                             debug!("Stream finished: tool_executed={}, current_response_len={}, full_response_len={}, chunks_received={}",
                                 tool_executed, current_response.len(), full_response.len(), chunks_received);
 
+                            // A response cut off at the output token limit is kept in the
+                            // context, joined with what it continues, so it can be resumed
+                            if !tool_executed
+                                && (chunk.truncated || self.context_window.awaiting_continuation())
+                            {
+                                let raw_text = parser.get_text_content().to_string();
+                                self.context_window.add_response_part(raw_text);
+                            }
+                            if !tool_executed && chunk.truncated {
+                                let max_continuations = self.config.agent.max_continuations;
+                                if self.is_autonomous && continuations < max_continuations {
+                                    continuations += 1;
+                                    self.events.publish(events::AgentEvent::Status {
+                                        message: format!(
+                                            "\n↪️ Response reached the output token limit, continuing ({}/{})...\n",
+                                            continuations, max_continuations
+                                        ),
+                                    });
+                                    self.context_window.add_message(Message {
+                                        role: MessageRole::User,
+                                        content: CONTINUE_PROMPT.to_string(),
+                                    });
+                                    request.messages =
+                                        self.context_window.conversation_history.clone();
+                                    parser.reset();
+                                    current_response.clear();
+                                    response_started = false;
+                                    continuing = true;
+                                    break;
+                                }
+                                self.response_truncated = true;
+                                self.events.publish(events::AgentEvent::Status {
+                                    message: if self.is_autonomous {
+                                        "\n⚠️ The response reached the output token limit and was not continued further.\n"
+                                    } else {
+                                        "\n⚠️ The response reached the output token limit. Type /continue to resume it.\n"
+                                    }
+                                    .to_string(),
+                                });
+                            }
+
                             // Stream finished - check if we should continue or return
                             if !tool_executed {
                                 // No tools were executed in this iteration
@@ -3385,15 +4188,9 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                     debug!("Warning: Using parser buffer text as fallback - this may duplicate output");
                                     // Extract only the undisplayed portion from parser buffer
                                     // Parser buffer accumulates across iterations, so we need to be careful
-                                    let clean_text = text_content
-                                        .replace("<|im_end|>", "")
-                                        .replace("</s>", "")
-                                        .replace("[/INST]", "")
-                                        .replace("<</SYS>>", "");
-
                                     let filtered_text =
                                         fixed_filter_json::fixed_filter_json_tool_calls(
-                                            &clean_text,
+                                            text_content,
                                         );
 
                                     // Only use this if we truly have nothing else
@@ -3416,8 +4213,7 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                     error!("Iteration: {}/{}", iteration_count, MAX_ITERATIONS);
                                     error!(
                                         "Provider: {} (model: {})",
-                                        provider.name(),
-                                        provider.model()
+                                        provider_name, provider_model
                                     );
                                     error!("Chunks received: {}", chunks_received);
                                     error!("Parser state:");
@@ -3499,9 +4295,9 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                     // No response received - this is an error condition
                                     warn!("Stream finished without any content or tool calls");
                                     warn!("Chunks received: {}", chunks_received);
-                                    return Err(anyhow::anyhow!(
-                                        "No response received from the model. The model may be experiencing issues or the request may have been malformed."
-                                    ));
+                                    return Err(G3Error::ServerError(
+                                        "No response received from the model. The model may be experiencing issues or the request may have been malformed.".to_string()
+                                    ).into());
                                 }
 
                                 // Set full_response to current_response (don't append)
@@ -3511,7 +4307,11 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                 // Return empty string to avoid duplication
                                 full_response = String::new();
 
-                                self.ui_writer.println("");
+                                self.record_response_usage(
+                                    accumulated_usage.take(),
+                                    &current_response,
+                                );
+                                self.events.publish(events::AgentEvent::ResponseFinished);
                                 let _ttft =
                                     first_token_time.unwrap_or_else(|| stream_start.elapsed());
 
@@ -3520,8 +4320,8 @@ If you can complete it with 1-2 tool calls, skip TODO.
                                     format!(
                                         "{}\n\n⏱️ {} | 💭 {}",
                                         full_response,
-                                        Self::format_duration(stream_start.elapsed()),
-                                        Self::format_duration(_ttft)
+                                        format_duration(stream_start.elapsed()),
+                                        format_duration(_ttft)
                                     )
                                 } else {
                                     full_response
@@ -3547,19 +4347,26 @@ If you can complete it with 1-2 tool calls, skip TODO.
                         // Store the error for potential logging later
                         _last_error = Some(error_details.clone());
                         
-                        // Check if this is a recoverable connection error
-                        let is_connection_error = error_msg.contains("unexpected EOF") 
-                            || error_msg.contains("connection") 
-                            || error_msg.contains("chunk size line")
-                            || error_msg.contains("body error");
-                        
-                        if is_connection_error {
-                            warn!("Connection error at chunk {}, treating as end of stream", chunks_received + 1);
-                            // If we have any content or tool calls, treat this as a graceful end
-                            if chunks_received > 0 && (!parser.get_text_content().is_empty() || parser.native_tool_calls.len() > 0) {
-                                warn!("Stream terminated unexpectedly but we have content, continuing");
-                                break; // Break to process what we have
-                            }
+                        // A dropped connection or a transient server error is resumed below
+                        let is_interruption = matches!(
+                            G3Error::from_error(&e),
+                            Some(
+                                G3Error::Network(_)
+                                    | G3Error::Timeout(_)
+                                    | G3Error::ServerError(_)
+                                    | G3Error::ModelBusy(_)
+                            )
+                        );
+                        if is_interruption && !tool_executed {
+                            warn!("Stream interrupted at chunk {}: {}", chunks_received + 1, error_msg);
+                            interrupted = Some(e);
+                            break;
+                        }
+
+                        // A content filter stops the same request again, so the turn ends here
+                        if G3Error::is_content_filtered(&e) {
+                            warn!("Response stopped by the content filter: {}", error_msg);
+                            return Err(e);
                         }
 
                         if tool_executed {
@@ -3578,19 +4385,84 @@ If you can complete it with 1-2 tool calls, skip TODO.
                 }
             }
 
-            // Update context window with actual usage if available
-            if let Some(usage) = accumulated_usage {
-                debug!("Updating context window with actual usage from stream");
-                self.context_window.update_usage_from_response(&usage);
-            } else {
-                // Fall back to estimation if no usage data was provided
-                debug!("No usage data from stream, using estimation");
-                let estimated_tokens = ContextWindow::estimate_tokens(&current_response);
-                self.context_window.add_streaming_tokens(estimated_tokens);
+            // The response is cut short at the time limit, keeping what it streamed, and
+            // the next iteration tells the model to wrap up
+            if out_of_time {
+                drop(stream);
+                warn!("Turn time limit reached after {} chunks of a response", chunks_received);
+                let partial = parser.get_text_content().to_string();
+                self.record_response_usage(accumulated_usage, &partial);
+                if !partial.trim().is_empty() {
+                    self.context_window.add_response_part(partial);
+                    self.events.publish(events::AgentEvent::ResponseFinished);
+                }
+                continue;
+            }
+            if stalled {
+                interrupted = Some(anyhow::Error::new(error_handling::StallError(stall_timeout)));
+            }
+            if let Some(interruption) = interrupted {
+                drop(stream);
+                warn!("{} after {} chunks", interruption, chunks_received);
+                // After a tool call the results are already in the context, so the next
+                // iteration's stream simply picks up from there
+                if !tool_executed {
+                    // What streamed was generated, and it is counted once here whether it is
+                    // resumed from or written again
+                    let streamed = parser.get_text_content().to_string();
+                    self.record_response_usage(accumulated_usage.take(), &streamed);
+                    interruptions += 1;
+                    if interruptions >= max_stall_retries {
+                        error_context.clone().log_error(&interruption);
+                        return Err(interruption);
+                    }
+                    let cause = if stalled {
+                        format!("Provider stalled (no data for {}s)", stall_timeout.as_secs())
+                    } else {
+                        "Connection to the provider was interrupted".to_string()
+                    };
+
+                    // What was streamed stays in the context, and the model is asked to
+                    // pick up from there rather than start the response over
+                    let action = if !streamed.trim().is_empty() {
+                        self.context_window.add_response_part(streamed);
+                        self.context_window.add_message(Message {
+                            role: MessageRole::User,
+                            content: RESUME_PROMPT.to_string(),
+                        });
+                        request.messages = self.context_window.conversation_history.clone();
+                        parser.reset();
+                        current_response.clear();
+                        response_started = false;
+                        "resuming the response"
+                    } else if response_started || partial_summary.is_shown() {
+                        // Nothing to resume from, so what was shown is written again
+                        self.events.publish(events::AgentEvent::ResponseDiscarded);
+                        response_started = false;
+                        "discarding the response shown and retrying"
+                    } else {
+                        "retrying"
+                    };
+                    self.events.publish(events::AgentEvent::Status {
+                        message: format!(
+                            "\n⚠️ {}, {} ({}/{})...\n",
+                            cause,
+                            action,
+                            interruptions,
+                            max_stall_retries - 1
+                        ),
+                    });
+                    let delay =
+                        error_handling::calculate_retry_delay(interruptions, self.is_autonomous);
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
             }
 
+            self.record_response_usage(accumulated_usage, &current_response);
+
             // If we get here and no tool was executed, we're done
-            if !tool_executed {
+            if !tool_executed && !continuing {
                 // IMPORTANT: Do NOT add parser text_content here!
                 // The text has already been displayed during streaming via current_response.
                 // The parser buffer accumulates ALL text and would cause duplication.
@@ -3618,7 +4490,7 @@ If you can complete it with 1-2 tool calls, skip TODO.
                             full_response.len()
                         );
                     }
-                    self.ui_writer.println("");
+                    self.events.publish(events::AgentEvent::ResponseFinished);
                 }
 
                 let _ttft = first_token_time.unwrap_or_else(|| stream_start.elapsed());
@@ -3628,18 +4500,9 @@ If you can complete it with 1-2 tool calls, skip TODO.
                 if !full_response.trim().is_empty() {
                     // Get the raw text from the parser (before filtering)
                     let raw_text = parser.get_text_content();
-                    let raw_clean = raw_text
-                        .replace("<|im_end|>", "")
-                        .replace("</s>", "")
-                        .replace("[/INST]", "")
-                        .replace("<</SYS>>", "");
-
-                    if !raw_clean.trim().is_empty() {
-                        let assistant_message = Message {
-                            role: MessageRole::Assistant,
-                            content: raw_clean,
-                        };
-                        self.context_window.add_message(assistant_message);
+
+                    if !raw_text.trim().is_empty() {
+                        self.context_window.add_response_part(raw_text.to_string());
                     }
                 }
 
@@ -3648,1565 +4511,411 @@ If you can complete it with 1-2 tool calls, skip TODO.
                     format!(
                         "{}\n\n⏱️ {} | 💭 {}",
                         full_response,
-                        Self::format_duration(stream_start.elapsed()),
-                        Self::format_duration(_ttft)
+                        format_duration(stream_start.elapsed()),
+                        format_duration(_ttft)
                     )
-                } else {
-                    full_response
-                };
-
-                return Ok(TaskResult::new(final_response, self.context_window.clone()));
-            }
-
-            // Continue the loop to start a new stream with updated context
-        }
-
-        // If we exit the loop due to max iterations
-        let _ttft = first_token_time.unwrap_or_else(|| stream_start.elapsed());
-
-        // Add timing if needed
-        let final_response = if show_timing {
-            format!(
-                "{}\n\n⏱️ {} | 💭 {}",
-                full_response,
-                Self::format_duration(stream_start.elapsed()),
-                Self::format_duration(_ttft)
-            )
-        } else {
-            full_response
-        };
-
-        Ok(TaskResult::new(final_response, self.context_window.clone()))
-    }
-
-    pub async fn execute_tool(&self, tool_call: &ToolCall) -> Result<String> {
-        debug!("=== EXECUTING TOOL ===");
-        debug!("Tool name: {}", tool_call.tool);
-        debug!("Tool args (raw): {:?}", tool_call.args);
-        debug!(
-            "Tool args (JSON): {}",
-            serde_json::to_string(&tool_call.args)
-                .unwrap_or_else(|_| "failed to serialize".to_string())
-        );
-        debug!("======================");
-
-        match tool_call.tool.as_str() {
-            "shell" => {
-                debug!("Processing shell tool call");
-                if let Some(command) = tool_call.args.get("command") {
-                    debug!("Found command parameter: {:?}", command);
-                    if let Some(command_str) = command.as_str() {
-                        debug!("Command string: {}", command_str);
-                        // Use shell escaping to handle filenames with spaces and special characters
-                        let escaped_command = shell_escape_command(command_str);
-
-                        let executor = CodeExecutor::new();
-
-                        // Create a receiver for streaming output
-                        struct ToolOutputReceiver<'a, W: UiWriter> {
-                            ui_writer: &'a W,
-                        }
-
-                        impl<'a, W: UiWriter> g3_execution::OutputReceiver for ToolOutputReceiver<'a, W> {
-                            fn on_output_line(&self, line: &str) {
-                                self.ui_writer.update_tool_output_line(line);
-                            }
-                        }
-
-                        let receiver = ToolOutputReceiver {
-                            ui_writer: &self.ui_writer,
-                        };
-
-                        match executor
-                            .execute_bash_streaming(&escaped_command, &receiver)
-                            .await
-                        {
-                            Ok(result) => {
-                                if result.success {
-                                    Ok(if result.stdout.is_empty() {
-                                        "✅ Command executed successfully".to_string()
-                                    } else {
-                                        result.stdout.trim().to_string()
-                                    })
-                                } else {
-                                    Ok(format!("❌ Command failed: {}", result.stderr.trim()))
-                                }
-                            }
-                            Err(e) => Ok(format!("❌ Execution error: {}", e)),
-                        }
-                    } else {
-                        debug!("Command parameter is not a string: {:?}", command);
-                        Ok("❌ Invalid command argument".to_string())
-                    }
-                } else {
-                    debug!("No command parameter found in args: {:?}", tool_call.args);
-                    debug!(
-                        "Available keys: {:?}",
-                        tool_call
-                            .args
-                            .as_object()
-                            .map(|obj| obj.keys().collect::<Vec<_>>())
-                    );
-                    Ok("❌ Missing command argument".to_string())
-                }
-            }
-            "read_file" => {
-                debug!("Processing read_file tool call");
-                if let Some(file_path) = tool_call.args.get("file_path") {
-                    if let Some(path_str) = file_path.as_str() {
-                        // Expand tilde (~) to home directory
-                        let expanded_path = shellexpand::tilde(path_str);
-                        let path_str = expanded_path.as_ref();
-
-                        // Check if this is an image file
-                        let is_image = path_str.to_lowercase().ends_with(".png")
-                            || path_str.to_lowercase().ends_with(".jpg")
-                            || path_str.to_lowercase().ends_with(".jpeg")
-                            || path_str.to_lowercase().ends_with(".gif")
-                            || path_str.to_lowercase().ends_with(".bmp")
-                            || path_str.to_lowercase().ends_with(".tiff")
-                            || path_str.to_lowercase().ends_with(".tif")
-                            || path_str.to_lowercase().ends_with(".webp");
-
-                        // If it's an image file, use OCR via extract_text
-                        if is_image {
-                            if let Some(controller) = &self.computer_controller {
-                                match controller.extract_text_from_image(path_str).await {
-                                    Ok(text) => {
-                                        return Ok(format!(
-                                            "📄 Image file (OCR extracted):\n{}",
-                                            text
-                                        ));
-                                    }
-                                    Err(e) => {
-                                        return Ok(format!(
-                                            "❌ Failed to extract text from image '{}': {}",
-                                            path_str, e
-                                        ))
-                                    }
-                                }
-                            } else {
-                                return Ok("❌ Computer control not enabled. Cannot perform OCR on image files. Set computer_control.enabled = true in config.".to_string());
-                            }
-                        }
-
-                        // Extract optional start and end positions
-                        let start_char = tool_call
-                            .args
-                            .get("start")
-                            .and_then(|v| v.as_u64())
-                            .map(|n| n as usize);
-                        let end_char = tool_call
-                            .args
-                            .get("end")
-                            .and_then(|v| v.as_u64())
-                            .map(|n| n as usize);
-
-                        debug!(
-                            "Reading file: {}, start={:?}, end={:?}",
-                            path_str, start_char, end_char
-                        );
-
-                        match std::fs::read_to_string(path_str) {
-                            Ok(content) => {
-                                // Validate and apply range if specified
-                                let start = start_char.unwrap_or(0);
-                                let end = end_char.unwrap_or(content.len());
-
-                                // Validation
-                                if start > content.len() {
-                                    return Ok(format!(
-                                        "❌ Start position {} exceeds file length {}",
-                                        start,
-                                        content.len()
-                                    ));
-                                }
-                                if end > content.len() {
-                                    return Ok(format!(
-                                        "❌ End position {} exceeds file length {}",
-                                        end,
-                                        content.len()
-                                    ));
-                                }
-                                if start > end {
-                                    return Ok(format!(
-                                        "❌ Start position {} is greater than end position {}",
-                                        start, end
-                                    ));
-                                }
-
-                                // Extract the requested portion, ensuring we're at char boundaries
-                                // Find the nearest valid char boundaries
-                                let start_boundary = if start == 0 {
-                                    0
-                                } else {
-                                    content
-                                        .char_indices()
-                                        .find(|(i, _)| *i >= start)
-                                        .map(|(i, _)| i)
-                                        .unwrap_or(start)
-                                };
-                                let end_boundary = content
-                                    .char_indices()
-                                    .find(|(i, _)| *i >= end)
-                                    .map(|(i, _)| i)
-                                    .unwrap_or(content.len());
-
-                                let partial_content = &content[start_boundary..end_boundary];
-                                let line_count = partial_content.lines().count();
-                                let total_lines = content.lines().count();
-
-                                // Format output with range info if partial
-                                if start_char.is_some() || end_char.is_some() {
-                                    Ok(format!(
-                                        "📄 File content (chars {}-{}, {} lines of {} total):\n{}",
-                                        start_boundary,
-                                        end_boundary,
-                                        line_count,
-                                        total_lines,
-                                        partial_content
-                                    ))
-                                } else {
-                                    Ok(format!(
-                                        "📄 File content ({} lines):\n{}",
-                                        line_count, content
-                                    ))
-                                }
-                            }
-                            Err(e) => Ok(format!("❌ Failed to read file '{}': {}", path_str, e)),
-                        }
-                    } else {
-                        Ok("❌ Invalid file_path argument".to_string())
-                    }
-                } else {
-                    Ok("❌ Missing file_path argument".to_string())
-                }
-            }
-            "write_file" => {
-                debug!("Processing write_file tool call");
-                debug!("Raw tool_call.args: {:?}", tool_call.args);
-                debug!(
-                    "Args as JSON: {}",
-                    serde_json::to_string(&tool_call.args)
-                        .unwrap_or_else(|_| "failed to serialize".to_string())
-                );
-                debug!(
-                    "Args type: {:?}",
-                    std::any::type_name_of_val(&tool_call.args)
-                );
-                debug!("Args is_object: {}", tool_call.args.is_object());
-                debug!("Args is_array: {}", tool_call.args.is_array());
-                debug!("Args is_null: {}", tool_call.args.is_null());
-
-                // Try multiple argument formats that different providers might use
-                let (path_str, content_str) = if let Some(args_obj) = tool_call.args.as_object() {
-                    debug!(
-                        "Args object keys: {:?}",
-                        args_obj.keys().collect::<Vec<_>>()
-                    );
-
-                    // Format 1: Standard format with file_path and content
-                    if let (Some(path_val), Some(content_val)) =
-                        (args_obj.get("file_path"), args_obj.get("content"))
-                    {
-                        debug!("Found file_path and content keys");
-                        if let (Some(path), Some(content)) =
-                            (path_val.as_str(), content_val.as_str())
-                        {
-                            debug!(
-                                "Successfully extracted file_path='{}', content_len={}",
-                                path,
-                                content.len()
-                            );
-                            (Some(path), Some(content))
-                        } else {
-                            debug!("file_path or content values are not strings: path_val={:?}, content_val={:?}", path_val, content_val);
-                            (None, None)
-                        }
-                    }
-                    // Format 2: Anthropic-style with path and content
-                    else if let (Some(path_val), Some(content_val)) =
-                        (args_obj.get("path"), args_obj.get("content"))
-                    {
-                        debug!("Found path and content keys (Anthropic style)");
-                        if let (Some(path), Some(content)) =
-                            (path_val.as_str(), content_val.as_str())
-                        {
-                            debug!(
-                                "Successfully extracted path='{}', content_len={}",
-                                path,
-                                content.len()
-                            );
-                            (Some(path), Some(content))
-                        } else {
-                            debug!("path or content values are not strings: path_val={:?}, content_val={:?}", path_val, content_val);
-                            (None, None)
-                        }
-                    }
-                    // Format 3: Alternative naming with filename and text
-                    else if let (Some(path_val), Some(content_val)) =
-                        (args_obj.get("filename"), args_obj.get("text"))
-                    {
-                        debug!("Found filename and text keys");
-                        if let (Some(path), Some(content)) =
-                            (path_val.as_str(), content_val.as_str())
-                        {
-                            debug!(
-                                "Successfully extracted filename='{}', text_len={}",
-                                path,
-                                content.len()
-                            );
-                            (Some(path), Some(content))
-                        } else {
-                            debug!("filename or text values are not strings: path_val={:?}, content_val={:?}", path_val, content_val);
-                            (None, None)
-                        }
-                    }
-                    // Format 4: Alternative naming with file and data
-                    else if let (Some(path_val), Some(content_val)) =
-                        (args_obj.get("file"), args_obj.get("data"))
-                    {
-                        debug!("Found file and data keys");
-                        if let (Some(path), Some(content)) =
-                            (path_val.as_str(), content_val.as_str())
-                        {
-                            debug!(
-                                "Successfully extracted file='{}', data_len={}",
-                                path,
-                                content.len()
-                            );
-                            (Some(path), Some(content))
-                        } else {
-                            debug!("file or data values are not strings: path_val={:?}, content_val={:?}", path_val, content_val);
-                            (None, None)
-                        }
-                    } else {
-                        debug!(
-                            "No matching key patterns found. Available argument keys: {:?}",
-                            args_obj.keys().collect::<Vec<_>>()
-                        );
-                        (None, None)
-                    }
-                } else {
-                    debug!("Args is not an object, checking if it's an array");
-                    // Format 5: Args might be an array [path, content]
-                    if let Some(args_array) = tool_call.args.as_array() {
-                        debug!("Args is an array with {} elements", args_array.len());
-                        if args_array.len() >= 2 {
-                            if let (Some(path), Some(content)) =
-                                (args_array[0].as_str(), args_array[1].as_str())
-                            {
-                                debug!(
-                                    "Successfully extracted from array: path='{}', content_len={}",
-                                    path,
-                                    content.len()
-                                );
-                                (Some(path), Some(content))
-                            } else {
-                                debug!(
-                                    "Array elements are not strings: [0]={:?}, [1]={:?}",
-                                    args_array[0], args_array[1]
-                                );
-                                (None, None)
-                            }
-                        } else {
-                            debug!("Array has insufficient elements: {}", args_array.len());
-                            (None, None)
-                        }
-                    } else {
-                        debug!("Args is neither object nor array");
-                        (None, None)
-                    }
-                };
-
-                debug!(
-                    "Final extracted values: path_str={:?}, content_str_len={:?}",
-                    path_str,
-                    content_str.map(|c| c.len())
-                );
-
-                if let (Some(path), Some(content)) = (path_str, content_str) {
-                    // Expand tilde (~) to home directory
-                    let expanded_path = shellexpand::tilde(path);
-                    let path = expanded_path.as_ref();
-
-                    debug!("Writing to file: {}", path);
-
-                    // Create parent directories if they don't exist
-                    if let Some(parent) = std::path::Path::new(path).parent() {
-                        if let Err(e) = std::fs::create_dir_all(parent) {
-                            return Ok(format!(
-                                "❌ Failed to create parent directories for '{}': {}",
-                                path, e
-                            ));
-                        }
-                    }
-
-                    match std::fs::write(path, content) {
-                        Ok(()) => {
-                            let line_count = content.lines().count();
-                            let char_count = content.len();
-                            Ok(format!(
-                                "✅ Successfully wrote {} lines ({} characters)",
-                                line_count, char_count
-                            ))
-                        }
-                        Err(e) => Ok(format!("❌ Failed to write to file '{}': {}", path, e)),
-                    }
-                } else {
-                    // Provide more detailed error information
-                    let available_keys = if let Some(obj) = tool_call.args.as_object() {
-                        obj.keys().collect::<Vec<_>>()
-                    } else {
-                        vec![]
-                    };
-
-                    Ok(format!(
-                        "❌ Missing file_path or content argument. Available keys: {:?}. Expected formats: {{\"file_path\": \"...\", \"content\": \"...\"}}, {{\"path\": \"...\", \"content\": \"...\"}}, {{\"filename\": \"...\", \"text\": \"...\"}}, or {{\"file\": \"...\", \"data\": \"...\"}}",
-                        available_keys
-                    ))
-                }
-            }
-            "str_replace" => {
-                debug!("Processing str_replace tool call");
-
-                // Extract arguments
-                let args_obj = match tool_call.args.as_object() {
-                    Some(obj) => obj,
-                    None => return Ok("❌ Invalid arguments: expected object".to_string()),
-                };
-
-                let file_path = match args_obj.get("file_path").and_then(|v| v.as_str()) {
-                    Some(path) => {
-                        // Expand tilde (~) to home directory
-                        let expanded_path = shellexpand::tilde(path);
-                        expanded_path.into_owned()
-                    }
-                    None => return Ok("❌ Missing or invalid file_path argument".to_string()),
-                };
-
-                let diff = match args_obj.get("diff").and_then(|v| v.as_str()) {
-                    Some(d) => d,
-                    None => return Ok("❌ Missing or invalid diff argument".to_string()),
-                };
-
-                // Optional start and end character positions (0-indexed, end is EXCLUSIVE)
-                let start_char = args_obj
-                    .get("start")
-                    .and_then(|v| v.as_u64())
-                    .map(|n| n as usize);
-                let end_char = args_obj
-                    .get("end")
-                    .and_then(|v| v.as_u64())
-                    .map(|n| n as usize);
-
-                debug!(
-                    "str_replace: path={}, start={:?}, end={:?}",
-                    file_path, start_char, end_char
-                );
-
-                // Read the existing file
-                let file_content = match std::fs::read_to_string(&file_path) {
-                    Ok(content) => content,
-                    Err(e) => return Ok(format!("❌ Failed to read file '{}': {}", file_path, e)),
-                };
-
-                // Apply unified diff to content
-                let result =
-                    match apply_unified_diff_to_string(&file_content, diff, start_char, end_char) {
-                        Ok(r) => r,
-                        Err(e) => return Ok(format!("❌ {}", e)),
-                    };
-
-                // Write the result back to the file
-                match std::fs::write(&file_path, &result) {
-                    Ok(()) => Ok("✅ applied unified diff".to_string()),
-                    Err(e) => Ok(format!("❌ Failed to write to file '{}': {}", file_path, e)),
-                }
-            }
-            "final_output" => {
-                if let Some(summary) = tool_call.args.get("summary") {
-                    if let Some(summary_str) = summary.as_str() {
-                        Ok(summary_str.to_string())
-                    } else {
-                        Ok("✅ Turn completed".to_string())
-                    }
-                } else {
-                    Ok("✅ Turn completed".to_string())
-                }
-            }
-            "take_screenshot" => {
-                if let Some(controller) = &self.computer_controller {
-                    let path = tool_call
-                        .args
-                        .get("path")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing path argument"))?;
-
-                    // Extract window_id (app name) - REQUIRED
-                    let window_id = tool_call.args.get("window_id").and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing window_id argument. You must specify which window to capture (e.g., 'Safari', 'Terminal', 'Google Chrome')."))?;
-
-                    // Extract region if provided
-                    let region = tool_call
-                        .args
-                        .get("region")
-                        .and_then(|v| v.as_object())
-                        .map(|region_obj| g3_computer_control::types::Rect {
-                            x: region_obj.get("x").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
-                            y: region_obj.get("y").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
-                            width: region_obj
-                                .get("width")
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                            height: region_obj
-                                .get("height")
-                                .and_then(|v| v.as_i64())
-                                .unwrap_or(0) as i32,
-                        });
-
-                    match controller
-                        .take_screenshot(path, region, Some(window_id))
-                        .await
-                    {
-                        Ok(_) => {
-                            // Get the actual path where the screenshot was saved
-                            let actual_path = if path.starts_with('/') {
-                                path.to_string()
-                            } else {
-                                let temp_dir = std::env::var("TMPDIR")
-                                    .or_else(|_| {
-                                        std::env::var("HOME").map(|h| format!("{}/tmp", h))
-                                    })
-                                    .unwrap_or_else(|_| "/tmp".to_string());
-                                format!("{}/{}", temp_dir.trim_end_matches('/'), path)
-                            };
-
-                            Ok(format!(
-                                "✅ Screenshot of {} saved to: {}",
-                                window_id, actual_path
-                            ))
-                        }
-                        Err(e) => Ok(format!("❌ Failed to take screenshot: {}", e)),
-                    }
-                } else {
-                    Ok("❌ Computer control not enabled. Set computer_control.enabled = true in config.".to_string())
-                }
-            }
-            "extract_text" => {
-                if let Some(controller) = &self.computer_controller {
-                    let path = tool_call
-                        .args
-                        .get("path")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing path argument"))?;
-
-                    // Extract text from image file only
-                    match controller.extract_text_from_image(path).await {
-                        Ok(text) => Ok(format!("✅ Extracted text:\n{}", text)),
-                        Err(e) => Ok(format!("❌ Failed to extract text: {}", e)),
-                    }
-                } else {
-                    Ok("❌ Computer control not enabled. Set computer_control.enabled = true in config.".to_string())
-                }
-            }
-            "todo_read" => {
-                debug!("Processing todo_read tool call");
-                // Read from todo.g3.md file in current workspace directory
-                let todo_path = std::env::current_dir()?.join("todo.g3.md");
-                
-                if !todo_path.exists() {
-                    // Also update in-memory content to stay in sync
-                    let mut todo = self.todo_content.write().await;
-                    *todo = String::new();
-                    Ok("📝 TODO list is empty (no todo.g3.md file found)".to_string())
-                } else {
-                    match std::fs::read_to_string(&todo_path) {
-                        Ok(content) => {
-                            // Update in-memory content to stay in sync
-                            let mut todo = self.todo_content.write().await;
-                            *todo = content.clone();
-                            
-                            if content.trim().is_empty() {
-                                Ok("📝 TODO list is empty".to_string())
-                            } else {
-                                Ok(format!("📝 TODO list:\n{}", content))
-                            }
-                        }
-                        Err(e) => Ok(format!("❌ Failed to read TODO.md: {}", e)),
-                    }
-                }
-            }
-            "todo_write" => {
-                debug!("Processing todo_write tool call");
-                if let Some(content) = tool_call.args.get("content") {
-                    if let Some(content_str) = content.as_str() {
-                        let char_count = content_str.chars().count();
-                        let max_chars = std::env::var("G3_TODO_MAX_CHARS")
-                            .ok()
-                            .and_then(|s| s.parse().ok())
-                            .unwrap_or(50_000);
-
-                        if max_chars > 0 && char_count > max_chars {
-                            return Ok(format!(
-                                "❌ TODO list too large: {} chars (max: {})",
-                                char_count, max_chars
-                            ));
-                        }
-
-                        // Write to todo.g3.md file in current workspace directory
-                        let todo_path = std::env::current_dir()?.join("todo.g3.md");
-                        
-                        match std::fs::write(&todo_path, content_str) {
-                            Ok(_) => {
-                                // Also update in-memory content to stay in sync
-                                let mut todo = self.todo_content.write().await;
-                                *todo = content_str.to_string();
-                                Ok(format!("✅ TODO list updated ({} chars) and saved to todo.g3.md", char_count))
-                            }
-                            Err(e) => Ok(format!("❌ Failed to write todo.g3.md: {}", e)),
-                        }
-                    } else {
-                        Ok("❌ Invalid content argument".to_string())
-                    }
-                } else {
-                    Ok("❌ Missing content argument".to_string())
-                }
-            }
-            "webdriver_start" => {
-                debug!("Processing webdriver_start tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                // Check if session already exists
-                let session_guard = self.webdriver_session.read().await;
-                if session_guard.is_some() {
-                    drop(session_guard);
-                    return Ok("✅ WebDriver session already active".to_string());
-                }
-                drop(session_guard);
-
-                // Note: Safari Remote Automation must be enabled before using WebDriver.
-                // Run this once: safaridriver --enable
-                // Or enable manually: Safari → Develop → Allow Remote Automation
-
-                // Start safaridriver process
-                let port = self.config.webdriver.safari_port;
-
-                let safaridriver_result = tokio::process::Command::new("safaridriver")
-                    .arg("--port")
-                    .arg(port.to_string())
-                    .stdout(std::process::Stdio::null())
-                    .stderr(std::process::Stdio::null())
-                    .spawn();
-
-                let mut safaridriver_process = match safaridriver_result {
-                    Ok(process) => process,
-                    Err(e) => {
-                        return Ok(format!("❌ Failed to start safaridriver: {}\n\nMake sure safaridriver is installed.", e));
-                    }
-                };
-
-                // Wait for safaridriver to start up
-                tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-
-                // Connect to SafariDriver
-                match g3_computer_control::SafariDriver::with_port(port).await {
-                    Ok(driver) => {
-                        let session = std::sync::Arc::new(tokio::sync::Mutex::new(driver));
-                        *self.webdriver_session.write().await = Some(session);
-
-                        // Store the process handle
-                        *self.safaridriver_process.write().await = Some(safaridriver_process);
-
-                        Ok("✅ WebDriver session started successfully! Safari should open automatically.".to_string())
-                    }
-                    Err(e) => {
-                        // Kill the safaridriver process if connection failed
-                        let _ = safaridriver_process.kill().await;
-
-                        Ok(format!("❌ Failed to connect to SafariDriver: {}\n\nThis might be because:\n  - Safari Remote Automation is not enabled (run: safaridriver --enable)\n  - Port {} is already in use\n  - Safari failed to start\n  - Network connectivity issue\n\nTo enable Remote Automation:\n  1. Run: safaridriver --enable (requires password, one-time setup)\n  2. Or manually: Safari → Develop → Allow Remote Automation", e, port))
-                    }
-                }
-            }
-            "webdriver_navigate" => {
-                debug!("Processing webdriver_navigate tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-                drop(session_guard);
-                let url = match tool_call.args.get("url").and_then(|v| v.as_str()) {
-                    Some(u) => u,
-                    None => return Ok("❌ Missing url argument".to_string()),
-                };
-
-                let mut driver = session.lock().await;
-                match driver.navigate(url).await {
-                    Ok(_) => Ok(format!("✅ Navigated to {}", url)),
-                    Err(e) => Ok(format!("❌ Failed to navigate: {}", e)),
-                }
-            }
-            "webdriver_get_url" => {
-                debug!("Processing webdriver_get_url tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let driver = session.lock().await;
-                match driver.current_url().await {
-                    Ok(url) => Ok(format!("Current URL: {}", url)),
-                    Err(e) => Ok(format!("❌ Failed to get URL: {}", e)),
-                }
-            }
-            "webdriver_get_title" => {
-                debug!("Processing webdriver_get_title tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let driver = session.lock().await;
-                match driver.title().await {
-                    Ok(title) => Ok(format!("Page title: {}", title)),
-                    Err(e) => Ok(format!("❌ Failed to get title: {}", e)),
-                }
-            }
-            "webdriver_find_element" => {
-                debug!("Processing webdriver_find_element tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
-                    Some(s) => s,
-                    None => return Ok("❌ Missing selector argument".to_string()),
-                };
-
-                let mut driver = session.lock().await;
-                match driver.find_element(selector).await {
-                    Ok(elem) => match elem.text().await {
-                        Ok(text) => Ok(format!("Element text: {}", text)),
-                        Err(e) => Ok(format!("❌ Failed to get element text: {}", e)),
-                    },
-                    Err(e) => Ok(format!("❌ Failed to find element '{}': {}", selector, e)),
-                }
-            }
-            "webdriver_find_elements" => {
-                debug!("Processing webdriver_find_elements tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
-                    Some(s) => s,
-                    None => return Ok("❌ Missing selector argument".to_string()),
-                };
-
-                let mut driver = session.lock().await;
-                match driver.find_elements(selector).await {
-                    Ok(elements) => {
-                        let mut results = Vec::new();
-                        for (i, elem) in elements.iter().enumerate() {
-                            match elem.text().await {
-                                Ok(text) => results.push(format!("[{}]: {}", i, text)),
-                                Err(_) => results.push(format!("[{}]: <error getting text>", i)),
-                            }
-                        }
-                        Ok(format!(
-                            "Found {} elements:\n{}",
-                            results.len(),
-                            results.join("\n")
-                        ))
-                    }
-                    Err(e) => Ok(format!("❌ Failed to find elements '{}': {}", selector, e)),
-                }
-            }
-            "webdriver_click" => {
-                debug!("Processing webdriver_click tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
-                    Some(s) => s,
-                    None => return Ok("❌ Missing selector argument".to_string()),
-                };
-
-                let mut driver = session.lock().await;
-                match driver.find_element(selector).await {
-                    Ok(mut elem) => match elem.click().await {
-                        Ok(_) => Ok(format!("✅ Clicked element '{}'", selector)),
-                        Err(e) => Ok(format!("❌ Failed to click element: {}", e)),
-                    },
-                    Err(e) => Ok(format!("❌ Failed to find element '{}': {}", selector, e)),
-                }
-            }
-            "webdriver_send_keys" => {
-                debug!("Processing webdriver_send_keys tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
-                    Some(s) => s,
-                    None => return Ok("❌ Missing selector argument".to_string()),
-                };
-
-                let text = match tool_call.args.get("text").and_then(|v| v.as_str()) {
-                    Some(t) => t,
-                    None => return Ok("❌ Missing text argument".to_string()),
-                };
-
-                let clear_first = tool_call
-                    .args
-                    .get("clear_first")
-                    .and_then(|v| v.as_bool())
-                    .unwrap_or(true);
-
-                let mut driver = session.lock().await;
-                match driver.find_element(selector).await {
-                    Ok(mut elem) => {
-                        if clear_first {
-                            if let Err(e) = elem.clear().await {
-                                return Ok(format!("❌ Failed to clear element: {}", e));
-                            }
-                        }
-                        match elem.send_keys(text).await {
-                            Ok(_) => Ok(format!("✅ Sent keys to element '{}'", selector)),
-                            Err(e) => Ok(format!("❌ Failed to send keys: {}", e)),
-                        }
-                    }
-                    Err(e) => Ok(format!("❌ Failed to find element '{}': {}", selector, e)),
-                }
-            }
-            "webdriver_execute_script" => {
-                debug!("Processing webdriver_execute_script tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let script = match tool_call.args.get("script").and_then(|v| v.as_str()) {
-                    Some(s) => s,
-                    None => return Ok("❌ Missing script argument".to_string()),
-                };
-
-                let mut driver = session.lock().await;
-                match driver.execute_script(script, vec![]).await {
-                    Ok(result) => Ok(format!("Script result: {:?}", result)),
-                    Err(e) => Ok(format!("❌ Failed to execute script: {}", e)),
-                }
-            }
-            "webdriver_get_page_source" => {
-                debug!("Processing webdriver_get_page_source tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let driver = session.lock().await;
-                match driver.page_source().await {
-                    Ok(source) => {
-                        // Truncate if too long
-                        if source.len() > 10000 {
-                            Ok(format!(
-                                "Page source ({} chars, truncated to 10000):\n{}...",
-                                source.len(),
-                                &source[..10000]
-                            ))
-                        } else {
-                            Ok(format!("Page source ({} chars):\n{}", source.len(), source))
-                        }
-                    }
-                    Err(e) => Ok(format!("❌ Failed to get page source: {}", e)),
-                }
-            }
-            "webdriver_screenshot" => {
-                debug!("Processing webdriver_screenshot tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let path = match tool_call.args.get("path").and_then(|v| v.as_str()) {
-                    Some(p) => p,
-                    None => return Ok("❌ Missing path argument".to_string()),
-                };
-
-                let mut driver = session.lock().await;
-                match driver.screenshot(path).await {
-                    Ok(_) => Ok(format!("✅ Screenshot saved to {}", path)),
-                    Err(e) => Ok(format!("❌ Failed to take screenshot: {}", e)),
-                }
-            }
-            "webdriver_back" => {
-                debug!("Processing webdriver_back tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let mut driver = session.lock().await;
-                match driver.back().await {
-                    Ok(_) => Ok("✅ Navigated back".to_string()),
-                    Err(e) => Ok(format!("❌ Failed to navigate back: {}", e)),
-                }
-            }
-            "webdriver_forward" => {
-                debug!("Processing webdriver_forward tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
-                };
-
-                let mut driver = session.lock().await;
-                match driver.forward().await {
-                    Ok(_) => Ok("✅ Navigated forward".to_string()),
-                    Err(e) => Ok(format!("❌ Failed to navigate forward: {}", e)),
-                }
-            }
-            "webdriver_refresh" => {
-                debug!("Processing webdriver_refresh tool call");
-
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                let session_guard = self.webdriver_session.read().await;
-                let session = match session_guard.as_ref() {
-                    Some(s) => s.clone(),
-                    None => {
-                        return Ok(
-                            "❌ No active WebDriver session. Call webdriver_start first."
-                                .to_string(),
-                        )
-                    }
+                } else {
+                    full_response
                 };
 
-                let mut driver = session.lock().await;
-                match driver.refresh().await {
-                    Ok(_) => Ok("✅ Page refreshed".to_string()),
-                    Err(e) => Ok(format!("❌ Failed to refresh page: {}", e)),
-                }
+                return Ok(TaskResult::new(final_response, self.context_window.clone()));
             }
-            "webdriver_quit" => {
-                debug!("Processing webdriver_quit tool call");
 
-                if !self.config.webdriver.enabled {
-                    return Ok(
-                        "❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string(),
-                    );
-                }
-
-                // Take the session
-                let session = match self.webdriver_session.write().await.take() {
-                    Some(s) => s.clone(),
-                    None => return Ok("❌ No active WebDriver session.".to_string()),
-                };
+            // Continue the loop to start a new stream with updated context
+        }
 
-                // Quit the WebDriver session
-                match std::sync::Arc::try_unwrap(session) {
-                    Ok(mutex) => {
-                        let driver = mutex.into_inner();
-                        match driver.quit().await {
-                            Ok(_) => {
-                                info!("WebDriver session closed successfully");
-
-                                // Kill the safaridriver process
-                                if let Some(mut process) =
-                                    self.safaridriver_process.write().await.take()
-                                {
-                                    if let Err(e) = process.kill().await {
-                                        warn!("Failed to kill safaridriver process: {}", e);
-                                    } else {
-                                        info!("Safaridriver process terminated");
-                                    }
-                                }
+        // If we exit the loop due to max iterations
+        let _ttft = first_token_time.unwrap_or_else(|| stream_start.elapsed());
 
-                                Ok("✅ WebDriver session closed and safaridriver stopped"
-                                    .to_string())
-                            }
-                            Err(e) => Ok(format!("❌ Failed to quit WebDriver: {}", e)),
-                        }
-                    }
-                    Err(_) => Ok("❌ Cannot quit: WebDriver session is still in use".to_string()),
-                }
-            }
-            "macax_list_apps" => {
-                debug!("Processing macax_list_apps tool call");
+        // Add timing if needed
+        let final_response = if show_timing {
+            format!(
+                "{}\n\n⏱️ {} | 💭 {}",
+                full_response,
+                format_duration(stream_start.elapsed()),
+                format_duration(_ttft)
+            )
+        } else {
+            full_response
+        };
 
-                if !self.config.macax.enabled {
-                    return Ok(
-                        "❌ macOS Accessibility is not enabled. Use --macax flag to enable."
-                            .to_string(),
-                    );
-                }
+        Ok(TaskResult::new(final_response, self.context_window.clone()))
+    }
 
-                let controller_guard = self.macax_controller.read().await;
-                let controller = match controller_guard.as_ref() {
-                    Some(c) => c,
-                    None => {
-                        return Ok("❌ macOS Accessibility controller not initialized.".to_string())
-                    }
-                };
+    pub async fn execute_tool(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("=== EXECUTING TOOL ===");
+        debug!("Tool name: {}", tool_call.tool);
+        debug!("Tool args (raw): {:?}", tool_call.args);
+        debug!(
+            "Tool args (JSON): {}",
+            serde_json::to_string(&tool_call.args)
+                .unwrap_or_else(|_| "failed to serialize".to_string())
+        );
+        debug!("======================");
 
-                match controller.list_applications() {
-                    Ok(apps) => {
-                        let app_list: Vec<String> = apps.iter().map(|a| a.name.clone()).collect();
-                        Ok(format!("Running applications:\n{}", app_list.join("\n")))
+        // The stepper sees the call as the model made it; edited arguments are checked below
+        let stepped;
+        let tool_call = match &self.stepper {
+            Some(stepper) => {
+                let (stepper, call) = (stepper.clone(), tool_call.clone());
+                match tokio::task::spawn_blocking(move || stepper.before_tool_call(&call)).await? {
+                    step::StepDecision::Run => tool_call,
+                    step::StepDecision::Edit(args) => {
+                        stepped = ToolCall {
+                            tool: tool_call.tool.clone(),
+                            args,
+                        };
+                        &stepped
                     }
-                    Err(e) => Ok(format!("❌ Failed to list applications: {}", e)),
+                    step::StepDecision::Skip => return Ok(step::SKIPPED_RESULT.to_string()),
+                    step::StepDecision::Abort => return Err(G3Error::Cancelled.into()),
                 }
             }
-            "macax_get_frontmost_app" => {
-                debug!("Processing macax_get_frontmost_app tool call");
-
-                if !self.config.macax.enabled {
-                    return Ok(
-                        "❌ macOS Accessibility is not enabled. Use --macax flag to enable."
-                            .to_string(),
-                    );
-                }
+            None => tool_call,
+        };
 
-                let controller_guard = self.macax_controller.read().await;
-                let controller = match controller_guard.as_ref() {
-                    Some(c) => c,
-                    None => {
-                        return Ok("❌ macOS Accessibility controller not initialized.".to_string())
-                    }
+        // Moderation comes first so that rewritten arguments still go through the checks below
+        let moderated;
+        let tool_call = match self.moderator.tool_call(tool_call, &self.moderation_log()).await {
+            moderation::Decision::Allow => tool_call,
+            moderation::Decision::Modify(args) => {
+                moderated = ToolCall {
+                    tool: tool_call.tool.clone(),
+                    args,
                 };
-
-                match controller.get_frontmost_app() {
-                    Ok(app) => Ok(format!("Frontmost application: {}", app.name)),
-                    Err(e) => Ok(format!("❌ Failed to get frontmost app: {}", e)),
-                }
+                &moderated
+            }
+            moderation::Decision::Block(reason) => {
+                warn!("Blocked tool call: {}", reason);
+                return Ok(format!("❌ Blocked by moderation hook: {}", reason));
             }
-            "macax_activate_app" => {
-                debug!("Processing macax_activate_app tool call");
+        };
 
-                if !self.config.macax.enabled {
-                    return Ok(
-                        "❌ macOS Accessibility is not enabled. Use --macax flag to enable."
-                            .to_string(),
-                    );
-                }
+        if self.config.agent.read_only {
+            if let Some(reason) = read_only::check_tool_call(tool_call) {
+                warn!("Blocked tool call in read-only mode: {}", reason);
+                return Ok(format!("❌ {}", reason));
+            }
+        }
 
-                let app_name = match tool_call.args.get("app_name").and_then(|v| v.as_str()) {
-                    Some(n) => n,
-                    None => return Ok("❌ Missing app_name argument".to_string()),
-                };
+        if let Some(reason) = self.g3ignore.check_tool_call(tool_call) {
+            warn!("Blocked tool call: {}", reason);
+            return Ok(format!("❌ {}", reason));
+        }
 
-                let controller_guard = self.macax_controller.read().await;
-                let controller = match controller_guard.as_ref() {
-                    Some(c) => c,
-                    None => {
-                        return Ok("❌ macOS Accessibility controller not initialized.".to_string())
-                    }
-                };
+        if let Some(reason) = self.scope.check_tool_call(tool_call) {
+            warn!("Blocked tool call: {}", reason);
+            return Ok(format!("❌ {}", reason));
+        }
 
-                match controller.activate_app(app_name) {
-                    Ok(_) => Ok(format!("✅ Activated application: {}", app_name)),
-                    Err(e) => Ok(format!("❌ Failed to activate app: {}", e)),
-                }
+        if let Some((name, profile)) = self.profile() {
+            if let Some(reason) = profile::check_tool_call(name, profile, tool_call) {
+                warn!("Blocked tool call: {}", reason);
+                return Ok(format!("❌ {}", reason));
             }
-            "macax_press_key" => {
-                debug!("Processing macax_press_key tool call");
-
-                if !self.config.macax.enabled {
-                    return Ok(
-                        "❌ macOS Accessibility is not enabled. Use --macax flag to enable."
-                            .to_string(),
-                    );
-                }
+        }
 
-                let app_name = match tool_call.args.get("app_name").and_then(|v| v.as_str()) {
-                    Some(n) => n,
-                    None => return Ok("❌ Missing app_name argument".to_string()),
-                };
+        // Tools of turned-off groups aren't offered, but a model may still call one
+        if let Some(spec) = ToolRegistry::builtin().get(&tool_call.tool) {
+            if !self.tool_specs().iter().any(|enabled| enabled.name == spec.name) {
+                warn!("Blocked call of disabled tool {}", spec.name);
+                return Ok(format!(
+                    "❌ {} is not available in this session: {}. Use only the tools you were given.",
+                    spec.name,
+                    spec.group.unavailable_reason()
+                ));
+            }
+        }
 
-                let key = match tool_call.args.get("key").and_then(|v| v.as_str()) {
-                    Some(k) => k,
-                    None => return Ok("❌ Missing key argument".to_string()),
-                };
+        if let Err(invalid) = ToolRegistry::builtin().validate(&tool_call.tool, &tool_call.args) {
+            warn!("Rejected call of {}: {:?}", invalid.tool, invalid.problems);
+            return Ok(format!("❌ {}", invalid));
+        }
 
-                let modifiers_vec: Vec<&str> = tool_call
-                    .args
-                    .get("modifiers")
-                    .and_then(|v| v.as_array())
-                    .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
-                    .unwrap_or_default();
+        let budget = self.tool_budgets.lock().unwrap().record(&tool_call.tool, Instant::now());
+        if let Err(exceeded) = budget {
+            warn!("Refused call of {}: {}", tool_call.tool, exceeded);
+            return Ok(format!("❌ {}", exceeded));
+        }
 
-                let controller_guard = self.macax_controller.read().await;
-                let controller = match controller_guard.as_ref() {
-                    Some(c) => c,
-                    None => {
-                        return Ok("❌ macOS Accessibility controller not initialized.".to_string())
-                    }
-                };
+        // Remote files are the host's, so the local bookkeeping below doesn't apply to them
+        if let Some(target) = &self.remote {
+            if remote::LOCAL_EDIT_TOOLS.contains(&tool_call.tool.as_str()) {
+                warn!("Refused call of {} on a remote session", tool_call.tool);
+                return Ok(format!("❌ {}", remote::refusal(&tool_call.tool, target)));
+            }
+            if remote::REMOTE_TOOLS.contains(&tool_call.tool.as_str()) {
+                return self.dispatch_tool(tool_call).await;
+            }
+        }
 
-                match controller.press_key(app_name, key, modifiers_vec.clone()) {
-                    Ok(_) => {
-                        let modifier_str = if modifiers_vec.is_empty() {
-                            String::new()
-                        } else {
-                            format!(" with modifiers: {}", modifiers_vec.join("+"))
-                        };
-                        Ok(format!("✅ Pressed key: {}{}", key, modifier_str))
-                    }
-                    Err(e) => Ok(format!("❌ Failed to press key: {}", e)),
-                }
+        // Hold the file while it is written, and don't overwrite changes the agent hasn't seen
+        let _lock = match self.lock_file_for_write(tool_call).await {
+            Ok(lock) => lock,
+            Err(conflict) => {
+                warn!("{}", conflict);
+                return Ok(format!("❌ {}", conflict));
             }
-            "macax_type_text" => {
-                debug!("Processing macax_type_text tool call");
+        };
 
-                if !self.config.macax.enabled {
-                    return Ok(
-                        "❌ macOS Accessibility is not enabled. Use --macax flag to enable."
-                            .to_string(),
-                    );
+        self.snapshot_round_before(tool_call);
+        self.start_edit_commits(tool_call).await;
+
+        // Keep what file tools are about to overwrite, so their changes can be undone
+        let snapshot = undo::Snapshot::take(tool_call);
+        let watch = file_changes::FileWatch::take(tool_call);
+        // Whether the file parsed before the edit decides which syntax errors are the edit's
+        let syntax_check = file_lock::target_path(tool_call)
+            .filter(|_| self.config.syntax_check.enabled && tool_call.tool != "read_file")
+            .and_then(|path| syntax_check::SyntaxCheck::before_edit(&path));
+        // A download is waited for from the start of the action before, which started it
+        if tool_call.tool.starts_with("webdriver_") && tool_call.tool != "webdriver_wait_for_download"
+        {
+            *self.webdriver_last_call.lock().unwrap() = Some(std::time::SystemTime::now());
+        }
+        // A browser session that died since the last call is restarted before this one
+        let webdriver_note = match self.check_webdriver(tool_call).await {
+            Ok(note) => note,
+            Err(diagnosis) => return Ok(format!("❌ {}", diagnosis)),
+        };
+        let result = self.dispatch_tool(tool_call).await;
+        let result = match webdriver_note {
+            Some(note) => result.map(|output| format!("{}\n{}", note, output)),
+            None => result,
+        };
+        if let Some(snapshot) = snapshot {
+            if let Err(e) = self.undo_stack().push_if_changed(snapshot) {
+                warn!("Failed to record file change for undo: {}", e);
+            }
+        }
+        if let Some(watch) = watch {
+            self.record_file_changes(watch);
+        }
+        self.record_file_versions(tool_call, &result);
+        let result = match (result, syntax_check) {
+            (Ok(output), Some(check)) if output.starts_with('✅') => {
+                match check.after_edit(&self.config.syntax_check).await {
+                    Some(report) => Ok(format!("{}\n{}", output, report)),
+                    None => Ok(output),
                 }
+            }
+            (result, _) => result,
+        };
+        let edited = undo::FILE_TOOLS.contains(&tool_call.tool.as_str());
+        if edited && matches!(&result, Ok(output) if output.starts_with('✅')) {
+            self.commit_edit(tool_call).await;
+        }
+        result
+    }
 
-                let app_name = match tool_call.args.get("app_name").and_then(|v| v.as_str()) {
-                    Some(n) => n,
-                    None => return Ok("❌ Missing app_name argument".to_string()),
-                };
+    /// Start the `commit_edits` session branch before the first file edit of the session
+    async fn start_edit_commits(&self, tool_call: &ToolCall) {
+        // Autonomous runs that commit rounds have a branch of their own
+        if !self.config.agent.commit_edits
+            || self.is_coach
+            || (self.is_autonomous && self.config.agent.commit_rounds)
+            || !undo::FILE_TOOLS.contains(&tool_call.tool.as_str())
+            || self
+                .edit_commits_started
+                .swap(true, std::sync::atomic::Ordering::SeqCst)
+        {
+            return;
+        }
+        let workspace = std::env::current_dir().unwrap_or_else(|_| ".".into());
+        let message = match edit_commits::EditCommits::start(&workspace) {
+            Ok(Some(commits)) => {
+                let message = format!("🌿 Committing each edit to {}", commits.branch);
+                *self.edit_commits.lock().await = Some(commits);
+                message
+            }
+            Ok(None) => "⚠️ Not committing edits: the workspace has no git history".to_string(),
+            Err(e) => format!("⚠️ Not committing edits: {}", e),
+        };
+        self.events.publish(events::AgentEvent::Status { message });
+    }
 
-                let text = match tool_call.args.get("text").and_then(|v| v.as_str()) {
-                    Some(t) => t,
-                    None => return Ok("❌ Missing text argument".to_string()),
-                };
+    /// Commit the file a successful edit changed to the session branch, with a subject the
+    /// model writes from the diff
+    async fn commit_edit(&self, tool_call: &ToolCall) {
+        let mut edit_commits = self.edit_commits.lock().await;
+        let (Some(commits), Some(path)) =
+            (edit_commits.as_mut(), file_lock::target_path(tool_call))
+        else {
+            return;
+        };
+        let diff = match commits.stage(&path) {
+            Ok(Some(diff)) => diff,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to stage {} for commit: {}", path.display(), e);
+                return;
+            }
+        };
+        let subject = match self.edit_commit_subject(&diff).await {
+            Ok(Some(subject)) => Some(subject),
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Failed to write a commit message: {}", e);
+                None
+            }
+        };
+        let subject = subject.unwrap_or_else(|| {
+            let workspace = std::env::current_dir().unwrap_or_default();
+            edit_commits::fallback_subject(&tool_call.tool, &path, &workspace)
+        });
+        match commits.commit(&subject, &tool_call.tool) {
+            Ok(hash) => self.events.publish(events::AgentEvent::Status {
+                message: format!("📝 {} {}", hash, subject),
+            }),
+            Err(e) => warn!("Failed to commit {}: {}", path.display(), e),
+        }
+    }
 
-                let controller_guard = self.macax_controller.read().await;
-                let controller = match controller_guard.as_ref() {
-                    Some(c) => c,
-                    None => {
-                        return Ok("❌ macOS Accessibility controller not initialized.".to_string())
-                    }
-                };
+    /// A one-line commit subject for `diff`, from the summarizer model if there is one
+    async fn edit_commit_subject(&self, diff: &str) -> Result<Option<String>> {
+        let summarizer = self.summarizer_provider().await;
+        let provider = self.providers.get(summarizer)?;
+        let mut request = CompletionRequest {
+            messages: vec![Message {
+                role: MessageRole::User,
+                content: edit_commits::message_prompt(diff),
+            }],
+            max_tokens: Some(64),
+            temperature: Some(0.2),
+            stream: false,
+            tools: None,
+            tool_choice: ToolChoice::Auto,
+            stop_sequences: Vec::new(),
+        };
+        self.moderate_request(&mut request).await?;
+        let response = provider.complete(request).await?;
+        Ok(edit_commits::parse_subject(&response.content))
+    }
 
-                match controller.type_text(app_name, text) {
-                    Ok(_) => Ok(format!("✅ Typed text into {}", app_name)),
-                    Err(e) => Ok(format!("❌ Failed to type text: {}", e)),
-                }
-            }
-            "vision_find_text" => {
-                debug!("Processing vision_find_text tool call");
-
-                if let Some(controller) = &self.computer_controller {
-                    let app_name = tool_call
-                        .args
-                        .get("app_name")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing app_name parameter"))?;
-
-                    let text = tool_call
-                        .args
-                        .get("text")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing text parameter"))?;
-
-                    match controller.find_text_in_app(app_name, text).await {
-                        Ok(Some(location)) => {
-                            Ok(format!(
-                                "✅ Found '{}' in {} at position ({}, {}) with size {}x{} (confidence: {:.0}%)",
-                                location.text, app_name, location.x, location.y, location.width, location.height,
-                                location.confidence * 100.0
-                            ))
-                        }
-                        Ok(None) => Ok(format!("❌ Could not find '{}' in {}", text, app_name)),
-                        Err(e) => Ok(format!("❌ Error finding text: {}", e)),
-                    }
-                } else {
-                    Ok("❌ Computer control not enabled. Set computer_control.enabled = true in config.".to_string())
+    /// Lock the file a file tool is about to write, waiting a little for another writer to
+    /// finish, and check that nobody changed it since the agent last saw it. `None` for
+    /// tools that don't write files.
+    async fn lock_file_for_write(
+        &self,
+        tool_call: &ToolCall,
+    ) -> std::result::Result<Option<file_lock::FileLock>, file_lock::FileConflict> {
+        if !undo::FILE_TOOLS.contains(&tool_call.tool.as_str()) {
+            return Ok(None);
+        }
+        let Some(path) = file_lock::target_path(tool_call) else {
+            return Ok(None);
+        };
+        let started = Instant::now();
+        let lock = loop {
+            match self.file_locks.try_lock(&path) {
+                Ok(lock) => break lock,
+                Err(conflict) if started.elapsed() >= file_lock::LOCK_WAIT => {
+                    return Err(conflict)
                 }
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
             }
-            "vision_click_text" => {
-                debug!("Processing vision_click_text tool call");
-
-                if let Some(controller) = &self.computer_controller {
-                    let app_name = tool_call
-                        .args
-                        .get("app_name")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing app_name parameter"))?;
-
-                    let text = tool_call
-                        .args
-                        .get("text")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing text parameter"))?;
-
-                    match controller.find_text_in_app(app_name, text).await {
-                        Ok(Some(location)) => {
-                            // Click on center of text
-                            // IMPORTANT: location coordinates are in NSScreen space (Y=0 at BOTTOM, increases UPWARD)
-                            // location.x is the LEFT edge of the bounding box
-                            // location.y is the TOP edge of the bounding box (highest Y value in NSScreen space)
-                            // location.width and location.height are already scaled to screen space
-                            // To get center: we need to add half the SCALED width and subtract half the SCALED height
-
-                            if location.width == 0 || location.height == 0 {
-                                return Ok(format!(
-                                    "❌ Invalid bounding box dimensions: width={}, height={}",
-                                    location.width, location.height
-                                ));
-                            }
+        };
+        self.file_versions.lock().unwrap().check(&path)?;
+        Ok(Some(lock))
+    }
 
-                            debug!("[vision_click_text] Location from find_text_in_app: x={}, y={}, width={}, height={}, text='{}'",
-                                location.x, location.y, location.width, location.height, location.text);
-
-                            // Calculate center using the SCALED dimensions
-                            // X: Use right edge instead of center (Vision OCR bounding box seems offset)
-                            // This gives us: left edge + full width = right edge
-                            // Y: top edge - half of scaled height (subtract because Y increases upward)
-                            let click_x = location.x + location.width; // Right edge
-                            let half_height = location.height / 2;
-                            let click_y = location.y - half_height;
-
-                            debug!("[vision_click_text] Click position calculation: x={} + {} = {} (right edge), y={} - {} = {}",
-                                location.x, location.width, click_x, location.y, half_height, click_y);
-                            debug!("[vision_click_text] This means: left_edge={}, center={}, right_edge={}",
-                                location.x, click_x, location.x + location.width);
-
-                            match controller.click_at(click_x, click_y, Some(app_name)) {
-                                Ok(_) => Ok(format!(
-                                    "✅ Clicked on '{}' in {} at ({}, {})",
-                                    text, app_name, click_x, click_y
-                                )),
-                                Err(e) => Ok(format!("❌ Failed to click: {}", e)),
-                            }
-                        }
-                        Ok(None) => Ok(format!("❌ Could not find '{}' in {}", text, app_name)),
-                        Err(e) => Ok(format!("❌ Error finding text: {}", e)),
-                    }
-                } else {
-                    Ok("❌ Computer control not enabled. Set computer_control.enabled = true in config.".to_string())
+    /// Remember the version of the file a file tool read or wrote. After a shell command
+    /// that may have changed files, or an undo, every remembered version is brought up to
+    /// date, since those changes are the agent's own.
+    fn record_file_versions(&self, tool_call: &ToolCall, result: &Result<String>) {
+        let mut versions = self.file_versions.lock().unwrap();
+        match tool_call.tool.as_str() {
+            "shell" => {
+                let read_only = tool_call
+                    .args
+                    .get("command")
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|command| read_only::check_shell_command(command).is_ok());
+                if !read_only {
+                    versions.refresh();
                 }
             }
-            "extract_text_with_boxes" => {
-                debug!("Processing extract_text_with_boxes tool call");
-
-                if !self.config.macax.enabled {
-                    return Ok(
-                        "❌ extract_text_with_boxes requires --macax flag to be enabled"
-                            .to_string(),
-                    );
+            "undo_edit" => versions.refresh(),
+            _ => {
+                let succeeded = matches!(result, Ok(output) if !output.starts_with('❌'));
+                if let (true, Some(path)) = (succeeded, file_lock::target_path(tool_call)) {
+                    versions.record(&path);
                 }
+            }
+        }
+    }
 
-                if let Some(controller) = &self.computer_controller {
-                    let path = tool_call
-                        .args
-                        .get("path")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing path parameter"))?;
+    /// `path` with a leading `~` expanded, unless it is a path on the remote host
+    fn expand_tilde<'a>(&self, path: &'a str) -> std::borrow::Cow<'a, str> {
+        match self.remote {
+            Some(_) => std::borrow::Cow::Borrowed(path),
+            None => shellexpand::tilde(path),
+        }
+    }
 
-                    // Optional: take screenshot of app first
-                    let final_path = if let Some(app_name) =
-                        tool_call.args.get("app_name").and_then(|v| v.as_str())
-                    {
-                        let temp_path =
-                            format!("/tmp/g3_extract_boxes_{}.png", uuid::Uuid::new_v4());
-                        match controller
-                            .take_screenshot(&temp_path, None, Some(app_name))
-                            .await
-                        {
-                            Ok(_) => temp_path,
-                            Err(e) => return Ok(format!("❌ Failed to take screenshot: {}", e)),
-                        }
-                    } else {
-                        path.to_string()
-                    };
+    /// Variables added to shell commands: the env files', unless commands run on a remote
+    /// host that they aren't forwarded to
+    fn command_env(&self) -> Vec<(String, String)> {
+        match self.remote {
+            Some(_) if !self.config.remote.forward_env => Vec::new(),
+            _ => self.project_env.command_vars(),
+        }
+    }
 
-                    // Extract text with locations
-                    match controller.extract_text_with_locations(&final_path).await {
-                        Ok(locations) => {
-                            // Clean up temp file if we created one
-                            if final_path != path {
-                                let _ = std::fs::remove_file(&final_path);
-                            }
+    /// The contents of a file for read_file, from the remote host if there is one
+    async fn read_file_bytes(&self, path: &str) -> Result<Vec<u8>> {
+        match &self.remote {
+            Some(remote) => remote.read_file(path).await,
+            None => Ok(std::fs::read(path)?),
+        }
+    }
 
-                            // Return as JSON
-                            match serde_json::to_string_pretty(&locations) {
-                                Ok(json) => Ok(format!(
-                                    "✅ Extracted {} text elements:\n{}",
-                                    locations.len(),
-                                    json
-                                )),
-                                Err(e) => Ok(format!("❌ Failed to serialize results: {}", e)),
-                            }
-                        }
-                        Err(e) => Ok(format!("❌ Failed to extract text: {}", e)),
-                    }
-                } else {
-                    Ok("❌ Computer control not enabled. Set computer_control.enabled = true in config.".to_string())
+    /// Run the call with the handler of its built-in tool, or else as a Unity Catalog,
+    /// plugin or registered tool
+    async fn dispatch_tool(&self, tool_call: &ToolCall) -> Result<String> {
+        if let Some(handler) = self.tool_handlers.get(&tool_call.tool) {
+            return handler.call(self, tool_call).await;
+        }
+        match tool_call.tool.as_str() {
+            name if unity_catalog::UnityCatalogTools::is_tool(name) => {
+                let Some(uc) = &self.unity_catalog else {
+                    return Ok(format!("❓ Unknown tool: {}", name));
+                };
+                match uc.call(name, &tool_call.args).await {
+                    Ok(result) => Ok(format!("✅ {}\n{}", name, result)),
+                    Err(e) => Ok(format!("❌ {} failed: {}", name, e)),
                 }
             }
-            "vision_click_near_text" => {
-                debug!("Processing vision_click_near_text tool call");
-
-                if let Some(controller) = &self.computer_controller {
-                    let app_name = tool_call
-                        .args
-                        .get("app_name")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing app_name parameter"))?;
-
-                    let text = tool_call
-                        .args
-                        .get("text")
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| anyhow::anyhow!("Missing text parameter"))?;
-
-                    let direction = tool_call
-                        .args
-                        .get("direction")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("right");
-
-                    let distance = tool_call
-                        .args
-                        .get("distance")
-                        .and_then(|v| v.as_i64())
-                        .unwrap_or(50) as i32;
-
-                    match controller.find_text_in_app(app_name, text).await {
-                        Ok(Some(location)) => {
-                            // Calculate click position based on direction
-                            // location.x is LEFT edge, location.y is TOP edge (in NSScreen space)
-                            let (click_x, click_y) = match direction {
-                                "right" => (
-                                    location.x + location.width + distance,
-                                    location.y - (location.height / 2),
-                                ),
-                                "below" => (
-                                    location.x + (location.width / 2),
-                                    location.y - location.height - distance,
-                                ),
-                                "left" => {
-                                    (location.x - distance, location.y - (location.height / 2))
-                                }
-                                "above" => {
-                                    (location.x + (location.width / 2), location.y + distance)
-                                }
-                                _ => (
-                                    location.x + location.width + distance,
-                                    location.y - (location.height / 2),
-                                ),
-                            };
-                            debug!(
-                                "[vision_click_near_text] Clicking {} of text at ({}, {})",
-                                direction, click_x, click_y
-                            );
-
-                            match controller.click_at(click_x, click_y, Some(app_name)) {
-                                Ok(_) => Ok(format!(
-                                    "✅ Clicked {} of '{}' in {} at ({}, {})",
-                                    direction, text, app_name, click_x, click_y
-                                )),
-                                Err(e) => Ok(format!("❌ Failed to click: {}", e)),
-                            }
-                        }
-                        Ok(None) => Ok(format!("❌ Could not find '{}' in {}", text, app_name)),
-                        Err(e) => Ok(format!("❌ Error finding text: {}", e)),
-                    }
-                } else {
-                    Ok("❌ Computer control not enabled. Set computer_control.enabled = true in config.".to_string())
+            name if self.plugins.find(name).is_some() => {
+                let Some((plugin, tool)) = self.plugins.find(name) else {
+                    return Ok(format!("❓ Unknown tool: {}", name));
+                };
+                if self.config.agent.read_only && !tool.read_only {
+                    return Ok(format!(
+                        "❌ {} is disabled in read-only mode. Describe the change instead of making it.",
+                        name
+                    ));
+                }
+                match plugin.call(&tool.name, &tool_call.args).await {
+                    Ok(result) if result.is_empty() => Ok(format!("✅ {}", name)),
+                    Ok(result) => Ok(result),
+                    Err(e) => Ok(format!("❌ {} failed: {}", name, e)),
                 }
             }
-            "code_search" => {
-                debug!("Processing code_search tool call");
-
-                // Parse the request
-                let request: crate::code_search::CodeSearchRequest =
-                    match serde_json::from_value(tool_call.args.clone()) {
-                        Ok(req) => req,
-                        Err(e) => {
-                            return Ok(format!("❌ Invalid code_search arguments: {}", e));
-                        }
-                    };
-
-                // Execute the code search
-                match crate::code_search::execute_code_search(request).await {
-                    Ok(response) => {
-                        // Serialize the response to JSON
-                        match serde_json::to_string_pretty(&response) {
-                            Ok(json_output) => {
-                                Ok(format!("✅ Code search completed\n{}", json_output))
-                            }
-                            Err(e) => Ok(format!("❌ Failed to serialize response: {}", e)),
-                        }
-                    }
-                    Err(e) => {
-                        Ok(format!("❌ Code search failed: {}", e))
-                    }
+            name if self.custom_tools.find(name).is_some() => {
+                let Some(tool) = self.custom_tools.find(name) else {
+                    return Ok(format!("❓ Unknown tool: {}", name));
+                };
+                if self.config.agent.read_only && !tool.read_only {
+                    return Ok(format!(
+                        "❌ {} is disabled in read-only mode. Describe the change instead of making it.",
+                        name
+                    ));
+                }
+                match tool.call(tool_call.args.clone()).await {
+                    Ok(result) if result.is_empty() => Ok(format!("✅ {}", name)),
+                    Ok(result) => Ok(result),
+                    Err(e) => Ok(format!("❌ {} failed: {}", name, e)),
                 }
             }
             _ => {
@@ -5215,21 +4924,6 @@ If you can complete it with 1-2 tool calls, skip TODO.
             }
         }
     }
-
-    fn format_duration(duration: Duration) -> String {
-        let total_ms = duration.as_millis();
-
-        if total_ms < 1000 {
-            format!("{}ms", total_ms)
-        } else if total_ms < 60_000 {
-            let seconds = duration.as_secs_f64();
-            format!("{:.1}s", seconds)
-        } else {
-            let minutes = total_ms / 60_000;
-            let remaining_seconds = (total_ms % 60_000) as f64 / 1000.0;
-            format!("{}m {:.1}s", minutes, remaining_seconds)
-        }
-    }
 }
 
 // Note: JSON tool call filtering is now handled by fixed_filter_json::fixed_filter_json_tool_calls
@@ -5498,6 +5192,148 @@ fn shell_escape_command(command: &str) -> String {
     }
 }
 
+/// Convert a 1-indexed, inclusive line range into a [start, end) character range
+fn line_range_to_char_range(
+    content: &str,
+    start_line: Option<usize>,
+    end_line: Option<usize>,
+) -> std::result::Result<(usize, usize), String> {
+    let total_lines = content.lines().count();
+    let start_line = start_line.unwrap_or(1).max(1);
+    let end_line = end_line.unwrap_or(total_lines).min(total_lines);
+
+    if start_line > total_lines.max(1) {
+        return Err(format!(
+            "Start line {} exceeds file length of {} lines",
+            start_line, total_lines
+        ));
+    }
+    // An empty file has no lines, but reading or editing all of them is reading nothing
+    if total_lines == 0 {
+        return Ok((0, 0));
+    }
+    if start_line > end_line {
+        return Err(format!(
+            "Start line {} is greater than end line {}",
+            start_line, end_line
+        ));
+    }
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let start = line_starts[start_line - 1];
+    let end = line_starts.get(end_line).copied().unwrap_or(content.len());
+    Ok((start, end))
+}
+
+/// A duration as milliseconds, seconds or minutes and seconds
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let total_ms = duration.as_millis();
+
+    if total_ms < 1000 {
+        format!("{}ms", total_ms)
+    } else if total_ms < 60_000 {
+        let seconds = duration.as_secs_f64();
+        format!("{:.1}s", seconds)
+    } else {
+        let minutes = total_ms / 60_000;
+        let remaining_seconds = (total_ms % 60_000) as f64 / 1000.0;
+        format!("{}m {:.1}s", minutes, remaining_seconds)
+    }
+}
+
+/// The `timeout_seconds` of a WebDriver wait tool, within bounds
+fn webdriver_wait_timeout(args: &serde_json::Value) -> Duration {
+    let seconds = args
+        .get("timeout_seconds")
+        .and_then(|v| v.as_f64())
+        .filter(|s| s.is_finite() && *s > 0.0)
+        .unwrap_or(WEBDRIVER_WAIT_DEFAULT.as_secs_f64());
+    Duration::from_secs_f64(seconds.min(WEBDRIVER_WAIT_MAX.as_secs_f64()))
+}
+
+/// The tab a WebDriver tool names with its 1-based `index` or its `handle`, among the open
+/// `handles`. `None` if it names none.
+fn webdriver_tab_handle(
+    handles: &[String],
+    args: &serde_json::Value,
+) -> std::result::Result<Option<String>, String> {
+    if let Some(handle) = args.get("handle").and_then(|v| v.as_str()) {
+        return match handles.iter().find(|h| *h == handle) {
+            Some(handle) => Ok(Some(handle.clone())),
+            None => Err(format!(
+                "No open tab has handle {}. Call webdriver_list_tabs to see the open tabs.",
+                handle
+            )),
+        };
+    }
+    match args.get("index").and_then(|v| v.as_u64()) {
+        Some(index) => match (index as usize).checked_sub(1).and_then(|i| handles.get(i)) {
+            Some(handle) => Ok(Some(handle.clone())),
+            None => Err(format!(
+                "No tab {}: {} tab(s) are open. Call webdriver_list_tabs to see them.",
+                index,
+                handles.len()
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+/// An OpenAI provider with the API, reasoning effort and context window from its config
+fn configure_openai(
+    provider: g3_providers::OpenAIProvider,
+    config: &g3_config::OpenAIConfig,
+) -> g3_providers::OpenAIProvider {
+    let provider = provider
+        .with_reasoning_effort(config.reasoning_effort.clone())
+        .with_context_length(config.context_length)
+        .with_compression(config.compress_requests);
+    match config.api {
+        Some(g3_config::OpenAIApi::ChatCompletions) => {
+            provider.with_api(g3_providers::openai::OpenAIApi::ChatCompletions)
+        }
+        Some(g3_config::OpenAIApi::Responses) => {
+            provider.with_api(g3_providers::openai::OpenAIApi::Responses)
+        }
+        None => provider,
+    }
+}
+
+/// A request to a summarizer to condense tool output, used by summarize-in-place thinning
+/// and for tool results over their `[context.tool_results]` limit
+fn tool_output_summary_request(content: &str, max_tokens: u32) -> CompletionRequest {
+    CompletionRequest {
+        messages: vec![
+            Message {
+                role: MessageRole::System,
+                content: "You condense tool output for a coding agent. Keep file paths, identifiers, error messages and numbers that may matter later; drop repetition and boilerplate.".to_string(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: format!("Summarize this tool output briefly:\n\n{}", content),
+            },
+        ],
+        max_tokens: Some(max_tokens),
+        temperature: Some(0.1),
+        stream: false,
+        tools: None,
+        tool_choice: ToolChoice::Auto,
+        stop_sequences: Vec::new(),
+    }
+}
+
+/// Prefix each line with its line number, right-aligned to the width of `last_line`
+fn number_lines(text: &str, first_line: usize, last_line: usize) -> String {
+    let width = last_line.max(first_line).to_string().len();
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| format!("{:>width$} | {}", first_line + i, line, width = width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 // Helper function to fix mixed quotes in JSON strings
 #[allow(dead_code)]
 fn fix_nested_quotes_in_shell_command(json_str: &str) -> String {
@@ -5612,7 +5448,10 @@ fn fix_mixed_quotes_in_json(json_str: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::parse_unified_diff_hunks;
+    use super::{
+        line_range_to_char_range, number_lines, parse_unified_diff_hunks, webdriver_tab_handle,
+        webdriver_wait_timeout,
+    };
 
     #[test]
     fn parses_minimal_unified_diff_without_hunk_header() {
@@ -5631,6 +5470,57 @@ mod tests {
         assert_eq!(hunks[0].0, "common\nold\ncommon2");
         assert_eq!(hunks[0].1, "common\nnew\ncommon2");
     }
+
+    #[test]
+    fn converts_line_range_to_char_range() {
+        let content = "one\ntwo\nthree\nfour";
+        let (start, end) = line_range_to_char_range(content, Some(2), Some(3)).unwrap();
+        assert_eq!(&content[start..end], "two\nthree\n");
+
+        let (start, end) = line_range_to_char_range(content, Some(4), None).unwrap();
+        assert_eq!(&content[start..end], "four");
+
+        assert!(line_range_to_char_range(content, Some(9), None).is_err());
+        assert!(line_range_to_char_range(content, Some(3), Some(2)).is_err());
+    }
+
+    #[test]
+    fn converts_line_range_of_empty_file_to_empty_range() {
+        assert_eq!(line_range_to_char_range("", None, None), Ok((0, 0)));
+        assert_eq!(line_range_to_char_range("", Some(1), Some(5)), Ok((0, 0)));
+        let error = line_range_to_char_range("", Some(2), None).unwrap_err();
+        assert_eq!(error, "Start line 2 exceeds file length of 0 lines");
+    }
+
+    #[test]
+    fn numbers_lines_from_offset() {
+        let numbered = number_lines("a\nb", 9, 10);
+        assert_eq!(numbered, " 9 | a\n10 | b");
+    }
+
+    #[test]
+    fn resolves_webdriver_tab_by_index_or_handle() {
+        let handles = vec!["main".to_string(), "popup".to_string()];
+        let resolve = |args| webdriver_tab_handle(&handles, &args);
+        assert_eq!(resolve(serde_json::json!({"index": 2})), Ok(Some("popup".to_string())));
+        assert_eq!(
+            resolve(serde_json::json!({"handle": "main", "index": 2})),
+            Ok(Some("main".to_string()))
+        );
+        assert_eq!(resolve(serde_json::json!({})), Ok(None));
+        assert!(resolve(serde_json::json!({"index": 0})).is_err());
+        assert!(resolve(serde_json::json!({"index": 3})).is_err());
+        assert!(resolve(serde_json::json!({"handle": "gone"})).is_err());
+    }
+
+    #[test]
+    fn bounds_webdriver_wait_timeout() {
+        let timeout = |args| webdriver_wait_timeout(&args).as_secs_f64();
+        assert_eq!(timeout(serde_json::json!({})), 10.0);
+        assert_eq!(timeout(serde_json::json!({"timeout_seconds": 2.5})), 2.5);
+        assert_eq!(timeout(serde_json::json!({"timeout_seconds": 600})), 120.0);
+        assert_eq!(timeout(serde_json::json!({"timeout_seconds": -1})), 10.0);
+    }
 }
 
 #[cfg(test)]
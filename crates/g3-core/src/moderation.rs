@@ -0,0 +1,337 @@
+//! Moderation hooks for organizations that need to enforce a policy on what the agent sends
+//! and receives.
+//!
+//! A hook is a shell command from `[moderation]`. It is run once per check with a JSON
+//! document on stdin:
+//!
+//! - `pre_send`: `{"hook": "pre_send", "subject": "messages", "messages": [...]}` before each
+//!   request to the model
+//! - `post_receive`: `{"hook": "post_receive", "subject": "tool_call", "tool": ..., "args": ...}`
+//!   before each tool call runs, and `{"hook": "post_receive", "subject": "response",
+//!   "content": ...}` for the model's final response to a task
+//!
+//! and replies on stdout with `{"action": "allow"}`, `{"action": "block", "reason": ...}` or
+//! `{"action": "modify", ...}` with replacement `messages`, `args` or `content`. An empty reply
+//! allows. Every decision is appended to `moderation_audit.jsonl` in the session directory.
+
+use chrono::{DateTime, Utc};
+use g3_config::ModerationConfig;
+use g3_providers::Message;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::ToolCall;
+
+/// Audit log of a session's moderation decisions, in its session directory
+pub const AUDIT_LOG_FILE: &str = "moderation_audit.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookPoint {
+    PreSend,
+    PostReceive,
+}
+
+/// What a hook decided about the content it was given
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision<T> {
+    Allow,
+    /// Use this instead
+    Modify(T),
+    /// Don't send or use the content, for this reason
+    Block(String),
+}
+
+/// One line of a session's moderation audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModerationEntry {
+    pub timestamp: DateTime<Utc>,
+    pub hook: HookPoint,
+    /// `messages`, `response` or `tool_call:<tool name>`
+    pub subject: String,
+    /// `allow`, `modify`, `block`, or `error` when the hook itself failed
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    #[default]
+    Allow,
+    Modify,
+    Block,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Reply {
+    #[serde(default)]
+    action: Action,
+    reason: Option<String>,
+    #[serde(flatten)]
+    fields: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Moderator {
+    config: ModerationConfig,
+}
+
+impl Moderator {
+    pub fn new(config: ModerationConfig) -> Self {
+        Self { config }
+    }
+
+    /// Check the messages of a request before it is sent
+    pub async fn pre_send(&self, messages: &[Message], log: &Path) -> Decision<Vec<Message>> {
+        let Some(command) = &self.config.pre_send else {
+            return Decision::Allow;
+        };
+        let input =
+            json!({"hook": HookPoint::PreSend, "subject": "messages", "messages": messages});
+        self.check(
+            HookPoint::PreSend,
+            command,
+            "messages",
+            input,
+            "messages",
+            log,
+        )
+        .await
+    }
+
+    /// Whether tool calls go through a hook, which may block or change them
+    pub fn checks_tool_calls(&self) -> bool {
+        self.config.post_receive.is_some()
+    }
+
+    /// Check a tool call the model made before it runs; a modification replaces its arguments
+    pub async fn tool_call(&self, tool_call: &ToolCall, log: &Path) -> Decision<Value> {
+        let Some(command) = &self.config.post_receive else {
+            return Decision::Allow;
+        };
+        let input = json!({
+            "hook": HookPoint::PostReceive,
+            "subject": "tool_call",
+            "tool": tool_call.tool,
+            "args": tool_call.args,
+        });
+        let subject = format!("tool_call:{}", tool_call.tool);
+        self.check(
+            HookPoint::PostReceive,
+            command,
+            &subject,
+            input,
+            "args",
+            log,
+        )
+        .await
+    }
+
+    /// Check the model's final response to a task
+    pub async fn response(&self, content: &str, log: &Path) -> Decision<String> {
+        let Some(command) = &self.config.post_receive else {
+            return Decision::Allow;
+        };
+        let input =
+            json!({"hook": HookPoint::PostReceive, "subject": "response", "content": content});
+        self.check(
+            HookPoint::PostReceive,
+            command,
+            "response",
+            input,
+            "content",
+            log,
+        )
+        .await
+    }
+
+    /// Run `command` on `input` and read the replacement from `field` of a `modify` reply
+    async fn check<T: DeserializeOwned>(
+        &self,
+        hook: HookPoint,
+        command: &str,
+        subject: &str,
+        input: Value,
+        field: &str,
+        log: &Path,
+    ) -> Decision<T> {
+        let decision =
+            self.run(command, &input)
+                .await
+                .and_then(|reply| match reply.action {
+                    Action::Allow => Ok(Decision::Allow),
+                    Action::Block => Ok(Decision::Block(
+                        reply
+                            .reason
+                            .unwrap_or_else(|| "no reason given".to_string()),
+                    )),
+                    Action::Modify => {
+                        let value = reply.fields.get(field).cloned().ok_or_else(|| {
+                            anyhow::anyhow!("\"modify\" reply without \"{}\"", field)
+                        })?;
+                        Ok(Decision::Modify(serde_json::from_value(value)?))
+                    }
+                });
+
+        let (decision, action, reason) = match decision {
+            Ok(decision) => {
+                let (action, reason) = match &decision {
+                    Decision::Allow => ("allow", None),
+                    Decision::Modify(_) => ("modify", None),
+                    Decision::Block(reason) => ("block", Some(reason.clone())),
+                };
+                (decision, action, reason)
+            }
+            Err(e) => {
+                warn!("Moderation hook '{}' failed on {}: {}", command, subject, e);
+                let reason = format!("Moderation hook failed: {}", e);
+                let decision = if self.config.fail_closed {
+                    Decision::Block(reason.clone())
+                } else {
+                    Decision::Allow
+                };
+                (decision, "error", Some(reason))
+            }
+        };
+
+        let entry = ModerationEntry {
+            timestamp: Utc::now(),
+            hook,
+            subject: subject.to_string(),
+            action: action.to_string(),
+            reason,
+        };
+        if let Err(e) = append(&entry, log) {
+            warn!("Failed to write moderation audit log: {}", e);
+        }
+        decision
+    }
+
+    async fn run(&self, command: &str, input: &Value) -> anyhow::Result<Reply> {
+        let mut child = tokio::process::Command::new("bash")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let input = input.to_string();
+        let exchange = async move {
+            if let Some(mut stdin) = child.stdin.take() {
+                // A hook that decides without reading its input may already have exited
+                if let Err(e) = stdin.write_all(input.as_bytes()).await {
+                    if e.kind() != std::io::ErrorKind::BrokenPipe {
+                        return Err(e);
+                    }
+                }
+            }
+            child.wait_with_output().await
+        };
+
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+        let output = tokio::time::timeout(timeout, exchange)
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out after {}s", timeout.as_secs()))??;
+        if !output.status.success() {
+            anyhow::bail!(
+                "exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(Reply::default());
+        }
+        Ok(serde_json::from_str(&stdout)?)
+    }
+}
+
+fn append(entry: &ModerationEntry, path: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(log, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moderator(post_receive: &str, fail_closed: bool) -> Moderator {
+        Moderator::new(ModerationConfig {
+            post_receive: Some(post_receive.to_string()),
+            fail_closed,
+            ..ModerationConfig::default()
+        })
+    }
+
+    fn call(command: &str) -> ToolCall {
+        ToolCall {
+            tool: "shell".to_string(),
+            args: json!({"command": command}),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hook_decisions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let log = dir.path().join(AUDIT_LOG_FILE);
+
+        // Blocks `rm`, rewrites `curl` and allows everything else with an empty reply
+        let hook = moderator(
+            r#"input=$(cat)
+case "$input" in
+  *'"rm '*) echo '{"action": "block", "reason": "no deleting"}' ;;
+  *curl*) echo '{"action": "modify", "args": {"command": "echo curl is not allowed"}}' ;;
+esac"#,
+            false,
+        );
+        assert_eq!(
+            hook.tool_call(&call("rm -rf build"), &log).await,
+            Decision::Block("no deleting".to_string())
+        );
+        assert_eq!(
+            hook.tool_call(&call("curl example.com"), &log).await,
+            Decision::Modify(json!({"command": "echo curl is not allowed"}))
+        );
+        assert_eq!(hook.tool_call(&call("ls"), &log).await, Decision::Allow);
+        // There is no pre_send hook
+        assert!(matches!(hook.pre_send(&[], &log).await, Decision::Allow));
+
+        // A failing hook allows unless the config says to fail closed
+        assert_eq!(
+            moderator("exit 3", false).response("hi", &log).await,
+            Decision::Allow
+        );
+        assert!(matches!(
+            moderator("echo not json", true).response("hi", &log).await,
+            Decision::Block(_)
+        ));
+
+        let entries: Vec<ModerationEntry> = std::fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let actions: Vec<&str> = entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["block", "modify", "allow", "error", "error"]);
+        assert_eq!(entries[0].subject, "tool_call:shell");
+        assert_eq!(entries[0].hook, HookPoint::PostReceive);
+    }
+}
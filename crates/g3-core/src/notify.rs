@@ -0,0 +1,170 @@
+//! Terminal bell and desktop notifications for `[notifications]`.
+//!
+//! A turn notifies when it took at least `min_turn_seconds` or ends with a question for the
+//! user. In autonomous mode individual turns don't notify; the run does when it ends.
+//! Desktop notifications go through `osascript` on macOS and `notify-send` elsewhere, and
+//! are skipped quietly when neither is available.
+
+use g3_config::NotificationsConfig;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::format_duration;
+
+/// Longest notification body, in characters
+const MAX_BODY_CHARS: usize = 200;
+
+/// A notification to show
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+}
+
+/// What a finished turn should notify about, if anything
+pub fn for_turn(
+    config: &NotificationsConfig,
+    elapsed: Duration,
+    success: bool,
+    response: &str,
+) -> Option<Notification> {
+    if !config.bell && !config.desktop {
+        return None;
+    }
+    if success && is_question(response) {
+        return Some(Notification {
+            title: "g3 has a question".to_string(),
+            body: truncate(last_line(response)),
+        });
+    }
+    if elapsed < Duration::from_secs(config.min_turn_seconds) {
+        return None;
+    }
+    Some(Notification {
+        title: if success {
+            format!("g3 finished after {}", format_duration(elapsed))
+        } else {
+            format!("g3 failed after {}", format_duration(elapsed))
+        },
+        body: truncate(first_line(response)),
+    })
+}
+
+/// Ring the bell and show the notification, as configured
+pub fn send(config: &NotificationsConfig, notification: &Notification) {
+    if config.bell {
+        let mut stderr = std::io::stderr();
+        let _ = stderr.write_all(b"\x07");
+        let _ = stderr.flush();
+    }
+    if config.desktop {
+        let mut command = if cfg!(target_os = "macos") {
+            let mut command = Command::new("osascript");
+            command.arg("-e").arg(format!(
+                "display notification {} with title {}",
+                applescript_string(&notification.body),
+                applescript_string(&notification.title)
+            ));
+            command
+        } else {
+            let mut command = Command::new("notify-send");
+            command
+                .arg("--app-name=g3")
+                .arg(&notification.title)
+                .arg(&notification.body);
+            command
+        };
+        match command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            // Reaped in the background so the turn isn't held up
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => debug!("Desktop notification failed: {}", e),
+        }
+    }
+}
+
+/// Whether the response ends by asking the user something
+fn is_question(response: &str) -> bool {
+    response
+        .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, '*' | '_' | '`'))
+        .ends_with('?')
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+}
+
+fn last_line(text: &str) -> &str {
+    text.lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .unwrap_or("")
+}
+
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_BODY_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// `text` as an AppleScript string literal
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turn_notifications() {
+        let config = NotificationsConfig {
+            bell: true,
+            desktop: false,
+            min_turn_seconds: 30,
+        };
+        let quick = Duration::from_secs(5);
+        let long = Duration::from_secs(95);
+
+        assert_eq!(for_turn(&config, quick, true, "Done."), None);
+        assert_eq!(
+            for_turn(&config, long, true, "\nAll tests pass.\nDetails follow."),
+            Some(Notification {
+                title: "g3 finished after 1m 35.0s".to_string(),
+                body: "All tests pass.".to_string(),
+            })
+        );
+        assert_eq!(
+            for_turn(
+                &config,
+                quick,
+                true,
+                "I found two configs.\n**Which one should I keep?**"
+            )
+            .unwrap()
+            .title,
+            "g3 has a question"
+        );
+        assert_eq!(
+            for_turn(&config, long, false, "timed out").unwrap().title,
+            "g3 failed after 1m 35.0s"
+        );
+        assert_eq!(
+            for_turn(&NotificationsConfig::default(), long, true, "Done."),
+            None
+        );
+        assert_eq!(applescript_string("say \"hi\""), "\"say \\\"hi\\\"\"");
+    }
+}
@@ -0,0 +1,243 @@
+//! Limits on OCR of images (`[computer_control.ocr]`).
+//!
+//! Before OCR an image is cropped to the region asked for and scaled down to
+//! `max_dimension`; the OCR runs with a timeout, and what it found is cut to `max_chars`.
+//! Results are cached by the hash of the image's content with the region and scale, so
+//! reading the same screenshot again is free.
+
+use anyhow::{anyhow, Result};
+use g3_computer_control::ocr::prepare::{prepare, PreparedImage};
+use g3_computer_control::types::{Rect, TextLocation};
+use g3_computer_control::ComputerController;
+use g3_config::OcrConfig;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Results kept of each kind of OCR; the oldest are dropped first
+const CACHE_SIZE: usize = 32;
+
+/// OCR results by the key of their image and options, oldest first
+#[derive(Debug)]
+struct Cache<V> {
+    entries: HashMap<String, V>,
+    order: VecDeque<String>,
+}
+
+impl<V: Clone> Cache<V> {
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<V> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: String, value: V) {
+        if self.entries.insert(key.clone(), value).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > CACHE_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// OCR with the limits of `[computer_control.ocr]` and a cache of its results
+#[derive(Debug)]
+pub struct Ocr {
+    config: OcrConfig,
+    text: Mutex<Cache<String>>,
+    locations: Mutex<Cache<Vec<TextLocation>>>,
+}
+
+impl Ocr {
+    pub fn new(config: &OcrConfig) -> Self {
+        Self {
+            config: config.clone(),
+            text: Mutex::new(Cache::new()),
+            locations: Mutex::new(Cache::new()),
+        }
+    }
+
+    /// The text in the image at `path`, or in `region` of it, cut to `max_chars`
+    pub async fn extract_text(
+        &self,
+        controller: &Arc<dyn ComputerController>,
+        path: &str,
+        region: Option<Rect>,
+    ) -> Result<String> {
+        let key = self.cache_key("text", path, region);
+        if let Some(text) = key
+            .as_ref()
+            .and_then(|key| self.text.lock().unwrap().get(key))
+        {
+            return Ok(text);
+        }
+        let controller = controller.clone();
+        let (_, text) = self
+            .run(path, region, move |prepared| async move {
+                let text = controller
+                    .extract_text_from_image(&prepared.path().display().to_string())
+                    .await;
+                (prepared, text)
+            })
+            .await?;
+        let text = truncate(&text?, self.config.max_chars);
+        if let Some(key) = key {
+            self.text.lock().unwrap().insert(key, text.clone());
+        }
+        Ok(text)
+    }
+
+    /// The text in the image at `path`, or in `region` of it, with where it is in the image.
+    /// Locations are cut to those whose text together is at most `max_chars` long.
+    pub async fn extract_text_with_locations(
+        &self,
+        controller: &Arc<dyn ComputerController>,
+        path: &str,
+        region: Option<Rect>,
+    ) -> Result<Vec<TextLocation>> {
+        let key = self.cache_key("locations", path, region);
+        if let Some(locations) = key
+            .as_ref()
+            .and_then(|key| self.locations.lock().unwrap().get(key))
+        {
+            return Ok(locations);
+        }
+        let controller = controller.clone();
+        let (prepared, locations) = self
+            .run(path, region, move |prepared| async move {
+                let locations = controller
+                    .extract_text_with_locations(&prepared.path().display().to_string())
+                    .await;
+                (prepared, locations)
+            })
+            .await?;
+        let mut chars = 0;
+        let locations: Vec<TextLocation> = locations?
+            .into_iter()
+            .take_while(|location| {
+                chars += location.text.chars().count();
+                chars <= self.config.max_chars
+            })
+            .map(|location| prepared.to_original(location))
+            .collect();
+        if let Some(key) = key {
+            self.locations
+                .lock()
+                .unwrap()
+                .insert(key, locations.clone());
+        }
+        Ok(locations)
+    }
+
+    /// Prepare the image and run `ocr` on it within the timeout. The OCR runs as a task of
+    /// its own, which is left to finish on its own when it takes too long.
+    async fn run<T, F, Fut>(&self, path: &str, region: Option<Rect>, ocr: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(PreparedImage) -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        let image = path.to_string();
+        let max_dimension = Some(self.config.max_dimension).filter(|max| *max > 0);
+        let prepared = tokio::task::spawn_blocking(move || {
+            prepare(std::path::Path::new(&image), region, max_dimension)
+        })
+        .await??;
+        let timeout = Duration::from_secs(self.config.timeout_seconds);
+        match tokio::time::timeout(timeout, tokio::spawn(ocr(prepared))).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(anyhow!(
+                "OCR took longer than {}s. Pass a region to read part of the image.",
+                timeout.as_secs()
+            )),
+        }
+    }
+
+    /// Cache key of an OCR of `kind` on the image at `path`; `None` without caching or when
+    /// the image can't be read
+    fn cache_key(&self, kind: &str, path: &str, region: Option<Rect>) -> Option<String> {
+        if !self.config.cache {
+            return None;
+        }
+        let content = std::fs::read(path).ok()?;
+        let region = region
+            .map(|r| format!("{},{},{},{}", r.x, r.y, r.width, r.height))
+            .unwrap_or_default();
+        Some(format!(
+            "{}:{:x}:{}:{}",
+            kind,
+            Sha256::digest(&content),
+            region,
+            self.config.max_dimension
+        ))
+    }
+}
+
+/// The `region` argument of an OCR tool, `{x, y, width, height}` in image pixels
+pub fn region_arg(args: &Value) -> Option<Rect> {
+    let region = args.get("region")?.as_object()?;
+    let value = |name: &str| region.get(name).and_then(Value::as_i64).unwrap_or(0) as i32;
+    Some(Rect {
+        x: value("x"),
+        y: value("y"),
+        width: value("width"),
+        height: value("height"),
+    })
+}
+
+/// `text` cut to `max_chars` characters, saying how much was left out
+fn truncate(text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max_chars).collect();
+    format!(
+        "{}\n... [cut to {} of {} characters; pass a region to read the rest]",
+        kept, max_chars, total
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cache_drops_oldest_results() {
+        let mut cache = Cache::new();
+        for i in 0..=CACHE_SIZE {
+            cache.insert(i.to_string(), i);
+        }
+        assert_eq!(cache.get("0"), None);
+        assert_eq!(cache.get("1"), Some(1));
+        assert_eq!(cache.get(&CACHE_SIZE.to_string()), Some(CACHE_SIZE));
+    }
+
+    #[test]
+    fn test_truncate_and_region_arg() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(
+            truncate("abcdef", 4),
+            "abcd\n... [cut to 4 of 6 characters; pass a region to read the rest]"
+        );
+
+        let region = region_arg(&json!({"region": {"x": 10, "y": 20, "width": 30}})).unwrap();
+        assert_eq!(
+            (region.x, region.y, region.width, region.height),
+            (10, 20, 30, 0)
+        );
+        assert!(region_arg(&json!({"path": "a.png"})).is_none());
+    }
+}
@@ -0,0 +1,149 @@
+//! How many tokens each request lets the model generate.
+//!
+//! A fixed `max_tokens` either truncates large generations, such as a whole file passed to
+//! `write_file`, or reserves far more than a short answer needs. Each request instead gets a
+//! limit from what it is for, what the task asks for, the model's output limit (from its provider's capabilities) and the room
+//! left in the context window. `[output_tokens]` and profiles can override the limits.
+
+use g3_config::OutputTokensConfig;
+
+/// Requests ask for at least this many tokens while the context window has room for them
+pub const MIN_OUTPUT_TOKENS: u32 = 1024;
+
+/// Requests that don't look like they will produce much, e.g. questions about the code
+const ANSWER_TOKENS: u32 = 8192;
+/// Continuing after tool results often means editing what was just read
+const FOLLOWUP_TOKENS: u32 = 16_000;
+const SUMMARY_TOKENS: u32 = 10_000;
+/// Output limit of models whose provider doesn't know theirs
+pub const DEFAULT_OUTPUT_LIMIT: u32 = 16_000;
+
+/// Words in a task that suggest it will produce a lot of code or text
+const GENERATION_WORDS: &[&str] = &[
+    "write",
+    "create",
+    "implement",
+    "generate",
+    "rewrite",
+    "refactor",
+    "scaffold",
+    "port",
+    "convert",
+    "translate",
+    "document",
+];
+
+/// Tasks this long are usually specifications of something to build
+const LONG_TASK_CHARS: usize = 2000;
+
+/// What a request is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// The first request of a task
+    Task,
+    /// A request continuing after tool results
+    ToolFollowUp,
+    /// A summary made when compacting the context
+    Summary,
+}
+
+/// Whether `task` asks for something large to be written
+pub fn expects_long_output(task: &str) -> bool {
+    task.len() >= LONG_TASK_CHARS
+        || task
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| GENERATION_WORDS.contains(&word.to_lowercase().as_str()))
+}
+
+/// `max_tokens` for a request of `phase` on a model that can generate `model_limit` tokens,
+/// with `remaining` tokens left in the context window
+pub fn max_tokens(
+    config: &OutputTokensConfig,
+    phase: Phase,
+    task: &str,
+    model_limit: u32,
+    remaining: u32,
+) -> u32 {
+    let configured = match phase {
+        Phase::Task => config.task,
+        Phase::ToolFollowUp => config.tool_followup,
+        Phase::Summary => config.summary,
+    };
+    let tokens = configured.unwrap_or_else(|| {
+        let wanted = match phase {
+            Phase::Summary => SUMMARY_TOKENS,
+            _ if expects_long_output(task) => model_limit,
+            Phase::Task => ANSWER_TOKENS,
+            Phase::ToolFollowUp => FOLLOWUP_TOKENS,
+        };
+        wanted.min(model_limit)
+    });
+    let tokens = config.max.map_or(tokens, |max| tokens.min(max));
+
+    // Leave the prompt room, but don't starve the response of a nearly full context;
+    // the request is checked against the context window again before it is sent
+    tokens.min(remaining.max(MIN_OUTPUT_TOKENS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_tokens() {
+        let config = OutputTokensConfig::default();
+        let limit = g3_providers::anthropic::output_limit("claude-sonnet-4-5");
+        assert_eq!(limit, 64_000);
+
+        // Questions get a modest limit, generation tasks the model's limit
+        let question = "Why does the parser reject trailing commas?";
+        let build = "Implement a CSV exporter for the report module";
+        assert_eq!(
+            max_tokens(&config, Phase::Task, question, limit, 150_000),
+            8192
+        );
+        assert_eq!(
+            max_tokens(&config, Phase::Task, build, limit, 150_000),
+            64_000
+        );
+        assert_eq!(
+            max_tokens(&config, Phase::ToolFollowUp, question, limit, 150_000),
+            16_000
+        );
+        assert_eq!(
+            max_tokens(&config, Phase::Summary, build, limit, 150_000),
+            10_000
+        );
+
+        // Never more than the context has room for, nor the model can produce
+        assert_eq!(
+            max_tokens(&config, Phase::Task, build, limit, 20_000),
+            20_000
+        );
+        assert_eq!(
+            max_tokens(&config, Phase::Task, build, limit, 100),
+            MIN_OUTPUT_TOKENS
+        );
+        assert_eq!(max_tokens(&config, Phase::Task, build, 4096, 150_000), 4096);
+
+        // Configured limits replace the heuristics and are capped by `max`
+        let config = OutputTokensConfig {
+            max: Some(12_000),
+            task: Some(2000),
+            tool_followup: Some(30_000),
+            summary: None,
+        };
+        assert_eq!(
+            max_tokens(&config, Phase::Task, build, limit, 150_000),
+            2000
+        );
+        assert_eq!(
+            max_tokens(&config, Phase::ToolFollowUp, build, limit, 150_000),
+            12_000
+        );
+        assert_eq!(
+            max_tokens(&config, Phase::Summary, build, limit, 150_000),
+            10_000
+        );
+    }
+}
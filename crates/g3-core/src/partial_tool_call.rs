@@ -0,0 +1,461 @@
+//! Tool calls whose arguments are still streaming.
+//!
+//! Providers that stream tool-call arguments send them as JSON fragments. They are assembled
+//! here so a call can be looked at before the response ends: once `file_path` of a
+//! `write_file` call is complete it can be checked against read-only mode, `.g3ignore`,
+//! `[scope]`, the profile and `[budgets]`, and with `progressive_writes` enabled the content
+//! received so far is written to `<file_path>.g3-partial` as it arrives. The partial file is
+//! removed when the stream ends; the real file is only written when the complete call runs.
+//! The `summary` of a `final_output` call is shown as it arrives, so a long summary doesn't
+//! appear all at once when the response ends.
+
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+use crate::ToolCall;
+
+/// Suffix of the file a streaming `write_file` call is written to
+pub const PARTIAL_SUFFIX: &str = ".g3-partial";
+
+/// Arguments are scanned again once this much more of them has arrived, so long payloads
+/// aren't rescanned for every small delta
+const SCAN_INTERVAL_BYTES: usize = 256;
+
+/// A tool call assembled from the deltas received so far
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PartialToolCall {
+    pub id: Option<String>,
+    pub tool: String,
+    /// The JSON-encoded arguments received so far
+    pub arguments: String,
+}
+
+/// A top-level argument as far as it has been received
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    /// A string, decoded up to the last complete character
+    Str { value: String, complete: bool },
+    /// Any other value, once it is complete
+    Other(Value),
+}
+
+impl PartialToolCall {
+    /// The value of string argument `key` received so far, and whether it is complete
+    pub fn str_arg(&self, key: &str) -> Option<(String, bool)> {
+        match fields(&self.arguments).remove(key)? {
+            Field::Str { value, complete } => Some((value, complete)),
+            Field::Other(_) => None,
+        }
+    }
+
+    /// The call with only the arguments that have been received completely, for checks
+    /// that can be made before the rest arrives
+    pub fn complete_args(&self) -> ToolCall {
+        let args: Map<String, Value> = fields(&self.arguments)
+            .into_iter()
+            .filter_map(|(key, field)| match field {
+                Field::Str {
+                    value,
+                    complete: true,
+                } => Some((key, Value::String(value))),
+                Field::Other(value) => Some((key, value)),
+                Field::Str { .. } => None,
+            })
+            .collect();
+        ToolCall {
+            tool: self.tool.clone(),
+            args: Value::Object(args),
+        }
+    }
+}
+
+/// Scan the top-level fields of a JSON object that may be cut off anywhere
+fn fields(json: &str) -> HashMap<String, Field> {
+    let mut fields = HashMap::new();
+    let bytes = json.as_bytes();
+    let mut pos = match json.find('{') {
+        Some(start) => start + 1,
+        None => return fields,
+    };
+
+    loop {
+        pos = skip_whitespace(bytes, pos, b',');
+        if bytes.get(pos) != Some(&b'"') {
+            return fields;
+        }
+        let (key, complete, end) = decode_string(json, pos);
+        if !complete {
+            return fields;
+        }
+        pos = skip_whitespace(bytes, end, b':');
+        match bytes.get(pos) {
+            None => return fields,
+            Some(b'"') => {
+                let (value, complete, end) = decode_string(json, pos);
+                fields.insert(key, Field::Str { value, complete });
+                if !complete {
+                    return fields;
+                }
+                pos = end;
+            }
+            Some(_) => {
+                let Some(end) = value_end(bytes, pos) else {
+                    return fields;
+                };
+                if let Ok(value) = serde_json::from_str(&json[pos..end]) {
+                    fields.insert(key, Field::Other(value));
+                }
+                pos = end;
+            }
+        }
+    }
+}
+
+/// Skip whitespace and any `separator` bytes
+fn skip_whitespace(bytes: &[u8], mut pos: usize, separator: u8) -> usize {
+    while let Some(&b) = bytes.get(pos) {
+        if b.is_ascii_whitespace() || b == separator {
+            pos += 1;
+        } else {
+            break;
+        }
+    }
+    pos
+}
+
+/// Decode the string starting with the quote at `start`. Returns the text decoded so far,
+/// whether the closing quote was reached and the position after it.
+fn decode_string(json: &str, start: usize) -> (String, bool, usize) {
+    let mut value = String::new();
+    let mut chars = json[start + 1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (value, true, start + 1 + i + 1),
+            '\\' => {
+                let Some((_, escape)) = chars.next() else {
+                    break;
+                };
+                match escape {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'r' => value.push('\r'),
+                    'b' => value.push('\u{8}'),
+                    'f' => value.push('\u{c}'),
+                    'u' => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let Some(code) = Some(&hex)
+                            .filter(|hex| hex.len() == 4)
+                            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        else {
+                            break;
+                        };
+                        if (0xD800..0xDC00).contains(&code) {
+                            // A surrogate pair is only decodable once both halves are here
+                            let rest = chars.as_str();
+                            let Some(low) = rest
+                                .strip_prefix("\\u")
+                                .and_then(|r| r.get(..4))
+                                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                                .filter(|low| (0xDC00..0xE000).contains(low))
+                            else {
+                                break;
+                            };
+                            let combined = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            value.extend(char::from_u32(combined));
+                            chars.by_ref().take(6).for_each(drop);
+                        } else {
+                            value.extend(char::from_u32(code));
+                        }
+                    }
+                    other => value.push(other),
+                }
+            }
+            c => value.push(c),
+        }
+    }
+    (value, false, json.len())
+}
+
+/// The end of the number, literal, array or object starting at `start`, if it is complete
+fn value_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (offset, &b) in bytes[start..].iter().enumerate() {
+        let pos = start + offset;
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' if depth == 0 => return Some(pos),
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos + 1);
+                }
+            }
+            b',' if depth == 0 => return Some(pos),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Files being written by streaming `write_file` calls, by the call's position in the response
+#[derive(Debug, Default)]
+pub struct PartialWrites {
+    files: HashMap<usize, PartialFile>,
+    /// Length of each call's arguments when they were last scanned
+    scanned: HashMap<usize, usize>,
+    /// Calls that were refused or failed, which aren't tried again
+    skipped: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct PartialFile {
+    path: PathBuf,
+    written: usize,
+}
+
+impl PartialWrites {
+    /// Write what has arrived of each `write_file` call's content. `check` returns a reason
+    /// if a call with the given complete arguments would be refused.
+    pub fn update(
+        &mut self,
+        calls: &[PartialToolCall],
+        check: impl Fn(&ToolCall) -> Option<String>,
+    ) {
+        for (index, call) in calls.iter().enumerate() {
+            if call.tool != "write_file" || self.skipped.contains(&index) {
+                continue;
+            }
+            let scanned = self.scanned.entry(index).or_default();
+            if call.arguments.len() < *scanned + SCAN_INTERVAL_BYTES {
+                continue;
+            }
+            *scanned = call.arguments.len();
+            let Some((content, _)) = call.str_arg("content") else {
+                continue;
+            };
+            if !self.files.contains_key(&index) {
+                let Some((file_path, true)) = call.str_arg("file_path") else {
+                    continue;
+                };
+                if let Some(reason) = check(&call.complete_args()) {
+                    warn!("Not writing {} while it streams: {}", file_path, reason);
+                    self.skipped.push(index);
+                    continue;
+                }
+                let path = PathBuf::from(format!(
+                    "{}{}",
+                    shellexpand::tilde(&file_path),
+                    PARTIAL_SUFFIX
+                ));
+                self.files.insert(index, PartialFile { path, written: 0 });
+            }
+
+            let file = self.files.get_mut(&index).expect("inserted above");
+            if content.len() == file.written {
+                continue;
+            }
+            if let Err(e) = append(file, &content) {
+                debug!("Failed to write {}: {}", file.path.display(), e);
+                self.skipped.push(index);
+            }
+        }
+    }
+}
+
+/// The summary of a streaming `final_output` call, shown as it arrives
+#[derive(Debug, Default)]
+pub struct PartialSummary {
+    /// The summary shown so far
+    shown: String,
+}
+
+impl PartialSummary {
+    /// What has arrived of the summary since it was last updated
+    pub fn update(&mut self, calls: &[PartialToolCall]) -> Option<String> {
+        let call = calls.iter().find(|call| call.tool == "final_output")?;
+        let (summary, _) = call.str_arg("summary")?;
+        let rest = summary.strip_prefix(self.shown.as_str())?;
+        if rest.is_empty() {
+            return None;
+        }
+        let rest = rest.to_string();
+        self.shown.push_str(&rest);
+        Some(rest)
+    }
+
+    /// Whether any of the summary was shown
+    pub fn is_shown(&self) -> bool {
+        !self.shown.is_empty()
+    }
+
+    /// The rest of the summary of the complete call, or `None` if none of it was shown while
+    /// it streamed. A summary that doesn't continue what was shown is left to be shown whole.
+    pub fn finish(&mut self, tool_call: &ToolCall) -> Option<String> {
+        if self.shown.is_empty() {
+            return None;
+        }
+        let summary = tool_call.args.get("summary")?.as_str()?;
+        let rest = summary.strip_prefix(self.shown.as_str())?.to_string();
+        self.shown.push_str(&rest);
+        Some(rest)
+    }
+}
+
+fn append(file: &mut PartialFile, content: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    if file.written == 0 {
+        if let Some(parent) = file.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    let mut out = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file.path)?;
+    out.write_all(&content.as_bytes()[file.written..])?;
+    file.written = content.len();
+    Ok(())
+}
+
+impl Drop for PartialWrites {
+    fn drop(&mut self) {
+        for file in self.files.values() {
+            let _ = std::fs::remove_file(&file.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(arguments: &str) -> PartialToolCall {
+        PartialToolCall {
+            id: None,
+            tool: "write_file".to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_partial_arguments() {
+        let args = r#"{"file_path": "src/a.rs", "mode": {"x": [1, 2]}, "content": "fn main() {\n    println!(\"é😀\");\n}"}"#;
+        // Every prefix decodes to a prefix of the final content
+        let full = "fn main() {\n    println!(\"é😀\");\n}";
+        for end in 0..=args.len() {
+            if !args.is_char_boundary(end) {
+                continue;
+            }
+            if let Some((content, _)) = call(&args[..end]).str_arg("content") {
+                assert!(full.starts_with(&content), "{:?}", &args[..end]);
+            }
+        }
+
+        let partial = call(&args[..40]);
+        assert_eq!(
+            partial.str_arg("file_path"),
+            Some(("src/a.rs".to_string(), true))
+        );
+        assert_eq!(
+            partial.complete_args().args,
+            serde_json::json!({"file_path": "src/a.rs"})
+        );
+        assert_eq!(
+            call(args).complete_args().args["mode"],
+            serde_json::json!({"x": [1, 2]})
+        );
+        assert_eq!(
+            call(args).str_arg("content"),
+            Some((full.to_string(), true))
+        );
+        assert_eq!(call(r#"{"file_pa"#).str_arg("file_path"), None);
+    }
+
+    #[test]
+    fn test_partial_writes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("out/notes.txt");
+        let partial_path = dir.path().join(format!("out/notes.txt{}", PARTIAL_SUFFIX));
+        let (one, two) = ("1".repeat(300), "2".repeat(300));
+        let args = format!(
+            r#"{{"file_path": "{}", "content": "{}\n{}"}}"#,
+            target.display(),
+            one,
+            two
+        );
+        let cut = args.find(&two).unwrap();
+
+        let mut writes = PartialWrites::default();
+        writes.update(&[call(&args[..cut])], |_| None);
+        assert_eq!(
+            std::fs::read_to_string(&partial_path).unwrap(),
+            format!("{}\n", one)
+        );
+        // Too little has arrived since to scan again
+        writes.update(&[call(&args[..cut + 10])], |_| None);
+        assert_eq!(std::fs::read_to_string(&partial_path).unwrap().len(), 301);
+        writes.update(&[call(&args)], |_| None);
+        assert_eq!(
+            std::fs::read_to_string(&partial_path).unwrap(),
+            format!("{}\n{}", one, two)
+        );
+        assert!(!target.exists());
+        drop(writes);
+        assert!(!partial_path.exists());
+
+        // Refused calls aren't written
+        let mut writes = PartialWrites::default();
+        writes.update(&[call(&args)], |_| Some("read-only".to_string()));
+        assert!(!partial_path.exists());
+    }
+
+    #[test]
+    fn test_partial_summary() {
+        let summary_call = |arguments: &str| PartialToolCall {
+            tool: "final_output".to_string(),
+            ..call(arguments)
+        };
+        let args = r#"{"summary": "Done\n\n- Fixed the \"parser\""}"#;
+        let mut summary = PartialSummary::default();
+        assert_eq!(summary.update(&[call(args)]), None);
+        assert_eq!(
+            summary.update(&[summary_call(&args[..15])]),
+            Some("Do".to_string())
+        );
+        assert_eq!(summary.update(&[summary_call(&args[..15])]), None);
+        // A cut escape is held back until it is complete
+        assert_eq!(
+            summary.update(&[summary_call(&args[..18])]),
+            Some("ne".to_string())
+        );
+        assert_eq!(
+            summary.update(&[summary_call(&args[..19])]),
+            Some("\n".to_string())
+        );
+
+        let complete = ToolCall {
+            tool: "final_output".to_string(),
+            args: serde_json::json!({"summary": "Done\n\n- Fixed the \"parser\""}),
+        };
+        assert_eq!(
+            summary.finish(&complete),
+            Some("\n- Fixed the \"parser\"".to_string())
+        );
+        // Nothing was shown while streaming, so the summary is shown whole
+        assert_eq!(PartialSummary::default().finish(&complete), None);
+    }
+}
@@ -0,0 +1,327 @@
+//! Command plugins: executables from `[plugins]` that offer tools to the agent.
+//!
+//! A plugin is started when the agent starts and talks JSON-RPC 2.0 over its stdin and
+//! stdout, one message per line, much like an editor hosting a language server:
+//!
+//! - `describe` → `{"name": ..., "version": ..., "description": ...}` (all optional)
+//! - `tools/list` → `{"tools": [{"name", "description", "input_schema", "read_only"}]}`
+//! - `tools/call` with `{"name", "arguments"}` → `{"content": ..., "is_error": false}`
+//!
+//! Its tools are offered as `<plugin>__<tool>`. Only tools that declare `"read_only": true`
+//! are offered in read-only mode. Anything the plugin writes to stderr goes to the debug log.
+
+use crate::tool_registry::external_tools_reference;
+use anyhow::{anyhow, bail, Context, Result};
+use g3_config::PluginConfig;
+use g3_providers::Tool;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// Between the plugin and tool name in the names of plugin tools
+pub const SEPARATOR: &str = "__";
+
+/// How long a plugin gets to answer `describe` and `tools/list`
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A tool as a plugin describes it
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "empty_schema")]
+    pub input_schema: Value,
+    /// The tool doesn't modify anything, so it may be used in read-only mode
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn empty_schema() -> Value {
+    json!({"type": "object", "properties": {}})
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Description {
+    version: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallResult {
+    #[serde(default)]
+    content: Value,
+    #[serde(default)]
+    is_error: bool,
+}
+
+/// The plugin process and its JSON-RPC connection
+struct Connection {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Connection {
+    /// Send a request and wait for the response with the same id. Responses to earlier
+    /// requests that timed out and notifications from the plugin are skipped.
+    async fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let message = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        self.stdin
+            .write_all(format!("{}\n", message).as_bytes())
+            .await?;
+        self.stdin.flush().await?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line).await? == 0 {
+                bail!("plugin exited");
+            }
+            let Ok(response) = serde_json::from_str::<Value>(&line) else {
+                debug!("Ignoring plugin output that isn't JSON: {}", line.trim());
+                continue;
+            };
+            if response.get("id").and_then(Value::as_u64) != Some(id) {
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                let message = error.get("message").and_then(Value::as_str);
+                bail!(
+                    "{}",
+                    message.map_or_else(|| error.to_string(), str::to_string)
+                );
+            }
+            return Ok(response.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+}
+
+pub struct Plugin {
+    name: String,
+    version: Option<String>,
+    description: Option<String>,
+    tools: Vec<PluginTool>,
+    connection: Mutex<Connection>,
+    timeout: Duration,
+}
+
+impl Plugin {
+    /// Start the plugin and ask it for its tools
+    pub async fn start(name: &str, config: &PluginConfig) -> Result<Self> {
+        let mut child = Command::new(shellexpand::tilde(&config.command).as_ref())
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", config.command))?;
+        if let Some(stderr) = child.stderr.take() {
+            let name = name.to_string();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    debug!("[plugin {}] {}", name, line);
+                }
+            });
+        }
+        let mut connection = Connection {
+            stdin: child.stdin.take().context("No stdin")?,
+            stdout: BufReader::new(child.stdout.take().context("No stdout")?),
+            _child: child,
+            next_id: 0,
+        };
+
+        let (description, tools) = tokio::time::timeout(STARTUP_TIMEOUT, async {
+            let description = connection.request("describe", json!({})).await?;
+            let mut tools = connection.request("tools/list", json!({})).await?;
+            anyhow::Ok((description, tools["tools"].take()))
+        })
+        .await
+        .map_err(|_| anyhow!("no answer within {}s", STARTUP_TIMEOUT.as_secs()))??;
+        let description: Description = serde_json::from_value(description).unwrap_or_default();
+        let tools: Vec<PluginTool> =
+            serde_json::from_value(tools).context("Invalid tools/list result")?;
+
+        Ok(Self {
+            name: name.to_string(),
+            version: description.version,
+            description: description.description,
+            tools,
+            connection: Mutex::new(connection),
+            timeout: Duration::from_secs(config.timeout_seconds),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tools(&self) -> &[PluginTool] {
+        &self.tools
+    }
+
+    fn tool_name(&self, tool: &PluginTool) -> String {
+        format!("{}{}{}", self.name, SEPARATOR, tool.name)
+    }
+
+    /// Call the plugin's tool `tool`. A result the plugin marks as an error is returned as one.
+    pub async fn call(&self, tool: &str, args: &Value) -> Result<String> {
+        let mut connection = self.connection.lock().await;
+        let result = tokio::time::timeout(
+            self.timeout,
+            connection.request("tools/call", json!({"name": tool, "arguments": args})),
+        )
+        .await
+        .map_err(|_| anyhow!("no result within {}s", self.timeout.as_secs()))??;
+
+        let result: CallResult =
+            serde_json::from_value(result).context("Invalid tools/call result")?;
+        let content = match result.content {
+            Value::String(text) => text,
+            Value::Null => String::new(),
+            other => serde_json::to_string_pretty(&other)?,
+        };
+        if result.is_error {
+            bail!("{}", content);
+        }
+        Ok(content)
+    }
+}
+
+/// The plugins that started, with their tools
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Start the plugins in `configs`. Plugins that fail to start are skipped with a warning.
+    pub async fn start(configs: &HashMap<String, PluginConfig>) -> Self {
+        let mut names: Vec<&String> = configs.keys().collect();
+        names.sort();
+        let mut plugins = Vec::new();
+        for name in names {
+            if name.is_empty() || name.contains(SEPARATOR) {
+                warn!(
+                    "Skipping plugin '{}': names can't contain '{}'",
+                    name, SEPARATOR
+                );
+                continue;
+            }
+            match Plugin::start(name, &configs[name]).await {
+                Ok(plugin) => {
+                    debug!(
+                        "Started plugin {} {} with {} tools",
+                        name,
+                        plugin.version.as_deref().unwrap_or(""),
+                        plugin.tools.len()
+                    );
+                    plugins.push(plugin);
+                }
+                Err(e) => warn!("Failed to start plugin {}: {}", name, e),
+            }
+        }
+        Self { plugins }
+    }
+
+    pub fn plugins(&self) -> &[Plugin] {
+        &self.plugins
+    }
+
+    /// The plugin and tool behind the tool named `name`
+    pub fn find(&self, name: &str) -> Option<(&Plugin, &PluginTool)> {
+        let (plugin, tool) = name.split_once(SEPARATOR)?;
+        let plugin = self.plugins.iter().find(|p| p.name == plugin)?;
+        let tool = plugin.tools.iter().find(|t| t.name == tool)?;
+        Some((plugin, tool))
+    }
+
+    /// Tool definitions for all plugin tools, or only the read-only ones
+    pub fn tools(&self, read_only: bool) -> Vec<Tool> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.tools.iter().map(move |tool| (plugin, tool)))
+            .filter(|(_, tool)| tool.read_only || !read_only)
+            .map(|(plugin, tool)| Tool {
+                name: plugin.tool_name(tool),
+                description: match &plugin.description {
+                    Some(about) => {
+                        format!("{} (plugin {}: {})", tool.description, plugin.name, about)
+                    }
+                    None => format!("{} (plugin {})", tool.description, plugin.name),
+                },
+                input_schema: tool.input_schema.clone(),
+            })
+            .collect()
+    }
+
+    /// The plugin tools described for providers without native tool calling
+    pub fn prompt_reference(&self, read_only: bool) -> String {
+        external_tools_reference("Plugin Tools", &self.tools(read_only))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers with canned responses, echoing the request id
+    const PLUGIN: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+  case "$line" in
+    *'"describe"'*) result='{"version": "1.0"}' ;;
+    *'"tools/list"'*) result='{"tools": [{"name": "greet", "description": "Say hello", "read_only": true}, {"name": "deploy"}]}' ;;
+    *'"name":"greet"'*)
+      echo '{"jsonrpc": "2.0", "method": "log", "params": {"message": "greeting"}}'
+      result='{"content": "hello"}' ;;
+    *) echo "{\"jsonrpc\": \"2.0\", \"id\": $id, \"error\": {\"code\": -32601, \"message\": \"no such tool\"}}"; continue ;;
+  esac
+  echo "{\"jsonrpc\": \"2.0\", \"id\": $id, \"result\": $result}"
+done"#;
+
+    #[tokio::test]
+    async fn test_command_plugin() {
+        let config = PluginConfig {
+            command: "bash".to_string(),
+            args: vec!["-c".to_string(), PLUGIN.to_string()],
+            env: HashMap::new(),
+            timeout_seconds: 5,
+        };
+        let plugins = Plugins::start(&HashMap::from([
+            ("demo".to_string(), config.clone()),
+            (
+                "missing".to_string(),
+                PluginConfig {
+                    command: "/nonexistent/plugin".to_string(),
+                    ..config
+                },
+            ),
+        ]))
+        .await;
+        assert_eq!(plugins.plugins().len(), 1);
+
+        let names: Vec<String> = plugins.tools(false).into_iter().map(|t| t.name).collect();
+        assert_eq!(names, ["demo__greet", "demo__deploy"]);
+        assert_eq!(plugins.tools(true).len(), 1);
+        assert!(plugins.prompt_reference(false).contains("**demo__deploy**"));
+
+        let (plugin, tool) = plugins.find("demo__greet").unwrap();
+        assert_eq!(plugin.call(&tool.name, &json!({})).await.unwrap(), "hello");
+        let err = plugin.call("deploy", &json!({})).await.unwrap_err();
+        assert_eq!(err.to_string(), "no such tool");
+        assert!(plugins.find("demo__other").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+//! Named agent profiles (`[profiles.<name>]` in config).
+//!
+//! A profile's provider, model and temperature are applied to the config by
+//! [`g3_config::Config::with_profile`]. This module handles the rest: the profile's section of
+//! the system prompt and its tool allowlist.
+
+use crate::ToolCall;
+use g3_config::ProfileConfig;
+
+/// Starts the profile's section at the end of the system prompt
+const PROFILE_PROMPT_HEADING: &str = "\n\n# Profile: ";
+
+/// Tools that stay available whatever the allowlist says, so the agent can still finish
+const ALWAYS_ALLOWED: &[&str] = &["final_output", "give_feedback"];
+
+/// `system_prompt` with any previous profile section replaced by `profile`'s
+pub fn with_profile_prompt(system_prompt: &str, profile: Option<(&str, &ProfileConfig)>) -> String {
+    let base = match system_prompt.find(PROFILE_PROMPT_HEADING) {
+        Some(start) => &system_prompt[..start],
+        None => system_prompt,
+    };
+    let prompt = profile.and_then(|(name, profile)| Some((name, profile.system_prompt.as_ref()?)));
+    match prompt {
+        Some((name, prompt)) => format!(
+            "{}{}{}\n\n{}",
+            base,
+            PROFILE_PROMPT_HEADING,
+            name,
+            prompt.trim()
+        ),
+        None => base.to_string(),
+    }
+}
+
+pub fn is_tool_allowed(profile: &ProfileConfig, tool: &str) -> bool {
+    match &profile.tools {
+        Some(tools) => ALWAYS_ALLOWED.contains(&tool) || tools.iter().any(|t| t == tool),
+        None => true,
+    }
+}
+
+/// Returns a reason if the profile does not allow the tool call
+pub fn check_tool_call(
+    name: &str,
+    profile: &ProfileConfig,
+    tool_call: &ToolCall,
+) -> Option<String> {
+    if is_tool_allowed(profile, &tool_call.tool) {
+        return None;
+    }
+    Some(format!(
+        "{} is not available in the '{}' profile. Available tools: {}",
+        tool_call.tool,
+        name,
+        profile.tools.as_deref().unwrap_or_default().join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_prompt_is_replaced() {
+        let reviewer = ProfileConfig {
+            system_prompt: Some("Only review.".to_string()),
+            ..Default::default()
+        };
+        let docs = ProfileConfig {
+            system_prompt: Some("Only write docs.".to_string()),
+            ..Default::default()
+        };
+
+        let prompt = with_profile_prompt("You are G3.", Some(("reviewer", &reviewer)));
+        assert_eq!(prompt, "You are G3.\n\n# Profile: reviewer\n\nOnly review.");
+
+        let prompt = with_profile_prompt(&prompt, Some(("docs", &docs)));
+        assert_eq!(prompt, "You are G3.\n\n# Profile: docs\n\nOnly write docs.");
+
+        assert_eq!(with_profile_prompt(&prompt, None), "You are G3.");
+    }
+
+    #[test]
+    fn test_tool_allowlist() {
+        let profile = ProfileConfig {
+            tools: Some(vec!["read_file".to_string()]),
+            ..Default::default()
+        };
+        assert!(is_tool_allowed(&profile, "read_file"));
+        assert!(is_tool_allowed(&profile, "final_output"));
+        assert!(!is_tool_allowed(&profile, "shell"));
+        assert!(is_tool_allowed(&ProfileConfig::default(), "shell"));
+
+        let call = ToolCall {
+            tool: "shell".to_string(),
+            args: serde_json::json!({"command": "ls"}),
+        };
+        let reason = check_tool_call("reviewer", &profile, &call).unwrap();
+        assert!(reason.contains("'reviewer' profile"));
+    }
+}
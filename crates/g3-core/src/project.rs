@@ -92,6 +92,12 @@ impl Project {
         &self.workspace_dir
     }
     
+    /// Detect the languages and build systems of the workspace and how to build, test and
+    /// lint it
+    pub fn detect_facts(&self) -> ProjectFacts {
+        ProjectFacts::detect(&self.workspace_dir)
+    }
+    
     /// Check if requirements file exists
     pub fn has_requirements(&self) -> bool {
         // Has requirements if either text override is provided or requirements file exists
@@ -181,4 +187,248 @@ impl Project {
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Start of the project facts' context section
+pub const FACTS_HEADER: &str = "🧰 Project Facts";
+
+/// A build system found in the workspace, with the commands it runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildSystem {
+    /// e.g. "Rust" or "TypeScript"
+    pub language: String,
+    /// e.g. "Cargo" or "pnpm"
+    pub tool: String,
+    pub build: Option<String>,
+    pub test: Option<String>,
+    pub lint: Option<String>,
+}
+
+/// What the agent needs to know to build and test a project without guessing
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProjectFacts {
+    pub build_systems: Vec<BuildSystem>,
+    /// Relative to the workspace
+    pub entry_points: Vec<String>,
+}
+
+impl ProjectFacts {
+    /// Detect Cargo, npm/pnpm/yarn/bun, poetry/uv/pip, Go and Maven projects at the root of
+    /// `workspace`
+    pub fn detect(workspace: &Path) -> Self {
+        let mut facts = Self::default();
+        let read = |name: &str| std::fs::read_to_string(workspace.join(name)).ok();
+        let exists = |name: &str| workspace.join(name).exists();
+
+        if let Some(cargo) = read("Cargo.toml") {
+            let flag = if cargo.contains("[workspace]") {
+                " --workspace"
+            } else {
+                ""
+            };
+            facts.add(
+                "Rust",
+                "Cargo",
+                Some(format!("cargo build{}", flag)),
+                Some(format!("cargo test{}", flag)),
+                Some(format!("cargo clippy{} --all-targets", flag)),
+            );
+            facts.add_entry_points(workspace, &["src/main.rs", "src/lib.rs"]);
+        }
+
+        if let Some(package) = read("package.json") {
+            let manager = [
+                ("pnpm-lock.yaml", "pnpm"),
+                ("yarn.lock", "yarn"),
+                ("bun.lockb", "bun"),
+            ]
+            .into_iter()
+            .find(|(lock, _)| exists(lock))
+            .map_or("npm", |(_, manager)| manager);
+            let language = if exists("tsconfig.json") {
+                "TypeScript"
+            } else {
+                "JavaScript"
+            };
+            let package: serde_json::Value = serde_json::from_str(&package).unwrap_or_default();
+            let script = |name: &str| {
+                package["scripts"]
+                    .get(name)
+                    .map(|_| format!("{} run {}", manager, name))
+            };
+            facts.add(
+                language,
+                manager,
+                script("build"),
+                script("test"),
+                script("lint"),
+            );
+            let main = package["main"]
+                .as_str()
+                .map(|main| main.trim_start_matches("./").to_string());
+            facts.entry_points.extend(main);
+        }
+
+        let pyproject = read("pyproject.toml");
+        if pyproject.is_some() || exists("requirements.txt") || exists("setup.py") {
+            let pyproject = pyproject.unwrap_or_default();
+            let (tool, run) = if exists("uv.lock") || pyproject.contains("[tool.uv") {
+                ("uv", "uv run ")
+            } else if exists("poetry.lock") || pyproject.contains("[tool.poetry") {
+                ("poetry", "poetry run ")
+            } else {
+                ("pip", "")
+            };
+            let build = match tool {
+                "pip" => None,
+                _ => Some(format!("{} build", tool)),
+            };
+            let lint = pyproject
+                .contains("ruff")
+                .then(|| format!("{}ruff check .", run));
+            facts.add("Python", tool, build, Some(format!("{}pytest", run)), lint);
+            facts.add_entry_points(
+                workspace,
+                &["main.py", "__main__.py", "app.py", "manage.py"],
+            );
+        }
+
+        if exists("go.mod") {
+            facts.add(
+                "Go",
+                "go",
+                Some("go build ./...".to_string()),
+                Some("go test ./...".to_string()),
+                Some("go vet ./...".to_string()),
+            );
+            facts.add_entry_points(workspace, &["main.go"]);
+        }
+
+        if exists("pom.xml") {
+            let mvn = if exists("mvnw") { "./mvnw" } else { "mvn" };
+            facts.add(
+                "Java",
+                "Maven",
+                Some(format!("{} package -DskipTests", mvn)),
+                Some(format!("{} test", mvn)),
+                None,
+            );
+        }
+
+        facts
+    }
+
+    fn add(
+        &mut self,
+        language: &str,
+        tool: &str,
+        build: Option<String>,
+        test: Option<String>,
+        lint: Option<String>,
+    ) {
+        self.build_systems.push(BuildSystem {
+            language: language.to_string(),
+            tool: tool.to_string(),
+            build,
+            test,
+            lint,
+        });
+    }
+
+    fn add_entry_points(&mut self, workspace: &Path, candidates: &[&str]) {
+        for candidate in candidates {
+            if workspace.join(candidate).is_file() {
+                self.entry_points.push(candidate.to_string());
+            }
+        }
+    }
+
+    /// The block added to the system prompt, or `None` if no build system was found
+    pub fn render(&self) -> Option<String> {
+        if self.build_systems.is_empty() {
+            return None;
+        }
+        let mut out = format!("{} (detected from the files in the workspace; use these commands instead of guessing):\n", FACTS_HEADER);
+        for system in &self.build_systems {
+            out.push_str(&format!("\n{} ({}):\n", system.language, system.tool));
+            for (purpose, command) in [
+                ("build", &system.build),
+                ("test", &system.test),
+                ("lint", &system.lint),
+            ] {
+                if let Some(command) = command {
+                    out.push_str(&format!("- {}: `{}`\n", purpose, command));
+                }
+            }
+        }
+        if !self.entry_points.is_empty() {
+            out.push_str(&format!(
+                "\nEntry points: {}\n",
+                self.entry_points.join(", ")
+            ));
+        }
+        Some(out.trim_end().to_string())
+    }
+
+    /// Languages and tools, e.g. "Rust (Cargo), TypeScript (pnpm)"
+    pub fn summary(&self) -> String {
+        self.build_systems
+            .iter()
+            .map(|system| format!("{} ({})", system.language, system.tool))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_facts() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        let project = Project::new(root.to_path_buf());
+        assert_eq!(project.detect_facts().render(), None);
+
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = []\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"main": "./index.js", "scripts": {"test": "jest", "lint": "eslint ."}}"#,
+        )
+        .unwrap();
+        std::fs::write(root.join("pnpm-lock.yaml"), "").unwrap();
+        std::fs::write(root.join("tsconfig.json"), "{}").unwrap();
+        std::fs::write(root.join("pyproject.toml"), "[tool.poetry]\n[tool.ruff]\n").unwrap();
+
+        let facts = project.detect_facts();
+        assert_eq!(
+            facts.summary(),
+            "Rust (Cargo), TypeScript (pnpm), Python (poetry)"
+        );
+        assert_eq!(facts.entry_points, vec!["src/main.rs", "index.js"]);
+        let rendered = facts.render().unwrap();
+        assert!(rendered.starts_with(FACTS_HEADER));
+        assert!(rendered.contains("- test: `cargo test --workspace`"));
+        assert!(rendered.contains("- lint: `cargo clippy --workspace --all-targets`"));
+        assert!(rendered.contains("- test: `pnpm run test`"));
+        assert!(!rendered.contains("pnpm run build"));
+        assert!(rendered.contains("- test: `poetry run pytest`"));
+        assert!(rendered.contains("- lint: `poetry run ruff check .`"));
+        assert!(rendered.contains("- build: `poetry build`"));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("go.mod"), "module example.com/x\n").unwrap();
+        std::fs::write(dir.path().join("uv.lock"), "").unwrap();
+        std::fs::write(dir.path().join("pyproject.toml"), "[project]\n").unwrap();
+        std::fs::write(dir.path().join("pom.xml"), "<project/>").unwrap();
+        let facts = ProjectFacts::detect(dir.path());
+        assert_eq!(facts.summary(), "Python (uv), Go (go), Java (Maven)");
+        let rendered = facts.render().unwrap();
+        assert!(rendered.contains("- test: `uv run pytest`"));
+        assert!(rendered.contains("- test: `go test ./...`"));
+        assert!(rendered.contains("- build: `mvn package -DskipTests`"));
+    }
+}
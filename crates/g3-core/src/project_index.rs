@@ -0,0 +1,535 @@
+//! `g3 index`: a cached map of the project that new sessions start with.
+//!
+//! The index lists the directory structure with file counts, likely entry points and the
+//! build and test commands found in manifests (Cargo.toml, package.json, go.mod, Makefile
+//! and so on). Each directory also gets a one-line description written by the model. The
+//! descriptions are generated once and kept when the index is rebuilt, so only new
+//! directories are sent to the model unless a refresh is asked for.
+//!
+//! The index is saved to `.g3/index.json` and a compact rendering of it is added to the
+//! context of every new session in the workspace, next to the README and agent instructions.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::g3ignore::G3Ignore;
+
+/// Where the index is saved, relative to the workspace
+pub const INDEX_FILE: &str = ".g3/index.json";
+
+/// Start of the index's context section
+pub const HEADER: &str = "🗺️ Project Index";
+
+/// Directories are summarized down to this depth
+const MAX_DEPTH: usize = 2;
+
+/// Entry points are looked for down to this depth
+const ENTRY_POINT_DEPTH: usize = 4;
+
+/// The walk stops after this many entries, so scanning a huge tree stays quick
+const MAX_ENTRIES: usize = 50_000;
+
+/// At most this many directories are indexed and this many entry points are kept
+const MAX_DIRS: usize = 150;
+const MAX_ENTRY_POINTS: usize = 20;
+
+/// File names listed per directory in the description prompt
+const SAMPLE_FILES: usize = 8;
+
+/// Directories that are never indexed, besides hidden ones
+pub(crate) const SKIPPED_DIRS: &[&str] = &[
+    "target",
+    "node_modules",
+    "dist",
+    "build",
+    "out",
+    "vendor",
+    "__pycache__",
+    "venv",
+];
+
+/// File names that usually start a program or library
+const ENTRY_POINT_NAMES: &[&str] = &[
+    "main.rs",
+    "lib.rs",
+    "main.go",
+    "main.py",
+    "__main__.py",
+    "app.py",
+    "manage.py",
+    "index.js",
+    "index.ts",
+    "index.tsx",
+    "main.js",
+    "main.ts",
+    "server.js",
+    "server.ts",
+    "Main.java",
+    "Program.cs",
+    "main.c",
+    "main.cpp",
+];
+
+/// Makefile targets reported as commands
+const MAKE_TARGETS: &[&str] = &["build", "test", "check", "lint", "run"];
+
+/// package.json scripts reported as commands
+const NPM_SCRIPTS: &[&str] = &["build", "test", "lint", "typecheck", "start", "dev"];
+
+/// A directory of the project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirSummary {
+    /// Relative to the workspace, with `/` separators
+    pub path: String,
+    /// Files in the directory and below it
+    pub files: usize,
+    /// One line written by the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// A build, test or other command found in a manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectCommand {
+    /// What the command is for, e.g. "build" or "test"
+    pub purpose: String,
+    pub command: String,
+    /// The manifest it came from
+    pub source: String,
+}
+
+/// The cached map of a project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectIndex {
+    pub generated_at: DateTime<Utc>,
+    /// Files in the project, outside skipped and ignored directories
+    pub files: usize,
+    pub dirs: Vec<DirSummary>,
+    pub entry_points: Vec<String>,
+    pub commands: Vec<ProjectCommand>,
+}
+
+/// `path` relative to `root`, with `/` separators
+pub(crate) fn relative(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Scan `workspace` for its structure, entry points and commands. Descriptions are left
+/// empty.
+pub fn scan(workspace: &Path) -> ProjectIndex {
+    let ignore = G3Ignore::load(workspace);
+    let mut dirs: BTreeMap<String, usize> = BTreeMap::new();
+    let mut entry_points = Vec::new();
+    let mut files = 0;
+
+    let walker = WalkDir::new(workspace)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            let skipped = entry.file_type().is_dir() && SKIPPED_DIRS.contains(&name.as_ref());
+            !name.starts_with('.') && !skipped && !ignore.is_ignored(entry.path())
+        });
+    for entry in walker.filter_map(|e| e.ok()).take(MAX_ENTRIES) {
+        let path = relative(entry.path(), workspace);
+        if entry.file_type().is_dir() {
+            if entry.depth() <= MAX_DEPTH {
+                dirs.entry(path).or_default();
+            }
+            continue;
+        }
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        files += 1;
+        // Count the file in each indexed directory above it
+        for (end, _) in path.match_indices('/').take(MAX_DEPTH) {
+            if let Some(count) = dirs.get_mut(&path[..end]) {
+                *count += 1;
+            }
+        }
+        let name = entry.file_name().to_string_lossy();
+        if entry.depth() <= ENTRY_POINT_DEPTH && ENTRY_POINT_NAMES.contains(&name.as_ref()) {
+            entry_points.push(path);
+        }
+    }
+
+    let (commands, manifest_entry_points) = detect_commands(workspace);
+    for entry_point in manifest_entry_points {
+        if !entry_points.contains(&entry_point) {
+            entry_points.insert(0, entry_point);
+        }
+    }
+    // Shallow entry points first
+    entry_points.sort_by_key(|path| path.matches('/').count());
+    entry_points.truncate(MAX_ENTRY_POINTS);
+
+    ProjectIndex {
+        generated_at: Utc::now(),
+        files,
+        dirs: dirs
+            .into_iter()
+            .take(MAX_DIRS)
+            .map(|(path, files)| DirSummary {
+                path,
+                files,
+                description: None,
+            })
+            .collect(),
+        entry_points,
+        commands,
+    }
+}
+
+/// Commands and entry points declared by the manifests in `workspace`
+fn detect_commands(workspace: &Path) -> (Vec<ProjectCommand>, Vec<String>) {
+    let mut commands = Vec::new();
+    let mut entry_points = Vec::new();
+    let mut add = |purpose: &str, command: String, source: &str| {
+        commands.push(ProjectCommand {
+            purpose: purpose.to_string(),
+            command,
+            source: source.to_string(),
+        })
+    };
+    let read = |name: &str| std::fs::read_to_string(workspace.join(name)).ok();
+
+    if let Some(cargo) = read("Cargo.toml") {
+        let flag = if cargo.contains("[workspace]") {
+            " --workspace"
+        } else {
+            ""
+        };
+        add("build", format!("cargo build{}", flag), "Cargo.toml");
+        add("test", format!("cargo test{}", flag), "Cargo.toml");
+        add(
+            "lint",
+            format!("cargo clippy{} --all-targets", flag),
+            "Cargo.toml",
+        );
+    }
+
+    if let Some(package) = read("package.json") {
+        match serde_json::from_str::<serde_json::Value>(&package) {
+            Ok(package) => {
+                let manager = [
+                    ("pnpm-lock.yaml", "pnpm"),
+                    ("yarn.lock", "yarn"),
+                    ("bun.lockb", "bun"),
+                ]
+                .into_iter()
+                .find(|(lock, _)| workspace.join(lock).exists())
+                .map_or("npm", |(_, manager)| manager);
+                if let Some(scripts) = package["scripts"].as_object() {
+                    for script in NPM_SCRIPTS.iter().filter(|s| scripts.contains_key(**s)) {
+                        add(
+                            script,
+                            format!("{} run {}", manager, script),
+                            "package.json",
+                        );
+                    }
+                }
+                if let Some(main) = package["main"].as_str() {
+                    entry_points.push(main.trim_start_matches("./").to_string());
+                }
+            }
+            Err(e) => warn!("Failed to parse package.json: {}", e),
+        }
+    }
+
+    if read("go.mod").is_some() {
+        add("build", "go build ./...".to_string(), "go.mod");
+        add("test", "go test ./...".to_string(), "go.mod");
+    }
+
+    if let Some(pyproject) = read("pyproject.toml") {
+        if pyproject.contains("pytest") || workspace.join("tests").is_dir() {
+            add("test", "pytest".to_string(), "pyproject.toml");
+        }
+        if pyproject.contains("ruff") {
+            add("lint", "ruff check .".to_string(), "pyproject.toml");
+        }
+    }
+
+    if workspace.join("pom.xml").exists() {
+        add("build", "mvn package".to_string(), "pom.xml");
+        add("test", "mvn test".to_string(), "pom.xml");
+    }
+    if let Some(gradle) = ["build.gradle.kts", "build.gradle"]
+        .into_iter()
+        .find(|name| workspace.join(name).exists())
+    {
+        let gradle_cmd = if workspace.join("gradlew").exists() {
+            "./gradlew"
+        } else {
+            "gradle"
+        };
+        add("build", format!("{} build", gradle_cmd), gradle);
+        add("test", format!("{} test", gradle_cmd), gradle);
+    }
+
+    if let Some(makefile) = read("Makefile") {
+        for line in makefile.lines() {
+            let Some((target, _)) = line.split_once(':') else {
+                continue;
+            };
+            if MAKE_TARGETS.contains(&target) && !line.starts_with(['\t', ' ']) {
+                add(target, format!("make {}", target), "Makefile");
+            }
+        }
+    }
+
+    (commands, entry_points)
+}
+
+impl ProjectIndex {
+    pub fn path(workspace: &Path) -> PathBuf {
+        workspace.join(INDEX_FILE)
+    }
+
+    /// The saved index of `workspace`, if there is one
+    pub fn load(workspace: &Path) -> Option<Self> {
+        let path = Self::path(workspace);
+        let contents = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&contents) {
+            Ok(index) => Some(index),
+            Err(e) => {
+                warn!("Failed to parse {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, workspace: &Path) -> Result<PathBuf> {
+        let path = Self::path(workspace);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        debug!("Saved project index to {}", path.display());
+        Ok(path)
+    }
+
+    /// Keep the descriptions of directories `previous` already described
+    pub fn keep_descriptions(&mut self, previous: &ProjectIndex) {
+        let described: HashMap<&str, &String> = previous
+            .dirs
+            .iter()
+            .filter_map(|dir| Some((dir.path.as_str(), dir.description.as_ref()?)))
+            .collect();
+        for dir in &mut self.dirs {
+            if let Some(description) = described.get(dir.path.as_str()) {
+                dir.description = Some((*description).clone());
+            }
+        }
+    }
+
+    /// Directories without a description yet
+    pub fn undescribed(&self) -> Vec<&DirSummary> {
+        self.dirs
+            .iter()
+            .filter(|dir| dir.description.is_none())
+            .collect()
+    }
+
+    /// Fill in descriptions parsed from the model's reply
+    pub fn apply_descriptions(&mut self, descriptions: &HashMap<String, String>) {
+        for dir in &mut self.dirs {
+            if let Some(description) = descriptions.get(&dir.path) {
+                dir.description = Some(description.clone());
+            }
+        }
+    }
+
+    /// Ask the model for one-line descriptions of the undescribed directories
+    pub fn description_prompt(&self, workspace: &Path) -> String {
+        let mut prompt = String::from(
+            "Below are directories of a software project, each with its number of files and \
+             some of the names in it. For each directory write a one-line description (at \
+             most 15 words) of what it contains, based on the names and the README excerpt.\n\n\
+             Reply with exactly one line per directory in the form `path: description`, in \
+             the same order, and nothing else.\n\n",
+        );
+        let readme = ["README.md", "readme.md", "README"]
+            .iter()
+            .find_map(|name| std::fs::read_to_string(workspace.join(name)).ok());
+        if let Some(readme) = readme {
+            let excerpt: String = readme.chars().take(2000).collect();
+            prompt.push_str(&format!("README excerpt:\n\n{}\n\n", excerpt));
+        }
+        prompt.push_str("Directories:\n\n");
+        for dir in self.undescribed() {
+            let mut names: Vec<String> = std::fs::read_dir(workspace.join(&dir.path))
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.file_name().to_string_lossy().into_owned())
+                        .filter(|name| !name.starts_with('.'))
+                        .collect()
+                })
+                .unwrap_or_default();
+            names.sort();
+            let more = names.len().saturating_sub(SAMPLE_FILES);
+            names.truncate(SAMPLE_FILES);
+            prompt.push_str(&format!(
+                "{} ({} files): {}{}\n",
+                dir.path,
+                dir.files,
+                names.join(", "),
+                if more > 0 {
+                    format!(", and {} more", more)
+                } else {
+                    String::new()
+                }
+            ));
+        }
+        prompt
+    }
+
+    /// The compact form added to new sessions
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "{} (from {}, generated {}; run `g3 index` to refresh it):\n\n",
+            HEADER,
+            INDEX_FILE,
+            self.generated_at.format("%Y-%m-%d")
+        );
+        if !self.commands.is_empty() {
+            out.push_str("Commands:\n");
+            for command in &self.commands {
+                out.push_str(&format!(
+                    "- {}: `{}` ({})\n",
+                    command.purpose, command.command, command.source
+                ));
+            }
+            out.push('\n');
+        }
+        if !self.entry_points.is_empty() {
+            out.push_str(&format!(
+                "Entry points: {}\n\n",
+                self.entry_points.join(", ")
+            ));
+        }
+        out.push_str(&format!("Directories ({} files in total):\n", self.files));
+        for dir in &self.dirs {
+            let indent = "  ".repeat(dir.path.matches('/').count());
+            out.push_str(&format!("{}- {}/ ({} files)", indent, dir.path, dir.files));
+            if let Some(description) = &dir.description {
+                out.push_str(&format!(": {}", description));
+            }
+            out.push('\n');
+        }
+        out.trim_end().to_string()
+    }
+}
+
+/// Parse `path: description` lines from the model's reply
+pub fn parse_descriptions(response: &str) -> HashMap<String, String> {
+    response
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches("- ");
+            let (path, description) = line.split_once(':')?;
+            let path = path.trim().trim_matches(['`', '*']).trim_end_matches('/');
+            let description = description.trim();
+            (!path.is_empty() && !description.is_empty())
+                .then(|| (path.to_string(), description.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_and_render() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path();
+        for path in [
+            "crates/app/src",
+            "crates/core/src",
+            "node_modules/left-pad",
+            "secrets",
+            ".git",
+        ] {
+            std::fs::create_dir_all(root.join(path)).unwrap();
+        }
+        for file in [
+            "crates/app/src/main.rs",
+            "crates/core/src/lib.rs",
+            "crates/core/src/parser.rs",
+            "node_modules/left-pad/index.js",
+            "secrets/key.txt",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        std::fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = []\n").unwrap();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"main": "./web/index.js", "scripts": {"test": "jest", "fmt": "prettier"}}"#,
+        )
+        .unwrap();
+        std::fs::write(root.join("yarn.lock"), "").unwrap();
+        std::fs::write(root.join("Makefile"), "test: build\n\tcargo test\nclean:\n").unwrap();
+        std::fs::write(root.join(".g3ignore"), "secrets/\n").unwrap();
+
+        let mut index = scan(root);
+        let dirs: Vec<(&str, usize)> = index
+            .dirs
+            .iter()
+            .map(|d| (d.path.as_str(), d.files))
+            .collect();
+        assert_eq!(dirs, [("crates", 3), ("crates/app", 1), ("crates/core", 2)]);
+        assert_eq!(
+            index.entry_points,
+            [
+                "web/index.js",
+                "crates/app/src/main.rs",
+                "crates/core/src/lib.rs"
+            ]
+        );
+        let commands: Vec<&str> = index.commands.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            [
+                "cargo build --workspace",
+                "cargo test --workspace",
+                "cargo clippy --workspace --all-targets",
+                "yarn run test",
+                "make test"
+            ]
+        );
+
+        // Descriptions from the model, kept when the index is rebuilt
+        index.apply_descriptions(&parse_descriptions(
+            "Here you go:\n- `crates/`: Workspace members\ncrates/core: Parser and shared types\n",
+        ));
+        assert_eq!(index.undescribed().len(), 1);
+        assert!(index
+            .description_prompt(root)
+            .contains("crates/app (1 files): src\n"));
+        index.save(root).unwrap();
+        let mut rebuilt = scan(root);
+        rebuilt.keep_descriptions(&ProjectIndex::load(root).unwrap());
+        assert_eq!(rebuilt.undescribed().len(), 1);
+
+        let rendered = rebuilt.render();
+        assert!(rendered.starts_with(HEADER));
+        assert!(rendered.contains("- test: `cargo test --workspace` (Cargo.toml)"));
+        assert!(rendered.contains("\n  - crates/core/ (2 files): Parser and shared types"));
+        assert!(!rendered.contains("secrets"));
+    }
+}
@@ -0,0 +1,263 @@
+//! Daily and weekly usage quotas per provider.
+//!
+//! When `[quotas]` sets limits for at least one provider, the tokens (and, with a price,
+//! the cost) of every turn are added to a local usage file (`~/.g3/usage.json` by default)
+//! under the provider and the local date. Every g3 process on the machine shares the file,
+//! so background autonomous runs count too. Before a turn, the active provider's usage of
+//! today and of this week (from Monday) is checked against its limits.
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use g3_config::{ProviderQuota, QuotasConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_USAGE_PATH: &str = "~/.g3/usage.json";
+
+/// Days of usage kept in the file; older ones are dropped when it is written
+const KEPT_DAYS: i64 = 14;
+
+/// Tokens and cost of one provider over some period
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub tokens: u64,
+    #[serde(default)]
+    pub cost: f64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.tokens += other.tokens;
+        self.cost += other.cost;
+    }
+}
+
+/// Usage by day and provider
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageLedger {
+    pub days: BTreeMap<NaiveDate, BTreeMap<String, Usage>>,
+}
+
+impl UsageLedger {
+    /// Add `usage` to `provider` on `date`, and forget days too old to matter
+    pub fn add(&mut self, provider: &str, date: NaiveDate, usage: &Usage) {
+        self.days
+            .entry(date)
+            .or_default()
+            .entry(provider.to_string())
+            .or_default()
+            .add(usage);
+        let oldest = date - Duration::days(KEPT_DAYS);
+        self.days.retain(|day, _| *day > oldest);
+    }
+
+    /// Usage of `provider` on `date`
+    pub fn day(&self, provider: &str, date: NaiveDate) -> Usage {
+        self.days
+            .get(&date)
+            .and_then(|providers| providers.get(provider))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Usage of `provider` in the week of `date`, from Monday up to `date`
+    pub fn week(&self, provider: &str, date: NaiveDate) -> Usage {
+        let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+        let mut total = Usage::default();
+        for (_, providers) in self.days.range(monday..=date) {
+            if let Some(usage) = providers.get(provider) {
+                total.add(usage);
+            }
+        }
+        total
+    }
+}
+
+/// A limit a provider has reached
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaExceeded {
+    pub provider: String,
+    /// "daily" or "weekly"
+    pub period: &'static str,
+    pub used: String,
+    pub limit: String,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} has used {} of its {} quota of {}",
+            self.provider, self.used, self.period, self.limit
+        )
+    }
+}
+
+/// The first limit of `quota` that `provider` has reached on `date`, if any
+pub fn check(
+    quota: &ProviderQuota,
+    provider: &str,
+    ledger: &UsageLedger,
+    date: NaiveDate,
+) -> Option<QuotaExceeded> {
+    let periods = [
+        (
+            "daily",
+            ledger.day(provider, date),
+            quota.daily_tokens,
+            quota.daily_cost,
+        ),
+        (
+            "weekly",
+            ledger.week(provider, date),
+            quota.weekly_tokens,
+            quota.weekly_cost,
+        ),
+    ];
+    for (period, usage, tokens, cost) in periods {
+        let exceeded = |used: String, limit: String| QuotaExceeded {
+            provider: provider.to_string(),
+            period,
+            used,
+            limit,
+        };
+        if let Some(limit) = tokens.filter(|limit| usage.tokens >= *limit) {
+            return Some(exceeded(
+                format!("{} tokens", usage.tokens),
+                format!("{} tokens", limit),
+            ));
+        }
+        if let Some(limit) = cost.filter(|limit| usage.cost >= *limit) {
+            return Some(exceeded(
+                format!("{:.2}", usage.cost),
+                format!("{:.2}", limit),
+            ));
+        }
+    }
+    None
+}
+
+/// The usage file, shared by every g3 process on the machine
+#[derive(Debug, Clone)]
+pub struct UsageStore {
+    path: PathBuf,
+}
+
+impl UsageStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn from_config(config: &QuotasConfig) -> Self {
+        let path = config.path.as_deref().unwrap_or(DEFAULT_USAGE_PATH);
+        Self::new(shellexpand::tilde(path).as_ref())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The recorded usage; empty if nothing was recorded yet or the file can't be read
+    pub fn load(&self) -> UsageLedger {
+        std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Add `usage` to `provider` on `date`. The file is locked while it is updated, so
+    /// processes recording at once don't lose each other's usage.
+    pub fn record(&self, provider: &str, date: NaiveDate, usage: &Usage) -> Result<UsageLedger> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock {}", self.path.display()))?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mut ledger: UsageLedger = serde_json::from_str(&contents).unwrap_or_default();
+        ledger.add(provider, date, usage);
+
+        file.rewind()?;
+        file.set_len(0)?;
+        file.write_all(serde_json::to_string_pretty(&ledger)?.as_bytes())?;
+        Ok(ledger)
+    }
+}
+
+/// What `tokens` cost at `price` per million tokens
+pub fn cost(tokens: u64, price: Option<f64>) -> f64 {
+    price.map_or(0.0, |price| tokens as f64 * price / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        // 2024-01-01 was a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn tokens(tokens: u64) -> Usage {
+        Usage {
+            tokens,
+            cost: cost(tokens, Some(10.0)),
+        }
+    }
+
+    #[test]
+    fn test_quota_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = UsageStore::new(dir.path().join("usage.json"));
+        store
+            .record("anthropic", date(1), &tokens(600_000))
+            .unwrap();
+        store
+            .record("anthropic", date(3), &tokens(300_000))
+            .unwrap();
+        store.record("openai", date(3), &tokens(900_000)).unwrap();
+        let ledger = store
+            .record("anthropic", date(3), &tokens(100_000))
+            .unwrap();
+        assert_eq!(ledger, store.load());
+        assert_eq!(ledger.day("anthropic", date(3)), tokens(400_000));
+        assert_eq!(ledger.week("anthropic", date(3)).tokens, 1_000_000);
+
+        let quota = ProviderQuota {
+            daily_tokens: Some(500_000),
+            weekly_tokens: Some(1_000_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            check(&quota, "anthropic", &ledger, date(3))
+                .unwrap()
+                .to_string(),
+            "anthropic has used 1000000 tokens of its weekly quota of 1000000 tokens"
+        );
+        // A new week starts on Monday
+        assert_eq!(check(&quota, "anthropic", &ledger, date(8)), None);
+
+        let quota = ProviderQuota {
+            daily_cost: Some(5.0),
+            ..Default::default()
+        };
+        assert_eq!(check(&quota, "anthropic", &ledger, date(3)), None);
+        assert_eq!(
+            check(&quota, "anthropic", &ledger, date(1)).unwrap().period,
+            "daily"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Repeated reads of unchanged files.
+//!
+//! Agents often read the same file, or the same part of it, several times in a session,
+//! and each read puts the whole content in the context again. When a read_file call
+//! returns exactly what the same call returned before, and that earlier result is still
+//! in the conversation, the new result is replaced by a short note pointing back to it.
+//! Passing `force: true` to read_file always returns the full content.
+
+use std::collections::HashMap;
+
+use g3_providers::Message;
+
+use crate::{file_changes, file_lock, ToolCall};
+
+/// read_file arguments that change what a read returns, besides the path
+const READ_ARGS: &[&str] = &[
+    "start",
+    "end",
+    "start_line",
+    "end_line",
+    "line_numbers",
+    "outline",
+    "exact",
+    "read_binary",
+];
+
+/// A read_file result as it was added to the context
+#[derive(Debug, Clone)]
+struct PastRead {
+    turn: usize,
+    len: usize,
+    hash: String,
+}
+
+/// The results of the read_file calls of a session, by path and range
+#[derive(Debug, Clone, Default)]
+pub struct ReadHistory {
+    reads: HashMap<String, PastRead>,
+}
+
+impl ReadHistory {
+    /// What identifies a read: the file and the arguments that choose what is returned.
+    /// `None` for other tools, and for reads that ask for the full content with `force`.
+    pub fn key(tool_call: &ToolCall) -> Option<String> {
+        if tool_call.tool != "read_file"
+            || tool_call.args.get("force").and_then(|v| v.as_bool()) == Some(true)
+        {
+            return None;
+        }
+        let path = file_lock::target_path(tool_call)?;
+        let mut key = path.to_string_lossy().to_string();
+        for arg in READ_ARGS {
+            if let Some(value) = tool_call.args.get(*arg) {
+                key.push_str(&format!("|{}={}", arg, value));
+            }
+        }
+        Some(key)
+    }
+
+    /// The turn of an earlier read with the same `key` that returned `result`, if that
+    /// result is still one of `messages`. Otherwise `result` is remembered as the read's
+    /// latest, from `turn`.
+    pub fn repeat_of(
+        &mut self,
+        key: String,
+        result: &str,
+        turn: usize,
+        messages: &[Message],
+    ) -> Option<usize> {
+        let hash = file_changes::hash(result.as_bytes());
+        if let Some(past) = self.reads.get(&key) {
+            let in_context = || {
+                messages
+                    .iter()
+                    .any(|message| message.content.strip_prefix("Tool result: ") == Some(result))
+            };
+            if past.len == result.len() && past.hash == hash && in_context() {
+                return Some(past.turn);
+            }
+        }
+        self.reads.insert(
+            key,
+            PastRead {
+                turn,
+                len: result.len(),
+                hash,
+            },
+        );
+        None
+    }
+}
+
+/// What a repeated read returns instead of the content
+pub fn unchanged_note(tool_call: &ToolCall, turn: usize) -> String {
+    let path = tool_call
+        .args
+        .get("file_path")
+        .and_then(|v| v.as_str())
+        .unwrap_or("the file");
+    format!(
+        "📎 {} is unchanged since you read it in turn {}, and that result is still in the conversation above; use it instead of reading again. If you need the content repeated, call read_file again with force: true.",
+        path, turn
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_providers::MessageRole;
+    use serde_json::json;
+
+    fn read(args: serde_json::Value) -> ToolCall {
+        ToolCall {
+            tool: "read_file".to_string(),
+            args,
+        }
+    }
+
+    fn result_message(content: &str) -> Message {
+        Message {
+            role: MessageRole::User,
+            content: format!("Tool result: {}", content),
+        }
+    }
+
+    #[test]
+    fn test_repeated_read_of_unchanged_file() {
+        let mut history = ReadHistory::default();
+        let call = read(json!({"file_path": "/repo/src/main.rs", "start_line": 1, "end_line": 40}));
+        let key = ReadHistory::key(&call).unwrap();
+        let mut messages = vec![];
+
+        assert_eq!(
+            history.repeat_of(key.clone(), "fn main() {}", 1, &messages),
+            None
+        );
+        messages.push(result_message("fn main() {}"));
+        assert_eq!(
+            history.repeat_of(key.clone(), "fn main() {}", 3, &messages),
+            Some(1)
+        );
+
+        // Another range is another read
+        let other = read(json!({"file_path": "/repo/src/main.rs", "start_line": 41}));
+        assert_ne!(ReadHistory::key(&other).unwrap(), key);
+
+        // Changed content is returned, and becomes the read to compare with
+        assert_eq!(
+            history.repeat_of(key.clone(), "fn main() { run() }", 4, &messages),
+            None
+        );
+        messages.push(result_message("fn main() { run() }"));
+        assert_eq!(
+            history.repeat_of(key.clone(), "fn main() { run() }", 5, &messages),
+            Some(4)
+        );
+
+        // Once the earlier result has left the context, the content is returned again
+        messages.clear();
+        assert_eq!(
+            history.repeat_of(key, "fn main() { run() }", 6, &messages),
+            None
+        );
+
+        let forced = read(json!({"file_path": "/repo/src/main.rs", "force": true}));
+        assert_eq!(ReadHistory::key(&forced), None);
+    }
+}
@@ -0,0 +1,443 @@
+//! Policy for read-only mode (`--read-only`), where the agent may explore a codebase but
+//! must not modify it.
+//!
+//! Tools the registry marks as `mutating` are removed from the tool definitions and refused
+//! if called anyway. Shell commands are checked against an allowlist of commands that only
+//! read, and output redirection, command and process substitution, variables that make
+//! programs run other programs, and the mutating options of allowed commands (e.g.
+//! `find -delete`, `git checkout`, `git -c`) are refused.
+
+use crate::shell_redirect::output_targets;
+use crate::tool_registry::ToolRegistry;
+use crate::ToolCall;
+
+/// Appended to the system prompt in read-only mode
+pub const READ_ONLY_PROMPT: &str = "
+
+# Read-Only Mode
+
+You are running in READ-ONLY mode. You may read files, search code and run commands that only inspect the system, but you must not modify anything. File editing tools are unavailable and shell commands that could write, delete, move or install anything will be refused.";
+
+/// Commands that only read. Anything not listed here is refused.
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "ls", "cat", "head", "tail", "grep", "egrep", "fgrep", "rg", "ag", "find", "fd", "wc", "file",
+    "stat", "du", "df", "pwd", "cd", "echo", "printf", "sort", "cut", "tr", "nl", "column", "diff",
+    "cmp", "comm", "md5sum", "sha1sum", "sha256sum", "shasum", "hexdump", "od", "strings", "which",
+    "whereis", "type", "basename", "dirname", "realpath", "readlink", "date", "uname", "whoami",
+    "id", "hostname", "ps", "printenv", "jq", "true", "false", "test", "git", "cargo",
+];
+
+/// git subcommands that only read
+const READ_ONLY_GIT: &[&str] = &[
+    "status", "log", "diff", "show", "blame", "ls-files", "ls-tree", "rev-parse", "grep",
+    "describe", "shortlog", "cat-file", "rev-list", "reflog", "whatchanged",
+];
+
+/// `git reflog` subcommands that only read; `expire` and `delete` destroy history
+const READ_ONLY_REFLOG: &[&str] = &["show", "exists"];
+
+/// cargo subcommands that do not write outside of cargo's own caches
+const READ_ONLY_CARGO: &[&str] = &["tree", "metadata", "--version", "version", "search"];
+
+/// cargo subcommands that write `Cargo.lock` when it is missing or stale, unless told not to
+/// with `--locked` or `--frozen`
+const LOCKING_CARGO: &[&str] = &["tree", "metadata"];
+
+/// git global options that set configuration or where git's own programs are, either of
+/// which can make any subcommand run another program (e.g. `-c core.pager=...`)
+const MUTATING_GIT_GLOBAL_OPTIONS: &[&str] = &["-c", "--config-env", "--exec-path"];
+
+/// git options that run a program: an external diff or a pager named on the command line
+const MUTATING_GIT_OPTIONS: &[&str] = &["--ext-diff", "-O", "--open-files-in-pager"];
+
+/// Options of allowed commands that run a program given as their value
+const EXECUTING_OPTIONS: &[(&str, &[&str])] = &[
+    ("rg", &["--pre"]),
+    ("ag", &["--pager"]),
+    ("fd", &["-x", "--exec", "-X", "--exec-batch"]),
+    ("sort", &["--compress-program"]),
+];
+
+/// Variables that make the commands reading them run another program, refused in
+/// `NAME=value command` assignments. Names ending in `*` are prefixes.
+const EXECUTING_VARIABLES: &[&str] = &[
+    "GIT_*", "PAGER", "LESSOPEN", "LESSCLOSE", "EDITOR", "VISUAL", "LD_*", "DYLD_*", "BASH_ENV",
+    "ENV", "PATH",
+];
+
+/// Options that make an otherwise read-only command write or execute other commands
+const MUTATING_FIND_OPTIONS: &[&str] = &[
+    "-delete", "-exec", "-execdir", "-ok", "-okdir", "-fprint", "-fprint0", "-fprintf", "-fls",
+];
+
+/// Returns a reason if the tool call is not allowed in read-only mode
+pub fn check_tool_call(tool_call: &ToolCall) -> Option<String> {
+    if ToolRegistry::builtin().is_mutating(&tool_call.tool) {
+        return Some(format!(
+            "{} is disabled in read-only mode. Describe the change instead of making it.",
+            tool_call.tool
+        ));
+    }
+    if tool_call.tool == "shell" {
+        let command = tool_call.args.get("command").and_then(|v| v.as_str())?;
+        return check_shell_command(command)
+            .err()
+            .map(|reason| format!("Command blocked in read-only mode: {}", reason));
+    }
+    None
+}
+
+/// Check that a shell command only reads. Returns the reason it was refused otherwise.
+pub fn check_shell_command(command: &str) -> Result<(), String> {
+    let segments = split_command(command)?;
+    if output_targets(command)
+        .iter()
+        .any(|target| target != "/dev/null")
+    {
+        return Err("output redirection to a file is not allowed".to_string());
+    }
+    for segment in segments {
+        let mut words: Vec<&str> = segment.split_whitespace().collect();
+        // Skip leading VAR=value assignments
+        let assignments = words.iter().take_while(|word| is_assignment(word)).count();
+        for assignment in words.drain(..assignments) {
+            let name = assignment
+                .split_once('=')
+                .map_or(assignment, |(name, _)| name);
+            if is_executing_variable(name) {
+                return Err(format!("setting {} can run another program", name));
+            }
+        }
+        let Some(program) = words.first() else {
+            continue;
+        };
+        let program = program.trim_matches(|c| c == '"' || c == '\'');
+        let name = program.rsplit('/').next().unwrap_or(program);
+        if !READ_ONLY_COMMANDS.contains(&name) {
+            return Err(format!("'{}' is not on the read-only allowlist", name));
+        }
+
+        let args = &words[1..];
+        if args.iter().any(|a| a.starts_with("--output")) {
+            return Err(format!("{} --output writes to a file", name));
+        }
+        for (command, options) in EXECUTING_OPTIONS {
+            if let Some(option) = args
+                .iter()
+                .find(|a| *command == name && options.iter().any(|o| is_option(a, o)))
+            {
+                return Err(format!("{} {} runs another program", name, option));
+            }
+        }
+        match name {
+            "git" => {
+                // Skip global options, including the value of -C <path>
+                let mut rest = args.iter();
+                let mut subcommand = None;
+                while let Some(arg) = rest.next() {
+                    if let Some(option) = MUTATING_GIT_GLOBAL_OPTIONS
+                        .iter()
+                        .find(|option| is_option(arg, option))
+                    {
+                        return Err(format!("git {} can make git run another program", option));
+                    }
+                    if *arg == "-C" {
+                        rest.next();
+                    } else if !arg.starts_with('-') {
+                        subcommand = Some(*arg);
+                        break;
+                    }
+                }
+                if !subcommand.is_some_and(|s| READ_ONLY_GIT.contains(&s)) {
+                    return Err(format!(
+                        "'git {}' may modify the repository",
+                        subcommand.unwrap_or("")
+                    ));
+                }
+                if subcommand == Some("reflog") {
+                    // Without a subcommand, or with options first, reflog shows the log
+                    let action = rest.clone().next().filter(|a| !a.starts_with('-'));
+                    if let Some(action) = action.filter(|a| !READ_ONLY_REFLOG.contains(a)) {
+                        return Err(format!("'git reflog {}' may modify the repository", action));
+                    }
+                }
+                let option = rest.find(|a| MUTATING_GIT_OPTIONS.iter().any(|o| is_option(a, o)));
+                if let Some(option) = option {
+                    return Err(format!("git {} runs another program", option));
+                }
+            }
+            "cargo" => {
+                let subcommand = args.first().copied().unwrap_or("");
+                if !READ_ONLY_CARGO.contains(&subcommand) {
+                    return Err(format!("'cargo {}' may write build output", subcommand));
+                }
+                if LOCKING_CARGO.contains(&subcommand)
+                    && !args.iter().any(|a| matches!(*a, "--locked" | "--frozen"))
+                {
+                    return Err(format!(
+                        "'cargo {}' may write Cargo.lock; add --locked",
+                        subcommand
+                    ));
+                }
+            }
+            "find" => {
+                if let Some(option) = args.iter().find(|a| MUTATING_FIND_OPTIONS.contains(a)) {
+                    return Err(format!("find {} is not allowed", option));
+                }
+            }
+            "sort" => {
+                // GNU sort also takes unambiguous prefixes of its long options
+                let writes = |a: &&str| {
+                    is_short_option_cluster_with(a, 'o') || is_abbreviated_option(a, "--output", 3)
+                };
+                if args.iter().any(writes) {
+                    return Err("sort -o writes to a file".to_string());
+                }
+                if let Some(option) = args
+                    .iter()
+                    .find(|a| is_abbreviated_option(a, "--compress-program", 4))
+                {
+                    return Err(format!("sort {} runs another program", option));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Split a command line into simple commands on `|`, `;`, `&` and newlines outside of quotes,
+/// refusing command and process substitution
+fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (Some('\''), c) => current.push(c),
+            (_, '\\') => {
+                // An escaped character, quote or separator is just text
+                current.push(c);
+                current.extend(chars.next());
+            }
+            (_, '$') if chars.peek() == Some(&'(') => {
+                return Err("command substitution is not allowed".to_string());
+            }
+            (_, '`') => return Err("command substitution is not allowed".to_string()),
+            (Some(_), c) => current.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (None, '|' | ';' | '&' | '\n') => {
+                // `2>&1` and `&>file` are redirections, checked by `output_targets`
+                if c == '&' && (current.ends_with('>') || chars.peek() == Some(&'>')) {
+                    current.push(c);
+                    continue;
+                }
+                segments.push(std::mem::take(&mut current));
+            }
+            (None, '<' | '>') if chars.peek() == Some(&'(') => {
+                return Err("process substitution is not allowed".to_string());
+            }
+            (None, c) => current.push(c),
+        }
+    }
+    segments.push(current);
+    Ok(segments)
+}
+
+/// Whether `arg` is a cluster of short options (`-uo`) that includes `flag`
+fn is_short_option_cluster_with(arg: &str, flag: char) -> bool {
+    arg.strip_prefix('-')
+        .is_some_and(|flags| !flags.starts_with('-') && flags.contains(flag))
+}
+
+/// Whether `arg` is `option` cut to at least `min_len` characters, with or without a value
+fn is_abbreviated_option(arg: &str, option: &str, min_len: usize) -> bool {
+    let name = arg.split_once('=').map_or(arg, |(name, _)| name);
+    name.len() >= min_len && option.starts_with(name)
+}
+
+/// Whether `arg` is `option`, with its value attached (`-Ocmd`, `--pre=cmd`) or not
+fn is_option(arg: &str, option: &str) -> bool {
+    match arg.strip_prefix(option) {
+        Some(rest) => rest.is_empty() || rest.starts_with('=') || !option.starts_with("--"),
+        None => false,
+    }
+}
+
+fn is_executing_variable(name: &str) -> bool {
+    EXECUTING_VARIABLES
+        .iter()
+        .any(|variable| match variable.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == *variable,
+        })
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_allows_inspection_commands() {
+        for command in [
+            "ls -la",
+            "cat src/main.rs | grep -n 'fn main'",
+            "rg \"a|b\" src && wc -l README.md",
+            "git log --oneline -5",
+            "git diff HEAD~1 -- src/",
+            "git -C repo status",
+            "find . -name '*.rs' 2>/dev/null | head",
+            "cargo tree --locked 2>&1",
+            "cargo metadata --frozen --format-version 1",
+            "git reflog",
+            "git reflog show --date=iso main",
+            "git reflog exists HEAD",
+            "echo \\' done",
+            "RUST_LOG=debug ls",
+            "ls >&2",
+            "ls 2>&- | head",
+            "ls &>/dev/null",
+            "grep '>&file' notes.txt",
+            "sort -u -k2 notes.txt",
+        ] {
+            assert!(
+                check_shell_command(command).is_ok(),
+                "{} was blocked",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_blocks_mutating_commands() {
+        for command in [
+            "rm -rf target",
+            "echo hi > notes.txt",
+            "cat a >> b",
+            "ls; touch x",
+            "git checkout -- .",
+            "git commit -am wip",
+            "find . -name '*.tmp' -delete",
+            "cargo build",
+            "echo $(rm x)",
+            "ls `rm x`",
+            "sed -i s/a/b/ file",
+            "/bin/rm x",
+            "ls | xargs rm",
+            "git diff --output=patch.diff",
+            "sort -o sorted.txt input.txt",
+            "uniq in.txt out.txt",
+            "xxd in.bin out.hex",
+            "tree -o listing.txt",
+            "echo x >&README.md",
+            "echo x >& README.md",
+            "ls &>listing.txt",
+            "ls >/dev/null.bak",
+            "ls 2>/dev/null/../../tmp/x",
+            "sort -uo out.txt in.txt",
+            "sort --output=out.txt in.txt",
+            "sort --out=out.txt in.txt",
+            "echo \\' ; rm -rf target ; echo \\'",
+            "git reflog expire --expire=now --all",
+            "git reflog delete HEAD@{1}",
+            "sort --compress-program=sh in.txt",
+            "sort --compress=sh in.txt",
+            "cargo tree",
+            "cargo metadata --format-version 1",
+        ] {
+            assert!(
+                check_shell_command(command).is_err(),
+                "{} was allowed",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_blocks_process_substitution() {
+        for command in ["cat <(touch x)", "diff a >(sh)", "ls; grep x <(rm -rf y)"] {
+            assert!(
+                check_shell_command(command).is_err(),
+                "{} was allowed",
+                command
+            );
+        }
+        // Quoted, it is just text
+        assert!(check_shell_command("grep '<(' notes.txt").is_ok());
+    }
+
+    #[test]
+    fn test_blocks_programs_run_by_git_and_search_tools() {
+        for command in [
+            r"git -c core.pager=sh\ -c\ 'touch\ x' log",
+            "git -c diff.external=./evil.sh diff",
+            "git --config-env=core.pager=EVIL log",
+            "git --exec-path=/tmp/evil status",
+            "git diff --ext-diff",
+            "git grep -Ovim TODO",
+            "git grep --open-files-in-pager=sh TODO",
+            "GIT_EXTERNAL_DIFF=./evil.sh git diff",
+            "PAGER=sh git log -p",
+            "rg --pre=./evil.sh TODO",
+            "fd -x rm",
+            "ag --pager sh TODO",
+        ] {
+            assert!(
+                check_shell_command(command).is_err(),
+                "{} was allowed",
+                command
+            );
+        }
+        for command in [
+            "git -C repo log -p",
+            "rg -o TODO src",
+            "LC_ALL=C sort notes.txt",
+        ] {
+            assert!(
+                check_shell_command(command).is_ok(),
+                "{} was blocked",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_tool_call() {
+        let write = ToolCall {
+            tool: "write_file".to_string(),
+            args: json!({"file_path": "a.txt", "content": "x"}),
+        };
+        assert!(check_tool_call(&write).is_some());
+
+        let read = ToolCall {
+            tool: "read_file".to_string(),
+            args: json!({"file_path": "a.txt"}),
+        };
+        assert!(check_tool_call(&read).is_none());
+
+        let shell = ToolCall {
+            tool: "shell".to_string(),
+            args: json!({"command": "rm a.txt"}),
+        };
+        assert!(check_tool_call(&shell).unwrap().contains("read-only"));
+    }
+}
@@ -0,0 +1,80 @@
+//! Working on another machine over SSH (`[remote]`).
+//!
+//! With a remote host configured, shell commands run there, and read_file and write_file
+//! read and write its files; paths are the host's. str_replace and edit_symbol would edit
+//! the local copy, so they are refused, and the other tools keep working on local files.
+//! The workspace's `.env` variables are only sent to the host with `forward_env`. Remote
+//! files aren't locked, tracked for `/diff` and undo, or committed with `commit_edits`,
+//! since all of that works on the local workspace.
+
+use g3_config::RemoteConfig;
+use g3_execution::SshTarget;
+
+/// Tools that work on the remote host when there is one
+pub const REMOTE_TOOLS: &[&str] = &["shell", "read_file", "write_file"];
+
+/// Tools that edit files in place, which only work on local files
+pub const LOCAL_EDIT_TOOLS: &[&str] = &["str_replace", "edit_symbol"];
+
+/// Why `tool` is refused while working on `target`
+pub fn refusal(tool: &str, target: &SshTarget) -> String {
+    format!(
+        "{} only edits local files, but this session works on {}. Read the file with \
+         read_file and write it back whole with write_file instead.",
+        tool,
+        target.host()
+    )
+}
+
+/// The host of `[remote]`, if one is set
+pub fn target(config: &RemoteConfig) -> Option<SshTarget> {
+    let host = config
+        .host
+        .as_deref()
+        .filter(|host| !host.trim().is_empty())?;
+    Some(
+        SshTarget::new(host)
+            .with_port(config.port)
+            .with_identity_file(
+                config
+                    .identity_file
+                    .as_deref()
+                    .map(|path| shellexpand::tilde(path).into_owned().into()),
+            )
+            .with_dir(config.dir.clone()),
+    )
+}
+
+/// What the system prompt says about the remote host
+pub fn prompt(target: &SshTarget) -> String {
+    let dir = match target.dir() {
+        Some(dir) => format!(", in {}", dir),
+        None => String::new(),
+    };
+    format!(
+        "\n\n# Remote host\n\nThis session works on the remote machine {}{}. Shell commands \
+         run there, and read_file and write_file read and write its files, so use paths on \
+         that machine. str_replace and edit_symbol are refused; to change a file, read it \
+         and write it back whole with write_file. Other tools work on local files.",
+        target.host(),
+        dir
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remote_target() {
+        assert_eq!(target(&RemoteConfig::default()), None);
+        let config = RemoteConfig {
+            host: Some("ci@build-01".to_string()),
+            dir: Some("/srv/app".to_string()),
+            ..Default::default()
+        };
+        let remote = target(&config).unwrap();
+        assert_eq!(remote.host(), "ci@build-01");
+        assert!(prompt(&remote).contains("remote machine ci@build-01, in /srv/app."));
+    }
+}
@@ -0,0 +1,382 @@
+//! Commits of an autonomous run's rounds, and the review that turns them into a clean branch.
+//!
+//! With `commit_rounds` enabled, an autonomous run in a git repository starts a branch
+//! `g3/run-<run id>` from the current commit and commits the player's changes at the end of
+//! each round, with the round number and the coach feedback it addressed in the message.
+//! The run is recorded in `.g3/runs/<run id>.json`. `g3 review-run <run id>` goes through
+//! the rounds one by one; the accepted ones are cherry-picked onto the run's starting point,
+//! squashed ones folded into the commit before, and the result is left on
+//! `g3/run-<run id>-reviewed`. The review works in a temporary worktree, so the working
+//! tree and the checked out branch are left alone.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+/// Where run records are kept, relative to the workspace
+pub const RUNS_DIR: &str = ".g3/runs";
+
+const BRANCH_PREFIX: &str = "g3/run-";
+
+/// Round commit subjects are cut to this many characters
+const MAX_SUBJECT_CHARS: usize = 72;
+
+/// The commit of one round
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundCommit {
+    pub round: usize,
+    pub commit: String,
+    pub subject: String,
+}
+
+/// An autonomous run whose rounds are committed to a branch of their own
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub run_id: String,
+    /// Commit the run started from
+    pub base: String,
+    pub branch: String,
+    pub started_at: DateTime<Utc>,
+    pub rounds: Vec<RoundCommit>,
+}
+
+/// What to do with a round when building the reviewed branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundChoice {
+    Accept,
+    Reject,
+    /// Fold into the previous accepted round; accepted as is if there is none
+    Squash,
+}
+
+/// The branch a review produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReviewOutcome {
+    pub branch: String,
+    pub commits: usize,
+    /// Rounds that didn't apply without the rounds rejected before them, and were left out
+    pub conflicts: Vec<usize>,
+}
+
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Stage everything but g3's own files, and say whether anything is staged
+fn stage_changes(workspace: &Path) -> Result<bool> {
+    git(workspace, &["add", "-A", "--", ".", ":(exclude).g3"])?;
+    Ok(!git(workspace, &["diff", "--cached", "--name-only"])?
+        .trim()
+        .is_empty())
+}
+
+fn head(dir: &Path) -> Result<String> {
+    Ok(git(dir, &["rev-parse", "HEAD"])?.trim().to_string())
+}
+
+impl RunRecord {
+    /// Start a branch for a run in `workspace` and check it out. Uncommitted changes from
+    /// before the run are committed first, so they aren't mistaken for the first round's.
+    /// Returns `None` if the workspace is not a git repository with at least one commit.
+    pub fn start(workspace: &Path) -> Result<Option<Self>> {
+        if git(workspace, &["rev-parse", "--verify", "HEAD"]).is_err() {
+            debug!("Not committing rounds: no git history in workspace");
+            return Ok(None);
+        }
+        let run_id = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let branch = format!("{}{}", BRANCH_PREFIX, run_id);
+        git(workspace, &["checkout", "-q", "-b", &branch])?;
+        if stage_changes(workspace)? {
+            let message = format!("g3 run {}: uncommitted changes from before the run", run_id);
+            git(workspace, &["commit", "-q", "--no-verify", "-m", &message])?;
+        }
+
+        let record = Self {
+            run_id,
+            base: head(workspace)?,
+            branch,
+            started_at: Utc::now(),
+            rounds: Vec::new(),
+        };
+        record.save(workspace)?;
+        Ok(Some(record))
+    }
+
+    fn path(workspace: &Path, run_id: &str) -> PathBuf {
+        workspace.join(RUNS_DIR).join(format!("{}.json", run_id))
+    }
+
+    pub fn load(workspace: &Path, run_id: &str) -> Result<Self> {
+        let path = Self::path(workspace, run_id);
+        let contents = std::fs::read_to_string(&path).map_err(|_| {
+            let known = Self::list(workspace).unwrap_or_default();
+            if known.is_empty() {
+                anyhow!(
+                    "No run {} in {}",
+                    run_id,
+                    workspace.join(RUNS_DIR).display()
+                )
+            } else {
+                anyhow!("No run {}; recorded runs: {}", run_id, known.join(", "))
+            }
+        })?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid run in {}", path.display()))
+    }
+
+    fn save(&self, workspace: &Path) -> Result<()> {
+        let path = Self::path(workspace, &self.run_id);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Ids of the recorded runs, oldest first
+    pub fn list(workspace: &Path) -> Result<Vec<String>> {
+        let entries = match std::fs::read_dir(workspace.join(RUNS_DIR)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut ids: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.strip_suffix(".json").map(str::to_string)
+            })
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Commit the workspace's changes as `round`. `summary` is the player's report of the
+    /// round and `feedback` the coach feedback it addressed. Returns `None` if the round
+    /// changed nothing.
+    pub fn commit_round(
+        &mut self,
+        workspace: &Path,
+        round: usize,
+        summary: &str,
+        feedback: &str,
+    ) -> Result<Option<&RoundCommit>> {
+        if !stage_changes(workspace)? {
+            return Ok(None);
+        }
+        let subject = round_subject(round, summary);
+        git(
+            workspace,
+            &[
+                "commit",
+                "-q",
+                "--no-verify",
+                "-m",
+                &round_message(&self.run_id, round, &subject, feedback),
+            ],
+        )?;
+        self.rounds.push(RoundCommit {
+            round,
+            commit: head(workspace)?,
+            subject,
+        });
+        self.save(workspace)?;
+        Ok(self.rounds.last())
+    }
+
+    /// Cherry-pick the rounds onto the run's starting point as `choices` (one per round)
+    /// say, on `<branch>-reviewed`
+    pub fn build_reviewed_branch(
+        &self,
+        workspace: &Path,
+        choices: &[RoundChoice],
+    ) -> Result<ReviewOutcome> {
+        let worktree =
+            std::env::temp_dir().join(format!("g3-review-{}-{}", self.run_id, std::process::id()));
+        let worktree_arg = worktree.to_string_lossy().to_string();
+        git(
+            workspace,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "--detach",
+                &worktree_arg,
+                &self.base,
+            ],
+        )?;
+        let result = self.apply_choices(&worktree, choices);
+        if let Err(e) = git(workspace, &["worktree", "remove", "--force", &worktree_arg]) {
+            debug!("Failed to remove review worktree: {}", e);
+        }
+        result
+    }
+
+    fn apply_choices(&self, worktree: &Path, choices: &[RoundChoice]) -> Result<ReviewOutcome> {
+        let mut commits = 0;
+        let mut conflicts = Vec::new();
+        for (round, choice) in self.rounds.iter().zip(choices) {
+            if *choice == RoundChoice::Reject {
+                continue;
+            }
+            if git(worktree, &["cherry-pick", "--no-commit", &round.commit]).is_err() {
+                git(worktree, &["reset", "-q", "--hard", "HEAD"])?;
+                conflicts.push(round.round);
+                continue;
+            }
+            if git(worktree, &["diff", "--cached", "--name-only"])?
+                .trim()
+                .is_empty()
+            {
+                continue;
+            }
+            if *choice == RoundChoice::Squash && commits > 0 {
+                git(
+                    worktree,
+                    &["commit", "-q", "--no-verify", "--amend", "--no-edit"],
+                )?;
+            } else {
+                git(
+                    worktree,
+                    &["commit", "-q", "--no-verify", "-C", &round.commit],
+                )?;
+                commits += 1;
+            }
+        }
+
+        let branch = format!("{}-reviewed", self.branch);
+        git(worktree, &["branch", "-f", &branch, "HEAD"])?;
+        Ok(ReviewOutcome {
+            branch,
+            commits,
+            conflicts,
+        })
+    }
+}
+
+/// The first line of the player's summary, as a commit subject
+fn round_subject(round: usize, summary: &str) -> String {
+    let line = summary
+        .lines()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or("player changes");
+    let subject = format!("g3 round {}: {}", round, line);
+    match subject.char_indices().nth(MAX_SUBJECT_CHARS) {
+        Some((end, _)) => format!("{}…", subject[..end].trim_end()),
+        None => subject,
+    }
+}
+
+fn round_message(run_id: &str, round: usize, subject: &str, feedback: &str) -> String {
+    let mut message = format!("{}\n\n", subject);
+    if !feedback.trim().is_empty() {
+        message.push_str(&format!(
+            "Addresses coach feedback:\n{}\n\n",
+            feedback.trim()
+        ));
+    }
+    message.push_str(&format!("G3-Run: {}\nG3-Round: {}\n", run_id, round));
+    message
+}
+
+/// The message, stat and patch of a round's commit
+pub fn round_diff(workspace: &Path, round: &RoundCommit) -> Result<String> {
+    git(
+        workspace,
+        &["show", "--stat", "--patch", "--format=%B", &round.commit],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn run_git(dir: &Path, args: &[&str]) -> String {
+        git(dir, args).unwrap()
+    }
+
+    #[test]
+    fn test_review_run_builds_branch_from_choices() {
+        let workspace = TempDir::new().unwrap();
+        let dir = workspace.path();
+        run_git(dir, &["init", "-q"]);
+        run_git(dir, &["config", "user.name", "g3"]);
+        run_git(dir, &["config", "user.email", "g3@example.com"]);
+        std::fs::write(dir.join("lib.rs"), "fn a() {}\n").unwrap();
+        run_git(dir, &["add", "."]);
+        run_git(dir, &["commit", "-q", "-m", "initial"]);
+        std::fs::write(dir.join("notes.txt"), "before the run").unwrap();
+
+        let mut run = RunRecord::start(dir).unwrap().unwrap();
+        assert_eq!(
+            run_git(dir, &["log", "-1", "--format=%s"]).trim(),
+            format!(
+                "g3 run {}: uncommitted changes from before the run",
+                run.run_id
+            )
+        );
+
+        std::fs::write(dir.join("a.rs"), "fn one() {}\n").unwrap();
+        let first = run
+            .commit_round(dir, 1, "## Added a.rs\nDetails", "")
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.subject, "g3 round 1: Added a.rs");
+        std::fs::write(dir.join("b.rs"), "fn two() {}\n").unwrap();
+        run.commit_round(dir, 2, "Added b.rs", "Add b").unwrap();
+        std::fs::write(dir.join("a.rs"), "fn one() { two() }\n").unwrap();
+        run.commit_round(dir, 3, "Call two from one", "Use b")
+            .unwrap();
+        assert!(run.commit_round(dir, 4, "Nothing", "").unwrap().is_none());
+        assert_eq!(RunRecord::load(dir, &run.run_id).unwrap(), run);
+        assert!(round_diff(dir, &run.rounds[1])
+            .unwrap()
+            .contains("G3-Round: 2"));
+
+        let outcome = run
+            .build_reviewed_branch(
+                dir,
+                &[
+                    RoundChoice::Accept,
+                    RoundChoice::Reject,
+                    RoundChoice::Squash,
+                ],
+            )
+            .unwrap();
+        assert_eq!(outcome.branch, format!("g3/run-{}-reviewed", run.run_id));
+        assert_eq!(outcome.commits, 1);
+        assert!(outcome.conflicts.is_empty());
+        let files = run_git(dir, &["ls-tree", "--name-only", &outcome.branch]);
+        assert_eq!(
+            files.split_whitespace().collect::<Vec<_>>(),
+            ["a.rs", "lib.rs", "notes.txt"]
+        );
+        assert_eq!(
+            run_git(dir, &["show", &format!("{}:a.rs", outcome.branch)]),
+            "fn one() { two() }\n"
+        );
+        // The working tree is left on the run's branch
+        assert_eq!(
+            run_git(dir, &["branch", "--show-current"]).trim(),
+            run.branch
+        );
+    }
+}
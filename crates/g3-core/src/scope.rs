@@ -0,0 +1,291 @@
+//! Per-directory tool scoping (`[scope]`), for working on one part of a monorepo.
+//!
+//! Files may only be written under the `write` directories and read under the `read`
+//! directories; an empty list allows the whole filesystem. Shell commands run in the
+//! `shell` directory, and commands that `cd` out of it or redirect output to a file outside
+//! the `write` directories are refused. Like `.g3ignore`, shell commands are checked on a
+//! best-effort basis; the scope keeps an agent on task rather than sandboxing it.
+
+use crate::shell_redirect::output_targets;
+use crate::tool_registry::ToolRegistry;
+use crate::ToolCall;
+use g3_config::ScopeConfig;
+use std::path::{Component, Path, PathBuf};
+
+/// The directories of `[scope]`, resolved against the workspace
+#[derive(Debug, Clone, Default)]
+pub struct PathScope {
+    root: PathBuf,
+    write: Vec<PathBuf>,
+    read: Vec<PathBuf>,
+    shell: Option<PathBuf>,
+}
+
+impl PathScope {
+    pub fn new(root: &Path, config: &ScopeConfig) -> Self {
+        let resolve = |dir: &String| normalize(root, dir);
+        Self {
+            root: root.to_path_buf(),
+            write: config.write.iter().map(resolve).collect(),
+            read: config.read.iter().map(resolve).collect(),
+            shell: config.shell.as_ref().map(resolve),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.write.is_empty() && self.read.is_empty() && self.shell.is_none()
+    }
+
+    /// The directory shell commands run in, if the scope sets one
+    pub fn shell_dir(&self) -> Option<&Path> {
+        self.shell.as_deref()
+    }
+
+    /// Appended to the system prompt, so the model knows its limits before hitting them.
+    /// Empty without a scope.
+    pub fn prompt(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        let mut prompt =
+            "\n\n# Scope\n\nThis session is limited to part of the workspace.".to_string();
+        if !self.write.is_empty() {
+            prompt.push_str(&format!(
+                " Only create or modify files under {}.",
+                self.describe(&self.write)
+            ));
+        }
+        if !self.read.is_empty() {
+            prompt.push_str(&format!(
+                " Only read files under {}.",
+                self.describe(&self.read)
+            ));
+        }
+        if let Some(shell) = &self.shell {
+            prompt.push_str(&format!(
+                " Shell commands run in {} and must not change out of it.",
+                self.display(shell)
+            ));
+        }
+        prompt.push_str(" Tool calls outside these directories will be refused.");
+        prompt
+    }
+
+    /// Returns a reason if the tool call acts outside the scope
+    pub fn check_tool_call(&self, tool_call: &ToolCall) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        if let Some(file_path) = tool_call.args.get("file_path").and_then(|v| v.as_str()) {
+            let path = normalize(&self.root, file_path);
+            if ToolRegistry::builtin().is_mutating(&tool_call.tool) {
+                if !within(&path, &self.write) {
+                    return Some(format!(
+                        "{} is outside the directories that may be written ({})",
+                        file_path,
+                        self.describe(&self.write)
+                    ));
+                }
+            } else if !within(&path, &self.read) {
+                return Some(format!(
+                    "{} is outside the directories that may be read ({})",
+                    file_path,
+                    self.describe(&self.read)
+                ));
+            }
+        }
+        if tool_call.tool == "shell" {
+            let command = tool_call.args.get("command").and_then(|v| v.as_str())?;
+            return self
+                .check_shell_command(command)
+                .err()
+                .map(|reason| format!("Command blocked by [scope]: {}", reason));
+        }
+        None
+    }
+
+    /// Check that a shell command stays in the shell directory and only redirects output
+    /// to files that may be written
+    pub fn check_shell_command(&self, command: &str) -> Result<(), String> {
+        let cwd = self.shell.as_deref().unwrap_or(&self.root);
+        if let Some(shell) = &self.shell {
+            for segment in command.split(['|', ';', '&', '\n']) {
+                let mut words = segment.split_whitespace().map(unquote);
+                if !matches!(words.next(), Some("cd" | "pushd")) {
+                    continue;
+                }
+                let target = words.next().unwrap_or("~");
+                if target == "-" || !normalize(cwd, target).starts_with(shell) {
+                    return Err(format!(
+                        "commands must run within {}, not {}",
+                        self.display(shell),
+                        target
+                    ));
+                }
+            }
+        }
+        if !self.write.is_empty() {
+            for target in output_targets(command) {
+                if target != "/dev/null" && !within(&normalize(cwd, &target), &self.write) {
+                    return Err(format!(
+                        "{} is outside the directories that may be written ({})",
+                        target,
+                        self.describe(&self.write)
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn describe(&self, dirs: &[PathBuf]) -> String {
+        dirs.iter()
+            .map(|dir| self.display(dir))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// `dir` relative to the workspace where it is inside it
+    fn display(&self, dir: &Path) -> String {
+        match dir.strip_prefix(&self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Ok(relative) => format!("{}/", relative.display()),
+            Err(_) => dir.display().to_string(),
+        }
+    }
+}
+
+/// Whether `path` is inside one of `dirs`; an empty list allows everything
+fn within(path: &Path, dirs: &[PathBuf]) -> bool {
+    dirs.is_empty() || dirs.iter().any(|dir| path.starts_with(dir))
+}
+
+fn unquote(word: &str) -> &str {
+    word.trim_matches(|c| c == '"' || c == '\'')
+}
+
+/// `path` made absolute against `base`, with `~` expanded and without `.` or `..` components
+fn normalize(base: &Path, path: &str) -> PathBuf {
+    let expanded = shellexpand::tilde(path);
+    let mut normalized = PathBuf::new();
+    for component in base.join(expanded.as_ref()).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(tool: &str, args: serde_json::Value) -> ToolCall {
+        ToolCall {
+            tool: tool.to_string(),
+            args,
+        }
+    }
+
+    fn payments_scope() -> PathScope {
+        PathScope::new(
+            Path::new("/repo"),
+            &ScopeConfig {
+                write: vec!["services/payments".to_string()],
+                read: Vec::new(),
+                shell: Some("services/payments".to_string()),
+            },
+        )
+    }
+
+    #[test]
+    fn test_writes_are_limited_to_the_write_directories() {
+        let scope = payments_scope();
+        let write = |path: &str| {
+            scope.check_tool_call(&call(
+                "write_file",
+                json!({"file_path": path, "content": ""}),
+            ))
+        };
+        assert_eq!(write("services/payments/src/refund.rs"), None);
+        assert_eq!(write("/repo/services/payments/Cargo.toml"), None);
+        assert_eq!(
+            write("services/payments/../billing/src/lib.rs").unwrap(),
+            "services/payments/../billing/src/lib.rs is outside the directories that may be \
+             written (services/payments/)"
+        );
+        assert!(write("services/payments-legacy/lib.rs").is_some());
+        assert!(scope
+            .check_tool_call(&call(
+                "str_replace",
+                json!({"file_path": "README.md", "diff": ""})
+            ))
+            .is_some());
+        // Reads are allowed anywhere
+        assert_eq!(
+            scope.check_tool_call(&call(
+                "read_file",
+                json!({"file_path": "services/auth/lib.rs"})
+            )),
+            None
+        );
+
+        let read_scope = PathScope::new(
+            Path::new("/repo"),
+            &ScopeConfig {
+                read: vec!["services".to_string()],
+                ..Default::default()
+            },
+        );
+        assert!(read_scope
+            .check_tool_call(&call("read_file", json!({"file_path": "/etc/passwd"})))
+            .is_some());
+        assert!(PathScope::new(Path::new("/repo"), &ScopeConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn test_shell_commands_stay_in_the_shell_directory() {
+        let scope = payments_scope();
+        assert_eq!(
+            scope.shell_dir(),
+            Some(Path::new("/repo/services/payments"))
+        );
+        for command in [
+            "cargo test 2>&1 | tail -20",
+            "cd src && ls",
+            "cat ../../README.md",
+            "echo done > notes.txt",
+            "grep -rn refund src >/dev/null",
+            "cargo test >&2",
+            "cargo build &>build.log",
+        ] {
+            assert!(
+                scope.check_shell_command(command).is_ok(),
+                "{} was blocked",
+                command
+            );
+        }
+        for command in [
+            "cd .. && cargo build",
+            "ls; cd /tmp",
+            "cd",
+            "pushd ../billing",
+            "echo hi > ../billing/notes.txt",
+            "cat a >> /etc/hosts",
+            "echo x >&/etc/passwd",
+            "echo x >& ../billing/notes.txt",
+            "cargo build &>../billing/build.log",
+        ] {
+            assert!(
+                scope.check_shell_command(command).is_err(),
+                "{} was allowed",
+                command
+            );
+        }
+    }
+}
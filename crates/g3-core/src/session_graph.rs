@@ -0,0 +1,269 @@
+//! Named states of a session's conversation, kept as a small graph.
+//!
+//! `/tag <name>` saves the conversation as a state whose parent is the state the
+//! conversation last came from, and names it. `/branch <tag>` puts a saved conversation
+//! back; states saved after that have it as their parent, so exploring several approaches
+//! from one point gives a tree of states, like commits and branches in git. The current
+//! conversation is saved (untagged) before a branch replaces it, so it can be branched back
+//! to by its number.
+//!
+//! States live in `.g3/sessions/<session id>/graph/`: `graph.json` holds the states, tags and
+//! head, and `states/<n>.json` the messages of each state. Only the conversation is saved;
+//! files are left as they are (see `/undo` and `/rollback`).
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use g3_providers::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::file_changes;
+
+/// Graph data, relative to the session directory
+pub const GRAPH_DIR: &str = "graph";
+
+const GRAPH_FILE: &str = "graph.json";
+const STATES_DIR: &str = "states";
+
+/// A saved conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    /// Numbered from 1 in the order the states were saved
+    pub id: usize,
+    /// The state the conversation came from, if any
+    pub parent: Option<usize>,
+    pub messages: usize,
+    /// SHA-256 of the messages, to tell whether the conversation changed
+    pub hash: String,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Graph {
+    states: Vec<State>,
+    tags: BTreeMap<String, usize>,
+    /// The state the current conversation came from
+    head: Option<usize>,
+}
+
+/// The saved states of one session
+pub struct SessionGraph {
+    dir: PathBuf,
+    graph: Graph,
+}
+
+impl SessionGraph {
+    /// The graph in `dir`, empty if nothing was saved there yet
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        let path = dir.join(GRAPH_FILE);
+        let graph = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Graph::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self { dir, graph })
+    }
+
+    pub fn states(&self) -> &[State] {
+        &self.graph.states
+    }
+
+    pub fn head(&self) -> Option<usize> {
+        self.graph.head
+    }
+
+    /// Tags of the state `id`, in name order
+    pub fn tags_of(&self, id: usize) -> Vec<&str> {
+        self.graph
+            .tags
+            .iter()
+            .filter(|(_, &state)| state == id)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Save `messages` as a child of the head, which it becomes. If the conversation hasn't
+    /// changed since the head was saved, the head is returned instead.
+    pub fn save(&mut self, messages: &[Message]) -> Result<usize> {
+        let json = serde_json::to_string(messages)?;
+        let hash = file_changes::hash(json.as_bytes());
+        if let Some(head) = self.graph.head.and_then(|id| self.state(id)) {
+            if head.hash == hash {
+                return Ok(head.id);
+            }
+        }
+
+        let id = self.graph.states.len() + 1;
+        let states_dir = self.dir.join(STATES_DIR);
+        std::fs::create_dir_all(&states_dir)
+            .with_context(|| format!("Failed to create {}", states_dir.display()))?;
+        std::fs::write(states_dir.join(format!("{}.json", id)), json)
+            .context("Failed to save the conversation")?;
+        self.graph.states.push(State {
+            id,
+            parent: self.graph.head,
+            messages: messages.len(),
+            hash,
+            created: Utc::now(),
+        });
+        self.graph.head = Some(id);
+        self.write()?;
+        Ok(id)
+    }
+
+    /// Save `messages` and name the state `name`
+    pub fn tag(&mut self, name: &str, messages: &[Message]) -> Result<usize> {
+        validate_tag(name)?;
+        if let Some(id) = self.graph.tags.get(name) {
+            bail!("Tag '{}' already names state #{}", name, id);
+        }
+        let id = self.save(messages)?;
+        self.graph.tags.insert(name.to_string(), id);
+        self.write()?;
+        Ok(id)
+    }
+
+    /// The state a tag names, or the state with a number (`3` or `#3`)
+    pub fn resolve(&self, target: &str) -> Option<usize> {
+        if let Some(&id) = self.graph.tags.get(target) {
+            return Some(id);
+        }
+        let id = target.strip_prefix('#').unwrap_or(target).parse().ok()?;
+        self.state(id).map(|state| state.id)
+    }
+
+    /// The messages of `target`, which becomes the head
+    pub fn checkout(&mut self, target: &str) -> Result<(usize, Vec<Message>)> {
+        let Some(id) = self.resolve(target) else {
+            bail!("No tag or state named '{}'", target);
+        };
+        let path = self.dir.join(STATES_DIR).join(format!("{}.json", id));
+        let json = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let messages = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        self.graph.head = Some(id);
+        self.write()?;
+        Ok((id, messages))
+    }
+
+    /// The states as a tree, children indented under their parent, with their tags. The
+    /// head is marked with `*`.
+    pub fn render(&self) -> String {
+        let mut lines = Vec::new();
+        self.render_children(None, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    fn render_children(&self, parent: Option<usize>, depth: usize, lines: &mut Vec<String>) {
+        for state in self.graph.states.iter().filter(|s| s.parent == parent) {
+            let tags = self.tags_of(state.id);
+            let mut line = format!(
+                "{}{} #{}",
+                "  ".repeat(depth),
+                if self.graph.head == Some(state.id) {
+                    '*'
+                } else {
+                    '-'
+                },
+                state.id
+            );
+            if !tags.is_empty() {
+                line.push_str(&format!(" [{}]", tags.join(", ")));
+            }
+            line.push_str(&format!(
+                " {} messages, {}",
+                state.messages,
+                state
+                    .created
+                    .with_timezone(&chrono::Local)
+                    .format("%H:%M:%S")
+            ));
+            lines.push(line);
+            self.render_children(Some(state.id), depth + 1, lines);
+        }
+    }
+
+    fn state(&self, id: usize) -> Option<&State> {
+        id.checked_sub(1)
+            .and_then(|index| self.graph.states.get(index))
+    }
+
+    fn write(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self.dir.join(GRAPH_FILE);
+        std::fs::write(&path, serde_json::to_string_pretty(&self.graph)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Tags are single words that can't be mistaken for a state number
+fn validate_tag(name: &str) -> Result<()> {
+    if name.is_empty() || name.chars().any(char::is_whitespace) {
+        bail!("Tags must be a single word");
+    }
+    if name
+        .trim_start_matches('#')
+        .chars()
+        .all(|c| c.is_ascii_digit())
+    {
+        bail!("Tags can't be numbers, which name states");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_providers::MessageRole;
+
+    fn conversation(texts: &[&str]) -> Vec<Message> {
+        texts
+            .iter()
+            .map(|text| Message {
+                role: MessageRole::User,
+                content: text.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_session_graph() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut graph = SessionGraph::open(dir.path()).unwrap();
+
+        let start = conversation(&["a"]);
+        assert_eq!(graph.tag("before-refactor", &start).unwrap(), 1);
+        // The same conversation isn't saved twice
+        assert_eq!(graph.tag("start", &start).unwrap(), 1);
+        assert!(graph.tag("start", &start).is_err());
+        assert!(graph.tag("42", &start).is_err());
+        assert!(graph.tag("two words", &start).is_err());
+
+        assert_eq!(graph.tag("try-a", &conversation(&["a", "b"])).unwrap(), 2);
+        let (id, messages) = graph.checkout("before-refactor").unwrap();
+        assert_eq!((id, messages.len()), (1, 1));
+        assert_eq!(
+            graph.tag("try-b", &conversation(&["a", "c", "d"])).unwrap(),
+            3
+        );
+        assert_eq!(graph.resolve("#2"), Some(2));
+        assert_eq!(graph.resolve("9"), None);
+        assert!(graph.checkout("missing").is_err());
+
+        // The graph survives being opened again
+        let graph = SessionGraph::open(dir.path()).unwrap();
+        assert_eq!(graph.head(), Some(3));
+        assert_eq!(graph.states()[2].parent, Some(1));
+        let rendered = graph.render();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("- #1 [before-refactor, start] 1 messages"));
+        assert!(lines[1].starts_with("  - #2 [try-a] 2 messages"));
+        assert!(lines[2].starts_with("  * #3 [try-b] 3 messages"));
+    }
+}
@@ -0,0 +1,279 @@
+//! Retention of session logs (`[logs]`).
+//!
+//! Each session writes its conversation to `logs/g3_session_<id>.json`. The most recent
+//! sessions are kept as plain JSON so they can be read and resumed straight away; older ones
+//! are compressed to `.json.zst` archives (readable with `zstd -d`), and the oldest are
+//! deleted once there are too many, they are too old or all logs together take too much
+//! space. Archives keep the modification time of their log, so sessions stay in order.
+
+use anyhow::{Context, Result};
+use g3_config::LogsConfig;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+pub const LOGS_DIR: &str = "logs";
+
+/// Extension added to a log when it is compressed
+const ARCHIVE_EXTENSION: &str = "zst";
+
+/// zstd level of archives
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// What a prune did
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PruneReport {
+    pub compressed: usize,
+    pub removed: usize,
+    /// Bytes freed by compressing and removing
+    pub freed_bytes: u64,
+}
+
+/// A session's log, plain or compressed
+#[derive(Debug, Clone)]
+struct LogFile {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+impl LogFile {
+    fn is_archive(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|ext| ext == ARCHIVE_EXTENSION)
+    }
+}
+
+/// The session logs in `dir`, newest first, without the log of `current_session`
+fn list(dir: &Path, current_session: Option<&str>) -> Result<Vec<LogFile>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let current = current_session.map(|id| format!("g3_session_{}.json", id));
+    let mut logs: Vec<LogFile> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with("g3_")
+                && (name.ends_with(".json") || name.ends_with(".json.zst"))
+                && current.as_deref() != Some(name.as_str())
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some(LogFile {
+                path: entry.path(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                size: metadata.len(),
+            })
+        })
+        .collect();
+    logs.sort_by_key(|log| std::cmp::Reverse(log.modified));
+    Ok(logs)
+}
+
+/// Compress `log` to an archive next to it, and remove the plain log
+fn compress(log: &LogFile) -> Result<LogFile> {
+    let content = std::fs::read(&log.path)?;
+    let compressed = zstd::encode_all(content.as_slice(), COMPRESSION_LEVEL)?;
+    let mut archive = log.path.clone().into_os_string();
+    archive.push(".");
+    archive.push(ARCHIVE_EXTENSION);
+    let archive = PathBuf::from(archive);
+    // Written aside first, so an interrupted prune never leaves a truncated archive
+    let partial = archive.with_extension("zst.partial");
+    std::fs::write(&partial, &compressed)?;
+    std::fs::File::options()
+        .write(true)
+        .open(&partial)?
+        .set_modified(log.modified)?;
+    std::fs::rename(&partial, &archive)?;
+    std::fs::remove_file(&log.path)?;
+    Ok(LogFile {
+        path: archive,
+        modified: log.modified,
+        size: compressed.len() as u64,
+    })
+}
+
+/// Compress and delete the session logs in `dir` as `config` says, leaving the log of
+/// `current_session` alone. The current session counts as the most recent one.
+pub fn prune(
+    dir: &Path,
+    config: &LogsConfig,
+    current_session: Option<&str>,
+) -> Result<PruneReport> {
+    let logs =
+        list(dir, current_session).with_context(|| format!("Failed to read {}", dir.display()))?;
+    let max_age = config
+        .max_age_days
+        .map(|days| Duration::from_secs(days as u64 * 24 * 60 * 60));
+    let offset = current_session.is_some() as usize;
+    let mut report = PruneReport::default();
+    let remove = |log: &LogFile, report: &mut PruneReport| match std::fs::remove_file(&log.path) {
+        Ok(()) => {
+            debug!("Removed session log {}", log.path.display());
+            report.removed += 1;
+            report.freed_bytes += log.size;
+        }
+        Err(e) => warn!("Failed to remove {}: {}", log.path.display(), e),
+    };
+
+    // Kept logs with their place among the sessions, newest first
+    let mut kept: Vec<(usize, LogFile)> = Vec::new();
+    for (i, log) in logs.into_iter().enumerate() {
+        let rank = i + offset;
+        let too_old =
+            max_age.is_some_and(|max_age| log.modified.elapsed().is_ok_and(|age| age > max_age));
+        let over_limit = config.max_sessions.is_some_and(|max| rank >= max);
+        if too_old || over_limit {
+            remove(&log, &mut report);
+            continue;
+        }
+        if rank < config.keep_uncompressed || log.is_archive() {
+            kept.push((rank, log));
+            continue;
+        }
+        match compress(&log) {
+            Ok(archive) => {
+                debug!("Compressed session log {}", log.path.display());
+                report.compressed += 1;
+                report.freed_bytes += log.size.saturating_sub(archive.size);
+                kept.push((rank, archive));
+            }
+            Err(e) => {
+                warn!("Failed to compress {}: {}", log.path.display(), e);
+                kept.push((rank, log));
+            }
+        }
+    }
+
+    // The most recent sessions stay even when they alone are over the size limit
+    if let Some(max_mb) = config.max_total_mb {
+        let max_bytes = max_mb * 1024 * 1024;
+        let mut total: u64 = kept.iter().map(|(_, log)| log.size).sum();
+        while total > max_bytes {
+            match kept.pop() {
+                Some((rank, log)) if rank >= config.keep_uncompressed => {
+                    total -= log.size;
+                    remove(&log, &mut report);
+                }
+                _ => break,
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Prune `logs/` in the background when a session starts, as `[logs]` says
+pub fn prune_in_background(config: &LogsConfig, current_session: &str) {
+    if !config.auto_prune {
+        return;
+    }
+    let (config, current_session) = (config.clone(), current_session.to_string());
+    tokio::task::spawn_blocking(move || {
+        match prune(Path::new(LOGS_DIR), &config, Some(&current_session)) {
+            Ok(report) if report == PruneReport::default() => {}
+            Ok(report) => debug!(
+                "Compressed {} and removed {} old session logs",
+                report.compressed, report.removed
+            ),
+            Err(e) => warn!("Failed to prune old session logs: {}", e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Write a log whose session was last active `age_days` ago
+    fn write_log(dir: &Path, name: &str, age_days: u64) {
+        let path = dir.join(name);
+        std::fs::write(&path, "{\"context_window\": {}}".repeat(100)).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_days * 24 * 60 * 60 + 1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_prune_compresses_and_removes_old_logs() {
+        let dir = TempDir::new().unwrap();
+        for (i, name) in ["a", "b", "c", "d"].iter().enumerate() {
+            write_log(dir.path(), &format!("g3_session_{}.json", name), i as u64);
+        }
+        write_log(dir.path(), "g3_session_current.json", 10);
+        write_log(dir.path(), "notes.txt", 10);
+        let config = LogsConfig {
+            keep_uncompressed: 2,
+            max_sessions: Some(4),
+            max_age_days: None,
+            max_total_mb: None,
+            ..Default::default()
+        };
+
+        let report = prune(dir.path(), &config, Some("current")).unwrap();
+        assert_eq!(report.compressed, 2);
+        assert_eq!(report.removed, 1);
+        assert!(report.freed_bytes > 0);
+        assert_eq!(
+            names(dir.path()),
+            [
+                "g3_session_a.json",
+                "g3_session_b.json.zst",
+                "g3_session_c.json.zst",
+                "g3_session_current.json",
+                "notes.txt",
+            ]
+        );
+        let archive = std::fs::read(dir.path().join("g3_session_b.json.zst")).unwrap();
+        let content = zstd::decode_all(archive.as_slice()).unwrap();
+        assert!(content.starts_with(b"{\"context_window\""));
+
+        // Archives are already compressed and keep their place among the sessions
+        let report = prune(dir.path(), &config, Some("current")).unwrap();
+        assert_eq!(report, PruneReport::default());
+    }
+
+    #[test]
+    fn test_prune_by_age_and_size() {
+        let dir = TempDir::new().unwrap();
+        write_log(dir.path(), "g3_session_new.json", 0);
+        write_log(dir.path(), "g3_context_1700000000.json", 40);
+        let config = LogsConfig {
+            keep_uncompressed: 10,
+            max_age_days: Some(30),
+            ..Default::default()
+        };
+        let report = prune(dir.path(), &config, None).unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(names(dir.path()), ["g3_session_new.json"]);
+
+        // The most recent sessions are kept even over the size limit
+        let config = LogsConfig {
+            keep_uncompressed: 1,
+            max_total_mb: Some(0),
+            ..Default::default()
+        };
+        write_log(dir.path(), "g3_session_old.json", 1);
+        let report = prune(dir.path(), &config, None).unwrap();
+        assert_eq!((report.compressed, report.removed), (1, 1));
+        assert_eq!(names(dir.path()), ["g3_session_new.json"]);
+    }
+}
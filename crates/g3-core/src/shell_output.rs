@@ -0,0 +1,161 @@
+//! Output of shell commands too long for a tool result.
+//!
+//! A command's first lines are held in memory. Once it writes more than a tool result
+//! keeps, everything it wrote goes to a `shell_output` artifact as it arrives, and the
+//! result holds only the head and tail of the output, with the line count and the path
+//! of the artifact to grep. Only the head is streamed to the UI.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::artifacts::{ArtifactKind, ArtifactStore};
+
+/// Lines kept from the start of each stream of a command's output
+pub const HEAD_LINES: usize = 50;
+/// Lines kept from the end of each stream of a command's output
+pub const TAIL_LINES: usize = 50;
+
+/// All output of a command that was saved to an artifact
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedOutput {
+    pub path: PathBuf,
+    /// Lines of stdout and stderr together
+    pub lines: usize,
+}
+
+impl CapturedOutput {
+    /// The note appended to a tool result whose output was cut
+    pub fn note(&self) -> String {
+        format!(
+            "📄 The command wrote {} lines, too many to show in full. All of it is in {} (search it with grep -n, or read parts of it with sed -n).",
+            self.lines,
+            self.path.display()
+        )
+    }
+}
+
+/// Collects a command's output, moving it to an artifact once it gets long
+pub struct OutputCapture {
+    store: ArtifactStore,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    lines: usize,
+    pending: Vec<String>,
+    file: Option<(PathBuf, BufWriter<File>)>,
+    failed: bool,
+}
+
+impl OutputCapture {
+    pub fn new(store: ArtifactStore) -> Self {
+        Self {
+            store,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Record a line of output. Returns whether it should be shown as it arrives.
+    pub fn push(&self, line: &str) -> bool {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        state.lines += 1;
+        let show = state.lines <= HEAD_LINES;
+        if state.failed {
+            return show;
+        }
+
+        let written = if let Some((_, file)) = state.file.as_mut() {
+            writeln!(file, "{}", line).map_err(anyhow::Error::from)
+        } else {
+            state.pending.push(line.to_string());
+            if state.pending.len() <= HEAD_LINES + TAIL_LINES {
+                return show;
+            }
+            // Too long for a tool result: everything so far goes to the artifact
+            self.open().and_then(|(path, mut file)| {
+                for line in state.pending.drain(..) {
+                    writeln!(file, "{}", line)?;
+                }
+                state.file = Some((path, file));
+                Ok(())
+            })
+        };
+        if let Err(e) = written {
+            warn!("Failed to save long shell output: {}", e);
+            state.failed = true;
+            state.pending.clear();
+        }
+        show
+    }
+
+    fn open(&self) -> anyhow::Result<(PathBuf, BufWriter<File>)> {
+        let path = self.store.path_for("shell_output.log")?;
+        let file = File::create(&path)?;
+        Ok((path, BufWriter::new(file)))
+    }
+
+    /// The artifact holding the command's output, if it was long enough to need one
+    pub fn finish(self) -> Option<CapturedOutput> {
+        let state = self.state.into_inner().unwrap();
+        if state.failed {
+            return None;
+        }
+        let (path, mut file) = state.file?;
+        if let Err(e) = file.flush() {
+            warn!("Failed to save long shell output: {}", e);
+            return None;
+        }
+        drop(file);
+        if let Err(e) = self
+            .store
+            .register(ArtifactKind::ShellOutput, &path, Some("shell"))
+        {
+            warn!("Failed to record shell output artifact: {}", e);
+        }
+        Some(CapturedOutput {
+            path,
+            lines: state.lines,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_capture() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = ArtifactStore::new(dir.path());
+
+        let capture = OutputCapture::new(store.clone());
+        for i in 0..HEAD_LINES + TAIL_LINES {
+            capture.push(&i.to_string());
+        }
+        // Short output stays in the tool result
+        assert!(capture.finish().is_none());
+        assert!(store.list().unwrap().is_empty());
+
+        let capture = OutputCapture::new(store.clone());
+        let shown = (0..1000)
+            .filter(|i| capture.push(&format!("line {}", i)))
+            .count();
+        assert_eq!(shown, HEAD_LINES);
+        let captured = capture.finish().unwrap();
+        assert_eq!(captured.lines, 1000);
+        let contents = std::fs::read_to_string(&captured.path).unwrap();
+        assert_eq!(contents.lines().count(), 1000);
+        assert!(contents.starts_with("line 0\nline 1\n"));
+        assert!(contents.ends_with("line 999\n"));
+        assert!(captured.note().contains("1000 lines"));
+
+        let artifacts = store.list().unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].kind, ArtifactKind::ShellOutput);
+    }
+}
@@ -0,0 +1,118 @@
+//! Output redirections of shell command lines, for the checks of read-only mode and `[scope]`
+//! that keep shell commands from writing files.
+
+/// The files a command line redirects output to, outside of quotes. Duplicating a descriptor
+/// (`2>&1`, `>&2`) or closing one (`>&-`) writes no file and is left out, but any other
+/// `>&word` writes to the file `word`, as does `&>word`.
+pub fn output_targets(command: &str) -> Vec<String> {
+    let chars: Vec<char> = command.chars().collect();
+    let mut targets = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => i += 1,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '>') => {
+                // `&>word` and `&>>word` always name a file
+                let both = i >= 2 && chars[i - 2] == '&';
+                if matches!(chars.get(i), Some('>' | '|')) {
+                    i += 1;
+                }
+                if chars.get(i) == Some(&'(') {
+                    // Process substitution, not a file
+                    continue;
+                }
+                let duplicates = !both && chars.get(i) == Some(&'&');
+                if duplicates {
+                    i += 1;
+                }
+                while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+                    i += 1;
+                }
+                let (word, end) = read_word(&chars, i);
+                i = end;
+                let descriptor =
+                    word == "-" || (!word.is_empty() && word.bytes().all(|b| b.is_ascii_digit()));
+                if word.is_empty() || (duplicates && descriptor) {
+                    continue;
+                }
+                targets.push(word);
+            }
+            (None, _) => {}
+        }
+    }
+    targets
+}
+
+/// The shell word starting at `start` with its quotes removed, and the index after it
+fn read_word(chars: &[char], start: usize) -> (String, usize) {
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+    let mut i = start;
+    while let Some(&c) = chars.get(i) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                i += 1;
+                if let Some(&escaped) = chars.get(i) {
+                    word.push(escaped);
+                }
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c)
+                if c.is_whitespace() || matches!(c, '|' | ';' | '&' | '<' | '>' | '(' | ')') =>
+            {
+                break;
+            }
+            (None, c) => word.push(c),
+        }
+        i += 1;
+    }
+    (word, i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_written_by_redirections() {
+        assert_eq!(output_targets("echo hi > notes.txt"), ["notes.txt"]);
+        assert_eq!(
+            output_targets("cat a >> 'b c' 2>err.log"),
+            ["b c", "err.log"]
+        );
+        assert_eq!(output_targets("ls >|listing"), ["listing"]);
+        assert_eq!(output_targets("echo x >&README.md"), ["README.md"]);
+        assert_eq!(output_targets("echo x >& README.md"), ["README.md"]);
+        assert_eq!(output_targets("make &>build.log"), ["build.log"]);
+        assert_eq!(output_targets("make &>>build.log"), ["build.log"]);
+    }
+
+    #[test]
+    fn test_descriptors_and_quoted_text_are_not_files() {
+        for command in [
+            "cargo test 2>&1 | tail",
+            "echo warning >&2",
+            "ls 2>&-",
+            "grep '>&file' notes.txt",
+            "echo \\> literal",
+            "diff a >(sort)",
+        ] {
+            assert!(
+                output_targets(command).is_empty(),
+                "{} writes {:?}",
+                command,
+                output_targets(command)
+            );
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Opt-in local usage statistics.
+//!
+//! When `[stats] enabled = true`, one JSON line per task is appended to a local file
+//! (`~/.g3/stats.jsonl` by default). Nothing is ever sent anywhere. `g3 stats` aggregates
+//! the file so configuration changes can be compared over weeks.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use g3_config::StatsConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+pub const DEFAULT_STATS_PATH: &str = "~/.g3/stats.jsonl";
+
+/// Per-tool counters for a task, or across tasks when aggregated
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolStats {
+    pub calls: usize,
+    pub failures: usize,
+    /// Calls made straight after a failed call to the same tool
+    pub retries: usize,
+}
+
+impl ToolStats {
+    pub fn success_rate(&self) -> f64 {
+        if self.calls == 0 {
+            return 0.0;
+        }
+        (self.calls - self.failures) as f64 / self.calls as f64
+    }
+
+    fn add(&mut self, other: &ToolStats) {
+        self.calls += other.calls;
+        self.failures += other.failures;
+        self.retries += other.retries;
+    }
+}
+
+/// One line of the stats file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub timestamp: DateTime<Utc>,
+    pub provider: String,
+    pub model: String,
+    pub success: bool,
+    pub tokens_used: u32,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub tools: BTreeMap<String, ToolStats>,
+    /// At the model's price when the task was recorded; `None` without a price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+impl TaskRecord {
+    /// Build a record from the ordered (tool, duration, success) calls made during a task
+    pub fn new(
+        provider: &str,
+        model: &str,
+        success: bool,
+        tokens_used: u32,
+        duration: Duration,
+        tool_calls: &[(String, Duration, bool)],
+    ) -> Self {
+        let mut tools: BTreeMap<String, ToolStats> = BTreeMap::new();
+        let mut last_failed: Option<&str> = None;
+        for (name, _, ok) in tool_calls {
+            let entry = tools.entry(name.clone()).or_default();
+            entry.calls += 1;
+            if !ok {
+                entry.failures += 1;
+            }
+            if last_failed == Some(name.as_str()) {
+                entry.retries += 1;
+            }
+            last_failed = (!ok).then_some(name.as_str());
+        }
+
+        Self {
+            timestamp: Utc::now(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            success,
+            tokens_used,
+            duration_ms: duration.as_millis() as u64,
+            tools,
+            cost: None,
+        }
+    }
+
+    /// Set the task's cost from its model's price per million tokens
+    pub fn with_price(mut self, price_per_million: Option<f64>) -> Self {
+        self.cost = price_per_million.map(|price| self.tokens_used as f64 * price / 1_000_000.0);
+        self
+    }
+}
+
+/// Append-only stats file
+#[derive(Debug, Clone)]
+pub struct StatsStore {
+    path: PathBuf,
+}
+
+impl StatsStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The store configured by `[stats]`, whether or not recording is enabled
+    pub fn from_config(config: &StatsConfig) -> Self {
+        let path = config.path.as_deref().unwrap_or(DEFAULT_STATS_PATH);
+        Self::new(shellexpand::tilde(path).as_ref())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, record: &TaskRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// Load all records. A missing file has no records; unreadable lines are skipped.
+    pub fn load(&self) -> Result<Vec<TaskRecord>> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+
+        Ok(contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    warn!("Skipping malformed stats line: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Tasks of one provider/model, across records
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelStats {
+    pub tasks: usize,
+    /// Tasks recorded with a cost, and what they cost in total
+    pub costed_tasks: usize,
+    pub cost: f64,
+}
+
+impl ModelStats {
+    /// Average over the tasks recorded with a cost, if any were
+    pub fn average_cost(&self) -> Option<f64> {
+        (self.costed_tasks > 0).then(|| self.cost / self.costed_tasks as f64)
+    }
+
+    fn add(&mut self, record: &TaskRecord) {
+        self.tasks += 1;
+        if let Some(cost) = record.cost {
+            self.costed_tasks += 1;
+            self.cost += cost;
+        }
+    }
+}
+
+/// Totals across a set of task records
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsSummary {
+    pub tasks: usize,
+    pub successful_tasks: usize,
+    pub total_tokens: u64,
+    pub total_duration: Duration,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    pub tools: BTreeMap<String, ToolStats>,
+    /// By provider/model, e.g. "anthropic/claude-sonnet-4"
+    pub models: BTreeMap<String, ModelStats>,
+    /// Across all models
+    pub cost: ModelStats,
+}
+
+impl StatsSummary {
+    /// Aggregate records at or after `since` (all records when `None`)
+    pub fn from_records(records: &[TaskRecord], since: Option<DateTime<Utc>>) -> Self {
+        let mut summary = Self::default();
+        for record in records
+            .iter()
+            .filter(|r| since.is_none_or(|since| r.timestamp >= since))
+        {
+            summary.tasks += 1;
+            if record.success {
+                summary.successful_tasks += 1;
+            }
+            summary.total_tokens += record.tokens_used as u64;
+            summary.total_duration += Duration::from_millis(record.duration_ms);
+            summary.first = Some(
+                summary
+                    .first
+                    .map_or(record.timestamp, |t| t.min(record.timestamp)),
+            );
+            summary.last = Some(
+                summary
+                    .last
+                    .map_or(record.timestamp, |t| t.max(record.timestamp)),
+            );
+            for (name, stats) in &record.tools {
+                summary.tools.entry(name.clone()).or_default().add(stats);
+            }
+            summary
+                .models
+                .entry(format!("{}/{}", record.provider, record.model))
+                .or_default()
+                .add(record);
+            summary.cost.add(record);
+        }
+        summary
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        if self.tasks == 0 {
+            return 0.0;
+        }
+        self.successful_tasks as f64 / self.tasks as f64
+    }
+
+    pub fn average_tokens_per_task(&self) -> f64 {
+        if self.tasks == 0 {
+            return 0.0;
+        }
+        self.total_tokens as f64 / self.tasks as f64
+    }
+
+    /// Tools sorted by retries, most retried first, omitting tools that were never retried
+    pub fn most_retried_tools(&self) -> Vec<(&str, &ToolStats)> {
+        let mut tools: Vec<_> = self
+            .tools
+            .iter()
+            .filter(|(_, stats)| stats.retries > 0)
+            .map(|(name, stats)| (name.as_str(), stats))
+            .collect();
+        tools.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.retries));
+        tools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn calls(calls: &[(&str, bool)]) -> Vec<(String, Duration, bool)> {
+        calls
+            .iter()
+            .map(|(name, ok)| (name.to_string(), Duration::from_millis(5), *ok))
+            .collect()
+    }
+
+    #[test]
+    fn test_task_record_counts_retries() {
+        let record = TaskRecord::new(
+            "anthropic",
+            "claude",
+            true,
+            1200,
+            Duration::from_secs(3),
+            &calls(&[
+                ("str_replace", false),
+                ("str_replace", false),
+                ("str_replace", true),
+                ("shell", false),
+                ("read_file", true),
+                ("shell", true),
+            ]),
+        );
+
+        let str_replace = &record.tools["str_replace"];
+        assert_eq!(
+            (str_replace.calls, str_replace.failures, str_replace.retries),
+            (3, 2, 2)
+        );
+        // The second shell call did not directly follow the failure
+        assert_eq!(record.tools["shell"].retries, 0);
+        assert_eq!(record.tools["read_file"].failures, 0);
+    }
+
+    #[test]
+    fn test_store_round_trip_and_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = StatsStore::new(temp_dir.path().join("nested").join("stats.jsonl"));
+        assert!(store.load().unwrap().is_empty());
+
+        let mut old = TaskRecord::new("openai", "gpt", false, 500, Duration::from_secs(1), &[])
+            .with_price(Some(2.0));
+        old.timestamp = Utc::now() - chrono::Duration::days(30);
+        store.record(&old).unwrap();
+        store
+            .record(&TaskRecord::new(
+                "anthropic",
+                "claude",
+                true,
+                1500,
+                Duration::from_secs(2),
+                &calls(&[("shell", false), ("shell", true)]),
+            ))
+            .unwrap();
+        store
+            .record(
+                &TaskRecord::new("openai", "gpt", true, 1000, Duration::from_secs(1), &[])
+                    .with_price(Some(4.0)),
+            )
+            .unwrap();
+
+        let records = store.load().unwrap();
+        assert_eq!(records.len(), 3);
+
+        let all_time = StatsSummary::from_records(&records, None);
+        assert_eq!(all_time.tasks, 3);
+        assert_eq!(all_time.successful_tasks, 2);
+        assert_eq!(all_time.average_tokens_per_task(), 1000.0);
+        assert_eq!(all_time.models.len(), 2);
+        assert_eq!(all_time.most_retried_tools()[0].0, "shell");
+        // Each task keeps the price it was recorded with; the unpriced one has no cost
+        let gpt = &all_time.models["openai/gpt"];
+        assert_eq!((gpt.tasks, gpt.costed_tasks), (2, 2));
+        assert_eq!(gpt.average_cost(), Some(0.0025));
+        assert_eq!(all_time.models["anthropic/claude"].average_cost(), None);
+        assert_eq!(all_time.cost.costed_tasks, 2);
+
+        let recent =
+            StatsSummary::from_records(&records, Some(Utc::now() - chrono::Duration::days(7)));
+        assert_eq!(recent.tasks, 2);
+        assert_eq!(recent.tools["shell"].success_rate(), 0.5);
+    }
+}
@@ -0,0 +1,33 @@
+//! Stepping through tool calls (`--step`), a debugger for agent behavior.
+//!
+//! With a [`ToolStepper`] set on the agent, every tool call waits for it before running. The
+//! stepper sees the call as the model made it, before moderation and the other checks, and
+//! can let it run, run it with other arguments, skip it, or abort the turn. A skipped call
+//! gets [`SKIPPED_RESULT`] as its result, so the model knows it didn't run.
+
+use serde_json::Value;
+
+use crate::ToolCall;
+
+/// The result the model gets for a call that was skipped
+pub const SKIPPED_RESULT: &str =
+    "⏭️ The user skipped this tool call, so it did not run. Continue without its result, \
+     or try something else.";
+
+/// What to do with a tool call
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepDecision {
+    Run,
+    /// Run it with these arguments instead
+    Edit(Value),
+    /// Don't run it, and tell the model so
+    Skip,
+    /// Stop the turn
+    Abort,
+}
+
+/// Decides about each tool call before it runs. The agent calls it on a blocking thread, so
+/// it may wait for the user.
+pub trait ToolStepper: Send + Sync {
+    fn before_tool_call(&self, tool_call: &ToolCall) -> StepDecision;
+}
@@ -0,0 +1,188 @@
+//! Live speed of a streaming response, for the indicator shown while it streams.
+//!
+//! Providers only report token usage once a response ends, so tokens are estimated from the
+//! length of the text, reasoning and tool call arguments of each chunk. The speed is taken
+//! over the last few seconds and falls to zero when the provider stops sending, so a slow
+//! provider is easy to tell apart from a hung stream before the stall watchdog gives up.
+
+use g3_providers::CompletionChunk;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How often the indicator is updated, also while no data arrives
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Span the speed is measured over
+const WINDOW: Duration = Duration::from_secs(3);
+
+const CHARS_PER_TOKEN: f32 = 4.0;
+
+/// Quiet for this long, the indicator says when data last arrived instead of the speed
+const QUIET: Duration = Duration::from_secs(5);
+
+/// Where a streaming response has got to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamProgress {
+    /// Since the request was sent
+    pub elapsed: Duration,
+    /// Estimated tokens received so far
+    pub tokens: u32,
+    /// Estimated tokens per second over the last few seconds
+    pub tokens_per_second: f32,
+    /// Since data last arrived from the provider
+    pub idle: Duration,
+}
+
+/// `⏳ 14s · 52 tok/s · ~730 tokens`, or how long the provider has been quiet
+impl std::fmt::Display for StreamProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "⏳ {}", whole_seconds(self.elapsed))?;
+        if self.tokens == 0 {
+            return write!(f, " · waiting for the first token");
+        }
+        if self.idle >= QUIET {
+            write!(
+                f,
+                " · ~{} tokens · no data for {}",
+                self.tokens,
+                whole_seconds(self.idle)
+            )
+        } else {
+            write!(
+                f,
+                " · {:.0} tok/s · ~{} tokens",
+                self.tokens_per_second, self.tokens
+            )
+        }
+    }
+}
+
+fn whole_seconds(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+/// Chunk timing of one streaming response
+#[derive(Debug, Clone)]
+pub struct StreamRate {
+    start: Instant,
+    last_data: Instant,
+    last_report: Option<Instant>,
+    chars: usize,
+    /// Characters received within the window, with when they arrived
+    recent: VecDeque<(Instant, usize)>,
+}
+
+impl StreamRate {
+    /// Timing of a response requested at `start`
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            last_data: start,
+            last_report: None,
+            chars: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Count a chunk that arrived at `now`. Chunks without text, such as keep-alive pings,
+    /// still show the stream is alive.
+    pub fn record(&mut self, now: Instant, chunk: &CompletionChunk) {
+        let chars = chunk.content.chars().count()
+            + chunk.reasoning.as_deref().map_or(0, |r| r.chars().count())
+            + chunk.tool_call_deltas.as_deref().map_or(0, |deltas| {
+                deltas.iter().map(|d| d.arguments.chars().count()).sum()
+            });
+        self.record_chars(now, chars);
+    }
+
+    fn record_chars(&mut self, now: Instant, chars: usize) {
+        self.last_data = now;
+        if chars > 0 {
+            self.chars += chars;
+            self.recent.push_back((now, chars));
+        }
+    }
+
+    /// Time since data last arrived, or since the request was sent
+    pub fn idle(&self, now: Instant) -> Duration {
+        now.duration_since(self.last_data)
+    }
+
+    /// The progress as of `now`, once every [`PROGRESS_INTERVAL`]
+    pub fn report(&mut self, now: Instant) -> Option<StreamProgress> {
+        if self
+            .last_report
+            .is_some_and(|last| now.duration_since(last) < PROGRESS_INTERVAL)
+        {
+            return None;
+        }
+        self.last_report = Some(now);
+        while self
+            .recent
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > WINDOW)
+        {
+            self.recent.pop_front();
+        }
+
+        // Over the window, or since the request if it is younger than that
+        let span = WINDOW.min(now.duration_since(self.start)).as_secs_f32();
+        let recent: usize = self.recent.iter().map(|(_, chars)| chars).sum();
+        let tokens_per_second = if span > 0.0 {
+            recent as f32 / CHARS_PER_TOKEN / span
+        } else {
+            0.0
+        };
+        Some(StreamProgress {
+            elapsed: now.duration_since(self.start),
+            tokens: (self.chars as f32 / CHARS_PER_TOKEN).round() as u32,
+            tokens_per_second,
+            idle: self.idle(now),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_rate() {
+        let start = Instant::now();
+        let mut rate = StreamRate::new(start);
+        let at = |millis: u64| start + Duration::from_millis(millis);
+
+        // 400 characters a second for four seconds
+        for tick in 1..=40 {
+            rate.record_chars(at(tick * 100), 40);
+        }
+        let progress = rate.report(at(4000)).unwrap();
+        assert_eq!(progress.tokens, 400);
+        assert_eq!(progress.elapsed, Duration::from_secs(4));
+        assert!((progress.tokens_per_second - 100.0).abs() < 5.0);
+        assert_eq!(progress.idle, Duration::ZERO);
+        assert!(progress.to_string().starts_with("⏳ 4s · 10"));
+        assert!(progress.to_string().ends_with(" tok/s · ~400 tokens"));
+
+        // Reports are spaced out
+        assert_eq!(rate.report(at(4200)), None);
+
+        // Once the provider goes quiet the speed drops to zero
+        let progress = rate.report(at(9000)).unwrap();
+        assert_eq!(progress.tokens_per_second, 0.0);
+        assert_eq!(progress.idle, Duration::from_secs(5));
+        assert_eq!(progress.tokens, 400);
+        assert_eq!(progress.to_string(), "⏳ 9s · ~400 tokens · no data for 5s");
+
+        let waiting = StreamRate::new(start).report(at(75_000)).unwrap();
+        assert_eq!(
+            waiting.to_string(),
+            "⏳ 1m 15s · waiting for the first token"
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Recordings of provider streams (`record_streams`), for debugging stream parsing.
+//!
+//! With `record_streams` enabled, every chunk a provider sends for a response is kept with
+//! the time it arrived, and when the response ends the recording is saved as a gzipped JSON
+//! lines artifact. `g3 replay-stream <artifact>` feeds the chunks through
+//! [`StreamingToolParser`] again, so a parsing bug can be reproduced offline and the
+//! recording attached to a provider bug report. Chunks are recorded as the provider decoded
+//! them from its SSE events, which is exactly what the parser sees.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use g3_providers::CompletionChunk;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tracing::{debug, warn};
+
+use crate::artifacts::{ArtifactKind, ArtifactStore};
+use crate::partial_tool_call::PartialToolCall;
+use crate::{StreamingToolParser, ToolCall};
+
+const FORMAT: u32 = 1;
+
+/// Text shown for each chunk is cut to this many characters
+const MAX_SUMMARY_TEXT: usize = 60;
+
+/// First line of a recording
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    /// Format version
+    g3_stream: u32,
+    provider: String,
+    model: String,
+    started_at: DateTime<Utc>,
+}
+
+/// A chunk of a recorded stream, or the error that ended it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedChunk {
+    /// Milliseconds since the stream opened
+    pub at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<CompletionChunk>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The chunks of one provider response
+#[derive(Debug, Clone)]
+pub struct StreamRecording {
+    pub provider: String,
+    pub model: String,
+    pub started_at: DateTime<Utc>,
+    pub chunks: Vec<RecordedChunk>,
+}
+
+impl StreamRecording {
+    /// Read a recording saved by [`StreamRecorder`]
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut contents = String::new();
+        GzDecoder::new(file)
+            .read_to_string(&mut contents)
+            .with_context(|| format!("{} is not a gzipped stream recording", path.display()))?;
+        Self::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+        let header: Header = serde_json::from_str(lines.next().unwrap_or_default())
+            .context("Not a g3 stream recording")?;
+        if header.g3_stream != FORMAT {
+            bail!(
+                "Stream recording format {} isn't supported by this version of g3 (expected {})",
+                header.g3_stream,
+                FORMAT
+            );
+        }
+        let chunks = lines
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("Bad chunk on line {} of the recording", i + 2))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            provider: header.provider,
+            model: header.model,
+            started_at: header.started_at,
+            chunks,
+        })
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let header = Header {
+            g3_stream: FORMAT,
+            provider: self.provider.clone(),
+            model: self.model.clone(),
+            started_at: self.started_at,
+        };
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        writeln!(encoder, "{}", serde_json::to_string(&header)?)?;
+        for chunk in &self.chunks {
+            writeln!(encoder, "{}", serde_json::to_string(chunk)?)?;
+        }
+        Ok(encoder.finish()?)
+    }
+}
+
+/// Records the chunks of one response, and saves them to the artifact store when dropped
+pub struct StreamRecorder {
+    recording: StreamRecording,
+    opened: Instant,
+    store: ArtifactStore,
+}
+
+impl StreamRecorder {
+    pub fn new(provider: &str, model: &str, store: ArtifactStore) -> Self {
+        Self {
+            recording: StreamRecording {
+                provider: provider.to_string(),
+                model: model.to_string(),
+                started_at: Utc::now(),
+                chunks: Vec::new(),
+            },
+            opened: Instant::now(),
+            store,
+        }
+    }
+
+    pub fn record(&mut self, chunk: &Result<CompletionChunk>) {
+        let (chunk, error) = match chunk {
+            Ok(chunk) => (Some(chunk.clone()), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.recording.chunks.push(RecordedChunk {
+            at_ms: self.opened.elapsed().as_millis() as u64,
+            chunk,
+            error,
+        });
+    }
+
+    /// Save the recording, unless nothing was received. Returns the artifact's path.
+    pub fn save(&self) -> Result<Option<PathBuf>> {
+        if self.recording.chunks.is_empty() {
+            return Ok(None);
+        }
+        let path = self.store.save(
+            ArtifactKind::StreamRecording,
+            "stream.jsonl.gz",
+            &self.recording.to_bytes()?,
+            Some(&self.recording.provider),
+        )?;
+        Ok(Some(path))
+    }
+}
+
+impl Drop for StreamRecorder {
+    fn drop(&mut self) {
+        match self.save() {
+            Ok(Some(path)) => debug!("Recorded provider stream to {}", path.display()),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to save the provider stream recording: {}", e),
+        }
+    }
+}
+
+/// What the parser made of one recorded chunk
+#[derive(Debug, Clone)]
+pub struct ReplayStep {
+    pub index: usize,
+    pub at_ms: u64,
+    /// What the chunk carried
+    pub summary: String,
+    /// Tool calls the parser completed on this chunk
+    pub tool_calls: Vec<ToolCall>,
+    pub error: Option<String>,
+}
+
+/// The result of feeding a recording through [`StreamingToolParser`]
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub steps: Vec<ReplayStep>,
+    /// The text the parser accumulated
+    pub text: String,
+    /// Tool calls still incomplete when the recording ended
+    pub partial_tool_calls: Vec<PartialToolCall>,
+    pub message_stopped: bool,
+}
+
+/// Feed the chunks of `recording` through a fresh parser, as the agent did
+pub fn replay(recording: &StreamRecording) -> Replay {
+    let mut parser = StreamingToolParser::new();
+    let steps = recording
+        .chunks
+        .iter()
+        .enumerate()
+        .map(|(index, recorded)| {
+            let (summary, tool_calls) = match &recorded.chunk {
+                Some(chunk) => (describe(chunk), parser.process_chunk(chunk)),
+                None => ("error".to_string(), Vec::new()),
+            };
+            ReplayStep {
+                index,
+                at_ms: recorded.at_ms,
+                summary,
+                tool_calls,
+                error: recorded.error.clone(),
+            }
+        })
+        .collect();
+    Replay {
+        steps,
+        text: parser.get_text_content().to_string(),
+        partial_tool_calls: parser.partial_tool_calls().to_vec(),
+        message_stopped: parser.is_message_stopped(),
+    }
+}
+
+/// One line describing what a chunk carries
+fn describe(chunk: &CompletionChunk) -> String {
+    let mut parts = Vec::new();
+    if !chunk.content.is_empty() {
+        parts.push(format!("text {}", quote(&chunk.content)));
+    }
+    if let Some(reasoning) = &chunk.reasoning {
+        parts.push(format!("reasoning {}", quote(reasoning)));
+    }
+    for delta in chunk.tool_call_deltas.iter().flatten() {
+        let tool = delta
+            .tool
+            .as_deref()
+            .map(|tool| format!(" {}", tool))
+            .unwrap_or_default();
+        parts.push(format!(
+            "delta #{}{} {}",
+            delta.index,
+            tool,
+            quote(&delta.arguments)
+        ));
+    }
+    for call in chunk.tool_calls.iter().flatten() {
+        parts.push(format!("tool call {} ({})", call.tool, call.id));
+    }
+    if let Some(usage) = &chunk.usage {
+        parts.push(format!(
+            "usage {}+{} tokens",
+            usage.prompt_tokens, usage.completion_tokens
+        ));
+    }
+    if chunk.truncated {
+        parts.push("truncated".to_string());
+    }
+    if chunk.finished {
+        parts.push("finished".to_string());
+    }
+    if parts.is_empty() {
+        "empty".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// `text` as an escaped string literal, cut to [`MAX_SUMMARY_TEXT`] characters
+fn quote(text: &str) -> String {
+    match text.char_indices().nth(MAX_SUMMARY_TEXT) {
+        Some((end, _)) => format!("{:?}… ({} chars)", &text[..end], text.chars().count()),
+        None => format!("{:?}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_providers::ToolCallDelta;
+    use tempfile::TempDir;
+
+    fn chunk(content: &str) -> CompletionChunk {
+        CompletionChunk {
+            content: content.to_string(),
+            finished: false,
+            tool_calls: None,
+            usage: None,
+            tool_call_deltas: None,
+            reasoning: None,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = TempDir::new().unwrap();
+        let store = ArtifactStore::new(dir.path());
+        let mut recorder = StreamRecorder::new("anthropic", "claude-sonnet-4-5", store.clone());
+        recorder.record(&Ok(chunk("Let me look. ")));
+        recorder.record(&Ok(CompletionChunk {
+            tool_call_deltas: Some(vec![ToolCallDelta {
+                index: 0,
+                id: Some("toolu_1".to_string()),
+                tool: Some("read_file".to_string()),
+                arguments: "{\"file_path\": \"src/".to_string(),
+            }]),
+            ..chunk("")
+        }));
+        recorder.record(&Ok(CompletionChunk {
+            tool_calls: Some(vec![g3_providers::ToolCall {
+                id: "toolu_1".to_string(),
+                tool: "read_file".to_string(),
+                args: serde_json::json!({"file_path": "src/lib.rs"}),
+            }]),
+            finished: true,
+            ..chunk("")
+        }));
+        recorder.record(&Err(anyhow::anyhow!("connection reset")));
+        drop(recorder);
+
+        let artifacts = store.list().unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].kind, ArtifactKind::StreamRecording);
+        assert_eq!(artifacts[0].source.as_deref(), Some("anthropic"));
+
+        let recording = StreamRecording::load(&dir.path().join(&artifacts[0].name)).unwrap();
+        assert_eq!(recording.model, "claude-sonnet-4-5");
+        assert_eq!(recording.chunks.len(), 4);
+
+        let replay = replay(&recording);
+        assert_eq!(replay.text, "Let me look. ");
+        assert_eq!(replay.steps[0].summary, "text \"Let me look. \"");
+        assert_eq!(
+            replay.steps[1].summary,
+            "delta #0 read_file \"{\\\"file_path\\\": \\\"src/\""
+        );
+        assert_eq!(replay.steps[2].tool_calls.len(), 1);
+        assert_eq!(replay.steps[2].tool_calls[0].tool, "read_file");
+        assert_eq!(replay.steps[3].error.as_deref(), Some("connection reset"));
+
+        // Nothing is saved for a stream that never sent anything
+        drop(StreamRecorder::new("anthropic", "claude", store.clone()));
+        assert_eq!(store.list().unwrap().len(), 1);
+
+        std::fs::write(dir.path().join("plain.txt"), "hello").unwrap();
+        assert!(StreamRecording::load(&dir.path().join("plain.txt")).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+//! Syntax checks of source files right after a file tool changes them (`[syntax_check]`).
+//!
+//! The file is parsed with the tree-sitter grammar code_search uses. Syntax errors are only
+//! reported when the file parsed cleanly before the edit, so errors it already had and a
+//! grammar's blind spots don't send the model chasing them. With `compiler`, Rust files also
+//! get `cargo check` in their crate and Python files are compiled (without writing bytecode);
+//! these report every error they find, wherever it is.
+
+use crate::code_search::outline::language_for_path;
+use g3_config::SyntaxCheckConfig;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::debug;
+use tree_sitter::{Node, Parser};
+
+/// Errors listed in a tool result; the rest are only counted
+const MAX_ERRORS: usize = 5;
+
+/// Line text shown with an error is cut to this many characters
+const MAX_LINE_WIDTH: usize = 80;
+
+/// Compiles the file named by the first argument, like `py_compile` but without writing a .pyc
+const PYTHON_COMPILE: &str =
+    "import sys; compile(open(sys.argv[1], 'rb').read(), sys.argv[1], 'exec')";
+
+/// A source file about to be changed by a file tool
+#[derive(Debug, Clone)]
+pub struct SyntaxCheck {
+    path: PathBuf,
+    clean_before: bool,
+}
+
+impl SyntaxCheck {
+    /// Look at `path` before it is changed. `None` for files without a grammar.
+    pub fn before_edit(path: &Path) -> Option<Self> {
+        language_for_path(path)?;
+        let clean_before = match std::fs::read_to_string(path) {
+            Ok(source) => syntax_errors(path, &source).is_empty(),
+            // A new file has nothing to compare with
+            Err(_) => true,
+        };
+        Some(Self {
+            path: path.to_path_buf(),
+            clean_before,
+        })
+    }
+
+    /// Check the file as the edit left it. What to add to the tool result, if anything.
+    pub async fn after_edit(self, config: &SyntaxCheckConfig) -> Option<String> {
+        let source = std::fs::read_to_string(&self.path).ok()?;
+        let mut report = Vec::new();
+        let errors = syntax_errors(&self.path, &source);
+        if self.clean_before && !errors.is_empty() {
+            report.push(format!(
+                "⚠️ The edit left syntax errors in {}:\n{}",
+                self.path.display(),
+                list(&errors)
+            ));
+        }
+        if config.compiler {
+            let timeout = Duration::from_secs(config.timeout_seconds);
+            if let Some((check, errors)) = compiler_check(&self.path, timeout).await {
+                report.push(format!("⚠️ {} found errors:\n{}", check, list(&errors)));
+            }
+        }
+        (!report.is_empty()).then(|| report.join("\n"))
+    }
+}
+
+/// The syntax errors tree-sitter finds in `source`, one per line, with their line numbers
+pub fn syntax_errors(path: &Path, source: &str) -> Vec<String> {
+    let Some((_, language)) = language_for_path(path) else {
+        return Vec::new();
+    };
+    let mut parser = Parser::new();
+    if parser.set_language(&language).is_err() {
+        return Vec::new();
+    }
+    let Some(tree) = parser.parse(source, None) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = source.lines().collect();
+    let mut errors: Vec<(usize, String)> = Vec::new();
+    collect_errors(tree.root_node(), &lines, &mut errors);
+    errors.dedup_by_key(|(line, _)| *line);
+    errors
+        .into_iter()
+        .map(|(line, error)| format!("line {}: {}", line, error))
+        .collect()
+}
+
+fn collect_errors(node: Node, lines: &[&str], errors: &mut Vec<(usize, String)>) {
+    if node.is_missing() {
+        let line = node.start_position().row + 1;
+        errors.push((line, format!("missing `{}`", node.kind())));
+        return;
+    }
+    if node.is_error() {
+        let line = node.start_position().row + 1;
+        let text = lines.get(line - 1).map(|text| text.trim()).unwrap_or("");
+        let text: String = text.chars().take(MAX_LINE_WIDTH).collect();
+        errors.push((line, format!("unexpected code in `{}`", text)));
+        return;
+    }
+    if !node.has_error() {
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_errors(child, lines, errors);
+    }
+}
+
+/// `errors` as an indented list, cut to [`MAX_ERRORS`]
+fn list(errors: &[String]) -> String {
+    let mut list: Vec<String> = errors
+        .iter()
+        .take(MAX_ERRORS)
+        .map(|error| format!("  {}", error))
+        .collect();
+    if errors.len() > MAX_ERRORS {
+        list.push(format!("  ... and {} more", errors.len() - MAX_ERRORS));
+    }
+    list.join("\n")
+}
+
+/// Run the compiler's check of `path`, returning its name and the errors it found. `None`
+/// when it passed, couldn't run or took longer than `timeout`.
+async fn compiler_check(path: &Path, timeout: Duration) -> Option<(&'static str, Vec<String>)> {
+    let (name, mut command) = match path.extension()?.to_str()? {
+        "rs" => {
+            let crate_dir = path
+                .ancestors()
+                .skip(1)
+                .find(|dir| dir.join("Cargo.toml").is_file())?;
+            let mut command = Command::new("cargo");
+            command
+                .args(["check", "--quiet", "--message-format", "short"])
+                .current_dir(crate_dir);
+            ("cargo check", command)
+        }
+        "py" => {
+            let mut command = Command::new("python3");
+            command.args(["-c", PYTHON_COMPILE]).arg(path);
+            ("Compiling the file", command)
+        }
+        _ => return None,
+    };
+    command.stdin(Stdio::null()).kill_on_drop(true);
+    let output = match tokio::time::timeout(timeout, command.output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            debug!("Failed to run {}: {}", name, e);
+            return None;
+        }
+        Err(_) => {
+            debug!("{} took longer than {:?}, skipped", name, timeout);
+            return None;
+        }
+    };
+    if output.status.success() {
+        return None;
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Some((name, compiler_errors(&stderr)))
+}
+
+/// The error lines of a failed check's output
+fn compiler_errors(stderr: &str) -> Vec<String> {
+    let errors: Vec<String> = stderr
+        .lines()
+        .filter(|line| line.contains(": error") || line.starts_with("error"))
+        .filter(|line| !line.starts_with("error: could not compile"))
+        .map(str::to_string)
+        .collect();
+    if !errors.is_empty() {
+        return errors;
+    }
+    // Python's compile errors are a traceback whose last frame is the line with the error
+    let lines: Vec<&str> = stderr.lines().collect();
+    let last_frame = lines
+        .iter()
+        .rposition(|line| line.trim_start().starts_with("File "))
+        .unwrap_or(0);
+    lines[last_frame..]
+        .iter()
+        .map(|line| line.trim_end().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_syntax_errors() {
+        let path = Path::new("lib.rs");
+        assert!(syntax_errors(path, "fn main() {\n    let x = 1;\n}\n").is_empty());
+        let errors = syntax_errors(path, "fn main() {\n    let x = 1\n}\n");
+        assert_eq!(errors, ["line 2: missing `;`"]);
+        let errors = syntax_errors(Path::new("app.py"), "def f(:\n    return 1\n");
+        assert!(errors[0].starts_with("line 1: "));
+        assert!(syntax_errors(Path::new("notes.txt"), "{{{").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_only_errors_the_edit_made_are_reported() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("lib.rs");
+        let config = SyntaxCheckConfig {
+            enabled: true,
+            ..Default::default()
+        };
+
+        std::fs::write(&path, "fn a() {}\n").unwrap();
+        let check = SyntaxCheck::before_edit(&path).unwrap();
+        std::fs::write(&path, "fn a() {\n").unwrap();
+        let report = check.after_edit(&config).await.unwrap();
+        assert!(report.contains("The edit left syntax errors in"));
+        assert!(report.ends_with("  line 1: unexpected code in `fn a() {`"));
+
+        // The file was already broken, so this edit isn't blamed
+        let check = SyntaxCheck::before_edit(&path).unwrap();
+        std::fs::write(&path, "fn a() {\nfn b() {\n").unwrap();
+        assert_eq!(check.after_edit(&config).await, None);
+
+        assert!(SyntaxCheck::before_edit(&dir.path().join("notes.md")).is_none());
+    }
+
+    #[test]
+    fn test_compiler_errors() {
+        let cargo = "src/lib.rs:2:13: error: expected `;`, found `}`\n\
+                     src/lib.rs:1:1: warning: unused import\n\
+                     error: could not compile `demo` (lib) due to 1 previous error\n";
+        assert_eq!(
+            compiler_errors(cargo),
+            ["src/lib.rs:2:13: error: expected `;`, found `}`"]
+        );
+        let python = "Traceback (most recent call last):\n  File \"<string>\", line 1, in <module>\n  \
+                      File \"app.py\", line 1\n    def f(:\n          ^\nSyntaxError: invalid syntax\n";
+        let errors = compiler_errors(python);
+        assert_eq!(errors[0], "  File \"app.py\", line 1");
+        assert_eq!(errors.last().unwrap(), "SyntaxError: invalid syntax");
+    }
+}
@@ -1,4 +1,40 @@
-use crate::ContextWindow;
+use crate::{file_changes::FileChangeSet, CoachFeedback, ContextWindow};
+use std::time::Duration;
+
+/// Token, time and tool accounting for a single turn (one task execution)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TurnMetrics {
+    /// 1-indexed position of the turn within the agent's session
+    pub turn_number: usize,
+    /// Tokens consumed by the turn (prompt and completion)
+    pub tokens_used: u32,
+    pub wall_clock_time: Duration,
+    pub tool_calls: usize,
+    pub failed_tool_calls: usize,
+    /// Tokens the model generated, as reported by the provider or estimated if it doesn't
+    pub output_tokens: u32,
+    /// Of `output_tokens`, those a reasoning model spent thinking
+    pub reasoning_tokens: u32,
+    /// Time from sending the turn's first request to the first streamed token
+    pub time_to_first_token: Option<Duration>,
+    /// Of `time_to_first_token`, the time to serialize and send the request until the
+    /// provider responded; the rest is the model's latency
+    pub request_time: Option<Duration>,
+}
+
+impl TurnMetrics {
+    /// Fold another turn's usage into this one, keeping this turn's number
+    pub fn add(&mut self, other: &TurnMetrics) {
+        self.tokens_used = self.tokens_used.saturating_add(other.tokens_used);
+        self.wall_clock_time += other.wall_clock_time;
+        self.tool_calls += other.tool_calls;
+        self.failed_tool_calls += other.failed_tool_calls;
+        self.output_tokens = self.output_tokens.saturating_add(other.output_tokens);
+        self.reasoning_tokens = self.reasoning_tokens.saturating_add(other.reasoning_tokens);
+        self.time_to_first_token = self.time_to_first_token.or(other.time_to_first_token);
+        self.request_time = self.request_time.or(other.request_time);
+    }
+}
 
 /// Result of a task execution containing both the response and the context window
 #[derive(Debug, Clone)]
@@ -7,6 +43,12 @@ pub struct TaskResult {
     pub response: String,
     /// The complete context window at the time of completion
     pub context_window: ContextWindow,
+    /// Usage accounting for the turn that produced this result
+    pub metrics: TurnMetrics,
+    /// Structured review from a coach agent's give_feedback call, if it made one
+    pub feedback: Option<CoachFeedback>,
+    /// Files the task created, modified or deleted
+    pub file_changes: FileChangeSet,
 }
 
 impl TaskResult {
@@ -14,9 +56,27 @@ impl TaskResult {
         Self {
             response,
             context_window,
+            metrics: TurnMetrics::default(),
+            feedback: None,
+            file_changes: FileChangeSet::default(),
         }
     }
 
+    pub fn with_metrics(mut self, metrics: TurnMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_feedback(mut self, feedback: Option<CoachFeedback>) -> Self {
+        self.feedback = feedback;
+        self
+    }
+
+    pub fn with_file_changes(mut self, file_changes: FileChangeSet) -> Self {
+        self.file_changes = file_changes;
+        self
+    }
+
     /// Extract the final_output content from the response (for coach feedback in autonomous mode)
     /// This looks for the complete final_output content, not just the last block
     pub fn extract_final_output(&self) -> String {
@@ -79,7 +139,10 @@ impl TaskResult {
 
     /// Check if the response contains an approval (for autonomous mode)
     pub fn is_approved(&self) -> bool {
-        self.extract_final_output().contains("IMPLEMENTATION_APPROVED")
+        match &self.feedback {
+            Some(feedback) => feedback.is_approved(),
+            None => self.extract_final_output().contains("IMPLEMENTATION_APPROVED"),
+        }
     }
 }
 
@@ -165,4 +228,53 @@ mod tests {
         let result = TaskResult::new(empty_response, context_window);
         assert_eq!(result.extract_final_output(), "");
     }
+
+    #[test]
+    fn test_turn_metrics_add() {
+        let mut total = TurnMetrics {
+            turn_number: 2,
+            tokens_used: 100,
+            wall_clock_time: Duration::from_millis(1500),
+            tool_calls: 3,
+            failed_tool_calls: 1,
+            ..Default::default()
+        };
+        total.add(&TurnMetrics {
+            turn_number: 7,
+            tokens_used: 50,
+            wall_clock_time: Duration::from_millis(500),
+            tool_calls: 2,
+            failed_tool_calls: 0,
+            output_tokens: 40,
+            reasoning_tokens: 25,
+            time_to_first_token: Some(Duration::from_millis(300)),
+            request_time: Some(Duration::from_millis(120)),
+        });
+
+        assert_eq!(total.turn_number, 2);
+        assert_eq!(total.tokens_used, 150);
+        assert_eq!(total.wall_clock_time, Duration::from_secs(2));
+        assert_eq!(total.tool_calls, 5);
+        assert_eq!(total.failed_tool_calls, 1);
+        assert_eq!(total.output_tokens, 40);
+        assert_eq!(total.reasoning_tokens, 25);
+        assert_eq!(total.time_to_first_token, Some(Duration::from_millis(300)));
+        assert_eq!(total.request_time, Some(Duration::from_millis(120)));
+    }
+
+    #[test]
+    fn test_feedback_verdict_decides_approval() {
+        let context_window = ContextWindow::new(1000);
+        let feedback = CoachFeedback::from_args(&serde_json::json!({
+            "verdict": "changes_requested",
+            "issues": ["Tests fail, so IMPLEMENTATION_APPROVED can't be given yet"]
+        }))
+        .unwrap();
+        assert!(feedback.to_string().contains("IMPLEMENTATION_APPROVED"));
+
+        // Structured feedback wins over any approval text in the response or the feedback
+        let result = TaskResult::new("IMPLEMENTATION_APPROVED".to_string(), context_window)
+            .with_feedback(Some(feedback));
+        assert!(!result.is_approved());
+    }
 }
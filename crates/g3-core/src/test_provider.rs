@@ -0,0 +1,254 @@
+//! A provider that answers with scripted responses, for tests that run whole turns of an
+//! [`Agent`].
+
+use crate::artifacts::ArtifactStore;
+use crate::events::{AgentEvent, ChannelSubscriber};
+use crate::ui_writer::NullUiWriter;
+use crate::Agent;
+use anyhow::Result;
+use g3_config::Config;
+use g3_providers::{
+    Capabilities, CompletionChunk, CompletionRequest, CompletionResponse, CompletionStream,
+    LLMProvider, ProviderError, ProviderRegistry, ToolCall, ToolCallDelta, Usage,
+};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// One scripted response
+#[derive(Debug, Clone)]
+pub struct Response {
+    chunks: Vec<CompletionChunk>,
+    /// Time before each chunk
+    delay: Duration,
+    /// The stream stops sending before its final chunk, without ending
+    stall: bool,
+    /// The stream ends with this error instead of its final chunk
+    error: Option<ProviderError>,
+}
+
+impl Response {
+    /// A response of text arriving in `pieces`
+    pub fn text(pieces: &[&str]) -> Self {
+        let mut chunks: Vec<CompletionChunk> = pieces.iter().map(|text| chunk(text)).collect();
+        chunks.push(CompletionChunk {
+            finished: true,
+            ..chunk("")
+        });
+        Self {
+            chunks,
+            delay: Duration::ZERO,
+            stall: false,
+            error: None,
+        }
+    }
+
+    /// A response of text arriving in `pieces` that the content filter then stops
+    pub fn content_filtered(pieces: &[&str]) -> Self {
+        let mut response = Self::text(pieces);
+        response.chunks.retain(|chunk| !chunk.finished);
+        response.error = Some(ProviderError::content_filtered(
+            "Scripted",
+            "the content filter blocked it",
+        ));
+        response
+    }
+
+    /// A response calling `tool`
+    pub fn tool_call(tool: &str, args: Value) -> Self {
+        let call = ToolCall {
+            id: format!("call_{}", tool),
+            tool: tool.to_string(),
+            args,
+        };
+        Self {
+            chunks: vec![CompletionChunk {
+                tool_calls: Some(vec![call]),
+                finished: true,
+                ..chunk("")
+            }],
+            delay: Duration::ZERO,
+            stall: false,
+            error: None,
+        }
+    }
+
+    /// A response streaming the JSON `arguments` of a call of `tool` in `pieces` deltas,
+    /// which then ends with `text` without making the call, so its partial state can be watched
+    pub fn streaming_tool_call(tool: &str, arguments: &str, pieces: usize, text: &str) -> Self {
+        let size = arguments.len().div_ceil(pieces);
+        let mut chunks: Vec<CompletionChunk> = arguments
+            .as_bytes()
+            .chunks(size)
+            .enumerate()
+            .map(|(i, piece)| CompletionChunk {
+                tool_call_deltas: Some(vec![ToolCallDelta {
+                    index: 0,
+                    id: (i == 0).then(|| format!("call_{}", tool)),
+                    tool: (i == 0).then(|| tool.to_string()),
+                    arguments: String::from_utf8_lossy(piece).into_owned(),
+                }]),
+                ..chunk("")
+            })
+            .collect();
+        chunks.push(CompletionChunk {
+            finished: true,
+            ..chunk(text)
+        });
+        Self {
+            chunks,
+            delay: Duration::ZERO,
+            stall: false,
+            error: None,
+        }
+    }
+
+    /// Wait `delay` before each chunk
+    pub fn every(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Stop sending before the final chunk and never end the stream
+    pub fn stalling(mut self) -> Self {
+        self.chunks.retain(|chunk| !chunk.finished);
+        self.stall = true;
+        self
+    }
+}
+
+fn chunk(text: &str) -> CompletionChunk {
+    CompletionChunk {
+        content: text.to_string(),
+        finished: false,
+        tool_calls: None,
+        usage: None,
+        tool_call_deltas: None,
+        reasoning: None,
+        truncated: false,
+    }
+}
+
+/// Answers requests with its responses in turn, and keeps the requests it was sent
+pub struct ScriptedProvider {
+    responses: Mutex<VecDeque<Response>>,
+    requests: Arc<Mutex<Vec<CompletionRequest>>>,
+    capabilities: Capabilities,
+}
+
+impl ScriptedProvider {
+    pub fn new(responses: Vec<Response>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into()),
+            requests: Arc::default(),
+            capabilities: Capabilities::default(),
+        }
+    }
+
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The requests sent so far, kept after the provider is moved into a registry
+    pub fn requests(&self) -> Arc<Mutex<Vec<CompletionRequest>>> {
+        Arc::clone(&self.requests)
+    }
+
+    fn next(&self, request: CompletionRequest) -> Result<Response> {
+        self.requests.lock().unwrap().push(request);
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("no scripted response left"))
+    }
+}
+
+#[async_trait::async_trait]
+impl LLMProvider for ScriptedProvider {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let response = self.next(request)?;
+        Ok(CompletionResponse {
+            content: response.chunks.iter().map(|c| c.content.as_str()).collect(),
+            usage: Usage {
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+                reasoning_tokens: 0,
+            },
+            model: self.model().to_string(),
+        })
+    }
+
+    async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
+        let response = self.next(request)?;
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            for chunk in response.chunks {
+                tokio::time::sleep(response.delay).await;
+                if tx.send(Ok(chunk)).await.is_err() {
+                    return;
+                }
+            }
+            if let Some(error) = response.error {
+                let _ = tx.send(Err(error.into())).await;
+            }
+            if response.stall {
+                tx.closed().await;
+            }
+        });
+        Ok(CompletionStream::new(rx))
+    }
+
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    fn model(&self) -> &str {
+        "scripted-model"
+    }
+
+    fn has_native_tool_calling(&self) -> bool {
+        true
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+}
+
+/// A quiet agent answered by `provider`, keeping its artifacts in the returned directory
+pub async fn agent(
+    provider: ScriptedProvider,
+    mut config: Config,
+) -> (Agent<NullUiWriter>, TempDir) {
+    config.stats.enabled = false;
+    let mut agent = Agent::new_with_quiet(config, NullUiWriter, true)
+        .await
+        .unwrap();
+    let mut providers = ProviderRegistry::new();
+    providers.register(provider);
+    agent.providers = providers;
+    let dir = TempDir::new().unwrap();
+    agent.session_id = Some("test".to_string());
+    agent.context_window.artifacts = ArtifactStore::new(dir.path());
+    (agent, dir)
+}
+
+/// The events `agent` publishes from now on
+pub fn events(agent: &Agent<NullUiWriter>) -> UnboundedReceiver<AgentEvent> {
+    let subscriber = Arc::new(ChannelSubscriber::default());
+    let receiver = subscriber.connect();
+    agent.events.subscribe(subscriber);
+    receiver
+}
+
+/// The events received so far
+pub fn received(events: &mut UnboundedReceiver<AgentEvent>) -> Vec<AgentEvent> {
+    std::iter::from_fn(|| events.try_recv().ok()).collect()
+}
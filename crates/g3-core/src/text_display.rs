@@ -0,0 +1,240 @@
+//! Text shown in a terminal: widths, truncation and wrapping.
+//!
+//! Text is measured in terminal columns and cut only between grapheme clusters, so
+//! multi-byte characters, combining accents and emoji (including ZWJ sequences and flags)
+//! are never split and wide characters count as two columns.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Appended to text that was cut
+pub const ELLIPSIS: &str = "...";
+
+/// Columns a grapheme cluster takes. Emoji sequences of several code points are drawn as a
+/// single wide character.
+fn grapheme_width(grapheme: &str) -> usize {
+    let width = grapheme.width();
+    if grapheme.chars().nth(1).is_some() {
+        width.min(2)
+    } else {
+        width
+    }
+}
+
+/// Columns `text` takes on one line
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// `text` cut to at most `max_width` columns, ending in [`ELLIPSIS`] if it was cut
+pub fn truncate(text: &str, max_width: usize) -> String {
+    if display_width(text) <= max_width {
+        return text.to_string();
+    }
+    let budget = max_width.saturating_sub(ELLIPSIS.len());
+    let mut width = 0;
+    let mut end = 0;
+    for (index, grapheme) in text.grapheme_indices(true) {
+        width += grapheme_width(grapheme);
+        if width > budget {
+            break;
+        }
+        end = index + grapheme.len();
+    }
+    format!("{}{}", text[..end].trim_end(), ELLIPSIS)
+}
+
+/// The first line of `text` cut to `max_width` columns, ending in [`ELLIPSIS`] if there is
+/// more to it
+pub fn first_line(text: &str, max_width: usize) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or("");
+    if lines.next().is_some() {
+        truncate(&format!("{}{}", first, ELLIPSIS), max_width)
+    } else {
+        truncate(first, max_width)
+    }
+}
+
+/// `text` wrapped to lines of at most `width` columns, breaking at whitespace where it can
+/// and between grapheme clusters where a word is too wide for a line
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in paragraph.split_word_bounds() {
+            let word_width = display_width(word);
+            if word_width <= width {
+                if line_width + word_width > width {
+                    lines.push(line.trim_end().to_string());
+                    line = String::new();
+                    line_width = 0;
+                    if word.trim().is_empty() {
+                        continue;
+                    }
+                }
+                line.push_str(word);
+                line_width += word_width;
+                continue;
+            }
+            // Too wide for any line: split it, filling the current line first
+            for grapheme in word.graphemes(true) {
+                let grapheme_width = grapheme_width(grapheme);
+                if line_width + grapheme_width > width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                    line_width = 0;
+                }
+                line.push_str(grapheme);
+                line_width += grapheme_width;
+            }
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+/// ASCII for the emoji and decorations g3 prints, for `plain_output`
+const PLAIN_REPLACEMENTS: &[(&str, &str)] = &[
+    ("✅", "[OK]"),
+    ("✓", "[OK]"),
+    ("❌", "[ERR]"),
+    ("💥", "[ERR]"),
+    ("⚠", "[WARN]"),
+    ("❓", "[?]"),
+    ("📄", "[FILE]"),
+    ("📁", "[DIR]"),
+    ("📂", "[DIR]"),
+    ("📝", "[NOTE]"),
+    ("📋", "[LIST]"),
+    ("📑", "[OUTLINE]"),
+    ("📊", "[STATS]"),
+    ("📈", "[STATS]"),
+    ("📚", "[DOCS]"),
+    ("📖", "[READ]"),
+    ("📦", "[PKG]"),
+    ("🗺", "[MAP]"),
+    ("🔍", "[SEARCH]"),
+    ("🔄", "[RETRY]"),
+    ("⏱", "[TIME]"),
+    ("🕝", "[TIME]"),
+    ("⏰", "[TIME]"),
+    ("⏳", "[WAIT]"),
+    ("⏸", "[PAUSED]"),
+    ("⏪", "[UNDO]"),
+    ("↩", "[UNDO]"),
+    ("🥒", "[CONTEXT]"),
+    ("🗜", "[CONTEXT]"),
+    ("💭", "[THINKING]"),
+    ("💡", "[TIP]"),
+    ("🔧", "[TOOL]"),
+    ("🛠", "[TOOL]"),
+    ("🔌", "[PLUGIN]"),
+    ("🔐", "[KEY]"),
+    ("🔑", "[KEY]"),
+    ("🗑", "[DELETE]"),
+    ("🏷", "[TAG]"),
+    ("🔀", "[BRANCH]"),
+    ("🎉", "[DONE]"),
+    ("🎯", "[GOAL]"),
+    ("🎓", "[COACH]"),
+    ("🤖", "[AGENT]"),
+    ("🚀", "[START]"),
+    ("─", "-"),
+    ("━", "-"),
+    ("═", "="),
+    ("│", "|"),
+    ("┃", "|"),
+    ("┌", "+"),
+    ("┐", "+"),
+    ("└", "+"),
+    ("┘", "+"),
+    ("├", "+"),
+    ("┤", "+"),
+    ("•", "-"),
+    ("●", "*"),
+    ("○", "o"),
+    ("☐", "[ ]"),
+    ("☑", "[x]"),
+    ("→", "->"),
+    ("←", "<-"),
+    ("▶", ">"),
+    ("▲", "^"),
+    ("▼", "v"),
+    ("…", "..."),
+    ("—", "-"),
+    ("█", "#"),
+    ("░", "."),
+];
+
+/// Whether a grapheme cluster is an emoji or other pictograph
+fn is_pictograph(grapheme: &str) -> bool {
+    grapheme.chars().any(|c| {
+        matches!(c as u32,
+            0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x2300..=0x23FF | 0x2580..=0x259F)
+    })
+}
+
+/// `text` with emoji and box-drawing characters replaced by ASCII tags such as `[OK]` and
+/// `[ERR]`, for screen readers and terminals without emoji. Other pictographs are removed
+/// along with the space after them.
+pub fn plain(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut graphemes = text.graphemes(true).peekable();
+    while let Some(grapheme) = graphemes.next() {
+        let base = grapheme.trim_end_matches('\u{fe0f}');
+        if let Some((_, ascii)) = PLAIN_REPLACEMENTS.iter().find(|(from, _)| *from == base) {
+            out.push_str(ascii);
+        } else if is_pictograph(grapheme) {
+            if graphemes.peek() == Some(&" ") {
+                graphemes.next();
+            }
+        } else {
+            out.push_str(grapheme);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_display() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("👩‍💻"), 2);
+        assert_eq!(display_width("🇯🇵"), 2);
+
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("abcdefghij", 8), "abcde...");
+        // Wide characters and emoji are never split
+        assert_eq!(truncate("日本語のテキスト", 10), "日本語...");
+        assert_eq!(truncate("ab👩‍💻👩‍💻cd", 7), "ab👩‍💻...");
+        assert_eq!(truncate("cafe\u{301} au lait", 8), "cafe\u{301}...");
+
+        assert_eq!(first_line("make\nmake test", 80), "make...");
+        assert_eq!(first_line("make", 80), "make");
+        assert_eq!(first_line("abcdefghij\nmore", 8), "abcde...");
+
+        assert_eq!(
+            wrap("the quick brown fox jumps", 10),
+            vec!["the quick", "brown fox", "jumps"]
+        );
+        assert_eq!(
+            wrap("日本語のテキスト", 6),
+            vec!["日本語", "のテキ", "スト"]
+        );
+        assert_eq!(wrap("a\n\nb", 10), vec!["a", "", "b"]);
+
+        assert_eq!(plain("✅ Done"), "[OK] Done");
+        assert_eq!(plain("❌ Failed: 日本"), "[ERR] Failed: 日本");
+        assert_eq!(plain("⚠️  Careful"), "[WARN]  Careful");
+        assert_eq!(plain("┌─ shell │ ls"), "+- shell | ls");
+        assert_eq!(plain("👋 Goodbye 🌍!"), "Goodbye !");
+        assert_eq!(plain(&plain("✅ twice")), "[OK] twice");
+    }
+}
@@ -0,0 +1,111 @@
+//! Token counting with a real BPE tokenizer, used to check requests against the model's
+//! context window before they are sent.
+//!
+//! OpenAI models are counted with their own encoding. Other providers don't publish their
+//! tokenizers, so their text is counted with cl100k and padded by [`FOREIGN_TOKENIZER_MARGIN`]
+//! to err on the side of overcounting.
+
+use g3_providers::{Message, Tool};
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+/// Tokens each message costs on top of its content (role and delimiters)
+const MESSAGE_OVERHEAD: u32 = 4;
+
+/// Allowance for models whose tokenizer differs from the one used to count
+const FOREIGN_TOKENIZER_MARGIN: f64 = 1.2;
+
+enum Encoding {
+    O200k,
+    Cl100k,
+    /// Not an OpenAI model; counted with cl100k plus a margin
+    Foreign,
+}
+
+fn encoding_for(model: &str) -> Encoding {
+    let model = model.to_lowercase();
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    if model.starts_with("gpt-4o")
+        || model.starts_with("gpt-4.1")
+        || model.starts_with("gpt-5")
+        || model.starts_with("o1")
+        || model.starts_with("o3")
+        || model.starts_with("o4")
+    {
+        Encoding::O200k
+    } else if model.starts_with("gpt-4") || model.starts_with("gpt-3.5") {
+        Encoding::Cl100k
+    } else {
+        Encoding::Foreign
+    }
+}
+
+fn cl100k() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| tiktoken_rs::cl100k_base().expect("cl100k encoding is bundled"))
+}
+
+fn o200k() -> &'static CoreBPE {
+    static BPE: OnceLock<CoreBPE> = OnceLock::new();
+    BPE.get_or_init(|| tiktoken_rs::o200k_base().expect("o200k encoding is bundled"))
+}
+
+/// Tokens in `text` for `model`
+pub fn count_tokens(model: &str, text: &str) -> u32 {
+    if text.is_empty() {
+        return 0;
+    }
+    match encoding_for(model) {
+        Encoding::O200k => o200k().encode_ordinary(text).len() as u32,
+        Encoding::Cl100k => cl100k().encode_ordinary(text).len() as u32,
+        Encoding::Foreign => {
+            let tokens = cl100k().encode_ordinary(text).len() as f64;
+            (tokens * FOREIGN_TOKENIZER_MARGIN).ceil() as u32
+        }
+    }
+}
+
+pub fn count_message_tokens(model: &str, message: &Message) -> u32 {
+    count_tokens(model, &message.content) + MESSAGE_OVERHEAD
+}
+
+/// Tokens taken by the tool definitions sent along with a request
+pub fn count_tool_tokens(model: &str, tools: Option<&[Tool]>) -> u32 {
+    tools
+        .unwrap_or_default()
+        .iter()
+        .map(|tool| {
+            let schema = serde_json::to_string(&tool.input_schema).unwrap_or_default();
+            count_tokens(model, &tool.name)
+                + count_tokens(model, &tool.description)
+                + count_tokens(model, &schema)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use g3_providers::MessageRole;
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens("gpt-4o", ""), 0);
+        assert_eq!(count_tokens("gpt-4", "hello world"), 2);
+        assert_eq!(count_tokens("openai/gpt-4o-mini", "hello world"), 2);
+
+        // Other models are padded
+        let claude = count_tokens("claude-sonnet-4", "hello world ".repeat(100).as_str());
+        let gpt = count_tokens("gpt-4", "hello world ".repeat(100).as_str());
+        assert!(claude > gpt);
+
+        let message = Message {
+            role: MessageRole::User,
+            content: "hello world".to_string(),
+        };
+        assert_eq!(
+            count_message_tokens("gpt-4", &message),
+            2 + MESSAGE_OVERHEAD
+        );
+    }
+}
@@ -0,0 +1,1845 @@
+//! Registry of the built-in tools.
+//!
+//! Each tool is declared once as a [`ToolSpec`]: its name, schema, version and capabilities.
+//! The tool definitions sent to providers, the read-only policy, the tool reference in the
+//! system prompt and the per-session tool audit log are all derived from these specs. What a
+//! call of the tool does is its handler in [`crate::tools`].
+//!
+//! Arguments of a call are checked against the tool's schema before it runs, so the model
+//! gets a list of what is missing or wrong instead of each tool guessing at what it meant.
+//!
+//! Bump a tool's `version` whenever its arguments or output change in a way that older
+//! sessions or external integrations would notice.
+
+use chrono::{DateTime, Utc};
+use g3_providers::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// What a tool can do, for policies that allow or refuse tools by effect
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Writes to the workspace
+    pub mutating: bool,
+    /// Talks to the network
+    pub network: bool,
+    /// Runs arbitrary commands or code
+    pub executes: bool,
+    /// Reads or controls the desktop (screen, keyboard, mouse, other applications)
+    pub desktop: bool,
+}
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities {
+        mutating: false,
+        network: false,
+        executes: false,
+        desktop: false,
+    };
+
+    /// Names of the capabilities that are set, e.g. `["mutating"]`
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.mutating, "mutating"),
+            (self.network, "network"),
+            (self.executes, "executes"),
+            (self.desktop, "desktop"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect()
+    }
+}
+
+/// Tools are offered in groups that are enabled by configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolGroup {
+    /// Always available
+    Core,
+    /// `webdriver.enabled`
+    WebDriver,
+    /// `macax.enabled`
+    MacAx,
+    /// `computer_control.enabled`
+    ComputerControl,
+    /// The coach in autonomous mode
+    Coach,
+}
+
+impl ToolGroup {
+    /// Why tools of this group can be missing, for a model that calls one anyway
+    pub fn unavailable_reason(&self) -> &'static str {
+        match self {
+            ToolGroup::Core => "it is not allowed here",
+            ToolGroup::WebDriver => {
+                "browser automation is turned off (set webdriver.enabled or pass --webdriver)"
+            }
+            ToolGroup::MacAx => {
+                "macOS Accessibility tools are turned off (set macax.enabled or pass --macax)"
+            }
+            ToolGroup::ComputerControl => {
+                "computer control is turned off (set computer_control.enabled)"
+            }
+            ToolGroup::Coach => "only the coach in autonomous mode can use it",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub version: u32,
+    pub group: ToolGroup,
+    pub capabilities: Capabilities,
+    pub description: &'static str,
+    pub input_schema: Value,
+}
+
+impl ToolSpec {
+    /// The definition sent to providers
+    pub fn tool(&self) -> Tool {
+        Tool {
+            name: self.name.to_string(),
+            description: self.description.to_string(),
+            input_schema: self.input_schema.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ToolRegistry {
+    specs: Vec<ToolSpec>,
+    /// Compiled input schemas, by tool name
+    validators: HashMap<&'static str, jsonschema::Validator>,
+}
+
+impl ToolRegistry {
+    /// The built-in tools
+    pub fn builtin() -> &'static ToolRegistry {
+        static REGISTRY: OnceLock<ToolRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            let specs = builtin_specs();
+            let validators = specs
+                .iter()
+                .map(|spec| {
+                    let validator = jsonschema::validator_for(&spec.input_schema)
+                        .unwrap_or_else(|e| panic!("invalid schema of {}: {}", spec.name, e));
+                    (spec.name, validator)
+                })
+                .collect();
+            ToolRegistry { specs, validators }
+        })
+    }
+
+    pub fn specs(&self) -> &[ToolSpec] {
+        &self.specs
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolSpec> {
+        self.specs.iter().find(|spec| spec.name == name)
+    }
+
+    /// Specs of the tools in `groups`, in registry order
+    pub fn enabled(&self, groups: &[ToolGroup]) -> Vec<&ToolSpec> {
+        self.specs
+            .iter()
+            .filter(|spec| groups.contains(&spec.group))
+            .collect()
+    }
+
+    /// Whether `name` is a built-in tool that writes to the workspace
+    pub fn is_mutating(&self, name: &str) -> bool {
+        self.get(name)
+            .is_some_and(|spec| spec.capabilities.mutating)
+    }
+
+    /// Check the arguments of a call of `name` against its schema. Tools not in the registry
+    /// aren't checked.
+    pub fn validate(&self, name: &str, args: &Value) -> Result<(), InvalidArguments> {
+        let Some(validator) = self.validators.get(name) else {
+            return Ok(());
+        };
+        // Tools without arguments are often called with none at all
+        let empty = json!({});
+        let args = if args.is_null() { &empty } else { args };
+        let problems: Vec<String> = validator.iter_errors(args).map(describe).collect();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let spec = self.get(name).expect("validators are built from the specs");
+        Err(InvalidArguments {
+            tool: spec.name,
+            problems,
+            expected: expected_arguments(&spec.input_schema),
+        })
+    }
+
+    /// `{name: version}` for every tool, recorded in session logs
+    pub fn versions(&self) -> Value {
+        Value::Object(
+            self.specs
+                .iter()
+                .map(|spec| (spec.name.to_string(), json!(spec.version)))
+                .collect(),
+        )
+    }
+}
+
+/// Why a call's arguments don't match its tool's schema, worded for the model to correct
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidArguments {
+    pub tool: &'static str,
+    /// One line per missing or invalid argument
+    pub problems: Vec<String>,
+    /// The arguments the tool takes, e.g. `file_path (string, required), start (integer)`
+    pub expected: String,
+}
+
+impl std::fmt::Display for InvalidArguments {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Invalid arguments for {}:", self.tool)?;
+        for problem in &self.problems {
+            writeln!(f, "- {}", problem)?;
+        }
+        write!(
+            f,
+            "{} takes: {}. Call it again with corrected arguments.",
+            self.tool, self.expected
+        )
+    }
+}
+
+/// One validation error as a line naming the argument, e.g. `` `start`: "10" is not of type "integer" ``
+fn describe(error: jsonschema::ValidationError) -> String {
+    if let jsonschema::error::ValidationErrorKind::Required { property } = &error.kind {
+        let property = property
+            .as_str()
+            .map_or(property.to_string(), str::to_string);
+        let parent = error.instance_path.as_str();
+        return if parent.is_empty() {
+            format!("missing required argument `{}`", property)
+        } else {
+            format!("`{}`: missing `{}`", argument_path(parent), property)
+        };
+    }
+    // Don't echo long values, such as file contents, back to the model
+    let message = if error.instance.to_string().len() > 60 {
+        error.masked().to_string()
+    } else {
+        error.to_string()
+    };
+    match error.instance_path.as_str() {
+        "" => format!("arguments: {}", message),
+        path => format!("`{}`: {}", argument_path(path), message),
+    }
+}
+
+/// `/searches/0/query` as `searches[0].query`
+fn argument_path(pointer: &str) -> String {
+    let mut path = String::new();
+    for segment in pointer.split('/').skip(1) {
+        if segment.parse::<usize>().is_ok() {
+            path.push_str(&format!("[{}]", segment));
+        } else {
+            if !path.is_empty() {
+                path.push('.');
+            }
+            path.push_str(&segment.replace("~1", "/").replace("~0", "~"));
+        }
+    }
+    path
+}
+
+/// The top-level arguments of a schema with their types, required ones first
+fn expected_arguments(schema: &Value) -> String {
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let Some(properties) = schema["properties"].as_object().filter(|p| !p.is_empty()) else {
+        return "no arguments".to_string();
+    };
+    let mut arguments: Vec<(bool, String)> = properties
+        .iter()
+        .map(|(name, property)| {
+            let is_required = required.contains(&name.as_str());
+            let kind = property["type"].as_str().unwrap_or("any");
+            let label = if is_required {
+                format!("{} ({}, required)", name, kind)
+            } else {
+                format!("{} ({})", name, kind)
+            };
+            (!is_required, label)
+        })
+        .collect();
+    // Stable, so properties stay in name order within each group
+    arguments.sort_by_key(|(optional, _)| *optional);
+    arguments
+        .into_iter()
+        .map(|(_, label)| label)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A compact reference of `tools` for the system prompt of providers without native tool
+/// calling: name, version, capabilities, description and example calls
+pub fn prompt_reference<'a>(tools: impl IntoIterator<Item = &'a ToolSpec>) -> String {
+    let mut reference = String::from("\n\n# Tool Reference\n");
+    for spec in tools {
+        let capabilities = spec.capabilities.names();
+        reference.push_str(&format!(
+            "\n- **{}** (v{}){}: {}",
+            spec.name,
+            spec.version,
+            if capabilities.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", capabilities.join(", "))
+            },
+            spec.description
+        ));
+        for example in json_examples(spec.name) {
+            reference.push_str(&format!("\n  - {}", example));
+        }
+    }
+    reference
+}
+
+/// A reference of tools from outside the registry, such as plugin tools, under `heading`
+/// for the system prompt of providers without native tool calling
+pub fn external_tools_reference(heading: &str, tools: &[Tool]) -> String {
+    if tools.is_empty() {
+        return String::new();
+    }
+    let mut reference = format!("\n\n# {}\n", heading);
+    for tool in tools {
+        let params = tool.input_schema["properties"]
+            .as_object()
+            .map(|p| p.keys().cloned().collect::<Vec<_>>().join(", "))
+            .unwrap_or_default();
+        reference.push_str(&format!(
+            "\n- **{}** ({}): {}",
+            tool.name, params, tool.description
+        ));
+    }
+    reference
+}
+
+/// Example calls of a tool in the JSON format of providers without native tool calling
+fn json_examples(name: &str) -> &'static [&'static str] {
+    match name {
+        "shell" => &[
+            "Format: {\"tool\": \"shell\", \"args\": {\"command\": \"your_command_here\"}",
+            "Example: {\"tool\": \"shell\", \"args\": {\"command\": \"ls ~/Downloads\"}",
+        ],
+        "read_file" => &[
+            "Format: {\"tool\": \"read_file\", \"args\": {\"file_path\": \"path/to/file\", \"start\": 0, \"end\": 100}",
+            "Example: {\"tool\": \"read_file\", \"args\": {\"file_path\": \"src/main.rs\"}",
+            "Example (partial): {\"tool\": \"read_file\", \"args\": {\"file_path\": \"large.log\", \"start\": 0, \"end\": 1000}",
+            "Example (outline, then lines): {\"tool\": \"read_file\", \"args\": {\"file_path\": \"src/lib.rs\", \"start_line\": 1, \"end_line\": 1, \"outline\": true}",
+            "Example (line range): {\"tool\": \"read_file\", \"args\": {\"file_path\": \"src/lib.rs\", \"start_line\": 120, \"end_line\": 180, \"line_numbers\": true}",
+            "Example (binary bytes as hex): {\"tool\": \"read_file\", \"args\": {\"file_path\": \"assets/logo.png\", \"start\": 0, \"end\": 512, \"read_binary\": true}",
+        ],
+        "write_file" => &[
+            "Format: {\"tool\": \"write_file\", \"args\": {\"file_path\": \"path/to/file\", \"content\": \"file content\"}",
+            "Example: {\"tool\": \"write_file\", \"args\": {\"file_path\": \"src/lib.rs\", \"content\": \"pub fn hello() {}\"}",
+        ],
+        "str_replace" => &[
+            "Format: {\"tool\": \"str_replace\", \"args\": {\"file_path\": \"path/to/file\", \"diff\": \"--- old\\n-old text\\n+++ new\\n+new text\"}",
+            "Example: {\"tool\": \"str_replace\", \"args\": {\"file_path\": \"src/main.rs\", \"diff\": \"--- old\\n-old_code();\\n+++ new\\n+new_code();\"}",
+        ],
+        "edit_symbol" => &[
+            "Format: {\"tool\": \"edit_symbol\", \"args\": {\"file_path\": \"path/to/file\", \"symbol\": \"Parent::name\", \"new_code\": \"full new definition\", \"kind\": \"fn\", \"body_only\": false}",
+            "Example: {\"tool\": \"edit_symbol\", \"args\": {\"file_path\": \"src/main.rs\", \"symbol\": \"greet\", \"new_code\": \"fn greet() {\\n    println!(\\\"hi\\\");\\n}\"}",
+        ],
+        "undo_edit" => &[
+            "Format: {\"tool\": \"undo_edit\", \"args\": {\"count\": 1}",
+        ],
+        "show_changes" => &[
+            "Format: {\"tool\": \"show_changes\", \"args\": {\"paths\": [\"src/\"]}}",
+            "Example (two files): {\"tool\": \"show_changes\", \"args\": {\"file_a\": \"config.old.toml\", \"file_b\": \"config.toml\"}}",
+        ],
+        "final_output" => &[
+            "Format: {\"tool\": \"final_output\", \"args\": {\"summary\": \"what_was_accomplished\"}",
+        ],
+        "todo_read" => &[
+            "Format: {\"tool\": \"todo_read\", \"args\": {}}",
+            "Example: {\"tool\": \"todo_read\", \"args\": {}}",
+        ],
+        "todo_write" => &[
+            "Format: {\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Task 1\\n- [ ] Task 2\"}}",
+            "Example: {\"tool\": \"todo_write\", \"args\": {\"content\": \"- [ ] Implement feature\\n  - [ ] Write tests\\n  - [ ] Run tests\"}}",
+        ],
+        "code_search" => &[
+            "Format: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"label\", \"query\": \"tree-sitter query\", \"language\": \"rust|python|javascript|typescript\", \"paths\": [\"src/\"], \"context_lines\": 0}]}}",
+            "Find functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"find_functions\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\", \"paths\": [\"src/\"]}]}}",
+            "Find async functions: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"find_async\", \"query\": \"(function_item (function_modifiers) name: (identifier) @name)\", \"language\": \"rust\"}]}}",
+            "Find structs: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"structs\", \"query\": \"(struct_item name: (type_identifier) @name)\", \"language\": \"rust\"}]}}",
+            "Multiple searches: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"funcs\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\"}, {\"name\": \"structs\", \"query\": \"(struct_item name: (type_identifier) @name)\", \"language\": \"rust\"}]}}",
+            "With context lines: {\"tool\": \"code_search\", \"args\": {\"searches\": [{\"name\": \"funcs\", \"query\": \"(function_item name: (identifier) @name)\", \"language\": \"rust\", \"context_lines\": 3}]}}",
+        ],
+        _ => &[],
+    }
+}
+
+/// Description of code_search_semantic when `[embeddings]` is the hashed fallback, whose
+/// matches share words with the query rather than meaning
+pub const HASHED_SEMANTIC_SEARCH_DESCRIPTION: &str = "Find code by describing it in plain words, for questions like 'where is retry logic implemented?' or 'how are config files loaded?'. Matching is lexical: it returns the functions, types and other chunks of the workspace that share the most words and word parts with the query (retry, retries, max_retry_attempts), with their first lines, so use the words the code itself is likely to use. Files are indexed on first use and re-indexed when they change. Use code_search or grep instead when you know a name or a pattern.";
+
+/// Audit log of a session's tool calls, in its session directory
+pub const AUDIT_LOG_FILE: &str = "tool_audit.jsonl";
+
+/// One line of a session's tool audit log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub tool: String,
+    /// Version of the tool that ran; `None` for tools not in the registry
+    pub version: Option<u32>,
+    pub capabilities: Vec<String>,
+    pub success: bool,
+    pub duration_ms: u64,
+}
+
+impl AuditEntry {
+    pub fn new(tool: &str, success: bool, duration: std::time::Duration) -> Self {
+        let spec = ToolRegistry::builtin().get(tool);
+        Self {
+            timestamp: Utc::now(),
+            tool: tool.to_string(),
+            version: spec.map(|spec| spec.version),
+            capabilities: spec
+                .map(|spec| spec.capabilities.names())
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            success,
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+
+    /// Append the entry to the JSONL audit log at `path`
+    pub fn append(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(log, "{}", serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+fn builtin_specs() -> Vec<ToolSpec> {
+    vec![
+        ToolSpec {
+            name: "shell",
+            version: 2,
+            group: ToolGroup::Core,
+            capabilities: Capabilities {
+                executes: true,
+                ..Capabilities::NONE
+            },
+            description: "Execute shell commands. Long output is cut to its first and last lines; all of it is saved to a file whose path and line count are given, so it can be searched with grep.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "command": {
+                        "type": "string",
+                        "description": "The shell command to execute"
+                    }
+                },
+                "required": ["command"]
+            }),
+        },
+        ToolSpec {
+            name: "read_file",
+            version: 5,
+            group: ToolGroup::Core,
+            capabilities: Capabilities::NONE,
+            description: "Read the contents of a file. For image files (png, jpg, jpeg, gif, bmp, tiff, webp), automatically extracts text using OCR. For text files, optionally read a specific character or line range, show line numbers, and include a symbol outline (functions, types, impl blocks with line ranges) for source files. For large source files, read with outline=true first, then read only the line ranges you need. Partial reads of source files are adjusted so they don't cut through functions or types, and list the symbols they include and the line to continue from. Binary files are described (type, size, hex dump of the first bytes); use read_binary=true to dump other byte ranges. Reading the same range of an unchanged file again returns a short note pointing to the earlier result instead of the content, unless force=true.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "The path to the file to read"
+                    },
+                    "start": {
+                        "type": "integer",
+                        "description": "Starting character position (0-indexed, inclusive). If omitted, reads from beginning."
+                    },
+                    "end": {
+                        "type": "integer",
+                        "description": "Ending character position (0-indexed, EXCLUSIVE). If omitted, reads to end of file."
+                    },
+                    "start_line": {
+                        "type": "integer",
+                        "description": "First line to read (1-indexed, inclusive). Ignored if start/end are given."
+                    },
+                    "end_line": {
+                        "type": "integer",
+                        "description": "Last line to read (1-indexed, inclusive). Ignored if start/end are given."
+                    },
+                    "line_numbers": {
+                        "type": "boolean",
+                        "description": "Prefix each line with its line number (default: false)"
+                    },
+                    "outline": {
+                        "type": "boolean",
+                        "description": "Append a symbol outline with line ranges for supported source files (default: false)"
+                    },
+                    "exact": {
+                        "type": "boolean",
+                        "description": "Return exactly the requested range instead of adjusting it to symbol boundaries (default: false)"
+                    },
+                    "read_binary": {
+                        "type": "boolean",
+                        "description": "Return a hex dump of bytes start..end (byte offsets, default the first 256 bytes, at most 4096) instead of text. Use for binary files."
+                    },
+                    "force": {
+                        "type": "boolean",
+                        "description": "Return the full content even if the same read of the unchanged file is already in the conversation (default: false)"
+                    }
+                },
+                "required": ["file_path"]
+            }),
+        },
+        ToolSpec {
+            name: "write_file",
+            version: 2,
+            group: ToolGroup::Core,
+            capabilities: Capabilities {
+                mutating: true,
+                ..Capabilities::NONE
+            },
+            description: "Write content to a file (creates or overwrites). You MUST provide all arguments",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "The path to the file to write"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "The content to write to the file"
+                    }
+                },
+                "required": ["file_path", "content"]
+            }),
+        },
+        ToolSpec {
+            name: "str_replace",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities {
+                mutating: true,
+                ..Capabilities::NONE
+            },
+            description: "Apply a unified diff to a file. Supports multiple hunks and context lines. Optionally constrain the search to a [start, end) character range (0-indexed; end is EXCLUSIVE). Useful to disambiguate matches or limit scope in large files.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "The path to the file to edit"
+                    },
+                    "diff": {
+                        "type": "string",
+                        "description": "A unified diff showing what to replace. Supports @@ hunk headers, context lines, and multiple hunks (---/+++ headers optional for minimal diffs)."
+                    },
+                    "start": {
+                        "type": "integer",
+                        "description": "Starting character position in the file (0-indexed, inclusive). If omitted, searches from beginning."
+                    },
+                    "end": {
+                        "type": "integer",
+                        "description": "Ending character position in the file (0-indexed, EXCLUSIVE - character at this position is NOT included). If omitted, searches to end of file."
+                    }
+                },
+                "required": ["file_path", "diff"]
+            }),
+        },
+        ToolSpec {
+            name: "edit_symbol",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities {
+                mutating: true,
+                ..Capabilities::NONE
+            },
+            description: "Replace a named function, method, class, struct, enum, trait or impl block in a source file, located structurally with tree-sitter. Prefer this over str_replace for well-scoped edits like \"rewrite function X\". Supports Rust, Python, JavaScript, TypeScript, Go, Java, C and C++.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "The path to the file to edit"
+                    },
+                    "symbol": {
+                        "type": "string",
+                        "description": "Name of the definition to replace. Qualify nested definitions with their parent, e.g. \"Point::new\" or \"MyClass.method\"."
+                    },
+                    "new_code": {
+                        "type": "string",
+                        "description": "The complete replacement code for the definition (signature included), or only the body if body_only is true"
+                    },
+                    "kind": {
+                        "type": "string",
+                        "description": "Optional kind to disambiguate matches: fn, struct, enum, trait, impl, mod, class, def, function, method, interface, type, ..."
+                    },
+                    "body_only": {
+                        "type": "boolean",
+                        "description": "Replace only the body and keep the existing signature. For brace-delimited languages the body includes its braces. Default: false"
+                    }
+                },
+                "required": ["file_path", "symbol", "new_code"]
+            }),
+        },
+        ToolSpec {
+            name: "undo_edit",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities {
+                mutating: true,
+                ..Capabilities::NONE
+            },
+            description: "Revert the most recent file modifications made by write_file, str_replace or edit_symbol in this session, newest first. Files the tools created are deleted.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "count": {
+                        "type": "integer",
+                        "description": "Number of modifications to revert (default: 1)"
+                    }
+                }
+            }),
+        },
+        ToolSpec {
+            name: "show_changes",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities::NONE,
+            description: "Show a unified diff of the files you changed this session against their content before the session first changed them, to check your edits. Changes made by others are left out. Pass file_a and file_b instead to compare two files.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "paths": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Only show changed files under these files or directories"
+                    },
+                    "file_a": {
+                        "type": "string",
+                        "description": "The original file, to compare with file_b"
+                    },
+                    "file_b": {
+                        "type": "string",
+                        "description": "The changed file, to compare with file_a"
+                    }
+                }
+            }),
+        },
+        ToolSpec {
+            name: "final_output",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities::NONE,
+            description: "Signal task completion with a detailed summary",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "summary": {
+                        "type": "string",
+                        "description": "A detailed summary in markdown of what was accomplished"
+                    }
+                },
+                "required": ["summary"]
+            }),
+        },
+        ToolSpec {
+            name: "take_screenshot",
+            version: 2,
+            group: ToolGroup::Core,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Capture a screenshot of a specific application window. You MUST specify the window_id parameter with the application name (e.g., 'Safari', 'Terminal', 'Google Chrome'). The tool will automatically use the native screencapture command with the application's window ID for a clean capture.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Filename for the screenshot (e.g., 'safari.png'). If a relative path is provided, the screenshot will be saved to the session's artifact directory. Use an absolute path to save elsewhere."
+                    },
+                    "window_id": {
+                        "type": "string",
+                        "description": "REQUIRED: Application name to capture (e.g., 'Safari', 'Terminal', 'Google Chrome'). The tool will capture the frontmost window of that application using its native window ID."
+                    },
+                    "region": {
+                        "type": "object",
+                        "properties": {
+                            "x": {"type": "integer"},
+                            "y": {"type": "integer"},
+                            "width": {"type": "integer"},
+                            "height": {"type": "integer"}
+                        }
+                    }
+                },
+                "required": ["path", "window_id"]
+            }),
+        },
+        ToolSpec {
+            name: "extract_text",
+            version: 2,
+            group: ToolGroup::Core,
+            capabilities: Capabilities::NONE,
+            description: "Extract text from an image file using OCR. For extracting text from a specific window, use vision_find_text instead which automatically handles window capture.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to image file (optional if region is provided)"
+                    },
+                    "region": {
+                        "type": "object",
+                        "description": "Optional: Only read this part of the image, in image pixels. Faster for large screenshots.",
+                        "properties": {
+                            "x": {"type": "integer"},
+                            "y": {"type": "integer"},
+                            "width": {"type": "integer"},
+                            "height": {"type": "integer"}
+                        }
+                    },
+                }
+            }),
+        },
+        ToolSpec {
+            name: "todo_read",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities::NONE,
+            description: "Read your current TODO list from todo.g3.md file in the workspace directory. Shows what tasks are planned and their status. Call this at the start of multi-step tasks to check for existing plans, and during execution to review progress before updating. TODO lists persist across g3 sessions.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "todo_write",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities {
+                mutating: true,
+                ..Capabilities::NONE
+            },
+            description: "Create or update your TODO list in todo.g3.md file with a complete task plan. Use markdown checkboxes: - [ ] for pending, - [x] for complete. This tool replaces the entire file content, so always call todo_read first to preserve existing content. Essential for multi-step tasks. Changes persist across g3 sessions.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "content": {
+                        "type": "string",
+                        "description": "The TODO list content to save. Use markdown checkbox format: - [ ] for incomplete tasks, - [x] for completed tasks. Support nested tasks with indentation."
+                    }
+                },
+                "required": ["content"]
+            }),
+        },
+        ToolSpec {
+            name: "code_search_semantic",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities::NONE,
+            description: "Find code by what it does rather than by its exact text, for questions like 'where is retry logic implemented?' or 'how are config files loaded?'. Returns the functions, types and other chunks of the workspace closest in meaning to the query, with their first lines. Files are indexed on first use and re-indexed when they change. Use code_search or grep instead when you know a name or a pattern.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "What the code you are looking for does, in plain words"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": "How many matches to return (default: 10, at most 50)"
+                    },
+                    "paths": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only search files under these paths, relative to the workspace"
+                    }
+                },
+                "required": ["query"]
+            }),
+        },
+        ToolSpec {
+            name: "code_search",
+            version: 1,
+            group: ToolGroup::Core,
+            capabilities: Capabilities::NONE,
+            description: "Syntax-aware code search that understands code structure, not just text. Finds actual functions, classes, methods, and other code constructs - ignores matches in comments and strings. Much more accurate than grep for code searches. Supports batch searches (up to 20 parallel) with structured results and context lines. Languages: Rust, Python, JavaScript, TypeScript, Go, Java, C, C++, Kotlin. Uses tree-sitter query syntax.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "searches": {
+                        "type": "array",
+                        "maxItems": 20,
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string", "description": "Label for this search." },
+                                "query": { "type": "string", "description": "tree-sitter query in S-expression format (e.g., \"(function_item name: (identifier) @name)\")"},
+                                "language": { "type": "string", "enum": ["rust", "python", "javascript", "typescript", "go", "java", "c", "cpp", "kotlin"], "description": "Programming language to search." },
+                                "paths": { "type": "array", "items": { "type": "string" }, "description": "Paths/dirs to search. Defaults to current dir if empty." },
+                                "context_lines": { "type": "integer", "minimum": 0, "maximum": 20, "default": 0, "description": "Lines of context to include around each match." }
+                            },
+                            "required": ["name", "query", "language"]
+                        }
+                    },
+                    "max_concurrency": { "type": "integer", "minimum": 1, "default": 4 },
+                    "max_matches_per_search": { "type": "integer", "minimum": 1, "default": 500 }
+                },
+                "required": ["searches"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_start",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Start a Safari WebDriver session for browser automation. Must be called before any other webdriver tools. Requires Safari's 'Allow Remote Automation' to be enabled in Develop menu.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_navigate",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Navigate to a URL in the browser",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "url": {
+                        "type": "string",
+                        "description": "The URL to navigate to (must include protocol, e.g., https://)"
+                    }
+                },
+                "required": ["url"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_get_url",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Get the current URL of the browser",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_get_title",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Get the title of the current page",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_find_element",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Find an element on the page by CSS selector and return its text content",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "selector": {
+                        "type": "string",
+                        "description": "CSS selector to find the element (e.g., 'h1', '.class-name', '#id')"
+                    }
+                },
+                "required": ["selector"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_wait_for_selector",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Wait until an element matching a CSS selector is on the page (and optionally visible). Use this after navigating or clicking instead of sleeping. Returns how long it took and the element's text, or what the page showed when it timed out.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "selector": {
+                        "type": "string",
+                        "description": "CSS selector of the element to wait for"
+                    },
+                    "visible": {
+                        "type": "boolean",
+                        "description": "Also wait until the element is displayed (default: false)"
+                    },
+                    "timeout_seconds": {
+                        "type": "number",
+                        "description": "How long to wait (default: 10, at most 120)"
+                    }
+                },
+                "required": ["selector"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_wait_for_text",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Wait until some text appears on the page, or in the element matching a CSS selector. Returns how long it took, or the element's text when it timed out.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "text": {
+                        "type": "string",
+                        "description": "Text to wait for"
+                    },
+                    "selector": {
+                        "type": "string",
+                        "description": "CSS selector of the element that should contain the text (default: 'body')"
+                    },
+                    "timeout_seconds": {
+                        "type": "number",
+                        "description": "How long to wait (default: 10, at most 120)"
+                    }
+                },
+                "required": ["text"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_set_download_dir",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Set the directory webdriver_wait_for_download watches (~/Downloads by default). Safari saves downloads to the folder chosen in its settings, so this should be that folder.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory the browser saves downloads to"
+                    }
+                },
+                "required": ["path"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_wait_for_download",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Wait until a download whose file name matches a pattern has finished, and return the file's path. Call it right after the click that starts the download; files downloaded before that click don't count.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "File name pattern with * and ? wildcards (e.g. '*.csv', 'report-*.pdf')"
+                    },
+                    "timeout_seconds": {
+                        "type": "number",
+                        "description": "How long to wait (default: 60, at most 600)"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_find_elements",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Find all elements matching a CSS selector and return their text content",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "selector": {
+                        "type": "string",
+                        "description": "CSS selector to find elements"
+                    }
+                },
+                "required": ["selector"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_click",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Click an element on the page",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "selector": {
+                        "type": "string",
+                        "description": "CSS selector for the element to click"
+                    }
+                },
+                "required": ["selector"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_send_keys",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Type text into an input element",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "selector": {
+                        "type": "string",
+                        "description": "CSS selector for the input element"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "Text to type into the element"
+                    },
+                    "clear_first": {
+                        "type": "boolean",
+                        "description": "Whether to clear the element before typing (default: true)"
+                    }
+                },
+                "required": ["selector", "text"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_execute_script",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                executes: true,
+                ..Capabilities::NONE
+            },
+            description: "Execute JavaScript code in the browser and return the result",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "script": {
+                        "type": "string",
+                        "description": "JavaScript code to execute (use 'return' to return a value)"
+                    }
+                },
+                "required": ["script"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_get_page_source",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Get the HTML source of the current page",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_screenshot",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Take a screenshot of the browser window",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path where to save the screenshot (e.g., '/tmp/screenshot.png')"
+                    }
+                },
+                "required": ["path"]
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_back",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Navigate back in browser history",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_forward",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Navigate forward in browser history",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_refresh",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Refresh the current page",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_new_tab",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Open a new tab (or window) and switch to it, optionally loading a URL",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "url": {
+                        "type": "string",
+                        "description": "URL to open in the new tab"
+                    },
+                    "window": {
+                        "type": "boolean",
+                        "description": "Open a separate window instead of a tab (default: false)"
+                    }
+                },
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_list_tabs",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "List the open tabs and windows, including popups such as OAuth sign-in windows, with their index, title, URL and handle. The current one is marked.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_switch_tab",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Switch to another tab or window, so later WebDriver commands act on it. Give either its index from webdriver_list_tabs or its handle.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "index": {
+                        "type": "integer",
+                        "description": "1-based index of the tab in webdriver_list_tabs"
+                    },
+                    "handle": {
+                        "type": "string",
+                        "description": "Window handle of the tab"
+                    }
+                },
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_close_tab",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Close a tab or window (the current one by default) and switch to one that is still open",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "index": {
+                        "type": "integer",
+                        "description": "1-based index of the tab in webdriver_list_tabs"
+                    },
+                    "handle": {
+                        "type": "string",
+                        "description": "Window handle of the tab"
+                    }
+                },
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "webdriver_quit",
+            version: 1,
+            group: ToolGroup::WebDriver,
+            capabilities: Capabilities {
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Close the browser and end the WebDriver session",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "macax_list_apps",
+            version: 1,
+            group: ToolGroup::MacAx,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "List all running applications that can be controlled via macOS Accessibility API",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "macax_get_frontmost_app",
+            version: 1,
+            group: ToolGroup::MacAx,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Get the name of the currently active (frontmost) application",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "macax_activate_app",
+            version: 1,
+            group: ToolGroup::MacAx,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Bring an application to the front (activate it)",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application to activate (e.g., 'Safari', 'TextEdit')"
+                    }
+                },
+                "required": ["app_name"]
+            }),
+        },
+        ToolSpec {
+            name: "macax_dump_elements",
+            version: 1,
+            group: ToolGroup::MacAx,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Dump an application's accessibility tree: role, title, identifier, value and frame (x, y, width x height in screen points) of each element. Hidden elements and unlabelled grouping containers are pruned. Use it to find UI elements by identity instead of by OCR text.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application (e.g., 'Safari', 'TextEdit')"
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "description": "How deep to descend into the tree (default: 30)"
+                    },
+                    "max_elements": {
+                        "type": "integer",
+                        "description": "Most elements to return after pruning (default: 300)"
+                    }
+                },
+                "required": ["app_name"]
+            }),
+        },
+        ToolSpec {
+            name: "macax_press_key",
+            version: 1,
+            group: ToolGroup::MacAx,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Press a keyboard key or shortcut in an application (e.g., Cmd+S to save)",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application"
+                    },
+                    "key": {
+                        "type": "string",
+                        "description": "Key to press (e.g., 's', 'return', 'tab')"
+                    },
+                    "modifiers": {
+                        "type": "array",
+                        "items": {
+                            "type": "string"
+                        },
+                        "description": "Modifier keys (e.g., ['command', 'shift'])"
+                    }
+                },
+                "required": ["app_name", "key"]
+            }),
+        },
+        ToolSpec {
+            name: "macax_type_text",
+            version: 1,
+            group: ToolGroup::MacAx,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Type arbitrary text into the currently focused element in an application (supports unicode, emojis, etc.)",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "Text to type (can include unicode, emojis, special characters)"
+                    }
+                },
+                "required": ["app_name", "text"]
+            }),
+        },
+        ToolSpec {
+            name: "extract_text_with_boxes",
+            version: 2,
+            group: ToolGroup::MacAx,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Extract all text from an image file with bounding box coordinates for each text element. Returns JSON array with text, position (x, y), size (width, height), and confidence for each detected text. Uses Apple Vision Framework for precise sub-pixel accuracy.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to image file to extract text from"
+                    },
+                    "app_name": {
+                        "type": "string",
+                        "description": "Optional: Name of application to screenshot first (e.g., 'Safari', 'Things3'). If provided, takes screenshot of app before extracting text."
+                    },
+                    "region": {
+                        "type": "object",
+                        "description": "Optional: Only read this part of the image, in image pixels. Coordinates returned are still those of the whole image.",
+                        "properties": {
+                            "x": {"type": "integer"},
+                            "y": {"type": "integer"},
+                            "width": {"type": "integer"},
+                            "height": {"type": "integer"}
+                        }
+                    }
+                },
+                "required": ["path"]
+            }),
+        },
+        ToolSpec {
+            name: "vision_find_text",
+            version: 2,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Find text in a specific application window and return its location with bounding box coordinates (x, y, width, height) and confidence score. Useful for locating UI elements. Uses Apple Vision Framework for precise sub-pixel accuracy.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application to search in (e.g., 'Things3', 'Safari', 'TextEdit')"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "The text to search for on screen"
+                    }
+                },
+                "required": ["app_name", "text"]
+            }),
+        },
+        ToolSpec {
+            name: "vision_click_text",
+            version: 2,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Find text in a specific application window and click on it (useful for clicking buttons, links, menu items)",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application (e.g., 'Things3', 'Safari', 'TextEdit')"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "The text to click on (e.g., 'Submit', 'OK', 'Cancel', '+')"
+                    }
+                },
+                "required": ["app_name", "text"]
+            }),
+        },
+        ToolSpec {
+            name: "vision_click_near_text",
+            version: 2,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Find text in a specific application window and click near it (useful for clicking text fields next to labels)",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application (e.g., 'Things3', 'Safari', 'TextEdit')"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "The label text to find (e.g., 'Name:', 'Email:', 'Task:')"
+                    },
+                    "direction": {
+                        "type": "string",
+                        "enum": ["right", "below", "left", "above"],
+                        "description": "Direction to click relative to the text (default: right)"
+                    },
+                    "distance": {
+                        "type": "integer",
+                        "description": "Distance in screen points from the text (default: 50)"
+                    }
+                },
+                "required": ["app_name", "text"]
+            }),
+        },
+        ToolSpec {
+            name: "vision_calibrate",
+            version: 1,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Check how screenshot positions map to the screen when clicks land in the wrong place, e.g. with several monitors or scaled (Retina) displays. Lists the displays with their positions and scale factors. Given an app and text visible in it, finds the text like vision_click_text, then reads the screen at the point a click would go to and reports whether the text is there. Nothing is clicked.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Optional: Name of the application showing the target text"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "Optional: Text known to be visible in the app, e.g. a button label"
+                    },
+                    "move_pointer": {
+                        "type": "boolean",
+                        "description": "Optional: Also move the mouse pointer to the click point so it can be seen (default: false)"
+                    }
+                }
+            }),
+        },
+        ToolSpec {
+            name: "vision_locate",
+            version: 2,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                network: true,
+                ..Capabilities::NONE
+            },
+            description: "Locate a UI element in an application window from a description (e.g. 'the blue Submit button', 'the gear icon in the toolbar') and return its bounding box. Works on icons and styled buttons that OCR can't read. Uses a vision model when one is configured ([models.vision_locate] or computer_control.vision_provider), otherwise falls back to OCR text matching.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Name of the application (e.g., 'Things3', 'Safari', 'TextEdit')"
+                    },
+                    "description": {
+                        "type": "string",
+                        "description": "Description of the element to find, including visual details like color, icon or position"
+                    },
+                    "text": {
+                        "type": "string",
+                        "description": "Optional: visible text of the element, matched with OCR if the vision model is unavailable or can't find it (default: the description)"
+                    }
+                },
+                "required": ["app_name", "description"]
+            }),
+        },
+        ToolSpec {
+            name: "mouse_click",
+            version: 1,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Click at screen coordinates. Coordinates for mouse_click, mouse_move, scroll and drag are global screen points with the origin at the top-left of the main display and y growing downward, the same space as macax_dump_elements frames (use the center of a frame to click an element). Screenshots of Retina displays are in pixels: divide by the display scale (usually 2).",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "x": {
+                        "type": "integer",
+                        "description": "Click x in screen points from the left edge of the main display"
+                    },
+                    "y": {
+                        "type": "integer",
+                        "description": "Click y in screen points from the top edge of the main display"
+                    },
+                    "button": {
+                        "type": "string",
+                        "enum": ["left", "right", "middle"],
+                        "description": "Mouse button (default: left)"
+                    },
+                    "clicks": {
+                        "type": "integer",
+                        "description": "Number of clicks, 2 for a double click (default: 1)"
+                    }
+                },
+                "required": ["x", "y"]
+            }),
+        },
+        ToolSpec {
+            name: "mouse_move",
+            version: 1,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Move the mouse pointer to screen coordinates (top-left origin, in points; see mouse_click), e.g. to reveal hover menus or tooltips",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "x": {
+                        "type": "integer",
+                        "description": "Target x in screen points from the left edge of the main display"
+                    },
+                    "y": {
+                        "type": "integer",
+                        "description": "Target y in screen points from the top edge of the main display"
+                    }
+                },
+                "required": ["x", "y"]
+            }),
+        },
+        ToolSpec {
+            name: "scroll",
+            version: 1,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Scroll the content under screen coordinates (top-left origin, in points; see mouse_click) by a number of lines",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "x": {
+                        "type": "integer",
+                        "description": "Pointer x in screen points from the left edge of the main display"
+                    },
+                    "y": {
+                        "type": "integer",
+                        "description": "Pointer y in screen points from the top edge of the main display"
+                    },
+                    "delta_y": {
+                        "type": "integer",
+                        "description": "Lines to scroll vertically; positive scrolls down, negative up"
+                    },
+                    "delta_x": {
+                        "type": "integer",
+                        "description": "Lines to scroll horizontally; positive scrolls right, negative left (default: 0)"
+                    }
+                },
+                "required": ["x", "y", "delta_y"]
+            }),
+        },
+        ToolSpec {
+            name: "drag",
+            version: 1,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Press the mouse at one point, move to another while holding it and release, e.g. to move windows, sliders or files (top-left origin, in points; see mouse_click)",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "from_x": {
+                        "type": "integer",
+                        "description": "Start x in screen points"
+                    },
+                    "from_y": {
+                        "type": "integer",
+                        "description": "Start y in screen points"
+                    },
+                    "to_x": {
+                        "type": "integer",
+                        "description": "End x in screen points"
+                    },
+                    "to_y": {
+                        "type": "integer",
+                        "description": "End y in screen points"
+                    },
+                    "button": {
+                        "type": "string",
+                        "enum": ["left", "right", "middle"],
+                        "description": "Mouse button (default: left)"
+                    }
+                },
+                "required": ["from_x", "from_y", "to_x", "to_y"]
+            }),
+        },
+        ToolSpec {
+            name: "start_recording",
+            version: 1,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Start recording the screen, or one app's window, to a video in the session's artifacts, e.g. to keep a record of a UI automation run. Stop it with stop_recording",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "app_name": {
+                        "type": "string",
+                        "description": "Only record the main window of this application (default: the whole screen)"
+                    },
+                    "max_seconds": {
+                        "type": "integer",
+                        "description": "Stop recording after this many seconds even if stop_recording is not called (default: 300)"
+                    }
+                },
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "stop_recording",
+            version: 1,
+            group: ToolGroup::ComputerControl,
+            capabilities: Capabilities {
+                desktop: true,
+                ..Capabilities::NONE
+            },
+            description: "Stop the recording started with start_recording and save the video as an artifact",
+            input_schema: json!({
+                "type": "object",
+                "properties": {},
+                "required": []
+            }),
+        },
+        ToolSpec {
+            name: "give_feedback",
+            version: 1,
+            group: ToolGroup::Coach,
+            capabilities: Capabilities::NONE,
+            description: "Deliver your review of the implementation and end your turn. Use this instead of final_output when reviewing.",
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "verdict": {
+                        "type": "string",
+                        "enum": ["approved", "changes_requested"],
+                        "description": "\"approved\" only if the implementation fully meets the requirements"
+                    },
+                    "issues": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Specific problems that must be fixed, one per item"
+                    },
+                    "next_steps": {
+                        "type": "string",
+                        "description": "Concise, actionable instructions for the next implementation turn"
+                    }
+                },
+                "required": ["verdict"]
+            }),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_registry() {
+        let registry = ToolRegistry::builtin();
+        let names: Vec<_> = registry.specs().iter().map(|s| s.name).collect();
+        let unique: std::collections::HashSet<_> = names.iter().collect();
+        assert_eq!(names.len(), unique.len(), "duplicate tool names");
+
+        assert!(registry.is_mutating("write_file"));
+        assert!(!registry.is_mutating("read_file"));
+        assert!(!registry.is_mutating("unknown_tool"));
+        assert_eq!(
+            registry.get("shell").unwrap().capabilities.names(),
+            vec!["executes"]
+        );
+
+        let core: Vec<_> = registry
+            .enabled(&[ToolGroup::Core])
+            .iter()
+            .map(|s| s.name)
+            .collect();
+        assert!(core.contains(&"read_file"));
+        assert!(!core.contains(&"webdriver_start"));
+        assert!(!core.contains(&"give_feedback"));
+
+        let reference = prompt_reference(registry.get("write_file"));
+        assert!(reference.contains("- **write_file** (v2) [mutating]: Write content"));
+
+        let entry = AuditEntry::new("read_file", true, std::time::Duration::from_millis(12));
+        assert_eq!(
+            entry.version,
+            Some(registry.get("read_file").unwrap().version)
+        );
+        assert!(entry.capabilities.is_empty());
+    }
+
+    #[test]
+    fn test_validate_arguments() {
+        let registry = ToolRegistry::builtin();
+        assert!(registry
+            .validate("read_file", &json!({"file_path": "src/lib.rs", "start": 0}))
+            .is_ok());
+        assert!(registry.validate("todo_read", &Value::Null).is_ok());
+        assert!(registry.validate("unity_catalog_fn", &json!(42)).is_ok());
+
+        let invalid = registry
+            .validate("read_file", &json!({"path": "src/lib.rs", "start": "10"}))
+            .unwrap_err();
+        assert_eq!(invalid.tool, "read_file");
+        assert_eq!(
+            invalid.problems,
+            vec![
+                "`start`: \"10\" is not of type \"integer\"",
+                "missing required argument `file_path`",
+            ]
+        );
+        assert!(invalid
+            .expected
+            .starts_with("file_path (string, required), end (integer), "));
+
+        let invalid = registry
+            .validate(
+                "code_search",
+                &json!({"searches": [{"name": "fns", "query": "(function_item)"}]}),
+            )
+            .unwrap_err();
+        assert_eq!(invalid.problems, vec!["`searches[0]`: missing `language`"]);
+        assert!(invalid.to_string().starts_with(
+            "Invalid arguments for code_search:\n- `searches[0]`: missing `language`\n"
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+//! Tool results too large to add to the context in full.
+//!
+//! A result over its `[context.tool_results]` token limit is saved as an artifact when it
+//! arrives, and the context gets its start and end (or a summary of it) with the path of
+//! the artifact, so a single large `read_file` or search can't fill the context before
+//! thinning gets a chance to act.
+
+use std::path::Path;
+
+/// Share of the limit given to the start of a result; the rest goes to its end
+const HEAD_SHARE: usize = 60;
+
+/// Whether `text` could be over `max_tokens`. Tokens are at least a byte long, so shorter
+/// text never needs counting.
+pub fn may_exceed(text: &str, max_tokens: u32) -> bool {
+    max_tokens > 0 && text.len() > max_tokens as usize
+}
+
+/// `result`, of `total_tokens`, cut to about `max_tokens`: its first and last lines with a
+/// note in between of what was left out and where all of it is
+pub fn head_tail(result: &str, total_tokens: u32, max_tokens: u32, path: &Path) -> String {
+    let budget = result.len() * max_tokens as usize / total_tokens.max(1) as usize;
+    let head_end = line_end_before(result, budget * HEAD_SHARE / 100);
+    let tail_start = line_start_after(result, result.len() - budget * (100 - HEAD_SHARE) / 100)
+        .max(head_end);
+    format!(
+        "{}\n\n[... about {} tokens left out. The full result, {} lines, is in {} (search it with grep -n, or read parts of it with sed -n).]\n\n{}",
+        result[..head_end].trim_end(),
+        total_tokens.saturating_sub(max_tokens),
+        result.lines().count(),
+        path.display(),
+        result[tail_start..].trim_start_matches('\n')
+    )
+}
+
+/// A summary standing in for a result of `total_tokens`
+pub fn summarized(summary: &str, total_tokens: u32, path: &Path) -> String {
+    format!(
+        "[Summary of a result of about {} tokens, too large to show in full. The full result is in {}.]\n\n{}",
+        total_tokens,
+        path.display(),
+        summary.trim()
+    )
+}
+
+/// The end of the last whole line before `index`, or `index` if the first line is longer
+fn line_end_before(text: &str, index: usize) -> usize {
+    let index = floor_char_boundary(text, index);
+    match text[..index].rfind('\n') {
+        Some(newline) if newline > index / 2 => newline + 1,
+        _ => index,
+    }
+}
+
+/// The start of the first whole line after `index`, or `index` if the last line is longer
+fn line_start_after(text: &str, index: usize) -> usize {
+    let index = floor_char_boundary(text, index);
+    let rest = text.len() - index;
+    match text[index..].find('\n') {
+        Some(newline) if newline < rest / 2 => index + newline + 1,
+        _ => index,
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_head_tail() {
+        let path = Path::new("/tmp/result.txt");
+        let result: String = (0..1000).map(|i| format!("line {}\n", i)).collect();
+        assert!(may_exceed(&result, 100));
+        assert!(!may_exceed(&result, 0));
+        assert!(!may_exceed("short", 100));
+
+        let cut = head_tail(&result, 4000, 400, path);
+        assert!(cut.starts_with("line 0\nline 1\n"));
+        assert!(cut.ends_with("line 999\n"));
+        assert!(cut.contains("about 3600 tokens left out. The full result, 1000 lines,"));
+        assert!(cut.contains("/tmp/result.txt"));
+        // Only whole lines are kept
+        assert!(cut
+            .lines()
+            .all(|line| line.is_empty() || line.starts_with("line ") || line.starts_with("[...")));
+        let kept = cut.lines().filter(|line| line.starts_with("line ")).count();
+        assert!((80..=110).contains(&kept), "{}", kept);
+
+        // A single long line is cut between characters
+        let long = "日本".repeat(2000);
+        let cut = head_tail(&long, 4000, 400, path);
+        assert!(cut.starts_with("日本"));
+        assert!(cut.contains("1 lines"));
+        assert!(cut.len() < long.len() / 5);
+
+        let summary = summarized("  It printed 1000 lines.\n", 4000, path);
+        assert!(summary.ends_with("\n\nIt printed 1000 lines."));
+    }
+}
@@ -0,0 +1,735 @@
+//! Computer control tools: screenshots, OCR, mouse and keyboard actions, screen recording
+//! and the `vision_*` tools that find text on screen and click it.
+
+use super::ToolHandlers;
+use crate::artifacts::ArtifactKind;
+use crate::ui_writer::UiWriter;
+use crate::{ocr, Agent, ToolCall};
+use anyhow::Result;
+use futures_util::FutureExt;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("take_screenshot", |agent, call| {
+        agent.run_take_screenshot(call).boxed()
+    });
+    handlers.register("extract_text", |agent, call| {
+        agent.run_extract_text(call).boxed()
+    });
+    handlers.register("vision_find_text", |agent, call| {
+        agent.run_vision_find_text(call).boxed()
+    });
+    handlers.register("mouse_click", |agent, call| agent.run_mouse(call).boxed());
+    handlers.register("mouse_move", |agent, call| agent.run_mouse(call).boxed());
+    handlers.register("scroll", |agent, call| agent.run_mouse(call).boxed());
+    handlers.register("drag", |agent, call| agent.run_mouse(call).boxed());
+    handlers.register("start_recording", |agent, call| {
+        agent.run_start_recording(call).boxed()
+    });
+    handlers.register("stop_recording", |agent, _| {
+        agent.run_stop_recording().boxed()
+    });
+    handlers.register("vision_click_text", |agent, call| {
+        agent.run_vision_click_text(call).boxed()
+    });
+    handlers.register("extract_text_with_boxes", |agent, call| {
+        agent.run_extract_text_with_boxes(call).boxed()
+    });
+    handlers.register("vision_click_near_text", |agent, call| {
+        agent.run_vision_click_near_text(call).boxed()
+    });
+    handlers.register("vision_calibrate", |agent, call| {
+        agent.run_vision_calibrate(call).boxed()
+    });
+    handlers.register("vision_locate", |agent, call| {
+        agent.run_vision_locate(call).boxed()
+    });
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_take_screenshot(&self, tool_call: &ToolCall) -> Result<String> {
+        if let Some(controller) = &self.computer_controller {
+            let path = tool_call
+                .args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing path argument"))?;
+
+            // Extract window_id (app name) - REQUIRED
+            let window_id = tool_call.args.get("window_id").and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing window_id argument. You must specify which window to capture (e.g., 'Safari', 'Terminal', 'Google Chrome')."))?;
+
+            // Extract region if provided
+            let region = tool_call
+                .args
+                .get("region")
+                .and_then(|v| v.as_object())
+                .map(|region_obj| g3_computer_control::types::Rect {
+                    x: region_obj.get("x").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
+                    y: region_obj.get("y").and_then(|v| v.as_i64()).unwrap_or(0) as i32,
+                    width: region_obj
+                        .get("width")
+                        .and_then(|v| v.as_i64())
+                        .unwrap_or(0) as i32,
+                    height: region_obj
+                        .get("height")
+                        .and_then(|v| v.as_i64())
+                        .unwrap_or(0) as i32,
+                });
+
+            // Relative paths go to the session's artifact directory
+            let is_artifact = !path.starts_with('/');
+            let actual_path = if is_artifact {
+                match self.artifacts().path_for(path) {
+                    Ok(artifact_path) => artifact_path.display().to_string(),
+                    Err(e) => return Ok(format!("❌ Failed to take screenshot: {}", e)),
+                }
+            } else {
+                path.to_string()
+            };
+
+            match controller
+                .take_screenshot(&actual_path, region, Some(window_id))
+                .await
+            {
+                Ok(_) => {
+                    if is_artifact {
+                        if let Err(e) = self.artifacts().register(
+                            ArtifactKind::Screenshot,
+                            std::path::Path::new(&actual_path),
+                            Some("take_screenshot"),
+                        ) {
+                            warn!("Failed to record screenshot artifact: {}", e);
+                        }
+                    }
+
+                    Ok(format!(
+                        "✅ Screenshot of {} saved to: {}",
+                        window_id, actual_path
+                    ))
+                }
+                Err(e) => Ok(format!("❌ Failed to take screenshot: {}", e)),
+            }
+        } else {
+            Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            )
+        }
+    }
+
+    async fn run_extract_text(&self, tool_call: &ToolCall) -> Result<String> {
+        if let Some(controller) = &self.computer_controller {
+            let path = tool_call
+                .args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing path argument"))?;
+
+            // Extract text from image file only
+            let region = ocr::region_arg(&tool_call.args);
+            match self.ocr.extract_text(controller, path, region).await {
+                Ok(text) => Ok(format!("✅ Extracted text:\n{}", text)),
+                Err(e) => Ok(format!("❌ Failed to extract text: {}", e)),
+            }
+        } else {
+            Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            )
+        }
+    }
+
+    async fn run_vision_find_text(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing vision_find_text tool call");
+
+        if let Some(controller) = &self.computer_controller {
+            let app_name = tool_call
+                .args
+                .get("app_name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing app_name parameter"))?;
+
+            let text = tool_call
+                .args
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing text parameter"))?;
+
+            match controller.find_text_in_app(app_name, text).await {
+                Ok(Some(location)) => Ok(format!(
+                    "✅ Found '{}' in {} at position ({}, {}) with size {}x{} (confidence: {:.0}%)",
+                    location.text,
+                    app_name,
+                    location.x,
+                    location.y,
+                    location.width,
+                    location.height,
+                    location.confidence * 100.0
+                )),
+                Ok(None) => Ok(format!("❌ Could not find '{}' in {}", text, app_name)),
+                Err(e) => Ok(format!("❌ Error finding text: {}", e)),
+            }
+        } else {
+            Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            )
+        }
+    }
+
+    async fn run_mouse(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing {} tool call", tool_call.tool);
+
+        let Some(controller) = &self.computer_controller else {
+            return Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            );
+        };
+        let coordinate = |name: &str| {
+            tool_call
+                .args
+                .get(name)
+                .and_then(|v| v.as_i64())
+                .map(|v| v as i32)
+                .ok_or_else(|| format!("❌ Missing {} argument", name))
+        };
+        let button = match tool_call.args.get("button").and_then(|v| v.as_str()) {
+            None => g3_computer_control::types::MouseButton::Left,
+            Some(name) => match g3_computer_control::types::MouseButton::parse(name) {
+                Some(button) => button,
+                None => return Ok(format!("❌ Unknown mouse button '{}'", name)),
+            },
+        };
+
+        let outcome = match tool_call.tool.as_str() {
+            "mouse_click" => {
+                let clicks = tool_call
+                    .args
+                    .get("clicks")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(1) as u32;
+                match (coordinate("x"), coordinate("y")) {
+                    (Ok(x), Ok(y)) => controller
+                        .mouse_click(x, y, button, clicks)
+                        .map(|_| format!("✅ Clicked at ({}, {})", x, y)),
+                    (Err(msg), _) | (_, Err(msg)) => return Ok(msg),
+                }
+            }
+            "mouse_move" => match (coordinate("x"), coordinate("y")) {
+                (Ok(x), Ok(y)) => controller
+                    .move_mouse(x, y)
+                    .map(|_| format!("✅ Moved mouse to ({}, {})", x, y)),
+                (Err(msg), _) | (_, Err(msg)) => return Ok(msg),
+            },
+            "scroll" => {
+                let delta_x = coordinate("delta_x").unwrap_or(0);
+                match (coordinate("x"), coordinate("y"), coordinate("delta_y")) {
+                    (Ok(x), Ok(y), Ok(delta_y)) => {
+                        controller.scroll(x, y, delta_x, delta_y).map(|_| {
+                            format!(
+                                "✅ Scrolled ({}, {}) lines at ({}, {})",
+                                delta_x, delta_y, x, y
+                            )
+                        })
+                    }
+                    (Err(msg), _, _) | (_, Err(msg), _) | (_, _, Err(msg)) => return Ok(msg),
+                }
+            }
+            _ => match (
+                coordinate("from_x"),
+                coordinate("from_y"),
+                coordinate("to_x"),
+                coordinate("to_y"),
+            ) {
+                (Ok(from_x), Ok(from_y), Ok(to_x), Ok(to_y)) => controller
+                    .drag((from_x, from_y), (to_x, to_y), button)
+                    .map(|_| {
+                        format!(
+                            "✅ Dragged from ({}, {}) to ({}, {})",
+                            from_x, from_y, to_x, to_y
+                        )
+                    }),
+                (Err(msg), _, _, _)
+                | (_, Err(msg), _, _)
+                | (_, _, Err(msg), _)
+                | (_, _, _, Err(msg)) => return Ok(msg),
+            },
+        };
+        match outcome {
+            Ok(message) => Ok(message),
+            Err(e) => Ok(format!("❌ {} failed: {}", tool_call.tool, e)),
+        }
+    }
+
+    async fn run_start_recording(&self, tool_call: &ToolCall) -> Result<String> {
+        let Some(controller) = &self.computer_controller else {
+            return Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            );
+        };
+        let mut recording = self.recording.lock().await;
+        if let Some(current) = recording.as_ref() {
+            return Ok(format!(
+                "❌ Already recording to {} ({}s so far). Call stop_recording first.",
+                current.path().display(),
+                current.elapsed().as_secs()
+            ));
+        }
+
+        let app_name = tool_call.args.get("app_name").and_then(|v| v.as_str());
+        let max_seconds = tool_call
+            .args
+            .get("max_seconds")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(300);
+        let path = match self.artifacts().path_for("recording.mov") {
+            Ok(path) => path,
+            Err(e) => return Ok(format!("❌ Failed to start recording: {}", e)),
+        };
+        match controller.start_recording(&path, app_name, Duration::from_secs(max_seconds)) {
+            Ok(started) => {
+                *recording = Some(started);
+                Ok(format!(
+                    "✅ Recording {} to {} (stops after {}s at the latest)",
+                    app_name.unwrap_or("the screen"),
+                    path.display(),
+                    max_seconds
+                ))
+            }
+            Err(e) => Ok(format!("❌ Failed to start recording: {}", e)),
+        }
+    }
+
+    async fn run_stop_recording(&self) -> Result<String> {
+        let Some(recording) = self.recording.lock().await.take() else {
+            return Ok("❌ No recording in progress. Start one with start_recording.".to_string());
+        };
+        match recording.stop().await {
+            Ok(info) => {
+                if let Err(e) = self.artifacts().register(
+                    ArtifactKind::Recording,
+                    &info.path,
+                    Some("start_recording"),
+                ) {
+                    warn!("Failed to record recording artifact: {}", e);
+                }
+                Ok(format!(
+                    "✅ Recording saved to {} ({}s, {} KB)",
+                    info.path.display(),
+                    info.duration.as_secs(),
+                    info.size / 1024
+                ))
+            }
+            Err(e) => Ok(format!("❌ Failed to stop recording: {}", e)),
+        }
+    }
+
+    async fn run_vision_click_text(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing vision_click_text tool call");
+
+        if let Some(controller) = &self.computer_controller {
+            let app_name = tool_call
+                .args
+                .get("app_name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing app_name parameter"))?;
+
+            let text = tool_call
+                .args
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing text parameter"))?;
+
+            match controller.find_text_in_app(app_name, text).await {
+                Ok(Some(location)) => {
+                    // Click on center of text. The location is in global screen
+                    // points, already converted from screenshot pixels for the
+                    // display the window is on.
+                    if location.width == 0 || location.height == 0 {
+                        return Ok(format!(
+                            "❌ Invalid bounding box dimensions: width={}, height={}",
+                            location.width, location.height
+                        ));
+                    }
+
+                    debug!("[vision_click_text] Location from find_text_in_app: x={}, y={}, width={}, height={}, text='{}'",
+                        location.x, location.y, location.width, location.height, location.text);
+
+                    let (click_x, click_y) = g3_computer_control::display::center(&location);
+
+                    match controller.click_at(click_x, click_y, Some(app_name)) {
+                        Ok(_) => Ok(format!(
+                            "✅ Clicked on '{}' in {} at ({}, {})",
+                            text, app_name, click_x, click_y
+                        )),
+                        Err(e) => Ok(format!("❌ Failed to click: {}", e)),
+                    }
+                }
+                Ok(None) => Ok(format!("❌ Could not find '{}' in {}", text, app_name)),
+                Err(e) => Ok(format!("❌ Error finding text: {}", e)),
+            }
+        } else {
+            Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            )
+        }
+    }
+
+    async fn run_extract_text_with_boxes(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing extract_text_with_boxes tool call");
+
+        if !self.config.macax.enabled {
+            return Ok(
+                "❌ extract_text_with_boxes requires --macax flag to be enabled".to_string(),
+            );
+        }
+
+        if let Some(controller) = &self.computer_controller {
+            let path = tool_call
+                .args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing path parameter"))?;
+
+            // Optional: take screenshot of app first
+            let final_path =
+                if let Some(app_name) = tool_call.args.get("app_name").and_then(|v| v.as_str()) {
+                    let temp_path = match self.artifacts().path_for("extract_boxes.png") {
+                        Ok(path) => path.display().to_string(),
+                        Err(e) => return Ok(format!("❌ Failed to take screenshot: {}", e)),
+                    };
+                    match controller
+                        .take_screenshot(&temp_path, None, Some(app_name))
+                        .await
+                    {
+                        Ok(_) => temp_path,
+                        Err(e) => return Ok(format!("❌ Failed to take screenshot: {}", e)),
+                    }
+                } else {
+                    path.to_string()
+                };
+
+            // Extract text with locations
+            let region = ocr::region_arg(&tool_call.args);
+            let extracted = self
+                .ocr
+                .extract_text_with_locations(controller, &final_path, region)
+                .await;
+            match extracted {
+                Ok(locations) => {
+                    // Clean up temp file if we created one
+                    if final_path != path {
+                        let _ = std::fs::remove_file(&final_path);
+                    }
+
+                    // Return as JSON
+                    match serde_json::to_string_pretty(&locations) {
+                        Ok(json) => Ok(format!(
+                            "✅ Extracted {} text elements:\n{}",
+                            locations.len(),
+                            json
+                        )),
+                        Err(e) => Ok(format!("❌ Failed to serialize results: {}", e)),
+                    }
+                }
+                Err(e) => Ok(format!("❌ Failed to extract text: {}", e)),
+            }
+        } else {
+            Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            )
+        }
+    }
+
+    async fn run_vision_click_near_text(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing vision_click_near_text tool call");
+
+        if let Some(controller) = &self.computer_controller {
+            let app_name = tool_call
+                .args
+                .get("app_name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing app_name parameter"))?;
+
+            let text = tool_call
+                .args
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing text parameter"))?;
+
+            let direction = tool_call
+                .args
+                .get("direction")
+                .and_then(|v| v.as_str())
+                .unwrap_or("right");
+
+            let distance = tool_call
+                .args
+                .get("distance")
+                .and_then(|v| v.as_i64())
+                .unwrap_or(50) as i32;
+
+            match controller.find_text_in_app(app_name, text).await {
+                Ok(Some(location)) => {
+                    // Calculate click position based on direction, in global screen
+                    // points (y grows downward)
+                    let (center_x, center_y) = g3_computer_control::display::center(&location);
+                    let (click_x, click_y) = match direction {
+                        "below" => (center_x, location.y + location.height + distance),
+                        "left" => (location.x - distance, center_y),
+                        "above" => (center_x, location.y - distance),
+                        _ => (location.x + location.width + distance, center_y),
+                    };
+                    debug!(
+                        "[vision_click_near_text] Clicking {} of text at ({}, {})",
+                        direction, click_x, click_y
+                    );
+
+                    match controller.click_at(click_x, click_y, Some(app_name)) {
+                        Ok(_) => Ok(format!(
+                            "✅ Clicked {} of '{}' in {} at ({}, {})",
+                            direction, text, app_name, click_x, click_y
+                        )),
+                        Err(e) => Ok(format!("❌ Failed to click: {}", e)),
+                    }
+                }
+                Ok(None) => Ok(format!("❌ Could not find '{}' in {}", text, app_name)),
+                Err(e) => Ok(format!("❌ Error finding text: {}", e)),
+            }
+        } else {
+            Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            )
+        }
+    }
+
+    async fn run_vision_calibrate(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing vision_calibrate tool call");
+
+        let Some(controller) = &self.computer_controller else {
+            return Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            );
+        };
+        let app_name = tool_call.args.get("app_name").and_then(|v| v.as_str());
+        let text = tool_call.args.get("text").and_then(|v| v.as_str());
+        let target = match (app_name, text) {
+            (Some(app_name), Some(text)) => Some((app_name, text)),
+            (None, None) => None,
+            _ => return Ok("❌ Pass both app_name and text, or neither".to_string()),
+        };
+        let move_pointer = tool_call
+            .args
+            .get("move_pointer")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        match self
+            .vision_calibrate(controller, target, move_pointer)
+            .await
+        {
+            Ok(report) => Ok(report),
+            Err(e) => Ok(format!("❌ Calibration failed: {}", e)),
+        }
+    }
+
+    async fn run_vision_locate(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing vision_locate tool call");
+
+        if let Some(controller) = &self.computer_controller {
+            let app_name = tool_call
+                .args
+                .get("app_name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing app_name parameter"))?;
+
+            let description = tool_call
+                .args
+                .get("description")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing description parameter"))?;
+
+            let text = tool_call
+                .args
+                .get("text")
+                .and_then(|v| v.as_str())
+                .unwrap_or(description);
+
+            // Only send screenshots to a model when one is explicitly configured
+            let mut vision_note = String::new();
+            if self.providers.contains(g3_config::VISION_LOCATE_MODEL) {
+                match self
+                    .vision_locate(controller.as_ref(), app_name, description)
+                    .await
+                {
+                    Ok(Some(location)) => {
+                        return Ok(format!(
+                            "✅ Located '{}' in {} at position ({}, {}) with size {}x{} (vision model)",
+                            description,
+                            app_name,
+                            location.x,
+                            location.y,
+                            location.width,
+                            location.height
+                        ));
+                    }
+                    Ok(None) => {
+                        vision_note = "vision model did not find it; ".to_string();
+                    }
+                    Err(e) => {
+                        warn!("vision_locate failed, falling back to OCR: {}", e);
+                        vision_note = format!("vision model failed ({}); ", e);
+                    }
+                }
+            }
+
+            match controller.find_text_in_app(app_name, text).await {
+                Ok(Some(location)) => Ok(format!(
+                    "✅ Located '{}' in {} at position ({}, {}) with size {}x{} (OCR, confidence: {:.0}%)",
+                    location.text,
+                    app_name,
+                    location.x,
+                    location.y,
+                    location.width,
+                    location.height,
+                    location.confidence * 100.0
+                )),
+                Ok(None) => Ok(format!(
+                    "❌ Could not locate '{}' in {} ({}OCR found no match for '{}')",
+                    description, app_name, vision_note, text
+                )),
+                Err(e) => Ok(format!("❌ Error locating element: {}{}", vision_note, e)),
+            }
+        } else {
+            Ok(
+                "❌ Computer control not enabled. Set computer_control.enabled = true in config."
+                    .to_string(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_provider::{agent, ScriptedProvider};
+    use crate::ToolCall;
+    use anyhow::Result;
+    use g3_computer_control::types::{MouseButton, Rect, TextLocation};
+    use g3_computer_control::ComputerController;
+    use g3_config::Config;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    /// Records the mouse actions it is asked for
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    #[async_trait::async_trait]
+    impl ComputerController for Recorder {
+        async fn take_screenshot(&self, _: &str, _: Option<Rect>, _: Option<&str>) -> Result<()> {
+            unimplemented!()
+        }
+        async fn extract_text_from_screen(&self, _: Rect, _: &str) -> Result<String> {
+            unimplemented!()
+        }
+        async fn extract_text_from_image(&self, _: &str) -> Result<String> {
+            unimplemented!()
+        }
+        async fn extract_text_with_locations(&self, _: &str) -> Result<Vec<TextLocation>> {
+            unimplemented!()
+        }
+        async fn find_text_in_app(&self, _: &str, _: &str) -> Result<Option<TextLocation>> {
+            unimplemented!()
+        }
+        fn move_mouse(&self, x: i32, y: i32) -> Result<()> {
+            self.0.lock().unwrap().push(format!("move {} {}", x, y));
+            Ok(())
+        }
+        fn click_at(&self, _: i32, _: i32, _: Option<&str>) -> Result<()> {
+            unimplemented!()
+        }
+        fn mouse_click(&self, x: i32, y: i32, button: MouseButton, clicks: u32) -> Result<()> {
+            let action = format!("click {} {} {:?} x{}", x, y, button, clicks);
+            self.0.lock().unwrap().push(action);
+            Ok(())
+        }
+        fn scroll(&self, x: i32, y: i32, delta_x: i32, delta_y: i32) -> Result<()> {
+            let action = format!("scroll {} {} by {} {}", x, y, delta_x, delta_y);
+            self.0.lock().unwrap().push(action);
+            Ok(())
+        }
+        fn drag(&self, from: (i32, i32), to: (i32, i32), button: MouseButton) -> Result<()> {
+            let action = format!("drag {:?} {:?} {:?}", from, to, button);
+            self.0.lock().unwrap().push(action);
+            Ok(())
+        }
+    }
+
+    fn call(tool: &str, args: Value) -> ToolCall {
+        ToolCall {
+            tool: tool.to_string(),
+            args,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mouse_tools_drive_the_controller() {
+        let recorder = Arc::new(Recorder::default());
+        let (mut agent, _artifacts) =
+            agent(ScriptedProvider::new(Vec::new()), Config::default()).await;
+        agent.computer_controller = Some(recorder.clone());
+
+        let click = call(
+            "mouse_click",
+            json!({"x": 10, "y": 20, "button": "right", "clicks": 2}),
+        );
+        assert_eq!(
+            agent.run_mouse(&click).await.unwrap(),
+            "✅ Clicked at (10, 20)"
+        );
+        let scroll = call("scroll", json!({"x": 1, "y": 2, "delta_y": -3}));
+        agent.run_mouse(&scroll).await.unwrap();
+        let drag = call(
+            "drag",
+            json!({"from_x": 0, "from_y": 0, "to_x": 50, "to_y": 60}),
+        );
+        agent.run_mouse(&drag).await.unwrap();
+        agent
+            .run_mouse(&call("mouse_move", json!({"x": 5, "y": 6})))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "click 10 20 Right x2",
+                "scroll 1 2 by 0 -3",
+                "drag (0, 0) (50, 60) Left",
+                "move 5 6",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mouse_tools_reject_bad_arguments() {
+        let recorder = Arc::new(Recorder::default());
+        let (mut agent, _artifacts) =
+            agent(ScriptedProvider::new(Vec::new()), Config::default()).await;
+        agent.computer_controller = Some(recorder.clone());
+
+        let missing = call("drag", json!({"from_x": 0, "from_y": 0, "to_x": 5}));
+        let result = agent.run_mouse(&missing).await.unwrap();
+        assert_eq!(result, "❌ Missing to_y argument");
+        let button = call("mouse_click", json!({"x": 1, "y": 1, "button": "side"}));
+        let result = agent.run_mouse(&button).await.unwrap();
+        assert_eq!(result, "❌ Unknown mouse button 'side'");
+        assert!(recorder.0.lock().unwrap().is_empty());
+    }
+}
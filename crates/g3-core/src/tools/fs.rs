@@ -0,0 +1,493 @@
+//! File tools: `read_file`, `write_file`, `str_replace`, `edit_symbol`, `show_changes` and
+//! `undo_edit`. Locks, undo snapshots and syntax checks around the edits are
+//! `Agent::execute_tool`'s.
+
+use super::ToolHandlers;
+use crate::ui_writer::UiWriter;
+use crate::{
+    apply_unified_diff_to_string, binary_file, code_search, line_range_to_char_range, number_lines,
+    Agent, ToolCall,
+};
+use anyhow::Result;
+use futures_util::FutureExt;
+use tracing::debug;
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("read_file", |agent, call| agent.run_read_file(call).boxed());
+    handlers.register("write_file", |agent, call| {
+        agent.run_write_file(call).boxed()
+    });
+    handlers.register("str_replace", |agent, call| {
+        agent.run_str_replace(call).boxed()
+    });
+    handlers.register("edit_symbol", |agent, call| {
+        agent.run_edit_symbol(call).boxed()
+    });
+    handlers.register("show_changes", |agent, call| {
+        agent.run_show_changes(call).boxed()
+    });
+    handlers.register("undo_edit", |agent, call| agent.run_undo_edit(call).boxed());
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_read_file(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing read_file tool call");
+        if let Some(file_path) = tool_call.args.get("file_path") {
+            if let Some(path_str) = file_path.as_str() {
+                // Expand tilde (~) to home directory
+                let expanded_path = self.expand_tilde(path_str);
+                let path_str = expanded_path.as_ref();
+
+                // Hex dump of a byte range, for binary files
+                if tool_call
+                    .args
+                    .get("read_binary")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+                {
+                    if self.remote.is_some() {
+                        return Ok("❌ read_binary only reads local files; use the shell \
+                                   tool with xxd for files on the remote host"
+                            .to_string());
+                    }
+                    let start = tool_call.args.get("start").and_then(|v| v.as_u64());
+                    let end = tool_call.args.get("end").and_then(|v| v.as_u64());
+                    return Ok(match binary_file::read_range(path_str, start, end) {
+                        Ok(dump) => dump,
+                        Err(msg) => format!("❌ {}", msg),
+                    });
+                }
+
+                // Check if this is an image file
+                let is_image = path_str.to_lowercase().ends_with(".png")
+                    || path_str.to_lowercase().ends_with(".jpg")
+                    || path_str.to_lowercase().ends_with(".jpeg")
+                    || path_str.to_lowercase().ends_with(".gif")
+                    || path_str.to_lowercase().ends_with(".bmp")
+                    || path_str.to_lowercase().ends_with(".tiff")
+                    || path_str.to_lowercase().ends_with(".tif")
+                    || path_str.to_lowercase().ends_with(".webp");
+
+                // If it's an image file, use OCR via extract_text
+                if is_image && self.remote.is_none() {
+                    if let Some(controller) = &self.computer_controller {
+                        match self.ocr.extract_text(controller, path_str, None).await {
+                            Ok(text) => {
+                                return Ok(format!("📄 Image file (OCR extracted):\n{}", text));
+                            }
+                            Err(e) => {
+                                return Ok(format!(
+                                    "❌ Failed to extract text from image '{}': {}",
+                                    path_str, e
+                                ))
+                            }
+                        }
+                    } else {
+                        return Ok(match std::fs::read(path_str) {
+                            Ok(bytes) => format!(
+                                "{}\n\nComputer control is not enabled, so text was not extracted with OCR. Set computer_control.enabled = true in config to enable it.",
+                                binary_file::describe(path_str, &bytes)
+                            ),
+                            Err(e) => format!("❌ Failed to read file '{}': {}", path_str, e),
+                        });
+                    }
+                }
+
+                // Extract optional start and end positions
+                let start_char = tool_call
+                    .args
+                    .get("start")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize);
+                let end_char = tool_call
+                    .args
+                    .get("end")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize);
+
+                let start_line = tool_call
+                    .args
+                    .get("start_line")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize);
+                let end_line = tool_call
+                    .args
+                    .get("end_line")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize);
+                let show_line_numbers = tool_call
+                    .args
+                    .get("line_numbers")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let show_outline = tool_call
+                    .args
+                    .get("outline")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let exact = tool_call
+                    .args
+                    .get("exact")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+
+                debug!(
+                    "Reading file: {}, start={:?}, end={:?}, start_line={:?}, end_line={:?}",
+                    path_str, start_char, end_char, start_line, end_line
+                );
+
+                let (content, encoding_note) = match self.read_file_bytes(path_str).await {
+                    Ok(bytes) if binary_file::is_binary(&bytes) => {
+                        return Ok(binary_file::describe(path_str, &bytes));
+                    }
+                    Ok(bytes) => match String::from_utf8(bytes) {
+                        Ok(content) => (content, None),
+                        Err(e) => (
+                            String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                            Some("⚠️ File is not valid UTF-8; invalid bytes are shown as �"),
+                        ),
+                    },
+                    Err(e) => return Ok(format!("❌ Failed to read file '{}': {}", path_str, e)),
+                };
+
+                // Line ranges are translated into character ranges
+                let (start_char, end_char) = if start_char.is_none()
+                    && end_char.is_none()
+                    && (start_line.is_some() || end_line.is_some())
+                {
+                    match line_range_to_char_range(&content, start_line, end_line) {
+                        Ok((start, end)) => (Some(start), Some(end)),
+                        Err(msg) => return Ok(format!("❌ {}", msg)),
+                    }
+                } else {
+                    (start_char, end_char)
+                };
+
+                // Validate and apply range if specified
+                let start = start_char.unwrap_or(0);
+                let end = end_char.unwrap_or(content.len());
+
+                // Validation
+                if start > content.len() {
+                    return Ok(format!(
+                        "❌ Start position {} exceeds file length {}",
+                        start,
+                        content.len()
+                    ));
+                }
+                if end > content.len() {
+                    return Ok(format!(
+                        "❌ End position {} exceeds file length {}",
+                        end,
+                        content.len()
+                    ));
+                }
+                if start > end {
+                    return Ok(format!(
+                        "❌ Start position {} is greater than end position {}",
+                        start, end
+                    ));
+                }
+
+                // Extract the requested portion, ensuring we're at char boundaries
+                // Find the nearest valid char boundaries
+                let start_boundary = if start == 0 {
+                    0
+                } else {
+                    content
+                        .char_indices()
+                        .find(|(i, _)| *i >= start)
+                        .map(|(i, _)| i)
+                        .unwrap_or(start)
+                };
+                let end_boundary = content
+                    .char_indices()
+                    .find(|(i, _)| *i >= end)
+                    .map(|(i, _)| i)
+                    .unwrap_or(content.len());
+
+                // Partial reads of source files are widened or narrowed so they
+                // don't cut through functions and types
+                let mut chunk_summary = None;
+                let partial = start_char.is_some() || end_char.is_some();
+                let (start_boundary, end_boundary) =
+                    if partial && !exact && end_boundary > start_boundary {
+                        match code_search::outline::outline_source(
+                            std::path::Path::new(path_str),
+                            &content,
+                        ) {
+                            Ok(Some(symbols)) => {
+                                let lines: Vec<&str> = content.lines().collect();
+                                let first_line =
+                                    content[..start_boundary].matches('\n').count() + 1;
+                                let read = &content[..end_boundary];
+                                let last_line =
+                                    read.matches('\n').count() + usize::from(!read.ends_with('\n'));
+                                let chunk = code_search::chunk::snap_range(
+                                    &symbols, &lines, first_line, last_line,
+                                );
+                                chunk_summary =
+                                    Some(code_search::chunk::format_chunk(&chunk, lines.len()));
+                                line_range_to_char_range(
+                                    &content,
+                                    Some(chunk.start_line),
+                                    Some(chunk.end_line),
+                                )
+                                .unwrap_or((start_boundary, end_boundary))
+                            }
+                            Ok(None) => (start_boundary, end_boundary),
+                            Err(e) => {
+                                debug!("Not adjusting range of {}: {}", path_str, e);
+                                (start_boundary, end_boundary)
+                            }
+                        }
+                    } else {
+                        (start_boundary, end_boundary)
+                    };
+
+                let partial_content = &content[start_boundary..end_boundary];
+                let line_count = partial_content.lines().count();
+                let total_lines = content.lines().count();
+
+                let body = if show_line_numbers {
+                    let first_line = content[..start_boundary].matches('\n').count() + 1;
+                    number_lines(partial_content, first_line, total_lines)
+                } else {
+                    partial_content.to_string()
+                };
+
+                // Format output with range info if partial
+                let mut output = if partial {
+                    format!(
+                        "📄 File content (chars {}-{}, {} lines of {} total):\n{}",
+                        start_boundary, end_boundary, line_count, total_lines, body
+                    )
+                } else {
+                    format!("📄 File content ({} lines):\n{}", line_count, body)
+                };
+
+                if let Some(summary) = chunk_summary {
+                    output.push_str(&format!("\n\n{}", summary));
+                }
+
+                if show_outline {
+                    match code_search::outline::outline_source(
+                        std::path::Path::new(path_str),
+                        &content,
+                    ) {
+                        Ok(Some(symbols)) if !symbols.is_empty() => {
+                            output.push_str(&format!(
+                                "\n\n📑 Outline ({} lines total):\n{}",
+                                total_lines,
+                                code_search::outline::format_outline(&symbols)
+                            ));
+                        }
+                        Ok(Some(_)) => {
+                            output.push_str("\n\n📑 Outline: no symbols found");
+                        }
+                        Ok(None) => {
+                            output.push_str("\n\n📑 Outline: not available for this file type");
+                        }
+                        Err(e) => {
+                            output
+                                .push_str(&format!("\n\n📑 Outline: failed to parse file: {}", e));
+                        }
+                    }
+                }
+
+                if let Some(note) = encoding_note {
+                    output = format!("{}\n{}", note, output);
+                }
+
+                Ok(output)
+            } else {
+                Ok("❌ Invalid file_path argument".to_string())
+            }
+        } else {
+            Ok("❌ Missing file_path argument".to_string())
+        }
+    }
+
+    async fn run_write_file(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing write_file tool call");
+        let path_str = tool_call.args.get("file_path").and_then(|v| v.as_str());
+        let content_str = tool_call.args.get("content").and_then(|v| v.as_str());
+
+        if let (Some(path), Some(content)) = (path_str, content_str) {
+            // Expand tilde (~) to home directory
+            let expanded_path = self.expand_tilde(path);
+            let path = expanded_path.as_ref();
+
+            debug!("Writing to file: {}", path);
+
+            let written = match &self.remote {
+                Some(remote) => remote.write_file(path, content.as_bytes()).await,
+                None => {
+                    // Create parent directories if they don't exist
+                    if let Some(parent) = std::path::Path::new(path).parent() {
+                        if let Err(e) = std::fs::create_dir_all(parent) {
+                            return Ok(format!(
+                                "❌ Failed to create parent directories for '{}': {}",
+                                path, e
+                            ));
+                        }
+                    }
+                    std::fs::write(path, content).map_err(Into::into)
+                }
+            };
+            match written {
+                Ok(()) => {
+                    let line_count = content.lines().count();
+                    let char_count = content.len();
+                    Ok(format!(
+                        "✅ Successfully wrote {} lines ({} characters)",
+                        line_count, char_count
+                    ))
+                }
+                Err(e) => Ok(format!("❌ Failed to write to file '{}': {}", path, e)),
+            }
+        } else {
+            Ok("❌ Missing file_path or content argument".to_string())
+        }
+    }
+
+    async fn run_str_replace(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing str_replace tool call");
+
+        // Extract arguments
+        let args_obj = match tool_call.args.as_object() {
+            Some(obj) => obj,
+            None => return Ok("❌ Invalid arguments: expected object".to_string()),
+        };
+
+        let file_path = match args_obj.get("file_path").and_then(|v| v.as_str()) {
+            Some(path) => {
+                // Expand tilde (~) to home directory
+                let expanded_path = shellexpand::tilde(path);
+                expanded_path.into_owned()
+            }
+            None => return Ok("❌ Missing or invalid file_path argument".to_string()),
+        };
+
+        let diff = match args_obj.get("diff").and_then(|v| v.as_str()) {
+            Some(d) => d,
+            None => return Ok("❌ Missing or invalid diff argument".to_string()),
+        };
+
+        // Optional start and end character positions (0-indexed, end is EXCLUSIVE)
+        let start_char = args_obj
+            .get("start")
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize);
+        let end_char = args_obj
+            .get("end")
+            .and_then(|v| v.as_u64())
+            .map(|n| n as usize);
+
+        debug!(
+            "str_replace: path={}, start={:?}, end={:?}",
+            file_path, start_char, end_char
+        );
+
+        // Read the existing file
+        let file_content = match binary_file::read_text(&file_path) {
+            Ok(content) => content,
+            Err(msg) => return Ok(format!("❌ {}", msg)),
+        };
+
+        // Apply unified diff to content
+        let result = match apply_unified_diff_to_string(&file_content, diff, start_char, end_char) {
+            Ok(r) => r,
+            Err(e) => return Ok(format!("❌ {}", e)),
+        };
+
+        // Write the result back to the file
+        match std::fs::write(&file_path, &result) {
+            Ok(()) => Ok("✅ applied unified diff".to_string()),
+            Err(e) => Ok(format!("❌ Failed to write to file '{}': {}", file_path, e)),
+        }
+    }
+
+    async fn run_edit_symbol(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing edit_symbol tool call");
+
+        let args_obj = match tool_call.args.as_object() {
+            Some(obj) => obj,
+            None => return Ok("❌ Invalid arguments: expected object".to_string()),
+        };
+
+        let file_path = match args_obj.get("file_path").and_then(|v| v.as_str()) {
+            Some(path) => shellexpand::tilde(path).into_owned(),
+            None => return Ok("❌ Missing or invalid file_path argument".to_string()),
+        };
+        let symbol = match args_obj.get("symbol").and_then(|v| v.as_str()) {
+            Some(symbol) => symbol,
+            None => return Ok("❌ Missing or invalid symbol argument".to_string()),
+        };
+        let new_code = match args_obj.get("new_code").and_then(|v| v.as_str()) {
+            Some(code) => code,
+            None => return Ok("❌ Missing or invalid new_code argument".to_string()),
+        };
+        let kind = args_obj.get("kind").and_then(|v| v.as_str());
+        let body_only = args_obj
+            .get("body_only")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let file_content = match binary_file::read_text(&file_path) {
+            Ok(content) => content,
+            Err(msg) => return Ok(format!("❌ {}", msg)),
+        };
+
+        let edit = match code_search::edit::replace_symbol(
+            std::path::Path::new(&file_path),
+            &file_content,
+            symbol,
+            kind,
+            new_code,
+            body_only,
+        ) {
+            Ok(edit) => edit,
+            Err(e) => return Ok(format!("❌ {}", e)),
+        };
+
+        match std::fs::write(&file_path, &edit.content) {
+            Ok(()) => Ok(format!(
+                "✅ Replaced {}{} {} (was lines {}-{}, now lines {}-{})",
+                if body_only { "body of " } else { "" },
+                edit.replaced.kind,
+                edit.replaced.name,
+                edit.replaced.start_line,
+                edit.replaced.end_line,
+                edit.replaced.start_line,
+                edit.replaced.start_line + edit.new_line_count - 1
+            )),
+            Err(e) => Ok(format!("❌ Failed to write to file '{}': {}", file_path, e)),
+        }
+    }
+
+    async fn run_show_changes(&self, tool_call: &ToolCall) -> Result<String> {
+        match self.show_changes(tool_call) {
+            Ok(result) => Ok(result),
+            Err(e) => Ok(format!("❌ Failed to show changes: {}", e)),
+        }
+    }
+
+    async fn run_undo_edit(&self, tool_call: &ToolCall) -> Result<String> {
+        let count = tool_call
+            .args
+            .get("count")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(1)
+            .max(1) as usize;
+        match self.undo_stack().undo(count) {
+            Ok(undone) if undone.is_empty() => Ok("❌ No file modifications to undo".to_string()),
+            Ok(undone) => Ok(format!(
+                "↩️ Undid {} file modification(s):\n- {}",
+                undone.len(),
+                undone.join("\n- ")
+            )),
+            Err(e) => Ok(format!("❌ Failed to undo: {}", e)),
+        }
+    }
+}
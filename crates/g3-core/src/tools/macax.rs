@@ -0,0 +1,229 @@
+//! macOS Accessibility API tools (`macax_*`).
+
+use super::ToolHandlers;
+use crate::ui_writer::UiWriter;
+use crate::{Agent, ToolCall};
+use anyhow::Result;
+use futures_util::FutureExt;
+use tracing::debug;
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("macax_list_apps", |agent, _| {
+        agent.run_macax_list_apps().boxed()
+    });
+    handlers.register("macax_get_frontmost_app", |agent, _| {
+        agent.run_macax_get_frontmost_app().boxed()
+    });
+    handlers.register("macax_activate_app", |agent, call| {
+        agent.run_macax_activate_app(call).boxed()
+    });
+    handlers.register("macax_dump_elements", |agent, call| {
+        agent.run_macax_dump_elements(call).boxed()
+    });
+    handlers.register("macax_press_key", |agent, call| {
+        agent.run_macax_press_key(call).boxed()
+    });
+    handlers.register("macax_type_text", |agent, call| {
+        agent.run_macax_type_text(call).boxed()
+    });
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_macax_list_apps(&self) -> Result<String> {
+        debug!("Processing macax_list_apps tool call");
+
+        if !self.config.macax.enabled {
+            return Ok(
+                "❌ macOS Accessibility is not enabled. Use --macax flag to enable.".to_string(),
+            );
+        }
+
+        let controller_guard = self.macax_controller.read().await;
+        let controller = match controller_guard.as_ref() {
+            Some(c) => c,
+            None => return Ok("❌ macOS Accessibility controller not initialized.".to_string()),
+        };
+
+        match controller.list_applications() {
+            Ok(apps) => {
+                let app_list: Vec<String> = apps.iter().map(|a| a.name.clone()).collect();
+                Ok(format!("Running applications:\n{}", app_list.join("\n")))
+            }
+            Err(e) => Ok(format!("❌ Failed to list applications: {}", e)),
+        }
+    }
+
+    async fn run_macax_get_frontmost_app(&self) -> Result<String> {
+        debug!("Processing macax_get_frontmost_app tool call");
+
+        if !self.config.macax.enabled {
+            return Ok(
+                "❌ macOS Accessibility is not enabled. Use --macax flag to enable.".to_string(),
+            );
+        }
+
+        let controller_guard = self.macax_controller.read().await;
+        let controller = match controller_guard.as_ref() {
+            Some(c) => c,
+            None => return Ok("❌ macOS Accessibility controller not initialized.".to_string()),
+        };
+
+        match controller.get_frontmost_app() {
+            Ok(app) => Ok(format!("Frontmost application: {}", app.name)),
+            Err(e) => Ok(format!("❌ Failed to get frontmost app: {}", e)),
+        }
+    }
+
+    async fn run_macax_activate_app(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing macax_activate_app tool call");
+
+        if !self.config.macax.enabled {
+            return Ok(
+                "❌ macOS Accessibility is not enabled. Use --macax flag to enable.".to_string(),
+            );
+        }
+
+        let app_name = match tool_call.args.get("app_name").and_then(|v| v.as_str()) {
+            Some(n) => n,
+            None => return Ok("❌ Missing app_name argument".to_string()),
+        };
+
+        let controller_guard = self.macax_controller.read().await;
+        let controller = match controller_guard.as_ref() {
+            Some(c) => c,
+            None => return Ok("❌ macOS Accessibility controller not initialized.".to_string()),
+        };
+
+        match controller.activate_app(app_name) {
+            Ok(_) => Ok(format!("✅ Activated application: {}", app_name)),
+            Err(e) => Ok(format!("❌ Failed to activate app: {}", e)),
+        }
+    }
+
+    async fn run_macax_dump_elements(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing macax_dump_elements tool call");
+
+        if !self.config.macax.enabled {
+            return Ok(
+                "❌ macOS Accessibility is not enabled. Use --macax flag to enable.".to_string(),
+            );
+        }
+
+        let app_name = match tool_call.args.get("app_name").and_then(|v| v.as_str()) {
+            Some(n) => n,
+            None => return Ok("❌ Missing app_name argument".to_string()),
+        };
+        let max_depth = tool_call
+            .args
+            .get("max_depth")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(30) as usize;
+        let max_elements = tool_call
+            .args
+            .get("max_elements")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(300) as usize;
+
+        let controller_guard = self.macax_controller.read().await;
+        let controller = match controller_guard.as_ref() {
+            Some(c) => c,
+            None => return Ok("❌ macOS Accessibility controller not initialized.".to_string()),
+        };
+
+        match controller.dump_elements(app_name, max_depth) {
+            Ok(roots) if roots.is_empty() => Ok(format!(
+                "❌ No visible accessibility elements found in {}",
+                app_name
+            )),
+            Ok(roots) => {
+                let total: usize = roots.iter().map(|r| r.count()).sum();
+                let tree = roots
+                    .iter()
+                    .map(|r| r.format(max_elements))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Ok(format!(
+                    "🌳 Accessibility tree of {} ({} elements):\n{}",
+                    app_name, total, tree
+                ))
+            }
+            Err(e) => Ok(format!("❌ Failed to dump elements: {}", e)),
+        }
+    }
+
+    async fn run_macax_press_key(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing macax_press_key tool call");
+
+        if !self.config.macax.enabled {
+            return Ok(
+                "❌ macOS Accessibility is not enabled. Use --macax flag to enable.".to_string(),
+            );
+        }
+
+        let app_name = match tool_call.args.get("app_name").and_then(|v| v.as_str()) {
+            Some(n) => n,
+            None => return Ok("❌ Missing app_name argument".to_string()),
+        };
+
+        let key = match tool_call.args.get("key").and_then(|v| v.as_str()) {
+            Some(k) => k,
+            None => return Ok("❌ Missing key argument".to_string()),
+        };
+
+        let modifiers_vec: Vec<&str> = tool_call
+            .args
+            .get("modifiers")
+            .and_then(|v| v.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
+            .unwrap_or_default();
+
+        let controller_guard = self.macax_controller.read().await;
+        let controller = match controller_guard.as_ref() {
+            Some(c) => c,
+            None => return Ok("❌ macOS Accessibility controller not initialized.".to_string()),
+        };
+
+        match controller.press_key(app_name, key, modifiers_vec.clone()) {
+            Ok(_) => {
+                let modifier_str = if modifiers_vec.is_empty() {
+                    String::new()
+                } else {
+                    format!(" with modifiers: {}", modifiers_vec.join("+"))
+                };
+                Ok(format!("✅ Pressed key: {}{}", key, modifier_str))
+            }
+            Err(e) => Ok(format!("❌ Failed to press key: {}", e)),
+        }
+    }
+
+    async fn run_macax_type_text(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing macax_type_text tool call");
+
+        if !self.config.macax.enabled {
+            return Ok(
+                "❌ macOS Accessibility is not enabled. Use --macax flag to enable.".to_string(),
+            );
+        }
+
+        let app_name = match tool_call.args.get("app_name").and_then(|v| v.as_str()) {
+            Some(n) => n,
+            None => return Ok("❌ Missing app_name argument".to_string()),
+        };
+
+        let text = match tool_call.args.get("text").and_then(|v| v.as_str()) {
+            Some(t) => t,
+            None => return Ok("❌ Missing text argument".to_string()),
+        };
+
+        let controller_guard = self.macax_controller.read().await;
+        let controller = match controller_guard.as_ref() {
+            Some(c) => c,
+            None => return Ok("❌ macOS Accessibility controller not initialized.".to_string()),
+        };
+
+        match controller.type_text(app_name, text) {
+            Ok(_) => Ok(format!("✅ Typed text into {}", app_name)),
+            Err(e) => Ok(format!("❌ Failed to type text: {}", e)),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Handlers of the built-in tools.
+//!
+//! A tool's schema, version and capabilities are its [`ToolSpec`] in the
+//! [`tool_registry`](crate::tool_registry); what a call of it does is the [`ToolHandler`]
+//! registered under the same name here, by the module of the tool's group. The checks every
+//! call goes through (moderation, read-only mode, scopes, budgets, file locks and undo) stay
+//! in `Agent::execute_tool`, which then looks the handler up by name. Tools of plugins,
+//! Unity Catalog and the embedding program are dispatched after the built-in ones.
+//!
+//! To add a tool, declare its spec, write its method on [`Agent`] in the module of its group
+//! and register it in that module's `register`.
+
+mod computer;
+mod fs;
+mod macax;
+mod output;
+mod search;
+mod shell;
+mod todo;
+mod webdriver;
+
+use crate::tool_registry::{ToolRegistry, ToolSpec};
+use crate::ui_writer::UiWriter;
+use crate::{Agent, ToolCall};
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+
+/// Runs calls of one built-in tool
+pub trait ToolHandler<W: UiWriter>: Send + Sync {
+    fn call<'a>(
+        &'a self,
+        agent: &'a Agent<W>,
+        tool_call: &'a ToolCall,
+    ) -> BoxFuture<'a, Result<String>>;
+}
+
+impl<W, F> ToolHandler<W> for F
+where
+    W: UiWriter,
+    F: for<'a> Fn(&'a Agent<W>, &'a ToolCall) -> BoxFuture<'a, Result<String>> + Send + Sync,
+{
+    fn call<'a>(
+        &'a self,
+        agent: &'a Agent<W>,
+        tool_call: &'a ToolCall,
+    ) -> BoxFuture<'a, Result<String>> {
+        self(agent, tool_call)
+    }
+}
+
+/// A handler with the spec of the tool it runs
+pub struct RegisteredTool<W: UiWriter> {
+    pub spec: &'static ToolSpec,
+    handler: Box<dyn ToolHandler<W>>,
+}
+
+impl<W: UiWriter> RegisteredTool<W> {
+    pub fn call<'a>(
+        &'a self,
+        agent: &'a Agent<W>,
+        tool_call: &'a ToolCall,
+    ) -> BoxFuture<'a, Result<String>> {
+        self.handler.call(agent, tool_call)
+    }
+}
+
+/// The handlers of the built-in tools, by name
+pub struct ToolHandlers<W: UiWriter> {
+    tools: HashMap<&'static str, RegisteredTool<W>>,
+}
+
+impl<W: UiWriter> ToolHandlers<W> {
+    /// A handler for each tool of the [`ToolRegistry`]
+    pub fn builtin() -> Self {
+        let mut handlers = Self {
+            tools: HashMap::new(),
+        };
+        shell::register(&mut handlers);
+        fs::register(&mut handlers);
+        search::register(&mut handlers);
+        todo::register(&mut handlers);
+        output::register(&mut handlers);
+        webdriver::register(&mut handlers);
+        macax::register(&mut handlers);
+        computer::register(&mut handlers);
+        handlers
+    }
+
+    /// Run calls of the tool `name` with `handler`, in place of any handler it had.
+    ///
+    /// Panics if the tool has no spec, since it couldn't be offered or checked.
+    pub fn register<F>(&mut self, name: &'static str, handler: F)
+    where
+        F: for<'a> Fn(&'a Agent<W>, &'a ToolCall) -> BoxFuture<'a, Result<String>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let spec = ToolRegistry::builtin()
+            .get(name)
+            .unwrap_or_else(|| panic!("The tool {} has no ToolSpec in the registry", name));
+        let handler = Box::new(handler);
+        self.tools
+            .insert(spec.name, RegisteredTool { spec, handler });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredTool<W>> {
+        self.tools.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui_writer::NullUiWriter;
+
+    #[test]
+    fn test_every_tool_has_a_handler() {
+        let handlers = ToolHandlers::<NullUiWriter>::builtin();
+        for spec in ToolRegistry::builtin().specs() {
+            let registered = handlers.get(spec.name);
+            assert!(registered.is_some(), "{} has no handler", spec.name);
+            assert_eq!(registered.unwrap().spec.name, spec.name);
+        }
+        assert_eq!(handlers.tools.len(), ToolRegistry::builtin().specs().len());
+        assert!(handlers.get("uc__main__sales__total").is_none());
+    }
+}
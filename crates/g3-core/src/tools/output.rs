@@ -0,0 +1,74 @@
+//! Tools that end a turn: `final_output`, and `give_feedback` for the coach.
+
+use super::ToolHandlers;
+use crate::artifacts::ArtifactKind;
+use crate::ui_writer::UiWriter;
+use crate::{Agent, CoachFeedback, ToolCall};
+use anyhow::Result;
+use futures_util::FutureExt;
+use tracing::warn;
+
+/// The latest summary of the session, next to its artifacts
+const SUMMARY_FILE: &str = "SUMMARY.md";
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("give_feedback", |agent, call| {
+        agent.run_give_feedback(call).boxed()
+    });
+    handlers.register("final_output", |agent, call| {
+        agent.run_final_output(call).boxed()
+    });
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_give_feedback(&self, tool_call: &ToolCall) -> Result<String> {
+        if !self.is_coach {
+            return Ok("❌ give_feedback is only available to the coach in autonomous mode. Use final_output instead.".to_string());
+        }
+        match CoachFeedback::from_args(&tool_call.args) {
+            Ok(feedback) => {
+                let rendered = feedback.to_string();
+                *self.coach_feedback.write().await = Some(feedback);
+                Ok(rendered)
+            }
+            Err(e) => Ok(format!("❌ {}", e)),
+        }
+    }
+
+    async fn run_final_output(&self, tool_call: &ToolCall) -> Result<String> {
+        match tool_call
+            .args
+            .get("summary")
+            .and_then(|summary| summary.as_str())
+        {
+            Some(summary) => {
+                self.save_summary(summary);
+                Ok(summary.to_string())
+            }
+            None => Ok("✅ Turn completed".to_string()),
+        }
+    }
+
+    /// Keep the summary as an artifact, and as the session's `SUMMARY.md`, for PR
+    /// descriptions and reports. A summary that can't be saved is still shown.
+    fn save_summary(&self, summary: &str) {
+        let contents = format!("{}\n", summary.trim_end());
+        let saved = self.artifacts().save(
+            ArtifactKind::Summary,
+            SUMMARY_FILE,
+            contents.as_bytes(),
+            Some("final_output"),
+        );
+        if let Err(e) = saved {
+            warn!("Failed to save the summary: {}", e);
+        }
+        if self.session_id.is_none() {
+            return;
+        }
+        if let Some(session_dir) = self.artifacts().dir().parent() {
+            if let Err(e) = std::fs::write(session_dir.join(SUMMARY_FILE), &contents) {
+                warn!("Failed to write {}: {}", SUMMARY_FILE, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Code search tools: `code_search` with tree-sitter queries and `code_search_semantic`
+//! over the embedding index.
+
+use super::ToolHandlers;
+use crate::ui_writer::UiWriter;
+use crate::{code_search, Agent, ToolCall};
+use anyhow::Result;
+use futures_util::FutureExt;
+use tracing::{debug, warn};
+
+/// How many matches code_search_semantic returns by default, and at most
+const SEMANTIC_SEARCH_DEFAULT_LIMIT: usize = 10;
+const SEMANTIC_SEARCH_MAX_LIMIT: usize = 50;
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("code_search", |agent, call| {
+        agent.run_code_search(call).boxed()
+    });
+    handlers.register("code_search_semantic", |agent, call| {
+        agent.run_code_search_semantic(call).boxed()
+    });
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_code_search(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing code_search tool call");
+
+        // Parse the request
+        let request: crate::code_search::CodeSearchRequest =
+            match serde_json::from_value(tool_call.args.clone()) {
+                Ok(req) => req,
+                Err(e) => {
+                    return Ok(format!("❌ Invalid code_search arguments: {}", e));
+                }
+            };
+
+        // Execute the code search
+        match crate::code_search::execute_code_search_with_ignore(request, self.g3ignore.clone())
+            .await
+        {
+            Ok(response) => {
+                // Serialize the response to JSON
+                match serde_json::to_string_pretty(&response) {
+                    Ok(json_output) => Ok(format!("✅ Code search completed\n{}", json_output)),
+                    Err(e) => Ok(format!("❌ Failed to serialize response: {}", e)),
+                }
+            }
+            Err(e) => Ok(format!("❌ Code search failed: {}", e)),
+        }
+    }
+
+    async fn run_code_search_semantic(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing code_search_semantic tool call");
+
+        let query = match tool_call.args.get("query").and_then(|v| v.as_str()) {
+            Some(q) if !q.trim().is_empty() => q,
+            _ => return Ok("❌ Missing query argument".to_string()),
+        };
+        let limit = tool_call
+            .args
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .map(|n| (n as usize).clamp(1, SEMANTIC_SEARCH_MAX_LIMIT))
+            .unwrap_or(SEMANTIC_SEARCH_DEFAULT_LIMIT);
+        let paths: Vec<String> = tool_call
+            .args
+            .get("paths")
+            .and_then(|v| v.as_array())
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|p| p.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let embedder = match self.embedding_provider() {
+            Ok(embedder) => embedder,
+            Err(e) => return Ok(format!("❌ {}", e)),
+        };
+
+        let workspace = std::env::current_dir()?;
+        let mut cached = self.semantic_index.lock().await;
+        if cached.as_ref().map(|(dir, _)| dir) != Some(&workspace) {
+            let index = code_search::semantic::SemanticIndex::load(&workspace).unwrap_or_default();
+            *cached = Some((workspace.clone(), index));
+        }
+        let Some((_, index)) = cached.as_mut() else {
+            return Ok("❌ Failed to load the semantic index".to_string());
+        };
+        let update = match index.update(&workspace, embedder.as_ref()).await {
+            Ok(update) => update,
+            Err(e) => return Ok(format!("❌ Failed to index the workspace: {}", e)),
+        };
+        if update != code_search::semantic::IndexUpdate::default() {
+            if let Err(e) = index.save(&workspace) {
+                warn!("Failed to save the semantic index: {}", e);
+            }
+        }
+
+        let query_vector = match embedder.embed(&[query.to_string()]).await {
+            Ok(mut vectors) if vectors.len() == 1 => vectors.remove(0),
+            Ok(_) => return Ok("❌ Failed to embed the query".to_string()),
+            Err(e) => return Ok(format!("❌ Failed to embed the query: {}", e)),
+        };
+        let matches = index.search(&query_vector, limit, &paths);
+        let summary = format!(
+            "{} chunks in {} files, {} re-indexed",
+            index.chunk_count(),
+            index.files.len(),
+            update.embedded_files
+        );
+        if matches.is_empty() {
+            return Ok(format!("❌ No matches for '{}' ({})", query, summary));
+        }
+        Ok(format!(
+            "✅ {} matches for '{}' ({})\n\n{}",
+            matches.len(),
+            query,
+            summary,
+            code_search::semantic::render_matches(&workspace, &matches)
+        ))
+    }
+}
@@ -0,0 +1,145 @@
+//! The `shell` tool: commands run with the project's environment, their output streamed
+//! to the UI and cut to its head and tail, with all of it kept as an artifact.
+
+use super::ToolHandlers;
+use crate::g3ignore::G3Ignore;
+use crate::ui_writer::UiWriter;
+use crate::{events, shell_escape_command, shell_output, Agent, ToolCall};
+use anyhow::Result;
+use futures_util::FutureExt;
+use g3_execution::CodeExecutor;
+use tracing::debug;
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("shell", |agent, call| agent.run_shell(call).boxed());
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_shell(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing shell tool call");
+        if let Some(command) = tool_call.args.get("command") {
+            debug!("Found command parameter: {:?}", command);
+            if let Some(command_str) = command.as_str() {
+                debug!("Command string: {}", command_str);
+                // Use shell escaping to handle filenames with spaces and special characters,
+                // and keep searches out of files hidden by .g3ignore
+                let escaped_command = self
+                    .g3ignore
+                    .exclude_from_searches(&shell_escape_command(command_str));
+                let cwd = match self.scope.shell_dir() {
+                    Some(dir) => dir.to_path_buf(),
+                    None => std::env::current_dir().unwrap_or_default(),
+                };
+
+                // Long output is cut to its head and tail; all of it goes to an artifact
+                let executor = CodeExecutor::with_output_limit(
+                    shell_output::HEAD_LINES,
+                    shell_output::TAIL_LINES,
+                )
+                .with_env(self.command_env())
+                .with_dir(self.scope.shell_dir().map(std::path::Path::to_path_buf))
+                .with_remote(self.remote.clone())
+                .with_container(self.container.clone());
+
+                // Create a receiver for streaming output
+                struct ToolOutputReceiver<'a, W: UiWriter> {
+                    events: &'a events::EventBus<W>,
+                    capture: shell_output::OutputCapture,
+                    env: &'a g3_config::env::ProjectEnv,
+                    ignore: &'a G3Ignore,
+                    cwd: &'a std::path::Path,
+                }
+
+                impl<'a, W: UiWriter> g3_execution::OutputReceiver for ToolOutputReceiver<'a, W> {
+                    fn on_output_line(&self, line: &str) {
+                        if self.ignore.names_ignored_path(line, self.cwd) {
+                            return;
+                        }
+                        // Secrets from the env files stay out of the terminal and the saved output
+                        let line = self.env.redact(line);
+                        if self.capture.push(&line) {
+                            self.events.publish(events::AgentEvent::ToolOutput { line });
+                        }
+                    }
+                }
+
+                let receiver = ToolOutputReceiver {
+                    events: &self.events,
+                    env: &self.project_env,
+                    ignore: &self.g3ignore,
+                    cwd: &cwd,
+                    capture: shell_output::OutputCapture::new(self.artifacts().clone()),
+                };
+
+                // Marks the command as running until it finishes or is dropped
+                struct Running<'a>(&'a std::sync::atomic::AtomicBool);
+
+                impl Drop for Running<'_> {
+                    fn drop(&mut self) {
+                        self.0.store(false, std::sync::atomic::Ordering::SeqCst);
+                    }
+                }
+
+                self.running_command
+                    .store(true, std::sync::atomic::Ordering::SeqCst);
+                let _running = Running(&self.running_command);
+
+                let result = executor
+                    .execute_bash_streaming_cancellable(
+                        &escaped_command,
+                        &receiver,
+                        &self.cancellation,
+                    )
+                    .await;
+                let captured = receiver.capture.finish();
+                match result {
+                    Ok(result) => {
+                        let output = if result.cancelled {
+                            let output = [result.stdout.trim(), result.stderr.trim()]
+                                .into_iter()
+                                .filter(|s| !s.is_empty())
+                                .collect::<Vec<_>>()
+                                .join("\n");
+                            if output.is_empty() {
+                                "❌ Command cancelled by the user".to_string()
+                            } else {
+                                format!(
+                                    "❌ Command cancelled by the user. Output so far:\n{}",
+                                    output
+                                )
+                            }
+                        } else if result.success {
+                            if result.stdout.is_empty() {
+                                "✅ Command executed successfully".to_string()
+                            } else {
+                                result.stdout.trim().to_string()
+                            }
+                        } else {
+                            format!("❌ Command failed: {}", result.stderr.trim())
+                        };
+                        let output = self.g3ignore.filter_output(&output, &cwd);
+                        let output = self.project_env.redact(&output);
+                        Ok(match captured {
+                            Some(captured) => format!("{}\n\n{}", output, captured.note()),
+                            None => output,
+                        })
+                    }
+                    Err(e) => Ok(format!("❌ Execution error: {}", e)),
+                }
+            } else {
+                debug!("Command parameter is not a string: {:?}", command);
+                Ok("❌ Invalid command argument".to_string())
+            }
+        } else {
+            debug!("No command parameter found in args: {:?}", tool_call.args);
+            debug!(
+                "Available keys: {:?}",
+                tool_call
+                    .args
+                    .as_object()
+                    .map(|obj| obj.keys().collect::<Vec<_>>())
+            );
+            Ok("❌ Missing command argument".to_string())
+        }
+    }
+}
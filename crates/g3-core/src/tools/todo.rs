@@ -0,0 +1,85 @@
+//! The TODO list tools, `todo_read` and `todo_write`.
+
+use super::ToolHandlers;
+use crate::ui_writer::UiWriter;
+use crate::{Agent, ToolCall};
+use anyhow::Result;
+use futures_util::FutureExt;
+use tracing::debug;
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("todo_read", |agent, _| agent.run_todo_read().boxed());
+    handlers.register("todo_write", |agent, call| {
+        agent.run_todo_write(call).boxed()
+    });
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_todo_read(&self) -> Result<String> {
+        debug!("Processing todo_read tool call");
+        // Read from todo.g3.md file in current workspace directory
+        let todo_path = std::env::current_dir()?.join("todo.g3.md");
+
+        if !todo_path.exists() {
+            // Also update in-memory content to stay in sync
+            let mut todo = self.todo_content.write().await;
+            *todo = String::new();
+            Ok("📝 TODO list is empty (no todo.g3.md file found)".to_string())
+        } else {
+            match std::fs::read_to_string(&todo_path) {
+                Ok(content) => {
+                    // Update in-memory content to stay in sync
+                    let mut todo = self.todo_content.write().await;
+                    *todo = content.clone();
+
+                    if content.trim().is_empty() {
+                        Ok("📝 TODO list is empty".to_string())
+                    } else {
+                        Ok(format!("📝 TODO list:\n{}", content))
+                    }
+                }
+                Err(e) => Ok(format!("❌ Failed to read TODO.md: {}", e)),
+            }
+        }
+    }
+
+    async fn run_todo_write(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing todo_write tool call");
+        if let Some(content) = tool_call.args.get("content") {
+            if let Some(content_str) = content.as_str() {
+                let char_count = content_str.chars().count();
+                let max_chars = std::env::var("G3_TODO_MAX_CHARS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(50_000);
+
+                if max_chars > 0 && char_count > max_chars {
+                    return Ok(format!(
+                        "❌ TODO list too large: {} chars (max: {})",
+                        char_count, max_chars
+                    ));
+                }
+
+                // Write to todo.g3.md file in current workspace directory
+                let todo_path = std::env::current_dir()?.join("todo.g3.md");
+
+                match std::fs::write(&todo_path, content_str) {
+                    Ok(_) => {
+                        // Also update in-memory content to stay in sync
+                        let mut todo = self.todo_content.write().await;
+                        *todo = content_str.to_string();
+                        Ok(format!(
+                            "✅ TODO list updated ({} chars) and saved to todo.g3.md",
+                            char_count
+                        ))
+                    }
+                    Err(e) => Ok(format!("❌ Failed to write todo.g3.md: {}", e)),
+                }
+            } else {
+                Ok("❌ Invalid content argument".to_string())
+            }
+        } else {
+            Ok("❌ Missing content argument".to_string())
+        }
+    }
+}
@@ -0,0 +1,933 @@
+//! Browser tools (`webdriver_*`), driving the WebDriver session `webdriver_start` opens.
+
+use super::ToolHandlers;
+use crate::ui_writer::UiWriter;
+use crate::{downloads, webdriver_tab_handle, webdriver_wait_timeout, Agent, ToolCall};
+use anyhow::Result;
+use futures_util::FutureExt;
+use g3_computer_control::WebDriverController;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// How long webdriver_wait_for_download waits by default, and at most
+const DOWNLOAD_WAIT_DEFAULT: Duration = Duration::from_secs(60);
+const DOWNLOAD_WAIT_MAX: Duration = Duration::from_secs(600);
+
+pub(super) fn register<W: UiWriter>(handlers: &mut ToolHandlers<W>) {
+    handlers.register("webdriver_start", |agent, _| {
+        agent.run_webdriver_start().boxed()
+    });
+    handlers.register("webdriver_navigate", |agent, call| {
+        agent.run_webdriver_navigate(call).boxed()
+    });
+    handlers.register("webdriver_get_url", |agent, _| {
+        agent.run_webdriver_get_url().boxed()
+    });
+    handlers.register("webdriver_get_title", |agent, _| {
+        agent.run_webdriver_get_title().boxed()
+    });
+    handlers.register("webdriver_find_element", |agent, call| {
+        agent.run_webdriver_find_element(call).boxed()
+    });
+    handlers.register("webdriver_wait_for_selector", |agent, call| {
+        agent.run_webdriver_wait_for_selector(call).boxed()
+    });
+    handlers.register("webdriver_wait_for_text", |agent, call| {
+        agent.run_webdriver_wait_for_text(call).boxed()
+    });
+    handlers.register("webdriver_set_download_dir", |agent, call| {
+        agent.run_webdriver_set_download_dir(call).boxed()
+    });
+    handlers.register("webdriver_wait_for_download", |agent, call| {
+        agent.run_webdriver_wait_for_download(call).boxed()
+    });
+    handlers.register("webdriver_find_elements", |agent, call| {
+        agent.run_webdriver_find_elements(call).boxed()
+    });
+    handlers.register("webdriver_click", |agent, call| {
+        agent.run_webdriver_click(call).boxed()
+    });
+    handlers.register("webdriver_send_keys", |agent, call| {
+        agent.run_webdriver_send_keys(call).boxed()
+    });
+    handlers.register("webdriver_execute_script", |agent, call| {
+        agent.run_webdriver_execute_script(call).boxed()
+    });
+    handlers.register("webdriver_get_page_source", |agent, _| {
+        agent.run_webdriver_get_page_source().boxed()
+    });
+    handlers.register("webdriver_screenshot", |agent, call| {
+        agent.run_webdriver_screenshot(call).boxed()
+    });
+    handlers.register("webdriver_back", |agent, _| {
+        agent.run_webdriver_back().boxed()
+    });
+    handlers.register("webdriver_forward", |agent, _| {
+        agent.run_webdriver_forward().boxed()
+    });
+    handlers.register("webdriver_refresh", |agent, _| {
+        agent.run_webdriver_refresh().boxed()
+    });
+    handlers.register("webdriver_new_tab", |agent, call| {
+        agent.run_webdriver_new_tab(call).boxed()
+    });
+    handlers.register("webdriver_list_tabs", |agent, _| {
+        agent.run_webdriver_list_tabs().boxed()
+    });
+    handlers.register("webdriver_switch_tab", |agent, call| {
+        agent.run_webdriver_switch_tab(call).boxed()
+    });
+    handlers.register("webdriver_close_tab", |agent, call| {
+        agent.run_webdriver_close_tab(call).boxed()
+    });
+    handlers.register("webdriver_quit", |agent, _| {
+        agent.run_webdriver_quit().boxed()
+    });
+}
+
+impl<W: UiWriter> Agent<W> {
+    async fn run_webdriver_start(&self) -> Result<String> {
+        debug!("Processing webdriver_start tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        // Check if session already exists
+        let session_guard = self.webdriver_session.read().await;
+        if session_guard.is_some() {
+            drop(session_guard);
+            return Ok("✅ WebDriver session already active".to_string());
+        }
+        drop(session_guard);
+
+        match self.start_webdriver().await {
+            Ok(()) => Ok(
+                "✅ WebDriver session started successfully! Safari should open automatically."
+                    .to_string(),
+            ),
+            Err(e) => Ok(format!("❌ {}", e)),
+        }
+    }
+
+    async fn run_webdriver_navigate(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_navigate tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+        drop(session_guard);
+        let url = match tool_call.args.get("url").and_then(|v| v.as_str()) {
+            Some(u) => u,
+            None => return Ok("❌ Missing url argument".to_string()),
+        };
+
+        let mut driver = session.lock().await;
+        match driver.navigate(url).await {
+            Ok(_) => {
+                *self.webdriver_last_url.lock().unwrap() = Some(url.to_string());
+                Ok(format!("✅ Navigated to {}", url))
+            }
+            Err(e) => Ok(format!("❌ Failed to navigate: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_get_url(&self) -> Result<String> {
+        debug!("Processing webdriver_get_url tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let driver = session.lock().await;
+        match driver.current_url().await {
+            Ok(url) => Ok(format!("Current URL: {}", url)),
+            Err(e) => Ok(format!("❌ Failed to get URL: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_get_title(&self) -> Result<String> {
+        debug!("Processing webdriver_get_title tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let driver = session.lock().await;
+        match driver.title().await {
+            Ok(title) => Ok(format!("Page title: {}", title)),
+            Err(e) => Ok(format!("❌ Failed to get title: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_find_element(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_find_element tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
+            Some(s) => s,
+            None => return Ok("❌ Missing selector argument".to_string()),
+        };
+
+        let mut driver = session.lock().await;
+        match driver.find_element(selector).await {
+            Ok(elem) => match elem.text().await {
+                Ok(text) => Ok(format!("Element text: {}", text)),
+                Err(e) => Ok(format!("❌ Failed to get element text: {}", e)),
+            },
+            Err(e) => Ok(format!("❌ Failed to find element '{}': {}", selector, e)),
+        }
+    }
+
+    async fn run_webdriver_wait_for_selector(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_wait_for_selector tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+        drop(session_guard);
+
+        let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
+            Some(s) => s,
+            None => return Ok("❌ Missing selector argument".to_string()),
+        };
+        let visible = tool_call
+            .args
+            .get("visible")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let timeout = webdriver_wait_timeout(&tool_call.args);
+
+        let mut driver = session.lock().await;
+        let outcome = driver.wait_for_selector(selector, visible, timeout).await;
+        let what = if visible { "visible" } else { "present" };
+        if outcome.met {
+            Ok(format!(
+                "✅ '{}' was {} after {:.1}s. Text: {}",
+                selector,
+                what,
+                outcome.elapsed.as_secs_f64(),
+                outcome.state
+            ))
+        } else {
+            Ok(format!(
+                "❌ Timed out after {:.1}s waiting for '{}' to be {}: {}",
+                outcome.elapsed.as_secs_f64(),
+                selector,
+                what,
+                outcome.state
+            ))
+        }
+    }
+
+    async fn run_webdriver_wait_for_text(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_wait_for_text tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+        drop(session_guard);
+
+        let text = match tool_call.args.get("text").and_then(|v| v.as_str()) {
+            Some(t) => t,
+            None => return Ok("❌ Missing text argument".to_string()),
+        };
+        let selector = tool_call
+            .args
+            .get("selector")
+            .and_then(|v| v.as_str())
+            .unwrap_or("body");
+        let timeout = webdriver_wait_timeout(&tool_call.args);
+
+        let mut driver = session.lock().await;
+        let outcome = driver.wait_for_text(text, selector, timeout).await;
+        if outcome.met {
+            Ok(format!(
+                "✅ '{}' appeared in '{}' after {:.1}s. Text: {}",
+                text,
+                selector,
+                outcome.elapsed.as_secs_f64(),
+                outcome.state
+            ))
+        } else {
+            Ok(format!(
+                "❌ Timed out after {:.1}s waiting for '{}': {}",
+                outcome.elapsed.as_secs_f64(),
+                text,
+                outcome.state
+            ))
+        }
+    }
+
+    async fn run_webdriver_set_download_dir(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_set_download_dir tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let path = match tool_call.args.get("path").and_then(|v| v.as_str()) {
+            Some(p) => std::path::PathBuf::from(shellexpand::tilde(p).as_ref()),
+            None => return Ok("❌ Missing path argument".to_string()),
+        };
+        if let Err(e) = std::fs::create_dir_all(&path) {
+            return Ok(format!(
+                "❌ Failed to create download directory {}: {}",
+                path.display(),
+                e
+            ));
+        }
+        let message = format!("✅ Watching {} for downloads", path.display());
+        *self.webdriver_download_dir.lock().unwrap() = path;
+        Ok(message)
+    }
+
+    async fn run_webdriver_wait_for_download(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_wait_for_download tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let pattern = match tool_call.args.get("pattern").and_then(|v| v.as_str()) {
+            Some(p) => p,
+            None => return Ok("❌ Missing pattern argument".to_string()),
+        };
+        let timeout = tool_call
+            .args
+            .get("timeout_seconds")
+            .and_then(|v| v.as_f64())
+            .filter(|s| s.is_finite() && *s > 0.0)
+            .map(|s| Duration::from_secs_f64(s.min(DOWNLOAD_WAIT_MAX.as_secs_f64())))
+            .unwrap_or(DOWNLOAD_WAIT_DEFAULT);
+        let dir = self.webdriver_download_dir.lock().unwrap().clone();
+        let since = self
+            .webdriver_last_call
+            .lock()
+            .unwrap()
+            .unwrap_or_else(std::time::SystemTime::now);
+        let mut watch = match downloads::DownloadWatch::new(&dir, pattern, since) {
+            Ok(watch) => watch,
+            Err(e) => return Ok(format!("❌ {}", e)),
+        };
+
+        let start = Instant::now();
+        loop {
+            let state = watch.poll();
+            if let downloads::DownloadState::Finished { path, size } = &state {
+                return Ok(format!(
+                    "✅ Downloaded {} ({} bytes) after {:.1}s",
+                    path.display(),
+                    size,
+                    start.elapsed().as_secs_f64()
+                ));
+            }
+            if start.elapsed() >= timeout {
+                let found = match state {
+                    downloads::DownloadState::InProgress(names) => {
+                        format!("still downloading: {}", names.join(", "))
+                    }
+                    _ => format!("no new file matching '{}'", pattern),
+                };
+                return Ok(format!(
+                    "❌ Timed out after {:.1}s waiting for a download in {}: {}",
+                    start.elapsed().as_secs_f64(),
+                    dir.display(),
+                    found
+                ));
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    async fn run_webdriver_find_elements(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_find_elements tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
+            Some(s) => s,
+            None => return Ok("❌ Missing selector argument".to_string()),
+        };
+
+        let mut driver = session.lock().await;
+        match driver.find_elements(selector).await {
+            Ok(elements) => {
+                let mut results = Vec::new();
+                for (i, elem) in elements.iter().enumerate() {
+                    match elem.text().await {
+                        Ok(text) => results.push(format!("[{}]: {}", i, text)),
+                        Err(_) => results.push(format!("[{}]: <error getting text>", i)),
+                    }
+                }
+                Ok(format!(
+                    "Found {} elements:\n{}",
+                    results.len(),
+                    results.join("\n")
+                ))
+            }
+            Err(e) => Ok(format!("❌ Failed to find elements '{}': {}", selector, e)),
+        }
+    }
+
+    async fn run_webdriver_click(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_click tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
+            Some(s) => s,
+            None => return Ok("❌ Missing selector argument".to_string()),
+        };
+
+        let mut driver = session.lock().await;
+        match driver.find_element(selector).await {
+            Ok(mut elem) => match elem.click().await {
+                Ok(_) => Ok(format!("✅ Clicked element '{}'", selector)),
+                Err(e) => Ok(format!("❌ Failed to click element: {}", e)),
+            },
+            Err(e) => Ok(format!("❌ Failed to find element '{}': {}", selector, e)),
+        }
+    }
+
+    async fn run_webdriver_send_keys(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_send_keys tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let selector = match tool_call.args.get("selector").and_then(|v| v.as_str()) {
+            Some(s) => s,
+            None => return Ok("❌ Missing selector argument".to_string()),
+        };
+
+        let text = match tool_call.args.get("text").and_then(|v| v.as_str()) {
+            Some(t) => t,
+            None => return Ok("❌ Missing text argument".to_string()),
+        };
+
+        let clear_first = tool_call
+            .args
+            .get("clear_first")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+
+        let mut driver = session.lock().await;
+        match driver.find_element(selector).await {
+            Ok(mut elem) => {
+                if clear_first {
+                    if let Err(e) = elem.clear().await {
+                        return Ok(format!("❌ Failed to clear element: {}", e));
+                    }
+                }
+                match elem.send_keys(text).await {
+                    Ok(_) => Ok(format!("✅ Sent keys to element '{}'", selector)),
+                    Err(e) => Ok(format!("❌ Failed to send keys: {}", e)),
+                }
+            }
+            Err(e) => Ok(format!("❌ Failed to find element '{}': {}", selector, e)),
+        }
+    }
+
+    async fn run_webdriver_execute_script(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_execute_script tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let script = match tool_call.args.get("script").and_then(|v| v.as_str()) {
+            Some(s) => s,
+            None => return Ok("❌ Missing script argument".to_string()),
+        };
+
+        let mut driver = session.lock().await;
+        match driver.execute_script(script, vec![]).await {
+            Ok(result) => Ok(format!("Script result: {:?}", result)),
+            Err(e) => Ok(format!("❌ Failed to execute script: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_get_page_source(&self) -> Result<String> {
+        debug!("Processing webdriver_get_page_source tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let driver = session.lock().await;
+        match driver.page_source().await {
+            Ok(source) => {
+                // Truncate if too long
+                if source.len() > 10000 {
+                    Ok(format!(
+                        "Page source ({} chars, truncated to 10000):\n{}...",
+                        source.len(),
+                        &source[..10000]
+                    ))
+                } else {
+                    Ok(format!("Page source ({} chars):\n{}", source.len(), source))
+                }
+            }
+            Err(e) => Ok(format!("❌ Failed to get page source: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_screenshot(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_screenshot tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let path = match tool_call.args.get("path").and_then(|v| v.as_str()) {
+            Some(p) => p,
+            None => return Ok("❌ Missing path argument".to_string()),
+        };
+
+        let mut driver = session.lock().await;
+        match driver.screenshot(path).await {
+            Ok(_) => Ok(format!("✅ Screenshot saved to {}", path)),
+            Err(e) => Ok(format!("❌ Failed to take screenshot: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_back(&self) -> Result<String> {
+        debug!("Processing webdriver_back tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let mut driver = session.lock().await;
+        match driver.back().await {
+            Ok(_) => Ok("✅ Navigated back".to_string()),
+            Err(e) => Ok(format!("❌ Failed to navigate back: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_forward(&self) -> Result<String> {
+        debug!("Processing webdriver_forward tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let mut driver = session.lock().await;
+        match driver.forward().await {
+            Ok(_) => Ok("✅ Navigated forward".to_string()),
+            Err(e) => Ok(format!("❌ Failed to navigate forward: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_refresh(&self) -> Result<String> {
+        debug!("Processing webdriver_refresh tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+
+        let mut driver = session.lock().await;
+        match driver.refresh().await {
+            Ok(_) => Ok("✅ Page refreshed".to_string()),
+            Err(e) => Ok(format!("❌ Failed to refresh page: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_new_tab(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_new_tab tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+        drop(session_guard);
+
+        let window = tool_call
+            .args
+            .get("window")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let url = tool_call.args.get("url").and_then(|v| v.as_str());
+
+        let mut driver = session.lock().await;
+        let handle = match driver.new_window(!window).await {
+            Ok(handle) => handle,
+            Err(e) => return Ok(format!("❌ Failed to open a new tab: {}", e)),
+        };
+        if let Err(e) = driver.switch_to_window(&handle).await {
+            return Ok(format!(
+                "❌ Opened tab {} but failed to switch to it: {}",
+                handle, e
+            ));
+        }
+        let kind = if window { "window" } else { "tab" };
+        match url {
+            Some(url) => match driver.navigate(url).await {
+                Ok(_) => {
+                    *self.webdriver_last_url.lock().unwrap() = Some(url.to_string());
+                    Ok(format!("✅ Opened {} in a new {} ({})", url, kind, handle))
+                }
+                Err(e) => Ok(format!(
+                    "❌ Opened a new {} ({}) but failed to navigate: {}",
+                    kind, handle, e
+                )),
+            },
+            None => Ok(format!("✅ Opened a new {} ({})", kind, handle)),
+        }
+    }
+
+    async fn run_webdriver_list_tabs(&self) -> Result<String> {
+        debug!("Processing webdriver_list_tabs tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+        drop(session_guard);
+
+        let mut driver = session.lock().await;
+        match driver.tabs().await {
+            Ok(tabs) => Ok(tabs
+                .iter()
+                .enumerate()
+                .map(|(i, tab)| {
+                    format!(
+                        "{}{}. {} — {} (handle: {})",
+                        if tab.current { "* " } else { "  " },
+                        i + 1,
+                        if tab.title.is_empty() {
+                            "(untitled)"
+                        } else {
+                            &tab.title
+                        },
+                        tab.url,
+                        tab.handle
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Err(e) => Ok(format!("❌ Failed to list tabs: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_switch_tab(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_switch_tab tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+        drop(session_guard);
+
+        let mut driver = session.lock().await;
+        let handles = match driver.window_handles().await {
+            Ok(handles) => handles,
+            Err(e) => return Ok(format!("❌ Failed to list tabs: {}", e)),
+        };
+        let handle = match webdriver_tab_handle(&handles, &tool_call.args) {
+            Ok(Some(handle)) => handle,
+            Ok(None) => return Ok("❌ Missing index or handle argument".to_string()),
+            Err(e) => return Ok(format!("❌ {}", e)),
+        };
+        match driver.switch_to_window(&handle).await {
+            Ok(_) => {
+                let title = driver.title().await.unwrap_or_default();
+                let url = driver.current_url().await.unwrap_or_default();
+                Ok(format!("✅ Switched to {} — {}", title, url))
+            }
+            Err(e) => Ok(format!("❌ Failed to switch tab: {}", e)),
+        }
+    }
+
+    async fn run_webdriver_close_tab(&self, tool_call: &ToolCall) -> Result<String> {
+        debug!("Processing webdriver_close_tab tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        let session_guard = self.webdriver_session.read().await;
+        let session = match session_guard.as_ref() {
+            Some(s) => s.clone(),
+            None => {
+                return Ok(
+                    "❌ No active WebDriver session. Call webdriver_start first.".to_string(),
+                )
+            }
+        };
+        drop(session_guard);
+
+        let mut driver = session.lock().await;
+        let handles = match driver.window_handles().await {
+            Ok(handles) => handles,
+            Err(e) => return Ok(format!("❌ Failed to list tabs: {}", e)),
+        };
+        if handles.len() < 2 {
+            return Ok(
+                "❌ This is the only open tab. Use webdriver_quit to close the browser."
+                    .to_string(),
+            );
+        }
+        let current = driver.current_window_handle().await.ok();
+        let target = match webdriver_tab_handle(&handles, &tool_call.args) {
+            Ok(Some(handle)) => handle,
+            Ok(None) => match &current {
+                Some(handle) => handle.clone(),
+                None => return Ok("❌ No current tab to close".to_string()),
+            },
+            Err(e) => return Ok(format!("❌ {}", e)),
+        };
+        if current.as_deref() != Some(target.as_str()) {
+            if let Err(e) = driver.switch_to_window(&target).await {
+                return Ok(format!("❌ Failed to switch to tab {}: {}", target, e));
+            }
+        }
+        if let Err(e) = driver.close_window().await {
+            return Ok(format!("❌ Failed to close tab: {}", e));
+        }
+        // Commands need a window to go to: go back to the one that was current, or
+        // the first one left if that was the one closed
+        let next = current
+            .filter(|handle| *handle != target)
+            .or_else(|| handles.iter().find(|handle| **handle != target).cloned());
+        match next {
+            Some(next) => match driver.switch_to_window(&next).await {
+                Ok(_) => {
+                    let url = driver.current_url().await.unwrap_or_default();
+                    Ok(format!("✅ Closed tab {}; now on {}", target, url))
+                }
+                Err(e) => Ok(format!(
+                    "❌ Closed tab {} but failed to switch to another: {}",
+                    target, e
+                )),
+            },
+            None => Ok(format!("✅ Closed tab {}", target)),
+        }
+    }
+
+    async fn run_webdriver_quit(&self) -> Result<String> {
+        debug!("Processing webdriver_quit tool call");
+
+        if !self.config.webdriver.enabled {
+            return Ok("❌ WebDriver is not enabled. Use --webdriver flag to enable.".to_string());
+        }
+
+        // Take the session
+        self.webdriver_last_url.lock().unwrap().take();
+        let session = match self.webdriver_session.write().await.take() {
+            Some(s) => s.clone(),
+            None => return Ok("❌ No active WebDriver session.".to_string()),
+        };
+
+        // Quit the WebDriver session
+        match std::sync::Arc::try_unwrap(session) {
+            Ok(mutex) => {
+                let driver = mutex.into_inner();
+                match driver.quit().await {
+                    Ok(_) => {
+                        info!("WebDriver session closed successfully");
+
+                        // Kill the safaridriver process
+                        if let Some(mut process) = self.safaridriver_process.write().await.take() {
+                            if let Err(e) = process.kill().await {
+                                warn!("Failed to kill safaridriver process: {}", e);
+                            } else {
+                                info!("Safaridriver process terminated");
+                            }
+                        }
+
+                        Ok("✅ WebDriver session closed and safaridriver stopped".to_string())
+                    }
+                    Err(e) => Ok(format!("❌ Failed to quit WebDriver: {}", e)),
+                }
+            }
+            Err(_) => Ok("❌ Cannot quit: WebDriver session is still in use".to_string()),
+        }
+    }
+}
@@ -0,0 +1,574 @@
+//! Whole turns of an agent answered by a [`ScriptedProvider`].
+
+use crate::context::ContextWindow;
+use crate::events::AgentEvent;
+use crate::partial_tool_call::PARTIAL_SUFFIX;
+use crate::test_provider::{agent, events, received, Response, ScriptedProvider};
+use crate::ui_writer::NullUiWriter;
+use g3_config::Config;
+use g3_providers::{Capabilities, Message, MessageRole};
+use serde_json::json;
+use std::path::Path;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_plain_output_keeps_tool_results_raw_in_the_context() {
+    let line = "a → b — c… • done ✓ ┌─┐ 🎉";
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, format!("{}\n", line)).unwrap();
+
+    let provider = ScriptedProvider::new(vec![
+        Response::tool_call(
+            "read_file",
+            json!({"file_path": path.display().to_string()}),
+        ),
+        Response::text(&["Read it."]),
+    ]);
+    let mut config = Config::default();
+    config.agent.plain_output = true;
+    let (mut agent, _artifacts) = agent(provider, config).await;
+    let mut events = events(&agent);
+
+    agent
+        .execute_task("read notes.txt", None, false)
+        .await
+        .unwrap();
+
+    let result = agent
+        .context_window
+        .conversation_history
+        .iter()
+        .find(|m| matches!(m.role, MessageRole::User) && m.content.starts_with("Tool result:"))
+        .unwrap();
+    assert!(result.content.contains(line), "{}", result.content);
+
+    // Only what is shown is made plain
+    let shown = received(&mut events)
+        .into_iter()
+        .find_map(|event| match event {
+            AgentEvent::ToolFinished { result, .. } => Some(result),
+            _ => None,
+        })
+        .unwrap();
+    assert!(!shown.contains('→') && !shown.contains('🎉'), "{}", shown);
+}
+
+#[tokio::test]
+async fn test_summary_budget_comes_from_the_provider_capabilities() {
+    let provider = ScriptedProvider::new(Vec::new()).with_capabilities(Capabilities {
+        context_window: Some(8_000),
+        max_output_tokens: Some(2_000),
+        ..Capabilities::default()
+    });
+    let (agent, _artifacts) = agent(provider, Config::default()).await;
+    let provider = agent.providers.get(None).unwrap();
+
+    // 8k window, less a 1k buffer and the conversation, and at most the output limit
+    assert_eq!(
+        agent.summary_max_tokens(provider, 0, 50_000).unwrap(),
+        2_000
+    );
+    assert_eq!(agent.summary_max_tokens(provider, 0, 1_500).unwrap(), 1_500);
+    assert_eq!(
+        agent.summary_max_tokens(provider, 6_000, 50_000).unwrap(),
+        1_000
+    );
+    assert!(agent.summary_max_tokens(provider, 7_000, 50_000).is_err());
+}
+
+#[tokio::test]
+async fn test_summary_fits_a_small_summarizer_window() {
+    let summarizer = ScriptedProvider::new(vec![Response::text(&["The summary."])])
+        .with_capabilities(Capabilities {
+            context_window: Some(4_000),
+            max_output_tokens: Some(4_000),
+            ..Capabilities::default()
+        });
+    let requests = summarizer.requests();
+    let (mut agent, _artifacts) = agent(ScriptedProvider::new(Vec::new()), Config::default()).await;
+    agent
+        .providers
+        .register_as(g3_config::SUMMARIZER_MODEL, Box::new(summarizer));
+    for i in 0..100 {
+        agent.context_window.add_message(Message {
+            role: MessageRole::User,
+            content: format!("message {} {}", i, "lorem ipsum dolor sit amet ".repeat(40)),
+        });
+    }
+
+    assert!(agent.force_summarize().await.unwrap());
+
+    let requests = requests.lock().unwrap();
+    let request = &requests[0];
+    let prompt: u32 = request
+        .messages
+        .iter()
+        .map(|m| crate::tokenizer::count_message_tokens("scripted-model", m))
+        .sum();
+    assert!(prompt + request.max_tokens.unwrap() <= 4_000, "{}", prompt);
+    assert!(request.max_tokens.unwrap() >= 1_000);
+    let conversation = &request.messages[1].content;
+    assert!(conversation.contains("message 99 "));
+    assert!(!conversation.contains("message 0 "));
+    assert!(conversation.contains("earlier messages left out"));
+}
+
+#[tokio::test]
+async fn test_summary_keeps_the_end_of_a_message_larger_than_the_window() {
+    let summarizer = ScriptedProvider::new(vec![Response::text(&["The summary."])])
+        .with_capabilities(Capabilities {
+            context_window: Some(4_000),
+            ..Capabilities::default()
+        });
+    let requests = summarizer.requests();
+    let (mut agent, _artifacts) = agent(ScriptedProvider::new(Vec::new()), Config::default()).await;
+    agent
+        .providers
+        .register_as(g3_config::SUMMARIZER_MODEL, Box::new(summarizer));
+    agent.context_window.add_message(Message {
+        role: MessageRole::User,
+        content: format!("{} the end", "lorem ipsum dolor sit amet ".repeat(2_000)),
+    });
+
+    assert!(agent.force_summarize().await.unwrap());
+
+    let requests = requests.lock().unwrap();
+    let conversation = &requests[0].messages[1].content;
+    assert!(conversation.contains("[... start left out]"));
+    assert!(conversation.ends_with("the end"));
+}
+
+#[tokio::test]
+async fn test_semantic_search_is_described_as_lexical_with_hashed_embeddings() {
+    let description = |agent: &crate::Agent<_>| {
+        agent
+            .tool_definitions()
+            .into_iter()
+            .find(|tool| tool.name == "code_search_semantic")
+            .unwrap()
+            .description
+    };
+    let (hashed, _artifacts) = agent(ScriptedProvider::new(Vec::new()), Config::default()).await;
+    assert!(description(&hashed).contains("lexical"));
+
+    let mut config = Config::default();
+    config.embeddings.provider = "openai".to_string();
+    let (openai, _artifacts) = agent(ScriptedProvider::new(Vec::new()), config).await;
+    assert!(!description(&openai).contains("lexical"));
+}
+
+/// Whether `messages` tell the model to wrap up in a system message
+fn told_to_wrap_up(messages: &[Message]) -> bool {
+    messages
+        .iter()
+        .any(|m| matches!(m.role, MessageRole::System) && m.content == crate::WRAP_UP_PROMPT)
+}
+
+#[tokio::test]
+async fn test_response_streaming_past_the_time_limit_is_cut_short() {
+    let provider = ScriptedProvider::new(vec![
+        Response::text(&["Looking ", "at ", "the ", "whole ", "codebase ", "first."])
+            .every(Duration::from_millis(300)),
+        Response::text(&["Out of time, here is where I got to."]),
+    ]);
+    let requests = provider.requests();
+    let mut config = Config::default();
+    config.agent.turn_timeout_seconds = 1;
+    let (mut agent, _artifacts) = agent(provider, config).await;
+
+    agent
+        .execute_task("review everything", None, false)
+        .await
+        .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(!told_to_wrap_up(&requests[0].messages));
+    let messages = &requests[1].messages;
+    assert!(matches!(messages.last().unwrap().role, MessageRole::System));
+    assert!(told_to_wrap_up(messages));
+    // What streamed before the cut stays in the context
+    let partial = &messages[messages.len() - 2];
+    assert!(matches!(partial.role, MessageRole::Assistant));
+    assert!(partial.content.starts_with("Looking"));
+    assert!(!partial.content.contains("first."));
+}
+
+#[tokio::test]
+async fn test_time_limit_passed_during_a_tool_call_is_a_system_message() {
+    let provider = ScriptedProvider::new(vec![
+        Response::tool_call("shell", json!({"command": "sleep 1.2"})),
+        Response::text(&["Done."]),
+    ]);
+    let requests = provider.requests();
+    let mut config = Config::default();
+    config.agent.turn_timeout_seconds = 1;
+    let (mut agent, _artifacts) = agent(provider, config).await;
+
+    agent.execute_task("wait", None, false).await.unwrap();
+
+    let requests = requests.lock().unwrap();
+    let messages = &requests[1].messages;
+    assert!(told_to_wrap_up(messages));
+    let result = &messages[messages.len() - 2];
+    assert!(result.content.starts_with("Tool result:"));
+    assert!(!result.content.contains(crate::WRAP_UP_PROMPT));
+}
+
+/// A config whose streams count as stalled after a second without data, tried `attempts`
+/// times
+fn stalling_config(attempts: u32) -> Config {
+    let mut config = Config::default();
+    config.agent.stream_stall_timeout_seconds = 1;
+    config.agent.max_retry_attempts = attempts;
+    config
+}
+
+fn shown_text(events: Vec<AgentEvent>) -> String {
+    events
+        .into_iter()
+        .filter_map(|event| match event {
+            AgentEvent::TextDelta { text } => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_stream_that_never_yields_is_retried() {
+    let provider = ScriptedProvider::new(vec![
+        Response::text(&[]).stalling(),
+        Response::text(&["Answer."]),
+    ]);
+    let requests = provider.requests();
+    let (mut agent, _artifacts) = agent(provider, stalling_config(3)).await;
+    let mut events = events(&agent);
+
+    agent.execute_task("answer", None, false).await.unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    // Nothing streamed, so the same request is sent again
+    assert_eq!(requests[0].messages.len(), requests[1].messages.len());
+    let events = received(&mut events);
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, AgentEvent::ResponseDiscarded)));
+    assert_eq!(shown_text(events), "Answer.");
+}
+
+#[tokio::test]
+async fn test_stream_that_never_yields_gives_up_after_the_retries() {
+    let provider = ScriptedProvider::new(vec![
+        Response::text(&[]).stalling(),
+        Response::text(&[]).stalling(),
+        Response::text(&["Never sent."]),
+    ]);
+    let requests = provider.requests();
+    let (mut agent, _artifacts) = agent(provider, stalling_config(2)).await;
+
+    let error = agent.execute_task("answer", None, false).await.unwrap_err();
+
+    assert!(error.to_string().contains("stalled"), "{}", error);
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_stall_after_text_resumes_without_showing_it_twice() {
+    let provider = ScriptedProvider::new(vec![
+        Response::text(&["The fix is "]).stalling(),
+        Response::text(&["in main.rs."]),
+    ]);
+    let requests = provider.requests();
+    let (mut agent, _artifacts) = agent(provider, stalling_config(3)).await;
+    let mut events = events(&agent);
+
+    agent
+        .execute_task("where is the fix?", None, false)
+        .await
+        .unwrap();
+
+    let requests = requests.lock().unwrap();
+    let messages = &requests[1].messages;
+    let partial = &messages[messages.len() - 2];
+    assert!(matches!(partial.role, MessageRole::Assistant));
+    assert_eq!(partial.content, "The fix is ");
+    assert_eq!(messages.last().unwrap().content, crate::RESUME_PROMPT);
+    assert_eq!(shown_text(received(&mut events)), "The fix is in main.rs.");
+    // The interrupted part is counted once, with the rest
+    assert_eq!(
+        agent.output_tokens,
+        ContextWindow::estimate_tokens("The fix is ")
+            + ContextWindow::estimate_tokens("in main.rs.")
+    );
+}
+
+#[tokio::test]
+async fn test_stall_with_nothing_to_resume_discards_what_was_shown() {
+    let provider = ScriptedProvider::new(vec![
+        Response::text(&["\n"]).stalling(),
+        Response::text(&["Answer."]),
+    ]);
+    let (mut agent, _artifacts) = agent(provider, stalling_config(3)).await;
+    let mut events = events(&agent);
+
+    agent.execute_task("answer", None, false).await.unwrap();
+
+    let events = received(&mut events);
+    let discarded = events
+        .iter()
+        .position(|event| matches!(event, AgentEvent::ResponseDiscarded))
+        .expect("the shown response is discarded");
+    let restarted = events
+        .iter()
+        .rposition(|event| matches!(event, AgentEvent::ResponseStarted))
+        .unwrap();
+    assert!(discarded < restarted);
+}
+
+#[tokio::test]
+async fn test_remote_session_refuses_local_edits_and_keeps_env_files_local() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("main.rs");
+    std::fs::write(&path, "fn main() {}\n").unwrap();
+    std::fs::write(dir.path().join(".env"), "G3_TEST_REMOTE_TOKEN=secret\n").unwrap();
+    let provider = ScriptedProvider::new(vec![
+        Response::tool_call(
+            "str_replace",
+            json!({
+                "file_path": path.display().to_string(),
+                "diff": "-fn main() {}\n+fn main() { run() }\n",
+            }),
+        ),
+        Response::text(&["Done."]),
+    ]);
+    let requests = provider.requests();
+    let mut config = Config::default();
+    config.remote.host = Some("ci@build-01".to_string());
+    let (mut agent, _artifacts) = agent(provider, config).await;
+    agent.project_env = g3_config::env::ProjectEnv::load(dir.path()).unwrap();
+
+    agent
+        .execute_task("fix main.rs", None, false)
+        .await
+        .unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+    let requests = requests.lock().unwrap();
+    let result = &requests[1].messages.last().unwrap().content;
+    assert!(result.contains("only edits local files"), "{}", result);
+    assert!(agent.command_env().is_empty());
+    agent.config.remote.forward_env = true;
+    assert!(!agent.command_env().is_empty());
+}
+
+#[tokio::test]
+async fn test_content_filtered_response_ends_the_turn_without_a_retry() {
+    let provider = ScriptedProvider::new(vec![
+        Response::content_filtered(&["Here is how "]),
+        Response::text(&["Never sent."]),
+    ]);
+    let requests = provider.requests();
+    let (mut agent, _artifacts) = agent(provider, stalling_config(3)).await;
+
+    let error = agent.execute_task("do it", None, false).await.unwrap_err();
+
+    assert!(
+        crate::error_handling::G3Error::is_content_filtered(&error),
+        "{}",
+        error
+    );
+    assert!(error.to_string().contains("the content filter blocked it"));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+/// The last message of the `request`th request: the result of the tool call before it
+fn last_tool_result(requests: &[g3_providers::CompletionRequest], request: usize) -> String {
+    requests[request].messages.last().unwrap().content.clone()
+}
+
+#[tokio::test]
+async fn test_show_changes_diffs_the_session_edits_and_two_files() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let edited = dir.path().join("edited.txt");
+    let other = dir.path().join("other.txt");
+    std::fs::write(&edited, "first\n").unwrap();
+    std::fs::write(&other, "first\nsecond\n").unwrap();
+    let (edited_path, other_path) = (edited.display().to_string(), other.display().to_string());
+
+    let provider = ScriptedProvider::new(vec![
+        Response::tool_call(
+            "write_file",
+            json!({"file_path": edited_path, "content": "first\nadded\n"}),
+        ),
+        Response::tool_call("show_changes", json!({})),
+        Response::tool_call("show_changes", json!({"paths": [other_path]})),
+        Response::tool_call(
+            "show_changes",
+            json!({"file_a": edited_path, "file_b": other_path}),
+        ),
+        Response::tool_call("show_changes", json!({"file_a": edited_path})),
+        Response::text(&["Checked."]),
+    ]);
+    let requests = provider.requests();
+    let (mut agent, _artifacts) = agent(provider, Config::default()).await;
+
+    agent
+        .execute_task("add a line and check it", None, false)
+        .await
+        .unwrap();
+
+    let requests = requests.lock().unwrap();
+    let session = last_tool_result(&requests, 2);
+    assert!(session.contains(" first\n+added"), "{}", session);
+    let unchanged = last_tool_result(&requests, 3);
+    assert!(
+        unchanged.contains("haven't changed any of those files"),
+        "{}",
+        unchanged
+    );
+    let compared = last_tool_result(&requests, 4);
+    assert!(
+        compared.contains(&format!("+++ {}", other_path)),
+        "{}",
+        compared
+    );
+    assert!(compared.contains("-added\n+second"), "{}", compared);
+    let one_file = last_tool_result(&requests, 5);
+    assert!(
+        one_file.contains("Pass both file_a and file_b"),
+        "{}",
+        one_file
+    );
+}
+
+#[tokio::test]
+async fn test_reviewing_player_delivers_its_verdict_with_give_feedback() {
+    let feedback = json!({
+        "verdict": "changes_requested",
+        "issues": ["The --json flag is parsed but never used"],
+        "next_steps": "Print the results as JSON when --json is set.",
+    });
+    let provider = ScriptedProvider::new(vec![
+        Response::tool_call("give_feedback", feedback.clone()),
+        Response::text(&["Done."]),
+        Response::tool_call("give_feedback", feedback),
+        Response::text(&["Reviewed."]),
+    ]);
+    let requests = provider.requests();
+    let (mut agent, _artifacts) = agent(provider, Config::default()).await;
+    let offers_feedback = |agent: &crate::Agent<_>| {
+        agent
+            .tool_definitions()
+            .iter()
+            .any(|tool| tool.name == "give_feedback")
+    };
+
+    // While implementing, the player can't hand in a verdict
+    assert!(!offers_feedback(&agent));
+    let result = agent.execute_task("implement", None, false).await.unwrap();
+    assert!(result.feedback.is_none());
+    let refused = last_tool_result(&requests.lock().unwrap(), 1);
+    assert!(refused.contains("only the coach"), "{}", refused);
+
+    agent.set_reviewing(true);
+    assert!(offers_feedback(&agent));
+    let result = agent.execute_task("review", None, false).await.unwrap();
+    agent.set_reviewing(false);
+
+    let verdict = result.feedback.expect("the review's verdict");
+    assert!(!verdict.is_approved());
+    assert_eq!(verdict.issues, ["The --json flag is parsed but never used"]);
+    assert!(!offers_feedback(&agent));
+}
+
+/// A response streaming a long `write_file` call to `path` that ends before the call is made
+fn streaming_write(path: &Path) -> ScriptedProvider {
+    // The path comes first, as models send it, so the file is written as the content arrives
+    let args = format!(
+        r#"{{"file_path":{},"content":{}}}"#,
+        json!(path.display().to_string()),
+        json!("line of the file\n".repeat(200)),
+    );
+    ScriptedProvider::new(vec![Response::streaming_tool_call(
+        "write_file",
+        &args,
+        10,
+        "Stopped.",
+    )
+    .every(Duration::from_millis(20))])
+}
+
+/// Run a turn and say whether `<path>.g3-partial` appeared while its response streamed
+async fn partial_file_appears(agent: &mut crate::Agent<NullUiWriter>, path: &Path) -> bool {
+    let partial = format!("{}{}", path.display(), PARTIAL_SUFFIX);
+    let seen = std::cell::Cell::new(false);
+    let watch = async {
+        for _ in 0..100 {
+            if Path::new(&partial).exists() {
+                seen.set(true);
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    };
+    let (result, ()) = tokio::join!(agent.execute_task("write it", None, false), watch);
+    result.unwrap();
+    assert!(!Path::new(&partial).exists());
+    seen.get()
+}
+
+struct RunEverything;
+
+impl crate::step::ToolStepper for RunEverything {
+    fn before_tool_call(&self, _tool_call: &crate::ToolCall) -> crate::step::StepDecision {
+        crate::step::StepDecision::Run
+    }
+}
+
+#[tokio::test]
+async fn test_progressive_writes_are_off_while_a_call_may_still_be_skipped_or_blocked() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("notes.txt");
+    let mut config = Config::default();
+    config.agent.progressive_writes = true;
+
+    let (mut writer, _artifacts) = agent(streaming_write(&path), config.clone()).await;
+    assert!(partial_file_appears(&mut writer, &path).await);
+
+    // --step may skip the call
+    let (mut stepped, _artifacts) = agent(streaming_write(&path), config.clone()).await;
+    stepped.set_stepper(std::sync::Arc::new(RunEverything));
+    assert!(!partial_file_appears(&mut stepped, &path).await);
+
+    // A moderation hook may block it
+    let mut moderated_config = config.clone();
+    moderated_config.moderation.post_receive = Some("cat >/dev/null".to_string());
+    let (mut moderated, _artifacts) = agent(streaming_write(&path), moderated_config).await;
+    assert!(!partial_file_appears(&mut moderated, &path).await);
+
+    // So may a budget it would go over
+    let mut budgeted_config = config;
+    budgeted_config.budgets.insert(
+        "write_file".to_string(),
+        g3_config::ToolBudget {
+            per_turn: Some(0),
+            ..Default::default()
+        },
+    );
+    let (mut budgeted, _artifacts) = agent(streaming_write(&path), budgeted_config).await;
+    assert!(!partial_file_appears(&mut budgeted, &path).await);
+}
+
+#[tokio::test]
+async fn test_progressive_writes_leave_local_files_alone_on_a_remote_session() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("notes.txt");
+    let mut config = Config::default();
+    config.agent.progressive_writes = true;
+    // The call would write the host's file, so nothing is written to the local path
+    config.remote.host = Some("ci@build-01".to_string());
+
+    let (mut remote, _artifacts) = agent(streaming_write(&path), config).await;
+    assert!(!partial_file_appears(&mut remote, &path).await);
+}
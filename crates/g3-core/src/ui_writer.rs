@@ -1,3 +1,5 @@
+use crate::stream_rate::StreamProgress;
+
 /// Interface for UI output operations
 /// This trait abstracts all UI operations to allow different implementations
 /// (console, TUI, web, etc.) without coupling the core logic to specific output methods.
@@ -46,9 +48,19 @@ pub trait UiWriter: Send + Sync {
     
     /// Print agent response inline (for streaming)
     fn print_agent_response(&self, content: &str);
+
+    /// Print a streamed summary of the model's reasoning. Not shown by default.
+    fn print_agent_reasoning(&self, _text: &str) {}
     
     /// Notify that an SSE event was received (including pings)
     fn notify_sse_received(&self);
+
+    /// Show the elapsed time and speed of the streaming response, in place. Not shown by
+    /// default.
+    fn print_stream_progress(&self, _progress: &StreamProgress) {}
+
+    /// Remove the progress of the last streamed response, if it is still shown
+    fn clear_stream_progress(&self) {}
     
     /// Flush any buffered output
     fn flush(&self);
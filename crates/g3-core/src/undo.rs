@@ -0,0 +1,239 @@
+//! Per-session undo stack for file modifications.
+//!
+//! Before `write_file`, `str_replace` or `edit_symbol` runs, the content of the file it
+//! targets is captured; if the tool changed the file, the previous content is stored in
+//! `.g3/sessions/<session id>/undo/objects/` under its SHA-256 (so identical content is kept
+//! once) and an entry is pushed onto `stack.jsonl`. `undo_edit` and `/undo` pop entries and
+//! put the files back.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::ToolCall;
+
+/// Undo data, relative to the session directory
+pub const UNDO_DIR: &str = "undo";
+
+const OBJECTS_DIR: &str = "objects";
+const STACK_FILE: &str = "stack.jsonl";
+
+/// Tools whose changes are recorded
+pub const FILE_TOOLS: &[&str] = &["write_file", "str_replace", "edit_symbol"];
+
+/// A file modification that can be undone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UndoEntry {
+    pub path: PathBuf,
+    /// Hash of the content before the change; `None` if the tool created the file
+    pub previous: Option<String>,
+    pub tool: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A file's content just before a tool ran
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    path: PathBuf,
+    tool: String,
+    content: Option<Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Capture the file `tool_call` is about to modify, if it is a file tool
+    pub fn take(tool_call: &ToolCall) -> Option<Self> {
+        if !FILE_TOOLS.contains(&tool_call.tool.as_str()) {
+            return None;
+        }
+        let path = tool_call
+            .args
+            .get("file_path")
+            .or_else(|| tool_call.args.get("path"))?
+            .as_str()?;
+        let path = PathBuf::from(shellexpand::tilde(path).as_ref());
+        let path = std::path::absolute(&path).unwrap_or(path);
+        Some(Self {
+            content: std::fs::read(&path).ok(),
+            path,
+            tool: tool_call.tool.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoStack {
+    dir: PathBuf,
+}
+
+impl UndoStack {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Record `snapshot` if its file no longer has the captured content
+    pub fn push_if_changed(&self, snapshot: Snapshot) -> Result<bool> {
+        if std::fs::read(&snapshot.path).ok() == snapshot.content {
+            return Ok(false);
+        }
+        let previous = snapshot
+            .content
+            .as_deref()
+            .map(|content| self.store(content))
+            .transpose()?;
+        let entry = UndoEntry {
+            path: snapshot.path,
+            previous,
+            tool: snapshot.tool,
+            timestamp: Utc::now(),
+        };
+
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(STACK_FILE))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(true)
+    }
+
+    /// Recorded modifications, oldest first
+    pub fn entries(&self) -> Result<Vec<UndoEntry>> {
+        let content = match std::fs::read_to_string(self.dir.join(STACK_FILE)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).context("Corrupt undo stack entry"))
+            .collect()
+    }
+
+    /// Revert the last `count` modifications, newest first, and describe what was done
+    pub fn undo(&self, count: usize) -> Result<Vec<String>> {
+        let mut entries = self.entries()?;
+        let mut undone = Vec::new();
+        while undone.len() < count {
+            let Some(entry) = entries.pop() else {
+                break;
+            };
+            let description = match &entry.previous {
+                Some(hash) => {
+                    let content = std::fs::read(self.object_path(hash)).with_context(|| {
+                        format!("Missing undo data for {}", entry.path.display())
+                    })?;
+                    if let Some(parent) = entry.path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&entry.path, content)?;
+                    format!(
+                        "Restored {} to its content before {}",
+                        entry.path.display(),
+                        entry.tool
+                    )
+                }
+                None => {
+                    if let Err(e) = std::fs::remove_file(&entry.path) {
+                        if e.kind() != std::io::ErrorKind::NotFound {
+                            return Err(e.into());
+                        }
+                    }
+                    format!(
+                        "Deleted {}, which {} created",
+                        entry.path.display(),
+                        entry.tool
+                    )
+                }
+            };
+            undone.push(description);
+
+            // Rewrite after each step so a failure part way leaves the stack consistent
+            let stack: String = entries
+                .iter()
+                .map(|e| serde_json::to_string(e).map(|line| line + "\n"))
+                .collect::<serde_json::Result<_>>()?;
+            std::fs::write(self.dir.join(STACK_FILE), stack)?;
+        }
+        Ok(undone)
+    }
+
+    /// Where content stored under `hash` is kept
+    pub fn object_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(OBJECTS_DIR).join(hash)
+    }
+
+    /// Forget every recorded modification, once the files have been put back some other
+    /// way. Stored content is kept.
+    pub fn clear(&self) -> Result<()> {
+        match std::fs::remove_file(self.dir.join(STACK_FILE)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Store `content` under its hash, once
+    pub fn store(&self, content: &[u8]) -> Result<String> {
+        let hash = format!("{:x}", Sha256::digest(content));
+        let path = self.object_path(&hash);
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+            std::fs::write(&path, content)?;
+        }
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(tool: &str, path: &Path) -> ToolCall {
+        ToolCall {
+            tool: tool.to_string(),
+            args: json!({"file_path": path.to_str().unwrap(), "content": "x"}),
+        }
+    }
+
+    #[test]
+    fn test_undo_stack() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let stack = UndoStack::new(dir.path().join("undo"));
+        let file = dir.path().join("notes.txt");
+
+        // Creating the file, then changing it twice to content it had before
+        let snapshot = Snapshot::take(&call("write_file", &file)).unwrap();
+        std::fs::write(&file, "one").unwrap();
+        assert!(stack.push_if_changed(snapshot).unwrap());
+        for content in ["two", "one"] {
+            let snapshot = Snapshot::take(&call("str_replace", &file)).unwrap();
+            std::fs::write(&file, content).unwrap();
+            assert!(stack.push_if_changed(snapshot).unwrap());
+        }
+
+        // A tool call that didn't change the file is not recorded
+        let snapshot = Snapshot::take(&call("str_replace", &file)).unwrap();
+        assert!(!stack.push_if_changed(snapshot).unwrap());
+        assert!(Snapshot::take(&call("read_file", &file)).is_none());
+
+        assert_eq!(stack.entries().unwrap().len(), 3);
+        // "one" is stored once
+        let objects = std::fs::read_dir(dir.path().join("undo/objects")).unwrap();
+        assert_eq!(objects.count(), 2);
+
+        let undone = stack.undo(2).unwrap();
+        assert_eq!(undone.len(), 2);
+        assert!(undone[0].starts_with("Restored"));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "one");
+
+        let undone = stack.undo(5).unwrap();
+        assert_eq!(undone.len(), 1);
+        assert!(undone[0].starts_with("Deleted"));
+        assert!(!file.exists());
+        assert!(stack.entries().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! Unity Catalog functions offered to the agent as tools, discovered when it starts.
+
+use anyhow::{anyhow, Result};
+use g3_config::Config;
+use g3_providers::databricks::DatabricksAuth;
+use g3_providers::unity_catalog::{CatalogFunction, UnityCatalogClient, TOOL_PREFIX};
+use g3_providers::Tool;
+use serde_json::Value;
+use tracing::{debug, warn};
+
+const DEFAULT_MAX_ROWS: usize = 100;
+
+pub struct UnityCatalogTools {
+    client: UnityCatalogClient,
+    functions: Vec<CatalogFunction>,
+    warehouse_id: String,
+    max_rows: usize,
+}
+
+impl UnityCatalogTools {
+    /// Discover the functions of the schemas in `[unity_catalog]`. `None` when it is
+    /// disabled; schemas that can't be listed are skipped with a warning.
+    pub async fn discover(config: &Config) -> Result<Option<Self>> {
+        let uc = &config.unity_catalog;
+        if !uc.enabled {
+            return Ok(None);
+        }
+        let databricks = config.providers.databricks.as_ref().ok_or_else(|| {
+            anyhow!("[unity_catalog] needs the Databricks credentials in [providers.databricks]")
+        })?;
+        let warehouse_id = uc
+            .warehouse_id
+            .clone()
+            .ok_or_else(|| anyhow!("[unity_catalog] needs a warehouse_id to run functions on"))?;
+
+        let auth = match databricks.resolved_token()? {
+            Some(token) => DatabricksAuth::token(token),
+            None => DatabricksAuth::oauth(databricks.host.clone()),
+        };
+        let client = UnityCatalogClient::new(&databricks.host, auth)?;
+
+        let mut functions = Vec::new();
+        for schema in &uc.schemas {
+            match client.list_functions(schema).await {
+                Ok(found) => {
+                    debug!(
+                        "Found {} Unity Catalog functions in {}",
+                        found.len(),
+                        schema
+                    );
+                    functions.extend(found);
+                }
+                Err(e) => warn!(
+                    "Failed to list Unity Catalog functions in {}: {}",
+                    schema, e
+                ),
+            }
+        }
+
+        Ok(Some(Self {
+            client,
+            functions,
+            warehouse_id,
+            max_rows: uc.max_rows.unwrap_or(DEFAULT_MAX_ROWS),
+        }))
+    }
+
+    pub fn is_tool(name: &str) -> bool {
+        name.starts_with(TOOL_PREFIX)
+    }
+
+    pub fn functions(&self) -> &[CatalogFunction] {
+        &self.functions
+    }
+
+    pub fn tools(&self) -> Vec<Tool> {
+        self.functions.iter().map(CatalogFunction::tool).collect()
+    }
+
+    /// Call the function behind tool `name`
+    pub async fn call(&self, name: &str, args: &Value) -> Result<String> {
+        let function = self
+            .functions
+            .iter()
+            .find(|f| f.tool_name() == name)
+            .ok_or_else(|| anyhow!("No Unity Catalog function for tool {}", name))?;
+        self.client
+            .call(function, args, &self.warehouse_id, self.max_rows)
+            .await
+    }
+
+    /// The functions described for providers without native tool calling
+    pub fn prompt_reference(&self) -> String {
+        let mut reference = String::from("\n\n# Unity Catalog Functions\n");
+        for tool in self.tools() {
+            let params = tool.input_schema["properties"]
+                .as_object()
+                .map(|p| p.keys().cloned().collect::<Vec<_>>().join(", "))
+                .unwrap_or_default();
+            reference.push_str(&format!(
+                "\n- **{}** ({}) [network]: {}",
+                tool.name, params, tool.description
+            ));
+        }
+        reference
+    }
+}
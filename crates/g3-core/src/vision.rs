@@ -0,0 +1,133 @@
+//! Vision-model UI element location for the vision_locate tool.
+//!
+//! The model is asked for a bounding box normalized to 0-1000 on both axes, which keeps the
+//! answer independent of any resizing the provider applies to the image before the model
+//! sees it. Boxes are converted back to screenshot pixels here.
+
+use g3_computer_control::types::TextLocation;
+use serde::Deserialize;
+
+/// Scale of the normalized coordinates the model is asked for
+const NORMALIZED_SCALE: f64 = 1000.0;
+
+pub fn locate_prompt(description: &str) -> String {
+    format!(
+        "This is a screenshot of an application window. Find this UI element: {}\n\n\
+         Respond with only a JSON object and no other text. If the element is visible, respond with \
+         {{\"found\": true, \"x\": <left>, \"y\": <top>, \"width\": <width>, \"height\": <height>}} \
+         where all values are integers from 0 to 1000, relative to the image width (x, width) and \
+         height (y, height), measured from the top-left corner. If it is not visible, respond with \
+         {{\"found\": false}}.",
+        description
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct NormalizedBox {
+    found: bool,
+    #[serde(default)]
+    x: f64,
+    #[serde(default)]
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+}
+
+/// Parse the model's answer into a box in screenshot pixels. Returns `Ok(None)` when the
+/// model reports the element is not visible.
+pub fn parse_bounding_box(
+    response: &str,
+    description: &str,
+    image_size: (i32, i32),
+) -> Result<Option<TextLocation>, String> {
+    // Models sometimes wrap the JSON in a code fence or a sentence
+    let start = response.find('{');
+    let end = response.rfind('}');
+    let json = match (start, end) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => {
+            return Err(format!(
+                "no JSON object in vision response: {}",
+                response.trim()
+            ))
+        }
+    };
+    let normalized: NormalizedBox = serde_json::from_str(json)
+        .map_err(|e| format!("invalid vision response '{}': {}", json, e))?;
+
+    if !normalized.found {
+        return Ok(None);
+    }
+    if normalized.width <= 0.0 || normalized.height <= 0.0 {
+        return Err(format!(
+            "vision response has an empty bounding box: {}",
+            json
+        ));
+    }
+
+    let (image_width, image_height) = image_size;
+    let scale = |value: f64, size: i32| {
+        (value.clamp(0.0, NORMALIZED_SCALE) / NORMALIZED_SCALE * size as f64).round() as i32
+    };
+    Ok(Some(TextLocation {
+        text: description.to_string(),
+        x: scale(normalized.x, image_width),
+        y: scale(normalized.y, image_height),
+        width: scale(normalized.width, image_width).max(1),
+        height: scale(normalized.height, image_height).max(1),
+        confidence: 1.0,
+    }))
+}
+
+/// Width and height of a PNG image from its IHDR chunk
+pub fn png_dimensions(data: &[u8]) -> Option<(i32, i32)> {
+    if data.len() < 24 || &data[0..8] != b"\x89PNG\r\n\x1a\n" {
+        return None;
+    }
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]) as i32;
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]) as i32;
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bounding_box_scales_to_pixels() {
+        let response = "```json\n{\"found\": true, \"x\": 500, \"y\": 250, \"width\": 100, \"height\": 50}\n```";
+        let location = parse_bounding_box(response, "Submit button", (2000, 1000))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (location.x, location.y, location.width, location.height),
+            (1000, 250, 200, 50)
+        );
+        assert_eq!(location.text, "Submit button");
+    }
+
+    #[test]
+    fn test_parse_bounding_box_not_found_and_errors() {
+        assert!(parse_bounding_box("{\"found\": false}", "x", (100, 100))
+            .unwrap()
+            .is_none());
+        assert!(parse_bounding_box("I can't see it", "x", (100, 100)).is_err());
+        assert!(parse_bounding_box(
+            "{\"found\": true, \"x\": 1, \"y\": 1, \"width\": 0, \"height\": 0}",
+            "x",
+            (100, 100)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_png_dimensions() {
+        let mut header = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        header.extend_from_slice(&640u32.to_be_bytes());
+        header.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(png_dimensions(&header), Some((640, 480)));
+        assert_eq!(png_dimensions(b"GIF89a"), None);
+    }
+}
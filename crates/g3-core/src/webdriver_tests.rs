@@ -0,0 +1,136 @@
+//! The WebDriver check made before each WebDriver tool, against a fake WebDriver server.
+
+use crate::test_provider::{agent, ScriptedProvider};
+use crate::ToolCall;
+use g3_config::Config;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+const PAGE: &str = "https://example.com/page";
+
+/// A WebDriver server with one session on `PAGE`, answering every other command with null
+async fn fake_webdriver() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let mut read = BufReader::new(read);
+                loop {
+                    let mut request_line = String::new();
+                    if read.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let mut length = 0;
+                    loop {
+                        let mut header = String::new();
+                        read.read_line(&mut header).await.unwrap();
+                        if header.trim().is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = header.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; length];
+                    read.read_exact(&mut body).await.unwrap();
+
+                    let value = if request_line.starts_with("POST /session ") {
+                        json!({"sessionId": "s1", "capabilities": {"browserName": "safari"}})
+                    } else if request_line.starts_with("GET /session/s1/url ") {
+                        json!(PAGE)
+                    } else {
+                        json!(null)
+                    };
+                    let body = json!({ "value": value }).to_string();
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    if write.write_all(response.as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+fn call(tool: &str) -> ToolCall {
+    ToolCall {
+        tool: tool.to_string(),
+        args: json!({}),
+    }
+}
+
+/// An agent with a WebDriver session on a fake server, and safaridriver played by `sleep`
+async fn agent_with_session() -> (
+    crate::Agent<crate::ui_writer::NullUiWriter>,
+    tempfile::TempDir,
+) {
+    let port = fake_webdriver().await;
+    let mut config = Config::default();
+    config.webdriver.enabled = true;
+    config.webdriver.safari_port = port;
+    let (agent, artifacts) = agent(ScriptedProvider::new(Vec::new()), config).await;
+    let driver = g3_computer_control::SafariDriver::with_port(port)
+        .await
+        .unwrap();
+    *agent.webdriver_session.write().await = Some(Arc::new(tokio::sync::Mutex::new(driver)));
+    let process = tokio::process::Command::new("sleep")
+        .arg("30")
+        .spawn()
+        .unwrap();
+    *agent.safaridriver_process.write().await = Some(process);
+    (agent, artifacts)
+}
+
+#[tokio::test]
+async fn test_healthy_session_is_kept_and_its_page_remembered() {
+    let (agent, _artifacts) = agent_with_session().await;
+
+    // Only tools that use the session are checked
+    assert_eq!(agent.check_webdriver(&call("read_file")).await, Ok(None));
+    assert_eq!(
+        agent.check_webdriver(&call("webdriver_start")).await,
+        Ok(None)
+    );
+    assert_eq!(*agent.webdriver_last_url.lock().unwrap(), None);
+
+    assert_eq!(
+        agent.check_webdriver(&call("webdriver_get_title")).await,
+        Ok(None)
+    );
+    // Reopened if the session has to be restarted later
+    assert!(agent.webdriver_last_url.lock().unwrap().is_some());
+    assert!(agent.webdriver_session.read().await.is_some());
+}
+
+// On macOS the restart would really launch safaridriver and Safari
+#[cfg(not(target_os = "macos"))]
+#[tokio::test]
+async fn test_exited_safaridriver_is_restarted() {
+    let (agent, _artifacts) = agent_with_session().await;
+    if let Some(process) = agent.safaridriver_process.write().await.as_mut() {
+        process.kill().await.unwrap();
+    }
+
+    let error = agent
+        .check_webdriver(&call("webdriver_get_title"))
+        .await
+        .unwrap_err();
+
+    assert!(error.contains("safaridriver exited"), "{}", error);
+    assert!(error.contains("restarting it failed"), "{}", error);
+    // The dead session and process are gone, so the next call asks for webdriver_start
+    assert!(agent.webdriver_session.read().await.is_none());
+    assert!(agent.safaridriver_process.read().await.is_none());
+}
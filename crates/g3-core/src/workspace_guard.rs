@@ -0,0 +1,157 @@
+//! Checks that g3 isn't started somewhere a shell tool with the user's full permissions
+//! could do lasting damage: as root, directly in the home directory, or in a system or
+//! credentials directory. Front ends refuse such workspaces unless explicitly allowed.
+
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::g3ignore::glob_to_regex;
+
+/// Directories g3 won't use as a workspace. A trailing `/**` covers everything inside too;
+/// `agent.protected_workspaces` adds more patterns.
+pub const PROTECTED_WORKSPACES: &[&str] = &[
+    "/",
+    "/bin/**",
+    "/boot/**",
+    "/dev/**",
+    "/etc/**",
+    "/lib/**",
+    "/lib64/**",
+    "/proc/**",
+    "/root",
+    "/sbin/**",
+    "/sys/**",
+    "/usr",
+    "/usr/bin/**",
+    "/usr/lib/**",
+    "/usr/sbin/**",
+    "/var",
+    "/Applications",
+    "/Library/**",
+    "/System/**",
+    "/Users",
+    "/home",
+    "~/.aws/**",
+    "~/.config",
+    "~/.gnupg/**",
+    "~/.kube/**",
+    "~/.ssh/**",
+    "~/Library",
+];
+
+/// A reason not to run in a workspace
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkspaceDanger {
+    RunningAsRoot,
+    HomeDirectory,
+    /// Matches this protected pattern
+    Protected(String),
+}
+
+impl std::fmt::Display for WorkspaceDanger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RunningAsRoot => write!(f, "g3 is running as root"),
+            Self::HomeDirectory => write!(f, "the workspace is your home directory"),
+            Self::Protected(pattern) => {
+                write!(f, "the workspace is a protected directory ({})", pattern)
+            }
+        }
+    }
+}
+
+/// Everything that makes `workspace` dangerous to run in
+pub fn check_workspace(workspace: &Path, extra_patterns: &[String]) -> Vec<WorkspaceDanger> {
+    let mut dangers = Vec::new();
+    if running_as_root() {
+        dangers.push(WorkspaceDanger::RunningAsRoot);
+    }
+
+    let workspace = workspace
+        .canonicalize()
+        .unwrap_or_else(|_| workspace.to_path_buf());
+    let home = PathBuf::from(shellexpand::tilde("~").as_ref());
+    if home.canonicalize().unwrap_or(home) == workspace {
+        dangers.push(WorkspaceDanger::HomeDirectory);
+    }
+
+    let path = workspace.to_string_lossy();
+    let patterns = PROTECTED_WORKSPACES
+        .iter()
+        .map(|p| p.to_string())
+        .chain(extra_patterns.iter().cloned());
+    for pattern in patterns {
+        if matches_pattern(&path, &pattern) {
+            dangers.push(WorkspaceDanger::Protected(pattern));
+            break;
+        }
+    }
+    dangers
+}
+
+fn matches_pattern(path: &str, pattern: &str) -> bool {
+    let expanded = shellexpand::tilde(pattern);
+    let expanded = expanded.trim_end_matches('/');
+    // `dir/**` covers the directory itself as well as everything in it
+    let (base, recursive) = match expanded.strip_suffix("/**") {
+        Some(base) => (if base.is_empty() { "/" } else { base }, true),
+        None => (if expanded.is_empty() { "/" } else { expanded }, false),
+    };
+
+    let mut regex = format!("^{}", glob_to_regex(base));
+    if recursive && base != "/" {
+        regex.push_str("(?:/.*)?");
+    } else if recursive {
+        regex.push_str(".*");
+    }
+    regex.push('$');
+    match Regex::new(&regex) {
+        Ok(regex) => regex.is_match(path),
+        Err(e) => {
+            warn!("Ignoring invalid protected workspace '{}': {}", pattern, e);
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn running_as_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn running_as_root() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_patterns() {
+        assert!(matches_pattern("/", "/"));
+        assert!(!matches_pattern("/srv/app", "/"));
+        assert!(matches_pattern("/etc", "/etc/**"));
+        assert!(matches_pattern("/etc/nginx/sites", "/etc/**"));
+        assert!(!matches_pattern("/etcetera", "/etc/**"));
+        assert!(matches_pattern("/usr", "/usr"));
+        assert!(!matches_pattern("/usr/local/src/app", "/usr"));
+        assert!(matches_pattern("/srv/prod-db", "/srv/prod-*"));
+
+        let ssh = PathBuf::from(shellexpand::tilde("~/.ssh/keys").as_ref());
+        assert!(matches_pattern(&ssh.to_string_lossy(), "~/.ssh/**"));
+
+        let project = tempfile::TempDir::new().unwrap();
+        let dangers = check_workspace(project.path(), &[]);
+        assert!(!dangers
+            .iter()
+            .any(|d| matches!(d, WorkspaceDanger::Protected(_))));
+        let root = project.path().canonicalize().unwrap();
+        let custom = vec![format!("{}/**", root.display())];
+        assert!(check_workspace(&root.join("sub"), &custom)
+            .contains(&WorkspaceDanger::Protected(custom[0].clone())));
+    }
+}
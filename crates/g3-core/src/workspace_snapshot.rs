@@ -0,0 +1,266 @@
+//! Workspace snapshots that let autonomous mode retry a player round from a clean slate.
+//!
+//! With `snapshot_rounds` enabled, the workspace is captured before the first mutating tool
+//! call of a round (a file tool or a shell command that isn't read-only). If the round fails
+//! and is retried, the workspace is put back first, so the next attempt doesn't build on a
+//! half-applied change.
+//!
+//! In a git repository the snapshot is a tree object written through a temporary index, so
+//! the working tree, the real index and the stash are left alone; files git ignores (build
+//! output, dependencies) are neither captured nor removed. Elsewhere the workspace is copied
+//! into the session directory, skipping `.git`, `target` and `node_modules`. `.g3` is never
+//! captured or restored.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+/// Snapshot data, relative to the session directory
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Directories a file snapshot skips, at any depth
+const SKIPPED_DIRS: &[&str] = &[".g3", ".git", "target", "node_modules"];
+
+/// File snapshots of larger workspaces are refused
+const MAX_FILE_SNAPSHOT_BYTES: u64 = 200 * 1024 * 1024;
+
+/// Excludes the session directory from git pathspecs
+const EXCLUDE_G3: &str = ":(exclude).g3";
+
+#[derive(Debug)]
+enum Kind {
+    Git {
+        /// Tree of the working tree, including untracked files
+        tree: String,
+        /// Tree of the real index, if it could be written (it can't with conflicts)
+        index_tree: Option<String>,
+    },
+    Files,
+}
+
+/// The state of a workspace at one point in time
+#[derive(Debug)]
+pub struct WorkspaceSnapshot {
+    workspace: PathBuf,
+    /// Holds the temporary index or the copied files
+    dir: PathBuf,
+    kind: Kind,
+}
+
+fn git(workspace: &Path, index: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(workspace);
+    if let Some(index) = index {
+        command.env("GIT_INDEX_FILE", index);
+    }
+    let output = command.output().context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl WorkspaceSnapshot {
+    /// Capture `workspace`, keeping any data the snapshot needs in `dir`
+    pub fn take(workspace: &Path, dir: &Path) -> Result<Self> {
+        let workspace = std::path::absolute(workspace)?;
+        let dir = std::path::absolute(dir)?;
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let kind = if git(&workspace, None, &["rev-parse", "--verify", "HEAD"]).is_ok() {
+            // Start from HEAD so parts of the repository outside the workspace are unchanged
+            let index = dir.join("index");
+            git(&workspace, Some(&index), &["read-tree", "HEAD"])?;
+            git(
+                &workspace,
+                Some(&index),
+                &["add", "-A", "--", ".", EXCLUDE_G3],
+            )?;
+            let tree = git(&workspace, Some(&index), &["write-tree"])?
+                .trim()
+                .to_string();
+            let index_tree = git(&workspace, None, &["write-tree"])
+                .ok()
+                .map(|tree| tree.trim().to_string());
+            Kind::Git { tree, index_tree }
+        } else {
+            let files = dir.join("files");
+            let size: u64 = walk(&workspace)?
+                .iter()
+                .filter_map(|path| std::fs::metadata(workspace.join(path)).ok())
+                .map(|metadata| metadata.len())
+                .sum();
+            if size > MAX_FILE_SNAPSHOT_BYTES {
+                bail!(
+                    "workspace is too large to copy ({} MB, at most {} MB)",
+                    size / (1024 * 1024),
+                    MAX_FILE_SNAPSHOT_BYTES / (1024 * 1024)
+                );
+            }
+            for path in walk(&workspace)? {
+                let target = files.join(&path);
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(workspace.join(&path), &target)
+                    .with_context(|| format!("Failed to copy {}", path.display()))?;
+            }
+            Kind::Files
+        };
+        debug!("Took {:?} snapshot of {}", kind, workspace.display());
+        Ok(Self {
+            workspace,
+            dir,
+            kind,
+        })
+    }
+
+    /// Put the workspace back the way it was: changed and deleted files are restored and
+    /// files created since are removed. Commits made in the meantime are kept.
+    pub fn restore(&self) -> Result<()> {
+        match &self.kind {
+            Kind::Git { tree, index_tree } => {
+                let index = self.dir.join("restore-index");
+                let ws = &self.workspace;
+                git(ws, Some(&index), &["read-tree", tree])?;
+                git(ws, Some(&index), &["checkout", tree, "--", ".", EXCLUDE_G3])?;
+                let created = git(
+                    ws,
+                    Some(&index),
+                    &[
+                        "ls-files",
+                        "--others",
+                        "--exclude-standard",
+                        "-z",
+                        "--",
+                        ".",
+                        EXCLUDE_G3,
+                    ],
+                )?;
+                for path in created.split('\0').filter(|p| !p.is_empty()) {
+                    std::fs::remove_file(ws.join(path))
+                        .with_context(|| format!("Failed to remove {}", path))?;
+                }
+                if let Some(index_tree) = index_tree {
+                    git(ws, None, &["read-tree", index_tree])?;
+                }
+                let _ = std::fs::remove_file(index);
+            }
+            Kind::Files => {
+                let files = self.dir.join("files");
+                let saved: HashSet<PathBuf> = walk(&files)?.into_iter().collect();
+                for path in walk(&self.workspace)? {
+                    if !saved.contains(&path) {
+                        std::fs::remove_file(self.workspace.join(&path))
+                            .with_context(|| format!("Failed to remove {}", path.display()))?;
+                    }
+                }
+                for path in &saved {
+                    let source = files.join(path);
+                    let target = self.workspace.join(path);
+                    if std::fs::read(&target).ok() == Some(std::fs::read(&source)?) {
+                        continue;
+                    }
+                    if let Some(parent) = target.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::copy(&source, &target)
+                        .with_context(|| format!("Failed to restore {}", path.display()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the snapshot's data
+    pub fn discard(self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            debug!("Failed to remove {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// Files under `root`, relative to it, outside the skipped directories. Symlinks are skipped.
+fn walk(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let entries = std::fs::read_dir(root.join(&relative))
+            .with_context(|| format!("Failed to read {}", root.join(&relative).display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                let name = entry.file_name();
+                if !SKIPPED_DIRS.iter().any(|skipped| name == *skipped) {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_restore(workspace: &Path, snapshots: &Path) {
+        std::fs::create_dir_all(workspace.join("src")).unwrap();
+        std::fs::write(workspace.join("src/lib.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(workspace.join("notes.txt"), "keep me").unwrap();
+
+        let snapshot = WorkspaceSnapshot::take(workspace, snapshots).unwrap();
+        std::fs::write(workspace.join("src/lib.rs"), "broken(").unwrap();
+        std::fs::remove_file(workspace.join("notes.txt")).unwrap();
+        std::fs::write(workspace.join("src/new.rs"), "half done").unwrap();
+        std::fs::create_dir_all(workspace.join(".g3")).unwrap();
+        std::fs::write(workspace.join(".g3/session.json"), "{}").unwrap();
+
+        snapshot.restore().unwrap();
+        let read = |path: &str| std::fs::read_to_string(workspace.join(path)).unwrap();
+        assert_eq!(read("src/lib.rs"), "fn a() {}\n");
+        assert_eq!(read("notes.txt"), "keep me");
+        assert!(!workspace.join("src/new.rs").exists());
+        assert_eq!(read(".g3/session.json"), "{}");
+        snapshot.discard();
+        assert!(!snapshots.exists());
+    }
+
+    #[test]
+    fn test_restore_snapshot() {
+        // Without git the files are copied
+        let plain = tempfile::TempDir::new().unwrap();
+        let snapshots = tempfile::TempDir::new().unwrap();
+        check_restore(plain.path(), &snapshots.path().join("1"));
+
+        // In a git repository with uncommitted changes and a staged file
+        let repo = tempfile::TempDir::new().unwrap();
+        let run = |args: &[&str]| git(repo.path(), None, args).unwrap();
+        run(&["init", "-q"]);
+        run(&["config", "user.email", "g3@example.com"]);
+        run(&["config", "user.name", "g3"]);
+        std::fs::write(repo.path().join("README.md"), "# Demo\n").unwrap();
+        run(&["add", "README.md"]);
+        run(&["commit", "-q", "-m", "init"]);
+        std::fs::write(repo.path().join("staged.txt"), "staged").unwrap();
+        run(&["add", "staged.txt"]);
+        check_restore(repo.path(), &snapshots.path().join("2"));
+        assert_eq!(
+            run(&["diff", "--cached", "--name-only"]).trim(),
+            "staged.txt"
+        );
+        assert!(run(&["stash", "list"]).is_empty());
+    }
+}
@@ -574,3 +574,101 @@ async fn test_kotlin_search() {
         .collect();
     assert!(names.contains(&"Person"));
 }
+
+#[test]
+fn test_rust_outline() {
+    use g3_core::code_search::outline::outline_source;
+    use std::path::Path;
+
+    let source = r#"
+struct Point {
+    x: i32,
+}
+
+impl Point {
+    fn new(x: i32) -> Self {
+        Self { x }
+    }
+}
+
+fn main() {}
+"#;
+
+    let symbols = outline_source(Path::new("point.rs"), source)
+        .unwrap()
+        .expect("rust files should have an outline");
+    let summary: Vec<(String, String, usize, usize, usize)> = symbols
+        .into_iter()
+        .map(|s| (s.kind, s.name, s.start_line, s.end_line, s.depth))
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            ("struct".to_string(), "Point".to_string(), 2, 4, 0),
+            ("impl".to_string(), "Point".to_string(), 6, 10, 0),
+            ("fn".to_string(), "new".to_string(), 7, 9, 1),
+            ("fn".to_string(), "main".to_string(), 12, 12, 0),
+        ]
+    );
+}
+
+#[test]
+fn test_outline_unsupported_file_type() {
+    use g3_core::code_search::outline::outline_source;
+    use std::path::Path;
+
+    assert!(outline_source(Path::new("notes.txt"), "hello").unwrap().is_none());
+}
+
+#[test]
+fn test_edit_symbol_replaces_qualified_method() {
+    use g3_core::code_search::edit::replace_symbol;
+    use std::path::Path;
+
+    let source = "struct Point;\n\nimpl Point {\n    fn new() -> Self {\n        Point\n    }\n}\n\nfn new() {}\n";
+
+    // Unqualified name matches both the method and the free function
+    let err = replace_symbol(Path::new("p.rs"), source, "new", None, "fn new() {}", false)
+        .unwrap_err();
+    assert!(err.to_string().contains("ambiguous"));
+
+    let edit = replace_symbol(
+        Path::new("p.rs"),
+        source,
+        "Point::new",
+        None,
+        "    fn new() -> Self {\n        let p = Point;\n        p\n    }",
+        false,
+    )
+    .unwrap();
+
+    assert_eq!(
+        edit.content,
+        "struct Point;\n\nimpl Point {\n    fn new() -> Self {\n        let p = Point;\n        p\n    }\n}\n\nfn new() {}\n"
+    );
+    assert_eq!((edit.replaced.start_line, edit.replaced.end_line), (4, 6));
+    assert_eq!(edit.new_line_count, 4);
+}
+
+#[test]
+fn test_edit_symbol_body_only_and_syntax_check() {
+    use g3_core::code_search::edit::replace_symbol;
+    use std::path::Path;
+
+    let source = "def greet(name):\n    return 'hi ' + name\n";
+    let edit = replace_symbol(
+        Path::new("greet.py"),
+        source,
+        "greet",
+        Some("def"),
+        "return f'hello {name}'",
+        true,
+    )
+    .unwrap();
+    assert_eq!(edit.content, "def greet(name):\n    return f'hello {name}'\n");
+
+    let err = replace_symbol(Path::new("g.rs"), "fn a() {}\n", "a", None, "fn a( {", false)
+        .unwrap_err();
+    assert!(err.to_string().contains("syntax error"));
+}
@@ -1,3 +1,4 @@
+use g3_config::ThinningStrategy;
 use g3_core::ContextWindow;
 use g3_providers::{Message, MessageRole};
 
@@ -268,3 +269,158 @@ fn test_thin_context_only_affects_first_third() {
         }
     }
 }
+
+/// Build a conversation of alternating assistant messages and tool results of the given sizes
+fn context_with_tool_results(sizes: &[usize]) -> ContextWindow {
+    let mut context = ContextWindow::new(10000);
+    for (i, size) in sizes.iter().enumerate() {
+        context.add_message(Message {
+            role: MessageRole::Assistant,
+            content: format!("Assistant message {}", i),
+        });
+        context.add_message(Message {
+            role: MessageRole::User,
+            content: format!("Tool result: {}", "x".repeat(*size)),
+        });
+    }
+    context.used_tokens = 5000;
+    context
+}
+
+#[test]
+fn test_custom_thinning_thresholds() {
+    let mut context = ContextWindow::new(10000);
+    context.thinning.start_percentage = 40;
+    context.thinning.step_percentage = 20;
+    context.thinning.max_percentage = 60;
+
+    context.used_tokens = 3900;
+    assert!(!context.should_thin());
+
+    context.used_tokens = 4500;
+    assert!(context.should_thin());
+
+    // 55% still rounds down to the 40% step
+    context.last_thinning_percentage = 40;
+    context.used_tokens = 5500;
+    assert!(!context.should_thin());
+
+    context.used_tokens = 6000;
+    assert!(context.should_thin());
+
+    // Above the max, summarization takes over
+    context.last_thinning_percentage = 60;
+    context.used_tokens = 8000;
+    assert!(!context.should_thin());
+}
+
+#[test]
+fn test_largest_first_stops_at_target_reduction() {
+    let mut context = context_with_tool_results(&[1000, 4000, 2000, 600, 600, 600]);
+    context.thinning.strategy = ThinningStrategy::LargestFirst;
+    context.thinning.chunk_size = Some(6);
+    context.thinning.target_reduction = 30;
+
+    let candidates = context.thinning_candidates();
+    let sizes: Vec<usize> = candidates.iter().map(|c| c.size).collect();
+    assert_eq!(candidates[0].index, 3);
+    assert!(sizes.windows(2).all(|w| w[0] >= w[1]), "Sizes were: {:?}", sizes);
+
+    // The 4000 char result alone covers 30% of the conversation
+    let (summary, _chars_saved) = context.thin_context();
+    assert!(summary.contains("1 tool results"), "Summary was: {}", summary);
+    assert!(context.conversation_history[3].content.starts_with("Tool result saved to"));
+    assert!(context.conversation_history[1].content.len() > 1000);
+}
+
+#[test]
+fn test_tool_results_only_keeps_tool_calls() {
+    let mut context = ContextWindow::new(10000);
+    context.thinning.strategy = ThinningStrategy::ToolResultsOnly;
+    let large_content = "z".repeat(1500);
+    context.add_message(Message {
+        role: MessageRole::Assistant,
+        content: format!(
+            r#"{{"tool": "write_file", "args": {{"file_path": "a.txt", "content": "{}"}}}}"#,
+            large_content
+        ),
+    });
+    context.add_message(Message {
+        role: MessageRole::User,
+        content: format!("Tool result: {}", "x".repeat(1500)),
+    });
+    for i in 0..4 {
+        context.add_message(Message {
+            role: MessageRole::Assistant,
+            content: format!("Assistant message {}", i),
+        });
+    }
+    context.used_tokens = 5000;
+
+    let (summary, _chars_saved) = context.thin_context();
+
+    assert!(summary.contains("1 tool results"), "Summary was: {}", summary);
+    assert!(context.conversation_history[0].content.contains(&large_content));
+    assert!(context.conversation_history[1].content.starts_with("Tool result saved to"));
+}
+
+#[test]
+fn test_thin_context_with_summaries() {
+    let mut context = context_with_tool_results(&[1500, 1500, 100]);
+    let mut summaries = std::collections::HashMap::new();
+    summaries.insert(1, "Listed 42 files under src/".to_string());
+
+    let (summary, _chars_saved) = context.thin_context_with_summaries(&summaries);
+
+    assert!(summary.contains("1 tool results"), "Summary was: {}", summary);
+    let thinned = &context.conversation_history[1].content;
+    assert!(thinned.starts_with("Tool result summarized"));
+    assert!(thinned.ends_with("Listed 42 files under src/"));
+}
+
+#[test]
+fn test_shrink_to_fit_moves_tool_results_then_drops_messages() {
+    let mut context = ContextWindow::new(10000);
+    context.add_message(Message {
+        role: MessageRole::System,
+        content: "You are g3".to_string(),
+    });
+    context.add_message(Message {
+        role: MessageRole::User,
+        content: "Task: tidy up".to_string(),
+    });
+    for i in 0..3 {
+        context.add_message(Message {
+            role: MessageRole::Assistant,
+            content: format!("Step {}:{}", i, " thinking".repeat(100)),
+        });
+        context.add_message(Message {
+            role: MessageRole::User,
+            content: format!("Tool result:{}", " output".repeat(1000)),
+        });
+    }
+    context.add_message(Message {
+        role: MessageRole::User,
+        content: "Keep going".to_string(),
+    });
+
+    // Moving the tool results to files is enough
+    let mut shrunk = context.clone();
+    let outcome = shrunk.shrink_to_fit("gpt-4", 600);
+    assert_eq!(outcome.tool_results, 3);
+    assert_eq!(outcome.messages_dropped, 0);
+    assert!(outcome.tokens <= 600);
+    assert!(shrunk.conversation_history[3]
+        .content
+        .starts_with("Tool result saved to"));
+
+    // Otherwise the oldest messages go, but the system prompt and latest message stay
+    let outcome = context.shrink_to_fit("gpt-4", 200);
+    assert!(outcome.messages_dropped > 0);
+    assert!(outcome.tokens <= 200);
+    let history = &context.conversation_history;
+    assert!(matches!(history[0].role, MessageRole::System));
+    assert!(matches!(history[1].role, MessageRole::User));
+    assert!(history[1].content.contains("earlier messages were removed"));
+    assert!(history.last().unwrap().content.ends_with("Keep going"));
+}
@@ -0,0 +1,78 @@
+#![cfg(unix)]
+
+use g3_config::{Config, OpenAIConfig};
+use g3_core::daemon::{serve, DaemonProvider};
+use g3_providers::{CompletionRequest, LLMProvider, Message, MessageRole, ToolChoice};
+
+fn openai(model: &str) -> OpenAIConfig {
+    OpenAIConfig {
+        api_key: "test-key".to_string(),
+        model: model.to_string(),
+        base_url: Some("http://127.0.0.1:9".to_string()),
+        max_tokens: Some(1000),
+        temperature: None,
+        api: None,
+        reasoning_effort: None,
+        context_length: None,
+        compress_requests: false,
+    }
+}
+
+#[tokio::test]
+async fn test_daemon_serves_client_providers() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let socket = dir.path().join("daemon.sock");
+
+    let mut config = Config::default();
+    config.providers.default_provider = "openai".to_string();
+    config.providers.openai = Some(openai("gpt-daemon"));
+    let daemon = {
+        let (config, socket) = (config.clone(), socket.clone());
+        tokio::spawn(async move { serve(&config, &socket).await })
+    };
+    for _ in 0..100 {
+        if socket.exists() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    let provider = DaemonProvider::connect(&socket, "openai", &config.providers)
+        .await
+        .unwrap();
+    assert_eq!(provider.name(), "openai");
+    assert_eq!(provider.model(), "gpt-daemon");
+
+    // Errors of the provider in the daemon are the client's errors
+    let request = CompletionRequest {
+        messages: vec![Message {
+            role: MessageRole::User,
+            content: "hi".to_string(),
+        }],
+        max_tokens: Some(10),
+        temperature: None,
+        stream: false,
+        tools: None,
+        tool_choice: ToolChoice::Auto,
+        stop_sequences: Vec::new(),
+    };
+    assert!(provider.complete(request).await.is_err());
+
+    // Clients with other settings get a provider of their own
+    let mut providers = config.providers.clone();
+    providers.openai = Some(openai("gpt-client"));
+    let provider = DaemonProvider::connect(&socket, "openai", &providers)
+        .await
+        .unwrap();
+    assert_eq!(provider.model(), "gpt-client");
+
+    let error = DaemonProvider::connect(&socket, "anthropic", &config.providers)
+        .await
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("anthropic"), "{}", error);
+
+    // A second daemon on the same socket is refused
+    assert!(serve(&config, &socket).await.is_err());
+    daemon.abort();
+}
@@ -0,0 +1,57 @@
+use g3_core::{ContextWindow, CONTINUE_PROMPT, RESUME_PROMPT};
+use g3_providers::{Message, MessageRole};
+
+fn user(content: &str) -> Message {
+    Message {
+        role: MessageRole::User,
+        content: content.to_string(),
+    }
+}
+
+#[test]
+fn test_continuation_is_joined_with_truncated_response() {
+    let mut window = ContextWindow::new(10000);
+    window.add_message(user("Task: write hello world"));
+    window.add_response_part("fn main() {\n    println!(\"hel".to_string());
+    assert!(!window.awaiting_continuation());
+
+    window.add_message(user(CONTINUE_PROMPT));
+    assert!(window.awaiting_continuation());
+    window.add_response_part("lo\");\n".to_string());
+    // A continuation that is itself cut off is joined the same way
+    window.add_message(user(CONTINUE_PROMPT));
+    window.add_response_part("}".to_string());
+
+    assert!(!window.awaiting_continuation());
+    assert_eq!(window.conversation_history.len(), 2);
+    assert!(matches!(
+        window.conversation_history[1].role,
+        MessageRole::Assistant
+    ));
+    assert_eq!(
+        window.conversation_history[1].content,
+        "fn main() {\n    println!(\"hello\");\n}"
+    );
+
+    // Without a continuation prompt a response is a message of its own
+    window.add_message(user("Task: and in Python?"));
+    window.add_response_part("print(\"hello\")".to_string());
+    assert_eq!(window.conversation_history.len(), 4);
+}
+
+#[test]
+fn test_resumed_response_is_joined_with_interrupted_one() {
+    let mut window = ContextWindow::new(10000);
+    window.add_message(user("Task: explain the bug"));
+    window.add_response_part("The index is off ".to_string());
+
+    window.add_message(user(RESUME_PROMPT));
+    assert!(window.awaiting_continuation());
+    window.add_response_part("by one.".to_string());
+
+    assert_eq!(window.conversation_history.len(), 2);
+    assert_eq!(
+        window.conversation_history[1].content,
+        "The index is off by one."
+    );
+}
@@ -10,6 +10,7 @@ fn test_token_accumulation() {
         prompt_tokens: 100,
         completion_tokens: 50,
         total_tokens: 150,
+        reasoning_tokens: 0,
     };
     window.update_usage_from_response(&usage1);
     assert_eq!(window.used_tokens, 150, "First call should have 150 tokens");
@@ -20,6 +21,7 @@ fn test_token_accumulation() {
         prompt_tokens: 200,
         completion_tokens: 75,
         total_tokens: 275,
+        reasoning_tokens: 0,
     };
     window.update_usage_from_response(&usage2);
     assert_eq!(window.used_tokens, 425, "Second call should accumulate to 425 tokens");
@@ -30,6 +32,7 @@ fn test_token_accumulation() {
         prompt_tokens: 50,
         completion_tokens: 25,
         total_tokens: 75,
+        reasoning_tokens: 0,
     };
     window.update_usage_from_response(&usage3);
     assert_eq!(window.used_tokens, 500, "Third call should accumulate to 500 tokens");
@@ -58,6 +61,7 @@ fn test_add_streaming_tokens() {
         prompt_tokens: 80,
         completion_tokens: 40,
         total_tokens: 120,
+        reasoning_tokens: 0,
     };
     window.update_usage_from_response(&usage);
     
@@ -75,6 +79,7 @@ fn test_percentage_calculation() {
         prompt_tokens: 150,
         completion_tokens: 100,
         total_tokens: 250,
+        reasoning_tokens: 0,
     };
     window.update_usage_from_response(&usage);
     
@@ -86,6 +91,7 @@ fn test_percentage_calculation() {
         prompt_tokens: 300,
         completion_tokens: 200,
         total_tokens: 500,
+        reasoning_tokens: 0,
     };
     window.update_usage_from_response(&usage2);
     
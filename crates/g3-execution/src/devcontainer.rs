@@ -0,0 +1,443 @@
+//! Dev containers: running commands with the toolchain a project's `devcontainer.json`
+//! describes.
+//!
+//! The container is built from the `image`, or the `build.dockerfile` (or older
+//! `dockerFile`), of `.devcontainer/devcontainer.json` or `.devcontainer.json`, and started
+//! with the workspace mounted at `workspaceFolder`, so files are shared and only commands
+//! run inside it, as `remoteUser` if one is set. It is named after the workspace and kept
+//! running between sessions until it is stopped. Compose-based dev containers, features and
+//! lifecycle scripts such as `postCreateCommand` aren't supported. Everything goes through
+//! the `docker` command.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Where a dev container may be described, relative to the workspace
+const CONFIG_PATHS: &[&str] = &[".devcontainer/devcontainer.json", ".devcontainer.json"];
+
+/// The parts of `devcontainer.json` that are supported
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Spec {
+    image: Option<String>,
+    build: Option<BuildSpec>,
+    docker_file: Option<String>,
+    docker_compose_file: Option<serde_json::Value>,
+    workspace_folder: Option<String>,
+    remote_user: Option<String>,
+    container_user: Option<String>,
+    #[serde(default)]
+    container_env: BTreeMap<String, String>,
+    #[serde(default)]
+    run_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct BuildSpec {
+    dockerfile: Option<String>,
+    context: Option<String>,
+    #[serde(default)]
+    args: BTreeMap<String, String>,
+}
+
+/// A workspace's dev container
+#[derive(Debug, Clone)]
+pub struct DevContainer {
+    workspace: PathBuf,
+    /// The `devcontainer.json`, for resolving the paths in it
+    config_path: PathBuf,
+    spec: Spec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Missing,
+    Stopped,
+    Running,
+}
+
+impl DevContainer {
+    /// The dev container `workspace` describes, if it has a `devcontainer.json`
+    pub fn find(workspace: &Path) -> Result<Option<Self>> {
+        let Some(config_path) = CONFIG_PATHS
+            .iter()
+            .map(|path| workspace.join(path))
+            .find(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+        let contents = std::fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let spec: Spec = serde_json::from_str(&strip_jsonc(&contents))
+            .with_context(|| format!("Invalid {}", config_path.display()))?;
+        if spec.docker_compose_file.is_some() {
+            bail!("Compose-based dev containers aren't supported");
+        }
+        if spec.image.is_none() && spec.dockerfile().is_none() {
+            bail!("{} has no image or Dockerfile", config_path.display());
+        }
+        Ok(Some(Self {
+            workspace: std::path::absolute(workspace)?,
+            config_path,
+            spec,
+        }))
+    }
+
+    /// The `devcontainer.json`
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    /// Name of the workspace's container, the same in every session
+    pub fn name(&self) -> String {
+        let dir: String = self
+            .workspace
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        format!(
+            "g3-{}-{:08x}",
+            dir.trim_matches('-'),
+            fnv1a(self.workspace.to_string_lossy().as_bytes()) as u32
+        )
+    }
+
+    /// The image the container runs
+    pub fn image(&self) -> String {
+        match (&self.spec.image, self.spec.dockerfile()) {
+            (_, Some(_)) => format!("{}-image", self.name()),
+            (Some(image), None) => image.clone(),
+            (None, None) => unreachable!("checked in find"),
+        }
+    }
+
+    /// Where the workspace is mounted in the container
+    pub fn workspace_folder(&self) -> String {
+        self.spec.workspace_folder.clone().unwrap_or_else(|| {
+            let dir = self
+                .workspace
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            format!("/workspaces/{}", dir)
+        })
+    }
+
+    /// Build the image, or pull it if the container uses a published one
+    pub async fn build(&self) -> Result<()> {
+        let Some(dockerfile) = self.spec.dockerfile() else {
+            docker(&["pull", &self.image()]).await?;
+            return Ok(());
+        };
+        let base = self.config_path.parent().unwrap_or(&self.workspace);
+        let context = base.join(
+            self.spec
+                .build
+                .as_ref()
+                .and_then(|build| build.context.as_deref())
+                .unwrap_or("."),
+        );
+        let mut args = vec![
+            "build".to_string(),
+            "-f".to_string(),
+            base.join(dockerfile).to_string_lossy().into_owned(),
+            "-t".to_string(),
+            self.image(),
+        ];
+        for (name, value) in self.spec.build.iter().flat_map(|build| &build.args) {
+            args.push("--build-arg".to_string());
+            args.push(format!("{}={}", name, value));
+        }
+        args.push(context.to_string_lossy().into_owned());
+        docker(&args.iter().map(String::as_str).collect::<Vec<_>>()).await?;
+        Ok(())
+    }
+
+    async fn state(&self) -> State {
+        let name = self.name();
+        match docker(&["inspect", "-f", "{{.State.Running}}", &name]).await {
+            Ok(running) if running.trim() == "true" => State::Running,
+            Ok(_) => State::Stopped,
+            Err(_) => State::Missing,
+        }
+    }
+
+    /// Start the container, building its image and creating it first if needed. With
+    /// `rebuild`, the image is built again and the container recreated from it.
+    pub async fn up(&self, rebuild: bool) -> Result<ContainerTarget> {
+        let name = self.name();
+        if rebuild {
+            self.down().await?;
+            self.build().await?;
+        }
+        match self.state().await {
+            State::Running => {}
+            State::Stopped => {
+                docker(&["start", &name]).await?;
+            }
+            State::Missing => {
+                let image = self.image();
+                if docker(&["image", "inspect", &image]).await.is_err() {
+                    self.build().await?;
+                }
+                let folder = self.workspace_folder();
+                let mut args = vec![
+                    "run".to_string(),
+                    "-d".to_string(),
+                    "--init".to_string(),
+                    "--name".to_string(),
+                    name.clone(),
+                    "--label".to_string(),
+                    format!("g3.workspace={}", self.workspace.display()),
+                    "-v".to_string(),
+                    format!("{}:{}", self.workspace.display(), folder),
+                    "-w".to_string(),
+                    folder,
+                ];
+                for (name, value) in &self.spec.container_env {
+                    args.push("-e".to_string());
+                    args.push(format!("{}={}", name, value));
+                }
+                if let Some(user) = &self.spec.container_user {
+                    args.push("-u".to_string());
+                    args.push(user.clone());
+                }
+                args.extend(self.spec.run_args.iter().cloned());
+                // Kept running for commands, whatever the image would run
+                args.extend(["--entrypoint".to_string(), "sleep".to_string()]);
+                args.extend([image, "infinity".to_string()]);
+                docker(&args.iter().map(String::as_str).collect::<Vec<_>>()).await?;
+            }
+        }
+        Ok(ContainerTarget {
+            name,
+            user: self
+                .spec
+                .remote_user
+                .clone()
+                .or_else(|| self.spec.container_user.clone()),
+            workdir: self.workspace_folder(),
+        })
+    }
+
+    /// Stop and remove the container. Returns whether there was one.
+    pub async fn down(&self) -> Result<bool> {
+        if self.state().await == State::Missing {
+            return Ok(false);
+        }
+        docker(&["rm", "-f", &self.name()]).await?;
+        Ok(true)
+    }
+}
+
+impl Spec {
+    fn dockerfile(&self) -> Option<&str> {
+        self.build
+            .as_ref()
+            .and_then(|build| build.dockerfile.as_deref())
+            .or(self.docker_file.as_deref())
+    }
+}
+
+/// A running container that commands are run in with `docker exec`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerTarget {
+    pub name: String,
+    pub user: Option<String>,
+    /// Directory commands run in
+    pub workdir: String,
+}
+
+impl ContainerTarget {
+    /// `docker exec` running `code` with bash in the container, with `env` added to its
+    /// environment
+    pub fn shell_command(&self, code: &str, env: &[(String, String)]) -> Command {
+        let mut command = Command::new("docker");
+        command.args(["exec", "-i", "-w", &self.workdir]);
+        if let Some(user) = &self.user {
+            command.arg("-u").arg(user);
+        }
+        for (name, value) in env {
+            command.arg("-e").arg(format!("{}={}", name, value));
+        }
+        command.arg(&self.name).args(["bash", "-c", code]);
+        command
+    }
+}
+
+async fn docker(args: &[&str]) -> Result<String> {
+    let output = Command::new("docker")
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .context("Failed to run docker; is it installed?")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<&str> = stderr.trim().lines().rev().take(5).collect();
+        return Err(anyhow!(
+            "docker {} failed: {}",
+            args.first().unwrap_or(&""),
+            tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// JSON with comments and trailing commas, as `devcontainer.json` allows, made plain JSON
+fn strip_jsonc(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.char_indices().peekable();
+    let mut in_string = false;
+    while let Some((i, c)) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next().map(|(_, c)| c)),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek().map(|(_, c)| *c)) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for (_, c) in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            (',', _) if matches!(next_significant(&source[i + 1..]), Some('}' | ']')) => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The first character of `rest` that isn't whitespace or in a comment
+fn next_significant(rest: &str) -> Option<char> {
+    let mut rest = rest.trim_start();
+    loop {
+        rest = if let Some(comment) = rest.strip_prefix("//") {
+            comment.split_once('\n').map_or("", |(_, after)| after)
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            comment.split_once("*/").map_or("", |(_, after)| after)
+        } else {
+            return rest.chars().next();
+        }
+        .trim_start();
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the standard hasher is the same in every build
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_dev_container() {
+        let workspace = TempDir::new().unwrap();
+        let root = workspace.path().join("My App");
+        std::fs::create_dir_all(root.join(".devcontainer")).unwrap();
+        assert!(DevContainer::find(&root).unwrap().is_none());
+
+        std::fs::write(
+            root.join(".devcontainer/devcontainer.json"),
+            r#"{
+                // The toolchain CI uses
+                "name": "app",
+                "build": { "dockerfile": "Dockerfile", "args": { "RUST": "1.80" } },
+                /* Mounted where the Dockerfile expects it */
+                "workspaceFolder": "/src",
+                "remoteUser": "vscode",
+                "containerEnv": { "URL": "http://localhost:8080/api" },
+            }"#,
+        )
+        .unwrap();
+        let container = DevContainer::find(&root).unwrap().unwrap();
+        let name = container.name();
+        assert!(name.starts_with("g3-my-app-"), "{}", name);
+        assert_eq!(name, DevContainer::find(&root).unwrap().unwrap().name());
+        assert_eq!(container.image(), format!("{}-image", name));
+        assert_eq!(container.workspace_folder(), "/src");
+        assert_eq!(
+            container.spec.container_env["URL"],
+            "http://localhost:8080/api"
+        );
+
+        std::fs::write(
+            root.join(".devcontainer/devcontainer.json"),
+            r#"{"dockerComposeFile": "compose.yml", "service": "app"}"#,
+        )
+        .unwrap();
+        assert!(DevContainer::find(&root).is_err());
+    }
+
+    #[test]
+    fn test_exec_command() {
+        let target = ContainerTarget {
+            name: "g3-app-1234abcd".to_string(),
+            user: Some("vscode".to_string()),
+            workdir: "/workspaces/app".to_string(),
+        };
+        let command = target.shell_command("cargo test", &[("CI".to_string(), "1".to_string())]);
+        let args: Vec<String> = command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            args,
+            [
+                "exec",
+                "-i",
+                "-w",
+                "/workspaces/app",
+                "-u",
+                "vscode",
+                "-e",
+                "CI=1",
+                "g3-app-1234abcd",
+                "bash",
+                "-c",
+                "cargo test"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_jsonc() {
+        assert_eq!(
+            strip_jsonc("{\"a\": \"x // y\", // note\n\"b\": [1, 2,],}"),
+            "{\"a\": \"x // y\", \n\"b\": [1, 2]}"
+        );
+    }
+}
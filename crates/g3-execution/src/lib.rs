@@ -1,12 +1,56 @@
 use anyhow::Result;
 use regex::Regex;
+use std::path::PathBuf;
 use std::process::Command;
 use tempfile::NamedTempFile;
 use std::io::Write;
 use tracing::{info, debug, error};
 
+pub use tokio_util::sync::CancellationToken;
+
+pub mod devcontainer;
+pub mod remote;
+pub use devcontainer::ContainerTarget;
+pub use remote::SshTarget;
+
+/// A command that could not be run at all, as opposed to one that ran and failed
+#[derive(Debug, thiserror::Error)]
+pub enum ExecutionError {
+    #[error("Failed to run {program}: {source}")]
+    Spawn {
+        program: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed waiting for command to finish: {source}")]
+    Wait {
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl ExecutionError {
+    fn spawn(program: &str, source: std::io::Error) -> Self {
+        Self::Spawn {
+            program: program.to_string(),
+            source,
+        }
+    }
+}
+
 pub struct CodeExecutor {
     // Future: add configuration for execution limits, sandboxing, etc.
+    /// Lines of streamed output kept from the start and the end of each stream; the lines
+    /// in between are only passed to the receiver. `None` keeps everything.
+    output_limit: Option<(usize, usize)>,
+    /// Variables added to the environment of bash commands
+    env: Vec<(String, String)>,
+    /// Directory bash commands run in, instead of the current directory
+    dir: Option<PathBuf>,
+    /// Host bash commands run on over SSH, instead of this machine
+    remote: Option<SshTarget>,
+    /// Dev container bash commands run in, instead of this machine
+    container: Option<ContainerTarget>,
 }
 
 #[derive(Debug, Clone)]
@@ -15,11 +59,82 @@ pub struct ExecutionResult {
     pub stderr: String,
     pub exit_code: i32,
     pub success: bool,
+    /// The command was stopped because the caller cancelled it
+    pub cancelled: bool,
 }
 
 impl CodeExecutor {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            output_limit: None,
+            env: Vec::new(),
+            dir: None,
+            remote: None,
+            container: None,
+        }
+    }
+
+    /// An executor that keeps only the first `head` and last `tail` lines of each stream
+    /// of streamed commands in their results, marking where lines were left out
+    pub fn with_output_limit(head: usize, tail: usize) -> Self {
+        Self {
+            output_limit: Some((head, tail)),
+            env: Vec::new(),
+            dir: None,
+            remote: None,
+            container: None,
+        }
+    }
+
+    /// Add `vars` to the environment of the bash commands this executor runs
+    pub fn with_env(mut self, vars: Vec<(String, String)>) -> Self {
+        self.env = vars;
+        self
+    }
+
+    /// Run the bash commands of this executor in `dir`, if given
+    pub fn with_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.dir = dir;
+        self
+    }
+
+    /// Run the bash commands of this executor on `remote`, if given. Cancelling a command
+    /// closes its SSH session, which stops it unless it ignores the hangup.
+    pub fn with_remote(mut self, remote: Option<SshTarget>) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Run the bash commands of this executor in the dev container `container`, if given.
+    /// Cancelling a command stops `docker exec`, but not always the command in the container.
+    pub fn with_container(mut self, container: Option<ContainerTarget>) -> Self {
+        self.container = container;
+        self
+    }
+
+    /// What runs commands, for errors
+    fn program(&self) -> &'static str {
+        match (&self.remote, &self.container) {
+            (Some(_), _) => "ssh",
+            (None, Some(_)) => "docker",
+            (None, None) => "bash",
+        }
+    }
+
+    /// `bash -c code`, here, on the remote host or in the dev container
+    fn bash_command(&self, code: &str) -> Result<tokio::process::Command> {
+        Ok(match (&self.remote, &self.container) {
+            (Some(remote), _) => remote.shell_command(code, &self.env)?,
+            (None, Some(container)) => container.shell_command(code, &self.env),
+            (None, None) => {
+                let mut command = tokio::process::Command::new("bash");
+                command.arg("-c").arg(code).envs(self.env.iter().cloned());
+                if let Some(dir) = &self.dir {
+                    command.current_dir(dir);
+                }
+                command
+            }
+        })
     }
     
     /// Extract code blocks from LLM response and execute them
@@ -154,13 +269,15 @@ impl CodeExecutor {
         
         let output = Command::new("python3")
             .arg(temp_path)
-            .output()?;
+            .output()
+            .map_err(|source| ExecutionError::spawn("python3", source))?;
         
         Ok(ExecutionResult {
             stdout: String::from_utf8_lossy(&output.stdout).to_string(),
             stderr: String::from_utf8_lossy(&output.stderr).to_string(),
             exit_code: output.status.code().unwrap_or(-1),
             success: output.status.success(),
+            cancelled: false,
         })
     }
     
@@ -181,26 +298,30 @@ impl CodeExecutor {
                 .stdin(Stdio::null())
                 .stdout(Stdio::null())
                 .stderr(Stdio::null())
-                .spawn()?;
+                .spawn()
+                .map_err(|source| ExecutionError::spawn("bash", source))?;
             
             return Ok(ExecutionResult {
                 stdout: "✅ Command launched in background (detached process)".to_string(),
                 stderr: String::new(),
                 exit_code: 0,
                 success: true,
+                cancelled: false,
             });
         }
         
         let output = Command::new("bash")
             .arg("-c")
             .arg(code)
-            .output()?;
+            .output()
+            .map_err(|source| ExecutionError::spawn("bash", source))?;
         
         Ok(ExecutionResult {
             stdout: String::from_utf8_lossy(&output.stdout).to_string(),
             stderr: String::from_utf8_lossy(&output.stderr).to_string(),
             exit_code: output.status.code().unwrap_or(-1),
             success: output.status.success(),
+            cancelled: false,
         })
     }
     
@@ -212,13 +333,15 @@ impl CodeExecutor {
         
         let output = Command::new("node")
             .arg(temp_path)
-            .output()?;
+            .output()
+            .map_err(|source| ExecutionError::spawn("node", source))?;
         
         Ok(ExecutionResult {
             stdout: String::from_utf8_lossy(&output.stdout).to_string(),
             stderr: String::from_utf8_lossy(&output.stderr).to_string(),
             exit_code: output.status.code().unwrap_or(-1),
             success: output.status.success(),
+            cancelled: false,
         })
     }
 }
@@ -241,10 +364,22 @@ impl CodeExecutor {
         &self, 
         code: &str, 
         receiver: &R
+    ) -> Result<ExecutionResult> {
+        self.execute_bash_streaming_cancellable(code, receiver, &CancellationToken::new())
+            .await
+    }
+
+    /// Execute bash command with streaming output, stopping it when `cancellation` is
+    /// cancelled. The command and everything it started get SIGINT, then SIGTERM and
+    /// finally SIGKILL if they are still running after a grace period.
+    pub async fn execute_bash_streaming_cancellable<R: OutputReceiver>(
+        &self,
+        code: &str,
+        receiver: &R,
+        cancellation: &CancellationToken,
     ) -> Result<ExecutionResult> {
         use std::process::Stdio;
         use tokio::io::{AsyncBufReadExt, BufReader};
-        use tokio::process::Command as TokioCommand;
         
         // Check if this is a detached/daemon command that should run independently
         // Look for patterns like: setsid, nohup with &, or explicit backgrounding with disown
@@ -255,10 +390,9 @@ impl CodeExecutor {
         
         if is_detached {
             // For detached commands, just spawn and return immediately
-            TokioCommand::new("bash")
-                .arg("-c")
-                .arg(code)
-                .spawn()?;
+            self.bash_command(code)?
+                .spawn()
+                .map_err(|source| ExecutionError::spawn(self.program(), source))?;
             
             // Don't wait for the process - it's meant to run independently
             return Ok(ExecutionResult {
@@ -266,15 +400,20 @@ impl CodeExecutor {
                 stderr: String::new(),
                 exit_code: 0,
                 success: true,
+                cancelled: false,
             });
         }
         
-        let mut child = TokioCommand::new("bash")
-            .arg("-c")
-            .arg(code)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+        let mut command = self.bash_command(code)?;
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        // A process group of its own, so cancelling reaches everything the command starts
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command
+            .spawn()
+            .map_err(|source| ExecutionError::spawn(self.program(), source))?;
+        // Kills the command if this future is dropped before it finishes
+        let mut group = ProcessGroup::new(&child);
         
         let stdout = child.stdout.take().unwrap();
         let stderr = child.stderr.take().unwrap();
@@ -285,12 +424,17 @@ impl CodeExecutor {
         let mut stdout_lines = stdout_reader.lines();
         let mut stderr_lines = stderr_reader.lines();
         
-        let mut stdout_output = Vec::new();
-        let mut stderr_output = Vec::new();
+        let mut stdout_output = LineBuffer::new(self.output_limit);
+        let mut stderr_output = LineBuffer::new(self.output_limit);
+        let mut cancelled = false;
         
         // Read output lines as they come
         loop {
             tokio::select! {
+                _ = cancellation.cancelled() => {
+                    cancelled = true;
+                    break;
+                }
                 line = stdout_lines.next_line() => {
                     match line {
                         Ok(Some(line)) => {
@@ -320,13 +464,126 @@ impl CodeExecutor {
             }
         }
         
-        let status = child.wait().await?;
+        let status = if cancelled {
+            info!("Command cancelled, stopping it");
+            group.terminate(&mut child).await
+        } else {
+            child.wait().await
+        }
+        .map_err(|source| ExecutionError::Wait { source })?;
+        group.disarm();
         
         Ok(ExecutionResult {
-            stdout: stdout_output.join("\n"),
-            stderr: stderr_output.join("\n"),
+            stdout: stdout_output.join(),
+            stderr: stderr_output.join(),
             exit_code: status.code().unwrap_or(-1),
-            success: status.success(),
+            success: status.success() && !cancelled,
+            cancelled,
         })
     }
 }
+
+/// Lines of a stream, keeping only its head and tail when limited
+struct LineBuffer {
+    limit: Option<(usize, usize)>,
+    head: Vec<String>,
+    tail: std::collections::VecDeque<String>,
+    omitted: usize,
+}
+
+impl LineBuffer {
+    fn new(limit: Option<(usize, usize)>) -> Self {
+        Self {
+            limit,
+            head: Vec::new(),
+            tail: std::collections::VecDeque::new(),
+            omitted: 0,
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        let Some((head, tail)) = self.limit else {
+            self.head.push(line);
+            return;
+        };
+        if self.head.len() < head {
+            self.head.push(line);
+            return;
+        }
+        self.tail.push_back(line);
+        if self.tail.len() > tail {
+            self.tail.pop_front();
+            self.omitted += 1;
+        }
+    }
+
+    fn join(self) -> String {
+        let mut lines = self.head;
+        if self.omitted > 0 {
+            lines.push(format!("... {} lines omitted ...", self.omitted));
+        }
+        lines.extend(self.tail);
+        lines.join("\n")
+    }
+}
+
+/// How long a cancelled command has to exit after SIGINT, and again after SIGTERM
+const CANCEL_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// The process group of a running command
+struct ProcessGroup {
+    id: Option<u32>,
+}
+
+impl ProcessGroup {
+    fn new(child: &tokio::process::Child) -> Self {
+        Self { id: child.id() }
+    }
+
+    /// Interrupt the command, terminate it if it keeps running and kill it if it still
+    /// does, then kill whatever it left behind in its group
+    async fn terminate(
+        &mut self,
+        child: &mut tokio::process::Child,
+    ) -> std::io::Result<std::process::ExitStatus> {
+        #[cfg(unix)]
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            self.signal(signal);
+            if let Ok(status) = tokio::time::timeout(CANCEL_GRACE_PERIOD, child.wait()).await {
+                self.kill();
+                return status;
+            }
+        }
+        self.kill();
+        let _ = child.start_kill();
+        child.wait().await
+    }
+
+    /// The command finished: nothing left to kill
+    fn disarm(&mut self) {
+        self.id = None;
+    }
+
+    fn kill(&self) {
+        #[cfg(unix)]
+        self.signal(libc::SIGKILL);
+    }
+
+    #[cfg(unix)]
+    fn signal(&self, signal: libc::c_int) {
+        if let Some(id) = self.id {
+            debug!("Sending signal {} to process group {}", signal, id);
+            // SAFETY: killpg only sends a signal; the group was created for the command,
+            // and a group that no longer exists is reported as an error we ignore
+            unsafe {
+                libc::killpg(id as libc::pid_t, signal);
+            }
+        }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
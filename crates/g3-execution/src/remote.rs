@@ -0,0 +1,227 @@
+//! Commands and files on another machine over SSH.
+//!
+//! The system `ssh` is used, so keys, agents and `~/.ssh/config` work as they do in a
+//! terminal, and it must be able to connect without a password prompt. All commands to a
+//! host share one connection: the first starts an SSH control master, which stays open for
+//! a few minutes after the last command, so a tool call doesn't pay for a new handshake.
+//! Files are read with `cat` and written through its stdin, so the host needs nothing but a
+//! POSIX shell and bash for commands.
+
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// How long the shared connection stays open after its last command
+const CONTROL_PERSIST_SECONDS: u32 = 300;
+
+/// An SSH host that commands run on and files are read from and written to
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshTarget {
+    /// `host` or `user@host`, or a `Host` alias of the SSH config
+    host: String,
+    port: Option<u16>,
+    identity_file: Option<PathBuf>,
+    /// Directory commands run in and relative paths start from, instead of the login
+    /// directory
+    dir: Option<String>,
+}
+
+impl SshTarget {
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port: None,
+            identity_file: None,
+            dir: None,
+        }
+    }
+
+    pub fn with_port(mut self, port: Option<u16>) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn with_identity_file(mut self, identity_file: Option<PathBuf>) -> Self {
+        self.identity_file = identity_file;
+        self
+    }
+
+    pub fn with_dir(mut self, dir: Option<String>) -> Self {
+        self.dir = dir;
+        self
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn dir(&self) -> Option<&str> {
+        self.dir.as_deref()
+    }
+
+    /// `ssh` running `remote_command` in the target's directory
+    fn command(&self, remote_command: &str) -> Command {
+        let control_path = std::env::temp_dir().join("g3-ssh-%C");
+        let mut command = Command::new("ssh");
+        command
+            .args(["-T", "-o", "BatchMode=yes", "-o", "ControlMaster=auto"])
+            .arg("-o")
+            .arg(format!("ControlPath={}", control_path.display()))
+            .arg("-o")
+            .arg(format!("ControlPersist={}", CONTROL_PERSIST_SECONDS));
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity_file) = &self.identity_file {
+            command.arg("-i").arg(identity_file);
+        }
+        let remote_command = match &self.dir {
+            Some(dir) => format!("cd {} && {}", quote_path(dir), remote_command),
+            None => remote_command.to_string(),
+        };
+        command.arg(&self.host).arg("--").arg(remote_command);
+        command
+    }
+
+    /// `ssh` running `code` with bash on the host, with `env` added to its environment.
+    /// The names are written into the remote command line, so they must be shell names.
+    pub fn shell_command(&self, code: &str, env: &[(String, String)]) -> Result<Command> {
+        let mut assignments = String::new();
+        for (name, value) in env {
+            if !is_shell_name(name) {
+                bail!("Invalid environment variable name `{}`", name);
+            }
+            assignments.push_str(&format!("{}={} ", name, quote(value)));
+        }
+        let env = if assignments.is_empty() { "" } else { "env " };
+        Ok(self.command(&format!("{}{}bash -c {}", env, assignments, quote(code))))
+    }
+
+    /// The contents of the file at `path` on the host
+    pub async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let output = self
+            .command(&format!("cat -- {}", quote_path(path)))
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Failed to run ssh")?;
+        if !output.status.success() {
+            bail!("{}", failure(&output.stderr, output.status));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Write `contents` to the file at `path` on the host, creating its directory if needed
+    pub async fn write_file(&self, path: &str, contents: &[u8]) -> Result<()> {
+        let path = quote_path(path);
+        let mut child = self
+            .command(&format!(
+                "mkdir -p -- \"$(dirname -- {path})\" && cat > {path}"
+            ))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run ssh")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(contents).await?;
+        }
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            bail!("{}", failure(&output.stderr, output.status));
+        }
+        Ok(())
+    }
+}
+
+fn failure(stderr: &[u8], status: std::process::ExitStatus) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    match stderr.trim() {
+        "" => format!("ssh exited with {}", status),
+        stderr => stderr.to_string(),
+    }
+}
+
+/// Whether `name` is a shell variable name: letters, digits and `_`, not starting with a
+/// digit
+fn is_shell_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `value` as one word of a POSIX shell command
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// `path` quoted, except for a leading `~`, which the remote shell expands to its home
+fn quote_path(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("~/{}", quote(rest)),
+        None if path == "~" => "~".to_string(),
+        None => quote(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_ssh_commands() {
+        let target = SshTarget::new("ci@build-01")
+            .with_port(Some(2222))
+            .with_dir(Some("~/src/app".to_string()));
+        let command = target
+            .shell_command(
+                "echo \"it's $HOME\"",
+                &[("API_URL".to_string(), "http://x".to_string())],
+            )
+            .unwrap();
+        let words = args(&command);
+        assert_eq!(command.as_std().get_program(), "ssh");
+        assert!(words.windows(2).any(|w| w == ["-p", "2222"]));
+        assert_eq!(
+            words[words.len() - 3..words.len() - 1],
+            ["ci@build-01", "--"]
+        );
+        assert_eq!(
+            words.last().unwrap(),
+            r#"cd ~/'src/app' && env API_URL='http://x' bash -c 'echo "it'\''s $HOME"'"#
+        );
+
+        let plain = SshTarget::new("box").shell_command("ls", &[]).unwrap();
+        assert_eq!(args(&plain).last().unwrap(), "bash -c 'ls'");
+        assert!(!args(&plain).contains(&"-p".to_string()));
+    }
+
+    #[test]
+    fn test_env_names_must_be_shell_names() {
+        let target = SshTarget::new("box");
+        for name in ["X;touch /tmp/pwned;Y", "A B", "1ST", "$(id)", ""] {
+            let env = [(name.to_string(), "v".to_string())];
+            assert!(target.shell_command("ls", &env).is_err(), "{}", name);
+        }
+        let env = [("_PRIVATE_2".to_string(), "v".to_string())];
+        assert!(target.shell_command("ls", &env).is_ok());
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote_path("~"), "~");
+        assert_eq!(quote_path("/etc/hosts"), "'/etc/hosts'");
+    }
+}
@@ -0,0 +1,55 @@
+#![cfg(unix)]
+
+use g3_execution::{CancellationToken, CodeExecutor, OutputReceiver};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct Lines(Mutex<Vec<String>>);
+
+impl OutputReceiver for Lines {
+    fn on_output_line(&self, line: &str) {
+        self.0.lock().unwrap().push(line.to_string());
+    }
+}
+
+#[tokio::test]
+async fn test_cancelled_command_is_stopped() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let marker = dir.path().join("finished");
+    // The trap keeps bash running after SIGINT, so it takes SIGTERM to stop it; the
+    // background sleep is in the same process group and goes too
+    let command = format!(
+        "trap '' INT; echo started; (sleep 30; touch {0}) & sleep 30; touch {0}",
+        marker.display()
+    );
+
+    let cancellation = CancellationToken::new();
+    let cancel = cancellation.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        cancel.cancel();
+    });
+
+    let lines = Lines::default();
+    let start = Instant::now();
+    let result = CodeExecutor::new()
+        .execute_bash_streaming_cancellable(&command, &lines, &cancellation)
+        .await
+        .unwrap();
+
+    assert!(result.cancelled);
+    assert!(!result.success);
+    assert_eq!(result.stdout, "started");
+    assert_eq!(*lines.0.lock().unwrap(), vec!["started"]);
+    assert!(start.elapsed() < Duration::from_secs(10));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!marker.exists());
+
+    // Commands that finish aren't marked cancelled
+    let result = CodeExecutor::new()
+        .execute_bash_streaming_cancellable("echo done", &lines, &CancellationToken::new())
+        .await
+        .unwrap();
+    assert!(result.success && !result.cancelled);
+}
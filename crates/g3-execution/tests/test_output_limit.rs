@@ -0,0 +1,30 @@
+use g3_execution::{CodeExecutor, OutputReceiver};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Default)]
+struct Counter(AtomicUsize);
+
+impl OutputReceiver for Counter {
+    fn on_output_line(&self, _line: &str) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_output_limit_keeps_head_and_tail() {
+    let counter = Counter::default();
+    let result = CodeExecutor::with_output_limit(3, 2)
+        .execute_bash_streaming("seq 1 100", &counter)
+        .await
+        .unwrap();
+
+    // The receiver sees every line; the result only the head and tail
+    assert_eq!(counter.0.load(Ordering::SeqCst), 100);
+    assert_eq!(result.stdout, "1\n2\n3\n... 95 lines omitted ...\n99\n100");
+
+    let result = CodeExecutor::with_output_limit(3, 2)
+        .execute_bash_streaming("seq 1 5", &counter)
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "1\n2\n3\n4\n5");
+}
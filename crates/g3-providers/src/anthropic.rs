@@ -14,7 +14,7 @@
 //! # Usage
 //!
 //! ```rust,no_run
-//! use g3_providers::{AnthropicProvider, LLMProvider, CompletionRequest, Message, MessageRole};
+//! use g3_providers::{AnthropicProvider, LLMProvider, CompletionRequest, Message, MessageRole, ToolChoice};
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
@@ -42,6 +42,8 @@
 //!         temperature: Some(0.7),
 //!         stream: false,
 //!         tools: None,
+//!         tool_choice: ToolChoice::Auto,
+//!         stop_sequences: Vec::new(),
 //!     };
 //!
 //!     // Get a completion
@@ -55,7 +57,7 @@
 //! # Streaming Example
 //!
 //! ```rust,no_run
-//! use g3_providers::{AnthropicProvider, LLMProvider, CompletionRequest, Message, MessageRole};
+//! use g3_providers::{AnthropicProvider, LLMProvider, CompletionRequest, Message, MessageRole, ToolChoice};
 //! use tokio_stream::StreamExt;
 //!
 //! #[tokio::main]
@@ -76,6 +78,8 @@
 //!         temperature: Some(0.7),
 //!         stream: true,
 //!         tools: None,
+//!         tool_choice: ToolChoice::Auto,
+//!         stop_sequences: Vec::new(),
 //!     };
 //!
 //!     let mut stream = provider.stream(request).await?;
@@ -106,15 +110,34 @@ use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{debug, error, warn};
+use tracing::{debug, error};
 
 use crate::{
-    CompletionChunk, CompletionRequest, CompletionResponse, CompletionStream, LLMProvider, Message,
-    MessageRole, Tool, ToolCall, Usage,
+    http, stop, Capabilities, CompletionChunk, CompletionRequest, CompletionResponse,
+    CompletionStream, ImageContent, LLMProvider, Message, MessageRole, ProviderError, Tool,
+    ToolCall, ToolCallDelta, ToolChoice, Usage,
 };
 
 const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1/messages";
 const ANTHROPIC_VERSION: &str = "2023-06-01";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Context window of Claude models
+pub const CONTEXT_WINDOW: u32 = 200_000;
+
+/// The most tokens `model` can generate in one response
+pub fn output_limit(model: &str) -> u32 {
+    let model = model.to_lowercase();
+    if model.contains("3-7") {
+        64_000
+    } else if model.contains("claude-3") {
+        8192
+    } else if model.contains("opus") {
+        32_000
+    } else {
+        64_000
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct AnthropicProvider {
@@ -132,10 +155,7 @@ impl AnthropicProvider {
         max_tokens: Option<u32>,
         temperature: Option<f32>,
     ) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(300))
-            .build()
-            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        let client = http::client();
 
         let model = model.unwrap_or_else(|| "claude-3-5-sonnet-20241022".to_string());
         
@@ -158,7 +178,7 @@ impl AnthropicProvider {
             .header("anthropic-version", ANTHROPIC_VERSION)
             // Anthropic beta 1m context window. Enable if needed. It costs extra, so check first.
             // .header("anthropic-beta", "context-1m-2025-08-07")
-            .header("content-type", "application/json");
+            .timeout(REQUEST_TIMEOUT);
         if streaming {
             builder = builder.header("accept", "text/event-stream");
         }
@@ -166,6 +186,15 @@ impl AnthropicProvider {
         builder
     }
 
+    fn convert_tool_choice(&self, choice: &ToolChoice) -> Option<serde_json::Value> {
+        match choice {
+            ToolChoice::Auto => None,
+            ToolChoice::Required => Some(serde_json::json!({ "type": "any" })),
+            ToolChoice::None => Some(serde_json::json!({ "type": "none" })),
+            ToolChoice::Tool(name) => Some(serde_json::json!({ "type": "tool", "name": name })),
+        }
+    }
+
     fn convert_tools(&self, tools: &[Tool]) -> Vec<AnthropicTool> {
         tools
             .iter()
@@ -198,17 +227,20 @@ impl AnthropicProvider {
     }
 
     fn convert_messages(&self, messages: &[Message]) -> Result<(Option<String>, Vec<AnthropicMessage>)> {
-        let mut system_message = None;
+        let mut system_message: Option<String> = None;
         let mut anthropic_messages = Vec::new();
 
         for message in messages {
             match message.role {
-                MessageRole::System => {
-                    if system_message.is_some() {
-                        warn!("Multiple system messages found, using the last one");
+                // Anthropic takes one system prompt, so later system messages (a summary,
+                // a wrap-up notice) are added to it rather than replacing it
+                MessageRole::System => match &mut system_message {
+                    Some(system) => {
+                        system.push_str("\n\n");
+                        system.push_str(&message.content);
                     }
-                    system_message = Some(message.content.clone());
-                }
+                    None => system_message = Some(message.content.clone()),
+                },
                 MessageRole::User => {
                     anthropic_messages.push(AnthropicMessage {
                         role: "user".to_string(),
@@ -235,6 +267,7 @@ impl AnthropicProvider {
         &self,
         messages: &[Message],
         tools: Option<&[Tool]>,
+        tool_choice: &ToolChoice,
         streaming: bool,
         max_tokens: u32,
         temperature: f32,
@@ -247,6 +280,9 @@ impl AnthropicProvider {
 
         // Convert tools if provided
         let anthropic_tools = tools.map(|t| self.convert_tools(t));
+        let tool_choice = anthropic_tools
+            .as_ref()
+            .and_then(|_| self.convert_tool_choice(tool_choice));
 
         let request = AnthropicRequest {
             model: self.model.clone(),
@@ -255,6 +291,8 @@ impl AnthropicProvider {
             messages: anthropic_messages,
             system,
             tools: anthropic_tools,
+            tool_choice,
+            stop_sequences: Vec::new(),
             stream: streaming,
         };
 
@@ -266,6 +304,59 @@ impl AnthropicProvider {
         Ok(request)
     }
 
+    async fn send_completion(&self, request_body: &AnthropicRequest) -> Result<CompletionResponse> {
+        debug!("Sending request to Anthropic API: model={}, max_tokens={}, temperature={}", 
+               request_body.model, request_body.max_tokens, request_body.temperature);
+
+        let response = http::json_body(self.create_request_builder(false), request_body, false)?
+            .send()
+            .await
+            .map_err(|e| ProviderError::from_reqwest("Failed to send request to Anthropic API", &e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::from_response("Anthropic", status, &error_text).into());
+        }
+
+        let anthropic_response: AnthropicResponse = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Anthropic response: {}", e))?;
+
+        // Extract text content from the response
+        let content = anthropic_response
+            .content
+            .iter()
+            .filter_map(|c| match c {
+                AnthropicContent::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("");
+
+        let usage = Usage {
+            prompt_tokens: anthropic_response.usage.input_tokens,
+            completion_tokens: anthropic_response.usage.output_tokens,
+            total_tokens: anthropic_response.usage.input_tokens + anthropic_response.usage.output_tokens,
+            reasoning_tokens: 0,
+        };
+
+        debug!(
+            "Anthropic completion successful: {} tokens generated",
+            usage.completion_tokens
+        );
+
+        Ok(CompletionResponse {
+            content,
+            usage,
+            model: anthropic_response.model,
+        })
+    }
+
     async fn parse_streaming_response(
         &self,
         mut stream: impl futures_util::Stream<Item = reqwest::Result<Bytes>> + Unpin,
@@ -277,6 +368,7 @@ impl AnthropicProvider {
         let mut accumulated_usage: Option<Usage> = None;
         let mut byte_buffer = Vec::new(); // Buffer for incomplete UTF-8 sequences
         let mut message_stopped = false; // Track if we've received message_stop
+        let mut truncated = false; // Whether message_delta reported stop_reason max_tokens
         
         while let Some(chunk_result) = stream.next().await {
             match chunk_result {
@@ -332,6 +424,9 @@ impl AnthropicProvider {
                                     finished: true,
                                     usage: accumulated_usage.clone(),
                                     tool_calls: if current_tool_calls.is_empty() { None } else { Some(current_tool_calls.clone()) },
+                                    tool_call_deltas: None,
+                                    reasoning: None,
+                                    truncated,
                                 };
                                 if tx.send(Ok(final_chunk)).await.is_err() {
                                     debug!("Receiver dropped, stopping stream");
@@ -353,6 +448,7 @@ impl AnthropicProvider {
                                                         prompt_tokens: usage.input_tokens,
                                                         completion_tokens: usage.output_tokens,
                                                         total_tokens: usage.input_tokens + usage.output_tokens,
+                                                        reasoning_tokens: 0,
                                                     });
                                                     debug!("Captured usage from message_start: {:?}", accumulated_usage);
                                                 }
@@ -382,6 +478,9 @@ impl AnthropicProvider {
                                                                 finished: false,
                                                                 usage: None,
                                                                 tool_calls: Some(vec![tool_call]),
+                                                                tool_call_deltas: None,
+                                                                reasoning: None,
+                                                                truncated: false,
                                                             };
                                                             if tx.send(Ok(chunk)).await.is_err() {
                                                                 debug!("Receiver dropped, stopping stream");
@@ -392,6 +491,24 @@ impl AnthropicProvider {
                                                             debug!("Tool call has empty args, will accumulate from partial_json");
                                                             current_tool_calls.push(tool_call);
                                                             partial_tool_json.clear();
+                                                            let chunk = CompletionChunk {
+                                                                content: String::new(),
+                                                                finished: false,
+                                                                usage: None,
+                                                                tool_calls: None,
+                                                                tool_call_deltas: Some(vec![ToolCallDelta {
+                                                                    index: current_tool_calls.len() - 1,
+                                                                    id: Some(id),
+                                                                    tool: Some(name),
+                                                                    arguments: String::new(),
+                                                                }]),
+                                                                reasoning: None,
+                                                                truncated: false,
+                                                            };
+                                                            if tx.send(Ok(chunk)).await.is_err() {
+                                                                debug!("Receiver dropped, stopping stream");
+                                                                return accumulated_usage;
+                                                            }
                                                         }
                                                     }
                                                     _ => {
@@ -409,6 +526,9 @@ impl AnthropicProvider {
                                                         finished: false,
                                                         usage: None,
                                                         tool_calls: None,
+                                                        tool_call_deltas: None,
+                                                        reasoning: None,
+                                                        truncated: false,
                                                     };
                                                     if tx.send(Ok(chunk)).await.is_err() {
                                                         debug!("Receiver dropped, stopping stream");
@@ -420,6 +540,25 @@ impl AnthropicProvider {
                                                     debug!("Received partial JSON: {}", partial_json);
                                                     partial_tool_json.push_str(&partial_json);
                                                     debug!("Accumulated tool JSON: {}", partial_tool_json);
+                                                    if !current_tool_calls.is_empty() {
+                                                        let chunk = CompletionChunk {
+                                                            content: String::new(),
+                                                            finished: false,
+                                                            usage: None,
+                                                            tool_calls: None,
+                                                            tool_call_deltas: Some(vec![ToolCallDelta {
+                                                                index: current_tool_calls.len() - 1,
+                                                                arguments: partial_json,
+                                                                ..Default::default()
+                                                            }]),
+                                                            reasoning: None,
+                                                            truncated: false,
+                                                        };
+                                                        if tx.send(Ok(chunk)).await.is_err() {
+                                                            debug!("Receiver dropped, stopping stream");
+                                                            return accumulated_usage;
+                                                        }
+                                                    }
                                                 }
                                             }
                                         }
@@ -449,6 +588,9 @@ impl AnthropicProvider {
                                                     finished: false,
                                                     usage: None,
                                                     tool_calls: Some(current_tool_calls.clone()),
+                                                    tool_call_deltas: None,
+                                                    reasoning: None,
+                                                    truncated: false,
                                                 };
                                                 if tx.send(Ok(chunk)).await.is_err() {
                                                     debug!("Receiver dropped, stopping stream");
@@ -456,6 +598,23 @@ impl AnthropicProvider {
                                                 }
                                             }
                                         }
+                                        "message_delta" => {
+                                            if let Some(stop_reason) = event.delta.and_then(|d| d.stop_reason) {
+                                                debug!("Message stop reason: {}", stop_reason);
+                                                truncated = stop_reason == "max_tokens";
+                                                // Claude declined to continue; retrying gets the same
+                                                if stop_reason == "refusal" {
+                                                    let _ = tx
+                                                        .send(Err(ProviderError::content_filtered(
+                                                            "Anthropic",
+                                                            "the model declined to respond (stop_reason: refusal)",
+                                                        )
+                                                        .into()))
+                                                        .await;
+                                                    break;
+                                                }
+                                            }
+                                        }
                                         "message_stop" => {
                                             debug!("Received message stop event");
                                             message_stopped = true;
@@ -464,6 +623,9 @@ impl AnthropicProvider {
                                                 finished: true,
                                                 usage: accumulated_usage.clone(),
                                                 tool_calls: if current_tool_calls.is_empty() { None } else { Some(current_tool_calls.clone()) },
+                                                tool_call_deltas: None,
+                                                reasoning: None,
+                                                truncated,
                                             };
                                             if tx.send(Ok(final_chunk)).await.is_err() {
                                                 debug!("Receiver dropped, stopping stream");
@@ -475,11 +637,32 @@ impl AnthropicProvider {
                                             if let Some(error) = event.error {
                                                 error!("Anthropic API error: {:?}", error);
                                                 let _ = tx
-                                                    .send(Err(anyhow!("Anthropic API error: {:?}", error)))
+                                                    .send(Err(ProviderError::from_stream_event(
+                                                        "Anthropic",
+                                                        &error.error_type,
+                                                        &error.message,
+                                                    )
+                                                    .into()))
                                                     .await;
                                                 break; // Break to let stream exhaust naturally
                                             }
                                         }
+                                        "ping" => {
+                                            // Forward keep-alives so the consumer knows the connection is alive
+                                            let chunk = CompletionChunk {
+                                                content: String::new(),
+                                                finished: false,
+                                                usage: None,
+                                                tool_calls: None,
+                                                tool_call_deltas: None,
+                                                reasoning: None,
+                                                truncated: false,
+                                            };
+                                            if tx.send(Ok(chunk)).await.is_err() {
+                                                debug!("Receiver dropped, stopping stream");
+                                                return accumulated_usage;
+                                            }
+                                        }
                                         _ => {
                                             debug!("Ignoring event type: {}", event.event_type);
                                         }
@@ -495,7 +678,7 @@ impl AnthropicProvider {
                 }
                 Err(e) => {
                     error!("Stream error: {}", e);
-                    let _ = tx.send(Err(anyhow!("Stream error: {}", e))).await;
+                    let _ = tx.send(Err(ProviderError::from_reqwest("Stream error", &e).into())).await;
                     // Don't return here either - let the stream exhaust naturally
                     // The error has been sent to the receiver, so it will handle it
                     // Breaking here ensures we clean up properly
@@ -510,6 +693,9 @@ impl AnthropicProvider {
             finished: true,
             usage: accumulated_usage.clone(),
             tool_calls: if current_tool_calls.is_empty() { None } else { Some(current_tool_calls) },
+            tool_call_deltas: None,
+            reasoning: None,
+            truncated,
         };
         let _ = tx.send(Ok(final_chunk)).await;
         accumulated_usage
@@ -527,65 +713,17 @@ impl LLMProvider for AnthropicProvider {
         let max_tokens = request.max_tokens.unwrap_or(self.max_tokens);
         let temperature = request.temperature.unwrap_or(self.temperature);
 
-        let request_body = self.create_request_body(
+        let mut request_body = self.create_request_body(
             &request.messages, 
             request.tools.as_deref(), 
+            &request.tool_choice,
             false, 
             max_tokens, 
             temperature
         )?;
+        request_body.stop_sequences = request.stop_sequences.clone();
 
-        debug!("Sending request to Anthropic API: model={}, max_tokens={}, temperature={}", 
-               request_body.model, request_body.max_tokens, request_body.temperature);
-
-        let response = self
-            .create_request_builder(false)
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to send request to Anthropic API: {}", e))?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Anthropic API error {}: {}", status, error_text));
-        }
-
-        let anthropic_response: AnthropicResponse = response
-            .json()
-            .await
-            .map_err(|e| anyhow!("Failed to parse Anthropic response: {}", e))?;
-
-        // Extract text content from the response
-        let content = anthropic_response
-            .content
-            .iter()
-            .filter_map(|c| match c {
-                AnthropicContent::Text { text } => Some(text.as_str()),
-                _ => None,
-            })
-            .collect::<Vec<_>>()
-            .join("");
-
-        let usage = Usage {
-            prompt_tokens: anthropic_response.usage.input_tokens,
-            completion_tokens: anthropic_response.usage.output_tokens,
-            total_tokens: anthropic_response.usage.input_tokens + anthropic_response.usage.output_tokens,
-        };
-
-        debug!(
-            "Anthropic completion successful: {} tokens generated",
-            usage.completion_tokens
-        );
-
-        Ok(CompletionResponse {
-            content,
-            usage,
-            model: anthropic_response.model,
-        })
+        self.send_completion(&request_body).await
     }
 
     async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
@@ -597,13 +735,15 @@ impl LLMProvider for AnthropicProvider {
         let max_tokens = request.max_tokens.unwrap_or(self.max_tokens);
         let temperature = request.temperature.unwrap_or(self.temperature);
 
-        let request_body = self.create_request_body(
+        let mut request_body = self.create_request_body(
             &request.messages, 
             request.tools.as_deref(), 
+            &request.tool_choice,
             true, 
             max_tokens, 
             temperature
         )?;
+        request_body.stop_sequences = request.stop_sequences.clone();
 
         debug!("Sending streaming request to Anthropic API: model={}, max_tokens={}, temperature={}", 
                request_body.model, request_body.max_tokens, request_body.temperature);
@@ -611,12 +751,10 @@ impl LLMProvider for AnthropicProvider {
         // Debug: Log the full request body
         debug!("Full request body: {}", serde_json::to_string_pretty(&request_body).unwrap_or_else(|_| "Failed to serialize".to_string()));
 
-        let response = self
-            .create_request_builder(true)
-            .json(&request_body)
+        let response = http::json_body(self.create_request_builder(true), &request_body, false)?
             .send()
             .await
-            .map_err(|e| anyhow!("Failed to send streaming request to Anthropic API: {}", e))?;
+            .map_err(|e| ProviderError::from_reqwest("Failed to send streaming request to Anthropic API", &e))?;
 
         let status = response.status();
         if !status.is_success() {
@@ -624,7 +762,7 @@ impl LLMProvider for AnthropicProvider {
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("Anthropic API error {}: {}", status, error_text));
+            return Err(ProviderError::from_response("Anthropic", status, &error_text).into());
         }
 
         let stream = response.bytes_stream();
@@ -643,7 +781,10 @@ impl LLMProvider for AnthropicProvider {
             }
         });
 
-        Ok(ReceiverStream::new(rx))
+        Ok(stop::filter_stream(
+            ReceiverStream::new(rx),
+            stop::sequences(self.stop_tokens(), &request),
+        ))
     }
 
     fn name(&self) -> &str {
@@ -658,6 +799,53 @@ impl LLMProvider for AnthropicProvider {
         // Claude models support native tool calling
         true
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            vision: true,
+            tool_streaming: true,
+            parallel_tools: true,
+            json_mode: false,
+            prompt_caching: true,
+            max_output_tokens: Some(output_limit(&self.model)),
+            context_window: Some(CONTEXT_WINDOW),
+        }
+    }
+
+    async fn complete_with_image(
+        &self,
+        prompt: &str,
+        image: &ImageContent,
+        max_tokens: Option<u32>,
+    ) -> Result<CompletionResponse> {
+        let request_body = AnthropicRequest {
+            model: self.model.clone(),
+            max_tokens: max_tokens.unwrap_or(self.max_tokens),
+            temperature: self.temperature,
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: vec![
+                    AnthropicContent::Image {
+                        source: AnthropicImageSource {
+                            source_type: "base64".to_string(),
+                            media_type: image.media_type.clone(),
+                            data: image.to_base64(),
+                        },
+                    },
+                    AnthropicContent::Text {
+                        text: prompt.to_string(),
+                    },
+                ],
+            }],
+            system: None,
+            tools: None,
+            tool_choice: None,
+            stop_sequences: Vec::new(),
+            stream: false,
+        };
+
+        self.send_completion(&request_body).await
+    }
 }
 
 // Anthropic API request/response structures
@@ -672,6 +860,10 @@ struct AnthropicRequest {
     system: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<AnthropicTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
     stream: bool,
 }
 
@@ -708,6 +900,16 @@ enum AnthropicContent {
         name: String,
         input: serde_json::Value,
     },
+    #[serde(rename = "image")]
+    Image { source: AnthropicImageSource },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AnthropicImageSource {
+    #[serde(rename = "type")]
+    source_type: String,
+    media_type: String,
+    data: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -749,14 +951,14 @@ struct AnthropicStreamMessage {
 struct AnthropicDelta {
     text: Option<String>,
     partial_json: Option<String>,
+    /// Sent in message_delta
+    stop_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct AnthropicError {
     #[serde(rename = "type")]
-    #[allow(dead_code)]
     error_type: String,
-    #[allow(dead_code)]
     message: String,
 }
 
@@ -796,6 +998,30 @@ mod tests {
         assert_eq!(anthropic_messages[1].role, "assistant");
     }
 
+    #[test]
+    fn test_later_system_messages_are_added_to_the_system_prompt() {
+        let provider = AnthropicProvider::new("test-key".to_string(), None, None, None).unwrap();
+        let messages = vec![
+            Message {
+                role: MessageRole::System,
+                content: "You are a coding agent.".to_string(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: "Fix the build".to_string(),
+            },
+            Message {
+                role: MessageRole::System,
+                content: "Wrap up.".to_string(),
+            },
+        ];
+
+        let (system, anthropic_messages) = provider.convert_messages(&messages).unwrap();
+
+        assert_eq!(system.as_deref(), Some("You are a coding agent.\n\nWrap up."));
+        assert_eq!(anthropic_messages.len(), 1);
+    }
+
     #[test]
     fn test_request_body_creation() {
         let provider = AnthropicProvider::new(
@@ -813,7 +1039,7 @@ mod tests {
         ];
 
         let request_body = provider
-            .create_request_body(&messages, None, false, 1000, 0.5)
+            .create_request_body(&messages, None, &ToolChoice::Auto, false, 1000, 0.5)
             .unwrap();
 
         assert_eq!(request_body.model, "claude-3-haiku-20240307");
@@ -859,4 +1085,37 @@ mod tests {
         assert!(anthropic_tools[0].input_schema.required.is_some());
         assert_eq!(anthropic_tools[0].input_schema.required.as_ref().unwrap()[0], "location");
     }
+
+    #[test]
+    fn test_tool_choice() {
+        let provider = AnthropicProvider::new("test-key".to_string(), None, None, None).unwrap();
+        let messages = vec![Message {
+            role: MessageRole::User,
+            content: "Test message".to_string(),
+        }];
+        let tools = vec![Tool {
+            name: "final_output".to_string(),
+            description: "Finish".to_string(),
+            input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+        }];
+        let choice = ToolChoice::Tool("final_output".to_string());
+
+        let request_body = provider
+            .create_request_body(&messages, Some(&tools), &choice, false, 1000, 0.5)
+            .unwrap();
+        assert_eq!(
+            request_body.tool_choice,
+            Some(serde_json::json!({ "type": "tool", "name": "final_output" }))
+        );
+
+        // No tool choice without tools, and none sent for the default
+        let request_body = provider
+            .create_request_body(&messages, None, &choice, false, 1000, 0.5)
+            .unwrap();
+        assert!(request_body.tool_choice.is_none());
+        let request_body = provider
+            .create_request_body(&messages, Some(&tools), &ToolChoice::Auto, false, 1000, 0.5)
+            .unwrap();
+        assert!(request_body.tool_choice.is_none());
+    }
 }
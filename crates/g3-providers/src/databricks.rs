@@ -15,7 +15,7 @@
 //! # Usage
 //!
 //! ```rust,no_run
-//! use g3_providers::{DatabricksProvider, LLMProvider, CompletionRequest, Message, MessageRole};
+//! use g3_providers::{DatabricksProvider, LLMProvider, CompletionRequest, Message, MessageRole, ToolChoice};
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
@@ -48,6 +48,8 @@
 //!         temperature: Some(0.7),
 //!         stream: false,
 //!         tools: None,
+//!         tool_choice: ToolChoice::Auto,
+//!         stop_sequences: Vec::new(),
 //!     };
 //!
 //!     // Get a completion
@@ -63,14 +65,17 @@ use bytes::Bytes;
 use futures_util::stream::StreamExt;
 use reqwest::{Client, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, error, info, warn};
 
+use crate::oauth::OAuthToken;
 use crate::{
-    CompletionChunk, CompletionRequest, CompletionResponse, CompletionStream, LLMProvider, Message,
-    MessageRole, Tool, ToolCall, Usage,
+    anthropic, http, stop, Capabilities, CompletionChunk, CompletionRequest, CompletionResponse,
+    CompletionStream, LLMProvider, Message, MessageRole, ProviderError, ProviderErrorKind, Tool,
+    ToolCall, ToolCallDelta, ToolChoice, Usage,
 };
 
 const DEFAULT_CLIENT_ID: &str = "databricks-cli";
@@ -95,7 +100,8 @@ pub enum DatabricksAuth {
         client_id: String,
         redirect_url: String,
         scopes: Vec<String>,
-        cached_token: Option<String>,
+        /// Shared by clones, so a token refreshed for one request serves the others
+        cached_token: Arc<tokio::sync::Mutex<Option<OAuthToken>>>,
     },
 }
 
@@ -106,7 +112,7 @@ impl DatabricksAuth {
             client_id: DEFAULT_CLIENT_ID.to_string(),
             redirect_url: DEFAULT_REDIRECT_URL.to_string(),
             scopes: DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect(),
-            cached_token: None,
+            cached_token: Arc::new(tokio::sync::Mutex::new(None)),
         }
     }
 
@@ -114,7 +120,19 @@ impl DatabricksAuth {
         Self::Token(token)
     }
 
-    async fn get_token(&mut self) -> Result<String> {
+    /// A token for the next request. OAuth tokens are refreshed a few minutes before they
+    /// expire.
+    pub(crate) async fn get_token(&self) -> Result<String> {
+        self.oauth_token(false).await
+    }
+
+    /// Refresh the OAuth token after the server rejected it. Fixed tokens can't be
+    /// refreshed.
+    pub(crate) async fn refresh_token(&self) -> Result<String> {
+        self.oauth_token(true).await
+    }
+
+    async fn oauth_token(&self, force_refresh: bool) -> Result<String> {
         match self {
             DatabricksAuth::Token(token) => Ok(token.clone()),
             DatabricksAuth::OAuth {
@@ -124,24 +142,36 @@ impl DatabricksAuth {
                 scopes,
                 cached_token,
             } => {
-                // Use the OAuth implementation with automatic refresh
-                let token =
-                    crate::oauth::get_oauth_token_async(host, client_id, redirect_url, scopes)
-                        .await?;
-                // Cache the token for potential reuse within the same session
-                *cached_token = Some(token.clone());
-                Ok(token)
+                // Held while refreshing, so concurrent requests refresh only once
+                let mut cached = cached_token.lock().await;
+                if let Some(token) = cached.as_ref() {
+                    if !force_refresh && !crate::oauth::expires_soon(token.expires_at) {
+                        return Ok(token.access_token.clone());
+                    }
+                }
+                let token = crate::oauth::get_oauth_token_async(
+                    host,
+                    client_id,
+                    redirect_url,
+                    scopes,
+                    force_refresh,
+                )
+                .await?;
+                let access_token = token.access_token.clone();
+                *cached = Some(token);
+                Ok(access_token)
             }
         }
     }
+}
 
-    /// Force a token refresh by clearing any cached token
-    /// This is useful when we get a 403 Invalid Token error
-    pub fn clear_cached_token(&mut self) {
-        if let DatabricksAuth::OAuth { cached_token, .. } = self {
-            *cached_token = None;
-        }
-    }
+/// Whether a failed request was refused because its OAuth token expired or was revoked
+fn is_token_rejected(status: reqwest::StatusCode, body: &str) -> bool {
+    status == reqwest::StatusCode::UNAUTHORIZED
+        || (status == reqwest::StatusCode::FORBIDDEN
+            && (body.contains("Invalid Token")
+                || body.contains("invalid_token")
+                || body.contains("expired")))
 }
 
 #[derive(Debug, Clone)]
@@ -152,6 +182,7 @@ pub struct DatabricksProvider {
     model: String,
     max_tokens: u32,
     temperature: f32,
+    compress_requests: bool,
 }
 
 impl DatabricksProvider {
@@ -162,10 +193,7 @@ impl DatabricksProvider {
         max_tokens: Option<u32>,
         temperature: Option<f32>,
     ) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(DEFAULT_TIMEOUT_SECS))
-            .build()
-            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        let client = http::client();
 
         info!(
             "Initialized Databricks provider with model: {} on host: {}",
@@ -179,6 +207,7 @@ impl DatabricksProvider {
             model,
             max_tokens: max_tokens.unwrap_or(50000),
             temperature: temperature.unwrap_or(0.1),
+            compress_requests: false,
         })
     }
 
@@ -188,10 +217,7 @@ impl DatabricksProvider {
         max_tokens: Option<u32>,
         temperature: Option<f32>,
     ) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(DEFAULT_TIMEOUT_SECS))
-            .build()
-            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        let client = http::client();
 
         info!(
             "Initialized Databricks provider with OAuth for model: {} on host: {}",
@@ -205,10 +231,74 @@ impl DatabricksProvider {
             model,
             max_tokens: max_tokens.unwrap_or(50000),
             temperature: temperature.unwrap_or(0.1),
+            compress_requests: false,
         })
     }
 
-    async fn create_request_builder(&mut self, streaming: bool) -> Result<RequestBuilder> {
+    /// Gzip large request bodies, for serving endpoints behind a gateway that accepts
+    /// compressed requests
+    pub fn with_compression(mut self, compress_requests: bool) -> Self {
+        self.compress_requests = compress_requests;
+        self
+    }
+
+    /// Send `body`. An OAuth token the server rejects is refreshed and the request sent once
+    /// more, so an expired token doesn't fail a long session.
+    async fn send_request(
+        &self,
+        body: &DatabricksRequest,
+        streaming: bool,
+    ) -> Result<reqwest::Response> {
+        let context = if streaming {
+            "Failed to send streaming request to Databricks API"
+        } else {
+            "Failed to send request to Databricks API"
+        };
+        let send = || async {
+            let builder = self.create_request_builder(streaming).await?;
+            http::json_body(builder, body, self.compress_requests)?
+                .send()
+                .await
+                .map_err(|e| anyhow::Error::from(ProviderError::from_reqwest(context, &e)))
+        };
+
+        let response = send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        if !matches!(self.auth, DatabricksAuth::OAuth { .. })
+            || !is_token_rejected(status, &error_text)
+        {
+            return Err(ProviderError::from_response("Databricks", status, &error_text).into());
+        }
+
+        info!("Databricks rejected the OAuth token ({}), refreshing it and retrying", status);
+        if let Err(e) = self.auth.refresh_token().await {
+            return Err(ProviderError::new(
+                ProviderErrorKind::Auth,
+                format!("Failed to refresh OAuth token: {}. Original error: {}", e, error_text),
+            )
+            .into());
+        }
+
+        let response = send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::from_response("Databricks", status, &error_text).into());
+        }
+        Ok(response)
+    }
+
+    async fn create_request_builder(&self, streaming: bool) -> Result<RequestBuilder> {
         let token = self.auth.get_token().await?;
 
         let mut builder = self
@@ -218,7 +308,7 @@ impl DatabricksProvider {
                 self.host, self.model
             ))
             .header("Authorization", format!("Bearer {}", token))
-            .header("Content-Type", "application/json");
+            .timeout(Duration::from_secs(DEFAULT_TIMEOUT_SECS));
 
         if streaming {
             builder = builder.header("Accept", "text/event-stream");
@@ -227,6 +317,18 @@ impl DatabricksProvider {
         Ok(builder)
     }
 
+    fn convert_tool_choice(&self, choice: &ToolChoice) -> Option<serde_json::Value> {
+        match choice {
+            ToolChoice::Auto => None,
+            ToolChoice::Required => Some(serde_json::json!("required")),
+            ToolChoice::None => Some(serde_json::json!("none")),
+            ToolChoice::Tool(name) => Some(serde_json::json!({
+                "type": "function",
+                "function": { "name": name },
+            })),
+        }
+    }
+
     fn convert_tools(&self, tools: &[Tool]) -> Vec<DatabricksTool> {
         tools
             .iter()
@@ -269,6 +371,7 @@ impl DatabricksProvider {
         &self,
         messages: &[Message],
         tools: Option<&[Tool]>,
+        tool_choice: &ToolChoice,
         streaming: bool,
         max_tokens: u32,
         temperature: f32,
@@ -277,12 +380,17 @@ impl DatabricksProvider {
 
         // Convert tools if provided
         let databricks_tools = tools.map(|t| self.convert_tools(t));
+        let tool_choice = databricks_tools
+            .as_ref()
+            .and_then(|_| self.convert_tool_choice(tool_choice));
 
         let request = DatabricksRequest {
             messages: databricks_messages,
             max_tokens,
             temperature,
             tools: databricks_tools,
+            tool_choice,
+            stop: Vec::new(),
             stream: streaming,
         };
 
@@ -406,6 +514,9 @@ impl DatabricksProvider {
                                     } else {
                                         Some(final_tool_calls)
                                     },
+                                    tool_call_deltas: None,
+                                    reasoning: None,
+                                    truncated: false,
                                 };
                                 if tx.send(Ok(final_chunk)).await.is_err() {
                                     debug!("Receiver dropped, stopping stream");
@@ -439,6 +550,9 @@ impl DatabricksProvider {
                                                         finished: false,
                                                         usage: None,
                                                         tool_calls: None,
+                                                        tool_call_deltas: None,
+                                                        reasoning: None,
+                                                        truncated: false,
                                                     };
                                                     if tx.send(Ok(chunk)).await.is_err() {
                                                         debug!("Receiver dropped, stopping stream");
@@ -452,8 +566,16 @@ impl DatabricksProvider {
                                                         "Processing {} tool call deltas",
                                                         tool_calls.len()
                                                     );
+                                                    let mut deltas = Vec::new();
                                                     for tool_call in tool_calls {
                                                         let index = tool_call.index.unwrap_or(0);
+                                                        deltas.push(ToolCallDelta {
+                                                            index,
+                                                            id: tool_call.id.clone(),
+                                                            tool: Some(tool_call.function.name.clone())
+                                                                .filter(|name| !name.is_empty()),
+                                                            arguments: tool_call.function.arguments.clone(),
+                                                        });
                                                         debug!("Tool call delta for index {}: id={:?}, name='{}', args_len={}",
                                                                index, tool_call.id, tool_call.function.name, tool_call.function.arguments.len());
 
@@ -499,9 +621,33 @@ impl DatabricksProvider {
                                                             );
                                                         }
                                                     }
+                                                    let chunk = CompletionChunk {
+                                                        content: String::new(),
+                                                        finished: false,
+                                                        usage: None,
+                                                        tool_calls: None,
+                                                        tool_call_deltas: Some(deltas),
+                                                        reasoning: None,
+                                                        truncated: false,
+                                                    };
+                                                    if tx.send(Ok(chunk)).await.is_err() {
+                                                        debug!("Receiver dropped, stopping stream");
+                                                        return accumulated_usage;
+                                                    }
                                                 }
                                             }
 
+                                            if choice.finish_reason.as_deref() == Some("content_filter") {
+                                                let _ = tx
+                                                    .send(Err(ProviderError::content_filtered(
+                                                        "Databricks",
+                                                        "the content filter blocked it (finish_reason: content_filter)",
+                                                    )
+                                                    .into()))
+                                                    .await;
+                                                return accumulated_usage;
+                                            }
+
                                             // Check if this choice is finished
                                             if choice.finish_reason.is_some() {
                                                 debug!(
@@ -545,6 +691,10 @@ impl DatabricksProvider {
                                                     } else {
                                                         Some(final_tool_calls)
                                                     },
+                                                    tool_call_deltas: None,
+                                                    reasoning: None,
+                                                    truncated: choice.finish_reason.as_deref()
+                                                        == Some("length"),
                                                 };
                                                 if tx.send(Ok(final_chunk)).await.is_err() {
                                                     debug!("Receiver dropped, stopping stream");
@@ -601,7 +751,7 @@ impl DatabricksProvider {
                         // Don't send error, just break and finalize
                         break;
                     } else {
-                        let _ = tx.send(Err(anyhow!("Stream error: {}", e))).await;
+                        let _ = tx.send(Err(ProviderError::from_reqwest("Stream error", &e).into())).await;
                     }
                     return accumulated_usage;
                 }
@@ -668,6 +818,9 @@ impl DatabricksProvider {
             } else {
                 Some(final_tool_calls)
             },
+            tool_call_deltas: None,
+            reasoning: None,
+            truncated: false,
         };
         let _ = tx.send(Ok(final_chunk)).await;
         accumulated_usage
@@ -753,13 +906,15 @@ impl LLMProvider for DatabricksProvider {
         let max_tokens = request.max_tokens.unwrap_or(self.max_tokens);
         let temperature = request.temperature.unwrap_or(self.temperature);
 
-        let request_body = self.create_request_body(
+        let mut request_body = self.create_request_body(
             &request.messages,
             request.tools.as_deref(),
+            &request.tool_choice,
             false,
             max_tokens,
             temperature,
         )?;
+        request_body.stop = request.stop_sequences.clone();
 
         debug!(
             "Sending request to Databricks API: model={}, max_tokens={}, temperature={}",
@@ -775,75 +930,7 @@ impl LLMProvider for DatabricksProvider {
             );
         }
 
-        let mut provider_clone = self.clone();
-        let mut response = provider_clone
-            .create_request_builder(false)
-            .await?
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to send request to Databricks API: {}", e))?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-
-            // Check if this is a 403 Invalid Token error that we can retry with token refresh
-            if status == reqwest::StatusCode::FORBIDDEN
-                && (error_text.contains("Invalid Token") || error_text.contains("invalid_token"))
-            {
-                info!("Received 403 Invalid Token error, attempting to refresh OAuth token");
-
-                // Try to refresh the token if we're using OAuth
-                if let DatabricksAuth::OAuth { .. } = &provider_clone.auth {
-                    // Clear any cached token to force a refresh
-                    provider_clone.auth.clear_cached_token();
-
-                    // Try to get a new token (will attempt refresh or new OAuth flow)
-                    match provider_clone.auth.get_token().await {
-                        Ok(_new_token) => {
-                            info!("Successfully refreshed OAuth token, retrying request");
-
-                            // Retry the request with the new token
-                            response = provider_clone
-                                .create_request_builder(false)
-                                .await?
-                                .json(&request_body)
-                                .send()
-                                .await
-                                .map_err(|e| anyhow!("Failed to send request to Databricks API after token refresh: {}", e))?;
-
-                            let retry_status = response.status();
-                            if !retry_status.is_success() {
-                                let retry_error_text = response
-                                    .text()
-                                    .await
-                                    .unwrap_or_else(|_| "Unknown error".to_string());
-                                return Err(anyhow!(
-                                    "Databricks API error {} after token refresh: {}",
-                                    retry_status,
-                                    retry_error_text
-                                ));
-                            }
-                        }
-                        Err(e) => {
-                            return Err(anyhow!(
-                                "Failed to refresh OAuth token: {}. Original error: {}",
-                                e,
-                                error_text
-                            ));
-                        }
-                    }
-                } else {
-                    return Err(anyhow!("Databricks API error {}: {}", status, error_text));
-                }
-            } else {
-                return Err(anyhow!("Databricks API error {}: {}", status, error_text));
-            }
-        }
+        let response = self.send_request(&request_body, false).await?;
 
         let response_text = response.text().await?;
         debug!("Raw Databricks API response: {}", response_text);
@@ -867,6 +954,7 @@ impl LLMProvider for DatabricksProvider {
             .and_then(|choice| choice.message.content.as_ref())
             .cloned()
             .unwrap_or_default();
+        let content = stop::truncate(&content, &stop::sequences(self.stop_tokens(), &request));
 
         // Check if there are tool calls in the response
         if let Some(first_choice) = databricks_response.choices.first() {
@@ -891,6 +979,7 @@ impl LLMProvider for DatabricksProvider {
             prompt_tokens: databricks_response.usage.prompt_tokens,
             completion_tokens: databricks_response.usage.completion_tokens,
             total_tokens: databricks_response.usage.total_tokens,
+            reasoning_tokens: 0,
         };
 
         debug!(
@@ -922,13 +1011,15 @@ impl LLMProvider for DatabricksProvider {
         let max_tokens = request.max_tokens.unwrap_or(self.max_tokens);
         let temperature = request.temperature.unwrap_or(self.temperature);
 
-        let request_body = self.create_request_body(
+        let mut request_body = self.create_request_body(
             &request.messages,
             request.tools.as_deref(),
+            &request.tool_choice,
             true,
             max_tokens,
             temperature,
         )?;
+        request_body.stop = request.stop_sequences.clone();
 
         debug!(
             "Sending streaming request to Databricks API: model={}, max_tokens={}, temperature={}",
@@ -942,75 +1033,7 @@ impl LLMProvider for DatabricksProvider {
                 .unwrap_or_else(|_| "Failed to serialize".to_string())
         );
 
-        let mut provider_clone = self.clone();
-        let mut response = provider_clone
-            .create_request_builder(true)
-            .await?
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to send streaming request to Databricks API: {}", e))?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-
-            // Check if this is a 403 Invalid Token error that we can retry with token refresh
-            if status == reqwest::StatusCode::FORBIDDEN
-                && (error_text.contains("Invalid Token") || error_text.contains("invalid_token"))
-            {
-                info!("Received 403 Invalid Token error, attempting to refresh OAuth token");
-
-                // Try to refresh the token if we're using OAuth
-                if let DatabricksAuth::OAuth { .. } = &provider_clone.auth {
-                    // Clear any cached token to force a refresh
-                    provider_clone.auth.clear_cached_token();
-
-                    // Try to get a new token (will attempt refresh or new OAuth flow)
-                    match provider_clone.auth.get_token().await {
-                        Ok(_new_token) => {
-                            info!("Successfully refreshed OAuth token, retrying streaming request");
-
-                            // Retry the request with the new token
-                            response = provider_clone
-                                .create_request_builder(true)
-                                .await?
-                                .json(&request_body)
-                                .send()
-                                .await
-                                .map_err(|e| anyhow!("Failed to send streaming request to Databricks API after token refresh: {}", e))?;
-
-                            let retry_status = response.status();
-                            if !retry_status.is_success() {
-                                let retry_error_text = response
-                                    .text()
-                                    .await
-                                    .unwrap_or_else(|_| "Unknown error".to_string());
-                                return Err(anyhow!(
-                                    "Databricks API error {} after token refresh: {}",
-                                    retry_status,
-                                    retry_error_text
-                                ));
-                            }
-                        }
-                        Err(e) => {
-                            return Err(anyhow!(
-                                "Failed to refresh OAuth token: {}. Original error: {}",
-                                e,
-                                error_text
-                            ));
-                        }
-                    }
-                } else {
-                    return Err(anyhow!("Databricks API error {}: {}", status, error_text));
-                }
-            } else {
-                return Err(anyhow!("Databricks API error {}: {}", status, error_text));
-            }
-        }
+        let response = self.send_request(&request_body, true).await?;
 
         let stream = response.bytes_stream();
         let (tx, rx) = mpsc::channel(100);
@@ -1021,7 +1044,10 @@ impl LLMProvider for DatabricksProvider {
             provider.parse_streaming_response(stream, tx).await;
         });
 
-        Ok(ReceiverStream::new(rx))
+        Ok(stop::filter_stream(
+            ReceiverStream::new(rx),
+            stop::sequences(self.stop_tokens(), &request),
+        ))
     }
 
     fn name(&self) -> &str {
@@ -1037,6 +1063,35 @@ impl LLMProvider for DatabricksProvider {
         // This includes Claude, Llama, DBRX, and most other models on the platform
         true
     }
+
+    fn stop_tokens(&self) -> Vec<String> {
+        // Served open models (Llama, Qwen, ...) can let their template tokens through
+        stop::chat_template_tokens(&self.model)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        // Claude models have the same limits as on Anthropic's API
+        let claude = self.model.contains("claude");
+        Capabilities {
+            vision: false,
+            tool_streaming: true,
+            parallel_tools: true,
+            json_mode: false,
+            prompt_caching: false,
+            max_output_tokens: Some(if claude {
+                anthropic::output_limit(&self.model)
+            } else {
+                32_000
+            }),
+            context_window: Some(if claude {
+                anthropic::CONTEXT_WINDOW
+            } else if self.model.contains("llama") || self.model.contains("dbrx") {
+                32_768
+            } else {
+                16_384
+            }),
+        }
+    }
 }
 
 // Databricks API request/response structures
@@ -1048,6 +1103,10 @@ struct DatabricksRequest {
     temperature: f32,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<DatabricksTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
     stream: bool,
 }
 
@@ -1193,7 +1252,7 @@ mod tests {
         }];
 
         let request_body = provider
-            .create_request_body(&messages, None, false, 1000, 0.5)
+            .create_request_body(&messages, None, &ToolChoice::Auto, false, 1000, 0.5)
             .unwrap();
 
         assert_eq!(request_body.max_tokens, 1000);
@@ -1272,5 +1331,32 @@ mod tests {
         assert!(claude_provider.has_native_tool_calling());
         assert!(llama_provider.has_native_tool_calling());
         assert!(dbrx_provider.has_native_tool_calling());
+
+        let claude = claude_provider.capabilities();
+        assert_eq!(claude.context_window, Some(200_000));
+        assert_eq!(claude.max_output_tokens, Some(64_000));
+        assert_eq!(
+            llama_provider.capabilities().context_window,
+            Some(32_768)
+        );
+        assert!(!dbrx_provider.capabilities().vision);
+    }
+
+    #[test]
+    fn test_token_rejection() {
+        use reqwest::StatusCode;
+
+        assert!(is_token_rejected(StatusCode::UNAUTHORIZED, ""));
+        assert!(is_token_rejected(
+            StatusCode::FORBIDDEN,
+            r#"{"error_code":"403","message":"Invalid Token"}"#
+        ));
+        assert!(is_token_rejected(StatusCode::FORBIDDEN, "Token expired"));
+        // Missing permissions aren't fixed by a new token
+        assert!(!is_token_rejected(
+            StatusCode::FORBIDDEN,
+            "PERMISSION_DENIED: no access to endpoint"
+        ));
+        assert!(!is_token_rejected(StatusCode::TOO_MANY_REQUESTS, "expired"));
     }
 }
@@ -1,9 +1,12 @@
 use anyhow::Result;
+use crate::stop::{self, StopFilter};
+use crate::tool_grammar::tool_call_grammar;
 use crate::{
-    CompletionChunk, CompletionRequest, CompletionResponse, CompletionStream, LLMProvider, Message,
-    MessageRole, Usage,
+    Capabilities, CompletionChunk, CompletionRequest, CompletionResponse, CompletionStream,
+    LLMProvider, Message, MessageRole, ProviderError, ProviderErrorKind, Usage,
 };
 use llama_cpp::{
+    grammar::LlamaGrammar,
     standard_sampler::{SamplerStage, StandardSampler},
     LlamaModel, LlamaParams, LlamaSession, SessionParams,
 };
@@ -14,12 +17,25 @@ use tokio::sync::Mutex;
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, error, info};
 
+/// Context length of a `model_type` that isn't configured with one
+fn default_context_length(model_type: &str) -> u32 {
+    match model_type.to_lowercase().as_str() {
+        "codellama" => 16384, // CodeLlama supports 16k context
+        "llama" => 4096,      // Base Llama models
+        "mistral" => 8192,    // Mistral models
+        "qwen" => 32768,      // Qwen2.5 supports 32k context
+        _ => 4096,            // Conservative default
+    }
+}
+
 pub struct EmbeddedProvider {
     session: Arc<Mutex<LlamaSession>>,
     model_name: String,
     max_tokens: u32,
     temperature: f32,
     context_length: u32,
+    /// Constrain JSON tool calls with a grammar when a request lists tools
+    constrained_tool_calls: bool,
 }
 
 impl EmbeddedProvider {
@@ -58,7 +74,8 @@ impl EmbeddedProvider {
             info!("Using {} GPU layers", gpu_layers);
         }
 
-        let context_size = context_length.unwrap_or(4096);
+        let context_size =
+            context_length.unwrap_or_else(|| default_context_length(&model_type));
         info!("Using context length: {}", context_size);
 
         // Load the model
@@ -87,9 +104,23 @@ impl EmbeddedProvider {
             max_tokens: max_tokens.unwrap_or(2048),
             temperature: temperature.unwrap_or(0.1),
             context_length: context_size,
+            constrained_tool_calls: false,
         })
     }
 
+    /// Decode with a grammar that only allows valid JSON tool calls to the request's tools,
+    /// with the arguments their input schemas require
+    pub fn with_constrained_tool_calls(mut self, constrained_tool_calls: bool) -> Self {
+        self.constrained_tool_calls = constrained_tool_calls;
+        self
+    }
+
+    /// The tool call grammar for `request`, if tool calls are constrained and it lists tools
+    fn tool_grammar(&self, request: &CompletionRequest) -> Option<String> {
+        let tools = request.tools.as_ref().filter(|tools| !tools.is_empty())?;
+        self.constrained_tool_calls.then(|| tool_call_grammar(tools))
+    }
+
     fn format_messages(&self, messages: &[Message]) -> String {
         // Determine the appropriate format based on model type
         let model_name_lower = self.model_name.to_lowercase();
@@ -182,6 +213,7 @@ impl EmbeddedProvider {
         prompt: &str,
         max_tokens: u32,
         temperature: f32,
+        stop_sequences: Vec<String>,
     ) -> Result<String> {
         let session = self.session.clone();
         let prompt = prompt.to_string();
@@ -197,8 +229,7 @@ impl EmbeddedProvider {
         debug!("Context calculation: prompt_tokens={}, context_length={}, available_tokens={}, dynamic_max_tokens={}",
                prompt_tokens, self.context_length, available_tokens, dynamic_max_tokens);
 
-        // Get stop sequences before entering the closure
-        let stop_sequences = self.get_stop_sequences();
+        let stops = stop_sequences.clone();
 
         // Add timeout to the entire operation
         let timeout_duration = std::time::Duration::from_secs(30); // Increased timeout for larger contexts
@@ -219,7 +250,7 @@ impl EmbeddedProvider {
                                 debug!("Session busy, retrying in {}ms (attempt {}/5)", 100 * (attempt + 1), attempt + 1);
                                 std::thread::sleep(std::time::Duration::from_millis(100 * (attempt + 1) as u64));
                             } else {
-                                return Err(anyhow::anyhow!("Model is busy after 5 attempts, please try again"));
+                                return Err(ProviderError::new(ProviderErrorKind::ModelBusy, "Model is busy after 5 attempts, please try again").into());
                             }
                         }
                     }
@@ -289,16 +320,9 @@ impl EmbeddedProvider {
                     }
 
                     // Stop on completion markers
-                    let mut hit_stop = false;
-                    for stop_seq in &stop_sequences {
-                        if generated_text.contains(stop_seq) {
-                            debug!("Hit stop sequence '{}' at {} tokens", stop_seq, token_count);
-                            hit_stop = true;
-                            break;
-                        }
-                    }
-                    
-                    if hit_stop {
+                    let hit = stops.iter().find(|stop| generated_text.contains(stop.as_str()));
+                    if let Some(stop) = hit {
+                        debug!("Hit stop sequence '{}' at {} tokens", stop, token_count);
                         break;
                     }
                 }
@@ -322,8 +346,7 @@ impl EmbeddedProvider {
                             "Completed generation: {} tokens (dynamic limit was {})",
                             token_count, dynamic_max_tokens
                         );
-                        // Clean stop sequences from the generated text after the closure
-                        Ok(self.clean_stop_sequences(&text))
+                        Ok(stop::truncate(&text, &stop_sequences).trim().to_string())
                     }
                     Err(e) => Err(e),
                 },
@@ -331,7 +354,7 @@ impl EmbeddedProvider {
             },
             Err(_) => {
                 error!("Generation timed out after 30 seconds");
-                Err(anyhow::anyhow!("Generation timed out"))
+                Err(ProviderError::new(ProviderErrorKind::Timeout, "Generation timed out").into())
             }
         }
     }
@@ -343,85 +366,6 @@ impl EmbeddedProvider {
         (text.len() as f32 / 4.0).ceil() as u32
     }
 
-    // Helper function to get stop sequences based on model type
-    fn get_stop_sequences(&self) -> Vec<&'static str> {
-        // Determine model type from model_name
-        let model_name_lower = self.model_name.to_lowercase();
-        
-        if model_name_lower.contains("qwen") {
-            vec![
-                "<|im_end|>",     // Qwen ChatML format end token
-                "<|endoftext|>",  // Alternative end token
-                "</s>",           // Generic end of sequence
-                "<|im_start|>",   // Start of new message (shouldn't appear in response)
-            ]
-        } else if model_name_lower.contains("codellama") || model_name_lower.contains("code-llama") {
-            vec![
-                "</s>",           // End of sequence
-                "[/INST]",        // End of instruction  
-                "<</SYS>>",       // End of system message
-                "[INST]",         // Start of new instruction (shouldn't appear in response)
-                "<<SYS>>",        // Start of system (shouldn't appear in response)
-            ]
-        } else if model_name_lower.contains("llama") {
-            vec![
-                "</s>",           // End of sequence
-                "[/INST]",        // End of instruction
-                "<</SYS>>",       // End of system message
-                "### Human:",     // Conversation format
-                "### Assistant:", // Conversation format
-                "[INST]",         // Start of new instruction
-            ]
-        } else if model_name_lower.contains("mistral") {
-            vec![
-                "</s>",           // End of sequence
-                "[/INST]",        // End of instruction
-                "<|im_end|>",     // ChatML format
-            ]
-        } else if model_name_lower.contains("vicuna") || model_name_lower.contains("wizard") {
-            vec![
-                "### Human:",     // Conversation format
-                "### Assistant:", // Conversation format
-                "USER:",          // Alternative format
-                "ASSISTANT:",     // Alternative format
-                "</s>",           // End of sequence
-            ]
-        } else if model_name_lower.contains("alpaca") {
-            vec![
-                "### Instruction:", // Alpaca format
-                "### Response:",    // Alpaca format
-                "### Input:",       // Alpaca format
-                "</s>",            // End of sequence
-            ]
-        } else {
-            // Generic/unknown model - use common stop sequences
-            vec![
-                "</s>",           // Most common end sequence
-                "<|endoftext|>",  // GPT-style
-                "<|im_end|>",     // ChatML
-                "### Human:",     // Common conversation format
-                "### Assistant:", // Common conversation format
-                "[/INST]",        // Instruction format
-                "<</SYS>>",       // System format
-            ]
-        }
-    }
-
-    // Helper function to clean up stop sequences from generated text
-    fn clean_stop_sequences(&self, text: &str) -> String {
-        let mut cleaned = text.to_string();
-        let stop_sequences = self.get_stop_sequences();
-        
-        for stop_seq in &stop_sequences {
-            if let Some(pos) = cleaned.find(stop_seq) {
-                cleaned.truncate(pos);
-                break; // Only remove the first occurrence to avoid over-truncation
-            }
-        }
-        
-        cleaned.trim().to_string()
-    }
-
     // Download the Qwen 2.5 7B model if it doesn't exist
     fn download_qwen_model(model_path: &Path) -> Result<()> {
         use std::fs;
@@ -496,8 +440,9 @@ impl LLMProvider for EmbeddedProvider {
 
         debug!("Formatted prompt length: {} chars", prompt.len());
 
+        let stop_sequences = stop::sequences(self.stop_tokens(), &request);
         let content = self
-            .generate_completion(&prompt, max_tokens, temperature)
+            .generate_completion(&prompt, max_tokens, temperature, stop_sequences)
             .await?;
 
         // Estimate token usage (rough approximation)
@@ -510,6 +455,7 @@ impl LLMProvider for EmbeddedProvider {
                 prompt_tokens,
                 completion_tokens,
                 total_tokens: prompt_tokens + completion_tokens,
+                reasoning_tokens: 0,
             },
             model: self.model_name.clone(),
         })
@@ -524,6 +470,8 @@ impl LLMProvider for EmbeddedProvider {
         let prompt = self.format_messages(&request.messages);
         let max_tokens = request.max_tokens.unwrap_or(self.max_tokens);
         let temperature = request.temperature.unwrap_or(self.temperature);
+        let grammar = self.tool_grammar(&request);
+        let stop_sequences = stop::sequences(self.stop_tokens(), &request);
 
         let (tx, rx) = mpsc::channel(100);
         let session = self.session.clone();
@@ -544,7 +492,7 @@ impl LLMProvider for EmbeddedProvider {
                             debug!("Session busy, retrying in {}ms (attempt {}/5)", 100 * (attempt + 1), attempt + 1);
                             std::thread::sleep(std::time::Duration::from_millis(100 * (attempt + 1) as u64));
                         } else {
-                            let _ = tx.blocking_send(Err(anyhow::anyhow!("Model is busy after 5 attempts, please try again")));
+                            let _ = tx.blocking_send(Err(ProviderError::new(ProviderErrorKind::ModelBusy, "Model is busy after 5 attempts, please try again").into()));
                             return;
                         }
                     }
@@ -565,12 +513,25 @@ impl LLMProvider for EmbeddedProvider {
                 return;
             }
 
-            // Create sampler with temperature
-            let stages = vec![
+            // Create sampler with temperature, after the tool call grammar if there is one
+            let mut stages = Vec::new();
+            if let Some(grammar) = grammar {
+                match grammar.parse::<LlamaGrammar>() {
+                    Ok(grammar) => stages.push(SamplerStage::from_grammar(grammar, None)),
+                    Err(e) => {
+                        let _ = tx.blocking_send(Err(anyhow::anyhow!(
+                            "Failed to parse the tool call grammar: {:?}",
+                            e
+                        )));
+                        return;
+                    }
+                }
+            }
+            stages.extend([
                 SamplerStage::Temperature(temperature),
                 SamplerStage::TopK(40),
                 SamplerStage::TopP(0.9),
-            ];
+            ]);
             let sampler = StandardSampler::new_softmax(stages, 1);
 
             // Start completion
@@ -585,178 +546,53 @@ impl LLMProvider for EmbeddedProvider {
                 }
             };
 
-            let mut accumulated_text = String::new();
+            let mut filter = StopFilter::new(stop_sequences);
             let mut token_count = 0;
-            let mut unsent_tokens = String::new(); // Buffer for tokens we're holding back
-            
-            // Get stop sequences dynamically based on model type
-            let stop_sequences = if prompt.contains("<|im_start|>") {
-                // Qwen ChatML format detected
-                vec!["<|im_end|>", "<|endoftext|>", "</s>", "<|im_start|>"]
-            } else if prompt.contains("[INST]") || prompt.contains("<<SYS>>") {
-                // Llama/CodeLlama format detected
-                vec!["</s>", "[/INST]", "<</SYS>>", "[INST]", "<<SYS>>", "### Human:", "### Assistant:"]
-            } else {
-                // Generic format
-                vec!["</s>", "<|endoftext|>", "<|im_end|>", "### Human:", "### Assistant:", "[/INST]", "<</SYS>>"]
-            };
+            let mut truncated = false; // Stopped at max_tokens rather than a stop sequence
 
             // Stream tokens with proper limits
             while let Some(token) = completion_handle.next_token() {
                 let token_string = session.model().token_to_piece(token);
-
-                accumulated_text.push_str(&token_string);
-                unsent_tokens.push_str(&token_string);
                 token_count += 1;
 
-                // Check if we've hit a complete stop sequence
-                let mut hit_stop = false;
-                for stop_seq in &stop_sequences {
-                    if accumulated_text.contains(stop_seq) {
-                        debug!("Hit complete stop sequence in streaming: {}", stop_seq);
-                        hit_stop = true;
+                // Text that could begin a stop sequence is held back until it's known not to
+                let content = filter.push(&token_string);
+                if !content.is_empty() {
+                    let chunk = CompletionChunk {
+                        content,
+                        finished: false,
+                        usage: None,
+                        tool_calls: None,
+                        tool_call_deltas: None,
+                        reasoning: None,
+                        truncated: false,
+                    };
+                    if tx.blocking_send(Ok(chunk)).is_err() {
                         break;
                     }
                 }
-
-                if hit_stop {
-                    // Before stopping, check if there might be an incomplete tool call
-                    // Look for JSON tool call patterns that might be cut off by the stop sequence
-                    let has_potential_tool_call = accumulated_text.contains(r#"{"tool":"#) || 
-                                                  accumulated_text.contains(r#"{"{""tool"":"#) ||
-                                                  accumulated_text.contains(r#"{{""tool"":"#);
-                    
-                    if has_potential_tool_call {
-                        // Check if the tool call appears to be complete (has closing brace after the stop sequence)
-                        let mut complete_tool_call = false;
-                        for stop_seq in &stop_sequences {
-                            if let Some(stop_pos) = accumulated_text.find(stop_seq) {
-                                // Look for tool call pattern before the stop sequence
-                                let before_stop = &accumulated_text[..stop_pos];
-                                if let Some(tool_start) = before_stop.rfind(r#"{"tool":"#) {
-                                    let tool_part = &before_stop[tool_start..];
-                                    // Count braces to see if JSON is complete
-                                    let open_braces = tool_part.matches('{').count();
-                                    let close_braces = tool_part.matches('}').count();
-                                    if open_braces > 0 && open_braces == close_braces {
-                                        complete_tool_call = true;
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        
-                        // If tool call is incomplete, send the raw content including stop sequences
-                        // so the main parser can handle it properly
-                        if !complete_tool_call {
-                            debug!("Found incomplete tool call, sending raw content with stop sequences");
-                            let already_sent_len = accumulated_text.len() - unsent_tokens.len();
-                            if accumulated_text.len() > already_sent_len {
-                                let remaining_to_send = &accumulated_text[already_sent_len..];
-                                if !remaining_to_send.is_empty() {
-                                    let chunk = CompletionChunk {
-                                        content: remaining_to_send.to_string(),
-                                        finished: false,
-                                        usage: None,
-                                        tool_calls: None,
-                                    };
-                                    let _ = tx.blocking_send(Ok(chunk));
-                                }
-                            }
-                            break;
-                        }
-                    }
-                    
-                    // Send any remaining clean content before stopping (original behavior)
-                    let mut clean_accumulated = accumulated_text.clone();
-                    for stop_seq in &stop_sequences {
-                        if let Some(pos) = clean_accumulated.find(stop_seq) {
-                            clean_accumulated.truncate(pos);
-                            break;
-                        }
-                    }
-                    
-                    // Calculate what part we haven't sent yet
-                    let already_sent_len = accumulated_text.len() - unsent_tokens.len();
-                    if clean_accumulated.len() > already_sent_len {
-                        let remaining_to_send = &clean_accumulated[already_sent_len..];
-                        if !remaining_to_send.is_empty() {
-                            let chunk = CompletionChunk {
-                                content: remaining_to_send.to_string(),
-                                finished: false,
-                                usage: None,
-                                tool_calls: None,
-                            };
-                            let _ = tx.blocking_send(Ok(chunk));
-                        }
-                    }
+                if filter.is_stopped() {
+                    debug!("Hit stop sequence in streaming after {} tokens", token_count);
                     break;
                 }
 
-                // Check if we're building towards a stop sequence
-                let mut might_be_stop = false;
-                for stop_seq in &stop_sequences {
-                    for i in 1..stop_seq.len() {
-                        let partial = &stop_seq[..i];
-                        if accumulated_text.ends_with(partial) {
-                            debug!("Detected potential partial stop sequence: '{}'", partial);
-                            might_be_stop = true;
-                            break;
-                        }
-                    }
-                    if might_be_stop {
-                        break;
-                    }
-                }
-
-                if might_be_stop {
-                    // Hold back tokens, but only for a limited buffer size
-                    if unsent_tokens.len() > 20 { // Don't hold back more than 20 characters
-                        // Send the oldest part and keep only the recent part that might be a stop sequence
-                        let to_send = &unsent_tokens[..unsent_tokens.len() - 10];
-                        if !to_send.is_empty() {
-                            let chunk = CompletionChunk {
-                                content: to_send.to_string(),
-                                finished: false,
-                                usage: None,
-                                tool_calls: None,
-                            };
-                            if tx.blocking_send(Ok(chunk)).is_err() {
-                                break;
-                            }
-                        }
-                        unsent_tokens = unsent_tokens[unsent_tokens.len() - 10..].to_string();
-                    }
-                    // Continue to next token without sending
-                } else {
-                    // No potential stop sequence, send all unsent tokens
-                    if !unsent_tokens.is_empty() {
-                        let chunk = CompletionChunk {
-                            content: unsent_tokens.clone(),
-                            finished: false,
-                            usage: None,
-                            tool_calls: None,
-                        };
-                        if tx.blocking_send(Ok(chunk)).is_err() {
-                            break;
-                        }
-                        unsent_tokens.clear();
-                    }
-                }
-
                 // Enforce token limit
                 if token_count >= max_tokens as usize {
                     debug!("Reached max token limit in streaming: {}", max_tokens);
+                    truncated = true;
                     break;
                 }
             }
 
-            // Send final chunk
+            // Send final chunk, with the text held back if no stop sequence completed it
             let final_chunk = CompletionChunk {
-                content: String::new(),
+                content: filter.finish(),
                 finished: true,
                 usage: None,  // Embedded models calculate usage differently
                 tool_calls: None,
+                tool_call_deltas: None,
+                reasoning: None,
+                truncated,
             };
             let _ = tx.blocking_send(Ok(final_chunk));
         });
@@ -771,4 +607,22 @@ impl LLMProvider for EmbeddedProvider {
     fn model(&self) -> &str {
         &self.model_name
     }
+
+    fn stop_tokens(&self) -> Vec<String> {
+        // format_messages uses ChatML for Qwen and the [INST] format for everything else
+        if self.model_name.to_lowercase().contains("qwen") {
+            stop::chat_template_tokens("qwen")
+        } else {
+            stop::chat_template_tokens("llama")
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            max_output_tokens: Some(self.max_tokens),
+            context_window: Some(self.context_length),
+            json_mode: self.constrained_tool_calls,
+            ..Capabilities::default()
+        }
+    }
 }
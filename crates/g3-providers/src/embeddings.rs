@@ -0,0 +1,257 @@
+//! Embeddings: vectors whose closeness says how alike two pieces of text are.
+//!
+//! [`OpenAIEmbeddings`] calls the `/embeddings` endpoint of OpenAI or an OpenAI-compatible
+//! server, which is also how local embedding models are used: there is no in-process one,
+//! but Ollama and llama.cpp's `llama-server` offer the endpoint. [`HashedEmbeddings`] needs neither a model nor the network: it hashes the words
+//! of a text, split at camelCase and snake_case boundaries, and their character trigrams
+//! into a fixed number of dimensions. It is lexical, not semantic: it catches shared
+//! vocabulary ("retry", "retries", "max_retry_attempts") rather than meaning, but is
+//! instant and works offline.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::debug;
+
+use crate::{http, ProviderError};
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Embedding model used with OpenAI unless another is configured
+pub const DEFAULT_OPENAI_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+/// Texts sent to the embeddings endpoint per request
+const OPENAI_BATCH_SIZE: usize = 64;
+
+/// Dimensions of [`HashedEmbeddings`] vectors
+pub const HASHED_DIMENSIONS: usize = 512;
+
+/// Turns texts into vectors
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// One vector per text, in the same order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    /// Get the provider name
+    fn name(&self) -> &str;
+
+    /// Get the model name. Vectors of different models can't be compared.
+    fn model(&self) -> &str;
+}
+
+/// Cosine similarity of two vectors: 1 for the same direction, 0 for unrelated ones
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// The embeddings endpoint of OpenAI or an OpenAI-compatible server
+#[derive(Clone)]
+pub struct OpenAIEmbeddings {
+    client: Client,
+    name: String,
+    api_key: String,
+    model: String,
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl OpenAIEmbeddings {
+    pub fn new(
+        name: String,
+        api_key: String,
+        model: Option<String>,
+        base_url: Option<String>,
+    ) -> Self {
+        Self {
+            client: http::client(),
+            name,
+            api_key,
+            model: model.unwrap_or_else(|| DEFAULT_OPENAI_EMBEDDING_MODEL.to_string()),
+            base_url: base_url.unwrap_or_else(|| OPENAI_BASE_URL.to_string()),
+        }
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/embeddings", self.base_url.trim_end_matches('/'));
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.api_key)
+            .json(&json!({ "model": self.model, "input": texts }))
+            .send()
+            .await
+            .map_err(|e| {
+                ProviderError::from_reqwest("Failed to send request to embeddings API", &e)
+            })?;
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_response(&self.name, status, &error_text).into());
+        }
+
+        let mut body: EmbeddingsResponse = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::from_reqwest("Failed to read embeddings response", &e))?;
+        if body.data.len() != texts.len() {
+            anyhow::bail!(
+                "Embeddings API returned {} vectors for {} texts",
+                body.data.len(),
+                texts.len()
+            );
+        }
+        body.data.sort_by_key(|data| data.index);
+        Ok(body.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAIEmbeddings {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(OPENAI_BATCH_SIZE) {
+            debug!("Embedding {} texts with {}", batch.len(), self.model);
+            vectors.extend(self.embed_batch(batch).await?);
+        }
+        Ok(vectors)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Lexical embeddings computed locally by hashing words and character trigrams
+#[derive(Debug, Clone, Default)]
+pub struct HashedEmbeddings;
+
+impl HashedEmbeddings {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The vector of one text, normalized to unit length
+    pub fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; HASHED_DIMENSIONS];
+        for word in words(text) {
+            add_feature(&mut vector, word.as_bytes(), 1.0);
+            let padded: Vec<char> = format!("<{}>", word).chars().collect();
+            for trigram in padded.windows(3) {
+                let trigram: String = trigram.iter().collect();
+                add_feature(&mut vector, trigram.as_bytes(), 0.5);
+            }
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashedEmbeddings {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_text(text)).collect())
+    }
+
+    fn name(&self) -> &str {
+        "hashed"
+    }
+
+    fn model(&self) -> &str {
+        "hashed-trigrams"
+    }
+}
+
+/// Lowercase words of `text`, split at non-alphanumeric characters and at camelCase
+/// boundaries. Single characters and digits alone are left out.
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for token in text.split(|c: char| !c.is_alphanumeric()) {
+        let mut word = String::new();
+        let mut previous_lower = false;
+        for c in token.chars() {
+            if c.is_uppercase() && previous_lower && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            previous_lower = c.is_lowercase() || c.is_ascii_digit();
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+    }
+    words.retain(|word| word.chars().count() > 1 && !word.chars().all(|c| c.is_ascii_digit()));
+    words
+}
+
+/// Add `weight` to the dimension `feature` hashes to, with a sign from the hash too so
+/// collisions cancel out rather than pile up
+fn add_feature(vector: &mut [f32], feature: &[u8], weight: f32) {
+    let hash = fnv1a(feature);
+    let index = (hash % vector.len() as u64) as usize;
+    let sign = if (hash >> 63) == 0 { 1.0 } else { -1.0 };
+    vector[index] += sign * weight;
+}
+
+/// FNV-1a, which unlike the standard library's hasher is the same on every run and
+/// version, so saved vectors stay comparable
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_split_identifiers() {
+        assert_eq!(
+            words("fn retryWithBackoff(max_retry_attempts: u32) // 3 tries"),
+            vec!["fn", "retry", "with", "backoff", "max", "retry", "attempts", "u32", "tries"]
+        );
+    }
+
+    #[test]
+    fn test_hashed_embeddings_rank_related_text_higher() {
+        let hashed = HashedEmbeddings::new();
+        let query = hashed.embed_text("where is retry logic implemented?");
+        let retry = hashed.embed_text(
+            "async fn retry_with_backoff(attempts: u32) { for attempt in 0..attempts { retries += 1 } }",
+        );
+        let parser = hashed.embed_text("fn parse_markdown_table(input: &str) -> Table");
+
+        assert!((cosine_similarity(&retry, &retry) - 1.0).abs() < 1e-5);
+        assert!(cosine_similarity(&query, &retry) > cosine_similarity(&query, &parser));
+    }
+}
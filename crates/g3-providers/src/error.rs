@@ -0,0 +1,178 @@
+//! Typed provider errors.
+//!
+//! Providers report failures as [`ProviderError`]s inside their `anyhow` errors so callers
+//! can decide whether to retry from the [`ProviderErrorKind`] instead of the message text.
+
+use reqwest::StatusCode;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderErrorKind {
+    /// Missing, invalid or expired credentials
+    Auth,
+    /// Too many requests
+    RateLimit,
+    /// The prompt does not fit in the model's context window
+    ContextOverflow,
+    /// The model is overloaded or still busy with another request
+    ModelBusy,
+    /// The provider failed on its side (5xx)
+    Server,
+    /// The request could not be sent or the connection dropped
+    Network,
+    /// The provider took too long to respond
+    Timeout,
+    /// The provider's content filter blocked the prompt or the response, or the model
+    /// refused to answer. Sending the same request again gets the same result.
+    ContentFilter,
+    /// The provider rejected the request for any other reason
+    Other,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("{message}")]
+pub struct ProviderError {
+    pub kind: ProviderErrorKind,
+    /// HTTP status of the response, if there was one
+    pub status: Option<u16>,
+    pub message: String,
+    /// How long the provider asked to wait before retrying, e.g. until a rate limit resets
+    pub retry_after: Option<Duration>,
+}
+
+impl ProviderError {
+    pub fn new(kind: ProviderErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            status: None,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// A response `provider` (e.g. "Anthropic") stopped because of its content filter, or
+    /// that the model refused; `reason` is the finish reason or the model's refusal
+    pub fn content_filtered(provider: &str, reason: &str) -> Self {
+        Self::new(
+            ProviderErrorKind::ContentFilter,
+            format!("{} stopped the response: {}", provider, reason.trim()),
+        )
+    }
+
+    /// Classify a non-success HTTP response from `provider` (e.g. "Anthropic")
+    pub fn from_response(provider: &str, status: StatusCode, body: &str) -> Self {
+        let kind = match status.as_u16() {
+            401 | 403 => ProviderErrorKind::Auth,
+            429 => ProviderErrorKind::RateLimit,
+            413 => ProviderErrorKind::ContextOverflow,
+            400 if is_context_overflow(body) => ProviderErrorKind::ContextOverflow,
+            400 if is_content_filter(body) => ProviderErrorKind::ContentFilter,
+            408 => ProviderErrorKind::Timeout,
+            // Anthropic's "overloaded" status
+            529 => ProviderErrorKind::ModelBusy,
+            503 if body.to_lowercase().contains("overloaded") => ProviderErrorKind::ModelBusy,
+            500..=599 => ProviderErrorKind::Server,
+            _ => ProviderErrorKind::Other,
+        };
+        Self {
+            kind,
+            status: Some(status.as_u16()),
+            message: format!("{} API error {}: {}", provider, status, body),
+            retry_after: None,
+        }
+    }
+
+    /// A failure to send a request or to read its response; `context` describes what was
+    /// being attempted
+    pub fn from_reqwest(context: &str, error: &reqwest::Error) -> Self {
+        let kind = if error.is_timeout() {
+            ProviderErrorKind::Timeout
+        } else {
+            ProviderErrorKind::Network
+        };
+        Self {
+            kind,
+            status: error.status().map(|s| s.as_u16()),
+            message: format!("{}: {}", context, error),
+            retry_after: None,
+        }
+    }
+
+    /// An error event inside an otherwise successful stream, classified by the
+    /// provider's error type (e.g. Anthropic's `overloaded_error` or OpenAI's
+    /// `rate_limit_exceeded`)
+    pub fn from_stream_event(provider: &str, error_type: &str, message: &str) -> Self {
+        let kind = match error_type {
+            "authentication_error" | "permission_error" => ProviderErrorKind::Auth,
+            "rate_limit_error" | "rate_limit_exceeded" => ProviderErrorKind::RateLimit,
+            "overloaded_error" => ProviderErrorKind::ModelBusy,
+            "api_error" | "server_error" => ProviderErrorKind::Server,
+            "request_too_large" | "context_length_exceeded" => ProviderErrorKind::ContextOverflow,
+            _ if is_context_overflow(message) => ProviderErrorKind::ContextOverflow,
+            _ if is_content_filter(error_type) || is_content_filter(message) => {
+                ProviderErrorKind::ContentFilter
+            }
+            _ => ProviderErrorKind::Other,
+        };
+        Self::new(
+            kind,
+            format!("{} API error ({}): {}", provider, error_type, message),
+        )
+    }
+}
+
+/// Content filter rejections are a 400 too, e.g. OpenAI's `content_policy_violation` and
+/// Azure's `content_filter`
+fn is_content_filter(body: &str) -> bool {
+    let body = body.to_lowercase();
+    body.contains("content_filter")
+        || body.contains("content_policy_violation")
+        || body.contains("content management policy")
+}
+
+/// Providers reject over-long prompts with a plain 400, so the body has to be checked
+fn is_context_overflow(body: &str) -> bool {
+    let body = body.to_lowercase();
+    body.contains("context length")
+        || body.contains("context_length_exceeded")
+        || body.contains("maximum context length")
+        || body.contains("prompt is too long")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_response_classifies_by_status() {
+        let kind = |status: u16, body: &str| {
+            ProviderError::from_response("Test", StatusCode::from_u16(status).unwrap(), body).kind
+        };
+        assert_eq!(kind(401, "invalid x-api-key"), ProviderErrorKind::Auth);
+        assert_eq!(kind(429, "slow down"), ProviderErrorKind::RateLimit);
+        assert_eq!(kind(529, "overloaded"), ProviderErrorKind::ModelBusy);
+        assert_eq!(kind(502, "bad gateway"), ProviderErrorKind::Server);
+        assert_eq!(
+            kind(400, "{\"error\": \"prompt is too long: 210000 tokens\"}"),
+            ProviderErrorKind::ContextOverflow
+        );
+        assert_eq!(kind(400, "missing field"), ProviderErrorKind::Other);
+        assert_eq!(
+            kind(400, "{\"error\": {\"code\": \"content_filter\"}}"),
+            ProviderErrorKind::ContentFilter
+        );
+
+        let error = ProviderError::from_response("Anthropic", StatusCode::TOO_MANY_REQUESTS, "{}");
+        assert_eq!(error.status, Some(429));
+        assert_eq!(
+            error.to_string(),
+            "Anthropic API error 429 Too Many Requests: {}"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Hosted OpenAI-compatible services g3 knows by name: Groq and Cerebras.
+//!
+//! Both serve open-weight models on custom hardware at hundreds to thousands of output
+//! tokens per second, which makes them a good fit for interactive sessions. Configured as
+//! `[providers.openai_compatible.groq]` or `[providers.openai_compatible.cerebras]`, they
+//! need no `base_url`, and the context window of their models is known.
+//!
+//! Their rate limits are tight on lower tiers, and both say in response headers when the
+//! limit resets: Groq with `retry-after` and `x-ratelimit-reset-tokens` (e.g. `7.66s`,
+//! `2m59.56s`), Cerebras with `x-ratelimit-reset-tokens-minute` and
+//! `x-ratelimit-reset-requests-day` (in seconds). A rate-limited request is retried once
+//! the limit resets rather than on the usual backoff schedule.
+
+use reqwest::header::HeaderMap;
+use std::time::Duration;
+
+/// A model a hosted service serves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostedModel {
+    pub id: &'static str,
+    pub context_length: u32,
+    /// Typical output speed the service advertises, in tokens per second
+    pub tokens_per_second: u32,
+}
+
+/// A hosted OpenAI-compatible service
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostedService {
+    /// The `openai_compatible` name it is configured under
+    pub name: &'static str,
+    /// Name used in messages
+    pub label: &'static str,
+    pub base_url: &'static str,
+    pub models: &'static [HostedModel],
+}
+
+impl HostedService {
+    /// The model with id `model`, if the service is known to serve it
+    pub fn model(&self, model: &str) -> Option<&HostedModel> {
+        self.models.iter().find(|m| m.id == model)
+    }
+}
+
+pub const GROQ: HostedService = HostedService {
+    name: "groq",
+    label: "Groq",
+    base_url: "https://api.groq.com/openai/v1",
+    models: &[
+        HostedModel {
+            id: "llama-3.3-70b-versatile",
+            context_length: 131_072,
+            tokens_per_second: 280,
+        },
+        HostedModel {
+            id: "llama-3.1-8b-instant",
+            context_length: 131_072,
+            tokens_per_second: 560,
+        },
+        HostedModel {
+            id: "openai/gpt-oss-120b",
+            context_length: 131_072,
+            tokens_per_second: 500,
+        },
+        HostedModel {
+            id: "openai/gpt-oss-20b",
+            context_length: 131_072,
+            tokens_per_second: 1000,
+        },
+        HostedModel {
+            id: "moonshotai/kimi-k2-instruct",
+            context_length: 131_072,
+            tokens_per_second: 200,
+        },
+        HostedModel {
+            id: "qwen/qwen3-32b",
+            context_length: 131_072,
+            tokens_per_second: 400,
+        },
+    ],
+};
+
+pub const CEREBRAS: HostedService = HostedService {
+    name: "cerebras",
+    label: "Cerebras",
+    base_url: "https://api.cerebras.ai/v1",
+    models: &[
+        HostedModel {
+            id: "llama-3.3-70b",
+            context_length: 65_536,
+            tokens_per_second: 2100,
+        },
+        HostedModel {
+            id: "llama3.1-8b",
+            context_length: 32_768,
+            tokens_per_second: 2200,
+        },
+        HostedModel {
+            id: "gpt-oss-120b",
+            context_length: 131_072,
+            tokens_per_second: 3000,
+        },
+        HostedModel {
+            id: "qwen-3-32b",
+            context_length: 65_536,
+            tokens_per_second: 2600,
+        },
+        HostedModel {
+            id: "qwen-3-coder-480b",
+            context_length: 131_072,
+            tokens_per_second: 2000,
+        },
+    ],
+};
+
+/// The hosted service configured as `name`, if g3 knows it
+pub fn service(name: &str) -> Option<&'static HostedService> {
+    [&GROQ, &CEREBRAS]
+        .into_iter()
+        .find(|service| service.name == name)
+}
+
+/// Headers that say when a rate limit resets, most specific first
+const RESET_HEADERS: &[&str] = &[
+    "retry-after",
+    "x-ratelimit-reset-tokens",
+    "x-ratelimit-reset-requests",
+    "x-ratelimit-reset-tokens-minute",
+    "x-ratelimit-reset-requests-minute",
+    "x-ratelimit-reset-requests-day",
+];
+
+/// How long to wait before retrying a rate-limited request, from the response's headers
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    RESET_HEADERS.iter().find_map(|name| {
+        let value = headers.get(*name)?.to_str().ok()?;
+        parse_reset(value)
+    })
+}
+
+/// A reset time as plain seconds (`"12"`, `"0.5"`) or as Go-style durations
+/// (`"2m59.56s"`, `"7.66s"`, `"120ms"`), to the millisecond
+fn parse_reset(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    let millis = match value.parse::<f64>() {
+        Ok(seconds) => seconds * 1000.0,
+        Err(_) => {
+            let mut total = 0.0;
+            let mut rest = value;
+            while !rest.is_empty() {
+                let number_end = rest
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                    .unwrap_or(rest.len());
+                let number: f64 = rest[..number_end].parse().ok()?;
+                rest = &rest[number_end..];
+                let unit_end = rest
+                    .find(|c: char| c.is_ascii_digit() || c == '.')
+                    .unwrap_or(rest.len());
+                total += number
+                    * match &rest[..unit_end] {
+                        "h" => 3_600_000.0,
+                        "m" => 60_000.0,
+                        "s" => 1000.0,
+                        "ms" => 1.0,
+                        _ => return None,
+                    };
+                rest = &rest[unit_end..];
+            }
+            total
+        }
+    };
+    (millis.is_finite() && millis >= 0.0).then(|| Duration::from_millis(millis.round() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_retry_after_from_rate_limit_headers() {
+        assert_eq!(
+            parse_reset("2m59.56s"),
+            Some(Duration::from_millis(179_560))
+        );
+        assert_eq!(parse_reset("7.66s"), Some(Duration::from_millis(7660)));
+        assert_eq!(parse_reset("120ms"), Some(Duration::from_millis(120)));
+        assert_eq!(parse_reset("12"), Some(Duration::from_secs(12)));
+        assert_eq!(parse_reset("soon"), None);
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(
+            "x-ratelimit-reset-tokens-minute",
+            HeaderValue::from_static("41.5"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(41_500)));
+        headers.insert("retry-after", HeaderValue::from_static("3"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+
+        assert_eq!(service("groq").unwrap().label, "Groq");
+        assert_eq!(
+            service("cerebras")
+                .and_then(|s| s.model("gpt-oss-120b"))
+                .map(|m| m.context_length),
+            Some(131_072)
+        );
+        assert!(service("openrouter").is_none());
+    }
+}
@@ -0,0 +1,121 @@
+//! The HTTP client the providers share.
+//!
+//! Every provider sends its requests through one pooled client, so connections and their
+//! TLS sessions are reused from request to request, across turns, and across providers that
+//! talk to the same host, and HTTP/2 is negotiated with servers that offer it. Timeouts are
+//! set on each request, since providers allow different times for a response.
+//!
+//! The prompts of long sessions run to megabytes of JSON. Providers whose server accepts
+//! `Content-Encoding: gzip` request bodies (`compress_requests`) send bodies larger than
+//! [`COMPRESS_MIN_BYTES`] compressed.
+
+use anyhow::Result;
+use flate2::{write::GzEncoder, Compression};
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Bodies smaller than this are sent as they are
+pub const COMPRESS_MIN_BYTES: usize = 16 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Idle connections are kept this long for the next request
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The shared client. Clones share its connection pool.
+pub fn client() -> Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+                .tcp_keepalive(KEEP_ALIVE_INTERVAL)
+                .http2_keep_alive_interval(KEEP_ALIVE_INTERVAL)
+                .http2_keep_alive_while_idle(true)
+                .http2_adaptive_window(true)
+                .build()
+                .unwrap_or_else(|e| {
+                    warn!(
+                        "Failed to set up the shared HTTP client, using defaults: {}",
+                        e
+                    );
+                    Client::new()
+                })
+        })
+        .clone()
+}
+
+/// Set `body` as the JSON body of `builder`, gzip-compressed if `compress` is set and it is
+/// large enough to be worth it
+pub fn json_body<T: Serialize + ?Sized>(
+    builder: RequestBuilder,
+    body: &T,
+    compress: bool,
+) -> Result<RequestBuilder> {
+    let json = serde_json::to_vec(body)?;
+    let builder = builder.header("content-type", "application/json");
+    if !compress || json.len() < COMPRESS_MIN_BYTES {
+        return Ok(builder.body(json));
+    }
+    let compressed = gzip(&json)?;
+    debug!(
+        "Compressed request body from {} to {} bytes",
+        json.len(),
+        compressed.len()
+    );
+    Ok(builder.header("content-encoding", "gzip").body(compressed))
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_json_body() {
+        let small = serde_json::json!({"messages": ["hi"]});
+        let request = json_body(client().post("http://localhost/"), &small, true)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(request.headers().get("content-encoding").is_none());
+        assert_eq!(
+            request.body().unwrap().as_bytes().unwrap(),
+            br#"{"messages":["hi"]}"#
+        );
+
+        let large = serde_json::json!({"messages": ["context ".repeat(4000)]});
+        let request = json_body(client().post("http://localhost/"), &large, true)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["content-encoding"], "gzip");
+        let compressed = request.body().unwrap().as_bytes().unwrap();
+        assert!(compressed.len() < COMPRESS_MIN_BYTES);
+        let mut json = String::new();
+        GzDecoder::new(compressed)
+            .read_to_string(&mut json)
+            .unwrap();
+        assert_eq!(json, large.to_string());
+
+        let request = json_body(client().post("http://localhost/"), &large, false)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(request.headers().get("content-encoding").is_none());
+    }
+}
@@ -21,6 +21,69 @@ pub trait LLMProvider: Send + Sync {
     fn has_native_tool_calling(&self) -> bool {
         false
     }
+
+    /// What the provider's model can do
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Special tokens of the model's chat template that can end up in its output. Responses
+    /// are cut at them like at the request's `stop_sequences`; see [`stop`].
+    fn stop_tokens(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Ask a single question about an image
+    async fn complete_with_image(
+        &self,
+        _prompt: &str,
+        _image: &ImageContent,
+        _max_tokens: Option<u32>,
+    ) -> Result<CompletionResponse> {
+        anyhow::bail!("Provider '{}' does not support image input", self.name())
+    }
+}
+
+/// What a provider's model can do, so callers don't have to tell from provider or model
+/// names. The default is a text-only model of unknown size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Accepts images (used by vision tools)
+    pub vision: bool,
+    /// Streams the arguments of tool calls as they are generated
+    pub tool_streaming: bool,
+    /// Can call several tools in one response
+    pub parallel_tools: bool,
+    /// Can be made to answer with a JSON object
+    pub json_mode: bool,
+    /// Reuses a cached prompt prefix across requests
+    pub prompt_caching: bool,
+    /// The most tokens one response can have, if known
+    pub max_output_tokens: Option<u32>,
+    /// Size of the context window, if known
+    pub context_window: Option<u32>,
+}
+
+/// An image sent to a vision-capable provider
+#[derive(Debug, Clone)]
+pub struct ImageContent {
+    /// MIME type, e.g. "image/png"
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+impl ImageContent {
+    pub fn png(data: Vec<u8>) -> Self {
+        Self {
+            media_type: "image/png".to_string(),
+            data,
+        }
+    }
+
+    pub fn to_base64(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::STANDARD.encode(&self.data)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,7 +92,31 @@ pub struct CompletionRequest {
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
     pub stream: bool,
+    /// Providers without native tool calling only use the names and input schemas, to
+    /// constrain their JSON tool calls when they support JSON mode
     pub tools: Option<Vec<Tool>>,
+    /// Ignored by providers without native tool calling and when `tools` is `None`
+    #[serde(default)]
+    pub tool_choice: ToolChoice,
+    /// Text that ends the response, besides the model's own stop tokens. Neither it nor
+    /// anything after it is returned.
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+}
+
+/// Whether the model may, must or must not call a tool in its response
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides
+    #[default]
+    Auto,
+    /// The model must call one of the tools
+    Required,
+    /// The model must answer without calling a tool
+    None,
+    /// The model must call the tool with this name
+    Tool(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +145,10 @@ pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// Of `completion_tokens`, those a reasoning model spent thinking. They are paid for
+    /// but don't stay in the conversation.
+    #[serde(default)]
+    pub reasoning_tokens: u32,
 }
 
 pub type CompletionStream = tokio_stream::wrappers::ReceiverStream<Result<CompletionChunk>>;
@@ -68,6 +159,29 @@ pub struct CompletionChunk {
     pub finished: bool,
     pub tool_calls: Option<Vec<ToolCall>>,
     pub usage: Option<Usage>,  // Add usage tracking for streaming
+    /// Fragments of tool calls whose arguments are still streaming, for providers that
+    /// stream them; the complete calls still arrive in `tool_calls`
+    #[serde(default)]
+    pub tool_call_deltas: Option<Vec<ToolCallDelta>>,
+    /// More of a summary of the model's reasoning, for people to read. It isn't part of
+    /// the response.
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    /// Set on the final chunk when the response stopped at the `max_tokens` limit
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// The next piece of a streaming tool call
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    /// Position of the tool call in the response, which identifies it across deltas
+    pub index: usize,
+    pub id: Option<String>,
+    /// Sent with the first delta of a call
+    pub tool: Option<String>,
+    /// The next fragment of the JSON-encoded arguments
+    pub arguments: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,24 +201,47 @@ pub struct Tool {
 pub mod anthropic;
 pub mod databricks;
 pub mod embedded;
+pub mod embeddings;
+pub mod error;
+pub mod hosted;
+pub mod http;
 pub mod oauth;
 pub mod openai;
+pub mod stop;
+pub mod tool_grammar;
+pub mod unity_catalog;
 
 pub use anthropic::AnthropicProvider;
 pub use databricks::DatabricksProvider;
 pub use embedded::EmbeddedProvider;
+pub use embeddings::{EmbeddingProvider, HashedEmbeddings, OpenAIEmbeddings};
+pub use error::{ProviderError, ProviderErrorKind};
 pub use openai::OpenAIProvider;
 
 /// Provider registry for managing multiple LLM providers
 pub struct ProviderRegistry {
     providers: HashMap<String, Box<dyn LLMProvider>>,
+    lazy: HashMap<String, LazyProvider>,
     default_provider: String,
 }
 
+/// Builds a provider when it is first needed
+pub type ProviderFactory = Box<
+    dyn Fn() -> futures_util::future::BoxFuture<'static, Result<Box<dyn LLMProvider>>>
+        + Send
+        + Sync,
+>;
+
+struct LazyProvider {
+    factory: ProviderFactory,
+    provider: tokio::sync::OnceCell<Box<dyn LLMProvider>>,
+}
+
 impl ProviderRegistry {
     pub fn new() -> Self {
         Self {
             providers: HashMap::new(),
+            lazy: HashMap::new(),
             default_provider: String::new(),
         }
     }
@@ -118,6 +255,51 @@ impl ProviderRegistry {
         }
     }
     
+    /// Register a provider under `name` rather than its own name, e.g. to use a second
+    /// model of a provider that is already registered
+    pub fn register_as(&mut self, name: &str, provider: Box<dyn LLMProvider>) {
+        self.providers.insert(name.to_string(), provider);
+    }
+
+    /// Register a provider under `name` that `factory` builds the first time it is
+    /// resolved, so providers that may never be used cost nothing until they are
+    pub fn register_lazy(&mut self, name: &str, factory: ProviderFactory) {
+        self.lazy.insert(
+            name.to_string(),
+            LazyProvider {
+                factory,
+                provider: tokio::sync::OnceCell::new(),
+            },
+        );
+    }
+
+    /// Whether a provider, loaded or not, is registered under `name`
+    pub fn contains(&self, name: &str) -> bool {
+        self.providers.contains_key(name) || self.lazy.contains_key(name)
+    }
+
+    /// The provider registered under `name`, building it first if it is registered
+    /// lazily and hasn't been built yet. A failed build is retried the next time.
+    pub async fn resolve(&self, name: &str) -> Result<&dyn LLMProvider> {
+        if let Some(provider) = self.providers.get(name) {
+            return Ok(provider.as_ref());
+        }
+        let lazy = self
+            .lazy
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Provider '{}' not found", name))?;
+        let provider = lazy
+            .provider
+            .get_or_try_init(|| (lazy.factory)())
+            .await?;
+        Ok(provider.as_ref())
+    }
+
+    /// Take the default provider out of the registry
+    pub fn into_default(mut self) -> Option<Box<dyn LLMProvider>> {
+        self.providers.remove(&self.default_provider)
+    }
+    
     pub fn set_default(&mut self, provider_name: &str) -> Result<()> {
         if !self.providers.contains_key(provider_name) {
             anyhow::bail!("Provider '{}' not found", provider_name);
@@ -126,16 +308,29 @@ impl ProviderRegistry {
         Ok(())
     }
     
+    /// The provider registered under `provider_name` (the default provider for `None`).
+    /// Lazily registered providers are only found here once they have been resolved.
     pub fn get(&self, provider_name: Option<&str>) -> Result<&dyn LLMProvider> {
         let name = provider_name.unwrap_or(&self.default_provider);
-        self.providers
-            .get(name)
-            .map(|p| p.as_ref())
-            .ok_or_else(|| anyhow::anyhow!("Provider '{}' not found", name))
+        if let Some(provider) = self.providers.get(name) {
+            return Ok(provider.as_ref());
+        }
+        match self.lazy.get(name) {
+            Some(lazy) => lazy
+                .provider
+                .get()
+                .map(|p| p.as_ref())
+                .ok_or_else(|| anyhow::anyhow!("Provider '{}' has not been loaded yet", name)),
+            None => Err(anyhow::anyhow!("Provider '{}' not found", name)),
+        }
     }
     
     pub fn list_providers(&self) -> Vec<&str> {
-        self.providers.keys().map(|s| s.as_str()).collect()
+        self.providers
+            .keys()
+            .chain(self.lazy.keys())
+            .map(|s| s.as_str())
+            .collect()
     }
 }
 
@@ -144,3 +339,62 @@ impl Default for ProviderRegistry {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Named(&'static str);
+
+    #[async_trait::async_trait]
+    impl LLMProvider for Named {
+        async fn complete(&self, _request: CompletionRequest) -> Result<CompletionResponse> {
+            anyhow::bail!("not used")
+        }
+
+        async fn stream(&self, _request: CompletionRequest) -> Result<CompletionStream> {
+            anyhow::bail!("not used")
+        }
+
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn model(&self) -> &str {
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lazy_providers() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Named("anthropic"));
+        let builds = Arc::new(AtomicUsize::new(0));
+        let counter = builds.clone();
+        registry.register_lazy(
+            "summarizer",
+            Box::new(move || {
+                let counter = counter.clone();
+                Box::pin(async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Ok(Box::new(Named("haiku")) as Box<dyn LLMProvider>)
+                })
+            }),
+        );
+
+        // Registered, but not built until it is resolved
+        assert!(registry.contains("summarizer"));
+        assert!(registry.get(Some("summarizer")).is_err());
+        assert_eq!(builds.load(Ordering::SeqCst), 0);
+
+        assert_eq!(registry.resolve("summarizer").await.unwrap().model(), "haiku");
+        assert_eq!(registry.resolve("summarizer").await.unwrap().model(), "haiku");
+        assert_eq!(registry.get(Some("summarizer")).unwrap().model(), "haiku");
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+
+        assert_eq!(registry.get(None).unwrap().name(), "anthropic");
+        assert!(registry.resolve("vision_locate").await.is_err());
+    }
+}
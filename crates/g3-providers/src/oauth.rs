@@ -29,6 +29,33 @@ struct TokenData {
     expires_at: Option<DateTime<Utc>>,
 }
 
+/// An access token, with when it expires if the server said
+#[derive(Debug, Clone)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<TokenData> for OAuthToken {
+    fn from(token: TokenData) -> Self {
+        Self {
+            access_token: token.access_token,
+            expires_at: token.expires_at,
+        }
+    }
+}
+
+/// Tokens this close to expiring are refreshed before they are used, so they don't expire
+/// during a long request
+const REFRESH_MARGIN_SECS: i64 = 300;
+
+/// Whether a token expiring at `expires_at` should be refreshed before it is used
+pub fn expires_soon(expires_at: Option<DateTime<Utc>>) -> bool {
+    expires_at.is_some_and(|expires_at| {
+        expires_at - chrono::Duration::seconds(REFRESH_MARGIN_SECS) <= Utc::now()
+    })
+}
+
 struct TokenCache {
     cache_path: PathBuf,
 }
@@ -350,31 +377,35 @@ impl OAuthFlow {
     }
 }
 
+/// A token for `host`: the cached one while it is good for a few more minutes, then a
+/// refreshed one, then one from a new browser sign-in. With `force_refresh` the cached
+/// token is refreshed even if it looks valid, for when the server rejected it.
 pub async fn get_oauth_token_async(
     host: &str,
     client_id: &str,
     redirect_url: &str,
     scopes: &[String],
-) -> Result<String> {
+    force_refresh: bool,
+) -> Result<OAuthToken> {
     let token_cache = TokenCache::new(host, client_id, scopes);
 
     // Try cache first
     if let Some(token) = token_cache.load_token() {
-        // If token has an expiration time, check if it's expired
-        if let Some(expires_at) = token.expires_at {
-            if expires_at > Utc::now() {
-                tracing::debug!("Using cached token");
-                return Ok(token.access_token);
-            }
-            // Token is expired, will try to refresh below
-            tracing::debug!("Token is expired, attempting to refresh");
-        } else {
+        if force_refresh {
+            tracing::debug!("Token was rejected, attempting to refresh");
+        } else if token.expires_at.is_none() {
             // No expiration time was provided by the server
             tracing::debug!("Token has no expiration time, using cached token");
-            return Ok(token.access_token);
+            return Ok(token.into());
+        } else if !expires_soon(token.expires_at) {
+            tracing::debug!("Using cached token");
+            return Ok(token.into());
+        } else {
+            // Token is expired or about to, will try to refresh below
+            tracing::debug!("Token is expiring, attempting to refresh");
         }
 
-        // Token is expired or has no expiration, try to refresh if we have a refresh token
+        // Try to refresh if we have a refresh token
         if let Some(refresh_token) = token.refresh_token {
             // Get endpoints for token refresh
             match get_workspace_endpoints(host).await {
@@ -393,7 +424,7 @@ pub async fn get_oauth_token_async(
                                 tracing::warn!("Failed to save refreshed token: {}", e);
                             }
                             tracing::info!("Successfully refreshed token");
-                            return Ok(new_token.access_token);
+                            return Ok(new_token.into());
                         }
                         Err(e) => {
                             tracing::warn!(
@@ -429,7 +460,7 @@ pub async fn get_oauth_token_async(
     if std::env::var("G3_RETRO_MODE").is_err() {
         println!("🎉 Databricks authentication complete!");
     }
-    Ok(token.access_token)
+    Ok(token.into())
 }
 
 #[cfg(test)]
@@ -460,4 +491,13 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_expires_soon() {
+        assert!(!expires_soon(None));
+        assert!(!expires_soon(Some(Utc::now() + chrono::Duration::hours(1))));
+        // Tokens about to expire are refreshed before they are used
+        assert!(expires_soon(Some(Utc::now() + chrono::Duration::seconds(60))));
+        assert!(expires_soon(Some(Utc::now() - chrono::Duration::seconds(1))));
+    }
 }
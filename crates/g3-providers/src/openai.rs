@@ -1,3 +1,10 @@
+//! OpenAI and OpenAI-compatible servers, through Chat Completions or the Responses API.
+//!
+//! Chat Completions (`/chat/completions`) is what every OpenAI-compatible server has. The
+//! Responses API (`/responses`) is OpenAI's newer API, which reasoning models need to
+//! stream summaries of their reasoning. Both are given the conversation as plain messages,
+//! so a provider can switch between them without changes elsewhere.
+
 use anyhow::Result;
 use async_trait::async_trait;
 use bytes::Bytes;
@@ -10,10 +17,84 @@ use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, error};
 
 use crate::{
-    CompletionChunk, CompletionRequest, CompletionResponse, CompletionStream, LLMProvider,
-    Message, MessageRole, Tool, ToolCall, Usage,
+    hosted, http, stop, Capabilities, CompletionChunk, CompletionRequest, CompletionResponse,
+    CompletionStream, ImageContent, LLMProvider, Message, MessageRole, ProviderError,
+    ProviderErrorKind, Tool, ToolCall, ToolCallDelta, ToolChoice, Usage,
 };
 
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// The API a provider calls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenAIApi {
+    ChatCompletions,
+    Responses,
+}
+
+impl OpenAIApi {
+    /// The Responses API for reasoning models on OpenAI's own servers, which other servers
+    /// may not have
+    pub fn for_model(model: &str, base_url: &str) -> Self {
+        if is_reasoning_model(model) && base_url.trim_end_matches('/') == OPENAI_BASE_URL {
+            OpenAIApi::Responses
+        } else {
+            OpenAIApi::ChatCompletions
+        }
+    }
+}
+
+/// Whether `model` reasons before it answers: the o-series and gpt-5
+pub fn is_reasoning_model(model: &str) -> bool {
+    let model = model.to_lowercase();
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    model.starts_with("o1")
+        || model.starts_with("o3")
+        || model.starts_with("o4")
+        || model.starts_with("gpt-5")
+}
+
+/// The size of `model`'s context window, for OpenAI's models
+pub fn context_length(model: &str) -> Option<u32> {
+    let model = model.to_lowercase();
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    if model.starts_with("gpt-5") {
+        Some(400_000)
+    } else if model.starts_with("gpt-4.1") {
+        Some(1_047_576)
+    } else if model.starts_with("o1-mini") {
+        Some(128_000)
+    } else if model.starts_with("o1") || model.starts_with("o3") || model.starts_with("o4") {
+        Some(200_000)
+    } else if model.starts_with("gpt-4o") || model.starts_with("gpt-4-turbo") {
+        Some(128_000)
+    } else if model.starts_with("gpt-4") {
+        Some(8192)
+    } else if model.starts_with("gpt-3.5") {
+        Some(16_385)
+    } else {
+        None
+    }
+}
+
+/// The most tokens `model` can generate in one response, for OpenAI's models
+pub fn output_limit(model: &str) -> Option<u32> {
+    let model = model.to_lowercase();
+    let model = model.rsplit('/').next().unwrap_or(&model);
+    if model.starts_with("gpt-5") {
+        Some(128_000)
+    } else if model.starts_with("o1") || model.starts_with("o3") || model.starts_with("o4") {
+        Some(100_000)
+    } else if model.starts_with("gpt-4.1") {
+        Some(32_768)
+    } else if model.starts_with("gpt-4o") {
+        Some(16_384)
+    } else if model.starts_with("gpt-4") {
+        Some(8192)
+    } else {
+        None
+    }
+}
+
 #[derive(Clone)]
 pub struct OpenAIProvider {
     client: Client,
@@ -23,6 +104,10 @@ pub struct OpenAIProvider {
     max_tokens: Option<u32>,
     _temperature: Option<f32>,
     name: String,
+    api: OpenAIApi,
+    reasoning_effort: Option<String>,
+    context_length: Option<u32>,
+    compress_requests: bool,
 }
 
 impl OpenAIProvider {
@@ -51,21 +136,95 @@ impl OpenAIProvider {
         max_tokens: Option<u32>,
         temperature: Option<f32>,
     ) -> Result<Self> {
+        let model = model.unwrap_or_else(|| "gpt-4o".to_string());
+        let base_url = base_url
+            .or_else(|| hosted::service(&name).map(|service| service.base_url.to_string()))
+            .unwrap_or_else(|| OPENAI_BASE_URL.to_string());
         Ok(Self {
-            client: Client::new(),
+            client: http::client(),
             api_key,
-            model: model.unwrap_or_else(|| "gpt-4o".to_string()),
-            base_url: base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            api: OpenAIApi::for_model(&model, &base_url),
+            model,
+            base_url,
             max_tokens,
             _temperature: temperature,
             name,
+            reasoning_effort: None,
+            context_length: None,
+            compress_requests: false,
         })
     }
 
+    /// Call `api` instead of the one picked for the model
+    pub fn with_api(mut self, api: OpenAIApi) -> Self {
+        self.api = api;
+        self
+    }
+
+    /// How much a reasoning model should think: `minimal`, `low`, `medium` or `high`
+    pub fn with_reasoning_effort(mut self, effort: Option<String>) -> Self {
+        self.reasoning_effort = effort;
+        self
+    }
+
+    /// The size of the model's context window, where it isn't known or is different
+    pub fn with_context_length(mut self, context_length: Option<u32>) -> Self {
+        self.context_length = context_length;
+        self
+    }
+
+    /// Gzip large request bodies, for servers that accept compressed requests
+    pub fn with_compression(mut self, compress_requests: bool) -> Self {
+        self.compress_requests = compress_requests;
+        self
+    }
+
+    /// Name of the service in error messages
+    fn label(&self) -> &'static str {
+        hosted::service(&self.name).map_or("OpenAI", |service| service.label)
+    }
+
+    fn endpoint(&self) -> String {
+        match self.api {
+            OpenAIApi::ChatCompletions => format!("{}/chat/completions", self.base_url),
+            OpenAIApi::Responses => format!("{}/responses", self.base_url),
+        }
+    }
+
+    fn request_body(&self, request: &CompletionRequest, stream: bool) -> serde_json::Value {
+        match self.api {
+            OpenAIApi::ChatCompletions => {
+                let mut body = self.create_request_body(
+                    &request.messages,
+                    request.tools.as_deref(),
+                    &request.tool_choice,
+                    stream,
+                    request.max_tokens,
+                    request.temperature,
+                );
+                // The API takes at most four and reasoning models reject them; the rest are
+                // cut from the response as it arrives
+                if !request.stop_sequences.is_empty() && !is_reasoning_model(&self.model) {
+                    let sequences: Vec<&String> = request.stop_sequences.iter().take(4).collect();
+                    body["stop"] = json!(sequences);
+                }
+                body
+            }
+            OpenAIApi::Responses => self.create_responses_body(
+                &request.messages,
+                request.tools.as_deref(),
+                &request.tool_choice,
+                stream,
+                request.max_tokens,
+            ),
+        }
+    }
+
     fn create_request_body(
         &self,
         messages: &[Message],
         tools: Option<&[Tool]>,
+        tool_choice: &ToolChoice,
         stream: bool,
         max_tokens: Option<u32>,
         _temperature: Option<f32>,
@@ -80,6 +239,10 @@ impl OpenAIProvider {
             body["max_completion_tokens"] = json!(max_tokens);
         }
 
+        if let Some(effort) = &self.reasoning_effort {
+            body["reasoning_effort"] = json!(effort);
+        }
+
         // OpenAI calls with temp setting seem to fail, so don't send one.
         // if let Some(temperature) = temperature.or(self.temperature) {
         //     body["temperature"] = json!(temperature);
@@ -88,6 +251,9 @@ impl OpenAIProvider {
         if let Some(tools) = tools {
             if !tools.is_empty() {
                 body["tools"] = json!(convert_tools(tools));
+                if let Some(choice) = convert_tool_choice(tool_choice) {
+                    body["tool_choice"] = choice;
+                }
             }
         }
 
@@ -100,6 +266,134 @@ impl OpenAIProvider {
         body
     }
 
+    /// A Responses API request. System messages become the instructions, and nothing is
+    /// stored on OpenAI's side since every request sends the whole conversation.
+    fn create_responses_body(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        tool_choice: &ToolChoice,
+        stream: bool,
+        max_tokens: Option<u32>,
+    ) -> serde_json::Value {
+        let instructions: Vec<&str> = messages
+            .iter()
+            .filter(|msg| matches!(msg.role, MessageRole::System))
+            .map(|msg| msg.content.as_str())
+            .collect();
+        let input: Vec<serde_json::Value> = messages
+            .iter()
+            .filter(|msg| !matches!(msg.role, MessageRole::System))
+            .map(|msg| {
+                json!({
+                    "role": match msg.role {
+                        MessageRole::Assistant => "assistant",
+                        _ => "user",
+                    },
+                    "content": msg.content,
+                })
+            })
+            .collect();
+
+        let mut body = json!({
+            "model": self.model,
+            "input": input,
+            "stream": stream,
+            "store": false,
+        });
+        if !instructions.is_empty() {
+            body["instructions"] = json!(instructions.join("\n\n"));
+        }
+
+        if let Some(max_tokens) = max_tokens.or(self.max_tokens) {
+            body["max_output_tokens"] = json!(max_tokens);
+        }
+
+        if is_reasoning_model(&self.model) {
+            let mut reasoning = json!({ "summary": "auto" });
+            if let Some(effort) = &self.reasoning_effort {
+                reasoning["effort"] = json!(effort);
+            }
+            body["reasoning"] = reasoning;
+        }
+
+        if let Some(tools) = tools {
+            if !tools.is_empty() {
+                body["tools"] = json!(convert_responses_tools(tools));
+                if let Some(choice) = convert_responses_tool_choice(tool_choice) {
+                    body["tool_choice"] = choice;
+                }
+            }
+        }
+
+        body
+    }
+
+    async fn send_completion(&self, body: &serde_json::Value) -> Result<CompletionResponse> {
+        debug!("Sending request to OpenAI API: model={}", self.model);
+
+        let builder = self
+            .client
+            .post(self.endpoint())
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        let response = http::json_body(builder, body, self.compress_requests)?
+            .send()
+            .await
+            .map_err(|e| ProviderError::from_reqwest("Failed to send request to OpenAI API", &e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = hosted::retry_after(response.headers());
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::from_response(self.label(), status, &error_text)
+                .with_retry_after(retry_after)
+                .into());
+        }
+
+        let (content, usage) = match self.api {
+            OpenAIApi::ChatCompletions => {
+                let openai_response: OpenAIResponse = response.json().await?;
+                let content = openai_response
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.message.content.clone())
+                    .unwrap_or_default();
+                (content, openai_response.usage.to_usage())
+            }
+            OpenAIApi::Responses => {
+                let response: ResponsesResponse = response.json().await?;
+                if let Some(error) = response.error {
+                    return Err(error.into_provider_error().into());
+                }
+                let usage = response
+                    .usage
+                    .as_ref()
+                    .map(ResponsesUsage::to_usage)
+                    .unwrap_or_else(|| Usage {
+                        prompt_tokens: 0,
+                        completion_tokens: 0,
+                        total_tokens: 0,
+                        reasoning_tokens: 0,
+                    });
+                (response.text(), usage)
+            }
+        };
+
+        debug!(
+            "OpenAI completion successful: {} tokens generated",
+            usage.completion_tokens
+        );
+
+        Ok(CompletionResponse {
+            content,
+            usage,
+            model: self.model.clone(),
+        })
+    }
+
     async fn parse_streaming_response(
         &self,
         mut stream: impl futures_util::Stream<Item = reqwest::Result<Bytes>> + Unpin,
@@ -109,6 +403,8 @@ impl OpenAIProvider {
         let mut accumulated_content = String::new();
         let mut accumulated_usage: Option<Usage> = None;
         let mut current_tool_calls: Vec<OpenAIStreamingToolCall> = Vec::new();
+        let mut truncated = false; // finish_reason "length": the response hit max_tokens
+        let mut refusal = String::new(); // The model's refusal, which replaces the content
 
         while let Some(chunk_result) = stream.next().await {
             match chunk_result {
@@ -155,6 +451,9 @@ impl OpenAIProvider {
                                         finished: true,
                                         tool_calls,
                                         usage: accumulated_usage.clone(),
+                                        tool_call_deltas: None,
+                                        reasoning: None,
+                                        truncated,
                                     };
                                     let _ = tx.send(Ok(final_chunk)).await;
                                 }
@@ -167,6 +466,31 @@ impl OpenAIProvider {
                                 Ok(chunk_data) => {
                                     // Handle content
                                     for choice in &chunk_data.choices {
+                                        if choice.finish_reason.as_deref() == Some("length") {
+                                            truncated = true;
+                                        }
+                                        if let Some(delta) = &choice.delta.refusal {
+                                            refusal.push_str(delta);
+                                        }
+                                        let filtered = choice.finish_reason.as_deref()
+                                            == Some("content_filter");
+                                        if filtered
+                                            || (choice.finish_reason.is_some()
+                                                && !refusal.is_empty())
+                                        {
+                                            let reason = if refusal.is_empty() {
+                                                "the content filter blocked it (finish_reason: content_filter)"
+                                            } else {
+                                                refusal.as_str()
+                                            };
+                                            let _ = tx
+                                                .send(Err(ProviderError::content_filtered(
+                                                    "OpenAI", reason,
+                                                )
+                                                .into()))
+                                                .await;
+                                            return accumulated_usage;
+                                        }
                                         if let Some(content) = &choice.delta.content {
                                             accumulated_content.push_str(content);
 
@@ -175,6 +499,9 @@ impl OpenAIProvider {
                                                 finished: false,
                                                 tool_calls: None,
                                                 usage: None,
+                                                tool_call_deltas: None,
+                                                reasoning: None,
+                                                truncated: false,
                                             };
                                             if tx.send(Ok(chunk)).await.is_err() {
                                                 debug!("Receiver dropped, stopping stream");
@@ -184,6 +511,7 @@ impl OpenAIProvider {
 
                                         // Handle tool calls
                                         if let Some(delta_tool_calls) = &choice.delta.tool_calls {
+                                            let mut deltas = Vec::new();
                                             for delta_tool_call in delta_tool_calls {
                                                 if let Some(index) = delta_tool_call.index {
                                                     // Ensure we have enough tool calls in our vector
@@ -206,6 +534,34 @@ impl OpenAIProvider {
                                                             tool_call.arguments.push_str(arguments);
                                                         }
                                                     }
+                                                    deltas.push(ToolCallDelta {
+                                                        index,
+                                                        id: delta_tool_call.id.clone(),
+                                                        tool: delta_tool_call
+                                                            .function
+                                                            .as_ref()
+                                                            .and_then(|f| f.name.clone()),
+                                                        arguments: delta_tool_call
+                                                            .function
+                                                            .as_ref()
+                                                            .and_then(|f| f.arguments.clone())
+                                                            .unwrap_or_default(),
+                                                    });
+                                                }
+                                            }
+                                            if !deltas.is_empty() {
+                                                let chunk = CompletionChunk {
+                                                    content: String::new(),
+                                                    finished: false,
+                                                    usage: None,
+                                                    tool_calls: None,
+                                                    tool_call_deltas: Some(deltas),
+                                                    reasoning: None,
+                                                    truncated: false,
+                                                };
+                                                if tx.send(Ok(chunk)).await.is_err() {
+                                                    debug!("Receiver dropped, stopping stream");
+                                                    return accumulated_usage;
                                                 }
                                             }
                                         }
@@ -213,11 +569,7 @@ impl OpenAIProvider {
 
                                     // Handle usage
                                     if let Some(usage) = chunk_data.usage {
-                                        accumulated_usage = Some(Usage {
-                                            prompt_tokens: usage.prompt_tokens,
-                                            completion_tokens: usage.completion_tokens,
-                                            total_tokens: usage.total_tokens,
-                                        });
+                                        accumulated_usage = Some(usage.to_usage());
                                     }
                                 }
                                 Err(e) => {
@@ -229,7 +581,7 @@ impl OpenAIProvider {
                 }
                 Err(e) => {
                     error!("Stream error: {}", e);
-                    let _ = tx.send(Err(anyhow::anyhow!("Stream error: {}", e))).await;
+                    let _ = tx.send(Err(ProviderError::from_reqwest("Stream error", &e).into())).await;
                     return accumulated_usage;
                 }
             }
@@ -252,6 +604,9 @@ impl OpenAIProvider {
             finished: true,
             tool_calls,
             usage: accumulated_usage.clone(),
+            tool_call_deltas: None,
+            reasoning: None,
+            truncated,
         };
         let _ = tx.send(Ok(final_chunk)).await;
         
@@ -267,57 +622,12 @@ impl LLMProvider for OpenAIProvider {
             request.messages.len()
         );
 
-        let body = self.create_request_body(
-            &request.messages,
-            request.tools.as_deref(),
-            false,
-            request.max_tokens,
-            request.temperature,
-        );
-
-        debug!("Sending request to OpenAI API: model={}", self.model);
+        let body = self.request_body(&request, false);
 
-        let response = self
-            .client
-            .post(format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&body)
-            .send()
-            .await?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!("OpenAI API error {}: {}", status, error_text));
-        }
-
-        let openai_response: OpenAIResponse = response.json().await?;
-
-        let content = openai_response
-            .choices
-            .first()
-            .and_then(|choice| choice.message.content.clone())
-            .unwrap_or_default();
-
-        let usage = Usage {
-            prompt_tokens: openai_response.usage.prompt_tokens,
-            completion_tokens: openai_response.usage.completion_tokens,
-            total_tokens: openai_response.usage.total_tokens,
-        };
-
-        debug!(
-            "OpenAI completion successful: {} tokens generated",
-            usage.completion_tokens
-        );
-
-        Ok(CompletionResponse {
-            content,
-            usage,
-            model: self.model.clone(),
-        })
+        let mut response = self.send_completion(&body).await?;
+        response.content =
+            stop::truncate(&response.content, &stop::sequences(self.stop_tokens(), &request));
+        Ok(response)
     }
 
     async fn stream(&self, request: CompletionRequest) -> Result<CompletionStream> {
@@ -326,31 +636,31 @@ impl LLMProvider for OpenAIProvider {
             request.messages.len()
         );
 
-        let body = self.create_request_body(
-            &request.messages,
-            request.tools.as_deref(),
-            true,
-            request.max_tokens,
-            request.temperature,
-        );
+        let body = self.request_body(&request, true);
 
         debug!("Sending streaming request to OpenAI API: model={}", self.model);
 
-        let response = self
+        let builder = self
             .client
-            .post(format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&body)
+            .post(self.endpoint())
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        let response = http::json_body(builder, &body, self.compress_requests)?
             .send()
-            .await?;
+            .await
+            .map_err(|e| {
+                ProviderError::from_reqwest("Failed to send streaming request to OpenAI API", &e)
+            })?;
 
         let status = response.status();
         if !status.is_success() {
+            let retry_after = hosted::retry_after(response.headers());
             let error_text = response
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow::anyhow!("OpenAI API error {}: {}", status, error_text));
+            return Err(ProviderError::from_response(self.label(), status, &error_text)
+                .with_retry_after(retry_after)
+                .into());
         }
 
         let stream = response.bytes_stream();
@@ -359,7 +669,10 @@ impl LLMProvider for OpenAIProvider {
         // Spawn task to process the stream
         let provider = self.clone();
         tokio::spawn(async move {
-            let usage = provider.parse_streaming_response(stream, tx).await;
+            let usage = match provider.api {
+                OpenAIApi::ChatCompletions => provider.parse_streaming_response(stream, tx).await,
+                OpenAIApi::Responses => parse_responses_stream(stream, tx).await,
+            };
             // Log the final usage if available
             if let Some(usage) = usage {
                 debug!(
@@ -369,7 +682,10 @@ impl LLMProvider for OpenAIProvider {
             }
         });
 
-        Ok(ReceiverStream::new(rx))
+        Ok(stop::filter_stream(
+            ReceiverStream::new(rx),
+            stop::sequences(self.stop_tokens(), &request),
+        ))
     }
 
     fn name(&self) -> &str {
@@ -384,6 +700,99 @@ impl LLMProvider for OpenAIProvider {
         // OpenAI models support native tool calling
         true
     }
+
+    fn stop_tokens(&self) -> Vec<String> {
+        // Other OpenAI-compatible servers can run open models whose template tokens leak
+        if self.base_url.trim_end_matches('/') == OPENAI_BASE_URL {
+            Vec::new()
+        } else {
+            stop::chat_template_tokens(&self.model)
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let openai = self.base_url.trim_end_matches('/') == OPENAI_BASE_URL;
+        Capabilities {
+            // Whether the model accepts images is only known to the API; a text-only
+            // model rejects the request with an error
+            vision: true,
+            tool_streaming: true,
+            parallel_tools: true,
+            json_mode: true,
+            // OpenAI caches long prompt prefixes by itself
+            prompt_caching: openai,
+            max_output_tokens: output_limit(&self.model),
+            context_window: self
+                .context_length
+                .or_else(|| {
+                    hosted::service(&self.name)?
+                        .model(&self.model)
+                        .map(|model| model.context_length)
+                })
+                .or_else(|| context_length(&self.model))
+                // Models g3 doesn't know on OpenAI's servers are assumed to be recent ones
+                .or(openai.then_some(400_000)),
+        }
+    }
+
+    async fn complete_with_image(
+        &self,
+        prompt: &str,
+        image: &ImageContent,
+        max_tokens: Option<u32>,
+    ) -> Result<CompletionResponse> {
+        let mut body = match self.api {
+            OpenAIApi::ChatCompletions => json!({
+                "model": self.model,
+                "messages": [image_message(prompt, image)],
+                "stream": false,
+            }),
+            OpenAIApi::Responses => json!({
+                "model": self.model,
+                "input": [responses_image_message(prompt, image)],
+                "store": false,
+            }),
+        };
+        if let Some(max_tokens) = max_tokens.or(self.max_tokens) {
+            let key = match self.api {
+                OpenAIApi::ChatCompletions => "max_completion_tokens",
+                OpenAIApi::Responses => "max_output_tokens",
+            };
+            body[key] = json!(max_tokens);
+        }
+
+        self.send_completion(&body).await
+    }
+}
+
+/// Vision models accept images as data URLs inside the content array
+fn image_message(prompt: &str, image: &ImageContent) -> serde_json::Value {
+    json!({
+        "role": "user",
+        "content": [
+            {"type": "text", "text": prompt},
+            {
+                "type": "image_url",
+                "image_url": {
+                    "url": format!("data:{};base64,{}", image.media_type, image.to_base64())
+                }
+            }
+        ]
+    })
+}
+
+/// The Responses API takes images as `input_image` parts
+fn responses_image_message(prompt: &str, image: &ImageContent) -> serde_json::Value {
+    json!({
+        "role": "user",
+        "content": [
+            {"type": "input_text", "text": prompt},
+            {
+                "type": "input_image",
+                "image_url": format!("data:{};base64,{}", image.media_type, image.to_base64())
+            }
+        ]
+    })
 }
 
 fn convert_messages(messages: &[Message]) -> Vec<serde_json::Value> {
@@ -402,6 +811,18 @@ fn convert_messages(messages: &[Message]) -> Vec<serde_json::Value> {
         .collect()
 }
 
+fn convert_tool_choice(choice: &ToolChoice) -> Option<serde_json::Value> {
+    match choice {
+        ToolChoice::Auto => None,
+        ToolChoice::Required => Some(json!("required")),
+        ToolChoice::None => Some(json!("none")),
+        ToolChoice::Tool(name) => Some(json!({
+            "type": "function",
+            "function": { "name": name },
+        })),
+    }
+}
+
 fn convert_tools(tools: &[Tool]) -> Vec<serde_json::Value> {
     tools
         .iter()
@@ -418,6 +839,28 @@ fn convert_tools(tools: &[Tool]) -> Vec<serde_json::Value> {
         .collect()
 }
 
+/// The Responses API describes functions without the `function` wrapper
+fn convert_responses_tools(tools: &[Tool]) -> Vec<serde_json::Value> {
+    tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "type": "function",
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": tool.input_schema,
+            })
+        })
+        .collect()
+}
+
+fn convert_responses_tool_choice(choice: &ToolChoice) -> Option<serde_json::Value> {
+    match choice {
+        ToolChoice::Tool(name) => Some(json!({ "type": "function", "name": name })),
+        choice => convert_tool_choice(choice),
+    }
+}
+
 // OpenAI API response structures
 #[derive(Debug, Deserialize)]
 struct OpenAIResponse {
@@ -480,6 +923,28 @@ struct OpenAIUsage {
     prompt_tokens: u32,
     completion_tokens: u32,
     total_tokens: u32,
+    #[serde(default)]
+    completion_tokens_details: Option<OpenAITokenDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAITokenDetails {
+    #[serde(default)]
+    reasoning_tokens: u32,
+}
+
+impl OpenAIUsage {
+    fn to_usage(&self) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            total_tokens: self.total_tokens,
+            reasoning_tokens: self
+                .completion_tokens_details
+                .as_ref()
+                .map_or(0, |details| details.reasoning_tokens),
+        }
+    }
 }
 
 // Streaming response structures
@@ -492,11 +957,16 @@ struct OpenAIStreamChunk {
 #[derive(Debug, Deserialize)]
 struct OpenAIStreamChoice {
     delta: OpenAIDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct OpenAIDelta {
     content: Option<String>,
+    /// Why the model won't answer, in place of `content`
+    #[serde(default)]
+    refusal: Option<String>,
     #[serde(default)]
     tool_calls: Option<Vec<OpenAIDeltaToolCall>>,
 }
@@ -513,3 +983,511 @@ struct OpenAIDeltaFunction {
     name: Option<String>,
     arguments: Option<String>,
 }
+
+/// Read a Responses API stream, sending its text, reasoning summaries and tool calls as
+/// they arrive. Returns the usage reported at the end.
+async fn parse_responses_stream(
+    mut stream: impl futures_util::Stream<Item = reqwest::Result<Bytes>> + Unpin,
+    tx: mpsc::Sender<Result<CompletionChunk>>,
+) -> Option<Usage> {
+    let mut buffer = String::new();
+    // Function calls in the order they started, by the output item they are
+    let mut tool_calls: Vec<(usize, OpenAIStreamingToolCall)> = Vec::new();
+    let mut summary_index = None;
+    // The model's refusal, which replaces the output text
+    let mut refusal = String::new();
+
+    while let Some(chunk_result) = stream.next().await {
+        let chunk = match chunk_result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                error!("Stream error: {}", e);
+                let _ = tx
+                    .send(Err(ProviderError::from_reqwest("Stream error", &e).into()))
+                    .await;
+                return None;
+            }
+        };
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(line_end) = buffer.find('\n') {
+            let line = buffer[..line_end].trim().to_string();
+            buffer.drain(..line_end + 1);
+            // The event name is repeated in the data as its type
+            let Some(data) = line.strip_prefix("data: ") else {
+                continue;
+            };
+            let event = match serde_json::from_str::<ResponsesEvent>(data) {
+                Ok(event) => event,
+                Err(e) => {
+                    debug!("Failed to parse stream event: {} - Data: {}", e, data);
+                    continue;
+                }
+            };
+
+            let chunk = match event {
+                ResponsesEvent::OutputTextDelta { delta } => text_chunk(delta, None),
+                ResponsesEvent::RefusalDelta { delta } => {
+                    refusal.push_str(&delta);
+                    continue;
+                }
+                ResponsesEvent::ReasoningSummaryTextDelta {
+                    delta,
+                    summary_index: index,
+                } => {
+                    // Parts of a summary are paragraphs
+                    let separator = match summary_index.replace(index) {
+                        Some(last) if last != index => "\n\n",
+                        _ => "",
+                    };
+                    text_chunk(String::new(), Some(format!("{}{}", separator, delta)))
+                }
+                ResponsesEvent::OutputItemAdded {
+                    output_index,
+                    item:
+                        ResponsesOutputItem::FunctionCall {
+                            call_id,
+                            name,
+                            arguments,
+                        },
+                } => {
+                    let index = tool_calls.len();
+                    tool_calls.push((
+                        output_index,
+                        OpenAIStreamingToolCall {
+                            id: Some(call_id.clone()),
+                            name: Some(name.clone()),
+                            arguments: arguments.clone(),
+                        },
+                    ));
+                    tool_call_chunk(ToolCallDelta {
+                        index,
+                        id: Some(call_id),
+                        tool: Some(name),
+                        arguments,
+                    })
+                }
+                ResponsesEvent::FunctionCallArgumentsDelta {
+                    output_index,
+                    delta,
+                } => {
+                    let Some(index) = tool_calls.iter().position(|(i, _)| *i == output_index)
+                    else {
+                        continue;
+                    };
+                    tool_calls[index].1.arguments.push_str(&delta);
+                    tool_call_chunk(ToolCallDelta {
+                        index,
+                        id: None,
+                        tool: None,
+                        arguments: delta,
+                    })
+                }
+                ResponsesEvent::OutputItemDone {
+                    output_index,
+                    item: ResponsesOutputItem::FunctionCall { arguments, .. },
+                } => {
+                    if let Some((_, call)) = tool_calls.iter_mut().find(|(i, _)| *i == output_index)
+                    {
+                        call.arguments = arguments;
+                    }
+                    continue;
+                }
+                ResponsesEvent::Completed { response } | ResponsesEvent::Incomplete { response } => {
+                    if response.content_filtered() || !refusal.is_empty() {
+                        let reason = if refusal.is_empty() {
+                            "the content filter blocked it (reason: content_filter)"
+                        } else {
+                            refusal.as_str()
+                        };
+                        let _ = tx
+                            .send(Err(ProviderError::content_filtered("OpenAI", reason).into()))
+                            .await;
+                        return response.usage.as_ref().map(ResponsesUsage::to_usage);
+                    }
+                    let usage = response.usage.as_ref().map(ResponsesUsage::to_usage);
+                    let calls: Vec<ToolCall> = tool_calls
+                        .iter()
+                        .filter_map(|(_, call)| call.to_tool_call())
+                        .collect();
+                    let final_chunk = CompletionChunk {
+                        content: String::new(),
+                        finished: true,
+                        tool_calls: (!calls.is_empty()).then_some(calls),
+                        usage: usage.clone(),
+                        tool_call_deltas: None,
+                        reasoning: None,
+                        truncated: response.truncated(),
+                    };
+                    let _ = tx.send(Ok(final_chunk)).await;
+                    return usage;
+                }
+                ResponsesEvent::Failed { response } => {
+                    let error = response.error.map_or_else(
+                        || ProviderError::new(ProviderErrorKind::Other, "OpenAI response failed"),
+                        ResponsesError::into_provider_error,
+                    );
+                    let _ = tx.send(Err(error.into())).await;
+                    return None;
+                }
+                ResponsesEvent::Error(error) => {
+                    let _ = tx.send(Err(error.into_provider_error().into())).await;
+                    return None;
+                }
+                _ => continue,
+            };
+            if tx.send(Ok(chunk)).await.is_err() {
+                debug!("Receiver dropped, stopping stream");
+                return None;
+            }
+        }
+    }
+
+    // The stream ended without a completion event
+    let _ = tx
+        .send(Err(ProviderError::new(
+            ProviderErrorKind::Network,
+            "OpenAI stream ended before the response was complete",
+        )
+        .into()))
+        .await;
+    None
+}
+
+fn text_chunk(content: String, reasoning: Option<String>) -> CompletionChunk {
+    CompletionChunk {
+        content,
+        finished: false,
+        tool_calls: None,
+        usage: None,
+        tool_call_deltas: None,
+        reasoning,
+        truncated: false,
+    }
+}
+
+fn tool_call_chunk(delta: ToolCallDelta) -> CompletionChunk {
+    CompletionChunk {
+        content: String::new(),
+        finished: false,
+        tool_calls: None,
+        usage: None,
+        tool_call_deltas: Some(vec![delta]),
+        reasoning: None,
+        truncated: false,
+    }
+}
+
+// Responses API structures
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ResponsesEvent {
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+    #[serde(rename = "response.refusal.delta")]
+    RefusalDelta { delta: String },
+    #[serde(rename = "response.reasoning_summary_text.delta")]
+    ReasoningSummaryTextDelta {
+        delta: String,
+        #[serde(default)]
+        summary_index: usize,
+    },
+    #[serde(rename = "response.output_item.added")]
+    OutputItemAdded {
+        output_index: usize,
+        item: ResponsesOutputItem,
+    },
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta { output_index: usize, delta: String },
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone {
+        output_index: usize,
+        item: ResponsesOutputItem,
+    },
+    #[serde(rename = "response.completed")]
+    Completed { response: ResponsesResponse },
+    #[serde(rename = "response.incomplete")]
+    Incomplete { response: ResponsesResponse },
+    #[serde(rename = "response.failed")]
+    Failed { response: ResponsesResponse },
+    #[serde(rename = "error")]
+    Error(ResponsesError),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesResponse {
+    #[serde(default)]
+    output: Vec<ResponsesOutputItem>,
+    #[serde(default)]
+    usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    incomplete_details: Option<ResponsesIncompleteDetails>,
+    #[serde(default)]
+    error: Option<ResponsesError>,
+}
+
+impl ResponsesResponse {
+    fn text(&self) -> String {
+        self.output
+            .iter()
+            .filter_map(|item| match item {
+                ResponsesOutputItem::Message { content } => Some(content),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|content| match content {
+                ResponsesContent::OutputText { text } => Some(text.as_str()),
+                ResponsesContent::Other => None,
+            })
+            .collect()
+    }
+
+    /// Whether the response stopped at `max_output_tokens`
+    fn truncated(&self) -> bool {
+        self.incomplete_reason() == Some("max_output_tokens")
+    }
+
+    /// Whether the content filter stopped the response
+    fn content_filtered(&self) -> bool {
+        self.incomplete_reason() == Some("content_filter")
+    }
+
+    fn incomplete_reason(&self) -> Option<&str> {
+        self.incomplete_details
+            .as_ref()
+            .and_then(|details| details.reason.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesOutputItem {
+    Message {
+        #[serde(default)]
+        content: Vec<ResponsesContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        #[serde(default)]
+        arguments: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesContent {
+    OutputText { text: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesIncompleteDetails {
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesError {
+    #[serde(default)]
+    code: Option<String>,
+    message: String,
+}
+
+impl ResponsesError {
+    fn into_provider_error(self) -> ProviderError {
+        ProviderError::from_stream_event(
+            "OpenAI",
+            self.code.as_deref().unwrap_or("error"),
+            &self.message,
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+    total_tokens: u32,
+    #[serde(default)]
+    output_tokens_details: Option<OpenAITokenDetails>,
+}
+
+impl ResponsesUsage {
+    fn to_usage(&self) -> Usage {
+        Usage {
+            prompt_tokens: self.input_tokens,
+            completion_tokens: self.output_tokens,
+            total_tokens: self.total_tokens,
+            reasoning_tokens: self
+                .output_tokens_details
+                .as_ref()
+                .map_or(0, |details| details.reasoning_tokens),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(model: &str) -> OpenAIProvider {
+        OpenAIProvider::new("test-key".to_string(), Some(model.to_string()), None, None, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_api_selection() {
+        assert_eq!(provider("o3").api, OpenAIApi::Responses);
+        assert_eq!(provider("gpt-5-mini").api, OpenAIApi::Responses);
+        assert_eq!(provider("gpt-4o").api, OpenAIApi::ChatCompletions);
+        // Other servers may not have the Responses API
+        assert_eq!(
+            OpenAIApi::for_model("o3", "https://openrouter.ai/api/v1"),
+            OpenAIApi::ChatCompletions
+        );
+
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let o4 = provider("o4-mini").capabilities();
+        assert_eq!(o4.context_window, Some(200_000));
+        assert_eq!(o4.max_output_tokens, Some(100_000));
+        assert!(o4.prompt_caching);
+        assert_eq!(
+            provider("gpt-4.1").capabilities().context_window,
+            Some(1_047_576)
+        );
+
+        let local = |context_length| {
+            OpenAIProvider::new_with_name(
+                "local".to_string(),
+                String::new(),
+                Some("llama-3".to_string()),
+                Some("http://localhost:8080/v1".to_string()),
+                None,
+                None,
+            )
+            .unwrap()
+            .with_context_length(context_length)
+            .capabilities()
+        };
+        assert_eq!(local(Some(8192)).context_window, Some(8192));
+        let unknown = local(None);
+        assert_eq!(unknown.context_window, None);
+        assert_eq!(unknown.max_output_tokens, None);
+        assert!(!unknown.prompt_caching);
+    }
+
+    #[test]
+    fn test_responses_request_body() {
+        let provider = provider("o3").with_reasoning_effort(Some("high".to_string()));
+        let messages = vec![
+            Message {
+                role: MessageRole::System,
+                content: "Be brief.".to_string(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: "Hello!".to_string(),
+            },
+        ];
+        let tools = vec![Tool {
+            name: "shell".to_string(),
+            description: "Run a command".to_string(),
+            input_schema: json!({"type": "object"}),
+        }];
+        let body = provider.create_responses_body(
+            &messages,
+            Some(&tools),
+            &ToolChoice::Tool("shell".to_string()),
+            true,
+            Some(1000),
+        );
+
+        assert_eq!(body["instructions"], "Be brief.");
+        assert_eq!(body["input"], json!([{"role": "user", "content": "Hello!"}]));
+        assert_eq!(body["max_output_tokens"], 1000);
+        assert_eq!(body["reasoning"], json!({"effort": "high", "summary": "auto"}));
+        assert_eq!(body["tools"][0]["name"], "shell");
+        assert_eq!(body["tool_choice"], json!({"type": "function", "name": "shell"}));
+        assert_eq!(body["store"], false);
+    }
+
+    #[tokio::test]
+    async fn test_responses_stream() {
+        let events = [
+            json!({"type": "response.created", "response": {}}),
+            json!({"type": "response.reasoning_summary_text.delta", "summary_index": 0, "delta": "Reading"}),
+            json!({"type": "response.reasoning_summary_text.delta", "summary_index": 1, "delta": "Done"}),
+            json!({"type": "response.output_text.delta", "delta": "Listing files"}),
+            json!({"type": "response.output_item.added", "output_index": 2,
+                "item": {"type": "function_call", "call_id": "call_1", "name": "shell", "arguments": ""}}),
+            json!({"type": "response.function_call_arguments.delta", "output_index": 2, "delta": "{\"command\":"}),
+            json!({"type": "response.function_call_arguments.delta", "output_index": 2, "delta": "\"ls\"}"}),
+            json!({"type": "response.completed", "response": {
+                "status": "completed",
+                "usage": {"input_tokens": 100, "output_tokens": 50, "total_tokens": 150,
+                    "output_tokens_details": {"reasoning_tokens": 30}}}}),
+        ];
+        // Events arrive split across network chunks
+        let sse: String = events
+            .iter()
+            .map(|event| format!("event: {}\ndata: {}\n\n", event["type"], event))
+            .collect();
+        let (first, second) = sse.split_at(sse.len() / 2);
+        let stream = futures_util::stream::iter(vec![
+            Ok(Bytes::from(first.to_string())),
+            Ok(Bytes::from(second.to_string())),
+        ]);
+        let (tx, mut rx) = mpsc::channel(100);
+        let usage = parse_responses_stream(stream, tx).await.unwrap();
+        assert_eq!((usage.completion_tokens, usage.reasoning_tokens), (50, 30));
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            chunks.push(chunk.unwrap());
+        }
+        let reasoning: String = chunks.iter().filter_map(|c| c.reasoning.clone()).collect();
+        assert_eq!(reasoning, "Reading\n\nDone");
+        let text: String = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(text, "Listing files");
+        let deltas: Vec<&ToolCallDelta> = chunks
+            .iter()
+            .filter_map(|c| c.tool_call_deltas.as_ref())
+            .flatten()
+            .collect();
+        assert_eq!(deltas.len(), 3);
+        assert!(deltas.iter().all(|delta| delta.index == 0));
+
+        let last = chunks.last().unwrap();
+        assert!(last.finished && !last.truncated);
+        let calls = last.tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].args, json!({"command": "ls"}));
+
+        // A refusal ends the stream with a content filter error instead of a response
+        let refused = [
+            json!({"type": "response.refusal.delta", "delta": "I can't help with that."}),
+            json!({"type": "response.completed", "response": {"status": "completed"}}),
+        ];
+        let sse: String = refused
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect();
+        let (tx, mut rx) = mpsc::channel(100);
+        parse_responses_stream(futures_util::stream::iter(vec![Ok(Bytes::from(sse))]), tx).await;
+        let error = rx.recv().await.unwrap().unwrap_err();
+        let error = error.downcast_ref::<ProviderError>().unwrap();
+        assert_eq!(error.kind, ProviderErrorKind::ContentFilter);
+        assert_eq!(
+            error.message,
+            "OpenAI stopped the response: I can't help with that."
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Stop sequences: text that ends a response.
+//!
+//! Each provider declares the special tokens that can leak from its model's chat template
+//! ([`LLMProvider::stop_tokens`](crate::LLMProvider::stop_tokens)), and a
+//! [`CompletionRequest`] can add `stop_sequences` of its own. Providers whose API takes stop
+//! sequences pass the request's on, and every provider runs its stream through
+//! [`filter_stream`], so neither a stop sequence nor anything after it reaches the caller,
+//! even when one arrives split across chunks.
+
+use crate::{CompletionChunk, CompletionRequest, CompletionStream};
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+
+/// Special tokens of the chat templates of open model families, which a server that
+/// doesn't apply the template itself can let through. Empty for other models.
+pub fn chat_template_tokens(model: &str) -> Vec<String> {
+    let model = model.to_lowercase();
+    let tokens: &[&str] = if model.contains("qwen") {
+        &["<|im_end|>", "<|endoftext|>", "<|im_start|>"]
+    } else if model.contains("llama-3") || model.contains("llama3") {
+        &["<|eot_id|>", "<|end_of_text|>", "<|start_header_id|>"]
+    } else if model.contains("llama") || model.contains("mistral") || model.contains("mixtral") {
+        &["</s>", "[INST]", "[/INST]", "<<SYS>>", "<</SYS>>"]
+    } else {
+        &[]
+    };
+    tokens.iter().map(|token| token.to_string()).collect()
+}
+
+/// The stop sequences of a request to a provider with `stop_tokens`
+pub fn sequences(stop_tokens: Vec<String>, request: &CompletionRequest) -> Vec<String> {
+    let mut sequences = stop_tokens;
+    for sequence in &request.stop_sequences {
+        if !sequence.is_empty() && !sequences.contains(sequence) {
+            sequences.push(sequence.clone());
+        }
+    }
+    sequences
+}
+
+/// `text` up to the first of `sequences` in it
+pub fn truncate(text: &str, sequences: &[String]) -> String {
+    let end = sequences
+        .iter()
+        .filter(|sequence| !sequence.is_empty())
+        .filter_map(|sequence| text.find(sequence.as_str()))
+        .min()
+        .unwrap_or(text.len());
+    text[..end].to_string()
+}
+
+/// Cuts streamed text at the first stop sequence. Text that could be the start of one is
+/// held back until the next piece shows whether it is.
+#[derive(Debug, Default)]
+pub struct StopFilter {
+    sequences: Vec<String>,
+    held: String,
+    stopped: bool,
+}
+
+impl StopFilter {
+    pub fn new(sequences: Vec<String>) -> Self {
+        Self {
+            sequences: sequences.into_iter().filter(|s| !s.is_empty()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether a stop sequence was found; everything after it is dropped
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// The next piece of the response. Returns the text that can be passed on.
+    pub fn push(&mut self, text: &str) -> String {
+        if self.stopped {
+            return String::new();
+        }
+        self.held.push_str(text);
+        let stop = self
+            .sequences
+            .iter()
+            .filter_map(|sequence| self.held.find(sequence.as_str()))
+            .min();
+        if let Some(stop) = stop {
+            self.stopped = true;
+            self.held.truncate(stop);
+            return std::mem::take(&mut self.held);
+        }
+        let partial = self
+            .sequences
+            .iter()
+            .map(|sequence| partial_suffix(&self.held, sequence))
+            .max()
+            .unwrap_or(0);
+        let rest = self.held.split_off(self.held.len() - partial);
+        std::mem::replace(&mut self.held, rest)
+    }
+
+    /// The text held back, once the response has ended without completing a stop sequence
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.held)
+    }
+}
+
+/// Length of the longest end of `text` that `sequence` starts with, short of all of it
+fn partial_suffix(text: &str, sequence: &str) -> usize {
+    (1..sequence.len())
+        .rev()
+        .filter(|len| sequence.is_char_boundary(*len))
+        .find(|len| text.ends_with(&sequence[..*len]))
+        .unwrap_or(0)
+}
+
+/// `stream` with its text cut at the first of `sequences`. Chunks after the cut still come
+/// through, without text, for their usage and end of the response.
+pub fn filter_stream(mut stream: CompletionStream, sequences: Vec<String>) -> CompletionStream {
+    if sequences.is_empty() {
+        return stream;
+    }
+    let (tx, rx) = mpsc::channel(100);
+    tokio::spawn(async move {
+        let mut filter = StopFilter::new(sequences);
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map(|mut chunk| {
+                chunk.content = filter.push(&chunk.content);
+                if chunk.finished {
+                    chunk.content.push_str(&filter.finish());
+                    // The response ended at a stop sequence, not at the token limit
+                    chunk.truncated &= !filter.is_stopped();
+                }
+                chunk
+            });
+            if tx.send(chunk).await.is_err() {
+                return;
+            }
+        }
+        let rest = filter.finish();
+        if !rest.is_empty() {
+            let _ = tx
+                .send(Ok(CompletionChunk {
+                    content: rest,
+                    finished: false,
+                    tool_calls: None,
+                    usage: None,
+                    tool_call_deltas: None,
+                    reasoning: None,
+                    truncated: false,
+                }))
+                .await;
+        }
+    });
+    CompletionStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequences(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_filter_cuts_at_split_stop_sequence() {
+        let mut filter = StopFilter::new(sequences(&["<|im_end|>", "END"]));
+        assert_eq!(filter.push("Hello <|im"), "Hello ");
+        assert_eq!(filter.push("_e"), "");
+        assert_eq!(filter.push("nd|> and more"), "");
+        assert!(filter.is_stopped());
+        assert_eq!(filter.push("ignored"), "");
+        assert_eq!(filter.finish(), "");
+
+        // Held text that turns out not to be a stop sequence is passed on
+        let mut filter = StopFilter::new(sequences(&["<|im_end|>"]));
+        assert_eq!(filter.push("a <|im"), "a ");
+        assert_eq!(filter.push("age"), "<|image");
+        assert_eq!(filter.push(" <"), " ");
+        assert_eq!(filter.finish(), "<");
+        assert!(!filter.is_stopped());
+    }
+
+    #[test]
+    fn test_truncate_and_template_tokens() {
+        let stops = sequences(&["</s>", "[/INST]"]);
+        assert_eq!(truncate("answer[/INST] junk</s>", &stops), "answer");
+        assert_eq!(truncate("no stop here", &stops), "no stop here");
+
+        assert!(chat_template_tokens("Qwen2.5-Coder-32B").contains(&"<|im_end|>".to_string()));
+        assert!(chat_template_tokens("meta-llama-3.1-70b").contains(&"<|eot_id|>".to_string()));
+        assert!(chat_template_tokens("embedded-mistral").contains(&"</s>".to_string()));
+        assert!(chat_template_tokens("gpt-4o").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_filter_stream() {
+        let (tx, rx) = mpsc::channel(10);
+        for (content, finished) in [("Done.<|im", false), ("_end|>\n{", false), ("", true)] {
+            tx.send(Ok(CompletionChunk {
+                content: content.to_string(),
+                finished,
+                tool_calls: None,
+                usage: None,
+                tool_call_deltas: None,
+                reasoning: None,
+                truncated: finished,
+            }))
+            .await
+            .unwrap();
+        }
+        drop(tx);
+        let stream = filter_stream(CompletionStream::new(rx), sequences(&["<|im_end|>"]));
+        let chunks: Vec<CompletionChunk> = stream.map(|chunk| chunk.unwrap()).collect().await;
+        let text: String = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(text, "Done.");
+        let last = chunks.last().unwrap();
+        assert!(last.finished && !last.truncated);
+    }
+}
@@ -0,0 +1,285 @@
+//! GBNF grammars for JSON tool calls, for providers without native tool calling.
+//!
+//! Without native tool calling, the model writes a tool call as a JSON object in its text,
+//! `{"tool": "<name>", "args": {...}}`, which is picked out of the stream by pattern matching.
+//! A model that gets the JSON slightly wrong produces a call that is silently dropped, and
+//! one that leaves out an argument gets an error back, which small models tend to repeat.
+//! With grammar-constrained decoding, the response is free text followed by at most one tool
+//! call that is always valid JSON, names a known tool and has the arguments its input schema
+//! requires, with the declared types. The response ends after the call. The text before a
+//! call can't contain `{"`, which is how the grammar tells a call apart.
+
+use serde_json::Value;
+
+use crate::Tool;
+
+/// Nested schemas deeper than this take any JSON value
+const MAX_DEPTH: usize = 4;
+
+const BASE_RULES: &str = r#"root ::= prose call?
+prose ::= ( [^{] | "{" [^"] )*
+value ::= object | array | string | number | ( "true" | "false" | "null" ) ws
+object ::= "{" ws ( string ":" ws value ( "," ws string ":" ws value )* )? "}" ws
+array ::= "[" ws ( value ( "," ws value )* )? "]" ws
+string ::= "\"" ( [^"\\\x00-\x1F] | "\\" ( ["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] ) )* "\"" ws
+number ::= "-"? ( [0-9] | [1-9] [0-9]* ) ( "." [0-9]+ )? ( [eE] [-+]? [0-9]+ )? ws
+integer ::= "-"? ( [0-9] | [1-9] [0-9]* ) ws
+boolean ::= ( "true" | "false" ) ws
+ws ::= ( [ \t\n] ws )?
+"#;
+
+/// A grammar for text followed by an optional call to one of `tools`, with arguments that
+/// match its input schema
+pub fn tool_call_grammar(tools: &[Tool]) -> String {
+    let mut rules = Rules::default();
+    let calls: Vec<String> = tools
+        .iter()
+        .map(|tool| {
+            let args = schema_expr(&mut rules, &tool.input_schema, 0);
+            rules.add(format!(
+                r#"{} ", \"args\": " {}"#,
+                literal(&Value::from(tool.name.as_str())),
+                args
+            ))
+        })
+        .collect();
+
+    let mut grammar = BASE_RULES.to_string();
+    grammar.push_str(&format!(
+        "call ::= \"{{\\\"tool\\\": \" ( {} ) \"}}\"\n",
+        calls.join(" | ")
+    ));
+    for (name, body) in &rules.rules {
+        grammar.push_str(&format!("{} ::= {}\n", name, body));
+    }
+    grammar
+}
+
+/// The parts of a tool definition the grammar uses, without descriptions, for sending to a
+/// provider that only needs them to constrain its tool calls
+pub fn grammar_tool(tool: Tool) -> Tool {
+    Tool {
+        name: tool.name,
+        description: String::new(),
+        input_schema: without_descriptions(tool.input_schema),
+    }
+}
+
+fn without_descriptions(schema: Value) -> Value {
+    match schema {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, value)| !(key == "description" && value.is_string()))
+                .map(|(key, value)| (key, without_descriptions(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(without_descriptions).collect()),
+        other => other,
+    }
+}
+
+/// Generated rules, named `r0`, `r1`, ...
+#[derive(Default)]
+struct Rules {
+    rules: Vec<(String, String)>,
+}
+
+impl Rules {
+    /// The name of a rule with `body`, shared with an identical rule if there is one
+    fn add(&mut self, body: String) -> String {
+        if let Some((name, _)) = self.rules.iter().find(|(_, existing)| *existing == body) {
+            return name.clone();
+        }
+        let name = format!("r{}", self.rules.len());
+        self.rules.push((name.clone(), body));
+        name
+    }
+}
+
+/// An expression matching JSON values that `schema` allows
+fn schema_expr(rules: &mut Rules, schema: &Value, depth: usize) -> String {
+    if depth > MAX_DEPTH {
+        return "value".to_string();
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        let values: Vec<String> = values.iter().map(literal).collect();
+        return format!("( {} ) ws", values.join(" | "));
+    }
+    match schema.get("type") {
+        Some(Value::String(kind)) => type_expr(rules, schema, kind, depth),
+        Some(Value::Array(kinds)) => {
+            let kinds: Vec<String> = kinds
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|kind| type_expr(rules, schema, kind, depth))
+                .collect();
+            format!("( {} )", kinds.join(" | "))
+        }
+        _ => "value".to_string(),
+    }
+}
+
+fn type_expr(rules: &mut Rules, schema: &Value, kind: &str, depth: usize) -> String {
+    match kind {
+        "string" | "number" | "integer" | "boolean" => kind.to_string(),
+        "null" => r#""null" ws"#.to_string(),
+        "array" => match schema.get("items") {
+            Some(items) => {
+                let item = schema_expr(rules, items, depth + 1);
+                let item = rules.add(item);
+                format!(r#""[" ws ( {item} ( "," ws {item} )* )? "]" ws"#)
+            }
+            None => "array".to_string(),
+        },
+        "object" => object_expr(rules, schema, depth),
+        _ => "value".to_string(),
+    }
+}
+
+/// An object with the required properties in the order they are listed, then any of the
+/// optional ones in name order
+fn object_expr(rules: &mut Rules, schema: &Value, depth: usize) -> String {
+    let properties = match schema.get("properties").and_then(Value::as_object) {
+        Some(properties) if !properties.is_empty() => properties,
+        _ => return "object".to_string(),
+    };
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter(|name| properties.contains_key(*name))
+        .collect();
+
+    let mut member = |name: &str| {
+        let value = schema_expr(rules, &properties[name], depth + 1);
+        rules.add(format!(
+            r#"{} ":" ws {}"#,
+            literal(&Value::from(name)),
+            value
+        ))
+    };
+    let required_members: Vec<String> = required.iter().map(|name| member(name)).collect();
+    let optional_members: Vec<String> = properties
+        .keys()
+        .filter(|name| !required.contains(&name.as_str()))
+        .map(|name| member(name))
+        .collect();
+
+    let optional_after = |start: usize| -> Vec<String> {
+        optional_members[start..]
+            .iter()
+            .map(|member| format!(r#"( "," ws {} )?"#, member))
+            .collect()
+    };
+    let members = if required_members.is_empty() {
+        // Whichever optional member comes first has no comma before it
+        let firsts: Vec<String> = (0..optional_members.len())
+            .map(|i| {
+                let mut first = vec![optional_members[i].clone()];
+                first.extend(optional_after(i + 1));
+                first.join(" ")
+            })
+            .collect();
+        format!("( {} )?", firsts.join(" | "))
+    } else {
+        let mut members = vec![required_members.join(r#" "," ws "#)];
+        members.extend(optional_after(0));
+        members.join(" ")
+    };
+    format!(r#""{{" ws {} "}}" ws"#, members)
+}
+
+/// A GBNF literal matching `value` as JSON
+fn literal(value: &Value) -> String {
+    let json = value.to_string();
+    format!("\"{}\"", json.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str, input_schema: Value) -> Tool {
+        Tool {
+            name: name.to_string(),
+            description: "A tool".to_string(),
+            input_schema,
+        }
+    }
+
+    #[test]
+    fn test_tool_call_grammar() {
+        let tools = [
+            tool(
+                "read_file",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "file_path": {"type": "string", "description": "Path"},
+                        "start": {"type": "integer"},
+                        "end": {"type": "integer"}
+                    },
+                    "required": ["file_path"]
+                }),
+            ),
+            tool(
+                "mouse_click",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "button": {"type": "string", "enum": ["left", "right"]},
+                        "double": {"type": "boolean"}
+                    }
+                }),
+            ),
+            tool("todo_read", json!({"type": "object", "properties": {}})),
+        ];
+        let grammar = tool_call_grammar(&tools);
+
+        assert!(grammar.starts_with("root ::= prose call?\n"));
+        assert!(grammar.contains(r#"call ::= "{\"tool\": " ( r3 | r6 | r7 ) "}""#));
+        // Required arguments come first, then optional ones
+        assert!(grammar.contains(r#"r0 ::= "\"file_path\"" ":" ws string"#));
+        assert!(grammar.contains(r#"r1 ::= "\"end\"" ":" ws integer"#));
+        assert!(grammar.contains(
+            r#"r3 ::= "\"read_file\"" ", \"args\": " "{" ws r0 ( "," ws r1 )? ( "," ws r2 )? "}" ws"#
+        ));
+        // Without required arguments, any of the optional ones can come first
+        assert!(grammar.contains(r#"r4 ::= "\"button\"" ":" ws ( "\"left\"" | "\"right\"" ) ws"#));
+        assert!(grammar.contains(
+            r#"r6 ::= "\"mouse_click\"" ", \"args\": " "{" ws ( r4 ( "," ws r5 )? | r5 )? "}" ws"#
+        ));
+        assert!(grammar.contains(r#"r7 ::= "\"todo_read\"" ", \"args\": " object"#));
+
+        // Every rule that is used is defined
+        let defined: Vec<&str> = grammar
+            .lines()
+            .filter_map(|line| line.split(" ::= ").next())
+            .collect();
+        for rule in grammar.split(|c: char| !c.is_ascii_alphanumeric()) {
+            if rule.starts_with('r') && rule[1..].parse::<usize>().is_ok() {
+                assert!(defined.contains(&rule), "{} is not defined", rule);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grammar_tool() {
+        let schema = json!({
+            "type": "object",
+            "description": "Arguments",
+            "properties": {
+                "description": {"type": "string", "description": "A description"}
+            }
+        });
+        let stripped = grammar_tool(tool("set_title", schema));
+        assert_eq!(stripped.description, "");
+        assert_eq!(
+            stripped.input_schema,
+            json!({"type": "object", "properties": {"description": {"type": "string"}}})
+        );
+    }
+}
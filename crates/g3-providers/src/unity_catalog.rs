@@ -0,0 +1,414 @@
+//! Databricks Unity Catalog functions as agent tools.
+//!
+//! Functions in the configured schemas are discovered through the workspace's Unity Catalog
+//! API and described as [`Tool`]s. Calling one runs `SELECT <function>(...)` on a SQL
+//! warehouse through the Statement Execution API, with the arguments bound as parameters.
+
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::databricks::DatabricksAuth;
+use crate::{ProviderError, Tool};
+
+/// Prefix of the tool names of Unity Catalog functions
+pub const TOOL_PREFIX: &str = "uc__";
+
+/// Providers reject tool names longer than this
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// How long the warehouse may take before the statement is cancelled
+const STATEMENT_WAIT_TIMEOUT: &str = "50s";
+
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FunctionParameter {
+    pub name: String,
+    /// e.g. `STRING`, `INT`, `ARRAY`
+    pub type_name: String,
+    /// Full SQL type, e.g. `array<string>`
+    #[serde(default)]
+    pub type_text: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub parameter_default: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogFunction {
+    /// `catalog.schema.function`
+    pub full_name: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_parameters")]
+    pub input_params: Vec<FunctionParameter>,
+    /// `TABLE_TYPE` for table-valued functions
+    #[serde(default)]
+    pub data_type: Option<String>,
+}
+
+/// The API nests parameters as `{"parameters": [...]}`
+fn deserialize_parameters<'de, D>(deserializer: D) -> Result<Vec<FunctionParameter>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Params {
+        #[serde(default)]
+        parameters: Vec<FunctionParameter>,
+    }
+    Ok(Option::<Params>::deserialize(deserializer)?
+        .map(|p| p.parameters)
+        .unwrap_or_default())
+}
+
+impl CatalogFunction {
+    /// `uc__catalog__schema__function`, shortened to fit provider limits
+    pub fn tool_name(&self) -> String {
+        let name = format!("{}{}", TOOL_PREFIX, self.full_name.replace('.', "__"));
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        name.chars().take(MAX_TOOL_NAME_LEN).collect()
+    }
+
+    pub fn is_table_function(&self) -> bool {
+        self.data_type.as_deref() == Some("TABLE_TYPE")
+    }
+
+    pub fn tool(&self) -> Tool {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for param in &self.input_params {
+            let mut schema = json!({ "type": json_type(&param.type_name) });
+            let description = match (&param.comment, param.type_text.is_empty()) {
+                (Some(comment), false) => format!("{} ({})", comment, param.type_text),
+                (Some(comment), true) => comment.clone(),
+                (None, false) => param.type_text.clone(),
+                (None, true) => param.type_name.to_lowercase(),
+            };
+            schema["description"] = json!(description);
+            properties.insert(param.name.clone(), schema);
+            if param.parameter_default.is_none() {
+                required.push(param.name.clone());
+            }
+        }
+
+        Tool {
+            name: self.tool_name(),
+            description: format!(
+                "Unity Catalog function {}: {}",
+                self.full_name,
+                self.comment.as_deref().unwrap_or("no description")
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            }),
+        }
+    }
+
+    /// The SQL statement calling the function with `args`, and its parameters. Arguments are
+    /// passed positionally, so an argument after an omitted one is an error.
+    pub fn statement(&self, args: &Value) -> Result<(String, Vec<Value>)> {
+        let mut placeholders = Vec::new();
+        let mut parameters = Vec::new();
+        let mut omitted: Option<&str> = None;
+        for param in &self.input_params {
+            let Some(value) = args.get(&param.name).filter(|v| !v.is_null()) else {
+                if param.parameter_default.is_none() {
+                    return Err(anyhow!("Missing required argument '{}'", param.name));
+                }
+                omitted.get_or_insert(&param.name);
+                continue;
+            };
+            if let Some(omitted) = omitted {
+                return Err(anyhow!(
+                    "Argument '{}' can't be given without '{}', which comes before it",
+                    param.name,
+                    omitted
+                ));
+            }
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            placeholders.push(format!(":{}", param.name));
+            parameters.push(json!({
+                "name": param.name,
+                "value": value,
+                "type": param.type_name,
+            }));
+        }
+
+        let function = self
+            .full_name
+            .split('.')
+            .map(|part| format!("`{}`", part.replace('`', "``")))
+            .collect::<Vec<_>>()
+            .join(".");
+        let call = format!("{}({})", function, placeholders.join(", "));
+        let statement = if self.is_table_function() {
+            format!("SELECT * FROM {}", call)
+        } else {
+            format!("SELECT {} AS result", call)
+        };
+        Ok((statement, parameters))
+    }
+}
+
+fn json_type(type_name: &str) -> &'static str {
+    match type_name {
+        "BYTE" | "SHORT" | "INT" | "LONG" => "integer",
+        "FLOAT" | "DOUBLE" | "DECIMAL" => "number",
+        "BOOLEAN" => "boolean",
+        "ARRAY" => "array",
+        "MAP" | "STRUCT" => "object",
+        _ => "string",
+    }
+}
+
+/// Format a statement result as a tab-separated table, at most `max_rows` rows
+pub fn format_result(response: &Value, max_rows: usize) -> Result<String> {
+    let state = response["status"]["state"].as_str().unwrap_or("UNKNOWN");
+    if state != "SUCCEEDED" {
+        let message = response["status"]["error"]["message"]
+            .as_str()
+            .unwrap_or("no error message");
+        return Err(anyhow!("Statement {}: {}", state.to_lowercase(), message));
+    }
+
+    let columns: Vec<&str> = response["manifest"]["schema"]["columns"]
+        .as_array()
+        .map(|columns| columns.iter().filter_map(|c| c["name"].as_str()).collect())
+        .unwrap_or_default();
+    let rows = response["result"]["data_array"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let total = response["manifest"]["total_row_count"]
+        .as_u64()
+        .unwrap_or(rows.len() as u64);
+
+    let mut output = columns.join("\t");
+    for row in rows.iter().take(max_rows) {
+        let cells: Vec<String> = row
+            .as_array()
+            .map(|cells| {
+                cells
+                    .iter()
+                    .map(|cell| match cell {
+                        Value::Null => "NULL".to_string(),
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        output.push('\n');
+        output.push_str(&cells.join("\t"));
+    }
+    let shown = rows.len().min(max_rows) as u64;
+    if total > shown {
+        output.push_str(&format!("\n... {} of {} rows shown", shown, total));
+    }
+    Ok(output)
+}
+
+#[derive(Debug, Clone)]
+pub struct UnityCatalogClient {
+    client: Client,
+    host: String,
+    auth: DatabricksAuth,
+}
+
+impl UnityCatalogClient {
+    pub fn new(host: &str, auth: DatabricksAuth) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(DEFAULT_TIMEOUT_SECS))
+            .build()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        Ok(Self {
+            client,
+            host: host.trim_end_matches('/').to_string(),
+            auth,
+        })
+    }
+
+    async fn token(&self) -> Result<String> {
+        self.auth.get_token().await
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder, context: &str) -> Result<Value> {
+        let response = request
+            .bearer_auth(self.token().await?)
+            .send()
+            .await
+            .map_err(|e| ProviderError::from_reqwest(context, &e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_response("Databricks", status, &body).into());
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Functions in `catalog.schema`
+    pub async fn list_functions(&self, schema: &str) -> Result<Vec<CatalogFunction>> {
+        let (catalog, schema_name) = schema
+            .split_once('.')
+            .ok_or_else(|| anyhow!("Expected 'catalog.schema', got '{}'", schema))?;
+
+        let mut functions = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut query = vec![("catalog_name", catalog), ("schema_name", schema_name)];
+            if let Some(token) = &page_token {
+                query.push(("page_token", token));
+            }
+            let request = self
+                .client
+                .get(format!("{}/api/2.1/unity-catalog/functions", self.host))
+                .query(&query);
+            let page = self
+                .send(request, "Failed to list Unity Catalog functions")
+                .await?;
+
+            if let Some(items) = page["functions"].as_array() {
+                for item in items {
+                    match serde_json::from_value::<CatalogFunction>(item.clone()) {
+                        Ok(function) => functions.push(function),
+                        Err(e) => debug!("Skipping unreadable function {}: {}", item["name"], e),
+                    }
+                }
+            }
+            page_token = page["next_page_token"]
+                .as_str()
+                .filter(|t| !t.is_empty())
+                .map(str::to_string);
+            if page_token.is_none() {
+                return Ok(functions);
+            }
+        }
+    }
+
+    /// Run `function` with `args` on `warehouse_id` and format up to `max_rows` rows
+    pub async fn call(
+        &self,
+        function: &CatalogFunction,
+        args: &Value,
+        warehouse_id: &str,
+        max_rows: usize,
+    ) -> Result<String> {
+        let (statement, parameters) = function.statement(args)?;
+        debug!("Running Unity Catalog function: {}", statement);
+        let request = self
+            .client
+            .post(format!("{}/api/2.0/sql/statements", self.host))
+            .json(&json!({
+                "warehouse_id": warehouse_id,
+                "statement": statement,
+                "parameters": parameters,
+                "wait_timeout": STATEMENT_WAIT_TIMEOUT,
+                "on_wait_timeout": "CANCEL",
+                "disposition": "INLINE",
+                "format": "JSON_ARRAY",
+            }));
+        let response = self
+            .send(request, "Failed to run Unity Catalog function")
+            .await?;
+        format_result(&response, max_rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function() -> CatalogFunction {
+        serde_json::from_value(json!({
+            "name": "revenue_by_region",
+            "full_name": "main.finance.revenue_by_region",
+            "comment": "Revenue per region for a quarter",
+            "data_type": "TABLE_TYPE",
+            "input_params": {
+                "parameters": [
+                    {"name": "quarter", "type_name": "STRING", "type_text": "string", "comment": "e.g. 2024-Q1"},
+                    {"name": "min_revenue", "type_name": "DOUBLE", "type_text": "double", "parameter_default": "0"},
+                    {"name": "currency", "type_name": "STRING", "type_text": "string", "parameter_default": "'USD'"}
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_function_tool() {
+        let tool = function().tool();
+        assert_eq!(tool.name, "uc__main__finance__revenue_by_region");
+        assert_eq!(tool.input_schema["required"], json!(["quarter"]));
+        assert_eq!(
+            tool.input_schema["properties"]["min_revenue"]["type"],
+            "number"
+        );
+        assert_eq!(
+            tool.input_schema["properties"]["quarter"]["description"],
+            "e.g. 2024-Q1 (string)"
+        );
+    }
+
+    #[test]
+    fn test_function_statement() {
+        let function = function();
+        let (statement, parameters) = function
+            .statement(&json!({"quarter": "2024-Q1", "min_revenue": 1000.5}))
+            .unwrap();
+        assert_eq!(
+            statement,
+            "SELECT * FROM `main`.`finance`.`revenue_by_region`(:quarter, :min_revenue)"
+        );
+        assert_eq!(parameters[1]["value"], "1000.5");
+        assert_eq!(parameters[1]["type"], "DOUBLE");
+
+        assert!(function.statement(&json!({})).is_err());
+        assert!(function
+            .statement(&json!({"quarter": "2024-Q1", "currency": "EUR"}))
+            .is_err());
+    }
+
+    #[test]
+    fn test_format_result() {
+        let response = json!({
+            "status": {"state": "SUCCEEDED"},
+            "manifest": {
+                "schema": {"columns": [{"name": "region"}, {"name": "revenue"}]},
+                "total_row_count": 3
+            },
+            "result": {"data_array": [["EMEA", "10.5"], ["APAC", null], ["AMER", "7"]]}
+        });
+        assert_eq!(
+            format_result(&response, 2).unwrap(),
+            "region\trevenue\nEMEA\t10.5\nAPAC\tNULL\n... 2 of 3 rows shown"
+        );
+
+        let failed =
+            json!({"status": {"state": "FAILED", "error": {"message": "no such warehouse"}}});
+        assert_eq!(
+            format_result(&failed, 10).unwrap_err().to_string(),
+            "Statement failed: no such warehouse"
+        );
+    }
+}